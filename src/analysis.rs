@@ -0,0 +1,582 @@
+//! Editor queries over a program: which names are in scope at a cursor, with
+//! their types, which expression the cursor is in, and how each token should
+//! be highlighted. The REPL uses this, and editor integrations should too.
+
+use std::collections::HashMap;
+
+use crate::ast::visit::{
+    walk_decl, walk_expr, walk_param, walk_pattern, walk_type_ann, walk_variant, Visitor,
+};
+use crate::ast::{
+    Ast, Decl, Expr, ExprId, LambdaParam, Pattern, Program, SpannedPattern, SpannedTypeAnn,
+    TypeAnnotation, Variant,
+};
+use crate::lexer::token::{Token, TokenKind, TokenOrTrivia, TriviaKind};
+use crate::span::Span;
+use crate::types::env::TypeEnv;
+use crate::types::infer::Inferencer;
+use crate::types::{MonoType, TypeScheme};
+use crate::{lexer, macros, parser};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// A parameter, `let ... in` binding or pattern variable.
+    Local,
+    /// A top-level binding, constructor or builtin.
+    Global,
+}
+
+/// A name in scope and its type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub ty: String,
+    pub kind: SymbolKind,
+}
+
+/// What is visible from one cursor position.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The identifier fragment just before the cursor.
+    pub prefix: String,
+    /// Every name in scope: locals innermost first, then globals by name.
+    pub symbols: Vec<Symbol>,
+    /// The innermost expression containing the cursor.
+    pub enclosing: Option<Span>,
+}
+
+impl Analysis {
+    /// Symbols that complete the prefix.
+    pub fn completions(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols
+            .iter()
+            .filter(move |s| s.name.starts_with(&self.prefix))
+    }
+}
+
+/// Analyze `source` at `offset`, a character offset like the ones in spans.
+/// `env` holds the names already defined outside `source`, e.g. the stdlib or
+/// earlier REPL inputs. Source that doesn't parse still gets the globals.
+pub fn analyze(source: &str, offset: usize, env: &TypeEnv) -> Analysis {
+    let chars: Vec<char> = source.chars().collect();
+    let offset = offset.min(chars.len());
+    let start = chars[..offset]
+        .iter()
+        .rposition(|c| !is_ident_char(*c))
+        .map_or(0, |i| i + 1);
+    let prefix: String = chars[start..offset].iter().collect();
+
+    let Program { mut ast, decls } = lexer::tokenize(source)
+        .ok()
+        .and_then(|tokens| parser::parse(tokens).ok())
+        .unwrap_or_default();
+    // Expanding stops at the first bad call; the declarations before it are
+    // expanded already
+    let _ = macros::expand_macros(&mut ast, &decls);
+
+    let mut env = env.clone();
+    let mut inferencer = Inferencer::new();
+    inferencer.enable_binder_types();
+    let mut at = ScopeAt {
+        offset,
+        scope: Vec::new(),
+        enclosing: None,
+    };
+
+    for decl in &decls {
+        let span = decl_span(&ast, decl);
+        if span.start >= offset {
+            break;
+        }
+        if span.end < offset {
+            if inferencer.infer_decl(&mut env, &ast, decl).is_err() {
+                inferencer.recover_decl(&mut env, &ast, decl);
+            }
+            continue;
+        }
+
+        // The cursor is inside this declaration: check it for its binder
+        // types, but only a recursive binding can see itself
+        let mut decl_env = env.clone();
+        let _ = inferencer.infer_decl(&mut decl_env, &ast, decl);
+        match decl {
+            Decl::Let {
+                name,
+                recursive,
+                body,
+                ..
+            } => {
+                if *recursive {
+                    if let Some(scheme) = decl_env.lookup(&name.node) {
+                        env.insert(name.node.clone(), scheme.clone());
+                    }
+                }
+                at.visit_expr(&ast, *body);
+            }
+            Decl::Prop { params, body, .. } => {
+                if covers(ast[*body].span, offset) {
+                    at.scope
+                        .extend(params.iter().map(|p| (p.name.span, p.name.node.clone())));
+                }
+                at.visit_expr(&ast, *body);
+            }
+            Decl::Expr(expr) => at.visit_expr(&ast, *expr),
+            Decl::Type { .. } | Decl::Import { .. } | Decl::Macro { .. } | Decl::Extern { .. } => {}
+        }
+        break;
+    }
+
+    let mut symbols: Vec<Symbol> = Vec::new();
+    for (span, name) in at.scope.into_iter().rev() {
+        if symbols.iter().any(|s| s.name == name) {
+            continue;
+        }
+        let ty = inferencer
+            .binder_types()
+            .iter()
+            .rev()
+            .find(|(s, _)| *s == span)
+            .map_or_else(|| "?".to_string(), |(_, ty)| display_mono(ty));
+        symbols.push(Symbol {
+            name,
+            ty,
+            kind: SymbolKind::Local,
+        });
+    }
+
+    let mut globals: Vec<&str> = env.names();
+    globals.sort_unstable();
+    for name in globals {
+        if symbols.iter().any(|s| s.name == name) {
+            continue;
+        }
+        let ty = env
+            .lookup(name)
+            .map(|scheme| scheme.normalized().to_string())
+            .unwrap_or_default();
+        symbols.push(Symbol {
+            name: name.to_string(),
+            ty,
+            kind: SymbolKind::Global,
+        });
+    }
+
+    Analysis {
+        prefix,
+        symbols,
+        enclosing: at.enclosing,
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether a cursor at `offset` is in `span`; a cursor right after the last
+/// character still counts.
+fn covers(span: Span, offset: usize) -> bool {
+    span.start <= offset && offset <= span.end
+}
+
+pub(crate) fn decl_span(ast: &Ast, decl: &Decl) -> Span {
+    match decl {
+        Decl::Let { name, body, .. } => name.span.merge(ast[*body].span),
+        Decl::Extern { name, type_ann, .. } => name.span.merge(type_ann.span),
+        Decl::Type { name, variants, .. } => {
+            variants.iter().fold(name.span, |span, v| span.merge(v.span))
+        }
+        Decl::Import { span, .. } | Decl::Prop { span, .. } | Decl::Macro { span, .. } => *span,
+        Decl::Expr(expr) => ast[*expr].span,
+    }
+}
+
+/// Walks down to a cursor, collecting each binder whose scope contains it
+/// (outermost first) and the innermost expression passed through.
+struct ScopeAt {
+    offset: usize,
+    scope: Vec<(Span, String)>,
+    enclosing: Option<Span>,
+}
+
+impl Visitor for ScopeAt {
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        let expr = &ast[id];
+        if !covers(expr.span, self.offset) {
+            return;
+        }
+        self.enclosing = Some(expr.span);
+
+        match &expr.node {
+            Expr::Lambda { params, body } => {
+                if covers(ast[*body].span, self.offset) {
+                    self.scope
+                        .extend(params.iter().map(|p| (p.name.span, p.name.node.clone())));
+                    self.visit_expr(ast, *body);
+                }
+            }
+            Expr::Let {
+                name,
+                recursive,
+                value,
+                body,
+                ..
+            } => {
+                if covers(ast[*value].span, self.offset) {
+                    if *recursive {
+                        self.scope.push((name.span, name.node.clone()));
+                    }
+                    self.visit_expr(ast, *value);
+                } else if covers(ast[*body].span, self.offset) {
+                    self.scope.push((name.span, name.node.clone()));
+                    self.visit_expr(ast, *body);
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.visit_expr(ast, *scrutinee);
+                for arm in arms {
+                    if covers(ast[arm.body].span, self.offset) {
+                        pattern_binders(&arm.pattern, &mut self.scope);
+                        self.visit_expr(ast, arm.body);
+                    }
+                }
+            }
+            _ => walk_expr(self, ast, id),
+        }
+    }
+}
+
+/// The variables a pattern binds, in source order.
+pub(crate) fn pattern_binders(pattern: &SpannedPattern, out: &mut Vec<(Span, String)>) {
+    struct Binders<'a>(&'a mut Vec<(Span, String)>);
+
+    impl Visitor for Binders<'_> {
+        fn visit_pattern(&mut self, pattern: &SpannedPattern) {
+            if let Pattern::Var(name) = &pattern.node {
+                self.0.push((pattern.span, name.clone()));
+            }
+            walk_pattern(self, pattern);
+        }
+    }
+
+    Binders(out).visit_pattern(pattern);
+}
+
+/// The names in `env` matching `query`, best first, for the REPL's
+/// `:search`: the name itself, then names starting with or containing the
+/// query, then types mentioning it (`[String]`, spacing aside), then names
+/// holding its letters in order (`strlen` for `str_length`), then names a
+/// typo away. Case is ignored throughout.
+pub fn search(query: &str, env: &TypeEnv) -> Vec<Symbol> {
+    let query = query.trim().to_lowercase();
+    let squashed: String = query.split_whitespace().collect();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut hits: Vec<(u8, Symbol)> = env
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let scheme = env.lookup(name)?.normalized();
+            let lower = name.to_lowercase();
+            let ty: String = scheme.ty.to_string().to_lowercase().split_whitespace().collect();
+            let mut letters = lower.chars();
+            let rank = if lower == query {
+                0
+            } else if lower.starts_with(&query) {
+                1
+            } else if lower.contains(&query) {
+                2
+            } else if ty.contains(&squashed) {
+                3
+            } else if query.chars().count() > 1 && query.chars().all(|c| letters.any(|l| l == c)) {
+                4
+            } else if crate::error::is_similar(&query, &lower) {
+                5
+            } else {
+                return None;
+            };
+            let symbol = Symbol {
+                name: name.to_string(),
+                ty: scheme.to_string(),
+                kind: SymbolKind::Global,
+            };
+            Some((rank, symbol))
+        })
+        .collect();
+    hits.sort_by(|(a, x), (b, y)| a.cmp(b).then_with(|| x.name.cmp(&y.name)));
+    hits.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+/// Show a local's type with its variables renumbered from `t0`.
+fn display_mono(ty: &MonoType) -> String {
+    TypeScheme {
+        vars: ty.free_vars().into_iter().collect(),
+        ty: ty.clone(),
+        constraints: vec![],
+    }
+    .normalized()
+    .ty
+    .to_string()
+}
+
+/// What a piece of source is, for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenClass {
+    Keyword,
+    Function,
+    Constructor,
+    Type,
+    Variable,
+    String,
+    Number,
+    Comment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub class: TokenClass,
+}
+
+/// Classify the keywords, names, literals and comments in `source`, in
+/// order; operators and punctuation are left out. Names are resolved against
+/// their bindings when the program parses, so a parameter holding a function
+/// is a `Function`; otherwise they are classified by spelling and `env`.
+/// Source that doesn't lex has no tokens.
+pub fn semantic_tokens(source: &str, env: &TypeEnv) -> Vec<SemanticToken> {
+    let Ok(pieces) = lexer::tokenize_lossless(source) else {
+        return Vec::new();
+    };
+    let tokens: Vec<Token> = pieces
+        .iter()
+        .filter_map(|p| match p {
+            TokenOrTrivia::Token(t) => Some(t.clone()),
+            TokenOrTrivia::Trivia(_) => None,
+        })
+        .collect();
+    let program = parser::parse(tokens.clone()).unwrap_or_default();
+    let mut resolver = Resolver {
+        env: env.clone(),
+        binder_types: HashMap::new(),
+        scopes: Vec::new(),
+        names: HashMap::new(),
+    };
+    resolver.resolve(&program.ast, &program.decls);
+
+    let mut out = Vec::new();
+    let mut next_token = 0;
+    for piece in &pieces {
+        let tok = match piece {
+            TokenOrTrivia::Trivia(t) => {
+                if t.kind != TriviaKind::Whitespace {
+                    out.push(SemanticToken {
+                        span: t.span,
+                        class: TokenClass::Comment,
+                    });
+                }
+                continue;
+            }
+            TokenOrTrivia::Token(tok) => tok,
+        };
+        next_token += 1;
+
+        let class = match &tok.kind {
+            TokenKind::Let
+            | TokenKind::In
+            | TokenKind::Fn
+            | TokenKind::Match
+            | TokenKind::With
+            | TokenKind::If
+            | TokenKind::Then
+            | TokenKind::Else
+            | TokenKind::Type
+            | TokenKind::Rec
+            | TokenKind::Import
+            | TokenKind::BoolLit(_) => TokenClass::Keyword,
+            TokenKind::StringLit(_) | TokenKind::InterpolatedString(_) => TokenClass::String,
+            TokenKind::IntLit(_) | TokenKind::FloatLit(_) => TokenClass::Number,
+            TokenKind::Ident(name)
+                if name == "prop"
+                    && matches!(
+                        tokens.get(next_token).map(|t| &t.kind),
+                        Some(TokenKind::StringLit(_))
+                    ) =>
+            {
+                TokenClass::Keyword
+            }
+            TokenKind::Ident(name)
+                if (name == "sig" || name == "val")
+                    && matches!(
+                        tokens.get(next_token).map(|t| &t.kind),
+                        Some(TokenKind::Ident(_))
+                    )
+                    && matches!(
+                        tokens.get(next_token + 1).map(|t| &t.kind),
+                        Some(TokenKind::Colon)
+                    ) =>
+            {
+                TokenClass::Keyword
+            }
+            TokenKind::Ident(name)
+                if name == "where"
+                    && match tokens.get(next_token).map(|t| &t.kind) {
+                        Some(TokenKind::Rec) => true,
+                        Some(TokenKind::Ident(_)) => matches!(
+                            tokens.get(next_token + 1).map(|t| &t.kind),
+                            Some(TokenKind::Eq)
+                        ),
+                        _ => false,
+                    } =>
+            {
+                TokenClass::Keyword
+            }
+            TokenKind::Ident(name) => match resolver.names.get(&tok.span.start) {
+                Some(class) => *class,
+                None => resolver.global_class(name),
+            },
+            _ => continue,
+        };
+        out.push(SemanticToken {
+            span: tok.span,
+            class,
+        });
+    }
+    out
+}
+
+/// Resolves each name in a program to the kind of thing it refers to, keyed
+/// by the start of the name's token.
+struct Resolver {
+    env: TypeEnv,
+    binder_types: HashMap<Span, MonoType>,
+    scopes: Vec<(String, TokenClass)>,
+    names: HashMap<usize, TokenClass>,
+}
+impl Resolver {
+    fn resolve(&mut self, ast: &Ast, decls: &[Decl]) {
+        let mut inferencer = Inferencer::new();
+        inferencer.enable_binder_types();
+        for decl in decls {
+            // Ill-typed declarations still resolve, with less type information
+            if inferencer.infer_decl(&mut self.env, ast, decl).is_err() {
+                inferencer.recover_decl(&mut self.env, ast, decl);
+            }
+            self.binder_types
+                .extend(inferencer.binder_types().iter().cloned());
+            self.visit_decl(ast, decl);
+        }
+    }
+
+    /// A name with no local binding: a constructor, or a global function or value.
+    fn global_class(&self, name: &str) -> TokenClass {
+        if name.starts_with(|c: char| c.is_uppercase()) {
+            TokenClass::Constructor
+        } else if matches!(self.env.lookup(name), Some(s) if matches!(s.ty, MonoType::Arrow(..))) {
+            TokenClass::Function
+        } else {
+            TokenClass::Variable
+        }
+    }
+
+    fn bind(&mut self, span: Span, name: &str) {
+        let class = match self.binder_types.get(&span) {
+            Some(MonoType::Arrow(..)) => TokenClass::Function,
+            _ => TokenClass::Variable,
+        };
+        self.names.insert(span.start, class);
+        self.scopes.push((name.to_string(), class));
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_decl(&mut self, ast: &Ast, decl: &Decl) {
+        let depth = self.scopes.len();
+        match decl {
+            Decl::Let { name, .. } => {
+                let class = self.global_class(&name.node);
+                self.names.insert(name.span.start, class);
+            }
+            Decl::Type {
+                name, type_params, ..
+            } => {
+                self.names.insert(name.span.start, TokenClass::Type);
+                for p in type_params {
+                    self.names.insert(p.span.start, TokenClass::Type);
+                }
+            }
+            _ => {}
+        }
+        walk_decl(self, ast, decl);
+        self.scopes.truncate(depth);
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        let expr = &ast[id];
+        let depth = self.scopes.len();
+        match &expr.node {
+            Expr::Var(name) => {
+                let class = match self.scopes.iter().rev().find(|(n, _)| n == name) {
+                    Some((_, class)) => *class,
+                    None => self.global_class(name),
+                };
+                self.names.insert(expr.span.start, class);
+            }
+            // The name is only in scope in the value when recursive
+            Expr::Let {
+                name,
+                recursive,
+                type_ann,
+                value,
+                body,
+            } => {
+                if let Some(ann) = type_ann {
+                    self.visit_type_ann(ann);
+                }
+                if *recursive {
+                    self.bind(name.span, &name.node);
+                    self.visit_expr(ast, *value);
+                } else {
+                    self.visit_expr(ast, *value);
+                    self.bind(name.span, &name.node);
+                }
+                self.visit_expr(ast, *body);
+            }
+            Expr::Match { scrutinee, arms } => {
+                self.visit_expr(ast, *scrutinee);
+                for arm in arms {
+                    let arm_depth = self.scopes.len();
+                    self.visit_pattern(&arm.pattern);
+                    self.visit_expr(ast, arm.body);
+                    self.scopes.truncate(arm_depth);
+                }
+            }
+            _ => walk_expr(self, ast, id),
+        }
+        self.scopes.truncate(depth);
+    }
+
+    fn visit_pattern(&mut self, pattern: &SpannedPattern) {
+        if let Pattern::Var(name) = &pattern.node {
+            self.bind(pattern.span, name);
+        }
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_param(&mut self, param: &LambdaParam) {
+        self.bind(param.name.span, &param.name.node);
+        walk_param(self, param);
+    }
+
+    fn visit_variant(&mut self, variant: &Variant) {
+        self.names
+            .insert(variant.name.span.start, TokenClass::Constructor);
+        walk_variant(self, variant);
+    }
+
+    fn visit_type_ann(&mut self, ann: &SpannedTypeAnn) {
+        if let TypeAnnotation::Named(_) | TypeAnnotation::Var(_) = &ann.node {
+            self.names.insert(ann.span.start, TokenClass::Type);
+        }
+        walk_type_ann(self, ann);
+    }
+}
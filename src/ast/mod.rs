@@ -1,37 +1,168 @@
 pub mod pretty;
+pub mod visit;
 
+use std::ops::{Index, IndexMut};
+
+use crate::error::LyraError;
 use crate::span::{Span, Spanned};
 
 pub type SpannedExpr = Spanned<Expr>;
 pub type SpannedPattern = Spanned<Pattern>;
 pub type SpannedTypeAnn = Spanned<TypeAnnotation>;
 
+/// An expression's place in its [`Ast`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExprId(u32);
+
+/// The arena a program's expressions live in. A node refers to its
+/// sub-expressions by [`ExprId`], so parsing fills one `Vec` instead of
+/// boxing every node, and sharing a subtree, as a closure shares its body,
+/// is copying an index. Patterns and type annotations hold no expressions
+/// and stay ordinary trees.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ast {
+    exprs: Vec<SpannedExpr>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Ast::default()
+    }
+
+    /// Add an expression, returning its id.
+    pub fn alloc(&mut self, node: Expr, span: Span) -> ExprId {
+        let id = ExprId(u32::try_from(self.exprs.len()).expect("more than 2^32 expressions"));
+        self.exprs.push(Spanned::new(node, span));
+        id
+    }
+
+    /// How many expressions the arena holds.
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+
+    /// Copy the subtree at `id` under fresh ids, for passes that place the
+    /// same code at more than one spot and rewrite each copy separately.
+    pub fn deep_copy(&mut self, id: ExprId) -> ExprId {
+        let expr = self[id].clone();
+        let node = expr.node.map_children(&mut |child| self.deep_copy(child));
+        self.alloc(node, expr.span)
+    }
+
+    /// Copy the subtree at `id` in another arena into this one.
+    pub fn graft(&mut self, from: &Ast, id: ExprId) -> ExprId {
+        let expr = &from[id];
+        let node = expr.node.map_children(&mut |child| self.graft(from, child));
+        self.alloc(node, expr.span)
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = SpannedExpr;
+
+    fn index(&self, id: ExprId) -> &SpannedExpr {
+        &self.exprs[id.0 as usize]
+    }
+}
+
+impl IndexMut<ExprId> for Ast {
+    fn index_mut(&mut self, id: ExprId) -> &mut SpannedExpr {
+        &mut self.exprs[id.0 as usize]
+    }
+}
+
+/// A parsed source file: its declarations and the arena their expressions
+/// live in.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub ast: Ast,
+    pub decls: Vec<Decl>,
+}
+
 /// Top-level declaration.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Decl {
     /// `let x = expr` or `let rec f = expr`
     Let {
         name: Spanned<String>,
         recursive: bool,
         type_ann: Option<SpannedTypeAnn>,
-        body: SpannedExpr,
+        body: ExprId,
+        /// Preceding `---` doc comment, if any.
+        doc: Option<String>,
     },
     /// `type Option a = Some a | None`
     Type {
         name: Spanned<String>,
         type_params: Vec<Spanned<String>>,
         variants: Vec<Variant>,
+        /// Preceding `---` doc comment, if any.
+        doc: Option<String>,
     },
     /// `import "path"`
     Import {
         path: String,
         span: Span,
     },
+    /// `prop "name" (xs : [Int]) -> reverse(reverse(xs)) == xs`, run by `lyra test`.
+    Prop {
+        name: String,
+        params: Vec<LambdaParam>,
+        body: ExprId,
+        span: Span,
+    },
+    /// `macro when(cond, body) = quote if unquote(cond) then unquote(body) else ()`.
+    /// Calls are replaced by the quoted template before checking; see `macros`.
+    Macro {
+        name: Spanned<String>,
+        params: Vec<Spanned<String>>,
+        body: ExprId,
+        span: Span,
+    },
+    /// `extern parse_config : String -> Config`, a value the embedding host
+    /// provides (see `RunOptions::host`), typed by its declaration.
+    Extern {
+        name: Spanned<String>,
+        type_ann: SpannedTypeAnn,
+        /// Preceding `---` doc comment, if any.
+        doc: Option<String>,
+    },
     /// Bare expression (for REPL).
-    Expr(SpannedExpr),
+    Expr(ExprId),
+}
+
+impl Decl {
+    /// The name bound by a `let`, `type` or `extern` declaration.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Decl::Let { name, .. } | Decl::Type { name, .. } | Decl::Extern { name, .. } => {
+                Some(&name.node)
+            }
+            _ => None,
+        }
+    }
+
+    /// The doc comment attached to a `let`, `type` or `extern` declaration.
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Decl::Let { doc, .. } | Decl::Type { doc, .. } | Decl::Extern { doc, .. } => {
+                doc.as_deref()
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Variant {
     pub name: Spanned<String>,
     pub fields: Vec<SpannedTypeAnn>,
@@ -40,6 +171,7 @@ pub struct Variant {
 
 /// Expressions.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     // Literals
     IntLit(i64),
@@ -49,48 +181,62 @@ pub enum Expr {
     UnitLit,
 
     // Collections
-    ListLit(Vec<SpannedExpr>),
-    TupleLit(Vec<SpannedExpr>),
+    ListLit(Vec<ExprId>),
+    TupleLit(Vec<ExprId>),
 
     // Variable reference
     Var(String),
 
+    // Local variable resolved for the tree-walking interpreter: the binding
+    // at `slot` in the scope `depth` levels out; see `eval::resolve`.
+    Local {
+        name: String,
+        depth: usize,
+        slot: usize,
+    },
+
     // Function literal: fn (a, b) -> body
     Lambda {
         params: Vec<LambdaParam>,
-        body: Box<SpannedExpr>,
+        body: ExprId,
     },
 
     // Function application: f(x, y)
     App {
-        func: Box<SpannedExpr>,
-        args: Vec<SpannedExpr>,
+        func: ExprId,
+        args: Vec<ExprId>,
+    },
+
+    // Call with labeled arguments: f(x, age: 36)
+    LabeledApp {
+        func: ExprId,
+        args: Vec<Arg>,
     },
 
     // Binary operation
     BinOp {
         op: BinOp,
-        lhs: Box<SpannedExpr>,
-        rhs: Box<SpannedExpr>,
+        lhs: ExprId,
+        rhs: ExprId,
     },
 
     // Unary operation
     UnaryOp {
         op: UnaryOp,
-        operand: Box<SpannedExpr>,
+        operand: ExprId,
     },
 
     // Pipe: expr |> func
     Pipe {
-        lhs: Box<SpannedExpr>,
-        rhs: Box<SpannedExpr>,
+        lhs: ExprId,
+        rhs: ExprId,
     },
 
     // If expression
     If {
-        cond: Box<SpannedExpr>,
-        then_branch: Box<SpannedExpr>,
-        else_branch: Box<SpannedExpr>,
+        cond: ExprId,
+        then_branch: ExprId,
+        else_branch: ExprId,
     },
 
     // Let expression: let x = e1 in e2
@@ -98,13 +244,13 @@ pub enum Expr {
         name: Spanned<String>,
         recursive: bool,
         type_ann: Option<SpannedTypeAnn>,
-        value: Box<SpannedExpr>,
-        body: Box<SpannedExpr>,
+        value: ExprId,
+        body: ExprId,
     },
 
     // Match expression
     Match {
-        scrutinee: Box<SpannedExpr>,
+        scrutinee: ExprId,
         arms: Vec<MatchArm>,
     },
 
@@ -112,41 +258,203 @@ pub enum Expr {
     Interpolation(Vec<InterpolationPart>),
 
     // Record literal: { name: "Alice", age: 30 }
-    Record(Vec<(String, SpannedExpr)>),
+    Record(Vec<(String, ExprId)>),
 
     // Field access: expr.field
     FieldAccess {
-        expr: Box<SpannedExpr>,
+        expr: ExprId,
         field: String,
     },
+
+    // Slice: xs[1:4], with either bound left out for the start or the end
+    Slice {
+        expr: ExprId,
+        start: Option<ExprId>,
+        end: Option<ExprId>,
+    },
+
+    // Module value: import "utils", a record of the file's top-level lets.
+    // Replaced by the file's contents before checking; see `resolve_modules`.
+    Import(String),
+
+    // Quoted code: quote e, the syntax of `e` as a value; see `eval::quote`.
+    Quote(ExprId),
+
+    // Splice inside a quote: unquote(e), the value of `e` in place of code.
+    Unquote(ExprId),
 }
 
 /// Part of a string interpolation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterpolationPart {
     Literal(String),
-    Expr(SpannedExpr),
+    /// `{expr}` or `{expr:spec}`.
+    Expr(ExprId, Option<FormatSpec>),
+}
+
+/// How an interpolated value is laid out: `[[fill]align][0][width][.precision]`
+/// after a `:`, as in `{pi:.3}`, `{n:>8}` or `{x:*^10}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FormatSpec {
+    pub fill: char,
+    pub align: Option<Align>,
+    /// Pad numbers with zeros after the sign.
+    pub zero: bool,
+    pub width: Option<usize>,
+    /// Digits after the point for floats; maximum length for strings.
+    pub precision: Option<usize>,
+}
+
+/// The largest width or precision a format spec may give, so a typo can't
+/// have a program build a string of gigabytes.
+pub const MAX_FORMAT_WIDTH: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl FormatSpec {
+    /// Parse the text after the `:`; `None` if it isn't a format spec.
+    pub fn parse(spec: &str) -> Option<FormatSpec> {
+        let align_of = |c: char| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+        let chars: Vec<char> = spec.chars().collect();
+        let mut out = FormatSpec {
+            fill: ' ',
+            align: None,
+            zero: false,
+            width: None,
+            precision: None,
+        };
+        let mut i = 0;
+        if let Some(align) = chars.get(1).and_then(|&c| align_of(c)) {
+            out.fill = chars[0];
+            out.align = Some(align);
+            i = 2;
+        } else if let Some(align) = chars.first().and_then(|&c| align_of(c)) {
+            out.align = Some(align);
+            i = 1;
+        }
+        if chars.get(i) == Some(&'0') {
+            out.zero = true;
+            i += 1;
+        }
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while chars.get(*i).is_some_and(char::is_ascii_digit) {
+                *i += 1;
+            }
+            let digits: String = chars[start..*i].iter().collect();
+            // Too many digits for a usize is over the limit too
+            (!digits.is_empty()).then(|| digits.parse().unwrap_or(usize::MAX))
+        };
+        out.width = digits(&mut i);
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            out.precision = Some(digits(&mut i)?);
+        }
+        (i == chars.len()).then_some(out)
+    }
+
+    /// Whether the width and precision are at most `MAX_FORMAT_WIDTH`.
+    pub fn within_limits(&self) -> bool {
+        self.width.into_iter().chain(self.precision).all(|n| n <= MAX_FORMAT_WIDTH)
+    }
+
+    /// Pad formatted text to the width. Numbers align right by default and
+    /// may be zero-padded; everything else aligns left.
+    pub fn pad(&self, text: &str, numeric: bool) -> String {
+        let len = text.chars().count();
+        let Some(width) = self.width.filter(|&w| w > len) else {
+            return text.to_string();
+        };
+        let gap = width - len;
+        if self.zero && numeric && self.align.is_none() {
+            let (sign, digits) = match text.strip_prefix('-') {
+                Some(rest) => ("-", rest),
+                None => ("", text),
+            };
+            return format!("{}{}{}", sign, "0".repeat(gap), digits);
+        }
+        let fill = |n: usize| self.fill.to_string().repeat(n);
+        let align = self
+            .align
+            .unwrap_or(if numeric { Align::Right } else { Align::Left });
+        match align {
+            Align::Left => format!("{}{}", text, fill(gap)),
+            Align::Right => format!("{}{}", fill(gap), text),
+            Align::Center => format!("{}{}{}", fill(gap / 2), text, fill(gap - gap / 2)),
+        }
+    }
+}
+
+impl std::fmt::Display for FormatSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(align) = self.align {
+            if self.fill != ' ' {
+                write!(f, "{}", self.fill)?;
+            }
+            let c = match align {
+                Align::Left => '<',
+                Align::Center => '^',
+                Align::Right => '>',
+            };
+            write!(f, "{}", c)?;
+        }
+        if self.zero {
+            write!(f, "0")?;
+        }
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+        Ok(())
+    }
+}
+
+/// An argument of a labeled call. Positional arguments come first.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Arg {
+    pub label: Option<Spanned<String>>,
+    pub value: ExprId,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LambdaParam {
     pub name: Spanned<String>,
     pub type_ann: Option<SpannedTypeAnn>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MatchArm {
     pub pattern: SpannedPattern,
-    pub body: SpannedExpr,
+    pub body: ExprId,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
     Div,
     Mod,
+    Pow,
     Eq,
     NotEq,
     Lt,
@@ -166,6 +474,7 @@ impl BinOp {
             BinOp::Mul => "*",
             BinOp::Div => "/",
             BinOp::Mod => "%",
+            BinOp::Pow => "**",
             BinOp::Eq => "==",
             BinOp::NotEq => "!=",
             BinOp::Lt => "<",
@@ -180,6 +489,7 @@ impl BinOp {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOp {
     Neg,
     Not,
@@ -187,6 +497,7 @@ pub enum UnaryOp {
 
 /// Patterns for match expressions.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Pattern {
     Wildcard,
     Var(String),
@@ -198,6 +509,11 @@ pub enum Pattern {
     Tuple(Vec<SpannedPattern>),
     List(Vec<SpannedPattern>),
     Cons(Box<SpannedPattern>, Box<SpannedPattern>),
+    /// `"GET " ++ rest`: a string starting with the literal, the rest of it
+    /// matching the pattern.
+    StringPrefix(String, Box<SpannedPattern>),
+    /// `name ++ ".txt"`: a string ending with the literal.
+    StringSuffix(Box<SpannedPattern>, String),
     Constructor {
         name: String,
         args: Vec<SpannedPattern>,
@@ -206,6 +522,7 @@ pub enum Pattern {
 
 /// Type annotations written by the user.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeAnnotation {
     Named(String),                                    // Int, Bool, etc.
     Var(String),                                      // a, b (type variables)
@@ -215,3 +532,289 @@ pub enum TypeAnnotation {
     List(Box<SpannedTypeAnn>),                        // [Int]
     Unit,                                             // ()
 }
+
+impl TypeAnnotation {
+    /// How many arguments a value of this type takes, one at a time: 0 for
+    /// anything but a function.
+    pub fn arity(&self) -> usize {
+        match self {
+            TypeAnnotation::Arrow(_, to) => 1 + to.node.arity(),
+            _ => 0,
+        }
+    }
+}
+
+/// The constructor a possibly qualified name refers to: `Tree.Node` and
+/// `Node` both build and match `Node` values.
+pub fn unqualified(name: &str) -> &str {
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+/// The operands of a left-nested chain `a + b + c`, in order; just `expr`
+/// if it isn't an addition.
+pub fn add_chain(ast: &Ast, expr: ExprId) -> Vec<ExprId> {
+    match &ast[expr].node {
+        Expr::BinOp {
+            op: BinOp::Add,
+            lhs,
+            rhs,
+        } => {
+            let mut operands = add_chain(ast, *lhs);
+            operands.push(*rhs);
+            operands
+        }
+        _ => vec![expr],
+    }
+}
+
+/// The error for an `import` expression checked or run without first being
+/// replaced by its module.
+pub fn unresolved_import(path: &str, span: Span) -> LyraError {
+    LyraError::RuntimeError {
+        message: format!("import \"{}\" was not resolved to a module", path),
+        span,
+    }
+}
+
+impl Expr {
+    /// Parameter names of a function literal, which labeled calls match
+    /// their arguments against.
+    pub fn param_names(&self) -> Option<Vec<String>> {
+        match self {
+            Expr::Lambda { params, .. } => {
+                Some(params.iter().map(|p| p.name.node.clone()).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// The node's sub-expressions, in source order.
+    pub fn children(&self) -> Vec<ExprId> {
+        match self {
+            Expr::IntLit(_)
+            | Expr::FloatLit(_)
+            | Expr::StringLit(_)
+            | Expr::BoolLit(_)
+            | Expr::UnitLit
+            | Expr::Var(_)
+            | Expr::Local { .. }
+            | Expr::Import(_) => Vec::new(),
+            Expr::ListLit(elems) | Expr::TupleLit(elems) => elems.clone(),
+            Expr::Lambda { body, .. } => vec![*body],
+            Expr::App { func, args } => std::iter::once(*func).chain(args.iter().copied()).collect(),
+            Expr::LabeledApp { func, args } => {
+                std::iter::once(*func).chain(args.iter().map(|a| a.value)).collect()
+            }
+            Expr::BinOp { lhs, rhs, .. } | Expr::Pipe { lhs, rhs } => vec![*lhs, *rhs],
+            Expr::UnaryOp { operand, .. } => vec![*operand],
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => vec![*cond, *then_branch, *else_branch],
+            Expr::Let { value, body, .. } => vec![*value, *body],
+            Expr::Match { scrutinee, arms } => {
+                std::iter::once(*scrutinee).chain(arms.iter().map(|a| a.body)).collect()
+            }
+            Expr::Interpolation(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    InterpolationPart::Expr(e, _) => Some(*e),
+                    InterpolationPart::Literal(_) => None,
+                })
+                .collect(),
+            Expr::Record(fields) => fields.iter().map(|(_, e)| *e).collect(),
+            Expr::FieldAccess { expr, .. } | Expr::Quote(expr) | Expr::Unquote(expr) => {
+                vec![*expr]
+            }
+            Expr::Slice { expr, start, end } => {
+                std::iter::once(*expr).chain(*start).chain(*end).collect()
+            }
+        }
+    }
+
+    /// A copy of the node with each sub-expression id replaced by `f` of it,
+    /// in source order.
+    pub fn map_children(&self, f: &mut dyn FnMut(ExprId) -> ExprId) -> Expr {
+        let mut all = |ids: &[ExprId]| ids.iter().map(|&id| f(id)).collect::<Vec<_>>();
+        match self {
+            Expr::IntLit(_)
+            | Expr::FloatLit(_)
+            | Expr::StringLit(_)
+            | Expr::BoolLit(_)
+            | Expr::UnitLit
+            | Expr::Var(_)
+            | Expr::Local { .. }
+            | Expr::Import(_) => self.clone(),
+            Expr::ListLit(elems) => Expr::ListLit(all(elems)),
+            Expr::TupleLit(elems) => Expr::TupleLit(all(elems)),
+            Expr::Lambda { params, body } => Expr::Lambda {
+                params: params.clone(),
+                body: f(*body),
+            },
+            Expr::App { func, args } => {
+                let func = f(*func);
+                Expr::App {
+                    func,
+                    args: args.iter().map(|&a| f(a)).collect(),
+                }
+            }
+            Expr::LabeledApp { func, args } => {
+                let func = f(*func);
+                let args = args
+                    .iter()
+                    .map(|a| Arg {
+                        label: a.label.clone(),
+                        value: f(a.value),
+                    })
+                    .collect();
+                Expr::LabeledApp { func, args }
+            }
+            Expr::BinOp { op, lhs, rhs } => {
+                let lhs = f(*lhs);
+                Expr::BinOp {
+                    op: *op,
+                    lhs,
+                    rhs: f(*rhs),
+                }
+            }
+            Expr::UnaryOp { op, operand } => Expr::UnaryOp {
+                op: *op,
+                operand: f(*operand),
+            },
+            Expr::Pipe { lhs, rhs } => {
+                let lhs = f(*lhs);
+                Expr::Pipe { lhs, rhs: f(*rhs) }
+            }
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond = f(*cond);
+                let then_branch = f(*then_branch);
+                Expr::If {
+                    cond,
+                    then_branch,
+                    else_branch: f(*else_branch),
+                }
+            }
+            Expr::Let {
+                name,
+                recursive,
+                type_ann,
+                value,
+                body,
+            } => {
+                let value = f(*value);
+                Expr::Let {
+                    name: name.clone(),
+                    recursive: *recursive,
+                    type_ann: type_ann.clone(),
+                    value,
+                    body: f(*body),
+                }
+            }
+            Expr::Match { scrutinee, arms } => {
+                let scrutinee = f(*scrutinee);
+                let arms = arms
+                    .iter()
+                    .map(|arm| MatchArm {
+                        pattern: arm.pattern.clone(),
+                        body: f(arm.body),
+                    })
+                    .collect();
+                Expr::Match { scrutinee, arms }
+            }
+            Expr::Interpolation(parts) => Expr::Interpolation(
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        InterpolationPart::Literal(s) => InterpolationPart::Literal(s.clone()),
+                        InterpolationPart::Expr(e, spec) => {
+                            InterpolationPart::Expr(f(*e), spec.clone())
+                        }
+                    })
+                    .collect(),
+            ),
+            Expr::Record(fields) => {
+                Expr::Record(fields.iter().map(|(name, e)| (name.clone(), f(*e))).collect())
+            }
+            Expr::FieldAccess { expr, field } => Expr::FieldAccess {
+                expr: f(*expr),
+                field: field.clone(),
+            },
+            Expr::Slice { expr, start, end } => {
+                let expr = f(*expr);
+                let start = start.map(&mut *f);
+                Expr::Slice {
+                    expr,
+                    start,
+                    end: end.map(f),
+                }
+            }
+            Expr::Quote(inner) => Expr::Quote(f(*inner)),
+            Expr::Unquote(inner) => Expr::Unquote(f(*inner)),
+        }
+    }
+}
+
+/// A name as the source wrote it, without the `#` suffix macro expansion
+/// adds to the names it renames.
+pub fn source_name(name: &str) -> &str {
+    name.split('#').next().unwrap_or(name)
+}
+
+/// Match a labeled call's arguments to the callee's parameters, which are
+/// labeled by their source names: for each parameter, the index of its
+/// argument. The arguments are still evaluated in source order.
+pub fn order_args(
+    ast: &Ast,
+    name: &str,
+    params: &[String],
+    args: &[Arg],
+    span: Span,
+) -> Result<Vec<usize>, LyraError> {
+    if args.len() > params.len() {
+        return Err(LyraError::ArityMismatch {
+            name: name.to_string(),
+            expected: params.len(),
+            found: args.len(),
+            span,
+        });
+    }
+    let mut slots: Vec<Option<usize>> = vec![None; params.len()];
+    for (i, arg) in args.iter().enumerate() {
+        let (slot, label_span) = match &arg.label {
+            None => (i, ast[arg.value].span),
+            Some(label) => match params.iter().position(|p| source_name(p) == label.node) {
+                Some(slot) => (slot, label.span),
+                None => {
+                    return Err(LyraError::LabelMismatch {
+                        name: name.to_string(),
+                        message: format!("no parameter named '{}'", label.node),
+                        span: label.span,
+                    })
+                }
+            },
+        };
+        if slots[slot].replace(i).is_some() {
+            return Err(LyraError::LabelMismatch {
+                name: name.to_string(),
+                message: format!("'{}' is given more than once", source_name(&params[slot])),
+                span: label_span,
+            });
+        }
+    }
+    slots
+        .into_iter()
+        .zip(params)
+        .map(|(slot, param)| {
+            slot.ok_or_else(|| LyraError::LabelMismatch {
+                name: name.to_string(),
+                message: format!("no argument for '{}'", source_name(param)),
+                span,
+            })
+        })
+        .collect()
+}
@@ -2,22 +2,51 @@ use std::fmt;
 
 use crate::ast::*;
 
-impl fmt::Display for Expr {
+/// An expression shown with the arena its sub-expressions live in; see
+/// [`Ast::display`].
+pub struct ExprDisplay<'a> {
+    ast: &'a Ast,
+    expr: ExprId,
+}
+
+/// A declaration shown with the arena its expressions live in; see
+/// [`Decl::display`].
+pub struct DeclDisplay<'a> {
+    ast: &'a Ast,
+    decl: &'a Decl,
+}
+
+impl Ast {
+    /// The expression at `expr` as source-like text.
+    pub fn display(&self, expr: ExprId) -> ExprDisplay<'_> {
+        ExprDisplay { ast: self, expr }
+    }
+}
+
+impl Decl {
+    /// The declaration as source-like text.
+    pub fn display<'a>(&'a self, ast: &'a Ast) -> DeclDisplay<'a> {
+        DeclDisplay { ast, decl: self }
+    }
+}
+
+impl fmt::Display for ExprDisplay<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
+        let show = |id: ExprId| self.ast.display(id);
+        match &self.ast[self.expr].node {
             Expr::IntLit(n) => write!(f, "{}", n),
             Expr::FloatLit(n) => write!(f, "{}", n),
             Expr::BoolLit(b) => write!(f, "{}", b),
             Expr::StringLit(s) => write!(f, "\"{}\"", s),
             Expr::UnitLit => write!(f, "()"),
-            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Var(name) | Expr::Local { name, .. } => write!(f, "{}", name),
             Expr::ListLit(elems) => {
                 write!(f, "[")?;
                 for (i, e) in elems.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{}", e.node)?;
+                    write!(f, "{}", show(*e))?;
                 }
                 write!(f, "]")
             }
@@ -27,7 +56,7 @@ impl fmt::Display for Expr {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{}", e.node)?;
+                    write!(f, "{}", show(*e))?;
                 }
                 write!(f, ")")
             }
@@ -39,27 +68,40 @@ impl fmt::Display for Expr {
                     }
                     write!(f, "{}", p.name.node)?;
                 }
-                write!(f, ") -> {}", body.node)
+                write!(f, ") -> {}", show(*body))
             }
             Expr::App { func, args } => {
-                write!(f, "{}(", func.node)?;
+                write!(f, "{}(", show(*func))?;
                 for (i, a) in args.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{}", a.node)?;
+                    write!(f, "{}", show(*a))?;
+                }
+                write!(f, ")")
+            }
+            Expr::LabeledApp { func, args } => {
+                write!(f, "{}(", show(*func))?;
+                for (i, a) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    if let Some(label) = &a.label {
+                        write!(f, "{}: ", label.node)?;
+                    }
+                    write!(f, "{}", show(a.value))?;
                 }
                 write!(f, ")")
             }
             Expr::BinOp { op, lhs, rhs } => {
-                write!(f, "({} {} {})", lhs.node, op.as_str(), rhs.node)
+                write!(f, "({} {} {})", show(*lhs), op.as_str(), show(*rhs))
             }
             Expr::UnaryOp { op, operand } => match op {
-                UnaryOp::Neg => write!(f, "(-{})", operand.node),
-                UnaryOp::Not => write!(f, "(!{})", operand.node),
+                UnaryOp::Neg => write!(f, "(-{})", show(*operand)),
+                UnaryOp::Not => write!(f, "(!{})", show(*operand)),
             },
             Expr::Pipe { lhs, rhs } => {
-                write!(f, "{} |> {}", lhs.node, rhs.node)
+                write!(f, "{} |> {}", show(*lhs), show(*rhs))
             }
             Expr::If {
                 cond,
@@ -69,7 +111,7 @@ impl fmt::Display for Expr {
                 write!(
                     f,
                     "if {} then {} else {}",
-                    cond.node, then_branch.node, else_branch.node
+                    show(*cond), show(*then_branch), show(*else_branch)
                 )
             }
             Expr::Let {
@@ -80,15 +122,15 @@ impl fmt::Display for Expr {
                 ..
             } => {
                 if *recursive {
-                    write!(f, "let rec {} = {} in {}", name.node, value.node, body.node)
+                    write!(f, "let rec {} = {} in {}", name.node, show(*value), show(*body))
                 } else {
-                    write!(f, "let {} = {} in {}", name.node, value.node, body.node)
+                    write!(f, "let {} = {} in {}", name.node, show(*value), show(*body))
                 }
             }
             Expr::Match { scrutinee, arms } => {
-                write!(f, "match {} with", scrutinee.node)?;
+                write!(f, "match {} with", show(*scrutinee))?;
                 for arm in arms {
-                    write!(f, " | {} -> {}", arm.pattern.node, arm.body.node)?;
+                    write!(f, " | {} -> {}", arm.pattern.node, show(arm.body))?;
                 }
                 Ok(())
             }
@@ -98,7 +140,10 @@ impl fmt::Display for Expr {
                 for part in parts {
                     match part {
                         crate::ast::InterpolationPart::Literal(s) => write!(f, "{}", s)?,
-                        crate::ast::InterpolationPart::Expr(e) => write!(f, "{{{}}}", e.node)?,
+                        crate::ast::InterpolationPart::Expr(e, None) => write!(f, "{{{}}}", show(*e))?,
+                        crate::ast::InterpolationPart::Expr(e, Some(spec)) => {
+                            write!(f, "{{{}:{}}}", show(*e), spec)?
+                        }
                     }
                 }
                 write!(f, "\"")
@@ -110,14 +155,28 @@ impl fmt::Display for Expr {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{}: {}", name, val.node)?;
+                    write!(f, "{}: {}", name, show(*val))?;
                 }
                 write!(f, " }}")
             }
 
             Expr::FieldAccess { expr, field } => {
-                write!(f, "{}.{}", expr.node, field)
+                write!(f, "{}.{}", show(*expr), field)
             }
+            Expr::Slice { expr, start, end } => {
+                write!(f, "{}[", show(*expr))?;
+                if let Some(start) = start {
+                    write!(f, "{}", show(*start))?;
+                }
+                write!(f, ":")?;
+                if let Some(end) = end {
+                    write!(f, "{}", show(*end))?;
+                }
+                write!(f, "]")
+            }
+            Expr::Import(path) => write!(f, "import \"{}\"", path),
+            Expr::Quote(inner) => write!(f, "quote({})", show(*inner)),
+            Expr::Unquote(inner) => write!(f, "unquote({})", show(*inner)),
         }
     }
 }
@@ -152,6 +211,8 @@ impl fmt::Display for Pattern {
                 }
                 write!(f, "]")
             }
+            Pattern::StringPrefix(prefix, rest) => write!(f, "\"{}\" ++ {}", prefix, rest.node),
+            Pattern::StringSuffix(rest, suffix) => write!(f, "{} ++ \"{}\"", rest.node, suffix),
             Pattern::Cons(head, tail) => {
                 write!(f, "{} :: {}", head.node, tail.node)
             }
@@ -202,9 +263,15 @@ impl fmt::Display for TypeAnnotation {
     }
 }
 
-impl fmt::Display for Decl {
+impl fmt::Display for DeclDisplay<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
+        let show = |id: ExprId| self.ast.display(id);
+        if let Some(doc) = self.decl.doc() {
+            for line in doc.lines() {
+                writeln!(f, "--- {}", line)?;
+            }
+        }
+        match self.decl {
             Decl::Let {
                 name,
                 recursive,
@@ -212,15 +279,16 @@ impl fmt::Display for Decl {
                 ..
             } => {
                 if *recursive {
-                    write!(f, "let rec {} = {}", name.node, body.node)
+                    write!(f, "let rec {} = {}", name.node, show(*body))
                 } else {
-                    write!(f, "let {} = {}", name.node, body.node)
+                    write!(f, "let {} = {}", name.node, show(*body))
                 }
             }
             Decl::Type {
                 name,
                 type_params,
                 variants,
+                ..
             } => {
                 write!(f, "type {}", name.node)?;
                 for p in type_params {
@@ -238,9 +306,36 @@ impl fmt::Display for Decl {
                 }
                 Ok(())
             }
-            Decl::Expr(expr) => write!(f, "{}", expr.node),
+            Decl::Expr(expr) => write!(f, "{}", show(*expr)),
 
             Decl::Import { path, .. } => write!(f, "import \"{}\"", path),
+
+            Decl::Extern { name, type_ann, .. } => {
+                write!(f, "extern {} : {}", name.node, type_ann.node)
+            }
+
+            Decl::Prop {
+                name, params, body, ..
+            } => {
+                write!(f, "prop \"{}\" (", name)?;
+                for (i, p) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", p.name.node)?;
+                    if let Some(ann) = &p.type_ann {
+                        write!(f, " : {}", ann.node)?;
+                    }
+                }
+                write!(f, ") -> {}", show(*body))
+            }
+
+            Decl::Macro {
+                name, params, body, ..
+            } => {
+                let params: Vec<&str> = params.iter().map(|p| p.node.as_str()).collect();
+                write!(f, "macro {}({}) = {}", name.node, params.join(", "), show(*body))
+            }
         }
     }
 }
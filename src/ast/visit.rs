@@ -0,0 +1,249 @@
+//! AST traversal.
+//!
+//! Implement [`Visitor`], overriding the `visit_*` methods for the nodes you
+//! care about. Each default method calls the matching `walk_*` function,
+//! which visits the node's children in source order; an override calls it
+//! too to keep descending, or skips it to prune the subtree.
+//!
+//! Expressions are visited by id, with the [`Ast`] they live in.
+//! [`VisitorMut`] is the same over a mutable arena, for passes that rewrite
+//! expressions in place.
+
+use crate::ast::*;
+
+pub trait Visitor {
+    fn visit_decl(&mut self, ast: &Ast, decl: &Decl) {
+        walk_decl(self, ast, decl);
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, expr: ExprId) {
+        walk_expr(self, ast, expr);
+    }
+
+    fn visit_pattern(&mut self, pattern: &SpannedPattern) {
+        walk_pattern(self, pattern);
+    }
+
+    fn visit_param(&mut self, param: &LambdaParam) {
+        walk_param(self, param);
+    }
+
+    fn visit_variant(&mut self, variant: &Variant) {
+        walk_variant(self, variant);
+    }
+
+    fn visit_type_ann(&mut self, ann: &SpannedTypeAnn) {
+        walk_type_ann(self, ann);
+    }
+}
+
+/// Visit every declaration of a program.
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, decls: &[Decl]) {
+    for decl in decls {
+        visitor.visit_decl(ast, decl);
+    }
+}
+
+pub fn walk_decl<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, decl: &Decl) {
+    match decl {
+        Decl::Let { type_ann, body, .. } => {
+            if let Some(ann) = type_ann {
+                visitor.visit_type_ann(ann);
+            }
+            visitor.visit_expr(ast, *body);
+        }
+        Decl::Type { variants, .. } => {
+            for v in variants {
+                visitor.visit_variant(v);
+            }
+        }
+        Decl::Prop { params, body, .. } => {
+            for p in params {
+                visitor.visit_param(p);
+            }
+            visitor.visit_expr(ast, *body);
+        }
+        Decl::Macro { body, .. } => visitor.visit_expr(ast, *body),
+        Decl::Extern { type_ann, .. } => visitor.visit_type_ann(type_ann),
+        Decl::Expr(expr) => visitor.visit_expr(ast, *expr),
+        Decl::Import { .. } => {}
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, ast: &Ast, expr: ExprId) {
+    match &ast[expr].node {
+        Expr::IntLit(_)
+        | Expr::FloatLit(_)
+        | Expr::StringLit(_)
+        | Expr::BoolLit(_)
+        | Expr::UnitLit
+        | Expr::Var(_)
+        | Expr::Local { .. }
+        | Expr::Import(_) => {}
+        Expr::ListLit(elems) | Expr::TupleLit(elems) => {
+            for e in elems {
+                visitor.visit_expr(ast, *e);
+            }
+        }
+        Expr::Lambda { params, body } => {
+            for p in params {
+                visitor.visit_param(p);
+            }
+            visitor.visit_expr(ast, *body);
+        }
+        Expr::App { func, args } => {
+            visitor.visit_expr(ast, *func);
+            for a in args {
+                visitor.visit_expr(ast, *a);
+            }
+        }
+        Expr::LabeledApp { func, args } => {
+            visitor.visit_expr(ast, *func);
+            for a in args {
+                visitor.visit_expr(ast, a.value);
+            }
+        }
+        Expr::BinOp { lhs, rhs, .. } | Expr::Pipe { lhs, rhs } => {
+            visitor.visit_expr(ast, *lhs);
+            visitor.visit_expr(ast, *rhs);
+        }
+        Expr::UnaryOp { operand, .. } => visitor.visit_expr(ast, *operand),
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(ast, *cond);
+            visitor.visit_expr(ast, *then_branch);
+            visitor.visit_expr(ast, *else_branch);
+        }
+        Expr::Let {
+            type_ann,
+            value,
+            body,
+            ..
+        } => {
+            if let Some(ann) = type_ann {
+                visitor.visit_type_ann(ann);
+            }
+            visitor.visit_expr(ast, *value);
+            visitor.visit_expr(ast, *body);
+        }
+        Expr::Match { scrutinee, arms } => {
+            visitor.visit_expr(ast, *scrutinee);
+            for arm in arms {
+                visitor.visit_pattern(&arm.pattern);
+                visitor.visit_expr(ast, arm.body);
+            }
+        }
+        Expr::Interpolation(parts) => {
+            for part in parts {
+                if let InterpolationPart::Expr(e, _) = part {
+                    visitor.visit_expr(ast, *e);
+                }
+            }
+        }
+        Expr::Record(fields) => {
+            for (_, e) in fields {
+                visitor.visit_expr(ast, *e);
+            }
+        }
+        Expr::FieldAccess { expr, .. } | Expr::Quote(expr) | Expr::Unquote(expr) => {
+            visitor.visit_expr(ast, *expr)
+        }
+        Expr::Slice { expr, start, end } => {
+            visitor.visit_expr(ast, *expr);
+            for bound in [start, end].into_iter().flatten() {
+                visitor.visit_expr(ast, *bound);
+            }
+        }
+    }
+}
+
+pub fn walk_pattern<V: Visitor + ?Sized>(visitor: &mut V, pattern: &SpannedPattern) {
+    match &pattern.node {
+        Pattern::Wildcard
+        | Pattern::Var(_)
+        | Pattern::IntLit(_)
+        | Pattern::FloatLit(_)
+        | Pattern::StringLit(_)
+        | Pattern::BoolLit(_)
+        | Pattern::UnitLit => {}
+        Pattern::Tuple(elems) | Pattern::List(elems) => {
+            for p in elems {
+                visitor.visit_pattern(p);
+            }
+        }
+        Pattern::Cons(head, tail) => {
+            visitor.visit_pattern(head);
+            visitor.visit_pattern(tail);
+        }
+        Pattern::StringPrefix(_, rest) | Pattern::StringSuffix(rest, _) => {
+            visitor.visit_pattern(rest);
+        }
+        Pattern::Constructor { args, .. } => {
+            for p in args {
+                visitor.visit_pattern(p);
+            }
+        }
+    }
+}
+
+pub fn walk_param<V: Visitor + ?Sized>(visitor: &mut V, param: &LambdaParam) {
+    if let Some(ann) = &param.type_ann {
+        visitor.visit_type_ann(ann);
+    }
+}
+
+pub fn walk_variant<V: Visitor + ?Sized>(visitor: &mut V, variant: &Variant) {
+    for field in &variant.fields {
+        visitor.visit_type_ann(field);
+    }
+}
+
+pub fn walk_type_ann<V: Visitor + ?Sized>(visitor: &mut V, ann: &SpannedTypeAnn) {
+    match &ann.node {
+        TypeAnnotation::Named(_) | TypeAnnotation::Var(_) | TypeAnnotation::Unit => {}
+        TypeAnnotation::Arrow(a, b) => {
+            visitor.visit_type_ann(a);
+            visitor.visit_type_ann(b);
+        }
+        TypeAnnotation::App(base, args) => {
+            visitor.visit_type_ann(base);
+            for a in args {
+                visitor.visit_type_ann(a);
+            }
+        }
+        TypeAnnotation::Tuple(elems) => {
+            for e in elems {
+                visitor.visit_type_ann(e);
+            }
+        }
+        TypeAnnotation::List(inner) => visitor.visit_type_ann(inner),
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: ExprId) {
+        walk_expr_mut(self, ast, expr);
+    }
+}
+
+/// Visit every expression of a program mutably.
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, decls: &[Decl]) {
+    for decl in decls {
+        match decl {
+            Decl::Let { body, .. } | Decl::Prop { body, .. } | Decl::Macro { body, .. } => {
+                visitor.visit_expr_mut(ast, *body)
+            }
+            Decl::Expr(expr) => visitor.visit_expr_mut(ast, *expr),
+            Decl::Type { .. } | Decl::Import { .. } | Decl::Extern { .. } => {}
+        }
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ast: &mut Ast, expr: ExprId) {
+    for child in ast[expr].node.children() {
+        visitor.visit_expr_mut(ast, child);
+    }
+}
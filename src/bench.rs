@@ -0,0 +1,264 @@
+//! Benchmarks: the `bench` builtin and `lyra bench`.
+//!
+//! A benchmark is a top-level `let bench_<name> = fn () -> ...`. `lyra bench`
+//! loads a file, or every `.lyra` file in a directory, and calls each
+//! benchmark in it repeatedly: a fixed number of times, or until a time
+//! budget is spent. The results are printed as one table, with each mean
+//! compared to the fastest.
+
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::ast::{Decl, Program};
+use crate::error::LyraError;
+use crate::eval::env::Env;
+use crate::eval::value::Value;
+use crate::span::Span;
+use crate::types::env::TypeEnv;
+use crate::types::infer::Inferencer;
+use crate::types::TypeVarGen;
+use crate::vm::VM;
+use crate::{compiler, eval, parse_file, stdlib, Imports};
+
+/// Prefix of the top-level names `lyra bench` runs.
+pub const PREFIX: &str = "bench_";
+
+/// Most calls a benchmark gets while filling its time budget.
+const MAX_ITERATIONS: usize = 1_000_000;
+
+/// Timings of repeated calls of a function.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    pub iterations: usize,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    pub fn mean(&self) -> Duration {
+        self.total.div_f64(self.iterations.max(1) as f64)
+    }
+
+    /// The record `bench` returns, with times in milliseconds.
+    pub fn to_value(&self) -> Value {
+        let ms = |d: Duration| Value::Float(d.as_secs_f64() * 1000.0);
+        let mut fields = BTreeMap::new();
+        fields.insert("iterations".to_string(), Value::Int(self.iterations as i64));
+        fields.insert("total_ms".to_string(), ms(self.total));
+        fields.insert("mean_ms".to_string(), ms(self.mean()));
+        fields.insert("min_ms".to_string(), ms(self.min));
+        fields.insert("max_ms".to_string(), ms(self.max));
+        Value::Record(fields)
+    }
+}
+
+/// How long each benchmark runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Iterations {
+    /// Exactly this many calls.
+    Fixed(usize),
+    /// As many calls as fit in this long, but at least one.
+    For(Duration),
+}
+
+impl Default for Iterations {
+    fn default() -> Self {
+        Iterations::For(Duration::from_millis(500))
+    }
+}
+
+/// Options for `lyra bench`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BenchConfig {
+    pub iterations: Iterations,
+    /// Run on the bytecode VM rather than the tree-walking interpreter.
+    pub vm: bool,
+}
+
+/// One benchmark's timings.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    pub stats: Stats,
+}
+
+/// Call `f` as `iterations` says, timing each call. The first error stops it.
+pub fn measure<E>(
+    iterations: Iterations,
+    mut f: impl FnMut() -> Result<(), E>,
+) -> Result<Stats, E> {
+    let mut stats = Stats {
+        iterations: 0,
+        total: Duration::ZERO,
+        min: Duration::MAX,
+        max: Duration::ZERO,
+    };
+    loop {
+        let done = match iterations {
+            Iterations::Fixed(n) => stats.iterations >= n,
+            Iterations::For(budget) => {
+                stats.iterations > 0
+                    && (stats.total >= budget || stats.iterations >= MAX_ITERATIONS)
+            }
+        };
+        if done {
+            break;
+        }
+        let start = Instant::now();
+        f()?;
+        let elapsed = start.elapsed();
+        stats.iterations += 1;
+        stats.total += elapsed;
+        stats.min = stats.min.min(elapsed);
+        stats.max = stats.max.max(elapsed);
+    }
+    if stats.iterations == 0 {
+        stats.min = Duration::ZERO;
+    }
+    Ok(stats)
+}
+
+/// Type-check and load a file (with its imports), then run every benchmark
+/// in it. Top-level expressions are skipped so loading doesn't print.
+pub fn run_benches(
+    source: &str,
+    filename: &str,
+    config: BenchConfig,
+) -> Result<Vec<BenchResult>, LyraError> {
+    let Program { mut ast, mut decls } = parse_file(source, filename, Imports::Inline)?.program;
+    decls.retain(|decl| !matches!(decl, Decl::Expr(_)));
+    eval::resolve::resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
+
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+    for decl in &decls {
+        inferencer.infer_decl(&mut type_env, &ast, decl)?;
+    }
+
+    let benches: Vec<(&String, Span)> = decls
+        .iter()
+        .filter_map(|decl| match decl {
+            Decl::Let { name, .. } if name.node.starts_with(PREFIX) => {
+                Some((&name.node, name.span))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut vm = VM::new();
+    if config.vm {
+        stdlib::register_vm_stdlib(&mut vm);
+        let main = compiler::compile(&ast, &decls).map_err(|message| LyraError::RuntimeError {
+            message,
+            span: Span::default(),
+        })?;
+        vm.run(main)?;
+    } else {
+        for decl in &decls {
+            eval::eval_decl(&runtime_env, &ast, decl)?;
+        }
+    }
+
+    let mut results = Vec::new();
+    for (name, span) in benches {
+        let value = if config.vm {
+            vm.global(name)
+        } else {
+            runtime_env.get(name)
+        };
+        let func = match value {
+            Some(func) if func.is_callable() && func.total_arity() == 0 => func,
+            _ => {
+                return Err(LyraError::RuntimeError {
+                    message: format!("benchmark '{}' must be a function of no arguments", name),
+                    span,
+                })
+            }
+        };
+        let stats = measure(config.iterations, || call(&mut vm, &func, span).map(|_| ()))?;
+        results.push(BenchResult {
+            name: name.clone(),
+            stats,
+        });
+    }
+    Ok(results)
+}
+
+/// Call a benchmark with no arguments, on `vm` if it was compiled.
+fn call(vm: &mut VM, func: &Value, span: Span) -> Result<Value, LyraError> {
+    match func {
+        Value::Function(proto) => vm.call_function(Rc::clone(proto), Vec::new()),
+        Value::ClosureVal { proto, upvalues } => {
+            vm.call_closure(Rc::clone(proto), Rc::clone(upvalues), Vec::new())
+        }
+        _ => eval::apply_function(func.clone(), Vec::new(), span),
+    }
+}
+
+/// The results as a table, each mean compared with the fastest.
+pub fn table(results: &[BenchResult]) -> String {
+    let fastest = results
+        .iter()
+        .map(|r| r.stats.mean())
+        .min()
+        .unwrap_or_default();
+    let mut rows = vec![[
+        "benchmark".to_string(),
+        "iterations".to_string(),
+        "mean".to_string(),
+        "min".to_string(),
+        "max".to_string(),
+        "relative".to_string(),
+    ]];
+    for result in results {
+        let stats = &result.stats;
+        let relative = if fastest.is_zero() {
+            1.0
+        } else {
+            stats.mean().as_secs_f64() / fastest.as_secs_f64()
+        };
+        rows.push([
+            result.name.clone(),
+            stats.iterations.to_string(),
+            duration(stats.mean()),
+            duration(stats.min),
+            duration(stats.max),
+            format!("{:.2}x", relative),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..6)
+        .map(|col| rows.iter().map(|row| row[col].chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in &rows {
+        // The name is left-aligned and the numbers right-aligned
+        let mut line = format!("{:<w$}", row[0], w = widths[0]);
+        for (cell, width) in row.iter().zip(&widths).skip(1) {
+            line.push_str(&format!("  {:>w$}", cell, w = width));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// A duration in the largest unit that keeps it at least 1.
+fn duration(d: Duration) -> String {
+    let ns = d.as_nanos();
+    if ns < 1_000 {
+        format!("{} ns", ns)
+    } else if ns < 1_000_000 {
+        format!("{:.2} µs", ns as f64 / 1e3)
+    } else if ns < 1_000_000_000 {
+        format!("{:.2} ms", ns as f64 / 1e6)
+    } else {
+        format!("{:.2} s", ns as f64 / 1e9)
+    }
+}
@@ -0,0 +1,125 @@
+//! Execution budgets: cap how many steps a program may take, or how long it
+//! may run. The VM counts instructions and the tree-walker counts evaluated
+//! expressions against the same per-thread meter, so callbacks between the
+//! two share one budget.
+//!
+//! The same steps check for an interrupt: a flag another thread sets, as the
+//! REPL's Ctrl-C handler does, to stop just the evaluation in progress.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::error::LyraError;
+use crate::span::Span;
+
+/// How often, in steps, the wall clock is consulted.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Limits for one run. The default is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Budget {
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Clone, Copy)]
+struct Meter {
+    budget: Budget,
+    steps: u64,
+    deadline: Option<Instant>,
+    exceeded: bool,
+}
+
+thread_local! {
+    static METER: Cell<Option<Meter>> = const { Cell::new(None) };
+    static INTERRUPT: Cell<Option<&'static AtomicBool>> = const { Cell::new(None) };
+}
+
+/// Run `f` under `budget`. Budgets don't nest: the innermost one applies,
+/// and the outer one is restored afterwards.
+pub fn with_budget<T>(budget: Budget, f: impl FnOnce() -> T) -> T {
+    if budget == Budget::default() {
+        return f();
+    }
+    let meter = Meter {
+        budget,
+        steps: 0,
+        deadline: budget.timeout.map(|t| Instant::now() + t),
+        exceeded: false,
+    };
+    let outer = METER.with(|m| m.replace(Some(meter)));
+    // Restore the outer meter even if `f` panics
+    struct Restore(Option<Meter>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            METER.with(|m| m.set(self.0));
+        }
+    }
+    let _restore = Restore(outer);
+    f()
+}
+
+/// Run `f`, stopping it at its next step once `flag` is set. The flag is
+/// cleared first, so an interrupt that came between runs doesn't stop this
+/// one. Like budgets, the innermost flag applies.
+pub fn interruptible<T>(flag: &'static AtomicBool, f: impl FnOnce() -> T) -> T {
+    flag.store(false, Ordering::Relaxed);
+    let outer = INTERRUPT.with(|i| i.replace(Some(flag)));
+    struct Restore(Option<&'static AtomicBool>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            INTERRUPT.with(|i| i.set(self.0));
+        }
+    }
+    let _restore = Restore(outer);
+    f()
+}
+
+fn interrupted() -> bool {
+    INTERRUPT.with(|i| i.get().is_some_and(|flag| flag.load(Ordering::Relaxed)))
+}
+
+/// Count one step. `false` means the budget is spent or the run was
+/// interrupted; the caller should stop with [`exceeded`]. Every later step
+/// fails too, so nested calls unwind.
+pub fn tick() -> bool {
+    if interrupted() {
+        return false;
+    }
+    METER.with(|m| {
+        let Some(mut meter) = m.get() else {
+            return true;
+        };
+        meter.steps += 1;
+        let over_steps = meter.budget.max_steps.is_some_and(|max| meter.steps > max);
+        let over_time = meter.steps % CLOCK_INTERVAL == 0
+            && meter.deadline.is_some_and(|d| Instant::now() >= d);
+        meter.exceeded |= over_steps || over_time;
+        m.set(Some(meter));
+        !meter.exceeded
+    })
+}
+
+/// Whether the current budget has been spent or the run interrupted.
+pub fn is_exceeded() -> bool {
+    interrupted() || METER.with(|m| m.get().is_some_and(|meter| meter.exceeded))
+}
+
+/// The error for a spent budget, describing which limit was hit, or for an
+/// interrupt.
+pub fn exceeded(span: Span) -> LyraError {
+    if interrupted() {
+        return LyraError::Interrupted { span };
+    }
+    let limit = METER.with(|m| m.get()).map_or_else(String::new, |meter| {
+        match meter.budget.max_steps {
+            Some(max) if meter.steps > max => format!("more than {} steps", max),
+            _ => match meter.budget.timeout {
+                Some(t) => format!("ran longer than {:?}", t),
+                None => String::new(),
+            },
+        }
+    });
+    LyraError::BudgetExceeded { limit, span }
+}
@@ -0,0 +1,64 @@
+//! Standalone executables, for `lyra build`. An executable is a copy of the
+//! `lyra` binary with a compiled program appended, then a trailer holding the
+//! program's length and a marker. On startup `lyra` looks for the trailer on
+//! its own file and, if it's there, runs the program instead of reading its
+//! arguments, so the result runs on machines without Lyra installed.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::compiler::lyrc::{self, Program};
+
+const MARKER: &[u8; 8] = b"LYRAEXE1";
+/// The program's length as a little-endian `u64`, then the marker.
+const TRAILER_LEN: u64 = 16;
+
+/// The bytes of an executable that runs `program`, given those of a `lyra`
+/// binary. A binary that already carries a program has it replaced.
+pub fn executable(runtime: &[u8], program: &Program) -> Vec<u8> {
+    let runtime = trailer(runtime)
+        .and_then(|len| runtime.len().checked_sub((len + TRAILER_LEN) as usize))
+        .map_or(runtime, |end| &runtime[..end]);
+    let payload = lyrc::encode(program);
+    let mut out = Vec::with_capacity(runtime.len() + payload.len() + TRAILER_LEN as usize);
+    out.extend_from_slice(runtime);
+    out.extend_from_slice(&payload);
+    out.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    out.extend_from_slice(MARKER);
+    out
+}
+
+/// The program embedded in the executable at `path`, if it has one. Only
+/// the trailer is read from a binary without one.
+pub fn embedded_program(path: &Path) -> io::Result<Option<Program>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    if size < TRAILER_LEN {
+        return Ok(None);
+    }
+    let mut end = [0; TRAILER_LEN as usize];
+    file.seek(SeekFrom::Start(size - TRAILER_LEN))?;
+    file.read_exact(&mut end)?;
+    let Some(len) = trailer(&end) else {
+        return Ok(None);
+    };
+    let start = (size - TRAILER_LEN)
+        .checked_sub(len)
+        .ok_or_else(|| invalid("embedded program is longer than the executable".to_string()))?;
+    let mut payload = vec![0; len as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut payload)?;
+    lyrc::decode(&payload).map(Some).map_err(invalid)
+}
+
+/// The length of the program before the trailer ending `bytes`, if any.
+fn trailer(bytes: &[u8]) -> Option<u64> {
+    let end = bytes.len().checked_sub(TRAILER_LEN as usize)?;
+    let (len, marker) = bytes[end..].split_at(8);
+    (marker == MARKER).then(|| u64::from_le_bytes(len.try_into().unwrap()))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -1,3 +1,7 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::{Ast, ExprId, FormatSpec};
 use crate::eval::value::Value;
 use crate::span::Span;
 
@@ -22,6 +26,8 @@ pub enum Op {
     SetLocal(usize),
     /// Push captured upvalue.
     GetUpvalue(usize),
+    /// Push the function currently executing, for recursive local lets.
+    GetSelf,
     /// Push global variable by name.
     GetGlobal(String),
     /// Define a global variable.
@@ -33,6 +39,7 @@ pub enum Op {
     Mul,
     Div,
     Mod,
+    Pow,
     Negate,
 
     // ── Comparison ──
@@ -59,6 +66,9 @@ pub enum Op {
     Call(u8),
     /// Tail call: reuse current frame.
     TailCall(u8),
+    /// Call function with no arguments, written `name()`: the variadic
+    /// builtin of that name runs, where `Call(0)` would leave it as it is.
+    CallBare(String),
     /// Return from function.
     Return,
     /// Create a closure from function prototype at constant index.
@@ -80,10 +90,17 @@ pub enum Op {
     TestTag(String, usize),
     /// Test if top of stack equals the int literal. Jump if not.
     TestInt(i64, usize),
+    /// Test if top of stack equals the float literal, exactly as `==` would
+    /// (so `0.0` matches `-0.0` and NaN matches nothing). Jump if not.
+    TestFloat(f64, usize),
     /// Test if top of stack equals the bool. Jump if not.
     TestBool(bool, usize),
     /// Test if top of stack equals the string. Jump if not.
     TestString(String, usize),
+    /// Test if top of stack is a string starting with the given one. Jump if not.
+    TestStringPrefix(String, usize),
+    /// Test if top of stack is a string ending with the given one. Jump if not.
+    TestStringSuffix(String, usize),
     /// Test if top of stack is unit. Jump if not.
     TestUnit(usize),
     /// Test if list is empty. Jump if not.
@@ -92,6 +109,8 @@ pub enum Op {
     TestCons(usize),
     /// Test if tuple has N elements. Jump if not.
     TestTuple(usize, usize),
+    /// Test if list has exactly N elements. Jump if not.
+    TestListLen(usize, usize),
     /// Duplicate top of stack.
     Dup,
     /// Get field at index from ADT on top of stack.
@@ -102,8 +121,18 @@ pub enum Op {
     GetListTail,
     /// Get tuple element at index.
     GetTupleField(usize),
+    /// Get list element at index.
+    GetListElement(usize),
+    /// Get a string without its first and last so many bytes.
+    GetStringSlice(usize, usize),
+
+    // ── Host bindings ──
+    /// Check the host defined the global for an `extern` taking N arguments.
+    CheckExtern(String, usize),
     /// Pop and discard (for failed pattern cleanup).
     PopMatch,
+    /// No arm matched the value on top of the stack; the patterns tried.
+    MatchFail(Vec<String>),
 
     // ── Records (Phase 3) ──
     /// Create record from N key-value pairs.
@@ -111,11 +140,30 @@ pub enum Op {
     /// Get field from record.
     GetField(String),
 
+    // ── Quoted code ──
+    /// Build the value of `quote e`, splicing in the N values on the stack.
+    /// `e` is copied out of the program into an arena of its own.
+    Quote(Rc<Ast>, ExprId, usize),
+
     // ── String interpolation (Phase 2) ──
     /// Convert top of stack to string.
     ToString,
-    /// Concatenate two strings.
-    StringConcat,
+    /// Convert top of stack to string laid out by a format spec.
+    Format(FormatSpec),
+    /// Concatenate the top N strings into one, first pushed first.
+    BuildString(usize),
+    /// Pop end, start and a list or string; push `value[start:end]`. A
+    /// bound of `()` was left out.
+    Slice,
+
+    // ── Superinstructions ──
+    // Made by `Chunk::fuse` from the ops they stand for, never emitted.
+    /// `GetLocal(a); GetLocal(b); Add`.
+    GetLocal2Add(usize, usize),
+    /// `Constant(idx); Add`: add the constant to the top of stack.
+    ConstantAdd(usize),
+    /// A comparison, then `JumpIfFalse(offset)`.
+    CompareJump(Comparison, usize),
 
     /// Swap top two stack values.
     Swap,
@@ -128,6 +176,164 @@ pub enum Op {
     PrintRaw,
 }
 
+impl Op {
+    /// How many values running this op adds to the stack, or removes if
+    /// negative. Ops that leave the function count as if they continued, so
+    /// the code after them, reached by a jump, is counted from the right
+    /// height.
+    pub fn stack_effect(&self) -> isize {
+        match self {
+            Op::Constant(_)
+            | Op::Unit
+            | Op::True
+            | Op::False
+            | Op::Dup
+            | Op::GetLocal(_)
+            | Op::GetUpvalue(_)
+            | Op::GetSelf
+            | Op::GetGlobal(_)
+            | Op::Closure(..)
+            | Op::GetAdtField(_)
+            | Op::GetListHead
+            | Op::GetListTail
+            | Op::GetTupleField(_)
+            | Op::GetListElement(_)
+            | Op::GetStringSlice(..)
+            | Op::GetLocal2Add(..) => 1,
+            Op::Pop
+            | Op::PopMatch
+            | Op::DefineGlobal(_)
+            | Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Mod
+            | Op::Pow
+            | Op::Equal
+            | Op::NotEqual
+            | Op::Less
+            | Op::Greater
+            | Op::LessEqual
+            | Op::GreaterEqual
+            | Op::Cons
+            | Op::Return
+            | Op::CompareJump(..) => -1,
+            // The callee and its arguments are replaced by the result
+            Op::Call(n) | Op::TailCall(n) => -(*n as isize),
+            Op::MakeList(n)
+            | Op::MakeTuple(n)
+            | Op::MakeAdt(_, n)
+            | Op::Quote(_, _, n)
+            | Op::BuildString(n) => 1 - *n as isize,
+            Op::MakeRecord(names) => 1 - names.len() as isize,
+            Op::PopUnder(n) => -(*n as isize),
+            // Fails with the value a failed test left, in place of the result
+            Op::MatchFail(_) => 0,
+            Op::Slice => -2,
+            Op::SetLocal(_)
+            | Op::ConstantAdd(_)
+            | Op::CheckExtern(..)
+            | Op::Negate
+            | Op::Not
+            | Op::Jump(_)
+            | Op::JumpIfFalse(_)
+            | Op::Loop(_)
+            | Op::TestTag(..)
+            | Op::TestInt(..)
+            | Op::TestFloat(..)
+            | Op::TestBool(..)
+            | Op::TestString(..)
+            | Op::TestStringPrefix(..)
+            | Op::TestStringSuffix(..)
+            | Op::TestUnit(_)
+            | Op::TestEmptyList(_)
+            | Op::TestCons(_)
+            | Op::TestTuple(..)
+            | Op::TestListLen(..)
+            | Op::GetField(_)
+            | Op::ToString
+            | Op::Format(_)
+            | Op::Swap
+            | Op::CallBare(_)
+            | Op::Print
+            | Op::PrintRaw => 0,
+        }
+    }
+}
+
+impl Op {
+    /// How many values this op reads off the top of the stack, whether it
+    /// pops them or not.
+    fn operands(&self) -> usize {
+        match self {
+            Op::Add
+            | Op::Sub
+            | Op::Mul
+            | Op::Div
+            | Op::Mod
+            | Op::Pow
+            | Op::Equal
+            | Op::NotEqual
+            | Op::Less
+            | Op::Greater
+            | Op::LessEqual
+            | Op::GreaterEqual
+            | Op::CompareJump(..)
+            | Op::Cons
+            | Op::Swap => 2,
+            Op::Slice => 3,
+            Op::Call(n) | Op::TailCall(n) => *n as usize + 1,
+            Op::PopUnder(n) => n + 1,
+            Op::MakeList(n)
+            | Op::MakeTuple(n)
+            | Op::MakeAdt(_, n)
+            | Op::Quote(_, _, n)
+            | Op::BuildString(n) => *n,
+            Op::MakeRecord(names) => names.len(),
+            Op::Constant(_)
+            | Op::Unit
+            | Op::True
+            | Op::False
+            | Op::GetLocal(_)
+            | Op::GetUpvalue(_)
+            | Op::GetSelf
+            | Op::GetGlobal(_)
+            | Op::Closure(..)
+            | Op::GetLocal2Add(..)
+            | Op::CheckExtern(..)
+            | Op::Jump(_)
+            | Op::Loop(_) => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// The comparison a `CompareJump` makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+}
+
+impl Comparison {
+    /// The comparison `op` makes, if it's one.
+    fn of(op: &Op) -> Option<Comparison> {
+        Some(match op {
+            Op::Equal => Comparison::Equal,
+            Op::NotEqual => Comparison::NotEqual,
+            Op::Less => Comparison::Less,
+            Op::Greater => Comparison::Greater,
+            Op::LessEqual => Comparison::LessEqual,
+            Op::GreaterEqual => Comparison::GreaterEqual,
+            _ => return None,
+        })
+    }
+}
+
 /// Reference to a captured variable for closures.
 #[derive(Debug, Clone)]
 pub struct UpvalueRef {
@@ -147,12 +353,165 @@ pub struct FunctionProto {
     pub upvalue_count: usize,
 }
 
+impl FunctionProto {
+    /// Check code that didn't come from the compiler, such as a loaded
+    /// `.lyrc` file, before it runs: that every constant, upvalue and local
+    /// an op names is there, that every jump lands inside the code, and
+    /// that the stack is as high where a jump lands as where it left and
+    /// never runs out. Functions in the constant pool are checked too.
+    pub fn validate(&self) -> Result<(), String> {
+        let chunk = &self.chunk;
+        let fail = |i: usize, problem: String| {
+            Err(format!("invalid bytecode in '{}' at op {}: {}", self.name, i, problem))
+        };
+        // The stack height at each op, from the ops that jump there until
+        // it's reached
+        let mut expected: Vec<Option<usize>> = vec![None; chunk.code.len() + 1];
+        let mut depth = Some(self.arity as usize);
+        for (i, op) in chunk.code.iter().enumerate() {
+            // After a jump or return, the code is only reached by jumping
+            let here = match (depth, expected[i]) {
+                (Some(d), Some(e)) if d != e => {
+                    return fail(i, format!("stack is {} high here by one path, {} by another", d, e))
+                }
+                (d, e) => d.or(e),
+            };
+            let Some(here) = here else {
+                return fail(i, "op is never reached".to_string());
+            };
+            expected[i] = Some(here);
+            if let Some(problem) = self.bad_operand(op, here) {
+                return fail(i, problem);
+            }
+            let after = here.checked_add_signed(op.stack_effect());
+            let Some(after) = after.filter(|_| op.operands() <= here) else {
+                return fail(i, format!("takes {} values from a stack {} high", op.operands(), here));
+            };
+            // Where the op jumps, if it's a jump, unless that's out of range
+            let target = match &mut op.clone() {
+                Op::Loop(offset) => Some((i + 1).checked_sub(*offset)),
+                op => forward_offset(op).map(|offset| (i + 1).checked_add(*offset)),
+            };
+            if let Some(target) = target {
+                let Some(target) = target.filter(|&t| t <= chunk.code.len()) else {
+                    return fail(i, "jumps outside the code".to_string());
+                };
+                match expected[target] {
+                    Some(e) if e != after => {
+                        return fail(i, format!("jumps with the stack {} high to where it's {}", after, e))
+                    }
+                    _ => expected[target] = Some(after),
+                }
+            }
+            depth = match op {
+                Op::Jump(_) | Op::Loop(_) | Op::Return | Op::MatchFail(_) => None,
+                _ => Some(after),
+            };
+        }
+        for constant in &chunk.constants {
+            if let Value::Function(proto) = constant {
+                proto.validate()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// What's wrong with `op`'s operands, run with the stack `depth` high.
+    fn bad_operand(&self, op: &Op, depth: usize) -> Option<String> {
+        let constants = &self.chunk.constants;
+        let local = |slot: usize| (slot >= depth).then(|| format!("no local slot {}", slot));
+        match op {
+            Op::Constant(idx) | Op::ConstantAdd(idx) if *idx >= constants.len() => {
+                Some(format!("no constant {}", idx))
+            }
+            Op::GetUpvalue(idx) if *idx >= self.upvalue_count => Some(format!("no upvalue {}", idx)),
+            Op::GetLocal(slot) | Op::SetLocal(slot) => local(*slot),
+            Op::GetLocal2Add(a, b) => local(*a).or_else(|| local(*b)),
+            Op::Closure(idx, refs) => {
+                let Some(Value::Function(proto)) = constants.get(*idx) else {
+                    return Some(format!("constant {} isn't a function", idx));
+                };
+                if refs.len() != proto.upvalue_count {
+                    return Some(format!(
+                        "'{}' captures {} values, not {}",
+                        proto.name,
+                        proto.upvalue_count,
+                        refs.len()
+                    ));
+                }
+                refs.iter().find_map(|r| {
+                    if r.is_local {
+                        local(r.index)
+                    } else {
+                        (r.index >= self.upvalue_count).then(|| format!("no upvalue {}", r.index))
+                    }
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
 /// A chunk of bytecode with its constant pool.
 #[derive(Debug, Clone)]
 pub struct Chunk {
     pub code: Vec<Op>,
     pub constants: Vec<Value>,
-    pub spans: Vec<Span>,
+    /// The ops' spans, run-length encoded: the index of the first op of
+    /// each run of ops sharing a span, with that span. See `span`.
+    spans: Vec<(usize, Span)>,
+    /// Where each literal already in `constants` is, so it's stored once.
+    pooled: HashMap<Literal, usize>,
+}
+
+/// The offset a forward jump or a pattern test jumps by, if `op` is one.
+fn forward_offset(op: &mut Op) -> Option<&mut usize> {
+    match op {
+        Op::Jump(target)
+        | Op::JumpIfFalse(target)
+        | Op::CompareJump(_, target)
+        | Op::TestTag(_, target)
+        | Op::TestInt(_, target)
+        | Op::TestFloat(_, target)
+        | Op::TestBool(_, target)
+        | Op::TestString(_, target)
+        | Op::TestStringPrefix(_, target)
+        | Op::TestStringSuffix(_, target)
+        | Op::TestUnit(target)
+        | Op::TestEmptyList(target)
+        | Op::TestCons(target)
+        | Op::TestTuple(_, target)
+        | Op::TestListLen(_, target) => Some(target),
+        _ => None,
+    }
+}
+
+/// The superinstruction for the ops at the start of `code`, if they make
+/// one: the op, how many it replaces, and which of them has its span.
+fn fused(code: &[Op]) -> Option<(Op, usize, usize)> {
+    match code {
+        [Op::GetLocal(a), Op::GetLocal(b), Op::Add, ..] => Some((Op::GetLocal2Add(*a, *b), 3, 2)),
+        [Op::Constant(idx), Op::Add, ..] => Some((Op::ConstantAdd(*idx), 2, 1)),
+        [compare, Op::JumpIfFalse(offset), ..] => {
+            Comparison::of(compare).map(|cmp| (Op::CompareJump(cmp, *offset), 2, 0))
+        }
+        _ => None,
+    }
+}
+
+/// A constant compared by value for pooling; Floats by their bits, so
+/// `0.0` and `-0.0` stay apart.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Literal {
+    Int(i64),
+    Float(u64),
+    String(String),
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chunk {
@@ -161,37 +520,113 @@ impl Chunk {
             code: Vec::new(),
             constants: Vec::new(),
             spans: Vec::new(),
+            pooled: HashMap::new(),
         }
     }
 
     pub fn emit(&mut self, op: Op, span: Span) -> usize {
         let idx = self.code.len();
         self.code.push(op);
-        self.spans.push(span);
+        self.mark_span(idx, span);
         idx
     }
 
+    /// The span of the op at `idx`, if there's one there.
+    pub fn span(&self, idx: usize) -> Option<Span> {
+        if idx >= self.code.len() {
+            return None;
+        }
+        let run = self.spans.partition_point(|&(start, _)| start <= idx);
+        Some(self.spans[run - 1].1)
+    }
+
+    /// Record that the op at `idx`, the last so far, has `span`.
+    fn mark_span(&mut self, idx: usize, span: Span) {
+        if self.spans.last().is_none_or(|&(_, last)| last != span) {
+            self.spans.push((idx, span));
+        }
+    }
+
+    /// Add `value` to the constant pool and return its index. An Int, Float
+    /// or String equal to one already pooled reuses its slot; function
+    /// prototypes are each compiled from their own code and always get one.
     pub fn add_constant(&mut self, value: Value) -> usize {
+        let literal = match &value {
+            Value::Int(n) => Some(Literal::Int(*n)),
+            Value::Float(f) => Some(Literal::Float(f.to_bits())),
+            Value::String(s) => Some(Literal::String(s.clone())),
+            _ => None,
+        };
+        if let Some(literal) = literal {
+            if let Some(&idx) = self.pooled.get(&literal) {
+                return idx;
+            }
+            self.pooled.insert(literal, self.constants.len());
+        }
         self.constants.push(value);
         self.constants.len() - 1
     }
 
     pub fn patch_jump(&mut self, offset: usize) {
         let jump = self.code.len() - offset - 1;
-        match &mut self.code[offset] {
-            Op::Jump(ref mut target)
-            | Op::JumpIfFalse(ref mut target)
-            | Op::TestTag(_, ref mut target)
-            | Op::TestInt(_, ref mut target)
-            | Op::TestBool(_, ref mut target)
-            | Op::TestString(_, ref mut target)
-            | Op::TestUnit(ref mut target)
-            | Op::TestEmptyList(ref mut target)
-            | Op::TestCons(ref mut target)
-            | Op::TestTuple(_, ref mut target) => {
-                *target = jump;
+        match forward_offset(&mut self.code[offset]) {
+            Some(target) => *target = jump,
+            None => panic!("Not a jump instruction at offset {}", offset),
+        }
+    }
+
+    /// The peephole pass: replace the op sequences hot code runs most with
+    /// one superinstruction each, so the VM dispatches once for them. A
+    /// sequence a jump lands inside is left alone, and jumps are retargeted
+    /// past the ops removed. The fused op keeps the span of the op in it
+    /// that can fail.
+    pub fn fuse(&mut self) {
+        // Where each jump lands, by op index
+        let targets: Vec<Option<usize>> = self
+            .code
+            .iter_mut()
+            .enumerate()
+            .map(|(i, op)| match op {
+                Op::Loop(offset) => Some(i + 1 - *offset),
+                op => forward_offset(op).map(|offset| i + 1 + *offset),
+            })
+            .collect();
+        let landed: HashSet<usize> = targets.iter().flatten().copied().collect();
+
+        let mut code = Vec::with_capacity(self.code.len());
+        let mut spans = Vec::with_capacity(self.code.len());
+        // The new index of each op, and of the end of the code
+        let mut moved = Vec::with_capacity(self.code.len() + 1);
+        // Where each new op's jump, if it has one, lands among the old ops
+        let mut new_targets = Vec::with_capacity(self.code.len());
+        let mut i = 0;
+        while i < self.code.len() {
+            let (op, len, span) = match fused(&self.code[i..]) {
+                Some((op, len, span)) if (i + 1..i + len).all(|j| !landed.contains(&j)) => {
+                    (op, len, i + span)
+                }
+                _ => (self.code[i].clone(), 1, i),
+            };
+            let span = self.span(span).expect("every op has a span");
+            moved.extend(std::iter::repeat_n(code.len(), len));
+            new_targets.push(targets[i + len - 1]);
+            code.push(op);
+            spans.push(span);
+            i += len;
+        }
+        moved.push(code.len());
+
+        for (j, op) in code.iter_mut().enumerate() {
+            let Some(target) = new_targets[j] else { continue };
+            match op {
+                Op::Loop(offset) => *offset = j + 1 - moved[target],
+                op => *forward_offset(op).expect("only jumps have targets") = moved[target] - j - 1,
             }
-            _ => panic!("Not a jump instruction at offset {}", offset),
+        }
+        self.code = code;
+        self.spans.clear();
+        for (idx, span) in spans.into_iter().enumerate() {
+            self.mark_span(idx, span);
         }
     }
 
@@ -199,11 +634,18 @@ impl Chunk {
         self.code.len()
     }
 
-    /// Disassemble for debugging.
+    /// Disassemble for debugging. Each op that starts a run of ops with
+    /// one span shows it.
     pub fn disassemble(&self, name: &str) -> String {
         let mut out = format!("== {} ==\n", name);
         for (i, op) in self.code.iter().enumerate() {
-            out.push_str(&format!("{:04} {:?}\n", i, op));
+            let op = format!("{:?}", op);
+            match self.span(i) {
+                Some(span) if i == 0 || self.span(i - 1) != Some(span) => {
+                    out.push_str(&format!("{:04} {:<32} @{}..{}\n", i, op, span.start, span.end))
+                }
+                _ => out.push_str(&format!("{:04} {}\n", i, op)),
+            }
         }
         out
     }
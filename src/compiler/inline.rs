@@ -0,0 +1,118 @@
+//! Inlining of small functions.
+//!
+//! A call to a function bound by a `let` to a lambda with a body of a few
+//! nodes, binding no names of its own and referring only to its parameters
+//! and to globals, compiles to that body: the arguments are pushed as locals
+//! named after the parameters, so the call and its frame go away. A call to
+//! a constructor compiles to the `MakeAdt` its wrapper function would run.
+
+use std::rc::Rc;
+
+use crate::ast::*;
+
+/// The largest body, in expression nodes, that is inlined.
+const MAX_SIZE: usize = 16;
+
+/// How deep inlined bodies may inline the functions they call.
+pub const MAX_DEPTH: usize = 4;
+
+/// A function or constructor whose calls can be compiled in place.
+#[derive(Debug, Clone)]
+pub enum Callee {
+    /// A constructor taking this many fields.
+    Constructor(String, usize),
+    /// A small function; `local` is the frame and slot it's bound at, or
+    /// `None` for a global.
+    Function {
+        inline: Rc<Inline>,
+        local: Option<(usize, usize)>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Inline {
+    pub params: Vec<String>,
+    pub body: ExprId,
+    /// The other names the body refers to, which must be globals wherever
+    /// it's inlined.
+    pub free: Vec<String>,
+}
+
+impl Inline {
+    /// The function `value` evaluates to, if it's a lambda small and simple
+    /// enough to inline.
+    pub fn of(ast: &Ast, value: ExprId) -> Option<Rc<Inline>> {
+        let Expr::Lambda { params, body } = &ast[value].node else {
+            return None;
+        };
+        let mut inline = Inline {
+            params: params.iter().map(|p| p.name.node.clone()).collect(),
+            body: *body,
+            free: Vec::new(),
+        };
+        let mut size = 0;
+        if !inline.scan(ast, *body, &mut size) {
+            return None;
+        }
+        Some(Rc::new(inline))
+    }
+
+    /// Count the nodes of `expr` into `size` and collect its free names,
+    /// returning false once it's too big or has a node that isn't inlined.
+    fn scan(&mut self, ast: &Ast, expr: ExprId, size: &mut usize) -> bool {
+        *size += 1;
+        if *size > MAX_SIZE {
+            return false;
+        }
+        match &ast[expr].node {
+            Expr::IntLit(_)
+            | Expr::FloatLit(_)
+            | Expr::StringLit(_)
+            | Expr::BoolLit(_)
+            | Expr::UnitLit => true,
+            Expr::Var(name) | Expr::Local { name, .. } => {
+                if !self.params.contains(name) && !self.free.contains(name) {
+                    self.free.push(name.clone());
+                }
+                true
+            }
+            Expr::ListLit(items) | Expr::TupleLit(items) => {
+                items.iter().all(|e| self.scan(ast, *e, size))
+            }
+            Expr::App { func, args } => {
+                self.scan(ast, *func, size) && args.iter().all(|e| self.scan(ast, *e, size))
+            }
+            Expr::BinOp { lhs, rhs, .. } | Expr::Pipe { lhs, rhs } => {
+                self.scan(ast, *lhs, size) && self.scan(ast, *rhs, size)
+            }
+            Expr::UnaryOp { operand, .. } => self.scan(ast, *operand, size),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.scan(ast, *cond, size)
+                    && self.scan(ast, *then_branch, size)
+                    && self.scan(ast, *else_branch, size)
+            }
+            Expr::Record(fields) => fields.iter().all(|(_, e)| self.scan(ast, *e, size)),
+            Expr::FieldAccess { expr, .. } => self.scan(ast, *expr, size),
+            Expr::Slice { expr, start, end } => {
+                self.scan(ast, *expr, size) && [start, end].into_iter().flatten().all(|e| self.scan(ast, *e, size))
+            }
+            Expr::Interpolation(parts) => parts.iter().all(|part| match part {
+                InterpolationPart::Literal(_) => true,
+                InterpolationPart::Expr(e, _) => self.scan(ast, *e, size),
+            }),
+            // Lets, matches and lambdas bind names, and labeled calls, quotes
+            // and imports are rare enough not to bother with
+            Expr::Lambda { .. }
+            | Expr::Let { .. }
+            | Expr::Match { .. }
+            | Expr::LabeledApp { .. }
+            | Expr::Import(_)
+            | Expr::Quote(_)
+            | Expr::Unquote(_) => false,
+        }
+    }
+}
@@ -3,6 +3,8 @@
 pub struct Local {
     pub name: String,
     pub depth: usize,
+    /// Stack offset from the frame's base.
+    pub slot: usize,
 }
 
 /// Tracks upvalues (captured variables) during compilation.
@@ -20,6 +22,12 @@ pub struct ScopeTracker {
     pub scope_depth: usize,
 }
 
+impl Default for ScopeTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ScopeTracker {
     pub fn new() -> Self {
         ScopeTracker {
@@ -46,19 +54,20 @@ impl ScopeTracker {
         pop_count
     }
 
-    pub fn add_local(&mut self, name: String) -> usize {
-        let index = self.locals.len();
+    pub fn add_local(&mut self, name: String, slot: usize) -> usize {
         self.locals.push(Local {
             name,
             depth: self.scope_depth,
+            slot,
         });
-        index
+        slot
     }
 
+    /// The stack slot of the innermost local named `name`.
     pub fn resolve_local(&self, name: &str) -> Option<usize> {
-        for (i, local) in self.locals.iter().enumerate().rev() {
+        for local in self.locals.iter().rev() {
             if local.name == name {
-                return Some(i);
+                return Some(local.slot);
             }
         }
         None
@@ -0,0 +1,565 @@
+//! `.lyrc` files: a compiled program saved to disk, so it can run without
+//! being parsed, checked or compiled again.
+//!
+//! The layout is the magic `LYRC`, a format version, the source file's name
+//! and text (kept for rendering runtime errors), then the top-level function.
+//! Integers are little-endian `u64`s, strings are a length and UTF-8 bytes,
+//! and each op is a one-byte tag followed by its operands.
+
+use std::rc::Rc;
+
+use crate::ast::{Ast, Decl, Expr, ExprId, FormatSpec};
+use crate::eval::value::Value;
+use crate::span::Span;
+use crate::{lexer, parser};
+
+use super::bytecode::{Chunk, Comparison, FunctionProto, Op, UpvalueRef};
+
+const MAGIC: &[u8; 4] = b"LYRC";
+/// Bumped whenever the encoding of ops changes; older files are refused.
+const VERSION: u64 = 2;
+
+/// A program ready for the VM.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub filename: String,
+    pub source: String,
+    pub main: FunctionProto,
+}
+
+/// Encode a compiled program.
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut w = Writer(MAGIC.to_vec());
+    w.uint(VERSION);
+    w.str(&program.filename);
+    w.str(&program.source);
+    w.proto(&program.main);
+    w.0
+}
+
+/// Decode a program written by [`encode`], rejecting bytecode the VM
+/// couldn't run safely.
+pub fn decode(bytes: &[u8]) -> Result<Program, String> {
+    if !bytes.starts_with(MAGIC) {
+        return Err("not a compiled Lyra program".to_string());
+    }
+    let mut r = Reader {
+        bytes,
+        pos: MAGIC.len(),
+    };
+    let version = r.uint()?;
+    if version != VERSION {
+        return Err(format!(
+            "compiled program is format {}, but this lyra reads format {}",
+            version, VERSION
+        ));
+    }
+    let filename = r.str()?;
+    let source = r.str()?;
+    let main = r.proto()?;
+    if r.pos != bytes.len() {
+        return Err("trailing bytes after compiled program".to_string());
+    }
+    // Checked before anything runs, as the VM trusts its operands
+    main.validate()?;
+    Ok(Program {
+        filename,
+        source,
+        main,
+    })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn byte(&mut self, b: u8) {
+        self.0.push(b);
+    }
+
+    fn uint(&mut self, n: u64) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn strs(&mut self, items: &[String]) {
+        self.uint(items.len() as u64);
+        items.iter().for_each(|s| self.str(s));
+    }
+
+    fn proto(&mut self, proto: &FunctionProto) {
+        self.str(&proto.name);
+        self.byte(proto.arity);
+        self.uint(proto.upvalue_count as u64);
+        let chunk = &proto.chunk;
+        self.uint(chunk.code.len() as u64);
+        for (idx, op) in chunk.code.iter().enumerate() {
+            let span = chunk.span(idx).unwrap_or_default();
+            self.op(op);
+            self.uint(span.start as u64);
+            self.uint(span.end as u64);
+        }
+        self.uint(chunk.constants.len() as u64);
+        for constant in &chunk.constants {
+            self.constant(constant);
+        }
+    }
+
+    fn constant(&mut self, value: &Value) {
+        match value {
+            Value::Int(n) => {
+                self.byte(0);
+                self.uint(*n as u64);
+            }
+            Value::Float(f) => {
+                self.byte(1);
+                self.uint(f.to_bits());
+            }
+            Value::String(s) => {
+                self.byte(2);
+                self.str(s);
+            }
+            Value::Function(proto) => {
+                self.byte(3);
+                self.proto(proto);
+            }
+            // The compiler only pools literals and function prototypes
+            other => unreachable!("constant {} in a chunk", other.type_name()),
+        }
+    }
+
+    fn op(&mut self, op: &Op) {
+        let usize = |w: &mut Self, n: usize| w.uint(n as u64);
+        match op {
+            Op::Constant(i) => {
+                self.byte(0);
+                usize(self, *i);
+            }
+            Op::Unit => self.byte(1),
+            Op::True => self.byte(2),
+            Op::False => self.byte(3),
+            Op::Pop => self.byte(4),
+            Op::GetLocal(i) => {
+                self.byte(5);
+                usize(self, *i);
+            }
+            Op::SetLocal(i) => {
+                self.byte(6);
+                usize(self, *i);
+            }
+            Op::GetUpvalue(i) => {
+                self.byte(7);
+                usize(self, *i);
+            }
+            Op::GetSelf => self.byte(8),
+            Op::GetGlobal(name) => {
+                self.byte(9);
+                self.str(name);
+            }
+            Op::DefineGlobal(name) => {
+                self.byte(10);
+                self.str(name);
+            }
+            Op::Add => self.byte(11),
+            Op::Sub => self.byte(12),
+            Op::Mul => self.byte(13),
+            Op::Div => self.byte(14),
+            Op::Mod => self.byte(15),
+            Op::Pow => self.byte(16),
+            Op::Negate => self.byte(17),
+            Op::Equal => self.byte(18),
+            Op::NotEqual => self.byte(19),
+            Op::Less => self.byte(20),
+            Op::Greater => self.byte(21),
+            Op::LessEqual => self.byte(22),
+            Op::GreaterEqual => self.byte(23),
+            Op::Not => self.byte(24),
+            Op::Jump(offset) => {
+                self.byte(25);
+                usize(self, *offset);
+            }
+            Op::JumpIfFalse(offset) => {
+                self.byte(26);
+                usize(self, *offset);
+            }
+            Op::Loop(offset) => {
+                self.byte(27);
+                usize(self, *offset);
+            }
+            Op::Call(argc) => {
+                self.byte(28);
+                self.byte(*argc);
+            }
+            Op::TailCall(argc) => {
+                self.byte(29);
+                self.byte(*argc);
+            }
+            Op::Return => self.byte(30),
+            Op::Closure(i, upvalues) => {
+                self.byte(31);
+                usize(self, *i);
+                usize(self, upvalues.len());
+                for upvalue in upvalues {
+                    self.byte(upvalue.is_local as u8);
+                    usize(self, upvalue.index);
+                }
+            }
+            Op::MakeList(n) => {
+                self.byte(32);
+                usize(self, *n);
+            }
+            Op::MakeTuple(n) => {
+                self.byte(33);
+                usize(self, *n);
+            }
+            Op::Cons => self.byte(34),
+            Op::MakeAdt(name, n) => {
+                self.byte(35);
+                self.str(name);
+                usize(self, *n);
+            }
+            Op::TestTag(name, offset) => {
+                self.byte(36);
+                self.str(name);
+                usize(self, *offset);
+            }
+            Op::TestInt(n, offset) => {
+                self.byte(37);
+                self.uint(*n as u64);
+                usize(self, *offset);
+            }
+            Op::TestBool(b, offset) => {
+                self.byte(38);
+                self.byte(*b as u8);
+                usize(self, *offset);
+            }
+            Op::TestString(s, offset) => {
+                self.byte(39);
+                self.str(s);
+                usize(self, *offset);
+            }
+            Op::TestUnit(offset) => {
+                self.byte(40);
+                usize(self, *offset);
+            }
+            Op::TestEmptyList(offset) => {
+                self.byte(41);
+                usize(self, *offset);
+            }
+            Op::TestCons(offset) => {
+                self.byte(42);
+                usize(self, *offset);
+            }
+            Op::TestTuple(n, offset) => {
+                self.byte(43);
+                usize(self, *n);
+                usize(self, *offset);
+            }
+            Op::Dup => self.byte(44),
+            Op::GetAdtField(i) => {
+                self.byte(45);
+                usize(self, *i);
+            }
+            Op::GetListHead => self.byte(46),
+            Op::GetListTail => self.byte(47),
+            Op::GetTupleField(i) => {
+                self.byte(48);
+                usize(self, *i);
+            }
+            Op::PopMatch => self.byte(49),
+            Op::MatchFail(patterns) => {
+                self.byte(50);
+                self.strs(patterns);
+            }
+            Op::MakeRecord(fields) => {
+                self.byte(51);
+                self.strs(fields);
+            }
+            Op::GetField(name) => {
+                self.byte(52);
+                self.str(name);
+            }
+            // Quoted code is kept as source, the `quote` included so its
+            // `unquote`s parse, and parsed again on loading
+            Op::Quote(ast, expr, n) => {
+                self.byte(53);
+                self.str(&format!("quote ({})", ast.display(*expr)));
+                usize(self, *n);
+            }
+            Op::ToString => self.byte(54),
+            Op::Format(spec) => {
+                self.byte(55);
+                self.str(&spec.to_string());
+            }
+            Op::BuildString(n) => {
+                self.byte(56);
+                usize(self, *n);
+            }
+            Op::Swap => self.byte(57),
+            Op::PopUnder(n) => {
+                self.byte(58);
+                usize(self, *n);
+            }
+            Op::Print => self.byte(59),
+            Op::PrintRaw => self.byte(60),
+            Op::TestFloat(f, offset) => {
+                self.byte(61);
+                self.uint(f.to_bits());
+                usize(self, *offset);
+            }
+            Op::TestListLen(n, offset) => {
+                self.byte(62);
+                usize(self, *n);
+                usize(self, *offset);
+            }
+            Op::GetListElement(idx) => {
+                self.byte(63);
+                usize(self, *idx);
+            }
+            Op::TestStringPrefix(s, offset) => {
+                self.byte(64);
+                self.str(s);
+                usize(self, *offset);
+            }
+            Op::TestStringSuffix(s, offset) => {
+                self.byte(65);
+                self.str(s);
+                usize(self, *offset);
+            }
+            Op::GetStringSlice(start, end) => {
+                self.byte(66);
+                usize(self, *start);
+                usize(self, *end);
+            }
+            Op::Slice => self.byte(68),
+            Op::CheckExtern(name, arity) => {
+                self.byte(67);
+                self.str(name);
+                usize(self, *arity);
+            }
+            Op::GetLocal2Add(a, b) => {
+                self.byte(69);
+                usize(self, *a);
+                usize(self, *b);
+            }
+            Op::ConstantAdd(i) => {
+                self.byte(70);
+                usize(self, *i);
+            }
+            // The comparison as the tag of the op it was fused from
+            Op::CompareJump(cmp, offset) => {
+                self.byte(71);
+                self.byte(match cmp {
+                    Comparison::Equal => 18,
+                    Comparison::NotEqual => 19,
+                    Comparison::Less => 20,
+                    Comparison::Greater => 21,
+                    Comparison::LessEqual => 22,
+                    Comparison::GreaterEqual => 23,
+                });
+                usize(self, *offset);
+            }
+            Op::CallBare(name) => {
+                self.byte(72);
+                self.str(name);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, String> {
+        let b = *self.bytes.get(self.pos).ok_or_else(truncated)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        let end = self.pos + 8;
+        let bytes = self.bytes.get(self.pos..end).ok_or_else(truncated)?;
+        self.pos = end;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn usize(&mut self) -> Result<usize, String> {
+        usize::try_from(self.uint()?).map_err(|_| "count out of range".to_string())
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.usize()?;
+        let end = self.pos.checked_add(len).ok_or_else(truncated)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or_else(truncated)?;
+        self.pos = end;
+        String::from_utf8(bytes.to_vec()).map_err(|_| "string is not UTF-8".to_string())
+    }
+
+    fn strs(&mut self) -> Result<Vec<String>, String> {
+        let len = self.usize()?;
+        (0..len).map(|_| self.str()).collect()
+    }
+
+    fn proto(&mut self) -> Result<FunctionProto, String> {
+        let name = self.str()?;
+        let arity = self.byte()?;
+        let upvalue_count = self.usize()?;
+        let mut chunk = Chunk::new();
+        for _ in 0..self.usize()? {
+            let op = self.op()?;
+            let start = self.usize()?;
+            let end = self.usize()?;
+            chunk.emit(op, Span { start, end });
+        }
+        // Pushed as they are, so the ops' indices still point at them
+        for _ in 0..self.usize()? {
+            let constant = self.constant()?;
+            chunk.constants.push(constant);
+        }
+        Ok(FunctionProto {
+            name,
+            arity,
+            chunk,
+            upvalue_count,
+        })
+    }
+
+    fn constant(&mut self) -> Result<Value, String> {
+        Ok(match self.byte()? {
+            0 => Value::Int(self.uint()? as i64),
+            1 => Value::Float(f64::from_bits(self.uint()?)),
+            2 => Value::String(self.str()?),
+            3 => Value::Function(Rc::new(self.proto()?)),
+            tag => return Err(format!("unknown constant tag {}", tag)),
+        })
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        Ok(match self.byte()? {
+            0 => Op::Constant(self.usize()?),
+            1 => Op::Unit,
+            2 => Op::True,
+            3 => Op::False,
+            4 => Op::Pop,
+            5 => Op::GetLocal(self.usize()?),
+            6 => Op::SetLocal(self.usize()?),
+            7 => Op::GetUpvalue(self.usize()?),
+            8 => Op::GetSelf,
+            9 => Op::GetGlobal(self.str()?),
+            10 => Op::DefineGlobal(self.str()?),
+            11 => Op::Add,
+            12 => Op::Sub,
+            13 => Op::Mul,
+            14 => Op::Div,
+            15 => Op::Mod,
+            16 => Op::Pow,
+            17 => Op::Negate,
+            18 => Op::Equal,
+            19 => Op::NotEqual,
+            20 => Op::Less,
+            21 => Op::Greater,
+            22 => Op::LessEqual,
+            23 => Op::GreaterEqual,
+            24 => Op::Not,
+            25 => Op::Jump(self.usize()?),
+            26 => Op::JumpIfFalse(self.usize()?),
+            27 => Op::Loop(self.usize()?),
+            28 => Op::Call(self.byte()?),
+            29 => Op::TailCall(self.byte()?),
+            30 => Op::Return,
+            31 => {
+                let index = self.usize()?;
+                let mut upvalues = Vec::new();
+                for _ in 0..self.usize()? {
+                    upvalues.push(UpvalueRef {
+                        is_local: self.byte()? != 0,
+                        index: self.usize()?,
+                    });
+                }
+                Op::Closure(index, upvalues)
+            }
+            32 => Op::MakeList(self.usize()?),
+            33 => Op::MakeTuple(self.usize()?),
+            34 => Op::Cons,
+            35 => Op::MakeAdt(self.str()?, self.usize()?),
+            36 => Op::TestTag(self.str()?, self.usize()?),
+            37 => Op::TestInt(self.uint()? as i64, self.usize()?),
+            38 => Op::TestBool(self.byte()? != 0, self.usize()?),
+            39 => Op::TestString(self.str()?, self.usize()?),
+            40 => Op::TestUnit(self.usize()?),
+            41 => Op::TestEmptyList(self.usize()?),
+            42 => Op::TestCons(self.usize()?),
+            43 => Op::TestTuple(self.usize()?, self.usize()?),
+            44 => Op::Dup,
+            45 => Op::GetAdtField(self.usize()?),
+            46 => Op::GetListHead,
+            47 => Op::GetListTail,
+            48 => Op::GetTupleField(self.usize()?),
+            49 => Op::PopMatch,
+            50 => Op::MatchFail(self.strs()?),
+            51 => Op::MakeRecord(self.strs()?),
+            52 => Op::GetField(self.str()?),
+            53 => {
+                let (ast, expr) = parse_quoted(&self.str()?)?;
+                Op::Quote(Rc::new(ast), expr, self.usize()?)
+            }
+            54 => Op::ToString,
+            55 => {
+                let spec = self.str()?;
+                let spec = FormatSpec::parse(&spec).filter(FormatSpec::within_limits);
+                Op::Format(spec.ok_or("malformed format spec")?)
+            }
+            56 => Op::BuildString(self.usize()?),
+            57 => Op::Swap,
+            58 => Op::PopUnder(self.usize()?),
+            59 => Op::Print,
+            60 => Op::PrintRaw,
+            61 => Op::TestFloat(f64::from_bits(self.uint()?), self.usize()?),
+            62 => Op::TestListLen(self.usize()?, self.usize()?),
+            63 => Op::GetListElement(self.usize()?),
+            64 => Op::TestStringPrefix(self.str()?, self.usize()?),
+            65 => Op::TestStringSuffix(self.str()?, self.usize()?),
+            66 => Op::GetStringSlice(self.usize()?, self.usize()?),
+            67 => Op::CheckExtern(self.str()?, self.usize()?),
+            68 => Op::Slice,
+            69 => Op::GetLocal2Add(self.usize()?, self.usize()?),
+            70 => Op::ConstantAdd(self.usize()?),
+            71 => {
+                let cmp = match self.byte()? {
+                    18 => Comparison::Equal,
+                    19 => Comparison::NotEqual,
+                    20 => Comparison::Less,
+                    21 => Comparison::Greater,
+                    22 => Comparison::LessEqual,
+                    23 => Comparison::GreaterEqual,
+                    tag => return Err(format!("unknown comparison tag {}", tag)),
+                };
+                Op::CompareJump(cmp, self.usize()?)
+            }
+            72 => Op::CallBare(self.str()?),
+            tag => return Err(format!("unknown op tag {}", tag)),
+        })
+    }
+}
+
+fn truncated() -> String {
+    "compiled program is truncated".to_string()
+}
+
+fn parse_quoted(text: &str) -> Result<(Ast, ExprId), String> {
+    let tokens = lexer::tokenize(text).map_err(|errs| errs[0].to_string())?;
+    let program = parser::parse(tokens).map_err(|e| e.to_string())?;
+    match program.decls.last() {
+        Some(Decl::Expr(expr)) => match program.ast[*expr].node {
+            Expr::Quote(inner) => Ok((program.ast, inner)),
+            _ => Err("malformed quoted code".to_string()),
+        },
+        _ => Err("malformed quoted code".to_string()),
+    }
+}
@@ -1,25 +1,47 @@
 pub mod bytecode;
+pub mod inline;
+pub mod lyrc;
 pub mod locals;
 
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
 use crate::ast::*;
+use crate::eval::builtins::VARIADIC_BUILTINS;
 use crate::eval::value::Value;
 use crate::span::Span;
 
 use bytecode::{Chunk, FunctionProto, Op, UpvalueRef};
+use inline::{Callee, Inline};
 use locals::ScopeTracker;
 
 /// Compiler state for one function scope.
 struct CompilerFrame {
     proto: FunctionProto,
     scope: ScopeTracker,
+    /// How many values the code compiled so far leaves on the stack above
+    /// the frame's base, locals and temporaries alike.
+    depth: usize,
 }
 
-pub struct Compiler {
+pub struct Compiler<'a> {
+    /// The program's expressions.
+    ast: &'a Ast,
     frames: Vec<CompilerFrame>,
+    /// Parameter names of the functions in scope, for reordering labeled
+    /// arguments into positional ones.
+    params: HashMap<String, Vec<String>>,
+    /// Functions and constructors in scope whose calls are compiled in place.
+    inlines: HashMap<String, Callee>,
+    /// Top-level names bound more than once. A function compiled while one
+    /// binding is in scope may run after the next, so none are inlined.
+    rebound: HashSet<String>,
+    /// How many inlined bodies the code being compiled is nested in.
+    inline_depth: usize,
 }
 
-impl Compiler {
-    pub fn new() -> Self {
+impl<'a> Compiler<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
         let main_frame = CompilerFrame {
             proto: FunctionProto {
                 name: "<main>".to_string(),
@@ -28,18 +50,221 @@ impl Compiler {
                 upvalue_count: 0,
             },
             scope: ScopeTracker::new(),
+            depth: 0,
         };
         Compiler {
+            ast,
             frames: vec![main_frame],
+            params: HashMap::new(),
+            inlines: HashMap::new(),
+            rebound: HashSet::new(),
+            inline_depth: 0,
+        }
+    }
+
+    /// Point `name` at the parameters of `value`, returning what it had.
+    fn bind_params(&mut self, name: &str, value: ExprId) -> Option<Vec<String>> {
+        match self.ast[value].node.param_names() {
+            Some(params) => self.params.insert(name.to_string(), params),
+            None => self.params.remove(name),
+        }
+    }
+
+    fn restore_params(&mut self, name: &str, saved: Option<Vec<String>>) {
+        match saved {
+            Some(params) => self.params.insert(name.to_string(), params),
+            None => self.params.remove(name),
+        };
+    }
+
+    /// Make `name` inline calls to `value` if it's small enough, or stop
+    /// inlining whatever `name` was, returning that. `local` is the slot a
+    /// local `name` is bound at.
+    fn bind_inline(
+        &mut self,
+        name: &str,
+        value: Option<ExprId>,
+        local: Option<usize>,
+    ) -> Option<Callee> {
+        let frame = self.frames.len() - 1;
+        let inline = value
+            .and_then(|value| Inline::of(self.ast, value))
+            .filter(|inline| !inline.free.iter().any(|n| n == name));
+        match inline {
+            Some(inline) => {
+                let local = local.map(|slot| (frame, slot));
+                self.inlines.insert(name.to_string(), Callee::Function { inline, local })
+            }
+            None => self.inlines.remove(name),
+        }
+    }
+
+    fn restore_inline(&mut self, name: &str, saved: Option<Callee>) {
+        match saved {
+            Some(callee) => self.inlines.insert(name.to_string(), callee),
+            None => self.inlines.remove(name),
+        };
+    }
+
+    /// The frame and slot of the local `name` refers to here, in the
+    /// function being compiled or one around it, or `None` for a global.
+    fn local_binding(&self, name: &str) -> Option<(usize, usize)> {
+        self.frames
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, frame)| frame.scope.resolve_local(name).map(|slot| (i, slot)))
+    }
+
+    /// What a call of `func` with `argc` arguments can be compiled to in
+    /// place: a function or constructor named by `func`, called with all
+    /// of its arguments, bound where the call sees it and, for a function,
+    /// with the globals its body uses not shadowed there.
+    fn inline_callee(&self, func: ExprId, argc: usize) -> Option<Callee> {
+        let (Expr::Var(name) | Expr::Local { name, .. }) = &self.ast[func].node else {
+            return None;
+        };
+        if self.inline_depth >= inline::MAX_DEPTH {
+            return None;
+        }
+        let callee = self.inlines.get(name)?;
+        let visible = match callee {
+            Callee::Constructor(_, arity) => {
+                *arity == argc && self.local_binding(name).is_none()
+            }
+            // A local function is inlined in the functions inside its scope
+            // too, which then don't capture it
+            Callee::Function { inline, local } => {
+                self.local_binding(name) == *local
+                    && inline.params.len() == argc
+                    && inline.free.iter().all(|n| self.local_binding(n).is_none())
+            }
+        };
+        visible.then(|| callee.clone())
+    }
+
+    /// The name `func` is called by, if `func()` may run a variadic builtin
+    /// with no arguments; see `Op::CallBare`.
+    fn bare_name(&self, func: ExprId, args: &[ExprId]) -> Option<String> {
+        match &self.ast[func].node {
+            Expr::Var(name) | Expr::Local { name, .. }
+                if args.is_empty() && VARIADIC_BUILTINS.contains(&name.as_str()) =>
+            {
+                Some(name.clone())
+            }
+            _ => None,
         }
     }
 
+    /// Compile a call to `callee` in place. A function's arguments become
+    /// locals named after its parameters, in scope for its body; in tail
+    /// position the body's own calls are tail calls. `order` gives each
+    /// parameter's index among `args` when they aren't in parameter order.
+    fn compile_inline(
+        &mut self,
+        callee: Callee,
+        args: &[ExprId],
+        order: Option<&[usize]>,
+        tail: bool,
+        span: Span,
+    ) -> Result<(), String> {
+        let inline = match callee {
+            Callee::Constructor(name, arity) => {
+                for &arg in args {
+                    self.compile_expr(arg)?;
+                }
+                self.emit(Op::MakeAdt(name, arity), span);
+                return Ok(());
+            }
+            Callee::Function { inline, .. } => inline,
+        };
+        self.current().scope.begin_scope();
+        for &arg in args {
+            self.compile_expr(arg)?;
+        }
+        // The arguments are only named once all are evaluated, so none of
+        // them sees another's parameter
+        let base = self.current().depth - args.len();
+        for (i, param) in inline.params.iter().enumerate() {
+            let slot = order.map_or(i, |order| order[i]);
+            self.current().scope.add_local(param.clone(), base + slot);
+        }
+        self.inline_depth += 1;
+        let body = if tail {
+            self.compile_expr_tail(inline.body)
+        } else {
+            self.compile_expr(inline.body)
+        };
+        self.inline_depth -= 1;
+        body?;
+        let pops = self.current().scope.end_scope();
+        if pops > 0 {
+            self.emit(Op::PopUnder(pops), span);
+        }
+        Ok(())
+    }
+
+    /// Compile a labeled call. Its arguments are evaluated in source order
+    /// and passed in parameter order.
+    fn compile_labeled(
+        &mut self,
+        func: ExprId,
+        args: &[Arg],
+        tail: bool,
+        span: Span,
+    ) -> Result<(), String> {
+        let Expr::Var(name) = &self.ast[func].node else {
+            return Err("labeled arguments need a named function".to_string());
+        };
+        let params = self
+            .params
+            .get(name)
+            .ok_or_else(|| format!("'{}' has no named parameters", name))?;
+        let order = order_args(self.ast, name, params, args, span).map_err(|e| e.to_string())?;
+        let values: Vec<ExprId> = args.iter().map(|a| a.value).collect();
+        if let Some(callee) = self.inline_callee(func, args.len()) {
+            return self.compile_inline(callee, &values, Some(&order), tail, span);
+        }
+        let in_order = order.iter().enumerate().all(|(slot, &i)| slot == i);
+        if in_order {
+            self.compile_expr(func)?;
+            for &arg in &values {
+                self.compile_expr(arg)?;
+            }
+        } else {
+            // Evaluate the arguments under the function, then copy them up
+            // in parameter order
+            let base = self.current().depth;
+            for &arg in &values {
+                self.compile_expr(arg)?;
+            }
+            self.compile_expr(func)?;
+            for &i in &order {
+                self.emit(Op::GetLocal(base + i), span);
+            }
+        }
+        let argc = args.len() as u8;
+        self.emit(if tail { Op::TailCall(argc) } else { Op::Call(argc) }, span);
+        if !in_order {
+            self.emit(Op::PopUnder(args.len()), span);
+        }
+        Ok(())
+    }
+
     fn current(&mut self) -> &mut CompilerFrame {
         self.frames.last_mut().unwrap()
     }
 
     fn emit(&mut self, op: Op, span: Span) -> usize {
-        self.current().proto.chunk.emit(op, span)
+        let frame = self.current();
+        frame.depth = frame.depth.saturating_add_signed(op.stack_effect());
+        frame.proto.chunk.emit(op, span)
+    }
+
+    /// Name the value on top of the stack as a local, returning its slot.
+    fn add_local(&mut self, name: String) -> usize {
+        let frame = self.current();
+        frame.scope.add_local(name, frame.depth - 1)
     }
 
     fn add_constant(&mut self, value: Value) -> usize {
@@ -57,14 +282,38 @@ impl Compiler {
 
     /// Compile a full program (list of declarations).
     pub fn compile_program(mut self, decls: &[Decl]) -> Result<FunctionProto, String> {
+        let mut bound = HashSet::new();
+        for decl in decls {
+            let names: Vec<String> = match decl {
+                Decl::Let { name, .. } | Decl::Extern { name, .. } => vec![name.node.clone()],
+                Decl::Type { name, variants, .. } => variants
+                    .iter()
+                    .flat_map(|v| [v.name.node.clone(), format!("{}.{}", name.node, v.name.node)])
+                    .collect(),
+                _ => Vec::new(),
+            };
+            for name in names {
+                if !bound.insert(name.clone()) {
+                    self.rebound.insert(name);
+                }
+            }
+        }
+        // The host's values are checked before anything runs
+        for decl in decls {
+            if let Decl::Extern { name, type_ann, .. } = decl {
+                let check = Op::CheckExtern(name.node.clone(), type_ann.node.arity());
+                self.emit(check, name.span);
+            }
+        }
         let last_idx = decls.len().saturating_sub(1);
         for (i, decl) in decls.iter().enumerate() {
             // For the last declaration, if it's an expression, keep its value on the stack
             if i == last_idx {
                 if let Decl::Expr(expr) = decl {
-                    self.compile_expr(expr)?;
-                    self.emit(Op::Return, expr.span);
-                    let frame = self.frames.pop().unwrap();
+                    self.compile_expr(*expr)?;
+                    self.emit(Op::Return, self.ast[*expr].span);
+                    let mut frame = self.frames.pop().unwrap();
+                    peephole(&mut frame.proto.chunk);
                     return Ok(frame.proto);
                 }
             }
@@ -73,7 +322,8 @@ impl Compiler {
         self.emit(Op::Unit, Span::default());
         self.emit(Op::Return, Span::default());
 
-        let frame = self.frames.pop().unwrap();
+        let mut frame = self.frames.pop().unwrap();
+        peephole(&mut frame.proto.chunk);
         Ok(frame.proto)
     }
 
@@ -85,20 +335,23 @@ impl Compiler {
                 body,
                 ..
             } => {
+                self.bind_params(&name.node, *body);
+                let inlined = !*recursive && !self.rebound.contains(&name.node);
+                self.bind_inline(&name.node, Some(*body).filter(|_| inlined), None);
                 if *recursive {
                     // For recursive functions: define the global first, then compile
                     self.emit(Op::Unit, name.span);
                     self.emit(Op::DefineGlobal(name.node.clone()), name.span);
-                    self.compile_expr(body)?;
+                    self.compile_expr(*body)?;
                     self.emit(Op::DefineGlobal(name.node.clone()), name.span);
                 } else {
-                    self.compile_expr(body)?;
+                    self.compile_expr(*body)?;
                     self.emit(Op::DefineGlobal(name.node.clone()), name.span);
                 }
                 Ok(())
             }
 
-            Decl::Type { variants, .. } => {
+            Decl::Type { name: type_name, variants, .. } => {
                 // Register constructors as globals
                 for variant in variants {
                     let name = &variant.name.node;
@@ -112,13 +365,23 @@ impl Compiler {
                         self.compile_constructor_fn(name, arity, variant.span)?;
                         self.emit(Op::DefineGlobal(name.clone()), variant.span);
                     }
+                    // Also reachable as `Type.Ctor`
+                    self.emit(Op::GetGlobal(name.clone()), variant.span);
+                    let qualified = format!("{}.{}", type_name.node, name);
+                    for key in [name, &qualified] {
+                        if arity > 0 && !self.rebound.contains(key) {
+                            let callee = Callee::Constructor(name.clone(), arity);
+                            self.inlines.insert(key.clone(), callee);
+                        }
+                    }
+                    self.emit(Op::DefineGlobal(qualified), variant.span);
                 }
                 Ok(())
             }
 
             Decl::Expr(expr) => {
-                self.compile_expr(expr)?;
-                self.emit(Op::Pop, expr.span);
+                self.compile_expr(*expr)?;
+                self.emit(Op::Pop, self.ast[*expr].span);
                 Ok(())
             }
 
@@ -147,12 +410,14 @@ impl Compiler {
         proto.chunk.emit(Op::MakeAdt(name.to_string(), arity), span);
         proto.chunk.emit(Op::Return, span);
 
-        let const_idx = self.add_constant(Value::Function(proto));
-        self.emit(Op::Closure(const_idx, vec![]), span);
+        let const_idx = self.add_constant(Value::Function(Rc::new(proto)));
+        self.emit(Op::Constant(const_idx), span);
         Ok(())
     }
 
-    fn compile_expr(&mut self, expr: &SpannedExpr) -> Result<(), String> {
+    fn compile_expr(&mut self, id: ExprId) -> Result<(), String> {
+        let ast = self.ast;
+        let expr = &ast[id];
         let span = expr.span;
         match &expr.node {
             Expr::IntLit(n) => {
@@ -177,69 +442,89 @@ impl Compiler {
                 self.emit(Op::Unit, span);
             }
 
-            Expr::Var(name) => {
+            Expr::Var(name) | Expr::Local { name, .. } => {
                 self.compile_var_access(name, span);
             }
 
             Expr::ListLit(elems) => {
                 for elem in elems {
-                    self.compile_expr(elem)?;
+                    self.compile_expr(*elem)?;
                 }
                 self.emit(Op::MakeList(elems.len()), span);
             }
 
             Expr::TupleLit(elems) => {
                 for elem in elems {
-                    self.compile_expr(elem)?;
+                    self.compile_expr(*elem)?;
                 }
                 self.emit(Op::MakeTuple(elems.len()), span);
             }
 
             Expr::Lambda { params, body } => {
-                self.compile_lambda(params, body, None, span)?;
+                self.compile_lambda(params, *body, None, span)?;
             }
 
             Expr::App { func, args } => {
-                self.compile_expr(func)?;
+                if let Some(callee) = self.inline_callee(*func, args.len()) {
+                    return self.compile_inline(callee, args, None, false, span);
+                }
+                self.compile_expr(*func)?;
                 for arg in args {
-                    self.compile_expr(arg)?;
+                    self.compile_expr(*arg)?;
                 }
-                self.emit(Op::Call(args.len() as u8), span);
+                match self.bare_name(*func, args) {
+                    Some(name) => self.emit(Op::CallBare(name), span),
+                    None => self.emit(Op::Call(args.len() as u8), span),
+                };
             }
 
+            Expr::LabeledApp { func, args } => self.compile_labeled(*func, args, false, span)?,
+
             Expr::BinOp { op, lhs, rhs } => {
                 // Short-circuit for && and ||
                 match op {
                     BinOp::And => {
-                        self.compile_expr(lhs)?;
+                        self.compile_expr(*lhs)?;
                         let jump = self.emit(Op::JumpIfFalse(0), span);
                         self.emit(Op::Pop, span);
-                        self.compile_expr(rhs)?;
+                        self.compile_expr(*rhs)?;
                         self.patch_jump(jump);
                         return Ok(());
                     }
                     BinOp::Or => {
-                        self.compile_expr(lhs)?;
+                        self.compile_expr(*lhs)?;
                         // If true, skip rhs
                         let else_jump = self.emit(Op::JumpIfFalse(0), span);
                         let end_jump = self.emit(Op::Jump(0), span);
                         self.patch_jump(else_jump);
                         self.emit(Op::Pop, span);
-                        self.compile_expr(rhs)?;
+                        self.compile_expr(*rhs)?;
                         self.patch_jump(end_jump);
                         return Ok(());
                     }
+                    // A chain of `+` over strings is built in one go
+                    BinOp::Add => {
+                        let operands = crate::ast::add_chain(ast, id);
+                        if operands.len() > 2 && operands.iter().any(|&e| is_string_expr(&ast[e])) {
+                            for &operand in &operands {
+                                self.compile_expr(operand)?;
+                            }
+                            self.emit(Op::BuildString(operands.len()), span);
+                            return Ok(());
+                        }
+                    }
                     _ => {}
                 }
 
-                self.compile_expr(lhs)?;
-                self.compile_expr(rhs)?;
+                self.compile_expr(*lhs)?;
+                self.compile_expr(*rhs)?;
                 match op {
                     BinOp::Add => self.emit(Op::Add, span),
                     BinOp::Sub => self.emit(Op::Sub, span),
                     BinOp::Mul => self.emit(Op::Mul, span),
                     BinOp::Div => self.emit(Op::Div, span),
                     BinOp::Mod => self.emit(Op::Mod, span),
+                    BinOp::Pow => self.emit(Op::Pow, span),
                     BinOp::Eq => self.emit(Op::Equal, span),
                     BinOp::NotEq => self.emit(Op::NotEqual, span),
                     BinOp::Lt => self.emit(Op::Less, span),
@@ -252,7 +537,7 @@ impl Compiler {
             }
 
             Expr::UnaryOp { op, operand } => {
-                self.compile_expr(operand)?;
+                self.compile_expr(*operand)?;
                 match op {
                     UnaryOp::Neg => self.emit(Op::Negate, span),
                     UnaryOp::Not => self.emit(Op::Not, span),
@@ -260,9 +545,18 @@ impl Compiler {
             }
 
             Expr::Pipe { lhs, rhs } => {
+                // a |> f(b) calls f with b and then a, as f(b, a) does
+                let (func, mut args) = match &ast[*rhs].node {
+                    Expr::App { func, args } => (*func, args.clone()),
+                    _ => (*rhs, Vec::new()),
+                };
+                args.push(*lhs);
+                if let Some(callee) = self.inline_callee(func, args.len()) {
+                    return self.compile_inline(callee, &args, None, false, span);
+                }
                 // a |> f  compiles to  f(a)
-                self.compile_expr(rhs)?;
-                self.compile_expr(lhs)?;
+                self.compile_expr(*rhs)?;
+                self.compile_expr(*lhs)?;
                 self.emit(Op::Call(1), span);
             }
 
@@ -271,14 +565,14 @@ impl Compiler {
                 then_branch,
                 else_branch,
             } => {
-                self.compile_expr(cond)?;
+                self.compile_expr(*cond)?;
                 let else_jump = self.emit(Op::JumpIfFalse(0), span);
                 self.emit(Op::Pop, span);
-                self.compile_expr(then_branch)?;
+                self.compile_expr(*then_branch)?;
                 let end_jump = self.emit(Op::Jump(0), span);
                 self.patch_jump(else_jump);
                 self.emit(Op::Pop, span);
-                self.compile_expr(else_branch)?;
+                self.compile_expr(*else_branch)?;
                 self.patch_jump(end_jump);
             }
 
@@ -291,18 +585,25 @@ impl Compiler {
             } => {
                 self.current().scope.begin_scope();
 
-                if *recursive {
+                let (saved, saved_inline) = if *recursive {
                     // Placeholder for recursive reference
                     self.emit(Op::Unit, span);
-                    let local_idx = self.current().scope.add_local(name.node.clone());
-                    self.compile_expr(value)?;
+                    let local_idx = self.add_local(name.node.clone());
+                    let saved = self.bind_params(&name.node, *value);
+                    self.compile_rec_value(&name.node, *value)?;
                     self.emit(Op::SetLocal(local_idx), span);
+                    self.emit(Op::Pop, span);
+                    (saved, self.bind_inline(&name.node, None, None))
                 } else {
-                    self.compile_expr(value)?;
-                    self.current().scope.add_local(name.node.clone());
-                }
+                    self.compile_expr(*value)?;
+                    let slot = self.add_local(name.node.clone());
+                    let saved = self.bind_params(&name.node, *value);
+                    (saved, self.bind_inline(&name.node, Some(*value), Some(slot)))
+                };
 
-                self.compile_expr(body)?;
+                self.compile_expr(*body)?;
+                self.restore_params(&name.node, saved);
+                self.restore_inline(&name.node, saved_inline);
 
                 // Stack: [... local_value body_result]
                 // Pop the local from under the result.
@@ -313,60 +614,96 @@ impl Compiler {
             }
 
             Expr::Match { scrutinee, arms } => {
-                self.compile_match(scrutinee, arms, span)?;
+                self.compile_match(*scrutinee, arms, span)?;
             }
 
             Expr::Interpolation(parts) => {
-                for (i, part) in parts.iter().enumerate() {
+                for part in parts {
                     match part {
                         InterpolationPart::Literal(s) => {
                             let idx = self.add_constant(Value::String(s.clone()));
                             self.emit(Op::Constant(idx), span);
                         }
-                        InterpolationPart::Expr(expr) => {
-                            self.compile_expr(expr)?;
-                            self.emit(Op::ToString, span);
+                        InterpolationPart::Expr(expr, spec) => {
+                            self.compile_expr(*expr)?;
+                            match spec {
+                                Some(spec) => self.emit(Op::Format(spec.clone()), span),
+                                None => self.emit(Op::ToString, span),
+                            };
                         }
                     }
-                    if i > 0 {
-                        self.emit(Op::StringConcat, span);
-                    }
                 }
-                if parts.is_empty() {
-                    let idx = self.add_constant(Value::String(String::new()));
-                    self.emit(Op::Constant(idx), span);
+                if parts.len() != 1 {
+                    self.emit(Op::BuildString(parts.len()), span);
                 }
             }
 
             Expr::Record(fields) => {
                 let names: Vec<String> = fields.iter().map(|(n, _)| n.clone()).collect();
                 for (_, val) in fields {
-                    self.compile_expr(val)?;
+                    self.compile_expr(*val)?;
                 }
                 self.emit(Op::MakeRecord(names), span);
             }
 
             Expr::FieldAccess { expr: obj, field } => {
-                self.compile_expr(obj)?;
+                self.compile_expr(*obj)?;
                 self.emit(Op::GetField(field.clone()), span);
             }
+
+            Expr::Slice { expr: obj, start, end } => {
+                self.compile_expr(*obj)?;
+                for bound in [start, end] {
+                    match bound {
+                        Some(bound) => self.compile_expr(*bound)?,
+                        None => {
+                            self.emit(Op::Unit, span);
+                        }
+                    }
+                }
+                self.emit(Op::Slice, span);
+            }
+            Expr::Import(path) => {
+                return Err(format!("import \"{}\" was not resolved to a module", path));
+            }
+            Expr::Quote(inner) => {
+                let spliced = crate::eval::quote::unquotes(ast, *inner);
+                for &e in &spliced {
+                    self.compile_expr(e)?;
+                }
+                let mut quoted = Ast::new();
+                let root = quoted.graft(ast, *inner);
+                self.emit(Op::Quote(Rc::new(quoted), root, spliced.len()), span);
+            }
+            Expr::Unquote(_) => {
+                return Err("`unquote` can only be used inside `quote`".to_string());
+            }
         }
         Ok(())
     }
 
     /// Compile an expression in tail position — emits TailCall for App nodes.
-    fn compile_expr_tail(&mut self, expr: &SpannedExpr) -> Result<(), String> {
+    fn compile_expr_tail(&mut self, id: ExprId) -> Result<(), String> {
+        let ast = self.ast;
+        let expr = &ast[id];
         let span = expr.span;
         match &expr.node {
             // App in tail position → TailCall
             Expr::App { func, args } => {
-                self.compile_expr(func)?;
+                if let Some(callee) = self.inline_callee(*func, args.len()) {
+                    return self.compile_inline(callee, args, None, true, span);
+                }
+                self.compile_expr(*func)?;
                 for arg in args {
-                    self.compile_expr(arg)?;
+                    self.compile_expr(*arg)?;
                 }
-                self.emit(Op::TailCall(args.len() as u8), span);
+                match self.bare_name(*func, args) {
+                    Some(name) => self.emit(Op::CallBare(name), span),
+                    None => self.emit(Op::TailCall(args.len() as u8), span),
+                };
                 Ok(())
             }
+            Expr::LabeledApp { func, args } => self.compile_labeled(*func, args, true, span),
 
             // If: propagate tail position into both branches
             Expr::If {
@@ -374,14 +711,14 @@ impl Compiler {
                 then_branch,
                 else_branch,
             } => {
-                self.compile_expr(cond)?;
+                self.compile_expr(*cond)?;
                 let else_jump = self.emit(Op::JumpIfFalse(0), span);
                 self.emit(Op::Pop, span);
-                self.compile_expr_tail(then_branch)?;
+                self.compile_expr_tail(*then_branch)?;
                 let end_jump = self.emit(Op::Jump(0), span);
                 self.patch_jump(else_jump);
                 self.emit(Op::Pop, span);
-                self.compile_expr_tail(else_branch)?;
+                self.compile_expr_tail(*else_branch)?;
                 self.patch_jump(end_jump);
                 Ok(())
             }
@@ -395,16 +732,23 @@ impl Compiler {
                 ..
             } => {
                 self.current().scope.begin_scope();
-                if *recursive {
+                let (saved, saved_inline) = if *recursive {
                     self.emit(Op::Unit, span);
-                    let local_idx = self.current().scope.add_local(name.node.clone());
-                    self.compile_expr(value)?;
+                    let local_idx = self.add_local(name.node.clone());
+                    let saved = self.bind_params(&name.node, *value);
+                    self.compile_rec_value(&name.node, *value)?;
                     self.emit(Op::SetLocal(local_idx), span);
+                    self.emit(Op::Pop, span);
+                    (saved, self.bind_inline(&name.node, None, None))
                 } else {
-                    self.compile_expr(value)?;
-                    self.current().scope.add_local(name.node.clone());
-                }
-                self.compile_expr_tail(body)?;
+                    self.compile_expr(*value)?;
+                    let slot = self.add_local(name.node.clone());
+                    let saved = self.bind_params(&name.node, *value);
+                    (saved, self.bind_inline(&name.node, Some(*value), Some(slot)))
+                };
+                self.compile_expr_tail(*body)?;
+                self.restore_params(&name.node, saved);
+                self.restore_inline(&name.node, saved_inline);
                 let pops = self.current().scope.end_scope();
                 if pops > 0 {
                     self.emit(Op::PopUnder(pops), span);
@@ -417,11 +761,11 @@ impl Compiler {
                 // Reuse the existing match compilation but with tail calls in bodies
                 // For simplicity, fall back to non-tail compilation
                 // (full TCO through match would require duplicating compile_match)
-                self.compile_match(scrutinee, arms, span)
+                self.compile_match(*scrutinee, arms, span)
             }
 
             // Everything else: compile normally (not in tail position)
-            _ => self.compile_expr(expr),
+            _ => self.compile_expr(id),
         }
     }
 
@@ -465,10 +809,22 @@ impl Compiler {
         None
     }
 
+    /// Compile the value of a `let rec`. A lambda refers to itself through
+    /// its own frame, since a closure captures the placeholder by value.
+    fn compile_rec_value(&mut self, name: &str, value: ExprId) -> Result<(), String> {
+        let ast = self.ast;
+        match &ast[value].node {
+            Expr::Lambda { params, body } => {
+                self.compile_lambda(params, *body, Some(name), ast[value].span)
+            }
+            _ => self.compile_expr(value),
+        }
+    }
+
     fn compile_lambda(
         &mut self,
         params: &[LambdaParam],
-        body: &SpannedExpr,
+        body: ExprId,
         rec_name: Option<&str>,
         span: Span,
     ) -> Result<(), String> {
@@ -482,18 +838,21 @@ impl Compiler {
                 upvalue_count: 0,
             },
             scope: ScopeTracker::new(),
+            depth: 0,
         };
         self.frames.push(new_frame);
 
         // Add params as locals
         self.current().scope.begin_scope();
-        for param in params {
-            self.current().scope.add_local(param.name.node.clone());
+        for (slot, param) in params.iter().enumerate() {
+            self.current().scope.add_local(param.name.node.clone(), slot);
         }
+        self.current().depth = params.len();
 
-        // If recursive, add self reference
+        // If recursive, the slot after the params holds the function itself
         if let Some(rec) = rec_name {
-            self.current().scope.add_local(rec.to_string());
+            self.emit(Op::GetSelf, span);
+            self.add_local(rec.to_string());
         }
 
         // Compile body with tail call optimization
@@ -503,6 +862,7 @@ impl Compiler {
         // Pop frame
         let mut frame = self.frames.pop().unwrap();
         frame.proto.upvalue_count = frame.scope.upvalues.len();
+        peephole(&mut frame.proto.chunk);
 
         // Build upvalue refs
         let upvalue_refs: Vec<UpvalueRef> = frame
@@ -515,48 +875,46 @@ impl Compiler {
             })
             .collect();
 
-        // Add function as constant in the parent frame
-        let const_idx = self.add_constant(Value::Function(frame.proto));
-        self.emit(Op::Closure(const_idx, upvalue_refs), span);
+        // Add function as constant in the parent frame; one that captures
+        // nothing is just that constant
+        let const_idx = self.add_constant(Value::Function(Rc::new(frame.proto)));
+        if upvalue_refs.is_empty() {
+            self.emit(Op::Constant(const_idx), span);
+        } else {
+            self.emit(Op::Closure(const_idx, upvalue_refs), span);
+        }
 
         Ok(())
     }
 
     fn compile_match(
         &mut self,
-        scrutinee: &SpannedExpr,
+        scrutinee: ExprId,
         arms: &[MatchArm],
         span: Span,
     ) -> Result<(), String> {
         // Compile scrutinee and store as a tracked local so slot numbering stays correct.
         self.current().scope.begin_scope();
         self.compile_expr(scrutinee)?;
-        let scrut_slot = self.current().scope.add_local("__scrutinee".to_string());
+        let scrut_slot = self.add_local("__scrutinee".to_string());
 
         let mut end_jumps = Vec::new();
 
         for (i, arm) in arms.iter().enumerate() {
             let is_last = i == arms.len() - 1;
             let _binding_count = self.count_pattern_bindings(&arm.pattern);
-            let needs_test = self.pattern_needs_test(&arm.pattern);
-
-            let mut next_arm_jump: Option<usize> = None;
 
-            if needs_test {
-                // Push scrutinee for test (test peeks, doesn't pop)
-                self.emit(Op::GetLocal(scrut_slot), span);
-                let jump = self.compile_pattern_test(&arm.pattern, span)?;
-                next_arm_jump = Some(jump);
-                // Test passed: pop the test copy
-                self.emit(Op::Pop, span);
-            }
+            // Test the scrutinee and each part of it the pattern constrains;
+            // any failure jumps out with the part it tested on the stack
+            let mut next_arm_jumps = Vec::new();
+            self.compile_pattern_tests(scrut_slot, &arm.pattern, &[], &mut next_arm_jumps, span);
 
             // Emit pattern bindings using GetLocal(scrut_slot) to access scrutinee
             self.current().scope.begin_scope();
-            self.emit_pattern_bindings(scrut_slot, &arm.pattern, span)?;
+            self.emit_pattern_bindings(scrut_slot, &arm.pattern, &[], span);
 
             // Compile arm body
-            self.compile_expr(&arm.body)?;
+            self.compile_expr(arm.body)?;
 
             // Clean up arm bindings from under the result
             let arm_pops = self.current().scope.end_scope();
@@ -568,12 +926,18 @@ impl Compiler {
             let end_jump = self.emit(Op::Jump(0), span);
             end_jumps.push(end_jump);
 
-            // Patch test failure jump
-            if let Some(jump) = next_arm_jump {
-                self.patch_jump(jump);
-                // Failed test: pop the test copy that's still on stack
-                if !is_last {
-                    self.emit(Op::Pop, span);
+            // Patch test failure jumps
+            if !next_arm_jumps.is_empty() {
+                for jump in next_arm_jumps {
+                    self.patch_jump(jump);
+                }
+                // Failed test: pop the tested part that's still on stack
+                self.emit(Op::Pop, span);
+                if is_last {
+                    // Report the whole value, not the part that didn't match
+                    self.emit(Op::GetLocal(scrut_slot), span);
+                    let tried = arms.iter().map(|a| a.pattern.node.to_string()).collect();
+                    self.emit(Op::MatchFail(tried), span);
                 }
             }
         }
@@ -592,13 +956,6 @@ impl Compiler {
         Ok(())
     }
 
-    fn pattern_needs_test(&self, pattern: &SpannedPattern) -> bool {
-        match &pattern.node {
-            Pattern::Wildcard | Pattern::Var(_) => false,
-            _ => true,
-        }
-    }
-
     fn count_pattern_bindings(&self, pattern: &SpannedPattern) -> usize {
         match &pattern.node {
             Pattern::Wildcard => 0,
@@ -611,167 +968,123 @@ impl Compiler {
             Pattern::Cons(head, tail) => {
                 self.count_pattern_bindings(head) + self.count_pattern_bindings(tail)
             }
+            Pattern::StringPrefix(_, rest) | Pattern::StringSuffix(rest, _) => {
+                self.count_pattern_bindings(rest)
+            }
             Pattern::Tuple(pats) | Pattern::List(pats) => {
                 pats.iter().map(|p| self.count_pattern_bindings(p)).sum()
             }
         }
     }
 
-    fn compile_pattern_test(&mut self, pattern: &SpannedPattern, span: Span) -> Result<usize, String> {
-        match &pattern.node {
-            Pattern::Wildcard | Pattern::Var(_) => {
-                unreachable!("wildcard/var patterns don't need tests")
-            }
-            Pattern::IntLit(n) => Ok(self.emit(Op::TestInt(*n, 0), span)),
-            Pattern::FloatLit(_) => Ok(self.emit(Op::JumpIfFalse(0), span)),
-            Pattern::BoolLit(b) => Ok(self.emit(Op::TestBool(*b, 0), span)),
-            Pattern::StringLit(s) => Ok(self.emit(Op::TestString(s.clone(), 0), span)),
-            Pattern::UnitLit => Ok(self.emit(Op::TestUnit(0), span)),
-            Pattern::Constructor { name, .. } => {
-                Ok(self.emit(Op::TestTag(name.clone(), 0), span))
-            }
-            Pattern::List(pats) if pats.is_empty() => {
-                Ok(self.emit(Op::TestEmptyList(0), span))
-            }
-            Pattern::Cons(_, _) => Ok(self.emit(Op::TestCons(0), span)),
-            _ => Ok(self.emit(Op::JumpIfFalse(0), span)),
-        }
-    }
-
-    /// Emit pattern bindings by reading from the scrutinee local.
-    fn emit_pattern_bindings(&mut self, scrut_slot: usize, pattern: &SpannedPattern, span: Span) -> Result<(), String> {
-        match &pattern.node {
-            Pattern::Var(name) => {
-                // Bind the variable to the scrutinee value
-                self.emit(Op::GetLocal(scrut_slot), span);
-                self.current().scope.add_local(name.clone());
-                Ok(())
-            }
-            Pattern::Wildcard | Pattern::IntLit(_) | Pattern::FloatLit(_)
-            | Pattern::StringLit(_) | Pattern::BoolLit(_) | Pattern::UnitLit => {
-                Ok(())
-            }
-            Pattern::Constructor { args, .. } => {
-                for (i, arg) in args.iter().enumerate() {
-                    self.emit_constructor_field_binding(scrut_slot, i, arg, span)?;
-                }
-                Ok(())
-            }
-            Pattern::Cons(head, tail) => {
-                // Bind head: push list, GetListHead pushes head, swap+pop list
-                self.emit_cons_head_binding(scrut_slot, head, span)?;
-                // Bind tail: push list, GetListTail pushes tail, swap+pop list
-                self.emit_cons_tail_binding(scrut_slot, tail, span)?;
-                Ok(())
-            }
-            Pattern::Tuple(pats) => {
-                for (i, pat) in pats.iter().enumerate() {
-                    self.emit_tuple_field_binding(scrut_slot, i, pat, span)?;
-                }
-                Ok(())
-            }
-            Pattern::List(pats) if pats.is_empty() => Ok(()),
-            Pattern::List(_pats) => Ok(()),
-        }
-    }
-
-    fn emit_constructor_field_binding(
+    /// Emit the tests for `pattern`, which matches the part of the
+    /// scrutinee reached by `path`, then for its subpatterns. Parents are
+    /// tested before their parts are read, so each read is safe. Each test
+    /// leaves the part it failed on on the stack; its jump goes in `jumps`.
+    fn compile_pattern_tests(
         &mut self,
         scrut_slot: usize,
-        field_idx: usize,
         pattern: &SpannedPattern,
+        path: &[Op],
+        jumps: &mut Vec<usize>,
         span: Span,
-    ) -> Result<(), String> {
-        match &pattern.node {
-            Pattern::Var(name) => {
-                // Push scrutinee, extract field, keep as local
-                self.emit(Op::GetLocal(scrut_slot), span);
-                self.emit(Op::GetAdtField(field_idx), span);
-                // Stack: [... scrut_copy field_value]
-                // Swap and pop to leave just field_value
-                self.emit(Op::Swap, span);
-                self.emit(Op::Pop, span);
-                // Stack: [... field_value]
-                self.current().scope.add_local(name.clone());
-                Ok(())
-            }
-            Pattern::Wildcard => Ok(()),
-            _ => {
-                // Nested patterns: push the field value and recursively bind
-                // For now, just extract as a single binding
-                Ok(())
+    ) {
+        let test = match &pattern.node {
+            Pattern::Wildcard | Pattern::Var(_) | Pattern::Tuple(_) => None,
+            Pattern::IntLit(n) => Some(Op::TestInt(*n, 0)),
+            Pattern::FloatLit(f) => Some(Op::TestFloat(*f, 0)),
+            Pattern::BoolLit(b) => Some(Op::TestBool(*b, 0)),
+            Pattern::StringLit(s) => Some(Op::TestString(s.clone(), 0)),
+            Pattern::StringPrefix(prefix, _) => Some(Op::TestStringPrefix(prefix.clone(), 0)),
+            Pattern::StringSuffix(_, suffix) => Some(Op::TestStringSuffix(suffix.clone(), 0)),
+            Pattern::UnitLit => Some(Op::TestUnit(0)),
+            Pattern::Constructor { name, .. } => {
+                Some(Op::TestTag(unqualified(name).to_string(), 0))
             }
+            Pattern::List(pats) if pats.is_empty() => Some(Op::TestEmptyList(0)),
+            Pattern::List(pats) => Some(Op::TestListLen(pats.len(), 0)),
+            Pattern::Cons(_, _) => Some(Op::TestCons(0)),
+        };
+        if let Some(test) = test {
+            // Push the part for the test (test peeks, doesn't pop)
+            self.emit_pattern_access(scrut_slot, path, span);
+            jumps.push(self.emit(test, span));
+            // Test passed: pop the test copy
+            self.emit(Op::Pop, span);
+        }
+        for (access, sub) in subpatterns(&pattern.node) {
+            let path = [path, &[access]].concat();
+            self.compile_pattern_tests(scrut_slot, sub, &path, jumps, span);
         }
     }
 
-    fn emit_tuple_field_binding(
+    /// Emit pattern bindings by reading the parts of the scrutinee local
+    /// they bind, `path` leading to the part `pattern` matches.
+    fn emit_pattern_bindings(
         &mut self,
         scrut_slot: usize,
-        field_idx: usize,
         pattern: &SpannedPattern,
+        path: &[Op],
         span: Span,
-    ) -> Result<(), String> {
-        match &pattern.node {
-            Pattern::Var(name) => {
-                self.emit(Op::GetLocal(scrut_slot), span);
-                self.emit(Op::GetTupleField(field_idx), span);
-                self.emit(Op::Swap, span);
-                self.emit(Op::Pop, span);
-                self.current().scope.add_local(name.clone());
-                Ok(())
-            }
-            Pattern::Wildcard => Ok(()),
-            _ => Ok(()),
+    ) {
+        if let Pattern::Var(name) = &pattern.node {
+            self.emit_pattern_access(scrut_slot, path, span);
+            self.add_local(name.clone());
+        }
+        for (access, sub) in subpatterns(&pattern.node) {
+            let path = [path, &[access]].concat();
+            self.emit_pattern_bindings(scrut_slot, sub, &path, span);
         }
     }
 
-    fn emit_cons_head_binding(
-        &mut self,
-        scrut_slot: usize,
-        head_pattern: &SpannedPattern,
-        span: Span,
-    ) -> Result<(), String> {
-        match &head_pattern.node {
-            Pattern::Var(name) => {
-                // GetLocal pushes list copy, GetListHead peeks list and pushes head
-                self.emit(Op::GetLocal(scrut_slot), span);
-                self.emit(Op::GetListHead, span);
-                // Stack: [... list_copy head]
-                self.emit(Op::Swap, span);
-                self.emit(Op::Pop, span);
-                // Stack: [... head]
-                self.current().scope.add_local(name.clone());
-                Ok(())
-            }
-            Pattern::Wildcard => Ok(()),
-            _ => Ok(()),
+    /// Push the part of the scrutinee at `path`, each step reading from the
+    /// value below and leaving only what it read.
+    fn emit_pattern_access(&mut self, scrut_slot: usize, path: &[Op], span: Span) {
+        self.emit(Op::GetLocal(scrut_slot), span);
+        for access in path {
+            self.emit(access.clone(), span);
+            // Stack: [... whole part]; swap and pop to leave just the part
+            self.emit(Op::Swap, span);
+            self.emit(Op::Pop, span);
         }
     }
+}
 
-    fn emit_cons_tail_binding(
-        &mut self,
-        scrut_slot: usize,
-        tail_pattern: &SpannedPattern,
-        span: Span,
-    ) -> Result<(), String> {
-        match &tail_pattern.node {
-            Pattern::Var(name) => {
-                self.emit(Op::GetLocal(scrut_slot), span);
-                self.emit(Op::GetListTail, span);
-                // Stack: [... list_copy tail]
-                self.emit(Op::Swap, span);
-                self.emit(Op::Pop, span);
-                // Stack: [... tail]
-                self.current().scope.add_local(name.clone());
-                Ok(())
-            }
-            Pattern::Wildcard => Ok(()),
-            _ => Ok(()),
+/// The subpatterns of a pattern, each with the op that reads the part of
+/// the value it matches.
+fn subpatterns(pattern: &Pattern) -> Vec<(Op, &SpannedPattern)> {
+    match pattern {
+        Pattern::Constructor { args, .. } => {
+            args.iter().enumerate().map(|(i, p)| (Op::GetAdtField(i), p)).collect()
+        }
+        Pattern::Tuple(pats) => {
+            pats.iter().enumerate().map(|(i, p)| (Op::GetTupleField(i), p)).collect()
+        }
+        Pattern::List(pats) => {
+            pats.iter().enumerate().map(|(i, p)| (Op::GetListElement(i), p)).collect()
         }
+        Pattern::Cons(head, tail) => vec![(Op::GetListHead, &**head), (Op::GetListTail, &**tail)],
+        Pattern::StringPrefix(prefix, rest) => vec![(Op::GetStringSlice(prefix.len(), 0), &**rest)],
+        Pattern::StringSuffix(rest, suffix) => vec![(Op::GetStringSlice(0, suffix.len()), &**rest)],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether an expression is a string whatever its operands, which makes a
+/// `+` chain it's in one over strings.
+fn is_string_expr(expr: &SpannedExpr) -> bool {
+    matches!(expr.node, Expr::StringLit(_) | Expr::Interpolation(_))
+}
+
+/// Fuse a finished function's hot op sequences; see `Chunk::fuse`. Under
+/// `lyra cov` every op is kept, so the span of each is recorded.
+fn peephole(chunk: &mut Chunk) {
+    if !crate::coverage::recording() {
+        chunk.fuse();
     }
 }
 
 /// Compile a program from declarations to a function prototype.
-pub fn compile(decls: &[Decl]) -> Result<FunctionProto, String> {
-    Compiler::new().compile_program(decls)
+pub fn compile(ast: &Ast, decls: &[Decl]) -> Result<FunctionProto, String> {
+    Compiler::new(ast).compile_program(decls)
 }
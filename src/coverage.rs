@@ -0,0 +1,211 @@
+//! Line coverage, for `lyra cov`. While [`with_coverage`] runs, the
+//! tree-walker records the span of every expression it evaluates and the VM
+//! the span of every instruction it executes. A report then parses each file
+//! again: a line is executable if an expression starts on it, and covered if
+//! one of those expressions was reached.
+//!
+//! Spans don't name their file, so a hit counts for every file with an
+//! expression at exactly that span. Distinct files rarely share one.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::ast::visit::{walk_expr, Visitor};
+use crate::ast::{Ast, Decl, Expr, ExprId, Program};
+use crate::error::LyraError;
+use crate::span::{LineIndex, Span};
+use crate::{lexer, macros, parser, resolve_import};
+
+/// How many times each span was reached.
+#[derive(Debug, Clone, Default)]
+pub struct Hits(HashMap<Span, u64>);
+
+thread_local! {
+    static HITS: RefCell<Hits> = RefCell::new(Hits::default());
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, returning what it reached along with its result. Recordings
+/// don't nest: an inner one takes the hits made while it runs.
+pub fn with_coverage<T>(f: impl FnOnce() -> T) -> (T, Hits) {
+    let outer_hits = HITS.with(|h| h.take());
+    let outer_recording = RECORDING.with(|r| r.replace(true));
+    let result = f();
+    RECORDING.with(|r| r.set(outer_recording));
+    let hits = HITS.with(|h| h.replace(outer_hits));
+    (result, hits)
+}
+
+/// Whether a recording is running; callers skip working out spans otherwise.
+pub(crate) fn recording() -> bool {
+    RECORDING.with(Cell::get)
+}
+
+pub(crate) fn record(span: Span) {
+    HITS.with(|h| *h.borrow_mut().0.entry(span).or_insert(0) += 1);
+}
+
+/// The coverage of one file.
+#[derive(Debug, Clone)]
+pub struct FileCoverage {
+    pub path: String,
+    /// Each executable line (1-based) and how often it was reached: the most
+    /// any expression starting on it was.
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl FileCoverage {
+    pub fn covered(&self) -> usize {
+        self.lines.values().filter(|&&count| count > 0).count()
+    }
+
+    /// The lines never reached, in order.
+    pub fn uncovered(&self) -> Vec<usize> {
+        self.lines
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&line, _)| line)
+            .collect()
+    }
+}
+
+/// Coverage for `path` and, after it, every file it imports, each once.
+pub fn report(path: &str, source: &str, hits: &Hits) -> Result<Vec<FileCoverage>, LyraError> {
+    let mut files = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(path.to_string());
+    cover_file(path, source, hits, &mut seen, &mut files)?;
+    Ok(files)
+}
+
+fn cover_file(
+    path: &str,
+    source: &str,
+    hits: &Hits,
+    seen: &mut HashSet<String>,
+    files: &mut Vec<FileCoverage>,
+) -> Result<(), LyraError> {
+    let tokens = lexer::tokenize(source).map_err(|errs| errs[0].clone())?;
+    let Program { mut ast, decls } = parser::parse(tokens)?;
+    macros::expand_macros(&mut ast, &decls)?;
+
+    let mut collect = Collect::default();
+    for decl in &decls {
+        match decl {
+            Decl::Import { path, .. } => collect.imports.push(path.clone()),
+            // Templates only run as the expansions of their calls
+            Decl::Macro { .. } => {}
+            _ => collect.visit_decl(&ast, decl),
+        }
+    }
+
+    let index = LineIndex::new(source);
+    let mut lines = BTreeMap::new();
+    for span in &collect.spans {
+        let count = hits.0.get(span).copied().unwrap_or(0);
+        let line = lines.entry(index.line_col(span.start).0).or_insert(0);
+        *line = count.max(*line);
+    }
+    files.push(FileCoverage {
+        path: path.to_string(),
+        lines,
+    });
+
+    for import in collect.imports {
+        let resolved = resolve_import(path, &import);
+        if !seen.insert(resolved.clone()) {
+            continue;
+        }
+        // A missing import already failed the run; there's nothing to cover
+        if let Ok(source) = std::fs::read_to_string(&resolved) {
+            cover_file(&resolved, &source, hits, seen, files)?;
+        }
+    }
+    Ok(())
+}
+
+/// Every expression's span, and the files imported.
+#[derive(Default)]
+struct Collect {
+    spans: Vec<Span>,
+    imports: Vec<String>,
+}
+
+impl Visitor for Collect {
+    fn visit_expr(&mut self, ast: &Ast, expr: ExprId) {
+        self.spans.push(ast[expr].span);
+        match &ast[expr].node {
+            Expr::Import(path) => self.imports.push(path.clone()),
+            // Quoted code is data, never run
+            Expr::Quote(_) => {}
+            _ => walk_expr(self, ast, expr),
+        }
+    }
+}
+
+/// A summary per file with its uncovered lines, then a total.
+pub fn render_text(files: &[FileCoverage]) -> String {
+    let percent = |covered: usize, total: usize| {
+        if total == 0 {
+            100.0
+        } else {
+            covered as f64 * 100.0 / total as f64
+        }
+    };
+    let mut out = String::new();
+    let (mut covered, mut total) = (0, 0);
+    for file in files {
+        let (c, t) = (file.covered(), file.lines.len());
+        out.push_str(&format!("{}: {}/{} lines ({:.1}%)\n", file.path, c, t, percent(c, t)));
+        let uncovered = file.uncovered();
+        if !uncovered.is_empty() {
+            out.push_str(&format!("  not run: {}\n", line_ranges(&uncovered)));
+        }
+        covered += c;
+        total += t;
+    }
+    if files.len() > 1 {
+        out.push_str(&format!(
+            "total: {}/{} lines ({:.1}%)\n",
+            covered,
+            total,
+            percent(covered, total)
+        ));
+    }
+    out
+}
+
+/// `3, 5-7, 10` for lines 3, 5, 6, 7 and 10.
+fn line_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &line in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The lcov tracefile format read by genhtml and most coverage viewers.
+pub fn render_lcov(files: &[FileCoverage]) -> String {
+    let mut out = String::from("TN:\n");
+    for file in files {
+        out.push_str(&format!("SF:{}\n", file.path));
+        for (line, count) in &file.lines {
+            out.push_str(&format!("DA:{},{}\n", line, count));
+        }
+        out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", file.lines.len(), file.covered()));
+    }
+    out
+}
@@ -0,0 +1,165 @@
+//! A line debugger for the tree-walking interpreter. `eval` reports every
+//! expression it reaches to [`hook`]; while a source runs under
+//! [`with_source`], the debugger stops when an expression starts a new line
+//! that has a breakpoint, or any new line while stepping. Each stop goes to
+//! the handler the front end installed, which shows it and says how to go on.
+//!
+//! Like the budget meter, the state is per thread, so an idle debugger costs
+//! `eval` one flag check.
+
+use std::cell::{Cell, RefCell};
+
+use crate::eval::env::Env;
+use crate::eval::value::Value;
+use crate::span::{LineIndex, Span};
+
+/// Where the program stopped.
+pub struct Stop {
+    pub file: String,
+    /// 1-based.
+    pub line: usize,
+    pub text: String,
+    /// Bindings in scope short of the globals, innermost first.
+    pub locals: Vec<(String, Value)>,
+}
+
+/// How to go on from a stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Stop again at the next line reached.
+    Step,
+    /// Run to the next breakpoint.
+    Continue,
+}
+
+type Handler = Box<dyn FnMut(&Stop) -> Resume>;
+
+#[derive(Default)]
+struct Debugger {
+    breakpoints: Vec<(String, usize)>,
+    handler: Option<Handler>,
+    stepping: bool,
+    /// The source running now, if any.
+    running: Option<Running>,
+}
+
+struct Running {
+    file: String,
+    /// Character offset and text of each line.
+    lines: Vec<(usize, String)>,
+    /// The line of the last expression reached, so one line stops once.
+    last_line: usize,
+}
+
+thread_local! {
+    static DEBUGGER: RefCell<Debugger> = RefCell::new(Debugger::default());
+    /// Whether `hook` has anything to do.
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install the function that handles stops, replacing any earlier one.
+pub fn set_handler(handler: impl FnMut(&Stop) -> Resume + 'static) {
+    DEBUGGER.with(|d| d.borrow_mut().handler = Some(Box::new(handler)));
+    update_active();
+}
+
+/// Stop whenever an expression starts on `line` of `file`.
+pub fn add_breakpoint(file: &str, line: usize) {
+    DEBUGGER.with(|d| {
+        let mut d = d.borrow_mut();
+        let breakpoint = (file.to_string(), line);
+        if !d.breakpoints.contains(&breakpoint) {
+            d.breakpoints.push(breakpoint);
+        }
+    });
+    update_active();
+}
+
+/// The breakpoints set, in the order they were added.
+pub fn breakpoints() -> Vec<(String, usize)> {
+    DEBUGGER.with(|d| d.borrow().breakpoints.clone())
+}
+
+/// Stop at the first line the next run reaches.
+pub fn step_next() {
+    DEBUGGER.with(|d| d.borrow_mut().stepping = true);
+    update_active();
+}
+
+/// Run `f`, which evaluates `source`, under the debugger. Spans reached in
+/// `f` are taken to be offsets into `source`, named `file` at stops.
+pub fn with_source<T>(file: &str, source: &str, f: impl FnOnce() -> T) -> T {
+    let index = LineIndex::new(source);
+    let lines = (1..=index.line_count())
+        .map(|line| {
+            let start = index.line_span(line).map_or(0, |span| span.start);
+            (start, index.line_text(line).unwrap_or("").to_string())
+        })
+        .collect();
+    let running = Running {
+        file: file.to_string(),
+        lines,
+        last_line: 0,
+    };
+    let outer = DEBUGGER.with(|d| d.borrow_mut().running.replace(running));
+    update_active();
+    let result = f();
+    DEBUGGER.with(|d| {
+        let mut d = d.borrow_mut();
+        d.running = outer;
+        d.stepping = false;
+    });
+    update_active();
+    result
+}
+
+/// Called by `eval` for each expression, before evaluating it.
+pub(crate) fn hook(span: Span, env: &Env) {
+    if !ACTIVE.with(Cell::get) {
+        return;
+    }
+    let stop = DEBUGGER.with(|d| {
+        let mut d = d.borrow_mut();
+        let stepping = d.stepping;
+        let breakpoints = d.breakpoints.clone();
+        let running = d.running.as_mut()?;
+        let line = running.lines.partition_point(|(start, _)| *start <= span.start);
+        if line == running.last_line {
+            return None;
+        }
+        running.last_line = line;
+        let at_breakpoint = breakpoints
+            .iter()
+            .any(|(file, l)| *l == line && *file == running.file);
+        (stepping || at_breakpoint).then(|| Stop {
+            file: running.file.clone(),
+            line,
+            text: running.lines[line - 1].1.clone(),
+            locals: env.locals(),
+        })
+    });
+    let Some(stop) = stop else {
+        return;
+    };
+    // The handler runs without the state borrowed, so it may set breakpoints
+    let Some(mut handler) = DEBUGGER.with(|d| d.borrow_mut().handler.take()) else {
+        return;
+    };
+    let resume = handler(&stop);
+    DEBUGGER.with(|d| {
+        let mut d = d.borrow_mut();
+        d.handler.get_or_insert(handler);
+        d.stepping = resume == Resume::Step;
+    });
+    update_active();
+}
+
+fn update_active() {
+    let active = DEBUGGER.with(|d| {
+        let d = d.borrow();
+        d.handler.is_some()
+            && d.running.is_some()
+            && (d.stepping || !d.breakpoints.is_empty())
+    });
+    ACTIVE.with(|a| a.set(active));
+}
@@ -0,0 +1,208 @@
+//! Structured diagnostics, kept apart from how they are shown.
+//!
+//! A [`Diagnostic`] is plain data: what went wrong, where, and what might fix
+//! it. [`Diagnostic::render`] turns one into colored terminal output, plain
+//! text, or a JSON object for editors and other tools.
+
+use crate::span::{LineIndex, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+/// A span with a short explanation, drawn under the source line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Broad category shown before the message, e.g. `type error`.
+    pub kind: &'static str,
+    /// Stable identifier such as `E0201`, for filtering and documentation.
+    pub code: &'static str,
+    pub message: String,
+    /// Where the problem is; `None` for errors not tied to source.
+    pub primary: Option<Label>,
+    /// Related locations, e.g. where a conflicting type came from.
+    pub secondary: Vec<Label>,
+    /// A replacement to offer, such as a similarly named variable.
+    pub suggestion: Option<String>,
+}
+
+/// How [`Diagnostic::render`] formats its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Source snippet with carets, colored for a terminal.
+    Ansi,
+    /// The same layout without escape codes.
+    Plain,
+    /// One JSON object, with 1-based lines and columns alongside each span.
+    Json,
+}
+
+struct Palette {
+    severity: &'static str,
+    gutter: &'static str,
+    primary: &'static str,
+    secondary: &'static str,
+    reset: &'static str,
+}
+
+impl Diagnostic {
+    pub fn render(&self, source: &str, filename: &str, format: Format) -> String {
+        match format {
+            Format::Ansi => {
+                let severity = match self.severity {
+                    Severity::Error => "\x1b[1;31m",
+                    Severity::Warning => "\x1b[1;33m",
+                    Severity::Note => "\x1b[1m",
+                };
+                self.render_text(
+                    source,
+                    filename,
+                    &Palette {
+                        severity,
+                        gutter: "\x1b[1;34m",
+                        primary: severity,
+                        secondary: "\x1b[1;34m",
+                        reset: "\x1b[0m",
+                    },
+                )
+            }
+            Format::Plain => self.render_text(
+                source,
+                filename,
+                &Palette {
+                    severity: "",
+                    gutter: "",
+                    primary: "",
+                    secondary: "",
+                    reset: "",
+                },
+            ),
+            Format::Json => self.render_json(source, filename),
+        }
+    }
+
+    fn render_text(&self, source: &str, filename: &str, p: &Palette) -> String {
+        let mut out = format!(
+            "{}{}{}: {}",
+            p.severity, self.kind, p.reset, self.message
+        );
+        let Some(primary) = &self.primary else {
+            if let Some(s) = &self.suggestion {
+                out.push_str(&format!("\n  = help: did you mean '{}'?", s));
+            }
+            return out;
+        };
+
+        let lines = LineIndex::new(source);
+        let width = std::iter::once(primary)
+            .chain(&self.secondary)
+            .map(|l| lines.line_col(l.span.start).0.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(width);
+
+        let (line, col) = lines.line_col(primary.span.start);
+        out.push_str(&format!(
+            "\n {}-->{} {}:{}:{}",
+            p.gutter, p.reset, filename, line, col
+        ));
+        let labels = std::iter::once((primary, '^', p.primary))
+            .chain(self.secondary.iter().map(|l| (l, '-', p.secondary)));
+        for (label, mark, color) in labels {
+            let (line, col) = lines.line_col(label.span.start);
+            let text = lines.line_text(line).unwrap_or_default();
+            let marks = label
+                .span
+                .len()
+                .max(1)
+                .min(text.chars().count().saturating_sub(col - 1))
+                .max(1);
+            out.push_str(&format!(
+                "\n{pad} {g}|{r}\n{g}{line:>width$}{r} {g}|{r} {text}\n{pad} {g}|{r} {spaces}{color}{marks} {message}{r}",
+                pad = pad,
+                g = p.gutter,
+                r = p.reset,
+                line = line,
+                width = width,
+                text = text,
+                spaces = " ".repeat(col - 1),
+                color = color,
+                marks = mark.to_string().repeat(marks),
+                message = label.message,
+            ));
+        }
+        if let Some(s) = &self.suggestion {
+            out.push_str(&format!(
+                "\n{} {}={} help: did you mean '{}'?",
+                pad, p.gutter, p.reset, s
+            ));
+        }
+        out
+    }
+
+    fn render_json(&self, source: &str, filename: &str) -> String {
+        let lines = LineIndex::new(source);
+        let label = |l: &Label| {
+            let (line, column) = lines.line_col(l.span.start);
+            format!(
+                "{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"message\":{}}}",
+                l.span.start,
+                l.span.end,
+                line,
+                column,
+                json_string(&l.message)
+            )
+        };
+        format!(
+            "{{\"severity\":\"{}\",\"kind\":{},\"code\":\"{}\",\"message\":{},\"file\":{},\"primary\":{},\"secondary\":[{}],\"suggestion\":{}}}",
+            self.severity.as_str(),
+            json_string(self.kind),
+            self.code,
+            json_string(&self.message),
+            json_string(filename),
+            self.primary.as_ref().map_or_else(|| "null".to_string(), label),
+            self.secondary.iter().map(label).collect::<Vec<_>>().join(","),
+            self.suggestion
+                .as_deref()
+                .map_or_else(|| "null".to_string(), json_string),
+        )
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
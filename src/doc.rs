@@ -0,0 +1,231 @@
+//! API documentation generator behind `lyra doc`.
+
+use std::collections::HashSet;
+use std::fs;
+
+use crate::ast::{Decl, Program, TypeAnnotation};
+use crate::error::LyraError;
+use crate::eval::env::Env;
+use crate::types::env::TypeEnv;
+use crate::types::infer::Inferencer;
+use crate::types::TypeVarGen;
+use crate::{parse_file, resolve_import, stdlib, Imports, Parsed};
+
+/// A documented top-level `let` or `type`.
+#[derive(Debug, Clone)]
+pub struct DocItem {
+    pub name: String,
+    /// `name : scheme` for bindings, the declaration itself for types.
+    pub signature: String,
+    /// `Constructor : scheme` for each ADT variant; empty for bindings.
+    pub variants: Vec<String>,
+    pub doc: Option<String>,
+}
+
+/// Documentation for one source file.
+#[derive(Debug, Clone)]
+pub struct ModuleDoc {
+    pub path: String,
+    pub items: Vec<DocItem>,
+}
+
+/// Type-check a file and everything it imports, collecting every top-level
+/// declaration with its inferred type. Imported modules come first.
+pub fn document(source: &str, filename: &str) -> Result<Vec<ModuleDoc>, LyraError> {
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let inferencer = Inferencer::new();
+    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+
+    let mut ctx = DocContext {
+        type_env,
+        inferencer,
+        visited: HashSet::new(),
+        modules: Vec::new(),
+    };
+    ctx.visited.insert(filename.to_string());
+    ctx.document_module(source, filename)?;
+    Ok(ctx.modules)
+}
+
+struct DocContext {
+    type_env: TypeEnv,
+    inferencer: Inferencer,
+    visited: HashSet<String>,
+    modules: Vec<ModuleDoc>,
+}
+
+impl DocContext {
+    fn document_module(&mut self, source: &str, filename: &str) -> Result<(), LyraError> {
+        let Parsed { program: Program { ast, decls }, hoisted, .. } =
+            parse_file(source, filename, Imports::Keep)?;
+
+        let mut items = Vec::new();
+        for (i, decl) in decls.iter().enumerate() {
+            if let Decl::Import { path, span } = decl {
+                let resolved = resolve_import(filename, path);
+                if !self.visited.insert(resolved.clone()) {
+                    continue;
+                }
+                let import_source =
+                    fs::read_to_string(&resolved).map_err(|e| LyraError::RuntimeError {
+                        message: format!("cannot import \"{}\": {}", path, e),
+                        span: *span,
+                    })?;
+                if let Err(e) = self.document_module(&import_source, &resolved) {
+                    eprintln!("{}", e.render(&import_source, &resolved));
+                    return Err(e);
+                }
+                continue;
+            }
+
+            // Expressions, properties and macros are neither documented nor run
+            if matches!(decl, Decl::Expr(_) | Decl::Prop { .. } | Decl::Macro { .. }) {
+                continue;
+            }
+            self.inferencer.infer_decl(&mut self.type_env, &ast, decl)?;
+            // What the file's modules declare is theirs, not the file's
+            if i >= hoisted {
+                items.push(self.document_decl(decl));
+            }
+        }
+
+        self.modules.push(ModuleDoc {
+            path: filename.to_string(),
+            items,
+        });
+        Ok(())
+    }
+
+    fn document_decl(&self, decl: &Decl) -> DocItem {
+        let scheme_of = |name: &str| {
+            self.type_env
+                .lookup(name)
+                .map(|s| s.normalized().to_string())
+                .unwrap_or_else(|| "?".to_string())
+        };
+
+        match decl {
+            Decl::Type {
+                name,
+                type_params,
+                variants,
+                doc,
+            } => {
+                let mut signature = format!("type {}", name.node);
+                for p in type_params {
+                    signature.push(' ');
+                    signature.push_str(&p.node);
+                }
+                let arms: Vec<String> = variants
+                    .iter()
+                    .map(|v| {
+                        let mut arm = v.name.node.clone();
+                        for field in &v.fields {
+                            match field.node {
+                                TypeAnnotation::App(..) | TypeAnnotation::Arrow(..) => {
+                                    arm.push_str(&format!(" ({})", field.node))
+                                }
+                                _ => arm.push_str(&format!(" {}", field.node)),
+                            }
+                        }
+                        arm
+                    })
+                    .collect();
+                signature.push_str(&format!(" = {}", arms.join(" | ")));
+
+                DocItem {
+                    name: name.node.clone(),
+                    signature,
+                    variants: variants
+                        .iter()
+                        .map(|v| format!("{} : {}", v.name.node, scheme_of(&v.name.node)))
+                        .collect(),
+                    doc: doc.clone(),
+                }
+            }
+            _ => {
+                let name = decl.name().unwrap_or_default().to_string();
+                DocItem {
+                    signature: format!("{} : {}", name, scheme_of(&name)),
+                    name,
+                    variants: Vec::new(),
+                    doc: decl.doc().map(str::to_string),
+                }
+            }
+        }
+    }
+}
+
+/// Render a module as Markdown.
+pub fn render_markdown(module: &ModuleDoc) -> String {
+    let mut out = format!("# `{}`\n", module.path);
+    for item in &module.items {
+        out.push_str(&format!("\n## `{}`\n\n```\n{}\n```\n", item.name, item.signature));
+        if let Some(doc) = &item.doc {
+            out.push_str(&format!("\n{}\n", doc));
+        }
+        if !item.variants.is_empty() {
+            out.push_str("\nConstructors:\n\n");
+            for v in &item.variants {
+                out.push_str(&format!("- `{}`\n", v));
+            }
+        }
+    }
+    out
+}
+
+/// Render a module as plain signatures, one per line, with constructors
+/// indented under their type. This is what `lyra types` prints.
+pub fn render_signatures(module: &ModuleDoc) -> String {
+    let mut out = String::new();
+    for item in &module.items {
+        out.push_str(&item.signature);
+        out.push('\n');
+        for v in &item.variants {
+            out.push_str(&format!("  {}\n", v));
+        }
+    }
+    out
+}
+
+/// Render a module as a standalone HTML page.
+pub fn render_html(module: &ModuleDoc) -> String {
+    let title = escape_html(&module.path);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1><code>{}</code></h1>\n",
+        title, title
+    );
+    for item in &module.items {
+        let name = escape_html(&item.name);
+        out.push_str(&format!(
+            "<section id=\"{}\">\n<h2><code>{}</code></h2>\n<pre><code>{}</code></pre>\n",
+            name,
+            name,
+            escape_html(&item.signature)
+        ));
+        if let Some(doc) = &item.doc {
+            for para in doc.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                out.push_str(&format!("<p>{}</p>\n", escape_html(para.trim())));
+            }
+        }
+        if !item.variants.is_empty() {
+            out.push_str("<ul>\n");
+            for v in &item.variants {
+                out.push_str(&format!("<li><code>{}</code></li>\n", escape_html(v)));
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
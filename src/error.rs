@@ -1,5 +1,6 @@
 use std::fmt;
 
+use crate::diagnostic::{Diagnostic, Format, Label, Severity};
 use crate::span::Span;
 
 #[derive(Debug, Clone)]
@@ -7,26 +8,49 @@ pub enum LyraError {
     // Lexer errors
     UnexpectedChar { ch: char, span: Span },
     UnterminatedString { span: Span },
+    MalformedNumber { text: String, span: Span },
+    /// A format spec's width or precision over `limit`.
+    FormatTooWide { limit: usize, span: Span },
 
     // Parser errors
     UnexpectedToken { expected: String, found: String, span: Span },
     ExpectedExpression { found: String, span: Span },
+    MisplacedUnquote { span: Span },
+    /// Expressions, patterns or types nested more than `limit` deep.
+    TooDeeplyNested { limit: usize, span: Span },
+
+    // Macro expansion errors
+    MacroError { message: String, span: Span },
 
     // Type errors
-    TypeMismatch { expected: String, found: String, span: Span },
+    /// `origin` points at whatever set the expected type, such as an
+    /// annotation or the first branch of an `if`.
+    TypeMismatch { expected: String, found: String, origin: Option<Box<Label>>, span: Span },
     InfiniteType { var: String, ty: String, span: Span },
     UndefinedVariable { name: String, suggestion: Option<String>, span: Span },
     UndefinedType { name: String, span: Span },
     UndefinedConstructor { name: String, span: Span },
     NonExhaustivePatterns { missing: Vec<String>, span: Span },
     ArityMismatch { name: String, expected: usize, found: usize, span: Span },
+    LabelMismatch { name: String, message: String, span: Span },
 
     // Runtime errors
     DivisionByZero { span: Span },
     IndexOutOfBounds { index: i64, length: usize, span: Span },
-    NotCallable { span: Span },
-    MatchFailure { span: Span },
+    NotCallable { value: String, callee: Option<String>, span: Span },
+    CallFailed { name: String, arity: usize, found: usize, message: String, span: Span },
+    MatchFailure { value: String, patterns: Vec<String>, span: Span },
     RuntimeError { message: String, span: Span },
+    BudgetExceeded { limit: String, span: Span },
+    /// Stopped from outside, by Ctrl-C in the REPL.
+    Interrupted { span: Span },
+    /// An `extern` declaration the host didn't supply a fitting value for.
+    UnboundExtern { name: String, reason: String, span: Span },
+
+    // Compilation errors
+    /// A construct a backend has no translation for, such as `quote` in
+    /// `lyra emit-js`.
+    Unsupported { message: String, span: Span },
 
     // IO errors
     FileNotFound { path: String },
@@ -38,8 +62,13 @@ impl LyraError {
         match self {
             LyraError::UnexpectedChar { span, .. }
             | LyraError::UnterminatedString { span, .. }
+            | LyraError::MalformedNumber { span, .. }
+            | LyraError::FormatTooWide { span, .. }
             | LyraError::UnexpectedToken { span, .. }
             | LyraError::ExpectedExpression { span, .. }
+            | LyraError::MisplacedUnquote { span }
+            | LyraError::TooDeeplyNested { span, .. }
+            | LyraError::MacroError { span, .. }
             | LyraError::TypeMismatch { span, .. }
             | LyraError::InfiniteType { span, .. }
             | LyraError::UndefinedVariable { span, .. }
@@ -47,11 +76,17 @@ impl LyraError {
             | LyraError::UndefinedConstructor { span, .. }
             | LyraError::NonExhaustivePatterns { span, .. }
             | LyraError::ArityMismatch { span, .. }
+            | LyraError::LabelMismatch { span, .. }
             | LyraError::DivisionByZero { span, .. }
             | LyraError::IndexOutOfBounds { span, .. }
             | LyraError::NotCallable { span, .. }
+            | LyraError::CallFailed { span, .. }
             | LyraError::MatchFailure { span, .. }
-            | LyraError::RuntimeError { span, .. } => Some(*span),
+            | LyraError::RuntimeError { span, .. }
+            | LyraError::BudgetExceeded { span, .. }
+            | LyraError::Interrupted { span }
+            | LyraError::UnboundExtern { span, .. }
+            | LyraError::Unsupported { span, .. } => Some(*span),
             LyraError::FileNotFound { .. } | LyraError::IoError { .. } => None,
         }
     }
@@ -62,6 +97,9 @@ impl LyraError {
                 format!("unexpected character '{}'", ch)
             }
             LyraError::UnterminatedString { .. } => "unterminated string literal".to_string(),
+            LyraError::MalformedNumber { text, .. } => {
+                format!("malformed number literal '{}': expected digits in the exponent", text)
+            }
             LyraError::UnexpectedToken {
                 expected, found, ..
             } => {
@@ -70,6 +108,18 @@ impl LyraError {
             LyraError::ExpectedExpression { found, .. } => {
                 format!("expected expression, found {}", found)
             }
+            LyraError::MisplacedUnquote { .. } => {
+                "`unquote` can only be used inside `quote`".to_string()
+            }
+            LyraError::FormatTooWide { limit, .. } => {
+                format!("format width and precision can be at most {}", limit)
+            }
+            LyraError::TooDeeplyNested { limit, .. } => {
+                format!("program too deeply nested: more than {} levels", limit)
+            }
+            LyraError::MacroError { message, .. } | LyraError::Unsupported { message, .. } => {
+                message.clone()
+            }
             LyraError::TypeMismatch {
                 expected, found, ..
             } => {
@@ -105,104 +155,184 @@ impl LyraError {
                     name, expected, found
                 )
             }
+            LyraError::LabelMismatch { name, message, .. } => {
+                format!("in call to '{}': {}", name, message)
+            }
             LyraError::DivisionByZero { .. } => "division by zero".to_string(),
             LyraError::IndexOutOfBounds { index, length, .. } => {
                 format!("index {} out of bounds for length {}", index, length)
             }
-            LyraError::NotCallable { .. } => "value is not callable".to_string(),
-            LyraError::MatchFailure { .. } => "no matching pattern found".to_string(),
+            LyraError::NotCallable { value, callee, .. } => match callee {
+                Some(callee) => format!("'{}' is not callable: it is {}", callee, value),
+                None => format!("value is not callable: {}", value),
+            },
+            LyraError::CallFailed {
+                name,
+                arity,
+                found,
+                message,
+                ..
+            } if arity == found => format!("in call to '{}': {}", name, message),
+            LyraError::CallFailed {
+                name,
+                arity,
+                found,
+                message,
+                ..
+            } => {
+                format!(
+                    "in call to '{}' (arity {}, called with {}): {}",
+                    name,
+                    arity,
+                    plural_args(*found),
+                    message
+                )
+            }
+            LyraError::MatchFailure { value, patterns, .. } => format!(
+                "no pattern matches {}; tried {}",
+                value,
+                patterns.join(", ")
+            ),
             LyraError::RuntimeError { message, .. } => message.clone(),
+            LyraError::BudgetExceeded { limit, .. } => {
+                format!("execution budget exceeded: {}", limit)
+            }
+            LyraError::Interrupted { .. } => "interrupted".to_string(),
+            LyraError::UnboundExtern { name, reason, .. } => {
+                format!("extern '{}' {}", name, reason)
+            }
             LyraError::FileNotFound { path } => format!("file not found: {}", path),
             LyraError::IoError { msg } => format!("IO error: {}", msg),
         }
     }
 
-    fn label(&self) -> String {
-        match self {
-            LyraError::TypeMismatch {
-                expected, found, ..
-            } => format!("expected {}, found {}", expected, found),
-            _ => self.message(),
-        }
-    }
-
     fn kind_str(&self) -> &'static str {
         match self {
             LyraError::UnexpectedChar { .. }
-            | LyraError::UnterminatedString { .. } => "syntax error",
+            | LyraError::UnterminatedString { .. }
+            | LyraError::MalformedNumber { .. }
+            | LyraError::FormatTooWide { .. } => "syntax error",
             LyraError::UnexpectedToken { .. }
-            | LyraError::ExpectedExpression { .. } => "parse error",
+            | LyraError::ExpectedExpression { .. }
+            | LyraError::MisplacedUnquote { .. }
+            | LyraError::TooDeeplyNested { .. } => "parse error",
+            LyraError::MacroError { .. } => "macro error",
             LyraError::TypeMismatch { .. }
             | LyraError::InfiniteType { .. }
             | LyraError::UndefinedVariable { .. }
             | LyraError::UndefinedType { .. }
             | LyraError::UndefinedConstructor { .. }
             | LyraError::NonExhaustivePatterns { .. }
-            | LyraError::ArityMismatch { .. } => "type error",
+            | LyraError::ArityMismatch { .. }
+            | LyraError::LabelMismatch { .. } => "type error",
             LyraError::DivisionByZero { .. }
             | LyraError::IndexOutOfBounds { .. }
             | LyraError::NotCallable { .. }
+            | LyraError::CallFailed { .. }
             | LyraError::MatchFailure { .. }
-            | LyraError::RuntimeError { .. } => "runtime error",
+            | LyraError::RuntimeError { .. }
+            | LyraError::BudgetExceeded { .. }
+            | LyraError::Interrupted { .. }
+            | LyraError::UnboundExtern { .. } => "runtime error",
+            LyraError::Unsupported { .. } => "unsupported",
             LyraError::FileNotFound { .. }
             | LyraError::IoError { .. } => "io error",
         }
     }
 
-    /// Render error with source snippet and caret pointing to the span.
-    pub fn render(&self, source: &str, filename: &str) -> String {
-        let msg = self.message();
-        let kind = self.kind_str();
+    /// Stable code identifying the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LyraError::UnexpectedChar { .. } => "E0001",
+            LyraError::UnterminatedString { .. } => "E0002",
+            LyraError::MalformedNumber { .. } => "E0003",
+            LyraError::FormatTooWide { .. } => "E0004",
+            LyraError::UnexpectedToken { .. } => "E0101",
+            LyraError::ExpectedExpression { .. } => "E0102",
+            LyraError::MisplacedUnquote { .. } => "E0103",
+            LyraError::MacroError { .. } => "E0104",
+            LyraError::TooDeeplyNested { .. } => "E0105",
+            LyraError::TypeMismatch { .. } => "E0201",
+            LyraError::InfiniteType { .. } => "E0202",
+            LyraError::UndefinedVariable { .. } => "E0203",
+            LyraError::UndefinedType { .. } => "E0204",
+            LyraError::UndefinedConstructor { .. } => "E0205",
+            LyraError::NonExhaustivePatterns { .. } => "E0206",
+            LyraError::ArityMismatch { .. } => "E0207",
+            LyraError::LabelMismatch { .. } => "E0208",
+            LyraError::DivisionByZero { .. } => "E0301",
+            LyraError::IndexOutOfBounds { .. } => "E0302",
+            LyraError::NotCallable { .. } => "E0303",
+            LyraError::CallFailed { .. } => "E0304",
+            LyraError::MatchFailure { .. } => "E0305",
+            LyraError::RuntimeError { .. } => "E0306",
+            LyraError::BudgetExceeded { .. } => "E0307",
+            LyraError::Interrupted { .. } => "E0308",
+            LyraError::UnboundExtern { .. } => "E0309",
+            LyraError::Unsupported { .. } => "E0501",
+            LyraError::FileNotFound { .. } => "E0401",
+            LyraError::IoError { .. } => "E0402",
+        }
+    }
 
-        let span = match self.span() {
-            Some(s) => s,
-            None => return format!("\x1b[1;31m{}\x1b[0m: {}", kind, msg),
+    /// The error as structured data, for rendering or for tools.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let (message, suggestion) = match self {
+            LyraError::UndefinedVariable {
+                name, suggestion, ..
+            } => (format!("undefined variable '{}'", name), suggestion.clone()),
+            _ => (self.message(), None),
         };
-
-        let (line_num, col, line_text) = locate_in_source(source, span);
-        let width = line_num.to_string().len();
-        let caret_len = span.len().max(1).min(line_text.len().saturating_sub(col.saturating_sub(1)));
-        let label = self.label();
-
-        format!(
-            "\x1b[1;31m{kind}\x1b[0m: {msg}\n \x1b[1;34m-->\x1b[0m {file}:{line}:{col}\n{pad} \x1b[1;34m|\x1b[0m\n\x1b[1;34m{line_num:>width$}\x1b[0m \x1b[1;34m|\x1b[0m {line_text}\n{pad} \x1b[1;34m|\x1b[0m {spaces}\x1b[1;31m{carets} {label}\x1b[0m",
-            kind = kind,
-            msg = msg,
-            file = filename,
-            line = line_num,
-            col = col,
-            pad = " ".repeat(width),
-            width = width,
-            line_text = line_text,
-            spaces = " ".repeat(col.saturating_sub(1)),
-            carets = "^".repeat(caret_len.max(1)),
-            label = label,
-        )
+        let label = match self {
+            LyraError::TypeMismatch {
+                expected, found, ..
+            } => format!("expected {}, found {}", expected, found),
+            _ => message.clone(),
+        };
+        Diagnostic {
+            severity: Severity::Error,
+            kind: self.kind_str(),
+            code: self.code(),
+            message,
+            primary: self.span().map(|span| Label {
+                span,
+                message: label,
+            }),
+            secondary: match self {
+                LyraError::TypeMismatch {
+                    origin: Some(origin),
+                    ..
+                } => vec![origin.as_ref().clone()],
+                _ => Vec::new(),
+            },
+            suggestion,
+        }
     }
-}
 
-fn locate_in_source(source: &str, span: Span) -> (usize, usize, &str) {
-    let mut line_num = 1;
-    let mut line_start = 0;
-
-    for (i, ch) in source.char_indices() {
-        if i >= span.start {
-            break;
-        }
-        if ch == '\n' {
-            line_num += 1;
-            line_start = i + 1;
+    /// Note where a mismatch's expected type came from, unless it already
+    /// says. Other errors pass through unchanged.
+    pub fn with_origin(mut self, span: Span, message: &str) -> Self {
+        if let LyraError::TypeMismatch { origin: origin @ None, .. } = &mut self {
+            *origin = Some(Box::new(Label {
+                span,
+                message: message.to_string(),
+            }));
         }
+        self
     }
 
-    let col = span.start - line_start + 1;
-    let line_end = source[line_start..]
-        .find('\n')
-        .map(|i| line_start + i)
-        .unwrap_or(source.len());
-    let line_text = &source[line_start..line_end];
+    /// Render error with source snippet and caret pointing to the span.
+    pub fn render(&self, source: &str, filename: &str) -> String {
+        self.diagnostic().render(source, filename, Format::Ansi)
+    }
+}
 
-    (line_num, col, line_text)
+pub(crate) fn plural_args(n: usize) -> String {
+    if n == 1 {
+        "1 argument".to_string()
+    } else {
+        format!("{} arguments", n)
+    }
 }
 
 impl fmt::Display for LyraError {
@@ -221,11 +351,11 @@ pub fn levenshtein(a: &str, b: &str) -> usize {
     let n = b_chars.len();
 
     let mut dp = vec![vec![0usize; n + 1]; m + 1];
-    for i in 0..=m {
-        dp[i][0] = i;
+    for (i, row) in dp.iter_mut().enumerate() {
+        row[0] = i;
     }
-    for j in 0..=n {
-        dp[0][j] = j;
+    for (j, cell) in dp[0].iter_mut().enumerate() {
+        *cell = j;
     }
     for i in 1..=m {
         for j in 1..=n {
@@ -242,19 +372,23 @@ pub fn levenshtein(a: &str, b: &str) -> usize {
     dp[m][n]
 }
 
-/// Find the closest match to `name` in `candidates` by edit distance.
-pub fn suggest_similar(name: &str, candidates: &[&str]) -> Option<String> {
+/// Whether `candidate` is a different name close enough to `name` to be
+/// what was meant: a few edits away, fewer for short names.
+pub fn is_similar(name: &str, candidate: &str) -> bool {
     let threshold = match name.len() {
         0..=2 => 1,
         3..=5 => 2,
         _ => 3,
     };
+    let dist = levenshtein(name, candidate);
+    dist > 0 && dist <= threshold
+}
+
+/// Find the closest match to `name` in `candidates` by edit distance.
+pub fn suggest_similar(name: &str, candidates: &[&str]) -> Option<String> {
     candidates
         .iter()
-        .filter(|c| {
-            let dist = levenshtein(name, c);
-            dist > 0 && dist <= threshold
-        })
+        .filter(|c| is_similar(name, c))
         .min_by_key(|c| levenshtein(name, c))
         .map(|c| c.to_string())
 }
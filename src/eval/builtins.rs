@@ -1,31 +1,294 @@
-use super::value::Value;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::{datetime, flags, logging, memory, terminal};
+use super::value::{int_pow, Builtin, Value};
 
 fn builtin(name: &str, arity: usize, func: fn(Vec<Value>) -> Result<Value, String>) -> (String, Value) {
+    (name.to_string(), Value::builtin(name, arity, func))
+}
+
+/// A builtin taking `arity` or more arguments.
+fn variadic_builtin(
+    name: &str,
+    arity: usize,
+    func: fn(Vec<Value>) -> Result<Value, String>,
+) -> (String, Value) {
     (
         name.to_string(),
-        Value::Builtin {
+        Value::Builtin(Rc::new(Builtin {
             name: name.to_string(),
             arity,
+            variadic: true,
             func,
-        },
+        })),
     )
 }
 
+/// Names of the builtins that accept extra arguments.
+pub const VARIADIC_BUILTINS: &[&str] = &["print", "println"];
+
+/// Arguments to `print`/`println`: strings as-is, separated by spaces.
+fn join_display(args: &[Value]) -> String {
+    args.iter()
+        .map(Value::display_unquoted)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+thread_local! {
+    /// What `print` and `println` have written while output is captured.
+    static CAPTURED: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with what `print` and `println` write collected rather than
+/// printed, returning it with `f`'s result. Captures don't nest: the
+/// innermost one collects, and the outer one is restored afterwards.
+pub fn capture_output<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let outer = CAPTURED.with(|c| c.replace(Some(String::new())));
+    // Restore the outer capture even if `f` panics
+    struct Restore(Option<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CAPTURED.with(|c| c.replace(self.0.take()));
+        }
+    }
+    let _restore = Restore(outer);
+    let result = f();
+    let output = CAPTURED.with(|c| c.borrow_mut().take()).unwrap_or_default();
+    (result, output)
+}
+
+/// Write program output: to stdout, or to the capture in progress.
+pub(crate) fn write_output(text: &str) {
+    CAPTURED.with(|c| match &mut *c.borrow_mut() {
+        Some(captured) => captured.push_str(text),
+        None => print!("{}", text),
+    });
+}
+
+thread_local! {
+    /// Whether the running program is sandboxed, so can't touch files.
+    static SANDBOXED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` with the file builtins failing if `sandboxed`. The outer
+/// setting is restored afterwards.
+pub fn with_sandbox<T>(sandboxed: bool, f: impl FnOnce() -> T) -> T {
+    let outer = SANDBOXED.with(|s| s.replace(sandboxed));
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SANDBOXED.with(|s| s.set(self.0));
+        }
+    }
+    let _restore = Restore(outer);
+    f()
+}
+
+thread_local! {
+    /// The running program's command-line arguments, for `args()`.
+    static ARGS: RefCell<Rc<[String]>> = RefCell::new(Rc::from([]));
+}
+
+/// Run `f` with `args()` returning `args`. The outer arguments are
+/// restored afterwards.
+pub fn with_args<T>(args: &[String], f: impl FnOnce() -> T) -> T {
+    let outer = ARGS.with(|a| a.replace(args.into()));
+    struct Restore(Rc<[String]>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            ARGS.with(|a| *a.borrow_mut() = self.0.clone());
+        }
+    }
+    let _restore = Restore(outer);
+    f()
+}
+
+/// The path argument of a file builtin, unless file access is denied.
+fn file_path<'a>(name: &str, arg: &'a Value) -> Result<&'a str, String> {
+    if SANDBOXED.with(Cell::get) {
+        return Err(format!("{}: file access is disabled in sandbox mode", name));
+    }
+    match arg {
+        Value::String(path) => Ok(path),
+        v => Err(format!("{}: expected String, got {}", name, v.type_name())),
+    }
+}
+
+fn bytes_arg<'a>(name: &str, arg: &'a Value) -> Result<&'a [u8], String> {
+    match arg {
+        Value::Bytes(bytes) => Ok(bytes),
+        v => Err(format!("{}: expected Bytes, got {}", name, v.type_name())),
+    }
+}
+
+/// The bytes of a String or Bytes argument, for the digest builtins.
+fn digest_input<'a>(name: &str, arg: &'a Value) -> Result<&'a [u8], String> {
+    match arg {
+        Value::String(s) => Ok(s.as_bytes()),
+        Value::Bytes(bytes) => Ok(bytes),
+        v => Err(format!("{}: expected String or Bytes, got {}", name, v.type_name())),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 64 random bits. Each `RandomState` is keyed from a per-thread seed the
+/// OS supplies, so no two calls agree; it isn't meant for cryptography.
+pub(crate) fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new().build_hasher().finish()
+}
+
+/// A random version 4 UUID in its canonical lowercase form.
+fn uuid_v4() -> String {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&random_u64().to_be_bytes());
+    bytes[8..].copy_from_slice(&random_u64().to_be_bytes());
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    uuid_format(&bytes)
+}
+
+fn uuid_format(bytes: &[u8; 16]) -> String {
+    let hex = hex(bytes);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+/// `text` as a UUID in canonical form: 8-4-4-4-12 hex digits, or 32
+/// without hyphens, in either case.
+fn uuid_parse(text: &str) -> Result<String, String> {
+    let digits: String = if text.len() == 36 {
+        for (i, c) in text.char_indices() {
+            if matches!(i, 8 | 13 | 18 | 23) != (c == '-') {
+                return Err(format!("'{}' is not a UUID: expected 8-4-4-4-12 hex digits", text));
+            }
+        }
+        text.chars().filter(|&c| c != '-').collect()
+    } else if text.len() == 32 {
+        text.to_string()
+    } else {
+        return Err(format!("'{}' is not a UUID: expected 8-4-4-4-12 hex digits", text));
+    };
+    let bytes = hex_decode(&digits)
+        .map_err(|_| format!("'{}' is not a UUID: expected 8-4-4-4-12 hex digits", text))?;
+    Ok(uuid_format(&bytes.try_into().expect("32 hex digits are 16 bytes")))
+}
+
+fn string_list_arg(name: &str, arg: &Value) -> Result<Vec<String>, String> {
+    match arg {
+        Value::List(items) => items
+            .iter()
+            .map(|item| string_arg(name, item).map(str::to_string))
+            .collect(),
+        v => Err(format!("{}: expected [String], got {}", name, v.type_name())),
+    }
+}
+
+fn string_arg<'a>(name: &str, arg: &'a Value) -> Result<&'a str, String> {
+    match arg {
+        Value::String(s) => Ok(s),
+        v => Err(format!("{}: expected String, got {}", name, v.type_name())),
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Standard base64, padded with `=`.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard base64. Padding is optional and whitespace is skipped.
+fn base64_decode(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<u8> = text
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    let digits = match digits.iter().position(|&b| b == b'=') {
+        Some(pad) if digits[pad..].iter().all(|&b| b == b'=') && digits.len().is_multiple_of(4) => {
+            &digits[..pad]
+        }
+        Some(_) => return Err("base64_decode: misplaced '=' padding".to_string()),
+        None => &digits[..],
+    };
+    if digits.len() % 4 == 1 {
+        return Err("base64_decode: truncated input".to_string());
+    }
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let mut n = 0u32;
+        for (i, &d) in chunk.iter().enumerate() {
+            let Some(v) = BASE64.iter().position(|&c| c == d) else {
+                return Err(format!("base64_decode: invalid character '{}'", d as char));
+            };
+            n |= (v as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn hex_decode(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = text.chars().collect();
+    if digits.len() % 2 == 1 {
+        return Err("hex_decode: odd number of digits".to_string());
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let hex: String = pair.iter().collect();
+            u8::from_str_radix(&hex, 16)
+                .map_err(|_| format!("hex_decode: invalid hex byte '{}'", hex))
+        })
+        .collect()
+}
+
+/// The dividend and nonzero divisor of a Euclidean division builtin.
+fn division(name: &str, args: &[Value]) -> Result<(i64, i64), String> {
+    match (&args[0], &args[1]) {
+        (Value::Int(_), Value::Int(0)) => Err(format!("{}: division by zero", name)),
+        (Value::Int(a), Value::Int(b)) => Ok((*a, *b)),
+        _ => Err(format!("{}: expected two Ints", name)),
+    }
+}
+
+/// Euclidean division, as `(quotient, remainder)`: the remainder is never
+/// negative, unlike `%`'s, which takes the dividend's sign.
+fn euclid(name: &str, args: &[Value]) -> Result<(i64, i64), String> {
+    let (a, b) = division(name, args)?;
+    let q = a
+        .checked_div_euclid(b)
+        .ok_or_else(|| format!("{}: integer overflow in {} / {}", name, a, b))?;
+    Ok((q, a.wrapping_rem_euclid(b)))
+}
+
 pub fn all_builtins() -> Vec<(String, Value)> {
-    vec![
+    #[allow(unused_mut)]
+    let mut builtins = vec![
         // IO
-        builtin("print", 1, |args| {
-            match &args[0] {
-                Value::String(s) => print!("{}", s),
-                v => print!("{}", v),
-            }
+        variadic_builtin("print", 1, |args| {
+            write_output(&join_display(&args));
             Ok(Value::Unit)
         }),
-        builtin("println", 1, |args| {
-            match &args[0] {
-                Value::String(s) => println!("{}", s),
-                v => println!("{}", v),
-            }
+        variadic_builtin("println", 1, |args| {
+            write_output(&format!("{}\n", join_display(&args)));
             Ok(Value::Unit)
         }),
 
@@ -33,6 +296,7 @@ pub fn all_builtins() -> Vec<(String, Value)> {
         builtin("to_string", 1, |args| {
             Ok(Value::String(format!("{}", args[0])))
         }),
+        builtin("type_of", 1, |args| Ok(Value::String(args[0].type_of()))),
         builtin("str_length", 1, |args| {
             match &args[0] {
                 Value::String(s) => Ok(Value::Int(s.len() as i64)),
@@ -166,12 +430,25 @@ pub fn all_builtins() -> Vec<(String, Value)> {
         }),
         builtin("pow", 2, |args| {
             match (&args[0], &args[1]) {
-                (Value::Int(base), Value::Int(exp)) => {
-                    Ok(Value::Int(base.pow(*exp as u32)))
-                }
+                (Value::Int(base), Value::Int(exp)) => int_pow(*base, *exp).map(Value::Int),
                 _ => Err("pow: expected two Ints".to_string()),
             }
         }),
+        builtin("float_pow", 2, |args| {
+            match (&args[0], &args[1]) {
+                (Value::Float(base), Value::Float(exp)) => Ok(Value::Float(base.powf(*exp))),
+                _ => Err("float_pow: expected two Floats".to_string()),
+            }
+        }),
+        builtin("divmod", 2, |args| {
+            let (q, r) = euclid("divmod", &args)?;
+            Ok(Value::Tuple(vec![Value::Int(q), Value::Int(r)]))
+        }),
+        builtin("div_euclid", 2, |args| euclid("div_euclid", &args).map(|(q, _)| Value::Int(q))),
+        builtin("mod_euclid", 2, |args| {
+            let (a, b) = division("mod_euclid", &args)?;
+            Ok(Value::Int(a.wrapping_rem_euclid(b)))
+        }),
         builtin("float_of_int", 1, |args| {
             match &args[0] {
                 Value::Int(n) => Ok(Value::Float(*n as f64)),
@@ -267,6 +544,223 @@ pub fn all_builtins() -> Vec<(String, Value)> {
             }
         }),
 
+        // Bytes
+        builtin("read_file_bytes", 1, |args| {
+            let path = file_path("read_file_bytes", &args[0])?;
+            std::fs::read(path)
+                .map(|bytes| Value::Bytes(bytes.into()))
+                .map_err(|e| format!("read_file_bytes: cannot read {}: {}", path, e))
+        }),
+        builtin("write_file_bytes", 2, |args| {
+            let path = file_path("write_file_bytes", &args[0])?;
+            let bytes = bytes_arg("write_file_bytes", &args[1])?;
+            std::fs::write(path, bytes)
+                .map(|_| Value::Unit)
+                .map_err(|e| format!("write_file_bytes: cannot write {}: {}", path, e))
+        }),
+        builtin("bytes_length", 1, |args| {
+            Ok(Value::Int(bytes_arg("bytes_length", &args[0])?.len() as i64))
+        }),
+        builtin("bytes_to_list", 1, |args| {
+            let bytes = bytes_arg("bytes_to_list", &args[0])?;
+            Ok(Value::List(bytes.iter().map(|b| Value::Int(*b as i64)).collect()))
+        }),
+        builtin("bytes_from_list", 1, |args| {
+            let Value::List(items) = &args[0] else {
+                return Err(format!("bytes_from_list: expected List, got {}", args[0].type_name()));
+            };
+            let bytes = items
+                .iter()
+                .map(|v| match v {
+                    Value::Int(n) => u8::try_from(*n)
+                        .map_err(|_| format!("bytes_from_list: {} is not a byte (0 to 255)", n)),
+                    v => Err(format!("bytes_from_list: expected Int, got {}", v.type_name())),
+                })
+                .collect::<Result<Vec<u8>, _>>()?;
+            Ok(Value::Bytes(bytes.into()))
+        }),
+        builtin("utf8_encode", 1, |args| {
+            let s = string_arg("utf8_encode", &args[0])?;
+            Ok(Value::Bytes(s.as_bytes().into()))
+        }),
+        builtin("utf8_decode", 1, |args| {
+            let bytes = bytes_arg("utf8_decode", &args[0])?;
+            std::str::from_utf8(bytes)
+                .map(|s| Value::String(s.to_string()))
+                .map_err(|e| format!("utf8_decode: invalid UTF-8 at byte {}", e.valid_up_to()))
+        }),
+        builtin("base64_encode", 1, |args| {
+            Ok(Value::String(base64_encode(bytes_arg("base64_encode", &args[0])?)))
+        }),
+        builtin("base64_decode", 1, |args| {
+            let text = string_arg("base64_decode", &args[0])?;
+            Ok(Value::Bytes(base64_decode(text)?.into()))
+        }),
+        builtin("hex_encode", 1, |args| {
+            let bytes = bytes_arg("hex_encode", &args[0])?;
+            Ok(Value::String(hex(bytes)))
+        }),
+        builtin("hex_decode", 1, |args| {
+            let text = string_arg("hex_decode", &args[0])?;
+            Ok(Value::Bytes(hex_decode(text)?.into()))
+        }),
+
+        // Hashing
+        builtin("hash", 1, |args| {
+            let mut state = std::collections::hash_map::DefaultHasher::new();
+            args[0].hash_into(&mut state)?;
+            Ok(Value::Int(std::hash::Hasher::finish(&state) as i64))
+        }),
+        builtin("sha256", 1, |args| {
+            let data = digest_input("sha256", &args[0])?;
+            Ok(Value::String(hex(&super::digest::sha256(data))))
+        }),
+        builtin("md5", 1, |args| {
+            let data = digest_input("md5", &args[0])?;
+            Ok(Value::String(hex(&super::digest::md5(data))))
+        }),
+
+        // Logging to stderr, filtered by the level
+        builtin("log_debug", 1, |args| {
+            logging::log(logging::Level::Debug, &args[0]);
+            Ok(Value::Unit)
+        }),
+        builtin("log_info", 1, |args| {
+            logging::log(logging::Level::Info, &args[0]);
+            Ok(Value::Unit)
+        }),
+        builtin("log_warn", 1, |args| {
+            logging::log(logging::Level::Warn, &args[0]);
+            Ok(Value::Unit)
+        }),
+        builtin("log_error", 1, |args| {
+            logging::log(logging::Level::Error, &args[0]);
+            Ok(Value::Unit)
+        }),
+        builtin("log_set_level", 1, |args| {
+            let name = string_arg("log_set_level", &args[0])?;
+            let level = logging::Level::parse(name).ok_or_else(|| {
+                format!("log_set_level: '{}' is not a level; use debug, info, warn, error or off", name)
+            })?;
+            logging::set_level(level);
+            Ok(Value::Unit)
+        }),
+        builtin("log_level", 0, |_| Ok(Value::String(logging::level().name().to_string()))),
+
+        // Terminal control: escapes come back as Strings to print
+        builtin("term_color", 2, |args| {
+            let spec = string_arg("term_color", &args[0])?;
+            Ok(Value::String(terminal::color(spec, &args[1].display_unquoted())?))
+        }),
+        builtin("term_clear", 0, |_| Ok(Value::String(terminal::CLEAR.to_string()))),
+        builtin("term_cursor_to", 2, |args| match (&args[0], &args[1]) {
+            (Value::Int(row), Value::Int(column)) => {
+                Ok(Value::String(terminal::cursor_to(*row, *column)?))
+            }
+            _ => Err("term_cursor_to: expected Int, Int".to_string()),
+        }),
+        builtin("term_size", 0, |_| {
+            let (columns, rows) = terminal::size();
+            let fields = [("columns", columns), ("rows", rows)];
+            Ok(Value::Record(
+                fields.into_iter().map(|(k, n)| (k.to_string(), Value::Int(n))).collect(),
+            ))
+        }),
+        builtin("term_read_key", 0, |_| {
+            // Whatever was printed should show before waiting on a key
+            std::io::Write::flush(&mut std::io::stdout()).ok();
+            Ok(Value::String(terminal::read_key()?))
+        }),
+
+        // Command-line arguments
+        builtin("memory_stats", 0, |_| {
+            let (env, vm) = memory::stats();
+            let fields = [("env", env), ("vm", vm)];
+            Ok(Value::Record(
+                fields.into_iter().map(|(k, usage)| (k.to_string(), usage.to_value())).collect(),
+            ))
+        }),
+
+        builtin("args", 0, |_| {
+            Ok(Value::List(ARGS.with(|a| a.borrow().iter().cloned().map(Value::String).collect())))
+        }),
+        builtin("parse_flags", 2, |args| {
+            let spec = flags::Spec::parse(string_arg("parse_flags", &args[0])?)?;
+            Ok(result_value(spec.parse_args(&string_list_arg("parse_flags", &args[1])?)))
+        }),
+        builtin("flag_value", 2, |args| match flag("flag_value", &args)? {
+            (_, value @ Value::String(_)) => Ok(value.clone()),
+            (name, _) => Err(format!("flag_value: '{}' isn't an option or argument", name)),
+        }),
+        builtin("flag_set", 2, |args| match flag("flag_set", &args)? {
+            (_, value @ Value::Bool(_)) => Ok(value.clone()),
+            (name, _) => Err(format!("flag_set: '{}' isn't a switch", name)),
+        }),
+        builtin("flag_rest", 2, |args| match flag("flag_rest", &args)? {
+            (_, value @ Value::List(_)) => Ok(value.clone()),
+            (name, _) => Err(format!("flag_rest: '{}' isn't a '...' argument", name)),
+        }),
+        builtin("flags_usage", 2, |args| {
+            let program = string_arg("flags_usage", &args[0])?;
+            let spec = flags::Spec::parse(string_arg("flags_usage", &args[1])?)?;
+            Ok(Value::String(spec.usage(program)))
+        }),
+
+        // UUIDs
+        builtin("uuid_v4", 0, |_| Ok(Value::String(uuid_v4()))),
+        builtin("uuid_valid", 1, |args| {
+            Ok(Value::Bool(uuid_parse(string_arg("uuid_valid", &args[0])?).is_ok()))
+        }),
+        builtin("uuid_parse", 1, |args| {
+            let text = string_arg("uuid_parse", &args[0])?;
+            Ok(result_value(uuid_parse(text).map(Value::String)))
+        }),
+
+        // Dates and times: `DateTime` records in UTC, durations in milliseconds
+        builtin("datetime_now", 0, |_| Ok(datetime::to_value(datetime::now()))),
+        builtin("datetime_parse", 2, |args| {
+            let format = string_arg("datetime_parse", &args[0])?;
+            let text = string_arg("datetime_parse", &args[1])?;
+            Ok(result_value(datetime::parse(format, text).map(datetime::to_value)))
+        }),
+        builtin("datetime_format", 2, |args| {
+            let format = string_arg("datetime_format", &args[0])?;
+            Ok(Value::String(datetime::format(format, datetime::from_value(&args[1])?)?))
+        }),
+        builtin("datetime_from_millis", 1, |args| match &args[0] {
+            Value::Int(ms) => Ok(datetime::to_value(*ms)),
+            v => Err(format!("datetime_from_millis: expected Int, got {}", v.type_name())),
+        }),
+        builtin("datetime_to_millis", 1, |args| {
+            Ok(Value::Int(datetime::from_value(&args[0])?))
+        }),
+        builtin("datetime_weekday", 1, |args| {
+            Ok(Value::Int(datetime::weekday(datetime::from_value(&args[0])?)))
+        }),
+        builtin("datetime_day_of_year", 1, |args| {
+            Ok(Value::Int(datetime::day_of_year(datetime::from_value(&args[0])?)))
+        }),
+        builtin("datetime_add", 2, |args| {
+            let ms = datetime::from_value(&args[0])?;
+            match &args[1] {
+                Value::Int(by) => ms
+                    .checked_add(*by)
+                    .map(datetime::to_value)
+                    .ok_or_else(|| "datetime_add: the result is out of range".to_string()),
+                v => Err(format!("datetime_add: expected Int, got {}", v.type_name())),
+            }
+        }),
+        builtin("datetime_diff", 2, |args| {
+            let (a, b) = (datetime::from_value(&args[0])?, datetime::from_value(&args[1])?);
+            a.checked_sub(b)
+                .map(Value::Int)
+                .ok_or_else(|| "datetime_diff: the result is out of range".to_string())
+        }),
+        builtin("duration_seconds", 1, |args| duration("duration_seconds", &args[0], 1000)),
+        builtin("duration_minutes", 1, |args| duration("duration_minutes", &args[0], 60_000)),
+        builtin("duration_hours", 1, |args| duration("duration_hours", &args[0], 3_600_000)),
+        builtin("duration_days", 1, |args| duration("duration_days", &args[0], 86_400_000)),
+
         // String utilities
         builtin("str_trim", 1, |args| {
             match &args[0] {
@@ -325,7 +819,156 @@ pub fn all_builtins() -> Vec<(String, Value)> {
             }
         }),
 
+        // Reflection
+        builtin("record_fields", 1, |args| {
+            match &args[0] {
+                Value::Record(fields) => Ok(Value::List(
+                    fields
+                        .iter()
+                        .map(|(k, v)| Value::Tuple(vec![Value::String(k.clone()), v.clone()]))
+                        .collect(),
+                )),
+                v => Err(format!("record_fields: expected Record, got {}", v.type_name())),
+            }
+        }),
+        builtin("record_get", 2, |args| {
+            match (&args[0], &args[1]) {
+                (Value::Record(fields), Value::String(name)) => Ok(match fields.get(name) {
+                    Some(v) => Value::adt("Some".to_string(), vec![v.clone()]),
+                    None => Value::adt("None".to_string(), vec![]),
+                }),
+                (v, _) => Err(format!("record_get: expected Record, got {}", v.type_name())),
+            }
+        }),
+        builtin("adt_constructor", 1, |args| {
+            match &args[0] {
+                Value::Adt(adt) => Ok(Value::String(adt.constructor.clone())),
+                v => Err(format!("adt_constructor: expected ADT, got {}", v.type_name())),
+            }
+        }),
+        builtin("adt_fields", 1, |args| {
+            match &args[0] {
+                Value::Adt(adt) => Ok(Value::List(adt.fields.clone())),
+                v => Err(format!("adt_fields: expected ADT, got {}", v.type_name())),
+            }
+        }),
+        // A reflected value back at its own type, or None
+        builtin("dynamic_int", 1, |args| {
+            Ok(dynamic_as(&args[0], matches!(args[0], Value::Int(_))))
+        }),
+        builtin("dynamic_float", 1, |args| {
+            Ok(dynamic_as(&args[0], matches!(args[0], Value::Float(_))))
+        }),
+        builtin("dynamic_string", 1, |args| {
+            Ok(dynamic_as(&args[0], matches!(args[0], Value::String(_))))
+        }),
+        builtin("dynamic_bool", 1, |args| {
+            Ok(dynamic_as(&args[0], matches!(args[0], Value::Bool(_))))
+        }),
+
+        // Runtime evaluation: Ok(value) or Err(message), for the program's own
+        // `type Result a e = Ok a | Err e`
+        builtin("eval", 1, |args| {
+            let source = match &args[0] {
+                Value::String(s) => s,
+                v => return Err(format!("eval: expected String, got {}", v.type_name())),
+            };
+            let result = crate::eval_source_vm(source);
+            // Running out of budget stops the caller too
+            if crate::budget::is_exceeded() {
+                return Err("eval: budget exceeded".to_string());
+            }
+            Ok(match result {
+                Ok(value) => Value::adt("Ok".to_string(), vec![value]),
+                Err(e) => Value::adt("Err".to_string(), vec![Value::String(e.to_string())]),
+            })
+        }),
+
+        // Memoization: the wrapper is applied in eval::apply_function
+        builtin("memo", 1, |args| {
+            Ok(Value::Memo {
+                func: Box::new(args[0].clone()),
+                cache: Default::default(),
+            })
+        }),
+
+        // Timing: call a function of no arguments `n` times
+        builtin("bench", 2, |args| {
+            let n = match &args[1] {
+                Value::Int(n) if *n > 0 => *n as usize,
+                Value::Int(n) => {
+                    return Err(format!("bench: iterations must be positive, got {}", n))
+                }
+                v => return Err(format!("bench: expected Int, got {}", v.type_name())),
+            };
+            if !args[0].is_callable() {
+                return Err(format!("bench: expected a function, got {}", args[0].type_name()));
+            }
+            let span = crate::span::Span::default();
+            let stats = crate::bench::measure(crate::bench::Iterations::Fixed(n), || {
+                crate::eval::apply_function(args[0].clone(), Vec::new(), span).map(|_| ())
+            })
+            .map_err(|e| e.to_string())?;
+            Ok(stats.to_value())
+        }),
+
         // Higher-order list functions are handled in eval/mod.rs
         // because they need to call back into the evaluator
-    ]
+    ];
+
+    // Config formats: Ok(value) or Err(message), like `eval`
+    #[cfg(feature = "toml")]
+    builtins.push(builtin("toml_parse", 1, |args| match &args[0] {
+        Value::String(s) => Ok(result_value(super::formats::parse_toml(s))),
+        v => Err(format!("toml_parse: expected String, got {}", v.type_name())),
+    }));
+    #[cfg(feature = "yaml")]
+    builtins.push(builtin("yaml_parse", 1, |args| match &args[0] {
+        Value::String(s) => Ok(result_value(super::formats::parse_yaml(s))),
+        v => Err(format!("yaml_parse: expected String, got {}", v.type_name())),
+    }));
+    builtins
+}
+
+/// A count of `unit_ms`-millisecond units in milliseconds, for
+/// `datetime_add`.
+fn duration(name: &str, count: &Value, unit_ms: i64) -> Result<Value, String> {
+    match count {
+        Value::Int(n) => n
+            .checked_mul(unit_ms)
+            .map(Value::Int)
+            .ok_or_else(|| format!("{}: {} is out of range", name, n)),
+        v => Err(format!("{}: expected Int, got {}", name, v.type_name())),
+    }
+}
+
+/// `Ok(value)` or `Err(message)` of the program's own `Result` type.
+/// The entry of parsed flags `args[0]` named by `args[1]`, written as in
+/// the spec or as its field, with the field's name.
+fn flag<'a>(builtin: &str, args: &'a [Value]) -> Result<(String, &'a Value), String> {
+    let Value::Record(fields) = &args[0] else {
+        return Err(format!("{}: expected Flags, got {}", builtin, args[0].type_name()));
+    };
+    let name = string_arg(builtin, &args[1])?;
+    let field = name.trim_start_matches('-').trim_matches(['<', '>']).replace('-', "_");
+    match fields.get(&field) {
+        Some(value) => Ok((field, value)),
+        None => Err(format!("{}: the spec has no '{}'", builtin, name)),
+    }
+}
+
+/// `Some(value)` if it has the type asked for, else `None`.
+fn dynamic_as(value: &Value, is_type: bool) -> Value {
+    if is_type {
+        Value::adt("Some".to_string(), vec![value.clone()])
+    } else {
+        Value::adt("None".to_string(), vec![])
+    }
+}
+
+fn result_value(result: Result<Value, String>) -> Value {
+    match result {
+        Ok(value) => Value::adt("Ok".to_string(), vec![value]),
+        Err(message) => Value::adt("Err".to_string(), vec![Value::String(message)]),
+    }
 }
@@ -0,0 +1,361 @@
+//! Calendar dates and times for the `datetime_*` builtins.
+//!
+//! A `DateTime` is a record `{ year, month, day, hour, minute, second,
+//! millisecond }` in UTC, so programs read components as fields and can
+//! write one as a literal. Arithmetic goes through milliseconds since the
+//! Unix epoch on the proleptic Gregorian calendar; durations are Ints of
+//! milliseconds.
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::value::Value;
+
+/// The record's fields, in the order `datetime_format` reads them.
+pub const FIELDS: [&str; 7] = ["year", "month", "day", "hour", "minute", "second", "millisecond"];
+
+const MONTHS: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September",
+    "October", "November", "December",
+];
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+/// Milliseconds since the Unix epoch, now.
+pub fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(before) => -(before.duration().as_millis() as i64),
+    }
+}
+
+/// A broken-down UTC time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Civil {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+    millisecond: i64,
+}
+
+impl Civil {
+    fn from_millis(ms: i64) -> Civil {
+        let days = ms.div_euclid(MS_PER_DAY);
+        let of_day = ms.rem_euclid(MS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        Civil {
+            year,
+            month,
+            day,
+            hour: of_day / 3_600_000,
+            minute: of_day / 60_000 % 60,
+            second: of_day / 1000 % 60,
+            millisecond: of_day % 1000,
+        }
+    }
+
+    fn to_millis(self) -> Result<i64, String> {
+        let checks = [
+            ("year", self.year, -999_999_999, 999_999_999),
+            ("month", self.month, 1, 12),
+            ("day", self.day, 1, days_in_month(self.year, self.month.clamp(1, 12))),
+            ("hour", self.hour, 0, 23),
+            ("minute", self.minute, 0, 59),
+            ("second", self.second, 0, 59),
+            ("millisecond", self.millisecond, 0, 999),
+        ];
+        for (field, value, low, high) in checks {
+            if value < low || value > high {
+                return Err(format!("{} {} is out of range {}..{}", field, value, low, high));
+            }
+        }
+        days_from_civil(self.year, self.month, self.day)
+            .checked_mul(MS_PER_DAY)
+            .and_then(|ms| ms.checked_add(self.hour * 3_600_000 + self.minute * 60_000))
+            .and_then(|ms| ms.checked_add(self.second * 1000 + self.millisecond))
+            .ok_or_else(|| format!("year {} is out of range", self.year))
+    }
+
+    /// 1 for Monday through 7 for Sunday; the epoch was a Thursday.
+    fn weekday(self) -> i64 {
+        (days_from_civil(self.year, self.month, self.day) + 3).rem_euclid(7) + 1
+    }
+
+    fn day_of_year(self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1) + 1
+    }
+}
+
+/// Days since 1970-01-01 of a date (Hinnant's `days_from_civil`).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// The date `days` after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The `DateTime` record for `ms` milliseconds since the epoch.
+pub fn to_value(ms: i64) -> Value {
+    let c = Civil::from_millis(ms);
+    let values = [c.year, c.month, c.day, c.hour, c.minute, c.second, c.millisecond];
+    Value::Record(
+        FIELDS
+            .iter()
+            .zip(values)
+            .map(|(field, n)| (field.to_string(), Value::Int(n)))
+            .collect::<BTreeMap<_, _>>(),
+    )
+}
+
+/// Milliseconds since the epoch of a `DateTime` record, checking that it
+/// names a real time.
+pub fn from_value(value: &Value) -> Result<i64, String> {
+    let Value::Record(fields) = value else {
+        return Err(format!("expected a DateTime record, got {}", value.type_name()));
+    };
+    let mut values = [0; 7];
+    for (slot, field) in values.iter_mut().zip(FIELDS) {
+        *slot = match fields.get(field) {
+            Some(Value::Int(n)) => *n,
+            Some(v) => return Err(format!("DateTime field '{}' is {}, not Int", field, v.type_name())),
+            None => return Err(format!("DateTime record has no field '{}'", field)),
+        };
+    }
+    let [year, month, day, hour, minute, second, millisecond] = values;
+    Civil { year, month, day, hour, minute, second, millisecond }.to_millis()
+}
+
+pub fn weekday(ms: i64) -> i64 {
+    Civil::from_millis(ms).weekday()
+}
+
+pub fn day_of_year(ms: i64) -> i64 {
+    Civil::from_millis(ms).day_of_year()
+}
+
+/// `ms` written out by `format`'s directives: `%Y` year, `%m` month, `%d`
+/// day, `%H` hour, `%M` minute, `%S` second, `%f` millisecond (3 digits),
+/// `%j` day of the year, `%a`/`%A` short and full weekday, `%b`/`%B` short
+/// and full month, `%z` the offset (always `+00:00`) and `%%` a percent
+/// sign. Anything else is copied as is.
+pub fn format(format: &str, ms: i64) -> Result<String, String> {
+    let c = Civil::from_millis(ms);
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", c.year)),
+            Some('m') => out.push_str(&format!("{:02}", c.month)),
+            Some('d') => out.push_str(&format!("{:02}", c.day)),
+            Some('H') => out.push_str(&format!("{:02}", c.hour)),
+            Some('M') => out.push_str(&format!("{:02}", c.minute)),
+            Some('S') => out.push_str(&format!("{:02}", c.second)),
+            Some('f') => out.push_str(&format!("{:03}", c.millisecond)),
+            Some('j') => out.push_str(&format!("{:03}", c.day_of_year())),
+            Some('a') => out.push_str(&WEEKDAYS[c.weekday() as usize - 1][..3]),
+            Some('A') => out.push_str(WEEKDAYS[c.weekday() as usize - 1]),
+            Some('b') => out.push_str(&MONTHS[c.month as usize - 1][..3]),
+            Some('B') => out.push_str(MONTHS[c.month as usize - 1]),
+            Some('z') => out.push_str("+00:00"),
+            Some('%') => out.push('%'),
+            Some(other) => return Err(format!("unknown directive '%{}'", other)),
+            None => return Err("format ends with a lone '%'".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// `text` read by the directives `format` documents. Numbers may have
+/// fewer digits than `format` writes, month and weekday names match in any
+/// case, a weekday is checked against the date, and `%z` takes `Z`,
+/// `+HH:MM` or `+HHMM` and converts to UTC. Unset fields default to the
+/// start of 1970-01-01.
+pub fn parse(format: &str, text: &str) -> Result<i64, String> {
+    let mut c = Civil { year: 1970, month: 1, day: 1, hour: 0, minute: 0, second: 0, millisecond: 0 };
+    let mut day_of_year = None;
+    let mut weekday = None;
+    let mut offset = 0;
+    let mut input = Input { text, at: 0 };
+    let mut chars = format.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            input.literal(ch)?;
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => {
+                let negative = input.eat('-');
+                let year = input.number("year", 4)?;
+                c.year = if negative { -year } else { year };
+            }
+            Some('m') => c.month = input.number("month", 2)?,
+            Some('d') => c.day = input.number("day", 2)?,
+            Some('H') => c.hour = input.number("hour", 2)?,
+            Some('M') => c.minute = input.number("minute", 2)?,
+            Some('S') => c.second = input.number("second", 2)?,
+            Some('f') => {
+                let start = input.at;
+                let fraction = input.number("fraction of a second", 9)?;
+                let digits = (input.at - start) as u32;
+                c.millisecond = if digits >= 3 {
+                    fraction / 10i64.pow(digits - 3)
+                } else {
+                    fraction * 10i64.pow(3 - digits)
+                };
+            }
+            Some('j') => day_of_year = Some(input.number("day of the year", 3)?),
+            Some('a') => weekday = Some(input.name("weekday", &WEEKDAYS, 3)?),
+            Some('A') => weekday = Some(input.name("weekday", &WEEKDAYS, 0)?),
+            Some('b') => c.month = input.name("month", &MONTHS, 3)?,
+            Some('B') => c.month = input.name("month", &MONTHS, 0)?,
+            Some('z') => offset = input.offset()?,
+            Some('%') => input.literal('%')?,
+            Some(other) => return Err(format!("unknown directive '%{}'", other)),
+            None => return Err("format ends with a lone '%'".to_string()),
+        }
+    }
+    if input.at < text.len() {
+        return Err(format!("unexpected '{}' at column {}", &text[input.at..], input.column()));
+    }
+
+    if let Some(n) = day_of_year {
+        let days = if is_leap(c.year) { 366 } else { 365 };
+        if n < 1 || n > days {
+            return Err(format!("day of the year {} is out of range 1..{}", n, days));
+        }
+        (_, c.month, c.day) = civil_from_days(days_from_civil(c.year, 1, 1) + n - 1);
+    }
+    let ms = c.to_millis()?;
+    if let Some(expected) = weekday {
+        if c.weekday() != expected {
+            return Err(format!(
+                "{}-{:02}-{:02} is a {}, not a {}",
+                c.year,
+                c.month,
+                c.day,
+                WEEKDAYS[c.weekday() as usize - 1],
+                WEEKDAYS[expected as usize - 1]
+            ));
+        }
+    }
+    Ok(ms - offset)
+}
+
+/// The rest of the text `parse` is reading.
+struct Input<'a> {
+    text: &'a str,
+    at: usize,
+}
+
+impl Input<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.at..]
+    }
+
+    fn column(&self) -> usize {
+        self.text[..self.at].chars().count() + 1
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        let found = self.rest().starts_with(ch);
+        if found {
+            self.at += ch.len_utf8();
+        }
+        found
+    }
+
+    fn literal(&mut self, ch: char) -> Result<(), String> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at column {}", ch, self.column()))
+        }
+    }
+
+    /// One to `max_digits` decimal digits.
+    fn number(&mut self, what: &str, max_digits: usize) -> Result<i64, String> {
+        let digits = self
+            .rest()
+            .bytes()
+            .take(max_digits)
+            .take_while(u8::is_ascii_digit)
+            .count();
+        if digits == 0 {
+            return Err(format!("expected {} at column {}", what, self.column()));
+        }
+        let n = self.rest()[..digits].parse().expect("ASCII digits");
+        self.at += digits;
+        Ok(n)
+    }
+
+    /// The 1-based index of the name in `names` the text starts with, in
+    /// full or, if `short` isn't 0, cut to its first `short` letters.
+    fn name(&mut self, what: &str, names: &[&str], short: usize) -> Result<i64, String> {
+        for (i, name) in names.iter().enumerate() {
+            let name = if short == 0 { name } else { &name[..short] };
+            let candidate = self.rest().get(..name.len()).unwrap_or_default();
+            if candidate.eq_ignore_ascii_case(name) {
+                self.at += name.len();
+                return Ok(i as i64 + 1);
+            }
+        }
+        Err(format!("expected {} name at column {}", what, self.column()))
+    }
+
+    /// A UTC offset in milliseconds.
+    fn offset(&mut self) -> Result<i64, String> {
+        if self.eat('Z') || self.eat('z') {
+            return Ok(0);
+        }
+        let sign = if self.eat('+') {
+            1
+        } else if self.eat('-') {
+            -1
+        } else {
+            return Err(format!("expected a UTC offset at column {}", self.column()));
+        };
+        let hours = self.number("offset hours", 2)?;
+        self.eat(':');
+        let minutes = self.number("offset minutes", 2)?;
+        if hours > 23 || minutes > 59 {
+            return Err(format!("offset {:02}:{:02} is out of range", hours, minutes));
+        }
+        Ok(sign * (hours * 3_600_000 + minutes * 60_000))
+    }
+}
@@ -1,52 +1,169 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 use super::value::Value;
 
+/// A scope of runtime bindings. The outermost, global scope binds by name;
+/// the local scopes inside it, one per `let`, match arm or call, keep their
+/// bindings in the order they were made, so a variable the resolver placed
+/// (see `resolve`) is found by position.
 #[derive(Clone)]
 pub struct Env {
     inner: Rc<EnvInner>,
 }
 
 struct EnvInner {
-    bindings: RefCell<HashMap<String, Value>>,
+    globals: RefCell<HashMap<String, Value>>,
+    locals: RefCell<Vec<(String, Value)>>,
     parent: Option<Env>,
 }
 
+impl Default for Env {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Env {
     pub fn new() -> Self {
         Env {
             inner: Rc::new(EnvInner {
-                bindings: RefCell::new(HashMap::new()),
+                globals: RefCell::new(HashMap::new()),
+                locals: RefCell::new(Vec::new()),
                 parent: None,
             }),
         }
     }
 
     pub fn extend(&self) -> Self {
+        self.extend_with(Vec::new())
+    }
+
+    /// A local scope inside this one holding `bindings`, in order.
+    pub fn extend_with(&self, bindings: Vec<(String, Value)>) -> Self {
         Env {
             inner: Rc::new(EnvInner {
-                bindings: RefCell::new(HashMap::new()),
+                globals: RefCell::new(HashMap::new()),
+                locals: RefCell::new(bindings),
                 parent: Some(self.clone()),
             }),
         }
     }
 
+    /// Bind `name`: in the global scope, replacing any earlier binding; in
+    /// a local scope, as the next slot, shadowing any earlier one.
     pub fn set(&self, name: String, value: Value) {
-        self.inner.bindings.borrow_mut().insert(name, value);
+        if self.inner.parent.is_none() {
+            self.inner.globals.borrow_mut().insert(name, value);
+        } else {
+            self.inner.locals.borrow_mut().push((name, value));
+        }
+    }
+
+    /// Names bound directly in this scope, not in its parents, sorted.
+    /// Names made up for the program, with a `#` in them, are left out.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.inner.globals.borrow().keys().cloned().collect();
+        names.extend(self.inner.locals.borrow().iter().map(|(name, _)| name.clone()));
+        names.retain(|name| !name.contains('#'));
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Every name visible from this scope, including its parents', sorted
+    /// and without duplicates.
+    pub fn visible_names(&self) -> Vec<String> {
+        let mut names = self.names();
+        let mut scope = self.inner.parent.clone();
+        while let Some(env) = scope {
+            names.extend(env.names());
+            scope = env.inner.parent.clone();
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// The bindings of this scope and its parents short of the outermost,
+    /// global one: innermost scope first, each sorted by name, with a
+    /// shadowed name listed only where it's visible.
+    pub fn locals(&self) -> Vec<(String, Value)> {
+        let mut locals: Vec<(String, Value)> = Vec::new();
+        let mut scope = Some(self.clone());
+        while let Some(env) = scope.filter(|env| env.inner.parent.is_some()) {
+            let start = locals.len();
+            // Later bindings in a scope shadow earlier ones
+            for (name, value) in env.inner.locals.borrow().iter().rev() {
+                if !locals.iter().any(|(seen, _)| seen == name) {
+                    locals.push((name.clone(), value.clone()));
+                }
+            }
+            locals[start..].sort_by(|a, b| a.0.cmp(&b.0));
+            scope = env.inner.parent.clone();
+        }
+        locals
     }
 
     pub fn get(&self, name: &str) -> Option<Value> {
-        if let Some(v) = self.inner.bindings.borrow().get(name) {
+        let locals = self.inner.locals.borrow();
+        if let Some((_, v)) = locals.iter().rev().find(|(n, _)| n == name) {
             Some(v.clone())
         } else if let Some(ref parent) = self.inner.parent {
             parent.get(name)
         } else {
-            None
+            self.inner.globals.borrow().get(name).cloned()
+        }
+    }
+
+    /// The binding at `slot` of the local scope `depth` levels out from this
+    /// one, as placed by the resolver.
+    pub fn get_slot(&self, depth: usize, slot: usize) -> Option<Value> {
+        let mut env = self;
+        for _ in 0..depth {
+            env = env.inner.parent.as_ref()?;
+        }
+        let locals = env.inner.locals.borrow();
+        locals.get(slot).map(|(_, v)| v.clone())
+    }
+
+    /// The bindings made directly in this scope, shadowed ones included,
+    /// for `f`.
+    pub fn for_each_binding(&self, mut f: impl FnMut(&str, &Value)) {
+        for (name, value) in self.inner.globals.borrow().iter() {
+            f(name, value);
+        }
+        for (name, value) in self.inner.locals.borrow().iter() {
+            f(name, value);
         }
     }
+
+    pub fn parent(&self) -> Option<&Env> {
+        self.inner.parent.as_ref()
+    }
+
+    /// Identifies the scope, shared by the `Env`s cloned from it.
+    pub fn id(&self) -> usize {
+        Rc::as_ptr(&self.inner) as usize
+    }
+
+    pub fn downgrade(&self) -> WeakEnv {
+        WeakEnv { inner: Rc::downgrade(&self.inner) }
+    }
+}
+
+/// An `Env` that doesn't keep its bindings alive.
+#[derive(Clone)]
+pub struct WeakEnv {
+    inner: Weak<EnvInner>,
+}
+
+impl WeakEnv {
+    pub fn upgrade(&self) -> Option<Env> {
+        self.inner.upgrade().map(|inner| Env { inner })
+    }
 }
 
 impl fmt::Debug for Env {
@@ -0,0 +1,282 @@
+//! Command-line parsing for scripts, behind `parse_flags` and `flags_usage`.
+//!
+//! A spec has a line per flag or argument, its help after two spaces:
+//!
+//! ```text
+//! -o, --output <file>  Where to write [default: out.txt]
+//! -v, --verbose        Print each step
+//! <input>              The file to read
+//! <rest>...            Anything else
+//! ```
+//!
+//! An option with a `<value>` becomes a String field, required unless it
+//! has a default; a flag without one is a Bool switch; `<name>` is a
+//! positional String, optional if it has a default; and a last `<name>...`
+//! collects the leftovers as a list. Field names are the long names with
+//! `-` as `_`. `-h, --help` is added unless the spec takes it, and when it
+//! is given the missing fields are left empty instead of reported.
+
+use std::collections::BTreeMap;
+
+use super::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    /// `--name <value>`
+    Option { value_name: String },
+    Switch,
+    /// `<name>`
+    Positional,
+    /// `<name>...`
+    Rest,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    /// As written in the spec: `--output` or `<input>`.
+    long: String,
+    short: Option<char>,
+    kind: Kind,
+    help: String,
+    default: Option<String>,
+}
+
+impl Entry {
+    fn field(&self) -> String {
+        self.long.trim_start_matches('-').trim_matches(['<', '>']).replace('-', "_")
+    }
+
+    /// The entry as the usage lists it.
+    fn synopsis(&self) -> String {
+        match &self.kind {
+            Kind::Option { value_name } => format!("{} <{}>", self.names(), value_name),
+            Kind::Switch => self.names(),
+            Kind::Positional => self.long.clone(),
+            Kind::Rest => format!("{}...", self.long),
+        }
+    }
+
+    fn names(&self) -> String {
+        match self.short {
+            Some(c) => format!("-{}, {}", c, self.long),
+            None => format!("    {}", self.long),
+        }
+    }
+}
+
+/// A parsed spec.
+#[derive(Debug, Clone)]
+pub struct Spec {
+    entries: Vec<Entry>,
+}
+
+impl Spec {
+    pub fn parse(text: &str) -> Result<Spec, String> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let entry = parse_line(line).map_err(|e| format!("spec line {}: {}", i + 1, e))?;
+            if entries.iter().any(|e| e.kind == Kind::Rest) && !matches!(entry.kind, Kind::Option { .. } | Kind::Switch) {
+                return Err(format!("spec line {}: nothing can follow a '...' argument", i + 1));
+            }
+            let clash = entries.iter().find(|e| {
+                e.field() == entry.field() || (e.short.is_some() && e.short == entry.short)
+            });
+            if let Some(earlier) = clash {
+                return Err(format!("spec line {}: '{}' is already defined", i + 1, earlier.long));
+            }
+            entries.push(entry);
+        }
+        if !entries.iter().any(|e| e.field() == "help") {
+            let short = (!entries.iter().any(|e| e.short == Some('h'))).then_some('h');
+            entries.push(Entry {
+                long: "--help".to_string(),
+                short,
+                kind: Kind::Switch,
+                help: "Show this help".to_string(),
+                default: None,
+            });
+        }
+        Ok(Spec { entries })
+    }
+
+    /// `args` as a record with a field per entry.
+    pub fn parse_args(&self, args: &[String]) -> Result<Value, String> {
+        let mut values: BTreeMap<String, Value> = BTreeMap::new();
+        let mut positional = Vec::new();
+        let mut iter = args.iter();
+        let mut flags_done = false;
+        while let Some(arg) = iter.next() {
+            if flags_done || arg == "-" || !arg.starts_with('-') {
+                positional.push(arg.clone());
+                continue;
+            }
+            if arg == "--" {
+                flags_done = true;
+                continue;
+            }
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if arg.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let entry = self
+                .entries
+                .iter()
+                .find(|e| match name.strip_prefix("--") {
+                    Some(_) => e.long == name,
+                    None => e.short.is_some_and(|c| name == format!("-{}", c)),
+                })
+                .filter(|e| matches!(e.kind, Kind::Option { .. } | Kind::Switch))
+                .ok_or_else(|| format!("unknown option '{}'", name))?;
+            let value = match (&entry.kind, inline) {
+                (Kind::Switch, None) => Value::Bool(true),
+                (Kind::Switch, Some(_)) => {
+                    return Err(format!("switch '{}' doesn't take a value", entry.long))
+                }
+                (_, Some(value)) => Value::String(value),
+                (_, None) => match iter.next() {
+                    Some(value) => Value::String(value.clone()),
+                    None => return Err(format!("option '{}' expects a value", entry.long)),
+                },
+            };
+            values.insert(entry.field(), value);
+        }
+
+        let help = values.get("help") == Some(&Value::Bool(true));
+        let mut positional = positional.into_iter();
+        for entry in &self.entries {
+            let field = entry.field();
+            let value = match &entry.kind {
+                Kind::Option { .. } if values.contains_key(&field) => continue,
+                Kind::Switch => Value::Bool(values.contains_key(&field)),
+                Kind::Rest => Value::List(positional.by_ref().map(Value::String).collect()),
+                Kind::Positional => match positional.next() {
+                    Some(arg) => Value::String(arg),
+                    None => match (&entry.default, help) {
+                        (Some(default), _) => Value::String(default.clone()),
+                        (None, true) => Value::String(String::new()),
+                        (None, false) => return Err(format!("missing argument {}", entry.long)),
+                    },
+                },
+                Kind::Option { .. } => match (&entry.default, help) {
+                    (Some(default), _) => Value::String(default.clone()),
+                    (None, true) => Value::String(String::new()),
+                    (None, false) => {
+                        return Err(format!("missing required option '{}'", entry.long))
+                    }
+                },
+            };
+            values.insert(field, value);
+        }
+        if let Some(extra) = positional.next() {
+            if !help {
+                return Err(format!("unexpected argument '{}'", extra));
+            }
+        }
+        Ok(Value::Record(values))
+    }
+
+    /// Usage text for `program`: a synopsis, then the arguments and options
+    /// with their help.
+    pub fn usage(&self, program: &str) -> String {
+        let mut synopsis = format!("Usage: {}", program);
+        if self.entries.iter().any(|e| matches!(e.kind, Kind::Option { .. } | Kind::Switch)) {
+            synopsis.push_str(" [options]");
+        }
+        for entry in &self.entries {
+            match (&entry.kind, &entry.default) {
+                (Kind::Positional, None) => synopsis.push_str(&format!(" {}", entry.long)),
+                (Kind::Positional, Some(_)) => synopsis.push_str(&format!(" [{}]", entry.long)),
+                (Kind::Rest, _) => synopsis.push_str(&format!(" [{}...]", entry.long)),
+                _ => {}
+            }
+        }
+
+        let width = self.entries.iter().map(|e| e.synopsis().len()).max().unwrap_or(0);
+        let section = |title: &str, flags: bool| {
+            let lines: Vec<String> = self
+                .entries
+                .iter()
+                .filter(|e| matches!(e.kind, Kind::Option { .. } | Kind::Switch) == flags)
+                .map(|e| {
+                    let mut help = e.help.clone();
+                    if let Some(default) = &e.default {
+                        help = format!("{} [default: {}]", help, default).trim_start().to_string();
+                    }
+                    format!("  {:width$}  {}", e.synopsis(), help).trim_end().to_string()
+                })
+                .collect();
+            match lines.is_empty() {
+                true => String::new(),
+                false => format!("\n\n{}:\n{}", title, lines.join("\n")),
+            }
+        };
+        format!("{}{}{}\n", synopsis, section("Arguments", false), section("Options", true))
+    }
+}
+
+/// One spec line, `names  help [default: value]`.
+fn parse_line(line: &str) -> Result<Entry, String> {
+    let (names, help) = match line.find("  ").or_else(|| line.find('\t')) {
+        Some(at) => (line[..at].trim(), line[at..].trim()),
+        None => (line, ""),
+    };
+    let (help, default) = match help.strip_suffix(']').and_then(|h| h.rsplit_once("[default:")) {
+        Some((help, default)) => (help.trim(), Some(default.trim().to_string())),
+        None => (help, None),
+    };
+
+    if names.starts_with('<') {
+        let (name, rest) = match names.strip_suffix("...") {
+            Some(name) => (name, true),
+            None => (names, false),
+        };
+        if !name.ends_with('>') || name.len() < 3 || name[1..name.len() - 1].contains(['<', '>', ' ']) {
+            return Err(format!("'{}' should be <name> or <name>...", names));
+        }
+        if rest && default.is_some() {
+            return Err(format!("'{}...' can't have a default", name));
+        }
+        return Ok(Entry {
+            long: name.to_string(),
+            short: None,
+            kind: if rest { Kind::Rest } else { Kind::Positional },
+            help: help.to_string(),
+            default,
+        });
+    }
+
+    let mut long = None;
+    let mut short = None;
+    let mut value_name = None;
+    for word in names.split([',', ' ']).filter(|w| !w.is_empty()) {
+        if let Some(name) = word.strip_prefix("--") {
+            if name.is_empty() || long.is_some() {
+                return Err(format!("'{}' needs one long --name", names));
+            }
+            long = Some(word.to_string());
+        } else if let Some(name) = word.strip_prefix('-') {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if short.is_none() => short = Some(c),
+                _ => return Err(format!("'{}' should be a single -letter", word)),
+            }
+        } else if word.starts_with('<') && word.ends_with('>') && value_name.is_none() {
+            value_name = Some(word[1..word.len() - 1].to_string());
+        } else {
+            return Err(format!("unexpected '{}'", word));
+        }
+    }
+    let Some(long) = long else {
+        return Err(format!("'{}' needs a long --name", names));
+    };
+    let kind = match value_name {
+        Some(value_name) => Kind::Option { value_name },
+        None if default.is_some() => return Err(format!("switch '{}' can't have a default", long)),
+        None => Kind::Switch,
+    };
+    Ok(Entry { long, short, kind, help: help.to_string(), default })
+}
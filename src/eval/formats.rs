@@ -0,0 +1,93 @@
+//! Config-file formats as Lyra values, for `toml_parse` and `yaml_parse`.
+//!
+//! Tables and mappings become records, arrays and sequences lists, and
+//! scalars the matching Int, Float, Bool or String; TOML dates are kept as
+//! their text and a YAML `null` is `()`. Each parser is behind the Cargo
+//! feature of the same name.
+
+use std::collections::BTreeMap;
+
+use super::value::Value;
+
+#[cfg(feature = "toml")]
+pub fn parse_toml(source: &str) -> Result<Value, String> {
+    fn convert(value: toml::Value) -> Value {
+        match value {
+            toml::Value::String(s) => Value::String(s),
+            toml::Value::Integer(n) => Value::Int(n),
+            toml::Value::Float(x) => Value::Float(x),
+            toml::Value::Boolean(b) => Value::Bool(b),
+            toml::Value::Datetime(d) => Value::String(d.to_string()),
+            toml::Value::Array(items) => Value::List(items.into_iter().map(convert).collect()),
+            toml::Value::Table(table) => Value::Record(
+                table
+                    .into_iter()
+                    .map(|(k, v)| (k, convert(v)))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+        }
+    }
+
+    let table: toml::Table = source.parse().map_err(|e: toml::de::Error| {
+        let message = match e.message().lines().collect::<Vec<_>>().join(", ") {
+            m if m.is_empty() => "invalid TOML".to_string(),
+            m => m,
+        };
+        match e.span() {
+            Some(span) => {
+                let before = &source[..span.start];
+                let line = before.matches('\n').count() + 1;
+                let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+                format!("line {}, column {}: {}", line, column, message)
+            }
+            None => message,
+        }
+    })?;
+    Ok(convert(toml::Value::Table(table)))
+}
+
+/// The first document of `source`; an empty source is `()`.
+#[cfg(feature = "yaml")]
+pub fn parse_yaml(source: &str) -> Result<Value, String> {
+    use yaml_rust2::{Yaml, YamlLoader};
+
+    fn convert(yaml: Yaml) -> Result<Value, String> {
+        Ok(match yaml {
+            Yaml::String(s) => Value::String(s),
+            Yaml::Integer(n) => Value::Int(n),
+            Yaml::Real(text) => Value::Float(
+                Yaml::Real(text.clone())
+                    .as_f64()
+                    .ok_or_else(|| format!("'{}' is not a number", text))?,
+            ),
+            Yaml::Boolean(b) => Value::Bool(b),
+            Yaml::Null => Value::Unit,
+            Yaml::Array(items) => Value::List(
+                items
+                    .into_iter()
+                    .map(convert)
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            Yaml::Hash(entries) => {
+                let mut fields = BTreeMap::new();
+                for (key, value) in entries {
+                    let key = match key {
+                        Yaml::String(s) | Yaml::Real(s) => s,
+                        Yaml::Integer(n) => n.to_string(),
+                        Yaml::Boolean(b) => b.to_string(),
+                        _ => return Err("mapping keys must be scalars".to_string()),
+                    };
+                    fields.insert(key, convert(value)?);
+                }
+                Value::Record(fields)
+            }
+            Yaml::Alias(_) | Yaml::BadValue => return Err("unsupported YAML value".to_string()),
+        })
+    }
+
+    let docs = YamlLoader::load_from_str(source).map_err(|e| e.to_string())?;
+    match docs.into_iter().next() {
+        Some(doc) => convert(doc),
+        None => Ok(Value::Unit),
+    }
+}
@@ -0,0 +1,98 @@
+//! Leveled logging to stderr for the `log_*` builtins.
+//!
+//! Each message is one logfmt line, `time=... level=info msg="..."`; a
+//! record logs its fields as the keys instead of `msg`. Messages below the
+//! level are dropped. The level starts from the `LYRA_LOG` environment
+//! variable, `info` if it's unset or not a level, and `log_set_level`
+//! changes it for the rest of the run.
+
+use std::cell::Cell;
+
+use super::datetime;
+use super::value::Value;
+
+/// How severe a message is; `Off` as a level drops everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Off,
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            "off" => Some(Level::Off),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Off => "off",
+        }
+    }
+}
+
+thread_local! {
+    static LEVEL: Cell<Level> = Cell::new(
+        std::env::var("LYRA_LOG")
+            .ok()
+            .and_then(|name| Level::parse(&name))
+            .unwrap_or(Level::Info),
+    );
+}
+
+pub fn level() -> Level {
+    LEVEL.with(Cell::get)
+}
+
+pub fn set_level(level: Level) {
+    LEVEL.with(|l| l.set(level));
+}
+
+/// Write `message` to stderr if `level` is at or above the current one.
+pub fn log(level: Level, message: &Value) {
+    if level >= self::level() {
+        eprintln!("{}", line(level, message, datetime::now()));
+    }
+}
+
+/// The line `message` logs at `level` and `ms` milliseconds since the epoch.
+pub fn line(level: Level, message: &Value, ms: i64) -> String {
+    let time = datetime::format("%Y-%m-%dT%H:%M:%S.%fZ", ms).expect("a fixed format");
+    let mut out = format!("time={} level={}", time, level.name());
+    match message {
+        Value::Record(fields) => {
+            for (key, value) in fields {
+                out.push_str(&format!(" {}={}", key, field(value)));
+            }
+        }
+        other => out.push_str(&format!(" msg={}", field(other))),
+    }
+    out
+}
+
+/// A logfmt value: bare if it's a single word, quoted otherwise.
+fn field(value: &Value) -> String {
+    let text = value.display_unquoted();
+    let bare = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_.:/@+".contains(c));
+    if bare {
+        text
+    } else {
+        format!("{:?}", text)
+    }
+}
@@ -0,0 +1,200 @@
+//! Approximate memory use, for `memory_stats()` and the REPL's `:memory`.
+//!
+//! A walk from a set of bindings counts every value it reaches and adds up
+//! their sizes and the heap they own: string and list buffers, record
+//! fields, closures' captured scopes, memo caches and compiled constants.
+//! What's shared through an `Rc`, a scope or a closure, is counted once,
+//! which also stops the walk going round a recursive binding. The
+//! program's syntax tree and the allocator's own overhead aren't counted,
+//! so the bytes are a lower bound.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem::size_of;
+
+use super::env::{Env, WeakEnv};
+use super::value::{Closure, Value};
+use crate::compiler::bytecode::{FunctionProto, Op};
+
+/// What a set of bindings holds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub bindings: usize,
+    /// Values reachable from the bindings, each shared one once.
+    pub values: usize,
+    pub bytes: usize,
+}
+
+impl Usage {
+    pub fn to_value(self) -> Value {
+        let fields = [("bindings", self.bindings), ("values", self.values), ("bytes", self.bytes)];
+        Value::Record(
+            fields.into_iter().map(|(k, n)| (k.to_string(), Value::Int(n as i64))).collect(),
+        )
+    }
+}
+
+thread_local! {
+    /// The global scope of the program running, for `memory_stats()`.
+    static GLOBALS: RefCell<Option<WeakEnv>> = const { RefCell::new(None) };
+}
+
+/// Make `env` the global scope `memory_stats()` reports on.
+pub fn track_globals(env: &Env) {
+    GLOBALS.with(|g| *g.borrow_mut() = Some(env.downgrade()));
+}
+
+/// The usage of the running program's global scope, and of the VM's
+/// globals when it's the VM running.
+pub fn stats() -> (Usage, Usage) {
+    let env = GLOBALS
+        .with(|g| g.borrow().as_ref().and_then(WeakEnv::upgrade))
+        .map(|env| env_usage(&env))
+        .unwrap_or_default();
+    let vm = super::get_vm_globals().map(|g| globals_usage(&g.borrow())).unwrap_or_default();
+    (env, vm)
+}
+
+/// The usage of `env`'s bindings and the scopes around it.
+pub fn env_usage(env: &Env) -> Usage {
+    let mut walk = Walk::default();
+    walk.env(env);
+    let mut bindings = 0;
+    let mut scope = Some(env);
+    while let Some(env) = scope {
+        env.for_each_binding(|_, _| bindings += 1);
+        scope = env.parent();
+    }
+    Usage { bindings, ..walk.usage }
+}
+
+/// The usage of the VM's `globals`.
+pub fn globals_usage(globals: &HashMap<String, Value>) -> Usage {
+    let mut walk = Walk::default();
+    walk.usage.bytes += globals.capacity() * size_of::<(String, Value)>();
+    for (name, value) in globals {
+        walk.usage.bytes += name.capacity();
+        walk.value(value);
+    }
+    Usage { bindings: globals.len(), ..walk.usage }
+}
+
+/// The usage of `value`, bound in `scope`: what it holds apart from the
+/// scope, which a closure it makes there captures.
+pub fn binding_usage(value: &Value, scope: &Env) -> Usage {
+    let mut walk = Walk::default();
+    walk.seen.insert(scope.id());
+    walk.value(value);
+    Usage { bindings: 1, ..walk.usage }
+}
+
+#[derive(Default)]
+struct Walk {
+    /// Addresses of the shared values and scopes already counted.
+    seen: HashSet<usize>,
+    usage: Usage,
+}
+
+impl Walk {
+    /// Whether the shared thing at `ptr` is new to the walk.
+    fn first_visit<T: ?Sized>(&mut self, ptr: *const T) -> bool {
+        self.seen.insert(ptr as *const () as usize)
+    }
+
+    fn env(&mut self, env: &Env) {
+        if !self.seen.insert(env.id()) {
+            return;
+        }
+        env.for_each_binding(|name, value| {
+            self.usage.bytes += size_of::<(String, Value)>() + name.len();
+            self.value(value);
+        });
+        if let Some(parent) = env.parent() {
+            self.env(parent);
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        self.usage.values += 1;
+        self.usage.bytes += size_of::<Value>();
+        match value {
+            Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Unit => {}
+            Value::String(s) => self.usage.bytes += s.capacity(),
+            Value::Bytes(bytes) => {
+                if self.first_visit(bytes.as_ptr()) {
+                    self.usage.bytes += bytes.len();
+                }
+            }
+            Value::List(items) | Value::Tuple(items) => {
+                self.usage.bytes += (items.capacity() - items.len()) * size_of::<Value>();
+                self.values(items);
+            }
+            Value::Record(fields) => self.record(fields),
+            Value::Closure(closure) => {
+                if self.first_visit(&**closure) {
+                    self.closure(closure);
+                }
+            }
+            Value::Builtin(builtin) => {
+                if self.first_visit(&**builtin) {
+                    self.usage.bytes += size_of::<super::value::Builtin>() + builtin.name.len();
+                }
+            }
+            Value::PartialApp { func, applied_args } => {
+                self.value(func);
+                if self.first_visit(applied_args.as_ptr()) {
+                    self.values(applied_args);
+                }
+            }
+            Value::Adt(adt) => {
+                if self.first_visit(&**adt) {
+                    self.usage.bytes += adt.constructor.len();
+                    self.values(&adt.fields);
+                }
+            }
+            Value::Memo { func, cache } => {
+                self.value(func);
+                if self.first_visit(cache.as_ptr()) {
+                    for (args, result) in cache.borrow().entries() {
+                        self.values(args);
+                        self.value(result);
+                    }
+                }
+            }
+            Value::Function(proto) => self.proto(proto),
+            Value::ClosureVal { proto, upvalues } => {
+                self.proto(proto);
+                if self.first_visit(upvalues.as_ptr()) {
+                    self.values(upvalues);
+                }
+            }
+        }
+    }
+
+    fn values(&mut self, values: &[Value]) {
+        for value in values {
+            self.value(value);
+        }
+    }
+
+    fn record(&mut self, fields: &BTreeMap<String, Value>) {
+        for (name, value) in fields {
+            self.usage.bytes += name.capacity();
+            self.value(value);
+        }
+    }
+
+    fn closure(&mut self, closure: &Closure) {
+        self.usage.bytes += size_of::<Closure>();
+        self.usage.bytes += closure.params.iter().map(|p| size_of::<String>() + p.len()).sum::<usize>();
+        self.env(&closure.env);
+    }
+
+    fn proto(&mut self, proto: &FunctionProto) {
+        if self.first_visit(proto) {
+            self.usage.bytes += size_of::<FunctionProto>() + proto.name.len();
+            self.usage.bytes += proto.chunk.code.len() * size_of::<Op>();
+            self.values(&proto.chunk.constants);
+        }
+    }
+}
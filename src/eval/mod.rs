@@ -1,10 +1,22 @@
 pub mod builtins;
+pub mod datetime;
+pub mod digest;
 pub mod env;
+pub mod flags;
+#[cfg(any(feature = "toml", feature = "yaml"))]
+pub mod formats;
+pub mod logging;
+pub mod memory;
 pub mod pattern;
+pub mod quote;
+pub mod resolve;
+pub mod terminal;
 pub mod value;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use crate::ast::*;
 use crate::error::LyraError;
@@ -12,25 +24,87 @@ use crate::span::Span;
 
 use env::Env;
 use pattern::match_pattern;
-use value::Value;
+use value::{Builtin, Closure, Value};
 
-// Thread-local storage for VM globals, used to pass globals to mini-VMs in callbacks.
+type VmGlobals = Rc<RefCell<HashMap<String, Value>>>;
+
+// Thread-local storage for VM globals, shared with mini-VMs in callbacks,
+// how many of those callbacks are running inside each other, and whether
+// they went too deep.
 thread_local! {
-    static VM_GLOBALS: RefCell<Option<HashMap<String, Value>>> = RefCell::new(None);
+    static VM_GLOBALS: RefCell<Option<VmGlobals>> = const { RefCell::new(None) };
+    static CALLBACK_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static CALLBACK_OVERFLOW: Cell<bool> = const { Cell::new(false) };
 }
 
-pub fn set_vm_globals(globals: HashMap<String, Value>) {
+pub fn set_vm_globals(globals: VmGlobals) {
     VM_GLOBALS.with(|g| {
         *g.borrow_mut() = Some(globals);
     });
 }
 
-fn get_vm_globals() -> Option<HashMap<String, Value>> {
+fn get_vm_globals() -> Option<VmGlobals> {
     VM_GLOBALS.with(|g| g.borrow().clone())
 }
 
+/// Run `f`, which starts a VM of its own, and give the callbacks of the
+/// VM that called it back their globals afterwards.
+pub(crate) fn with_own_vm_globals<T>(f: impl FnOnce() -> T) -> T {
+    let outer = VM_GLOBALS.with(|g| g.borrow_mut().take());
+    let result = f();
+    VM_GLOBALS.with(|g| *g.borrow_mut() = outer);
+    result
+}
+
+/// How deeply callbacks into compiled functions may nest. Each one runs on
+/// the native stack of the builtin that called it, so this is well below
+/// the VM's own frame limit.
+const MAX_CALLBACK_DEPTH: usize = 128;
+
+/// Run a compiled function called back from a builtin on a VM sharing the
+/// calling VM's globals.
+fn run_callback(
+    span: Span,
+    run: impl FnOnce(&mut crate::vm::VM) -> Result<Value, LyraError>,
+) -> Result<Value, LyraError> {
+    let depth = CALLBACK_DEPTH.with(Cell::get);
+    if depth >= MAX_CALLBACK_DEPTH {
+        CALLBACK_OVERFLOW.with(|o| o.set(true));
+        return Err(callback_overflow(span));
+    }
+    let mut vm = match get_vm_globals() {
+        Some(globals) => crate::vm::VM::with_globals(globals),
+        None => {
+            let mut vm = crate::vm::VM::new();
+            crate::stdlib::register_vm_stdlib(&mut vm);
+            vm
+        }
+    };
+    CALLBACK_DEPTH.with(|d| d.set(depth + 1));
+    let result = run(&mut vm);
+    CALLBACK_DEPTH.with(|d| d.set(depth));
+    result
+}
+
+fn callback_overflow(span: Span) -> LyraError {
+    LyraError::RuntimeError {
+        message: "stack overflow".to_string(),
+        span,
+    }
+}
+
 /// Evaluate an expression in the given environment.
-pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
+pub fn eval(env: &Env, ast: &Rc<Ast>, expr: ExprId) -> Result<Value, LyraError> {
+    let id = expr;
+    let expr = &ast[id];
+    if !crate::budget::tick() {
+        return Err(crate::budget::exceeded(expr.span));
+    }
+    crate::debugger::hook(expr.span, env);
+    crate::hooks::op(expr.span)?;
+    if crate::coverage::recording() {
+        crate::coverage::record(expr.span);
+    }
     match &expr.node {
         // ── Literals ──
         Expr::IntLit(n) => Ok(Value::Int(*n)),
@@ -40,82 +114,124 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
         Expr::UnitLit => Ok(Value::Unit),
 
         // ── Variable ──
-        Expr::Var(name) => env.get(name).ok_or_else(|| LyraError::UndefinedVariable {
-            suggestion: None, // env doesn't expose all names easily
-            name: name.clone(),
-            span: expr.span,
+        Expr::Var(name) => env.get(name).ok_or_else(|| {
+            let names = env.visible_names();
+            let candidates: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+            LyraError::UndefinedVariable {
+                suggestion: crate::error::suggest_similar(name, &candidates),
+                name: name.clone(),
+                span: expr.span,
+            }
+        }),
+        Expr::Local { name, depth, slot } => env.get_slot(*depth, *slot).ok_or_else(|| {
+            runtime_err(&format!("'{}' is not in the scope it was resolved to", name), expr.span)
         }),
 
         // ── List literal ──
         Expr::ListLit(elems) => {
-            let vals: Result<Vec<Value>, _> = elems.iter().map(|e| eval(env, e)).collect();
+            let vals: Result<Vec<Value>, _> = elems.iter().map(|&e| eval(env, ast, e)).collect();
             Ok(Value::List(vals?))
         }
 
         // ── Tuple literal ──
         Expr::TupleLit(elems) => {
-            let vals: Result<Vec<Value>, _> = elems.iter().map(|e| eval(env, e)).collect();
+            let vals: Result<Vec<Value>, _> = elems.iter().map(|&e| eval(env, ast, e)).collect();
             Ok(Value::Tuple(vals?))
         }
 
         // ── Lambda ──
-        Expr::Lambda { params, body } => Ok(Value::Closure {
+        Expr::Lambda { params, body } => Ok(Value::Closure(Rc::new(Closure {
             params: params.iter().map(|p| p.name.node.clone()).collect(),
-            body: *body.clone(),
+            ast: ast.clone(),
+            body: *body,
             env: env.clone(),
             recursive_name: None,
-        }),
+        }))),
 
         // ── Application ──
         Expr::App { func, args } => {
-            let func_val = eval(env, func)?;
-            let arg_vals: Result<Vec<Value>, _> = args.iter().map(|a| eval(env, a)).collect();
+            let func_val = eval(env, ast, *func)?;
+            if !func_val.is_callable() {
+                return Err(not_callable(&func_val, callee_text(ast, *func), expr.span));
+            }
+            if let Expr::Var(name) | Expr::Local { name, .. } = &ast[*func].node {
+                if let Some(builtin) = bare_call(&func_val, name).filter(|_| args.is_empty()) {
+                    return call_builtin(builtin, Vec::new(), expr.span);
+                }
+            }
+            let arg_vals: Result<Vec<Value>, _> = args.iter().map(|&a| eval(env, ast, a)).collect();
             apply_function(func_val, arg_vals?, expr.span)
         }
 
+        Expr::LabeledApp { func, args } => {
+            let func_val = eval(env, ast, *func)?;
+            let Value::Closure(closure) = &func_val else {
+                return Err(runtime_err(
+                    "labeled arguments need a function declared with named parameters",
+                    ast[*func].span,
+                ));
+            };
+            let name = match &ast[*func].node {
+                Expr::Var(name) | Expr::Local { name, .. } => name.clone(),
+                _ => func_val.callee_name(),
+            };
+            let order = order_args(ast, &name, &closure.params, args, expr.span)?;
+            let arg_vals: Vec<Value> =
+                args.iter().map(|a| eval(env, ast, a.value)).collect::<Result<_, _>>()?;
+            let arg_vals = order.into_iter().map(|i| arg_vals[i].clone()).collect();
+            apply_function(func_val, arg_vals, expr.span)
+        }
+
         // ── Binary operation ──
         Expr::BinOp { op, lhs, rhs } => {
             // Short-circuit for && and ||
             if *op == BinOp::And {
-                let l = eval(env, lhs)?;
+                let l = eval(env, ast, *lhs)?;
                 return match l {
                     Value::Bool(false) => Ok(Value::Bool(false)),
-                    Value::Bool(true) => eval(env, rhs),
-                    _ => Err(runtime_err("&& requires Bool operands", expr.span)),
+                    Value::Bool(true) => eval(env, ast, *rhs),
+                    other => Err(runtime_err(
+                        &format!("&& requires Bool operands, found {}", other.describe()),
+                        expr.span,
+                    )),
                 };
             }
             if *op == BinOp::Or {
-                let l = eval(env, lhs)?;
+                let l = eval(env, ast, *lhs)?;
                 return match l {
                     Value::Bool(true) => Ok(Value::Bool(true)),
-                    Value::Bool(false) => eval(env, rhs),
-                    _ => Err(runtime_err("|| requires Bool operands", expr.span)),
+                    Value::Bool(false) => eval(env, ast, *rhs),
+                    other => Err(runtime_err(
+                        &format!("|| requires Bool operands, found {}", other.describe()),
+                        expr.span,
+                    )),
                 };
             }
 
-            let l = eval(env, lhs)?;
-            let r = eval(env, rhs)?;
+            if *op == BinOp::Add && matches!(ast[*lhs].node, Expr::BinOp { op: BinOp::Add, .. }) {
+                return eval_add_chain(env, ast, &crate::ast::add_chain(ast, id));
+            }
+
+            let l = eval(env, ast, *lhs)?;
+            let r = eval(env, ast, *rhs)?;
             eval_binop(op, l, r, expr.span)
         }
 
         // ── Unary operation ──
         Expr::UnaryOp { op, operand } => {
-            let val = eval(env, operand)?;
+            let val = eval(env, ast, *operand)?;
             match (op, &val) {
                 (UnaryOp::Neg, Value::Int(n)) => Ok(Value::Int(-n)),
                 (UnaryOp::Neg, Value::Float(n)) => Ok(Value::Float(-n)),
                 (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
-                _ => Err(runtime_err(
-                    &format!("invalid unary operation on {}", val.type_name()),
-                    expr.span,
-                )),
+                _ => Err(runtime_err(&value::unop_error(*op, &val), expr.span)),
             }
         }
 
         // ── Pipe ──
         Expr::Pipe { lhs, rhs } => {
-            let arg = eval(env, lhs)?;
-            let func = eval(env, rhs)?;
+            let arg = eval(env, ast, *lhs)?;
+            let func = eval(env, ast, *rhs)?;
             apply_function(func, vec![arg], expr.span)
         }
 
@@ -125,11 +241,11 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
             then_branch,
             else_branch,
         } => {
-            let cond_val = eval(env, cond)?;
+            let cond_val = eval(env, ast, *cond)?;
             match cond_val {
-                Value::Bool(true) => eval(env, then_branch),
-                Value::Bool(false) => eval(env, else_branch),
-                _ => Err(runtime_err("if condition must be Bool", cond.span)),
+                Value::Bool(true) => eval(env, ast, *then_branch),
+                Value::Bool(false) => eval(env, ast, *else_branch),
+                _ => Err(runtime_err("if condition must be Bool", ast[*cond].span)),
             }
         }
 
@@ -143,45 +259,41 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
         } => {
             if *recursive {
                 // For recursive let, evaluate the binding and patch self-reference
-                let val = eval(env, value)?;
+                let val = eval(env, ast, *value)?;
                 let val = match val {
-                    Value::Closure {
-                        params,
-                        body: cb,
-                        env: cenv,
-                        ..
-                    } => Value::Closure {
-                        params,
-                        body: cb,
-                        env: cenv,
+                    Value::Closure(c) => Value::Closure(Rc::new(Closure {
+                        params: c.params.clone(),
+                        ast: c.ast.clone(),
+                        body: c.body,
+                        env: c.env.clone(),
                         recursive_name: Some(name.node.clone()),
-                    },
+                    })),
                     other => other,
                 };
                 let new_env = env.extend();
                 new_env.set(name.node.clone(), val);
-                eval(&new_env, body)
+                eval(&new_env, ast, *body)
             } else {
-                let val = eval(env, value)?;
+                let val = eval(env, ast, *value)?;
                 let new_env = env.extend();
                 new_env.set(name.node.clone(), val);
-                eval(&new_env, body)
+                eval(&new_env, ast, *body)
             }
         }
 
         // ── Match expression ──
         Expr::Match { scrutinee, arms } => {
-            let scrut_val = eval(env, scrutinee)?;
+            let scrut_val = eval(env, ast, *scrutinee)?;
             for arm in arms {
                 if let Some(bindings) = match_pattern(&arm.pattern, &scrut_val) {
-                    let arm_env = env.extend();
-                    for (name, val) in bindings {
-                        arm_env.set(name, val);
-                    }
-                    return eval(&arm_env, &arm.body);
+                    return eval(&env.extend_with(bindings), ast, arm.body);
                 }
             }
-            Err(LyraError::MatchFailure { span: expr.span })
+            Err(LyraError::MatchFailure {
+                value: scrut_val.describe(),
+                patterns: arms.iter().map(|a| a.pattern.node.to_string()).collect(),
+                span: expr.span,
+            })
         }
 
         // ── String interpolation ──
@@ -190,9 +302,12 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
             for part in parts {
                 match part {
                     crate::ast::InterpolationPart::Literal(s) => result.push_str(s),
-                    crate::ast::InterpolationPart::Expr(e) => {
-                        let val = eval(env, e)?;
-                        result.push_str(&val.display_unquoted());
+                    crate::ast::InterpolationPart::Expr(e, spec) => {
+                        let val = eval(env, ast, *e)?;
+                        match spec {
+                            Some(spec) => result.push_str(&val.display_formatted(spec)),
+                            None => result.push_str(&val.display_unquoted()),
+                        }
                     }
                 }
             }
@@ -203,7 +318,7 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
         Expr::Record(fields) => {
             let mut map = std::collections::BTreeMap::new();
             for (name, val_expr) in fields {
-                let val = eval(env, val_expr)?;
+                let val = eval(env, ast, *val_expr)?;
                 map.insert(name.clone(), val);
             }
             Ok(Value::Record(map))
@@ -211,7 +326,7 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
 
         // ── Field access ──
         Expr::FieldAccess { expr: obj, field } => {
-            let val = eval(env, obj)?;
+            let val = eval(env, ast, *obj)?;
             match val {
                 Value::Record(map) => map.get(field).cloned().ok_or_else(|| {
                     runtime_err(&format!("record has no field '{}'", field), expr.span)
@@ -222,102 +337,157 @@ pub fn eval(env: &Env, expr: &SpannedExpr) -> Result<Value, LyraError> {
                 )),
             }
         }
+
+        // ── Slice ──
+        Expr::Slice { expr: obj, start, end } => {
+            let val = eval(env, ast, *obj)?;
+            let start = eval_bound(env, ast, *start)?;
+            let end = eval_bound(env, ast, *end)?;
+            value::slice(&val, start, end).map_err(|msg| runtime_err(&msg, expr.span))
+        }
+
+        Expr::Import(path) => Err(unresolved_import(path, expr.span)),
+
+        Expr::Quote(inner) => quote::quote(ast, *inner, &mut |spliced| eval(env, ast, spliced)),
+        Expr::Unquote(_) => Err(LyraError::MisplacedUnquote { span: expr.span }),
     }
 }
 
+/// The value of a slice bound, if it wasn't left out.
+fn eval_bound(env: &Env, ast: &Rc<Ast>, bound: Option<ExprId>) -> Result<Option<i64>, LyraError> {
+    let Some(bound) = bound else {
+        return Ok(None);
+    };
+    match eval(env, ast, bound)? {
+        Value::Int(i) => Ok(Some(i)),
+        other => Err(runtime_err(
+            &format!("slice bound must be an Int, found {}", other.describe()),
+            ast[bound].span,
+        )),
+    }
+}
+
+/// The builtin `name()` runs with no arguments, where a call without
+/// arguments otherwise leaves `func` as it is: only a variadic builtin
+/// called by its own name is run, as in `println()`, since only then does
+/// the checker know it's variadic.
+pub(crate) fn bare_call(func: &Value, name: &str) -> Option<Rc<Builtin>> {
+    match func {
+        Value::Builtin(builtin) if builtin.variadic && builtin.name == name => {
+            Some(builtin.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Run a builtin on at least as many arguments as its arity, or on any
+/// number if it's variadic, applying the result to the rest.
+pub(crate) fn call_builtin(
+    builtin: Rc<Builtin>,
+    args: Vec<Value>,
+    span: Span,
+) -> Result<Value, LyraError> {
+    let Builtin {
+        ref name,
+        arity,
+        variadic,
+        func: f,
+    } = *builtin;
+    let taken = if variadic { args.len() } else { arity };
+    crate::hooks::call(name, &args[..taken]);
+    let result = f(args[..taken].to_vec()).map_err(|msg| {
+        // A callback ran out of budget: report that, not the builtin
+        if crate::budget::is_exceeded() {
+            return crate::budget::exceeded(span);
+        }
+        // So did callbacks nested too deep, once it's back outside them all
+        if CALLBACK_OVERFLOW.with(Cell::get) {
+            if CALLBACK_DEPTH.with(Cell::get) == 0 {
+                CALLBACK_OVERFLOW.with(|o| o.set(false));
+            }
+            return callback_overflow(span);
+        }
+        // Builtin messages usually lead with their own name
+        let prefix = format!("{}: ", name);
+        LyraError::CallFailed {
+            message: msg.strip_prefix(&prefix).unwrap_or(&msg).to_string(),
+            name: name.clone(),
+            arity,
+            found: args.len(),
+            span,
+        }
+    })?;
+    crate::hooks::ret(&result);
+    apply_extra_args(result, &args, taken, name, span)
+}
+
 /// Apply a function value to arguments.
 pub fn apply_function(func: Value, args: Vec<Value>, span: Span) -> Result<Value, LyraError> {
     match func {
-        Value::Closure {
-            params,
-            body,
-            env,
-            recursive_name,
-        } => {
+        Value::Closure(closure) => {
+            let params = &closure.params;
             if args.len() < params.len() {
                 // Partial application
                 return Ok(Value::PartialApp {
-                    func: Box::new(Value::Closure {
-                        params,
-                        body,
-                        env,
-                        recursive_name,
-                    }),
-                    applied_args: args,
+                    func: Box::new(Value::Closure(closure)),
+                    applied_args: args.into(),
                 });
             }
 
-            let call_env = env.extend();
-
-            // Self-reference for recursive functions
-            if let Some(ref rec_name) = recursive_name {
-                call_env.set(
-                    rec_name.clone(),
-                    Value::Closure {
-                        params: params.clone(),
-                        body: body.clone(),
-                        env: env.clone(),
-                        recursive_name: recursive_name.clone(),
-                    },
-                );
-            }
-
-            for (param, arg) in params.iter().zip(args.iter()) {
-                call_env.set(param.clone(), arg.clone());
+            // The parameters, then the function itself if it's recursive and
+            // no parameter shadows it, as `resolve` lays the scope out
+            let mut bindings: Vec<(String, Value)> =
+                params.iter().cloned().zip(args.iter().cloned()).collect();
+            if let Some(rec_name) = closure.recursive_name.as_ref().filter(|n| !params.contains(n)) {
+                bindings.push((rec_name.clone(), Value::Closure(closure.clone())));
             }
+            let call_env = closure.env.extend_with(bindings);
 
             // If more args than params, apply rest to the result (currying)
-            let result = eval(&call_env, &body)?;
-            if args.len() > params.len() {
-                apply_function(result, args[params.len()..].to_vec(), span)
-            } else {
-                Ok(result)
-            }
+            let name = closure.recursive_name.as_deref().unwrap_or("<lambda>");
+            crate::hooks::call(name, &args[..params.len()]);
+            let result = eval(&call_env, &closure.ast, closure.body)?;
+            crate::hooks::ret(&result);
+            apply_extra_args(result, &args, params.len(), name, span)
         }
 
-        Value::Builtin {
-            func: f,
-            arity,
-            name,
-        } => {
-            if args.len() < arity {
+        Value::Builtin(builtin) => {
+            if args.len() < builtin.arity {
                 return Ok(Value::PartialApp {
-                    func: Box::new(Value::Builtin {
-                        name,
-                        arity,
-                        func: f,
-                    }),
-                    applied_args: args,
+                    func: Box::new(Value::Builtin(builtin)),
+                    applied_args: args.into(),
                 });
             }
-            let (call_args, rest) = args.split_at(arity);
-            let result = f(call_args.to_vec()).map_err(|msg| LyraError::RuntimeError {
-                message: msg,
-                span,
-            })?;
-            if rest.is_empty() {
-                Ok(result)
-            } else {
-                apply_function(result, rest.to_vec(), span)
-            }
+            call_builtin(builtin, args, span)
         }
 
-        Value::PartialApp {
-            func,
-            mut applied_args,
-        } => {
-            applied_args.extend(args);
-            apply_function(*func, applied_args, span)
+        Value::PartialApp { func, applied_args } => {
+            let mut all_args = applied_args.to_vec();
+            all_args.extend(args);
+            apply_function(*func, all_args, span)
         }
 
-        // ADT constructors can be applied like functions
-        Value::Adt {
-            constructor,
-            fields,
-        } if fields.is_empty() && !args.is_empty() => Ok(Value::Adt {
-            constructor,
-            fields: args,
-        }),
+        Value::Memo { func, cache } => {
+            let arity = func.total_arity().max(1);
+            let name = func.callee_name();
+            if args.len() < arity {
+                return Ok(Value::PartialApp {
+                    func: Box::new(Value::Memo { func, cache }),
+                    applied_args: args.into(),
+                });
+            }
+            let call_args = &args[..arity];
+            let cached = cache.borrow().get(call_args);
+            let result = match cached {
+                Some(v) => v,
+                None => {
+                    let v = apply_function(*func, call_args.to_vec(), span)?;
+                    cache.borrow_mut().insert(call_args.to_vec(), v.clone());
+                    v
+                }
+            };
+            apply_extra_args(result, &args, arity, &name, span)
+        }
 
         // VM compiled functions — execute via mini VM with globals from calling VM
         Value::Function(proto) => {
@@ -325,22 +495,12 @@ pub fn apply_function(func: Value, args: Vec<Value>, span: Span) -> Result<Value
             if args.len() < arity {
                 return Ok(Value::PartialApp {
                     func: Box::new(Value::Function(proto)),
-                    applied_args: args,
+                    applied_args: args.into(),
                 });
             }
-            let mut vm = crate::vm::VM::new();
-            crate::stdlib::register_vm_stdlib(&mut vm);
-            if let Some(globals) = get_vm_globals() {
-                for (name, val) in globals {
-                    vm.define_global(name, val);
-                }
-            }
-            let result = vm.call_function(proto, args[..arity].to_vec())?;
-            if args.len() > arity {
-                apply_function(result, args[arity..].to_vec(), span)
-            } else {
-                Ok(result)
-            }
+            let name = proto.name.clone();
+            let result = run_callback(span, |vm| vm.call_function(proto, args[..arity].to_vec()))?;
+            apply_extra_args(result, &args, arity, &name, span)
         }
 
         Value::ClosureVal { proto, upvalues } => {
@@ -348,29 +508,75 @@ pub fn apply_function(func: Value, args: Vec<Value>, span: Span) -> Result<Value
             if args.len() < arity {
                 return Ok(Value::PartialApp {
                     func: Box::new(Value::ClosureVal { proto, upvalues }),
-                    applied_args: args,
+                    applied_args: args.into(),
                 });
             }
-            let mut vm = crate::vm::VM::new();
-            crate::stdlib::register_vm_stdlib(&mut vm);
-            if let Some(globals) = get_vm_globals() {
-                for (name, val) in globals {
-                    vm.define_global(name, val);
-                }
-            }
-            let result = vm.call_closure(proto, upvalues, args[..arity].to_vec())?;
-            if args.len() > arity {
-                apply_function(result, args[arity..].to_vec(), span)
-            } else {
-                Ok(result)
-            }
+            let name = proto.name.clone();
+            let result =
+                run_callback(span, |vm| vm.call_closure(proto, upvalues, args[..arity].to_vec()))?;
+            apply_extra_args(result, &args, arity, &name, span)
         }
 
-        _ => Err(LyraError::NotCallable { span }),
+        other => Err(not_callable(&other, None, span)),
     }
 }
 
+/// Apply whatever arguments are left after a callee of `arity` was saturated.
+/// If the result can't take them, the failure names the original callee.
+fn apply_extra_args(
+    result: Value,
+    args: &[Value],
+    arity: usize,
+    name: &str,
+    span: Span,
+) -> Result<Value, LyraError> {
+    if args.len() <= arity {
+        return Ok(result);
+    }
+    if !result.is_callable() {
+        return Err(LyraError::CallFailed {
+            name: name.to_string(),
+            arity,
+            found: args.len(),
+            message: format!("result {} is not a function", result.describe()),
+            span,
+        });
+    }
+    apply_function(result, args[arity..].to_vec(), span)
+}
+
+/// `a + b + c ...` left to right. Once the sum is a string the rest are
+/// appended to one buffer, rather than copying it at every `+`.
+fn eval_add_chain(env: &Env, ast: &Rc<Ast>, operands: &[ExprId]) -> Result<Value, LyraError> {
+    let first = operands[0];
+    let mut sum = eval(env, ast, first)?;
+    for &operand in &operands[1..] {
+        let rhs = eval(env, ast, operand)?;
+        sum = match (sum, rhs) {
+            (Value::String(mut built), Value::String(s)) => {
+                built.push_str(&s);
+                Value::String(built)
+            }
+            (lhs, rhs) => {
+                let span = ast[first].span.merge(ast[operand].span);
+                eval_binop(&BinOp::Add, lhs, rhs, span)?
+            }
+        };
+    }
+    Ok(sum)
+}
+
 fn eval_binop(op: &BinOp, lhs: Value, rhs: Value, span: Span) -> Result<Value, LyraError> {
+    // Mixed Int/Float arithmetic and ordering promote to Float
+    let (lhs, rhs) = match op {
+        BinOp::Add
+        | BinOp::Sub
+        | BinOp::Mul
+        | BinOp::Div
+        | BinOp::Mod
+        | BinOp::Pow => value::promote_numeric(lhs, rhs),
+        _ => (lhs, rhs),
+    };
     match (op, &lhs, &rhs) {
         // Int arithmetic
         (BinOp::Add, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
@@ -380,6 +586,9 @@ fn eval_binop(op: &BinOp, lhs: Value, rhs: Value, span: Span) -> Result<Value, L
         (BinOp::Div, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a / b)),
         (BinOp::Mod, Value::Int(_), Value::Int(0)) => Err(LyraError::DivisionByZero { span }),
         (BinOp::Mod, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a % b)),
+        (BinOp::Pow, Value::Int(a), Value::Int(b)) => value::int_pow(*a, *b)
+            .map(Value::Int)
+            .map_err(|msg| runtime_err(&msg, span)),
 
         // Float arithmetic
         (BinOp::Add, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
@@ -387,31 +596,30 @@ fn eval_binop(op: &BinOp, lhs: Value, rhs: Value, span: Span) -> Result<Value, L
         (BinOp::Mul, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
         (BinOp::Div, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
         (BinOp::Mod, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a % b)),
+        (BinOp::Pow, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a.powf(*b))),
 
         // String concatenation via +
         (BinOp::Add, Value::String(a), Value::String(b)) => {
             Ok(Value::String(format!("{}{}", a, b)))
         }
 
-        // Int comparison
-        (BinOp::Lt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
-        (BinOp::Gt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
-        (BinOp::Le, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
-        (BinOp::Ge, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
-
-        // Float comparison
-        (BinOp::Lt, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a < b)),
-        (BinOp::Gt, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a > b)),
-        (BinOp::Le, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a <= b)),
-        (BinOp::Ge, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a >= b)),
-
-        // String comparison
-        (BinOp::Lt, Value::String(a), Value::String(b)) => Ok(Value::Bool(a < b)),
-        (BinOp::Gt, Value::String(a), Value::String(b)) => Ok(Value::Bool(a > b)),
+        // Ordering (structural)
+        (BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge, a, b) => {
+            let test = match op {
+                BinOp::Lt => Ordering::is_lt,
+                BinOp::Gt => Ordering::is_gt,
+                BinOp::Le => Ordering::is_le,
+                _ => Ordering::is_ge,
+            };
+            let order = a.compare(b).map_err(|msg| runtime_err(&msg, span))?;
+            Ok(Value::Bool(order.is_some_and(test)))
+        }
 
-        // Equality (polymorphic)
-        (BinOp::Eq, a, b) => Ok(Value::Bool(a == b)),
-        (BinOp::NotEq, a, b) => Ok(Value::Bool(a != b)),
+        // Equality (structural)
+        (BinOp::Eq | BinOp::NotEq, a, b) => {
+            let equal = a.equals(b).map_err(|msg| runtime_err(&msg, span))?;
+            Ok(Value::Bool(equal == matches!(op, BinOp::Eq)))
+        }
 
         // Cons
         (BinOp::Cons, val, Value::List(list)) => {
@@ -420,15 +628,27 @@ fn eval_binop(op: &BinOp, lhs: Value, rhs: Value, span: Span) -> Result<Value, L
             Ok(Value::List(new_list))
         }
 
-        _ => Err(runtime_err(
-            &format!(
-                "invalid operation {} {} {}",
-                lhs.type_name(),
-                op.as_str(),
-                rhs.type_name()
-            ),
-            span,
-        )),
+        _ => Err(runtime_err(&value::binop_error(*op, &lhs, &rhs), span)),
+    }
+}
+
+/// The error for calling `value`, naming the callee when the call site
+/// spells it out as a variable or field.
+pub(crate) fn not_callable(value: &Value, callee: Option<String>, span: Span) -> LyraError {
+    LyraError::NotCallable {
+        value: value.describe(),
+        callee,
+        span,
+    }
+}
+
+fn callee_text(ast: &Ast, func: ExprId) -> Option<String> {
+    match &ast[func].node {
+        Expr::Var(name) | Expr::Local { name, .. } => Some(name.clone()),
+        Expr::FieldAccess { expr, field } => {
+            callee_text(ast, *expr).map(|base| format!("{}.{}", base, field))
+        }
+        _ => None,
     }
 }
 
@@ -439,8 +659,36 @@ fn runtime_err(message: &str, span: Span) -> LyraError {
     }
 }
 
-/// Evaluate a top-level declaration, updating the environment.
-pub fn eval_decl(env: &Env, decl: &Decl) -> Result<Option<Value>, LyraError> {
+/// Check the host's value for an `extern` declared to take `arity`
+/// arguments: any value will do for a non-function, but a builtin has to
+/// take as many arguments as declared.
+pub(crate) fn check_extern(
+    name: &str,
+    arity: usize,
+    value: Option<&Value>,
+    span: Span,
+) -> Result<(), LyraError> {
+    let reason = match value {
+        None => "was not provided by the host".to_string(),
+        Some(Value::Builtin(builtin)) if !builtin.variadic && builtin.arity != arity => format!(
+            "is declared to take {}, but the host's takes {}",
+            crate::error::plural_args(arity),
+            crate::error::plural_args(builtin.arity)
+        ),
+        Some(_) => return Ok(()),
+    };
+    Err(LyraError::UnboundExtern {
+        name: name.to_string(),
+        reason,
+        span,
+    })
+}
+
+/// Evaluate a top-level declaration, updating the environment. Its local
+/// variables should be resolved to slots first (see `resolve`); any that
+/// aren't are looked up by name.
+pub fn eval_decl(env: &Env, ast: &Rc<Ast>, decl: &Decl) -> Result<Option<Value>, LyraError> {
+    memory::track_globals(env);
     match decl {
         Decl::Let {
             name,
@@ -448,20 +696,16 @@ pub fn eval_decl(env: &Env, decl: &Decl) -> Result<Option<Value>, LyraError> {
             body,
             ..
         } => {
-            let val = eval(env, body)?;
+            let val = eval(env, ast, *body)?;
             let val = if *recursive {
                 match val {
-                    Value::Closure {
-                        params,
-                        body: cb,
-                        env: cenv,
-                        ..
-                    } => Value::Closure {
-                        params,
-                        body: cb,
-                        env: cenv,
+                    Value::Closure(c) => Value::Closure(Rc::new(Closure {
+                        params: c.params.clone(),
+                        ast: c.ast.clone(),
+                        body: c.body,
+                        env: c.env.clone(),
                         recursive_name: Some(name.node.clone()),
-                    },
+                    })),
                     other => other,
                 }
             } else {
@@ -471,40 +715,39 @@ pub fn eval_decl(env: &Env, decl: &Decl) -> Result<Option<Value>, LyraError> {
             Ok(None)
         }
 
-        Decl::Type { variants, .. } => {
-            // Register constructor functions
+        Decl::Type { name: type_name, variants, .. } => {
+            // Register constructor functions, under `Ctor` and `Type.Ctor`
             for variant in variants {
+                // Nullary constructors are just a value; the others are functions
                 let ctor_name = variant.name.node.clone();
-                let arity = variant.fields.len();
-                if arity == 0 {
-                    // Nullary constructor — just a value
-                    env.set(
-                        ctor_name.clone(),
-                        Value::Adt {
-                            constructor: ctor_name,
-                            fields: vec![],
-                        },
-                    );
-                } else {
-                    // Constructor with fields — stored as empty ADT,
-                    // apply_function handles filling in fields
-                    env.set(
-                        ctor_name.clone(),
-                        Value::Adt {
-                            constructor: ctor_name.clone(),
-                            fields: vec![],
-                        },
-                    );
-                }
+                let ctor = match variant.fields.len() {
+                    0 => Value::adt(ctor_name.clone(), vec![]),
+                    arity => Value::constructor(&ctor_name, arity),
+                };
+                env.set(format!("{}.{}", type_name.node, ctor_name), ctor.clone());
+                env.set(ctor_name, ctor);
             }
             Ok(None)
         }
 
+        // The host defined it before the program started
+        Decl::Extern { name, type_ann, .. } => {
+            let value = env.get(&name.node);
+            check_extern(&name.node, type_ann.node.arity(), value.as_ref(), name.span)?;
+            Ok(None)
+        }
+
         Decl::Expr(expr) => {
-            let val = eval(env, expr)?;
+            let val = eval(env, ast, *expr)?;
             Ok(Some(val))
         }
 
+        // Properties only run under `lyra test`
+        Decl::Prop { .. } => Ok(None),
+
+        // Macro calls were already expanded
+        Decl::Macro { .. } => Ok(None),
+
         Decl::Import { path, span } => {
             Err(LyraError::RuntimeError {
                 message: format!("import not yet supported: \"{}\"", path),
@@ -520,187 +763,169 @@ pub fn register_hof_builtins(env: &Env) {
     // map: (a -> b) -> [a] -> [b]
     env.set(
         "map".to_string(),
-        Value::Builtin {
-            name: "map".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("map: expected List, got {}", v.type_name())),
-                };
-                let mut results = Vec::new();
-                for item in list {
-                    let result = apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    results.push(result);
-                }
-                Ok(Value::List(results))
-            },
-        },
+        Value::builtin("map", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("map: expected List, got {}", v.type_name())),
+            };
+            let mut results = Vec::new();
+            for item in list {
+                let result = apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                results.push(result);
+            }
+            Ok(Value::List(results))
+        }),
     );
 
     // filter: (a -> Bool) -> [a] -> [a]
     env.set(
         "filter".to_string(),
-        Value::Builtin {
-            name: "filter".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("filter: expected List, got {}", v.type_name())),
-                };
-                let mut results = Vec::new();
-                for item in list {
-                    let keep = apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    if matches!(keep, Value::Bool(true)) {
-                        results.push(item.clone());
-                    }
+        Value::builtin("filter", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("filter: expected List, got {}", v.type_name())),
+            };
+            let mut results = Vec::new();
+            for item in list {
+                let keep = apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                if matches!(keep, Value::Bool(true)) {
+                    results.push(item.clone());
                 }
-                Ok(Value::List(results))
-            },
-        },
+            }
+            Ok(Value::List(results))
+        }),
     );
 
     // fold: b -> (b -> a -> b) -> [a] -> b
     env.set(
         "fold".to_string(),
-        Value::Builtin {
-            name: "fold".to_string(),
-            arity: 3,
-            func: |args| {
-                let mut acc = args[0].clone();
-                let func = &args[1];
-                let list = match &args[2] {
-                    Value::List(l) => l,
-                    v => return Err(format!("fold: expected List, got {}", v.type_name())),
-                };
-                for item in list {
-                    acc = apply_function(
-                        func.clone(),
-                        vec![acc, item.clone()],
-                        Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                }
-                Ok(acc)
-            },
-        },
+        Value::builtin("fold", 3, |args| {
+            let mut acc = args[0].clone();
+            let func = &args[1];
+            let list = match &args[2] {
+                Value::List(l) => l,
+                v => return Err(format!("fold: expected List, got {}", v.type_name())),
+            };
+            for item in list {
+                acc = apply_function(
+                    func.clone(),
+                    vec![acc, item.clone()],
+                    Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+            }
+            Ok(acc)
+        }),
     );
 
     // zip: [a] -> [b] -> [(a, b)]
     env.set(
         "zip".to_string(),
-        Value::Builtin {
-            name: "zip".to_string(),
-            arity: 2,
-            func: |args| {
-                let a = match &args[0] {
-                    Value::List(l) => l,
-                    v => return Err(format!("zip: expected List, got {}", v.type_name())),
-                };
-                let b = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("zip: expected List, got {}", v.type_name())),
-                };
-                let pairs: Vec<Value> = a
-                    .iter()
-                    .zip(b.iter())
-                    .map(|(x, y)| Value::Tuple(vec![x.clone(), y.clone()]))
-                    .collect();
-                Ok(Value::List(pairs))
-            },
-        },
+        Value::builtin("zip", 2, |args| {
+            let a = match &args[0] {
+                Value::List(l) => l,
+                v => return Err(format!("zip: expected List, got {}", v.type_name())),
+            };
+            let b = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("zip: expected List, got {}", v.type_name())),
+            };
+            let pairs: Vec<Value> = a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| Value::Tuple(vec![x.clone(), y.clone()]))
+                .collect();
+            Ok(Value::List(pairs))
+        }),
     );
 
     // any: (a -> Bool) -> [a] -> Bool
     env.set(
         "any".to_string(),
-        Value::Builtin {
-            name: "any".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("any: expected List, got {}", v.type_name())),
-                };
-                for item in list {
-                    let result = apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    if matches!(result, Value::Bool(true)) {
-                        return Ok(Value::Bool(true));
-                    }
+        Value::builtin("any", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("any: expected List, got {}", v.type_name())),
+            };
+            for item in list {
+                let result = apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                if matches!(result, Value::Bool(true)) {
+                    return Ok(Value::Bool(true));
                 }
-                Ok(Value::Bool(false))
-            },
-        },
+            }
+            Ok(Value::Bool(false))
+        }),
     );
 
     // all: (a -> Bool) -> [a] -> Bool
     env.set(
         "all".to_string(),
-        Value::Builtin {
-            name: "all".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("all: expected List, got {}", v.type_name())),
-                };
-                for item in list {
-                    let result = apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    if matches!(result, Value::Bool(false)) {
-                        return Ok(Value::Bool(false));
-                    }
+        Value::builtin("all", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("all: expected List, got {}", v.type_name())),
+            };
+            for item in list {
+                let result = apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                if matches!(result, Value::Bool(false)) {
+                    return Ok(Value::Bool(false));
                 }
-                Ok(Value::Bool(true))
-            },
-        },
+            }
+            Ok(Value::Bool(true))
+        }),
+    );
+
+    // tap: (a -> b) -> a -> a
+    env.set(
+        "tap".to_string(),
+        Value::builtin("tap", 2, |args| {
+            apply_function(args[0].clone(), vec![args[1].clone()], Span::default())
+                .map_err(|e| format!("{}", e))?;
+            Ok(args[1].clone())
+        }),
     );
 
     // sort: [Int] -> [Int]
     env.set(
         "sort".to_string(),
-        Value::Builtin {
-            name: "sort".to_string(),
-            arity: 1,
-            func: |args| {
-                let list = match &args[0] {
-                    Value::List(l) => l.clone(),
-                    v => return Err(format!("sort: expected List, got {}", v.type_name())),
-                };
-                let mut ints: Vec<i64> = list
-                    .iter()
-                    .map(|v| match v {
-                        Value::Int(n) => Ok(*n),
-                        v => Err(format!("sort: expected Int elements, got {}", v.type_name())),
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
-                ints.sort();
-                Ok(Value::List(ints.into_iter().map(Value::Int).collect()))
-            },
-        },
+        Value::builtin("sort", 1, |args| {
+            let list = match &args[0] {
+                Value::List(l) => l.clone(),
+                v => return Err(format!("sort: expected List, got {}", v.type_name())),
+            };
+            let mut ints: Vec<i64> = list
+                .iter()
+                .map(|v| match v {
+                    Value::Int(n) => Ok(*n),
+                    v => Err(format!("sort: expected Int elements, got {}", v.type_name())),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            ints.sort();
+            Ok(Value::List(ints.into_iter().map(Value::Int).collect()))
+        }),
     );
 }
@@ -1,4 +1,4 @@
-use crate::ast::{Pattern, SpannedPattern};
+use crate::ast::{unqualified, Pattern, SpannedPattern};
 
 use super::value::Value;
 
@@ -19,6 +19,14 @@ pub fn match_pattern(pattern: &SpannedPattern, value: &Value) -> Option<Vec<(Str
         (Pattern::BoolLit(a), Value::Bool(b)) if *a == *b => Some(vec![]),
         (Pattern::UnitLit, Value::Unit) => Some(vec![]),
 
+        // String prefix and suffix patterns: the rest of the string
+        (Pattern::StringPrefix(prefix, rest), Value::String(s)) => {
+            match_pattern(rest, &Value::String(s.strip_prefix(prefix.as_str())?.to_string()))
+        }
+        (Pattern::StringSuffix(rest, suffix), Value::String(s)) => {
+            match_pattern(rest, &Value::String(s.strip_suffix(suffix.as_str())?.to_string()))
+        }
+
         // Tuple pattern
         (Pattern::Tuple(pats), Value::Tuple(vals)) if pats.len() == vals.len() => {
             let mut bindings = Vec::new();
@@ -61,13 +69,10 @@ pub fn match_pattern(pattern: &SpannedPattern, value: &Value) -> Option<Vec<(Str
         // Constructor pattern
         (
             Pattern::Constructor { name: pname, args },
-            Value::Adt {
-                constructor,
-                fields,
-            },
-        ) if pname == constructor && args.len() == fields.len() => {
+            Value::Adt(adt),
+        ) if unqualified(pname) == adt.constructor && args.len() == adt.fields.len() => {
             let mut bindings = Vec::new();
-            for (pat, val) in args.iter().zip(fields.iter()) {
+            for (pat, val) in args.iter().zip(adt.fields.iter()) {
                 match match_pattern(pat, val) {
                     Some(b) => bindings.extend(b),
                     None => return None,
@@ -0,0 +1,214 @@
+//! Quoted code as values.
+//!
+//! `quote e` turns the syntax of `e` into a tree of ADT values named after
+//! the [`Expr`] variants, so `quote(1 + x)` is `BinOp("+", IntLit(1), Var("x"))`
+//! and `adt_constructor`, `adt_fields` or `match` can take it apart. Operators
+//! and names are strings, and sequences are lists:
+//!
+//! - `IntLit(n)`, `FloatLit(f)`, `StringLit(s)`, `BoolLit(b)`, `UnitLit`, `Var(name)`
+//! - `ListLit([e])`, `TupleLit([e])`, `Record([(name, e)])`, `FieldAccess(e, name)`
+//! - `Slice(e, start, end)` with `UnitLit` for a bound left out
+//! - `Lambda([param], body)`, `App(f, [arg])`, `LabeledApp(f, [(label, arg)])`
+//!   with `""` labelling a positional argument
+//! - `BinOp(op, lhs, rhs)`, `UnaryOp(op, e)`, `Pipe(lhs, rhs)`, `If(cond, then, else)`
+//! - `Let(name, recursive, value, body)`, `Match(e, [(pattern, body)])` with
+//!   the pattern as source text
+//! - `Interpolation([part])`, each part a `StringLit`, an expression, or
+//!   `Formatted(e, spec)`
+//! - `Import(path)`, `Quote(e)`
+//!
+//! An `unquote(e)` inside splices the value of `e` in place of code: quoted
+//! code (or any ADT) as is, and plain data as the literal that builds it.
+
+use crate::ast::visit::{walk_expr, Visitor};
+use crate::ast::*;
+use crate::error::LyraError;
+use crate::span::Span;
+
+use super::value::Value;
+
+/// The expressions a `quote` splices in, in the order [`quote`] asks for them.
+pub fn unquotes(ast: &Ast, expr: ExprId) -> Vec<ExprId> {
+    struct Collect(Vec<ExprId>);
+    impl Visitor for Collect {
+        fn visit_expr(&mut self, ast: &Ast, expr: ExprId) {
+            match &ast[expr].node {
+                Expr::Unquote(inner) => self.0.push(*inner),
+                _ => walk_expr(self, ast, expr),
+            }
+        }
+    }
+
+    let mut collect = Collect(Vec::new());
+    collect.visit_expr(ast, expr);
+    collect.0
+}
+
+/// Build the value for quoted `expr`, calling `splice` for the value of each
+/// `unquote` in turn.
+pub fn quote(
+    ast: &Ast,
+    expr: ExprId,
+    splice: &mut dyn FnMut(ExprId) -> Result<Value, LyraError>,
+) -> Result<Value, LyraError> {
+    let text = |s: &str| Value::String(s.to_string());
+    let pair = |a: Value, b: Value| Value::Tuple(vec![a, b]);
+
+    Ok(match &ast[expr].node {
+        Expr::IntLit(n) => node("IntLit", vec![Value::Int(*n)]),
+        Expr::FloatLit(f) => node("FloatLit", vec![Value::Float(*f)]),
+        Expr::StringLit(s) => node("StringLit", vec![text(s)]),
+        Expr::BoolLit(b) => node("BoolLit", vec![Value::Bool(*b)]),
+        Expr::UnitLit => node("UnitLit", vec![]),
+        Expr::Var(name) | Expr::Local { name, .. } => node("Var", vec![text(name)]),
+        Expr::ListLit(elems) => node("ListLit", vec![quote_all(ast, elems, splice)?]),
+        Expr::TupleLit(elems) => node("TupleLit", vec![quote_all(ast, elems, splice)?]),
+        Expr::Lambda { params, body } => {
+            let names = params.iter().map(|p| text(&p.name.node)).collect();
+            node("Lambda", vec![Value::List(names), quote(ast, *body, splice)?])
+        }
+        Expr::App { func, args } => {
+            node("App", vec![quote(ast, *func, splice)?, quote_all(ast, args, splice)?])
+        }
+        Expr::LabeledApp { func, args } => {
+            let func = quote(ast, *func, splice)?;
+            let mut quoted = Vec::new();
+            for arg in args {
+                let label = arg.label.as_ref().map_or("", |l| l.node.as_str());
+                quoted.push(pair(text(label), quote(ast, arg.value, splice)?));
+            }
+            node("LabeledApp", vec![func, Value::List(quoted)])
+        }
+        Expr::BinOp { op, lhs, rhs } => node(
+            "BinOp",
+            vec![text(op.as_str()), quote(ast, *lhs, splice)?, quote(ast, *rhs, splice)?],
+        ),
+        Expr::UnaryOp { op, operand } => {
+            let op = match op {
+                UnaryOp::Neg => "-",
+                UnaryOp::Not => "!",
+            };
+            node("UnaryOp", vec![text(op), quote(ast, *operand, splice)?])
+        }
+        Expr::Pipe { lhs, rhs } => node("Pipe", vec![quote(ast, *lhs, splice)?, quote(ast, *rhs, splice)?]),
+        Expr::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => node(
+            "If",
+            vec![
+                quote(ast, *cond, splice)?,
+                quote(ast, *then_branch, splice)?,
+                quote(ast, *else_branch, splice)?,
+            ],
+        ),
+        Expr::Let {
+            name,
+            recursive,
+            value,
+            body,
+            ..
+        } => node(
+            "Let",
+            vec![
+                text(&name.node),
+                Value::Bool(*recursive),
+                quote(ast, *value, splice)?,
+                quote(ast, *body, splice)?,
+            ],
+        ),
+        Expr::Match { scrutinee, arms } => {
+            let scrutinee = quote(ast, *scrutinee, splice)?;
+            let mut quoted = Vec::new();
+            for arm in arms {
+                quoted.push(pair(text(&arm.pattern.node.to_string()), quote(ast, arm.body, splice)?));
+            }
+            node("Match", vec![scrutinee, Value::List(quoted)])
+        }
+        Expr::Interpolation(parts) => {
+            let mut quoted = Vec::new();
+            for part in parts {
+                quoted.push(match part {
+                    InterpolationPart::Literal(s) => node("StringLit", vec![text(s)]),
+                    InterpolationPart::Expr(e, None) => quote(ast, *e, splice)?,
+                    InterpolationPart::Expr(e, Some(spec)) => {
+                        node("Formatted", vec![quote(ast, *e, splice)?, text(&spec.to_string())])
+                    }
+                });
+            }
+            node("Interpolation", vec![Value::List(quoted)])
+        }
+        Expr::Record(fields) => {
+            let mut quoted = Vec::new();
+            for (name, e) in fields {
+                quoted.push(pair(text(name), quote(ast, *e, splice)?));
+            }
+            node("Record", vec![Value::List(quoted)])
+        }
+        Expr::FieldAccess { expr, field } => {
+            node("FieldAccess", vec![quote(ast, *expr, splice)?, text(field)])
+        }
+        Expr::Slice { expr, start, end } => {
+            let mut fields = vec![quote(ast, *expr, splice)?];
+            for bound in [start, end] {
+                fields.push(match bound {
+                    Some(e) => quote(ast, *e, splice)?,
+                    None => node("UnitLit", vec![]),
+                });
+            }
+            node("Slice", fields)
+        }
+        Expr::Import(path) => node("Import", vec![text(path)]),
+        Expr::Quote(inner) => node("Quote", vec![quote(ast, *inner, splice)?]),
+        Expr::Unquote(inner) => lift(splice(*inner)?, ast[*inner].span)?,
+    })
+}
+
+/// The code for a spliced value: ADTs are taken to be code already, and
+/// plain data becomes the literal that would build it.
+pub fn lift(value: Value, span: Span) -> Result<Value, LyraError> {
+    let lift_all = |items: Vec<Value>| {
+        items
+            .into_iter()
+            .map(|v| lift(v, span))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::List)
+    };
+    Ok(match value {
+        Value::Int(n) => node("IntLit", vec![Value::Int(n)]),
+        Value::Float(f) => node("FloatLit", vec![Value::Float(f)]),
+        Value::String(s) => node("StringLit", vec![Value::String(s)]),
+        Value::Bool(b) => node("BoolLit", vec![Value::Bool(b)]),
+        Value::Unit => node("UnitLit", vec![]),
+        Value::List(items) => node("ListLit", vec![lift_all(items)?]),
+        Value::Tuple(items) => node("TupleLit", vec![lift_all(items)?]),
+        Value::Record(fields) => {
+            let mut quoted = Vec::new();
+            for (name, v) in fields {
+                quoted.push(Value::Tuple(vec![Value::String(name), lift(v, span)?]));
+            }
+            node("Record", vec![Value::List(quoted)])
+        }
+        adt @ Value::Adt(_) => adt,
+        other => {
+            return Err(LyraError::RuntimeError {
+                message: format!("cannot splice {} into quoted code", other.type_name()),
+                span,
+            })
+        }
+    })
+}
+
+fn quote_all(
+    ast: &Ast,
+    exprs: &[ExprId],
+    splice: &mut dyn FnMut(ExprId) -> Result<Value, LyraError>,
+) -> Result<Value, LyraError> {
+    let quoted = exprs.iter().map(|&e| quote(ast, e, splice)).collect::<Result<_, _>>()?;
+    Ok(Value::List(quoted))
+}
+
+fn node(constructor: &str, fields: Vec<Value>) -> Value {
+    Value::adt(constructor, fields)
+}
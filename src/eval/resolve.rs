@@ -0,0 +1,191 @@
+//! Variable resolution for the tree-walking interpreter.
+//!
+//! Before a declaration runs, every variable bound by a `let`, a match arm
+//! or a function's parameters is rewritten to an [`Expr::Local`] giving the
+//! scope it lives in, counted outward from the use, and its slot there. The
+//! scopes mirror those `eval` makes: a `let` binds one slot, a match arm the
+//! pattern's variables in the order `match_pattern` returns them, and a call
+//! the parameters followed by the function itself when it's a `let rec`.
+//! Looking a variable up is then a walk out a known number of scopes and an
+//! index, not a search by name through every scope. Globals keep their
+//! `Expr::Var` and are found by name.
+
+use crate::ast::visit::{walk_expr_mut, VisitorMut};
+use crate::ast::*;
+
+/// Resolve the variables of every declaration of a program.
+pub fn resolve_program(ast: &mut Ast, decls: &[Decl]) {
+    for decl in decls {
+        resolve_decl(ast, decl);
+    }
+}
+
+/// Resolve the variables of a top-level declaration.
+pub fn resolve_decl(ast: &mut Ast, decl: &Decl) {
+    let mut resolver = Resolver::default();
+    match decl {
+        Decl::Let {
+            name,
+            recursive: true,
+            body,
+            ..
+        } => resolver.resolve_lambda(ast, *body, Some(&name.node)),
+        Decl::Let { body, .. } | Decl::Expr(body) => resolver.visit_expr_mut(ast, *body),
+        // A property runs in a scope holding its parameters
+        Decl::Prop { params, body, .. } => {
+            let names = params.iter().map(|p| p.name.node.clone()).collect();
+            resolver.scoped(Scope::Frame(names), |r| r.visit_expr_mut(ast, *body));
+        }
+        Decl::Type { .. } | Decl::Import { .. } | Decl::Macro { .. } | Decl::Extern { .. } => {}
+    }
+}
+
+enum Scope {
+    /// A scope `eval` makes, with the names of its slots.
+    Frame(Vec<String>),
+    /// A name bound only at run time, which has to be looked up by name.
+    Dynamic(String),
+}
+
+#[derive(Default)]
+struct Resolver {
+    scopes: Vec<Scope>,
+}
+
+impl Resolver {
+    /// Where `name` is bound, or `None` if it's global or dynamic.
+    fn lookup(&self, name: &str) -> Option<(usize, usize)> {
+        let mut depth = 0;
+        for scope in self.scopes.iter().rev() {
+            match scope {
+                // A later binding in a scope shadows an earlier one
+                Scope::Frame(names) => {
+                    if let Some(slot) = names.iter().rposition(|n| n == name) {
+                        return Some((depth, slot));
+                    }
+                    depth += 1;
+                }
+                Scope::Dynamic(n) if n == name => return None,
+                Scope::Dynamic(_) => {}
+            }
+        }
+        None
+    }
+
+    fn scoped(&mut self, scope: Scope, f: impl FnOnce(&mut Self)) {
+        self.scopes.push(scope);
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Resolve the value of a `let rec`. A function literal's calls bind it
+    /// after its parameters, unless one of them has its name. Any other
+    /// value gets its name only if it evaluates to a function, so uses of
+    /// the name inside it are left to be looked up.
+    fn resolve_lambda(&mut self, ast: &mut Ast, value: ExprId, name: Option<&str>) {
+        if let Expr::Lambda { .. } = ast[value].node {
+            self.resolve_function(ast, value, name);
+        } else if let Some(name) = name {
+            self.scoped(Scope::Dynamic(name.to_string()), |r| r.visit_expr_mut(ast, value));
+        } else {
+            self.visit_expr_mut(ast, value);
+        }
+    }
+
+    /// Resolve the body of the function literal at `lambda`.
+    fn resolve_function(&mut self, ast: &mut Ast, lambda: ExprId, name: Option<&str>) {
+        let Expr::Lambda { params, body } = &ast[lambda].node else {
+            return;
+        };
+        let body = *body;
+        let mut names: Vec<String> = params.iter().map(|p| p.name.node.clone()).collect();
+        if let Some(name) = name.filter(|n| !names.iter().any(|p| p == n)) {
+            names.push(name.to_string());
+        }
+        self.scoped(Scope::Frame(names), |r| r.visit_expr_mut(ast, body));
+    }
+}
+
+impl VisitorMut for Resolver {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: ExprId) {
+        match &mut ast[expr].node {
+            Expr::Var(name) => {
+                if let Some((depth, slot)) = self.lookup(name) {
+                    let name = std::mem::take(name);
+                    ast[expr].node = Expr::Local { name, depth, slot };
+                }
+            }
+            Expr::Lambda { .. } => self.resolve_function(ast, expr, None),
+            Expr::Let {
+                name,
+                recursive,
+                value,
+                body,
+                ..
+            } => {
+                let (name, value, body) = (name.node.clone(), *value, *body);
+                if *recursive {
+                    self.resolve_lambda(ast, value, Some(&name));
+                } else {
+                    self.visit_expr_mut(ast, value);
+                }
+                self.scoped(Scope::Frame(vec![name]), |r| r.visit_expr_mut(ast, body));
+            }
+            Expr::Match { scrutinee, arms } => {
+                let scrutinee = *scrutinee;
+                let arms: Vec<_> = arms
+                    .iter()
+                    .map(|arm| {
+                        let mut names = Vec::new();
+                        pattern_vars(&arm.pattern, &mut names);
+                        (names, arm.body)
+                    })
+                    .collect();
+                self.visit_expr_mut(ast, scrutinee);
+                for (names, body) in arms {
+                    self.scoped(Scope::Frame(names), |r| r.visit_expr_mut(ast, body));
+                }
+            }
+            // Quoted code stays as written; only what it splices in runs here
+            Expr::Quote(inner) => {
+                struct Unquotes<'a>(&'a mut Resolver);
+                impl VisitorMut for Unquotes<'_> {
+                    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: ExprId) {
+                        match ast[expr].node {
+                            Expr::Unquote(inner) => self.0.visit_expr_mut(ast, inner),
+                            _ => walk_expr_mut(self, ast, expr),
+                        }
+                    }
+                }
+                let inner = *inner;
+                Unquotes(self).visit_expr_mut(ast, inner);
+            }
+            _ => walk_expr_mut(self, ast, expr),
+        }
+    }
+}
+
+/// The variables `pattern` binds, in the order `match_pattern` binds them.
+fn pattern_vars(pattern: &SpannedPattern, names: &mut Vec<String>) {
+    match &pattern.node {
+        Pattern::Var(name) => names.push(name.clone()),
+        Pattern::Tuple(pats) | Pattern::List(pats) | Pattern::Constructor { args: pats, .. } => {
+            for p in pats {
+                pattern_vars(p, names);
+            }
+        }
+        Pattern::Cons(head, tail) => {
+            pattern_vars(head, names);
+            pattern_vars(tail, names);
+        }
+        Pattern::StringPrefix(_, rest) | Pattern::StringSuffix(rest, _) => {
+            pattern_vars(rest, names)
+        }
+        Pattern::Wildcard
+        | Pattern::IntLit(_)
+        | Pattern::FloatLit(_)
+        | Pattern::StringLit(_)
+        | Pattern::BoolLit(_)
+        | Pattern::UnitLit => {}
+    }
+}
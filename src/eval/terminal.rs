@@ -0,0 +1,212 @@
+//! Terminal control for the `term_*` builtins: ANSI escapes as strings to
+//! print, the window size, and single key presses read in raw mode.
+
+use std::io::BufRead;
+
+/// The SGR code of a color or style name.
+fn sgr(name: &str) -> Option<u8> {
+    let base = |color: &str| {
+        ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"]
+            .iter()
+            .position(|&c| c == color)
+            .map(|i| i as u8)
+    };
+    Some(match name {
+        "bold" => 1,
+        "dim" => 2,
+        "italic" => 3,
+        "underline" => 4,
+        "reverse" => 7,
+        _ => match name.strip_prefix("bright_") {
+            Some(color) => 90 + base(color)?,
+            None => match name.strip_prefix("bg_") {
+                Some(color) => 40 + base(color)?,
+                None => 30 + base(name)?,
+            },
+        },
+    })
+}
+
+/// `text` in the colors and styles `spec` names, separated by spaces or
+/// commas: `"red"`, `"bold bright_green"`, `"white bg_blue"`. Plain when
+/// the `NO_COLOR` environment variable is set.
+pub fn color(spec: &str, text: &str) -> Result<String, String> {
+    let codes = spec
+        .split([' ', ','])
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            sgr(name).map(|code| code.to_string()).ok_or_else(|| {
+                format!(
+                    "unknown color '{}'; use black, red, green, yellow, blue, magenta, cyan or white (with bright_ or bg_), bold, dim, italic, underline or reverse",
+                    name
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if codes.is_empty() || std::env::var_os("NO_COLOR").is_some() {
+        return Ok(text.to_string());
+    }
+    Ok(format!("\x1b[{}m{}\x1b[0m", codes.join(";"), text))
+}
+
+/// Clears the screen and moves the cursor to the top left.
+pub const CLEAR: &str = "\x1b[2J\x1b[H";
+
+/// Moves the cursor to 1-based `row` and `column`.
+pub fn cursor_to(row: i64, column: i64) -> Result<String, String> {
+    if row < 1 || column < 1 {
+        return Err(format!("position ({}, {}) is off the screen; rows and columns start at 1", row, column));
+    }
+    Ok(format!("\x1b[{};{}H", row, column))
+}
+
+/// `(columns, rows)` of the terminal on stdout, or of the `COLUMNS` and
+/// `LINES` environment variables, or 80 by 24.
+pub fn size() -> (i64, i64) {
+    #[cfg(unix)]
+    {
+        // SAFETY: TIOCGWINSZ fills in the `winsize` it's given
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0
+            && ws.ws_col > 0
+        {
+            return (ws.ws_col as i64, ws.ws_row as i64);
+        }
+    }
+    let var = |name: &str, default: i64| {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|&n: &i64| n > 0)
+            .unwrap_or(default)
+    };
+    (var("COLUMNS", 80), var("LINES", 24))
+}
+
+/// The next key pressed, read from stdin in raw mode when it's a terminal:
+/// the character itself, or `"enter"`, `"tab"`, `"backspace"`, `"escape"`,
+/// `"up"`, `"down"`, `"left"`, `"right"`, `"home"`, `"end"`, `"delete"`,
+/// `"page_up"`, `"page_down"` or `"ctrl+<letter>"`. At the end of input
+/// it's `""`.
+pub fn read_key() -> Result<String, String> {
+    let _raw = RawMode::enable();
+    let mut stdin = std::io::stdin().lock();
+    // Whether more than the first byte has arrived, before it's consumed
+    let buffered = stdin.fill_buf().map_err(|e| e.to_string())?.len() > 1;
+    let mut peek = |consume: bool| -> Result<Option<u8>, String> {
+        let next = stdin.fill_buf().map_err(|e| e.to_string())?.first().copied();
+        if consume && next.is_some() {
+            stdin.consume(1);
+        }
+        Ok(next)
+    };
+    let Some(first) = peek(true)? else {
+        return Ok(String::new());
+    };
+    Ok(match first {
+        b'\r' | b'\n' => "enter".to_string(),
+        b'\t' => "tab".to_string(),
+        0x7f | 0x08 => "backspace".to_string(),
+        // A lone Escape isn't followed by the rest of a sequence
+        0x1b if buffered || more_input() => {
+            match peek(false)? {
+                Some(b'[') | Some(b'O') => {
+                    peek(true)?;
+                    let mut sequence = Vec::new();
+                    while let Some(b) = peek(true)? {
+                        sequence.push(b);
+                        if b.is_ascii_alphabetic() || b == b'~' {
+                            break;
+                        }
+                    }
+                    match sequence.as_slice() {
+                        b"A" => "up",
+                        b"B" => "down",
+                        b"C" => "right",
+                        b"D" => "left",
+                        b"H" | b"1~" | b"7~" => "home",
+                        b"F" | b"4~" | b"8~" => "end",
+                        b"3~" => "delete",
+                        b"5~" => "page_up",
+                        b"6~" => "page_down",
+                        _ => "escape",
+                    }
+                    .to_string()
+                }
+                _ => "escape".to_string(),
+            }
+        }
+        0x1b => "escape".to_string(),
+        1..=26 => format!("ctrl+{}", (b'a' + first - 1) as char),
+        _ => {
+            // The rest of a multi-byte UTF-8 character
+            let len = match first {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![first];
+            for _ in 1..len {
+                bytes.extend(peek(true)?);
+            }
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+    })
+}
+
+/// Whether stdin has more to read within a moment, the way a terminal
+/// sends the rest of an escape sequence.
+fn more_input() -> bool {
+    #[cfg(unix)]
+    {
+        let mut fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+        // SAFETY: poll reads and writes the one `pollfd` it's given
+        unsafe { libc::poll(&mut fd, 1, 50) > 0 }
+    }
+    #[cfg(not(unix))]
+    true
+}
+
+/// Raw mode on stdin while alive, if stdin is a terminal.
+struct RawMode {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl RawMode {
+    fn enable() -> RawMode {
+        #[cfg(unix)]
+        {
+            // SAFETY: tcgetattr fills in the `termios` it's given, which
+            // tcsetattr then reads
+            unsafe {
+                let mut saved: libc::termios = std::mem::zeroed();
+                if libc::isatty(libc::STDIN_FILENO) == 1
+                    && libc::tcgetattr(libc::STDIN_FILENO, &mut saved) == 0
+                {
+                    let mut raw = saved;
+                    libc::cfmakeraw(&mut raw);
+                    if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) == 0 {
+                        return RawMode { saved: Some(saved) };
+                    }
+                }
+            }
+            RawMode { saved: None }
+        }
+        #[cfg(not(unix))]
+        RawMode {}
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            // SAFETY: restores the settings tcgetattr read
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+            }
+        }
+    }
+}
@@ -1,69 +1,160 @@
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::rc::Rc;
 
-use crate::ast::SpannedExpr;
+use crate::ast::{Ast, BinOp, ExprId, FormatSpec, UnaryOp};
 use crate::compiler::bytecode::FunctionProto;
 
 use super::env::Env;
 
+/// A runtime value. Every variant fits in three words, so a `Value` is
+/// four: functions and constructed values live behind an `Rc`, which also
+/// makes copying them cheap.
 #[derive(Clone)]
 pub enum Value {
     Int(i64),
     Float(f64),
     Bool(bool),
     String(String),
+    /// Raw bytes, e.g. a binary file, shared between copies.
+    Bytes(Rc<[u8]>),
     Unit,
     List(Vec<Value>),
     Tuple(Vec<Value>),
     Record(BTreeMap<String, Value>),
-    Closure {
-        params: Vec<String>,
-        body: SpannedExpr,
-        env: Env,
-        recursive_name: Option<String>,
-    },
-    Builtin {
-        name: String,
-        arity: usize,
-        func: fn(Vec<Value>) -> Result<Value, String>,
-    },
+    Closure(Rc<Closure>),
+    Builtin(Rc<Builtin>),
     PartialApp {
         func: Box<Value>,
-        applied_args: Vec<Value>,
+        applied_args: Rc<[Value]>,
     },
-    Adt {
-        constructor: String,
-        fields: Vec<Value>,
+    Adt(Rc<Adt>),
+    /// Memoized wrapper produced by `memo`. Clones share the cache.
+    Memo {
+        func: Box<Value>,
+        cache: Rc<RefCell<MemoCache>>,
     },
-    /// Compiled function (bytecode).
-    Function(FunctionProto),
+    /// Compiled function (bytecode), shared with the frames running it.
+    Function(Rc<FunctionProto>),
     /// Compiled closure (bytecode + captured values).
     ClosureVal {
-        proto: FunctionProto,
-        upvalues: Vec<Value>,
+        proto: Rc<FunctionProto>,
+        /// Shared by the closure's copies and the frames running it.
+        upvalues: Rc<[Value]>,
     },
 }
 
+/// A function made by the tree-walking interpreter.
+pub struct Closure {
+    pub params: Vec<String>,
+    /// The program the body is in, shared with every closure made from it.
+    pub ast: Rc<Ast>,
+    pub body: ExprId,
+    pub env: Env,
+    pub recursive_name: Option<String>,
+}
+
+/// A function implemented in Rust.
+pub struct Builtin {
+    pub name: String,
+    pub arity: usize,
+    /// Takes `arity` or more arguments, all passed to `func`.
+    pub variadic: bool,
+    pub func: fn(Vec<Value>) -> Result<Value, String>,
+}
+
+/// A constructed value, `Some(1)`, or a constructor with fields still to
+/// be applied.
+pub struct Adt {
+    pub constructor: String,
+    pub fields: Vec<Value>,
+}
+
 impl Value {
+    /// A builtin taking exactly `arity` arguments.
+    pub fn builtin(name: &str, arity: usize, func: fn(Vec<Value>) -> Result<Value, String>) -> Value {
+        Value::Builtin(Rc::new(Builtin {
+            name: name.to_string(),
+            arity,
+            variadic: false,
+            func,
+        }))
+    }
+
+    /// The function building `constructor` from its `arity` fields: a
+    /// builtin that takes the name first, already applied to it.
+    pub fn constructor(constructor: &str, arity: usize) -> Value {
+        let build = Value::Builtin(Rc::new(Builtin {
+            name: constructor.to_string(),
+            arity: arity + 1,
+            variadic: false,
+            func: |mut args| match args.remove(0) {
+                Value::String(name) => Ok(Value::adt(name, args)),
+                other => Err(format!("not a constructor name: {}", other)),
+            },
+        }));
+        Value::PartialApp {
+            func: Box::new(build),
+            applied_args: vec![Value::String(constructor.to_string())].into(),
+        }
+    }
+
+    /// The value `constructor(fields...)`.
+    pub fn adt(constructor: impl Into<String>, fields: Vec<Value>) -> Value {
+        Value::Adt(Rc::new(Adt {
+            constructor: constructor.into(),
+            fields,
+        }))
+    }
+
     pub fn type_name(&self) -> &str {
         match self {
             Value::Int(_) => "Int",
             Value::Float(_) => "Float",
             Value::Bool(_) => "Bool",
             Value::String(_) => "String",
+            Value::Bytes(_) => "Bytes",
             Value::Unit => "()",
             Value::List(_) => "List",
             Value::Tuple(_) => "Tuple",
             Value::Record(_) => "Record",
-            Value::Closure { .. } => "Function",
-            Value::Builtin { .. } => "Function",
+            Value::Closure(_) => "Function",
+            Value::Builtin(_) => "Function",
             Value::PartialApp { .. } => "Function",
             Value::Function { .. } => "Function",
             Value::ClosureVal { .. } => "Function",
-            Value::Adt { constructor, .. } => constructor.as_str(),
+            Value::Memo { .. } => "Function",
+            Value::Adt(adt) => adt.constructor.as_str(),
+        }
+    }
+
+    /// The runtime shape behind `type_of`: `type_name`, except that a
+    /// non-empty list names its element, as in `[String]`. It only sees the
+    /// value, so it can be less precise than the static type: an ADT gives
+    /// its constructor (`Circle`, not `Shape`), every function is `Function`,
+    /// and an empty list is just `List`.
+    pub fn type_of(&self) -> String {
+        match self {
+            Value::List(items) => match items.first() {
+                Some(first) => format!("[{}]", first.type_of()),
+                None => "List".to_string(),
+            },
+            other => other.type_name().to_string(),
         }
     }
 
+    /// Display a value for `{value:spec}` in an interpolated string.
+    pub fn display_formatted(&self, spec: &FormatSpec) -> String {
+        let text = match (self, spec.precision) {
+            (Value::Float(f), Some(p)) => format!("{:.*}", p, f),
+            (Value::String(s), Some(p)) => s.chars().take(p).collect(),
+            _ => self.display_unquoted(),
+        };
+        spec.pad(&text, matches!(self, Value::Int(_) | Value::Float(_)))
+    }
+
     /// Display a value for string interpolation (strings without quotes).
     pub fn display_unquoted(&self) -> String {
         match self {
@@ -72,16 +163,297 @@ impl Value {
         }
     }
 
+    /// The value's type and a display cut short at 40 characters, for error
+    /// messages: `Int 42`, `String "hi"`. Constructors name themselves.
+    pub fn describe(&self) -> String {
+        const MAX: usize = 40;
+        let mut shown = self.to_string();
+        if shown.chars().count() > MAX {
+            shown = shown.chars().take(MAX - 3).collect::<String>() + "...";
+        }
+        match self {
+            Value::Adt(_) => shown,
+            _ => format!("{} {}", self.type_name(), shown),
+        }
+    }
+
+    /// Whether `apply_function` can apply arguments to this value.
+    pub fn is_callable(&self) -> bool {
+        matches!(
+            self,
+            Value::Closure(_)
+                | Value::Builtin(_)
+                | Value::PartialApp { .. }
+                | Value::Memo { .. }
+                | Value::Function(_)
+                | Value::ClosureVal { .. }
+        )
+    }
+
+    /// Name used when reporting a failed call to this value.
+    pub fn callee_name(&self) -> String {
+        match self {
+            Value::Closure(c) => c
+                .recursive_name
+                .clone()
+                .unwrap_or_else(|| "<lambda>".to_string()),
+            Value::Builtin(b) => b.name.clone(),
+            Value::Function(proto) | Value::ClosureVal { proto, .. } => proto.name.clone(),
+            Value::PartialApp { func, .. } | Value::Memo { func, .. } => func.callee_name(),
+            Value::Adt(adt) => adt.constructor.clone(),
+            other => other.type_name().to_string(),
+        }
+    }
+
     pub fn total_arity(&self) -> usize {
         match self {
-            Value::Closure { params, .. } => params.len(),
-            Value::Builtin { arity, .. } => *arity,
+            Value::Closure(c) => c.params.len(),
+            Value::Builtin(b) => b.arity,
             Value::PartialApp { func, applied_args } => {
                 func.total_arity() - applied_args.len()
             }
+            Value::Function(proto) | Value::ClosureVal { proto, .. } => proto.arity as usize,
+            Value::Memo { func, .. } => func.total_arity(),
             _ => 0,
         }
     }
+
+    /// The structural order of two values of one type, behind `<`, `>`,
+    /// `<=` and `>=`: numbers by value, Strings by code point, `false`
+    /// before `true`, lists and tuples element by element and then by
+    /// length, constructed values by constructor name and then field by
+    /// field, and records field by field in name order. `Ok(None)` means
+    /// unordered, as a NaN makes any comparison it decides false. Functions
+    /// have no order.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, String> {
+        fn sequence<'a>(
+            mut a: impl Iterator<Item = &'a Value>,
+            mut b: impl Iterator<Item = &'a Value>,
+        ) -> Result<Option<Ordering>, String> {
+            loop {
+                match (a.next(), b.next()) {
+                    (Some(x), Some(y)) => match x.compare(y)? {
+                        Some(Ordering::Equal) => {}
+                        decided => return Ok(decided),
+                    },
+                    // The shorter sequence comes first
+                    (x, y) => return Ok(Some(x.is_some().cmp(&y.is_some()))),
+                }
+            }
+        }
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Ok(Some(a.cmp(b))),
+            (Value::Float(a), Value::Float(b)) => Ok(a.partial_cmp(b)),
+            (Value::Int(a), Value::Float(b)) => Ok((*a as f64).partial_cmp(b)),
+            (Value::Float(a), Value::Int(b)) => Ok(a.partial_cmp(&(*b as f64))),
+            (Value::String(a), Value::String(b)) => Ok(Some(a.cmp(b))),
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(Some(a.cmp(b))),
+            (Value::Bool(a), Value::Bool(b)) => Ok(Some(a.cmp(b))),
+            (Value::Unit, Value::Unit) => Ok(Some(Ordering::Equal)),
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+                sequence(a.iter(), b.iter())
+            }
+            (Value::Adt(a), Value::Adt(b)) => match a.constructor.cmp(&b.constructor) {
+                Ordering::Equal => sequence(a.fields.iter(), b.fields.iter()),
+                decided => Ok(Some(decided)),
+            },
+            (Value::Record(a), Value::Record(b)) if a.keys().eq(b.keys()) => {
+                sequence(a.values(), b.values())
+            }
+            (a, b) if a.is_callable() || b.is_callable() => {
+                Err(format!("cannot order functions: {} and {}", a.describe(), b.describe()))
+            }
+            (a, b) => Err(format!("cannot order {} and {}", a.describe(), b.describe())),
+        }
+    }
+
+    /// Equality behind `==` and `!=`: structural, with Floats equal as IEEE
+    /// 754 numbers are, so NaN equals nothing, itself included, and `0.0`
+    /// equals `-0.0`; an Int equals the Float of its value. Whether two
+    /// functions are equal can't be decided, so reaching one is an error
+    /// rather than an arbitrary answer.
+    pub fn equals(&self, other: &Value) -> Result<bool, String> {
+        fn all_equal<'a>(
+            a: impl Iterator<Item = &'a Value>,
+            mut b: impl Iterator<Item = &'a Value>,
+        ) -> Result<bool, String> {
+            for x in a {
+                match b.next() {
+                    Some(y) if x.equals(y)? => {}
+                    _ => return Ok(false),
+                }
+            }
+            Ok(true)
+        }
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Ok(a == b),
+            (Value::Float(a), Value::Float(b)) => Ok(a == b),
+            (Value::Int(a), Value::Float(b)) => Ok(*a as f64 == *b),
+            (Value::Float(a), Value::Int(b)) => Ok(*a == *b as f64),
+            (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+            (Value::String(a), Value::String(b)) => Ok(a == b),
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(a == b),
+            (Value::Unit, Value::Unit) => Ok(true),
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+                Ok(a.len() == b.len() && all_equal(a.iter(), b.iter())?)
+            }
+            (Value::Adt(a), Value::Adt(b)) => Ok(a.constructor == b.constructor
+                && a.fields.len() == b.fields.len()
+                && all_equal(a.fields.iter(), b.fields.iter())?),
+            (Value::Record(a), Value::Record(b)) => {
+                Ok(a.keys().eq(b.keys()) && all_equal(a.values(), b.values())?)
+            }
+            (a, b) if a.is_callable() || b.is_callable() => Err(format!(
+                "cannot compare functions for equality: {} and {}",
+                a.describe(),
+                b.describe()
+            )),
+            _ => Ok(false),
+        }
+    }
+
+    /// Feed the value's structure to `state`, agreeing with [`Value::equals`]:
+    /// equal values hash alike, so `0.0` and `-0.0` do too. Functions can't
+    /// be compared, so they can't be hashed either.
+    pub fn hash_into(&self, state: &mut impl std::hash::Hasher) -> Result<(), String> {
+        use std::hash::Hash;
+
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Int(n) => n.hash(state),
+            Value::Float(f) => (if *f == 0.0 { 0.0f64 } else { *f }).to_bits().hash(state),
+            Value::Bool(b) => b.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::Unit => {}
+            Value::List(items) | Value::Tuple(items) => {
+                items.len().hash(state);
+                for item in items {
+                    item.hash_into(state)?;
+                }
+            }
+            Value::Record(fields) => {
+                for (name, v) in fields {
+                    name.hash(state);
+                    v.hash_into(state)?;
+                }
+            }
+            Value::Adt(adt) => {
+                adt.constructor.hash(state);
+                for field in &adt.fields {
+                    field.hash_into(state)?;
+                }
+            }
+            f => return Err(format!("cannot hash functions: {}", f.describe())),
+        }
+        Ok(())
+    }
+}
+
+/// `xs[start:end]` for a list or a string, whose chars it counts. A
+/// negative bound counts from the end, and bounds are clamped to the
+/// length, so a slice never fails; one that ends before it starts is empty.
+pub fn slice(value: &Value, start: Option<i64>, end: Option<i64>) -> Result<Value, String> {
+    let range = |len: usize| {
+        let bound = |i: i64| {
+            let i = if i < 0 { i + len as i64 } else { i };
+            i.clamp(0, len as i64) as usize
+        };
+        let start = start.map_or(0, bound);
+        let end = end.map_or(len, bound);
+        start..end.max(start)
+    };
+    match value {
+        Value::List(items) => Ok(Value::List(items[range(items.len())].to_vec())),
+        Value::String(s) => {
+            let range = range(s.chars().count());
+            let taken = s.chars().skip(range.start).take(range.len());
+            Ok(Value::String(taken.collect()))
+        }
+        other => Err(format!("cannot slice {}", other.describe())),
+    }
+}
+
+/// Promote a mixed Int/Float operand pair to two Floats. Any other pair is
+/// returned unchanged.
+pub fn promote_numeric(lhs: Value, rhs: Value) -> (Value, Value) {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Float(b)) => (Value::Float(a as f64), Value::Float(b)),
+        (Value::Float(a), Value::Int(b)) => (Value::Float(a), Value::Float(b as f64)),
+        pair => pair,
+    }
+}
+
+/// The error for a binary operator its operands don't support, worded the
+/// same by the tree-walker and the VM: `cannot add Int 1 and Bool true`.
+pub fn binop_error(op: BinOp, lhs: &Value, rhs: &Value) -> String {
+    let (lhs, rhs) = (lhs.describe(), rhs.describe());
+    match op {
+        BinOp::Add => format!("cannot add {} and {}", lhs, rhs),
+        BinOp::Sub => format!("cannot subtract {} from {}", rhs, lhs),
+        BinOp::Mul => format!("cannot multiply {} by {}", lhs, rhs),
+        BinOp::Div => format!("cannot divide {} by {}", lhs, rhs),
+        BinOp::Mod => format!("cannot take {} mod {}", lhs, rhs),
+        BinOp::Pow => format!("cannot raise {} to {}", lhs, rhs),
+        BinOp::Cons => format!("cannot prepend {} to {}, which is not a list", lhs, rhs),
+        op => format!("invalid operation {} {} {}", lhs, op.as_str(), rhs),
+    }
+}
+
+/// The error for a unary operator its operand doesn't support.
+pub fn unop_error(op: UnaryOp, operand: &Value) -> String {
+    match op {
+        UnaryOp::Neg => format!("cannot negate {}", operand.describe()),
+        UnaryOp::Not => format!("cannot apply ! to {}", operand.describe()),
+    }
+}
+
+/// Integer exponentiation that rejects negative exponents and overflow
+/// instead of wrapping or truncating.
+pub fn int_pow(base: i64, exp: i64) -> Result<i64, String> {
+    if exp < 0 {
+        return Err(format!("negative exponent {} for Int power", exp));
+    }
+    u32::try_from(exp)
+        .ok()
+        .and_then(|e| base.checked_pow(e))
+        .ok_or_else(|| format!("integer overflow in {} ** {}", base, exp))
+}
+
+/// Result cache for a `memo` wrapper. Entries are bucketed by the printed form
+/// of the arguments and confirmed with structural equality.
+#[derive(Default)]
+pub struct MemoCache {
+    buckets: HashMap<String, Vec<(Vec<Value>, Value)>>,
+}
+
+impl MemoCache {
+    fn key(args: &[Value]) -> String {
+        args.iter()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>()
+            .join("\u{1f}")
+    }
+
+    pub fn get(&self, args: &[Value]) -> Option<Value> {
+        self.buckets
+            .get(&Self::key(args))?
+            .iter()
+            .find(|(k, _)| k.as_slice() == args)
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Every cached call, as its arguments and result.
+    pub fn entries(&self) -> impl Iterator<Item = &(Vec<Value>, Value)> {
+        self.buckets.values().flatten()
+    }
+
+    pub fn insert(&mut self, args: Vec<Value>, result: Value) {
+        self.buckets
+            .entry(Self::key(&args))
+            .or_default()
+            .push((args, result));
+    }
 }
 
 impl fmt::Debug for Value {
@@ -103,6 +475,20 @@ impl fmt::Display for Value {
             }
             Value::Bool(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "\"{}\"", s),
+            // The length, and the first bytes in hex
+            Value::Bytes(bytes) => {
+                write!(f, "<{} bytes", bytes.len())?;
+                if !bytes.is_empty() {
+                    write!(f, ": ")?;
+                    for b in bytes.iter().take(16) {
+                        write!(f, "{:02x}", b)?;
+                    }
+                    if bytes.len() > 16 {
+                        write!(f, "...")?;
+                    }
+                }
+                write!(f, ">")
+            }
             Value::Unit => write!(f, "()"),
             Value::List(items) => {
                 write!(f, "[")?;
@@ -134,19 +520,18 @@ impl fmt::Display for Value {
                 }
                 write!(f, " }}")
             }
-            Value::Closure { .. } => write!(f, "<function>"),
-            Value::Builtin { name, .. } => write!(f, "<builtin:{}>", name),
+            // The VM's functions show as the tree-walker's do
+            Value::Closure(_) | Value::Function(_) | Value::ClosureVal { .. } => {
+                write!(f, "<function>")
+            }
+            Value::Builtin(b) => write!(f, "<builtin:{}>", b.name),
             Value::PartialApp { .. } => write!(f, "<partial>"),
-            Value::Function(proto) => write!(f, "<fn:{}>", proto.name),
-            Value::ClosureVal { proto, .. } => write!(f, "<closure:{}>", proto.name),
-            Value::Adt {
-                constructor,
-                fields,
-            } => {
-                write!(f, "{}", constructor)?;
-                if !fields.is_empty() {
+            Value::Memo { func, .. } => write!(f, "<memo:{}>", func),
+            Value::Adt(adt) => {
+                write!(f, "{}", adt.constructor)?;
+                if !adt.fields.is_empty() {
                     write!(f, "(")?;
-                    for (i, v) in fields.iter().enumerate() {
+                    for (i, v) in adt.fields.iter().enumerate() {
                         if i > 0 {
                             write!(f, ", ")?;
                         }
@@ -167,19 +552,11 @@ impl PartialEq for Value {
             (Value::Float(a), Value::Float(b)) => a == b,
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
+            (Value::Bytes(a), Value::Bytes(b)) => a == b,
             (Value::Unit, Value::Unit) => true,
             (Value::List(a), Value::List(b)) => a == b,
             (Value::Tuple(a), Value::Tuple(b)) => a == b,
-            (
-                Value::Adt {
-                    constructor: c1,
-                    fields: f1,
-                },
-                Value::Adt {
-                    constructor: c2,
-                    fields: f2,
-                },
-            ) => c1 == c2 && f1 == f2,
+            (Value::Adt(a), Value::Adt(b)) => a.constructor == b.constructor && a.fields == b.fields,
             (Value::Record(a), Value::Record(b)) => a == b,
             _ => false,
         }
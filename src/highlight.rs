@@ -0,0 +1,113 @@
+//! Syntax-highlighted source behind `lyra highlight`, as ANSI escapes for a
+//! terminal or HTML for a web page. Tokens are classified by
+//! [`analysis::semantic_tokens`], so a parameter holding a function is
+//! colored as one; operators, punctuation and source that doesn't lex are
+//! left plain.
+
+use crate::analysis::{self, TokenClass};
+use crate::doc::escape_html;
+use crate::eval::env::Env;
+use crate::stdlib;
+use crate::types::env::TypeEnv;
+use crate::types::TypeVarGen;
+
+/// The pieces of `source` in order, each with its class if it has one.
+fn pieces(source: &str) -> Vec<(String, Option<TokenClass>)> {
+    let mut env = TypeEnv::new();
+    stdlib::register_stdlib(&mut env, &Env::new(), &mut TypeVarGen::new());
+    let chars: Vec<char> = source.chars().collect();
+    let mut out = Vec::new();
+    let mut at = 0;
+    for token in analysis::semantic_tokens(source, &env) {
+        if token.span.start > at {
+            out.push((chars[at..token.span.start].iter().collect(), None));
+        }
+        out.push((
+            chars[token.span.start..token.span.end].iter().collect(),
+            Some(token.class),
+        ));
+        at = token.span.end;
+    }
+    if at < chars.len() {
+        out.push((chars[at..].iter().collect(), None));
+    }
+    out
+}
+
+/// `source` with ANSI color escapes, for printing to a terminal.
+pub fn render_ansi(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    for (text, class) in pieces(source) {
+        let color = match class {
+            Some(TokenClass::Keyword) => "\x1b[1;34m",
+            Some(TokenClass::Function) => "\x1b[36m",
+            Some(TokenClass::Constructor) => "\x1b[33m",
+            Some(TokenClass::Type) => "\x1b[35m",
+            Some(TokenClass::String) => "\x1b[32m",
+            Some(TokenClass::Number) => "\x1b[96m",
+            Some(TokenClass::Comment) => "\x1b[90m",
+            Some(TokenClass::Variable) | None => {
+                out.push_str(&text);
+                continue;
+            }
+        };
+        out.push_str(color);
+        out.push_str(&text);
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// `source` as a `<pre class="lyra">` block with a `<span>` per classified
+/// token, for embedding in a page that styles the classes itself.
+pub fn render_html_fragment(source: &str) -> String {
+    let mut out = String::from("<pre class=\"lyra\"><code>");
+    for (text, class) in pieces(source) {
+        let text = escape_html(&text);
+        match class {
+            Some(class) => out.push_str(&format!(
+                "<span class=\"{}\">{}</span>",
+                css_class(class),
+                text
+            )),
+            None => out.push_str(&text),
+        }
+    }
+    out.push_str("</code></pre>");
+    out
+}
+
+/// `source` as a standalone HTML page with a stylesheet for the classes.
+pub fn render_html(source: &str, title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape_html(title),
+        STYLESHEET,
+        render_html_fragment(source)
+    )
+}
+
+/// The `class` of a token's `<span>`.
+pub fn css_class(class: TokenClass) -> &'static str {
+    match class {
+        TokenClass::Keyword => "kw",
+        TokenClass::Function => "fn",
+        TokenClass::Constructor => "ctor",
+        TokenClass::Type => "ty",
+        TokenClass::Variable => "var",
+        TokenClass::String => "str",
+        TokenClass::Number => "num",
+        TokenClass::Comment => "com",
+    }
+}
+
+const STYLESHEET: &str = "\
+pre.lyra { background: #fafafa; padding: 1em; }
+.lyra .kw { color: #0033b3; font-weight: bold; }
+.lyra .fn { color: #00627a; }
+.lyra .ctor { color: #9e6a00; }
+.lyra .ty { color: #871094; }
+.lyra .str { color: #067d17; }
+.lyra .num { color: #1750eb; }
+.lyra .com { color: #8c8c8c; font-style: italic; }
+";
@@ -0,0 +1,133 @@
+//! Instrumentation for embedders: callbacks the host runs as the program
+//! calls functions, returns from them and takes steps, for tracing, audit
+//! logs or budgets of its own without changing the interpreter loop.
+//!
+//! Both backends report the same calls and returns. A call is reported
+//! once it has all its arguments, so partial application isn't one, under
+//! the name its errors use, which may be `<lambda>`. A step is an
+//! expression the tree-walker evaluates or an instruction the VM runs.
+//! Like the budget meter, the hooks are per thread, so a run without them
+//! costs one flag check per step.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+use crate::error::LyraError;
+use crate::eval::value::Value;
+use crate::span::Span;
+
+type OnCall = Rc<dyn Fn(&str, &[Value])>;
+type OnReturn = Rc<dyn Fn(&Value)>;
+type OnOp = Rc<dyn Fn(Span) -> Result<(), String>>;
+
+/// The callbacks for a run, set in `RunOptions::hooks`; any left out
+/// aren't called. They take `&self`, so one that keeps count holds a
+/// `Cell` or `RefCell`.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    on_call: Option<OnCall>,
+    on_return: Option<OnReturn>,
+    on_op: Option<OnOp>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `f` with a function's name and arguments as it's called.
+    pub fn on_call(mut self, f: impl Fn(&str, &[Value]) + 'static) -> Self {
+        self.on_call = Some(Rc::new(f));
+        self
+    }
+
+    /// Call `f` with each call's result as it returns, innermost first.
+    /// Calls that fail don't return.
+    pub fn on_return(mut self, f: impl Fn(&Value) + 'static) -> Self {
+        self.on_return = Some(Rc::new(f));
+        self
+    }
+
+    /// Call `f` with the span of each step before it's taken. An `Err`
+    /// stops the run there with the message as a runtime error.
+    pub fn on_op(mut self, f: impl Fn(Span) -> Result<(), String> + 'static) -> Self {
+        self.on_op = Some(Rc::new(f));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.on_call.is_none() && self.on_return.is_none() && self.on_op.is_none()
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_call", &self.on_call.is_some())
+            .field("on_return", &self.on_return.is_some())
+            .field("on_op", &self.on_op.is_some())
+            .finish()
+    }
+}
+
+thread_local! {
+    static HOOKS: RefCell<Hooks> = RefCell::new(Hooks::default());
+    /// Whether any hook is set.
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f` with `hooks` called. Hooks don't nest: the innermost ones
+/// apply, and the outer ones are restored afterwards.
+pub fn with_hooks<T>(hooks: &Hooks, f: impl FnOnce() -> T) -> T {
+    if hooks.is_empty() && !ACTIVE.with(Cell::get) {
+        return f();
+    }
+    let outer = HOOKS.with(|h| h.replace(hooks.clone()));
+    ACTIVE.with(|a| a.set(!hooks.is_empty()));
+    // Restore the outer hooks even if `f` panics
+    struct Restore(Option<Hooks>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let outer = self.0.take().unwrap_or_default();
+            ACTIVE.with(|a| a.set(!outer.is_empty()));
+            HOOKS.with(|h| *h.borrow_mut() = outer);
+        }
+    }
+    let _restore = Restore(Some(outer));
+    f()
+}
+
+/// Whether a hook is set, so the caller can skip building its arguments.
+pub(crate) fn active() -> bool {
+    ACTIVE.with(Cell::get)
+}
+
+/// Report a call to `name` with `args`.
+pub(crate) fn call(name: &str, args: &[Value]) {
+    if active() {
+        // Taken out of the cell so the hook can start a run of its own
+        if let Some(f) = HOOKS.with(|h| h.borrow().on_call.clone()) {
+            f(name, args);
+        }
+    }
+}
+
+/// Report a call returning `value`.
+pub(crate) fn ret(value: &Value) {
+    if active() {
+        if let Some(f) = HOOKS.with(|h| h.borrow().on_return.clone()) {
+            f(value);
+        }
+    }
+}
+
+/// Report a step at `span`, failing if the host stops the run.
+pub(crate) fn op(span: Span) -> Result<(), LyraError> {
+    if active() {
+        if let Some(f) = HOOKS.with(|h| h.borrow().on_op.clone()) {
+            return f(span).map_err(|message| LyraError::RuntimeError { message, span });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,270 @@
+//! Incremental type checking for long-lived sessions (watch mode, editors).
+//!
+//! Each file's tokens and AST are cached by a hash of its source, and its
+//! checked interface (exported types and bindings) is reused until either the
+//! file itself or the interface of something it imports changes.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+
+use crate::ast::{Decl, Program};
+use crate::error::LyraError;
+use crate::eval::env::Env;
+use crate::types::env::TypeEnv;
+use crate::types::infer::Inferencer;
+use crate::types::{TypeScheme, TypeVarGen};
+use crate::{parse_file, resolve_import, stdlib, Imports, Parsed};
+
+/// A failure while checking one file of the import graph.
+#[derive(Debug, Clone)]
+pub struct CheckError {
+    pub path: String,
+    pub error: LyraError,
+}
+
+/// Which files a `check` call actually re-checked and which it reused.
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub checked: Vec<String>,
+    pub reused: Vec<String>,
+}
+
+/// What a file contributes to the files that import it.
+#[derive(Debug, Clone)]
+struct Interface {
+    type_decls: Vec<Decl>,
+    bindings: Vec<(String, TypeScheme)>,
+    fingerprint: u64,
+}
+
+struct FileEntry {
+    source_hash: u64,
+    program: Program,
+    /// How many declarations at the front of `program` bind its modules.
+    hoisted: usize,
+    /// The files its `import` expressions loaded, with the hash of each as
+    /// parsed into `program`.
+    modules: Vec<(String, u64)>,
+    imports: Vec<String>,
+    /// Interface fingerprints of `imports` when this file was last checked.
+    checked_against: Vec<u64>,
+    /// `None` until the file type-checks.
+    interface: Option<Interface>,
+}
+
+/// Type checker that keeps per-file results between calls.
+#[derive(Default)]
+pub struct Checker {
+    files: HashMap<String, FileEntry>,
+    overlays: HashMap<String, String>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `source` for `path` instead of reading the file (unsaved editor buffers).
+    pub fn set_source(&mut self, path: &str, source: String) {
+        self.overlays.insert(path.to_string(), source);
+    }
+
+    /// Go back to reading `path` from disk.
+    pub fn clear_source(&mut self, path: &str) {
+        self.overlays.remove(path);
+    }
+
+    /// Bindings exported by `path` as of its last successful check.
+    pub fn bindings(&self, path: &str) -> Option<&[(String, TypeScheme)]> {
+        self.files
+            .get(path)?
+            .interface
+            .as_ref()
+            .map(|i| i.bindings.as_slice())
+    }
+
+    /// Check `entry` and everything it imports, re-using whatever is still valid.
+    pub fn check(&mut self, entry: &str) -> Result<CheckReport, CheckError> {
+        let mut report = CheckReport::default();
+        let mut done = HashMap::new();
+        let mut stack = Vec::new();
+        self.visit(entry, &mut stack, &mut done, &mut report)?;
+        Ok(report)
+    }
+
+    /// Bring `path` up to date and return its interface fingerprint.
+    fn visit(
+        &mut self,
+        path: &str,
+        stack: &mut Vec<String>,
+        done: &mut HashMap<String, u64>,
+        report: &mut CheckReport,
+    ) -> Result<u64, CheckError> {
+        if let Some(fp) = done.get(path) {
+            return Ok(*fp);
+        }
+        let fail = |error| CheckError {
+            path: path.to_string(),
+            error,
+        };
+
+        // Tokenize and parse only when the source changed
+        let source = match self.overlays.get(path) {
+            Some(s) => s.clone(),
+            None => fs::read_to_string(path).map_err(|_| {
+                fail(LyraError::FileNotFound {
+                    path: path.to_string(),
+                })
+            })?,
+        };
+        let source_hash = hash_of(&source);
+        let stale = self.files.get(path).is_none_or(|f| {
+            f.source_hash != source_hash
+                || f.modules.iter().any(|(module, hash)| file_hash(module) != Some(*hash))
+        });
+        if stale {
+            let Parsed { program, hoisted, modules } =
+                parse_file(&source, path, Imports::Keep).map_err(fail)?;
+            let modules = modules
+                .into_iter()
+                .filter_map(|module| file_hash(&module).map(|hash| (module, hash)))
+                .collect();
+            let imports = program
+                .decls
+                .iter()
+                .filter_map(|d| match d {
+                    Decl::Import { path: p, .. } => Some(resolve_import(path, p)),
+                    _ => None,
+                })
+                .collect();
+            self.files.insert(
+                path.to_string(),
+                FileEntry {
+                    source_hash,
+                    program,
+                    hoisted,
+                    modules,
+                    imports,
+                    checked_against: Vec::new(),
+                    interface: None,
+                },
+            );
+        }
+
+        // Dependencies first; an import cycle is cut at the back edge
+        stack.push(path.to_string());
+        let imports = self.files[path].imports.clone();
+        let mut dep_fingerprints = Vec::new();
+        for import in &imports {
+            if !stack.contains(import) {
+                dep_fingerprints.push(self.visit(import, stack, done, report)?);
+            }
+        }
+        stack.pop();
+
+        let entry = &self.files[path];
+        let fingerprint = match &entry.interface {
+            Some(interface) if entry.checked_against == dep_fingerprints => {
+                report.reused.push(path.to_string());
+                interface.fingerprint
+            }
+            _ => {
+                let interface = self.check_file(path).map_err(fail)?;
+                let fp = interface.fingerprint;
+                let entry = self.files.get_mut(path).unwrap();
+                entry.interface = Some(interface);
+                entry.checked_against = dep_fingerprints;
+                report.checked.push(path.to_string());
+                fp
+            }
+        };
+        done.insert(path.to_string(), fingerprint);
+        Ok(fingerprint)
+    }
+
+    /// Type-check one file against the interfaces of its (transitive) imports.
+    fn check_file(&self, path: &str) -> Result<Interface, LyraError> {
+        let mut type_env = TypeEnv::new();
+        let runtime_env = Env::new();
+        let mut gen = TypeVarGen::new();
+        let mut inferencer = Inferencer::new();
+        stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+
+        let mut seen = HashSet::new();
+        seen.insert(path.to_string());
+        for import in &self.files[path].imports {
+            self.load_interface(import, &mut seen, &mut type_env, &mut inferencer)?;
+        }
+
+        let mut type_decls = Vec::new();
+        let mut bindings = Vec::new();
+        let FileEntry { program, hoisted, .. } = &self.files[path];
+        for (i, decl) in program.decls.iter().enumerate() {
+            inferencer.infer_decl(&mut type_env, &program.ast, decl)?;
+            match decl {
+                Decl::Type { .. } => type_decls.push(decl.clone()),
+                // The bindings of its modules aren't the file's to export
+                Decl::Let { name, .. } if i >= *hoisted => {
+                    if let Some(scheme) = type_env.lookup(&name.node) {
+                        bindings.push((name.node.clone(), scheme.clone()));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut hasher = DefaultHasher::new();
+        for decl in &type_decls {
+            decl.display(&program.ast).to_string().hash(&mut hasher);
+        }
+        for (name, scheme) in &bindings {
+            (name, scheme.normalized().to_string()).hash(&mut hasher);
+        }
+        Ok(Interface {
+            type_decls,
+            bindings,
+            fingerprint: hasher.finish(),
+        })
+    }
+
+    /// Add an import's interface, and those of its own imports, to the environment.
+    fn load_interface(
+        &self,
+        path: &str,
+        seen: &mut HashSet<String>,
+        type_env: &mut TypeEnv,
+        inferencer: &mut Inferencer,
+    ) -> Result<(), LyraError> {
+        if !seen.insert(path.to_string()) {
+            return Ok(());
+        }
+        let Some(entry) = self.files.get(path) else {
+            return Ok(());
+        };
+        for import in &entry.imports {
+            self.load_interface(import, seen, type_env, inferencer)?;
+        }
+        if let Some(interface) = &entry.interface {
+            for decl in &interface.type_decls {
+                inferencer.register_type_decl(type_env, decl)?;
+            }
+            for (name, scheme) in &interface.bindings {
+                type_env.insert(name.clone(), scheme.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The hash of the file at `path`, if it can be read.
+fn file_hash(path: &str) -> Option<u64> {
+    fs::read_to_string(path).ok().map(|source| hash_of(&source))
+}
+
+fn hash_of(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,1041 @@
+//! JavaScript backend, for `lyra emit-js`. Lowers a checked program to a
+//! standalone script: functions become arrow functions, constructed values
+//! `{ $tag, $fields }` objects, and `match` a `switch` on the tag when every
+//! arm tests a constructor, or a chain of `if`s otherwise. Only the runtime
+//! builtins the program uses are included (see `runtime.js`).
+//!
+//! Ints and Floats are both JavaScript numbers, so the expression types
+//! recorded while checking decide how arithmetic and printing behave: Int
+//! division truncates, and a Float prints with its `.0`. Ints are exact only
+//! up to 2^53, and deep recursion can overflow the JavaScript stack where
+//! the VM would reuse the frame.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::ast::*;
+use crate::error::LyraError;
+use crate::span::{Span, Spanned};
+use crate::types::env::TypeEnv;
+use crate::types::{MonoType, TypeVar};
+
+const RUNTIME: &str = include_str!("runtime.js");
+
+/// Names a binding can't take: JavaScript's reserved words and the globals
+/// the runtime uses.
+const RESERVED: &[&str] = &[
+    "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger",
+    "default", "delete", "do", "else", "enum", "eval", "export", "extends", "false", "finally",
+    "for", "function", "if", "implements", "import", "in", "instanceof", "interface", "let",
+    "new", "null", "package", "private", "protected", "public", "return", "static", "super",
+    "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "void", "while",
+    "with", "yield", "Array", "Error", "Infinity", "JSON", "LyraError", "Map", "Math", "NaN",
+    "Number", "Object", "String", "TextEncoder", "console", "globalThis", "process",
+];
+
+/// Runtime calls an operator can lower to on its own.
+const HELPERS: &[&str] = &["$div(", "$mod(", "$pow(", "$eq("];
+
+/// Lower checked declarations to a JavaScript program. `types` are the
+/// expression types recorded while checking them, and `env` the type
+/// environment after the last one.
+pub fn emit(
+    ast: &Ast,
+    decls: &[Decl],
+    types: &[(Span, MonoType)],
+    env: &TypeEnv,
+    filename: &str,
+) -> Result<String, LyraError> {
+    let mut expr_types = HashMap::new();
+    for (span, ty) in types {
+        expr_types.entry(*span).or_insert(ty);
+    }
+    let runtime = runtime_chunks();
+    let mut emitter = Emitter {
+        ast,
+        types: expr_types,
+        env,
+        adts: HashMap::new(),
+        builtins: runtime[1..]
+            .iter()
+            .map(|chunk| (chunk.name, (chunk.arity, chunk.variadic)))
+            .collect(),
+        used: HashSet::new(),
+        scopes: vec![Vec::new()],
+        temps: 0,
+    };
+
+    // Externs come first, so a missing one stops the program before it starts
+    let mut program = String::new();
+    let (externs, rest): (Vec<&Decl>, Vec<&Decl>) =
+        decls.iter().partition(|d| matches!(d, Decl::Extern { .. }));
+    for decl in externs.into_iter().chain(rest) {
+        emitter.decl(decl, &mut program)?;
+    }
+
+    let mut out = format!("// Compiled from {} by `lyra emit-js`.\n", filename);
+    for chunk in &runtime {
+        if chunk.name == "core" || emitter.used.contains(chunk.name) {
+            out.push_str(chunk.text);
+        }
+    }
+    out.push_str(&program);
+    Ok(out)
+}
+
+/// A section of `runtime.js`, headed `//# name arity`, with a `+` after the
+/// arity for a builtin taking extra arguments.
+struct Chunk {
+    name: &'static str,
+    arity: usize,
+    variadic: bool,
+    text: &'static str,
+}
+
+fn runtime_chunks() -> Vec<Chunk> {
+    RUNTIME
+        .split("//# ")
+        .filter(|section| !section.is_empty())
+        .map(|section| {
+            let (header, text) = section.split_once('\n').unwrap_or((section, ""));
+            let mut header = header.split_whitespace();
+            let name = header.next().unwrap_or_default();
+            let arity = header.next().unwrap_or("0");
+            Chunk {
+                name,
+                arity: arity.trim_end_matches('+').parse().unwrap_or(0),
+                variadic: arity.ends_with('+'),
+                text,
+            }
+        })
+        .collect()
+}
+
+/// A Lyra name in scope and what's known about its value.
+#[derive(Clone)]
+struct Binding {
+    js: String,
+    /// Parameters of a function literal or constructor, for direct calls
+    /// and for ordering labeled arguments.
+    params: Option<Vec<String>>,
+}
+
+struct Emitter<'a> {
+    ast: &'a Ast,
+    types: HashMap<Span, &'a MonoType>,
+    env: &'a TypeEnv,
+    /// Constructors of each declared type, for describing values to print.
+    adts: HashMap<String, Vec<String>>,
+    /// Builtins in the runtime, with their arity and whether they're variadic.
+    builtins: HashMap<&'static str, (usize, bool)>,
+    used: HashSet<&'static str>,
+    scopes: Vec<Vec<(String, Binding)>>,
+    /// Temporaries made so far, numbering the next.
+    temps: usize,
+}
+
+impl Emitter<'_> {
+    fn decl(&mut self, decl: &Decl, out: &mut String) -> Result<(), LyraError> {
+        match decl {
+            Decl::Let {
+                name,
+                recursive,
+                body,
+                ..
+            } => {
+                let value = self.let_value(name, *recursive, *body, 0)?;
+                let js = &self.lookup(&name.node).expect("bound by let_value").js;
+                writeln!(out, "const {} = {};", js, value).unwrap();
+            }
+            Decl::Type {
+                name: type_name,
+                variants,
+                ..
+            } => {
+                let ctors = variants.iter().map(|v| v.name.node.clone()).collect();
+                self.adts.insert(type_name.node.clone(), ctors);
+                for variant in variants {
+                    let ctor = &variant.name.node;
+                    let params: Vec<String> = (0..variant.fields.len())
+                        .map(|i| format!("a{}", i))
+                        .collect();
+                    let js = self.bind(ctor, Some(params.clone()));
+                    let binding = self.lookup(ctor).expect("just bound").clone();
+                    let qualified = format!("{}.{}", type_name.node, ctor);
+                    self.scopes.last_mut().unwrap().push((qualified, binding));
+                    let value = format!(
+                        "{{ $tag: {}, $fields: [{}] }}",
+                        js_string(ctor),
+                        params.join(", ")
+                    );
+                    if params.is_empty() {
+                        writeln!(out, "const {} = {};", js, value).unwrap();
+                    } else {
+                        writeln!(
+                            out,
+                            "const {} = ({}) => ({});",
+                            js,
+                            params.join(", "),
+                            value
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+            Decl::Expr(expr) => {
+                let js = self.expr(*expr, 0)?;
+                // A statement can't start with an object literal
+                if js.starts_with('{') {
+                    writeln!(out, "({});", js).unwrap();
+                } else {
+                    writeln!(out, "{};", js).unwrap();
+                }
+            }
+            Decl::Extern { name, type_ann, .. } => {
+                let js = self.bind(&name.node, None);
+                let arity = type_ann.node.arity();
+                let value = format!("$extern({}, {})", js_string(&name.node), arity);
+                writeln!(out, "const {} = {};", js, value).unwrap();
+            }
+            // Props run under `lyra test`, and imports and macros are gone
+            // by the time a program is checked
+            Decl::Prop { .. } | Decl::Import { .. } | Decl::Macro { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Bind `name` in the current scope and lower the value it's bound to;
+    /// a recursive binding is in scope for its own value.
+    fn let_value(
+        &mut self,
+        name: &Spanned<String>,
+        recursive: bool,
+        value: ExprId,
+        indent: usize,
+    ) -> Result<String, LyraError> {
+        let params = self.ast[value].node.param_names();
+        if recursive {
+            self.bind(&name.node, params);
+            self.expr(value, indent)
+        } else {
+            let js = self.expr(value, indent)?;
+            self.bind(&name.node, params);
+            Ok(js)
+        }
+    }
+
+    // ── Scopes ──
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, _)| n == name)
+            .map(|(_, b)| b)
+    }
+
+    /// Bind `name` to a JavaScript name no binding in scope uses, returning it.
+    fn bind(&mut self, name: &str, params: Option<Vec<String>>) -> String {
+        let base = name.replace('#', "$");
+        let taken = |js: &str| {
+            RESERVED.contains(&js)
+                || self.builtins.contains_key(js)
+                || self.scopes.iter().flatten().any(|(_, b)| b.js == js)
+        };
+        let mut js = base.clone();
+        let mut n = 1;
+        while taken(&js) {
+            js = format!("{}${}", base, n);
+            n += 1;
+        }
+        let binding = Binding {
+            js: js.clone(),
+            params,
+        };
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .push((name.to_string(), binding));
+        js
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("$v{}", self.temps)
+    }
+
+    // ── Expressions ──
+
+    /// Lower an expression; lines after the first are indented to `indent`.
+    fn expr(&mut self, id: ExprId, indent: usize) -> Result<String, LyraError> {
+        let ast = self.ast;
+        let expr = &ast[id];
+        Ok(match &expr.node {
+            Expr::IntLit(n) => n.to_string(),
+            Expr::FloatLit(f) => format!("{:?}", f),
+            Expr::StringLit(s) => js_string(s),
+            Expr::BoolLit(b) => b.to_string(),
+            Expr::UnitLit => "undefined".to_string(),
+            Expr::ListLit(elems) => format!("[{}]", self.exprs(elems, indent)?.join(", ")),
+            Expr::TupleLit(elems) => format!("$tuple({})", self.exprs(elems, indent)?.join(", ")),
+            Expr::Var(name) | Expr::Local { name, .. } => self.var(name, expr.span)?,
+            Expr::Lambda { params, body } => {
+                let names: Vec<_> = params.iter().map(|p| &p.name.node).collect();
+                self.lambda(&names, *body, indent)?
+            }
+            Expr::App { func, args } => self.call(*func, args, indent)?,
+            Expr::LabeledApp { func, args } => {
+                let params = match &ast[*func].node {
+                    Expr::Var(name) => self.lookup(name).and_then(|b| b.params.clone()),
+                    _ => None,
+                };
+                let Some(params) = params else {
+                    return Err(unsupported(
+                        "labeled arguments need a function declared with named parameters",
+                        ast[*func].span,
+                    ));
+                };
+                let name = match &ast[*func].node {
+                    Expr::Var(name) => name.as_str(),
+                    _ => "<lambda>",
+                };
+                let order = order_args(ast, name, &params, args, expr.span)?;
+                let values: Vec<ExprId> = args.iter().map(|a| a.value).collect();
+                if order.iter().enumerate().all(|(slot, &i)| slot == i) {
+                    self.call(*func, &values, indent)?
+                } else {
+                    // Evaluated in source order, passed in parameter order
+                    let temps: Vec<String> = values.iter().map(|_| self.temp()).collect();
+                    let shown = self.exprs(&values, indent)?;
+                    let callee = self.var(name, ast[*func].span)?;
+                    let passed: Vec<&str> = order.iter().map(|&i| temps[i].as_str()).collect();
+                    format!(
+                        "(({}) => {}({}))({})",
+                        temps.join(", "),
+                        callee,
+                        passed.join(", "),
+                        shown.join(", ")
+                    )
+                }
+            }
+            Expr::BinOp { op, lhs, rhs } => self.binop(*op, *lhs, *rhs, indent)?,
+            Expr::UnaryOp { op, operand } => {
+                let operand = self.operand(*operand, UNARY + 1, indent)?;
+                match op {
+                    UnaryOp::Neg => format!("-{}", operand),
+                    UnaryOp::Not => format!("!{}", operand),
+                }
+            }
+            // `x |> f(a)` is `f(a, x)`
+            Expr::Pipe { lhs, rhs } => match &ast[*rhs].node {
+                Expr::App { func, args } => {
+                    let mut args = args.clone();
+                    args.push(*lhs);
+                    self.call(*func, &args, indent)?
+                }
+                _ => self.call(*rhs, &[*lhs], indent)?,
+            },
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } if !is_block(ast, *then_branch) && !is_block(ast, *else_branch) => format!(
+                "{} ? {} : {}",
+                self.operand(*cond, 1, indent)?,
+                self.operand(*then_branch, 1, indent)?,
+                self.operand(*else_branch, 1, indent)?
+            ),
+            Expr::If { .. } | Expr::Let { .. } | Expr::Match { .. } => {
+                // A block in an expression runs as an immediately-called function
+                let mut body = String::new();
+                self.scoped(|this| this.stmts(id, indent + 1, &mut body))?;
+                format!("(() => {{\n{}{}}})()", body, pad(indent))
+            }
+            Expr::Interpolation(parts) => self.interpolation(parts, indent)?,
+            Expr::Record(fields) => {
+                let mut shown = Vec::new();
+                for (name, value) in fields {
+                    shown.push(format!("{}: {}", name, self.expr(*value, indent)?));
+                }
+                if shown.is_empty() {
+                    "{}".to_string()
+                } else {
+                    format!("{{ {} }}", shown.join(", "))
+                }
+            }
+            Expr::FieldAccess { expr, field } => {
+                format!("{}.{}", self.operand(*expr, ATOM, indent)?, field)
+            }
+            Expr::Slice { expr, start, end } => {
+                let mut args = vec![self.expr(*expr, indent)?];
+                for bound in [start, end] {
+                    args.push(match bound {
+                        Some(e) => self.expr(*e, indent)?,
+                        None => "undefined".to_string(),
+                    });
+                }
+                format!("$slice({})", args.join(", "))
+            }
+            Expr::Import(path) => return Err(unresolved_import(path, expr.span)),
+            Expr::Quote(_) => {
+                return Err(unsupported(
+                    "quoted code has no JavaScript version",
+                    expr.span,
+                ))
+            }
+            Expr::Unquote(_) => return Err(LyraError::MisplacedUnquote { span: expr.span }),
+        })
+    }
+
+    fn exprs(&mut self, exprs: &[ExprId], indent: usize) -> Result<Vec<String>, LyraError> {
+        exprs.iter().map(|&e| self.expr(e, indent)).collect()
+    }
+
+    /// An expression used inside another, parenthesized unless it binds
+    /// at least as tightly as `needed` (see `precedence`).
+    fn operand(
+        &mut self,
+        expr: ExprId,
+        needed: u8,
+        indent: usize,
+    ) -> Result<String, LyraError> {
+        let js = self.expr(expr, indent)?;
+        let binds = match &self.ast[expr].node {
+            Expr::IntLit(n) if *n < 0 => UNARY,
+            Expr::FloatLit(f) if *f < 0.0 => UNARY,
+            Expr::UnaryOp { .. } => UNARY,
+            Expr::Lambda { .. } => 0,
+            // Lowered to `?:` rather than a block
+            Expr::If {
+                then_branch,
+                else_branch,
+                ..
+            } if !is_block(self.ast, *then_branch) && !is_block(self.ast, *else_branch) => 0,
+            Expr::BinOp { op, .. } => match js.as_str() {
+                // Lowered to a runtime call
+                js if HELPERS.iter().any(|h| js.starts_with(h)) => ATOM,
+                js if js.starts_with("!$eq(") => UNARY,
+                js if js.starts_with("$compare(") => precedence(BinOp::Lt),
+                _ if *op == BinOp::Cons => ATOM,
+                _ => precedence(*op),
+            },
+            _ => ATOM,
+        };
+        Ok(if binds < needed {
+            format!("({})", js)
+        } else {
+            js
+        })
+    }
+
+    fn var(&mut self, name: &str, span: Span) -> Result<String, LyraError> {
+        if let Some(binding) = self.lookup(name) {
+            return Ok(binding.js.clone());
+        }
+        match self.builtins.get_key_value(name) {
+            Some((&builtin, _)) => {
+                self.used.insert(builtin);
+                Ok(builtin.to_string())
+            }
+            None => Err(unsupported(
+                &format!("'{}' has no JavaScript version", name),
+                span,
+            )),
+        }
+    }
+
+    fn lambda(
+        &mut self,
+        params: &[&String],
+        body: ExprId,
+        indent: usize,
+    ) -> Result<String, LyraError> {
+        self.scoped(|this| {
+            let params: Vec<String> = params.iter().map(|p| this.bind(p, None)).collect();
+            let params = params.join(", ");
+            if is_block(this.ast, body) {
+                let mut block = String::new();
+                this.stmts(body, indent + 1, &mut block)?;
+                Ok(format!("({}) => {{\n{}{}}}", params, block, pad(indent)))
+            } else {
+                let js = this.expr(body, indent)?;
+                if js.starts_with('{') {
+                    Ok(format!("({}) => ({})", params, js))
+                } else {
+                    Ok(format!("({}) => {}", params, js))
+                }
+            }
+        })
+    }
+
+    /// A call, made directly when the callee's parameters are known and
+    /// the arguments fill them exactly, and through `$call` otherwise.
+    fn call(
+        &mut self,
+        func: ExprId,
+        args: &[ExprId],
+        indent: usize,
+    ) -> Result<String, LyraError> {
+        let mut shown = Vec::new();
+        for &arg in args {
+            shown.push(self.expr(arg, indent)?);
+        }
+        let ast = self.ast;
+        if let Expr::Var(name) = &ast[func].node {
+            if let Some(binding) = self.lookup(name) {
+                if binding
+                    .params
+                    .as_ref()
+                    .is_some_and(|p| p.len() == args.len())
+                {
+                    return Ok(format!("{}({})", binding.js, shown.join(", ")));
+                }
+            } else if let Some(&(arity, variadic)) = self.builtins.get(name.as_str()) {
+                // `println()` runs a variadic builtin with no arguments
+                if args.len() == arity || (variadic && (args.len() > arity || args.is_empty())) {
+                    let callee = self.var(name, ast[func].span)?;
+                    // Say which numbers are Floats to the builtins that print
+                    if matches!(name.as_str(), "print" | "println" | "to_string") {
+                        for (js, arg) in shown.iter_mut().zip(args) {
+                            if let Some(desc) = self.describe_expr(*arg) {
+                                let show = if name == "to_string" { "$show" } else { "$str" };
+                                *js = format!("{}({}, {})", show, js, desc);
+                            }
+                        }
+                        if name == "to_string" && shown[0].starts_with("$show(") {
+                            return Ok(shown.remove(0));
+                        }
+                    }
+                    return Ok(format!("{}({})", callee, shown.join(", ")));
+                }
+            }
+        }
+        let func = self.operand(func, ATOM, indent)?;
+        Ok(format!("$call({}, [{}])", func, shown.join(", ")))
+    }
+
+    fn binop(
+        &mut self,
+        op: BinOp,
+        lhs: ExprId,
+        rhs: ExprId,
+        indent: usize,
+    ) -> Result<String, LyraError> {
+        let lhs_ty = self.types.get(&self.ast[lhs].span).copied();
+        let rhs_ty = self.types.get(&self.ast[rhs].span).copied();
+        let ints = lhs_ty == Some(&MonoType::Int) && rhs_ty == Some(&MonoType::Int);
+        // JavaScript has one kind of number, so whether `/` truncates has to
+        // be known here, not left to a generic function's caller
+        let generic = |ty: Option<&MonoType>| matches!(ty, Some(MonoType::Var(_)));
+        if op == BinOp::Div && (generic(lhs_ty) || generic(rhs_ty)) {
+            return Err(unsupported(
+                "`/` on a number that may be an Int or a Float has no JavaScript version; give the function a signature",
+                self.ast[lhs].span.merge(self.ast[rhs].span),
+            ));
+        }
+        let primitive = lhs_ty.is_some_and(is_primitive);
+        // JavaScript orders strings by UTF-16 unit, not by code point
+        let ordered = primitive && lhs_ty != Some(&MonoType::String);
+        let helper = match op {
+            BinOp::Div | BinOp::Mod | BinOp::Pow if ints => true,
+            BinOp::Cons => true,
+            BinOp::Eq | BinOp::NotEq => !primitive,
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => !ordered,
+            _ => false,
+        };
+        // Arguments of a runtime helper or list elements need no parentheses
+        let (l, r) = if helper {
+            (self.expr(lhs, indent)?, self.expr(rhs, indent)?)
+        } else {
+            // `**` is right-associative, and JavaScript wants a negated
+            // base in parentheses
+            let (l, r) = match op {
+                BinOp::Pow => (UNARY + 1, precedence(op)),
+                _ => (precedence(op), precedence(op) + 1),
+            };
+            (self.operand(lhs, l, indent)?, self.operand(rhs, r, indent)?)
+        };
+        Ok(match op {
+            BinOp::Div if ints => format!("$div({}, {})", l, r),
+            BinOp::Mod if ints => format!("$mod({}, {})", l, r),
+            BinOp::Pow if ints => format!("$pow({}, {})", l, r),
+            BinOp::Eq if primitive => format!("{} === {}", l, r),
+            BinOp::NotEq if primitive => format!("{} !== {}", l, r),
+            BinOp::Eq => format!("$eq({}, {})", l, r),
+            BinOp::NotEq => format!("!$eq({}, {})", l, r),
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge if !ordered => {
+                format!("$compare({}, {}) {} 0", l, r, op.as_str())
+            }
+            BinOp::Cons => format!("[{}, ...{}]", l, r),
+            _ => format!("{} {} {}", l, op.as_str(), r),
+        })
+    }
+
+    fn interpolation(
+        &mut self,
+        parts: &[InterpolationPart],
+        indent: usize,
+    ) -> Result<String, LyraError> {
+        let mut out = String::from("`");
+        for part in parts {
+            match part {
+                InterpolationPart::Literal(s) => out.push_str(&template_text(s)),
+                InterpolationPart::Expr(e, spec) => {
+                    let js = self.expr(*e, indent)?;
+                    let desc = self.describe_expr(*e);
+                    let shown = match (spec, self.types.get(&self.ast[*e].span)) {
+                        (Some(spec), _) => format!(
+                            "$format({}, {}, {})",
+                            js,
+                            format_spec(spec),
+                            desc.as_deref().unwrap_or("undefined")
+                        ),
+                        (None, Some(MonoType::String)) => js,
+                        (None, _) => match desc {
+                            Some(desc) => format!("$str({}, {})", js, desc),
+                            None => format!("$str({})", js),
+                        },
+                    };
+                    write!(out, "${{{}}}", shown).unwrap();
+                }
+            }
+        }
+        out.push('`');
+        Ok(out)
+    }
+
+    // ── Statements ──
+
+    /// Lower an expression as the statements of a function body ending in
+    /// `return`.
+    fn stmts(
+        &mut self,
+        expr: ExprId,
+        indent: usize,
+        out: &mut String,
+    ) -> Result<(), LyraError> {
+        let tab = pad(indent);
+        let ast = self.ast;
+        match &ast[expr].node {
+            Expr::Let {
+                name,
+                recursive,
+                value,
+                body,
+                ..
+            } => self.scoped(|this| {
+                let js = this.let_value(name, *recursive, *value, indent)?;
+                let bound = &this.lookup(&name.node).expect("bound by let_value").js;
+                writeln!(out, "{}const {} = {};", tab, bound, js).unwrap();
+                this.stmts(*body, indent, out)
+            }),
+            Expr::If {
+                cond,
+                then_branch,
+                else_branch,
+            } if is_block(ast, *then_branch) || is_block(ast, *else_branch) => {
+                writeln!(out, "{}if ({}) {{", tab, self.expr(*cond, indent)?).unwrap();
+                self.scoped(|this| this.stmts(*then_branch, indent + 1, out))?;
+                writeln!(out, "{}}}", tab).unwrap();
+                self.scoped(|this| this.stmts(*else_branch, indent, out))
+            }
+            Expr::Match { scrutinee, arms } => self.match_stmts(*scrutinee, arms, indent, out),
+            _ => {
+                writeln!(out, "{}return {};", tab, self.expr(expr, indent)?).unwrap();
+                Ok(())
+            }
+        }
+    }
+
+    fn match_stmts(
+        &mut self,
+        scrutinee: ExprId,
+        arms: &[MatchArm],
+        indent: usize,
+        out: &mut String,
+    ) -> Result<(), LyraError> {
+        let tab = pad(indent);
+        let value = match &self.ast[scrutinee].node {
+            Expr::Var(name) => self.var(name, self.ast[scrutinee].span)?,
+            _ => {
+                let temp = self.temp();
+                let js = self.expr(scrutinee, indent)?;
+                writeln!(out, "{}const {} = {};", tab, temp, js).unwrap();
+                temp
+            }
+        };
+        let tried: Vec<String> = arms
+            .iter()
+            .map(|arm| arm.pattern.node.to_string())
+            .collect();
+        let no_match = format!(
+            "{}return $noMatch({}, {});\n",
+            tab,
+            value,
+            js_string(&tried.join(", "))
+        );
+
+        if is_switch(arms) {
+            writeln!(out, "{}switch ({}.$tag) {{", tab, value).unwrap();
+            let mut exhausted = false;
+            for arm in arms {
+                match &arm.pattern.node {
+                    Pattern::Constructor { name, .. } => {
+                        writeln!(out, "{}  case {}: {{", tab, js_string(unqualified(name))).unwrap()
+                    }
+                    _ => {
+                        writeln!(out, "{}  default: {{", tab).unwrap();
+                        exhausted = true;
+                    }
+                }
+                self.arm(arm, &value, indent + 2, out)?;
+                writeln!(out, "{}  }}", tab).unwrap();
+            }
+            writeln!(out, "{}}}", tab).unwrap();
+            if !exhausted {
+                out.push_str(&no_match);
+            }
+            return Ok(());
+        }
+
+        for arm in arms {
+            let mut conds = Vec::new();
+            pattern_conds(&arm.pattern, &value, &mut conds);
+            if conds.is_empty() {
+                // Matches anything, so the arms after it never run
+                return self.arm(arm, &value, indent, out);
+            }
+            writeln!(out, "{}if ({}) {{", tab, conds.join(" && ")).unwrap();
+            self.arm(arm, &value, indent + 1, out)?;
+            writeln!(out, "{}}}", tab).unwrap();
+        }
+        out.push_str(&no_match);
+        Ok(())
+    }
+
+    /// Bind an arm's pattern variables, then lower its body.
+    fn arm(
+        &mut self,
+        arm: &MatchArm,
+        value: &str,
+        indent: usize,
+        out: &mut String,
+    ) -> Result<(), LyraError> {
+        self.scoped(|this| {
+            let mut binds = Vec::new();
+            pattern_binds(&arm.pattern, value, &mut binds);
+            for (name, path) in binds {
+                let js = this.bind(&name, None);
+                writeln!(out, "{}const {} = {};", pad(indent), js, path).unwrap();
+            }
+            this.stmts(arm.body, indent, out)
+        })
+    }
+
+    fn scoped<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(Vec::new());
+        let result = f(self);
+        self.scopes.pop();
+        result
+    }
+
+    // ── Describing Floats for printing ──
+
+    fn describe_expr(&self, expr: ExprId) -> Option<String> {
+        let ty = self.types.get(&self.ast[expr].span)?;
+        self.describe(ty, &HashMap::new(), &mut Vec::new())
+    }
+
+    /// The `type` argument of the runtime's `$show` for a value of type
+    /// `ty`, or `None` when there are no Floats in it to tell from Ints.
+    fn describe(
+        &self,
+        ty: &MonoType,
+        vars: &HashMap<TypeVar, Option<String>>,
+        visiting: &mut Vec<String>,
+    ) -> Option<String> {
+        match ty {
+            MonoType::Float => Some("\"F\"".to_string()),
+            MonoType::Var(v) => vars.get(v).cloned().flatten(),
+            MonoType::List(elem) => {
+                let elem = self.describe(elem, vars, visiting)?;
+                Some(format!("[\"L\", {}]", elem))
+            }
+            MonoType::Tuple(elems) => {
+                let elems: Vec<_> = elems
+                    .iter()
+                    .map(|t| self.describe(t, vars, visiting))
+                    .collect();
+                elems
+                    .iter()
+                    .any(Option::is_some)
+                    .then(|| format!("[\"T\", [{}]]", described(&elems).join(", ")))
+            }
+            MonoType::Record(fields) | MonoType::OpenRecord(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .filter_map(|(k, t)| {
+                        Some(format!("{}: {}", k, self.describe(t, vars, visiting)?))
+                    })
+                    .collect();
+                (!fields.is_empty()).then(|| format!("[\"R\", {{ {} }}]", fields.join(", ")))
+            }
+            // A type inside itself describes as nothing rather than forever
+            MonoType::Con(name, args) if !visiting.contains(name) => {
+                let ctors = self.adts.get(name)?;
+                visiting.push(name.clone());
+                let mut described_ctors = Vec::new();
+                for ctor in ctors {
+                    let Some(scheme) = self.env.lookup(ctor) else {
+                        continue;
+                    };
+                    let mut result = &scheme.ty;
+                    while let MonoType::Arrow(_, ret) = result {
+                        result = ret;
+                    }
+                    let MonoType::Con(_, params) = result else {
+                        continue;
+                    };
+                    let mut inner = HashMap::new();
+                    for (param, arg) in params.iter().zip(args) {
+                        if let MonoType::Var(v) = param {
+                            inner.insert(*v, self.describe(arg, vars, visiting));
+                        }
+                    }
+                    let fields: Vec<_> = scheme
+                        .ty
+                        .params()
+                        .into_iter()
+                        .map(|t| self.describe(t, &inner, visiting))
+                        .collect();
+                    if fields.iter().any(Option::is_some) {
+                        described_ctors.push(format!(
+                            "{}: [{}]",
+                            ctor,
+                            described(&fields).join(", ")
+                        ));
+                    }
+                }
+                visiting.pop();
+                (!described_ctors.is_empty())
+                    .then(|| format!("[\"A\", {{ {} }}]", described_ctors.join(", ")))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn described(items: &[Option<String>]) -> Vec<String> {
+    items
+        .iter()
+        .map(|d| d.clone().unwrap_or_else(|| "undefined".to_string()))
+        .collect()
+}
+
+/// Whether an expression lowers to statements rather than one expression.
+fn is_block(ast: &Ast, expr: ExprId) -> bool {
+    match &ast[expr].node {
+        Expr::Let { .. } | Expr::Match { .. } => true,
+        Expr::If {
+            then_branch,
+            else_branch,
+            ..
+        } => is_block(ast, *then_branch) || is_block(ast, *else_branch),
+        _ => false,
+    }
+}
+
+/// How tightly a JavaScript operator binds. `UNARY` is for `-` and `!`, and
+/// `ATOM` for what never needs parentheses, like calls and literals.
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or => 1,
+        BinOp::And => 2,
+        BinOp::Eq | BinOp::NotEq => 3,
+        BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => 4,
+        BinOp::Add | BinOp::Sub => 5,
+        BinOp::Mul | BinOp::Div | BinOp::Mod => 6,
+        BinOp::Pow => 7,
+        BinOp::Cons => ATOM,
+    }
+}
+
+const UNARY: u8 = 8;
+const ATOM: u8 = 10;
+
+fn is_primitive(ty: &MonoType) -> bool {
+    matches!(
+        ty,
+        MonoType::Int | MonoType::Float | MonoType::String | MonoType::Bool | MonoType::Unit
+    )
+}
+
+/// Whether a match can be a `switch` on the constructor's tag: every arm
+/// but a last catch-all tests a different constructor and only binds its
+/// fields.
+fn is_switch(arms: &[MatchArm]) -> bool {
+    let mut seen = HashSet::new();
+    arms.iter()
+        .enumerate()
+        .all(|(i, arm)| match &arm.pattern.node {
+            Pattern::Constructor { name, args } => {
+                seen.insert(unqualified(name))
+                    && args
+                        .iter()
+                        .all(|a| matches!(a.node, Pattern::Var(_) | Pattern::Wildcard))
+            }
+            Pattern::Var(_) | Pattern::Wildcard => i > 0 && i == arms.len() - 1,
+            _ => false,
+        })
+}
+
+/// The tests `value` must pass to match `pattern`.
+fn pattern_conds(pattern: &SpannedPattern, value: &str, conds: &mut Vec<String>) {
+    match &pattern.node {
+        Pattern::Wildcard | Pattern::Var(_) | Pattern::UnitLit => {}
+        Pattern::IntLit(n) => conds.push(format!("{} === {}", value, n)),
+        Pattern::FloatLit(f) => conds.push(format!("{} === {:?}", value, f)),
+        Pattern::StringLit(s) => conds.push(format!("{} === {}", value, js_string(s))),
+        Pattern::BoolLit(b) => conds.push(format!("{} === {}", value, b)),
+        Pattern::StringPrefix(prefix, rest) => {
+            conds.push(format!("{}.startsWith({})", value, js_string(prefix)));
+            pattern_conds(rest, &string_after(value, prefix), conds);
+        }
+        Pattern::StringSuffix(rest, suffix) => {
+            conds.push(format!("{}.endsWith({})", value, js_string(suffix)));
+            pattern_conds(rest, &string_before(value, suffix), conds);
+        }
+        Pattern::Tuple(elems) => {
+            for (i, elem) in elems.iter().enumerate() {
+                pattern_conds(elem, &format!("{}[{}]", value, i), conds);
+            }
+        }
+        Pattern::List(elems) => {
+            conds.push(format!("{}.length === {}", value, elems.len()));
+            for (i, elem) in elems.iter().enumerate() {
+                pattern_conds(elem, &format!("{}[{}]", value, i), conds);
+            }
+        }
+        Pattern::Cons(head, tail) => {
+            conds.push(format!("{}.length > 0", value));
+            pattern_conds(head, &format!("{}[0]", value), conds);
+            pattern_conds(tail, &format!("{}.slice(1)", value), conds);
+        }
+        Pattern::Constructor { name, args } => {
+            conds.push(format!(
+                "{}.$tag === {}",
+                value,
+                js_string(unqualified(name))
+            ));
+            for (i, arg) in args.iter().enumerate() {
+                pattern_conds(arg, &format!("{}.$fields[{}]", value, i), conds);
+            }
+        }
+    }
+}
+
+/// The variables `pattern` binds and where in `value` each one is.
+fn pattern_binds(pattern: &SpannedPattern, value: &str, binds: &mut Vec<(String, String)>) {
+    match &pattern.node {
+        Pattern::Var(name) => binds.push((name.clone(), value.to_string())),
+        Pattern::Tuple(elems) | Pattern::List(elems) => {
+            for (i, elem) in elems.iter().enumerate() {
+                pattern_binds(elem, &format!("{}[{}]", value, i), binds);
+            }
+        }
+        Pattern::Cons(head, tail) => {
+            pattern_binds(head, &format!("{}[0]", value), binds);
+            pattern_binds(tail, &format!("{}.slice(1)", value), binds);
+        }
+        Pattern::Constructor { args, .. } => {
+            for (i, arg) in args.iter().enumerate() {
+                pattern_binds(arg, &format!("{}.$fields[{}]", value, i), binds);
+            }
+        }
+        Pattern::StringPrefix(prefix, rest) => {
+            pattern_binds(rest, &string_after(value, prefix), binds)
+        }
+        Pattern::StringSuffix(rest, suffix) => {
+            pattern_binds(rest, &string_before(value, suffix), binds)
+        }
+        _ => {}
+    }
+}
+
+/// `value` with `prefix` sliced off its start. JS measures strings in
+/// UTF-16 code units.
+fn string_after(value: &str, prefix: &str) -> String {
+    format!("{}.slice({})", value, prefix.encode_utf16().count())
+}
+
+/// `value` with `suffix` sliced off its end.
+fn string_before(value: &str, suffix: &str) -> String {
+    let len = suffix.encode_utf16().count();
+    format!("{0}.slice(0, {0}.length - {1})", value, len)
+}
+
+/// A format spec as the object the runtime's `$format` takes.
+fn format_spec(spec: &FormatSpec) -> String {
+    let mut fields = Vec::new();
+    if spec.fill != ' ' {
+        fields.push(format!("fill: {}", js_string(&spec.fill.to_string())));
+    }
+    if let Some(align) = spec.align {
+        let align = match align {
+            Align::Left => "<",
+            Align::Center => "^",
+            Align::Right => ">",
+        };
+        fields.push(format!("align: \"{}\"", align));
+    }
+    if spec.zero {
+        fields.push("zero: true".to_string());
+    }
+    if let Some(width) = spec.width {
+        fields.push(format!("width: {}", width));
+    }
+    if let Some(precision) = spec.precision {
+        fields.push(format!("precision: {}", precision));
+    }
+    format!("{{ {} }}", fields.join(", "))
+}
+
+fn js_string(s: &str) -> String {
+    format!("\"{}\"", escape(s, '"'))
+}
+
+/// Text between the holes of a template literal.
+fn template_text(s: &str) -> String {
+    escape(s, '`').replace("${", "\\${")
+}
+
+fn escape(s: &str, quote: char) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_control() => write!(out, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn pad(indent: usize) -> String {
+    "  ".repeat(indent)
+}
+
+fn unsupported(message: &str, span: Span) -> LyraError {
+    LyraError::Unsupported {
+        message: message.to_string(),
+        span,
+    }
+}
@@ -0,0 +1,385 @@
+//# core
+"use strict";
+
+// Lyra runtime. Ints and Floats are numbers, Strings strings, Bools
+// booleans, Unit is undefined, Lists arrays, tuples arrays made by $tuple,
+// records plain objects, and constructed values { $tag, $fields }.
+
+class LyraError extends Error {}
+
+const $fail = (message) => {
+  throw new LyraError(message);
+};
+
+const $tuple = (...items) => Object.assign(items, { $tuple: true });
+
+// The value the host set on globalThis for an `extern` declaration.
+const $extern = (name, arity) => {
+  const value = globalThis[name];
+  if (value === undefined) $fail(`extern '${name}' was not provided by the host`);
+  if (typeof value === "function" && !value.$variadic && value.length !== arity) {
+    const args = (n) => (n === 1 ? "1 argument" : `${n} arguments`);
+    const given = args(value.length);
+    $fail(`extern '${name}' is declared to take ${args(arity)}, but the host's takes ${given}`);
+  }
+  return value;
+};
+
+// xs[start:end] on an array, or a string by code point. A negative bound
+// counts from the end, and bounds are clamped to the length.
+const $slice = (v, start, end) => {
+  const items = typeof v === "string" ? Array.from(v) : v;
+  const bound = (i) => Math.min(Math.max(i < 0 ? i + items.length : i, 0), items.length);
+  const from = start === undefined ? 0 : bound(start);
+  const to = end === undefined ? items.length : Math.max(bound(end), from);
+  const sliced = items.slice(from, to);
+  return typeof v === "string" ? sliced.join("") : sliced;
+};
+
+// Apply f to args as Lyra does: too few arguments give a partial
+// application, and too many go to the function f returns. A variadic
+// builtin runs with none only when called by its own name.
+const $call = (f, args) => {
+  if (typeof f !== "function") $fail(`value is not callable: ${$show(f)}`);
+  const arity = f.length;
+  if (f.$variadic) return args.length > 0 ? f(...args) : f;
+  if (args.length < arity) {
+    const partial = (...more) => $call(f, [...args, ...more]);
+    return Object.defineProperty(partial, "length", { value: arity - args.length });
+  }
+  const result = f(...args.slice(0, arity));
+  return args.length === arity ? result : $call(result, args.slice(arity));
+};
+
+const $div = (a, b) => (b === 0 ? $fail("division by zero") : Math.trunc(a / b));
+
+const $mod = (a, b) => (b === 0 ? $fail("division by zero") : a % b);
+
+// Euclidean division: the remainder is never negative.
+const $divmod = (name, a, b) => {
+  if (b === 0) $fail(`${name}: division by zero`);
+  const r = ((a % b) + Math.abs(b)) % Math.abs(b);
+  return $tuple((a - r) / b, r);
+};
+
+const $pow = (base, exp) => {
+  if (exp < 0) $fail(`negative exponent ${exp} for Int power`);
+  const result = base ** exp;
+  if (!Number.isSafeInteger(result)) $fail(`integer overflow in ${base} ** ${exp}`);
+  return result;
+};
+
+// Structural equality. NaN equals nothing, and functions can't be compared.
+const $eq = (a, b) => {
+  if (typeof a === "function" || typeof b === "function") {
+    $fail("cannot compare functions for equality");
+  }
+  if (a === b) return true;
+  if (Array.isArray(a) && Array.isArray(b)) {
+    return a.length === b.length && a.every((x, i) => $eq(x, b[i]));
+  }
+  if (a && b && typeof a === "object" && typeof b === "object") {
+    const keys = Object.keys(a);
+    return keys.length === Object.keys(b).length && keys.every((k) => $eq(a[k], b[k]));
+  }
+  return false;
+};
+
+// Structural order, as the interpreter's: constructed values by tag and
+// then fields, records by field in name order. NaN is unordered, so every
+// comparison it decides is false.
+const $compare = (a, b) => {
+  if (Array.isArray(a) && Array.isArray(b)) {
+    for (let i = 0; i < Math.min(a.length, b.length); i++) {
+      const c = $compare(a[i], b[i]);
+      if (c !== 0) return c;
+    }
+    return a.length - b.length;
+  }
+  if (a && b && typeof a === "object" && typeof b === "object") {
+    if (a.$tag !== undefined) {
+      return a.$tag !== b.$tag ? $compare(a.$tag, b.$tag) : $compare(a.$fields, b.$fields);
+    }
+    const keys = Object.keys(a).sort();
+    return $compare(keys.map((k) => a[k]), keys.map((k) => b[k]));
+  }
+  if (typeof a === "function" || typeof b === "function") $fail("cannot order functions");
+  // By code point, as Rust orders strings; `<` would compare UTF-16 units
+  if (typeof a === "string" && typeof b === "string") {
+    const xs = a[Symbol.iterator](), ys = b[Symbol.iterator]();
+    for (;;) {
+      const x = xs.next(), y = ys.next();
+      if (x.done || y.done) return x.done && y.done ? 0 : x.done ? -1 : 1;
+      const c = x.value.codePointAt(0) - y.value.codePointAt(0);
+      if (c !== 0) return c;
+    }
+  }
+  return a < b ? -1 : a > b ? 1 : a === b ? 0 : NaN;
+};
+
+// Display a value as Lyra does. Numbers can't tell Ints from Floats, so
+// `type` describes where the Floats are: "F" for a Float, ["L", t] for a
+// list, ["T", [t, ...]] for a tuple, ["R", { field: t }] for a record and
+// ["A", { Constructor: [t, ...] }] for a constructed value.
+const $show = (v, type) => {
+  if (v === undefined) return "()";
+  if (typeof v === "number") {
+    if (type !== "F") return String(v);
+    if (!Number.isFinite(v)) return Number.isNaN(v) ? "NaN" : v > 0 ? "inf" : "-inf";
+    return Number.isInteger(v) ? v.toFixed(1) : String(v);
+  }
+  if (typeof v === "string") return `"${v}"`;
+  if (typeof v === "boolean") return String(v);
+  if (typeof v === "function") return "<function>";
+  const inner = (i) => (type && type[0] === "T" ? type[1][i] : type && type[0] === "L" ? type[1] : undefined);
+  if (Array.isArray(v)) {
+    const items = v.map((x, i) => $show(x, inner(i))).join(", ");
+    return v.$tuple ? `(${items})` : `[${items}]`;
+  }
+  if (v.$tag !== undefined) {
+    if (v.$fields.length === 0) return v.$tag;
+    const fields = type && type[0] === "A" ? type[1][v.$tag] || [] : [];
+    return `${v.$tag}(${v.$fields.map((x, i) => $show(x, fields[i])).join(", ")})`;
+  }
+  const fields = type && type[0] === "R" ? type[1] : {};
+  const shown = Object.keys(v).sort().map((k) => `${k}: ${$show(v[k], fields[k])}`);
+  return `{ ${shown.join(", ")} }`;
+};
+
+// Display for interpolation and printing: strings without quotes.
+const $str = (v, type) => (typeof v === "string" ? v : $show(v, type));
+
+// Display for `{value:spec}`; spec is { fill, align, zero, width, precision }.
+const $format = (v, spec, type) => {
+  let text;
+  if (typeof v === "number" && type === "F" && spec.precision !== undefined) {
+    text = v.toFixed(spec.precision);
+  } else if (typeof v === "string" && spec.precision !== undefined) {
+    text = [...v].slice(0, spec.precision).join("");
+  } else {
+    text = $str(v, type);
+  }
+  const gap = (spec.width || 0) - [...text].length;
+  if (gap <= 0) return text;
+  const numeric = typeof v === "number";
+  if (spec.zero && numeric && !spec.align) {
+    const sign = text.startsWith("-") ? "-" : "";
+    return sign + "0".repeat(gap) + text.slice(sign.length);
+  }
+  const fill = (n) => (spec.fill || " ").repeat(n);
+  switch (spec.align || (numeric ? ">" : "<")) {
+    case "<":
+      return text + fill(gap);
+    case ">":
+      return fill(gap) + text;
+    default:
+      return fill(Math.floor(gap / 2)) + text + fill(gap - Math.floor(gap / 2));
+  }
+};
+
+const $noMatch = (v, patterns) => $fail(`no pattern matches ${$show(v)}; tried ${patterns}`);
+
+// Output goes to stdout under Node and to the console elsewhere, where a
+// line is logged once it ends.
+let $pending = "";
+const $write = (text, newline) => {
+  if (typeof process !== "undefined" && process.stdout) {
+    process.stdout.write(newline ? text + "\n" : text);
+  } else if (newline) {
+    console.log($pending + text);
+    $pending = "";
+  } else {
+    $pending += text;
+  }
+};
+
+//# print 1+
+const print = Object.assign((...args) => {
+  $write(args.map((a) => $str(a)).join(" "), false);
+}, { $variadic: true });
+
+//# println 1+
+const println = Object.assign((...args) => {
+  $write(args.map((a) => $str(a)).join(" "), true);
+}, { $variadic: true });
+
+//# to_string 1
+const to_string = (v) => $show(v);
+
+//# str_length 1
+const str_length = (s) => new TextEncoder().encode(s).length;
+
+//# str_concat 2
+const str_concat = (a, b) => a + b;
+
+//# str_contains 2
+const str_contains = (s, needle) => s.includes(needle);
+
+//# str_split 2
+const str_split = (s, delim) => s.split(delim);
+
+//# str_chars 1
+const str_chars = (s) => [...s];
+
+//# length 1
+const length = (l) => l.length;
+
+//# head 1
+const head = (l) => (l.length > 0 ? l[0] : $fail("head: empty list"));
+
+//# tail 1
+const tail = (l) => (l.length > 0 ? l.slice(1) : $fail("tail: empty list"));
+
+//# reverse 1
+const reverse = (l) => [...l].reverse();
+
+//# append 2
+const append = (a, b) => [...a, ...b];
+
+//# range 2
+const range = (start, end) => Array.from({ length: Math.max(end - start, 0) }, (_, i) => start + i);
+
+//# nth 2
+const nth = (l, i) => (i >= 0 && i < l.length ? l[i] : $fail(`nth: index ${i} out of bounds for length ${l.length}`));
+
+//# map 2
+const map = (f, l) => l.map((x) => $call(f, [x]));
+
+//# filter 2
+const filter = (f, l) => l.filter((x) => $call(f, [x]));
+
+//# fold 3
+const fold = (init, f, l) => l.reduce((acc, x) => $call(f, [acc, x]), init);
+
+//# zip 2
+const zip = (a, b) => a.slice(0, Math.min(a.length, b.length)).map((x, i) => $tuple(x, b[i]));
+
+//# any 2
+const any = (f, l) => l.some((x) => $call(f, [x]));
+
+//# all 2
+const all = (f, l) => l.every((x) => $call(f, [x]));
+
+//# tap 2
+const tap = (f, x) => {
+  $call(f, [x]);
+  return x;
+};
+
+//# memo 1
+const memo = (f) => {
+  const cache = new Map();
+  const arity = Math.max(f.length, 1);
+  const wrapper = (...args) => {
+    const key = $show($tuple(...args));
+    if (!cache.has(key)) cache.set(key, $call(f, args));
+    return cache.get(key);
+  };
+  return Object.defineProperty(wrapper, "length", { value: arity });
+};
+
+//# bench 2
+const bench = (f, n) => {
+  if (n <= 0) $fail(`bench: iterations must be positive, got ${n}`);
+  let total = 0;
+  let min = Infinity;
+  let max = 0;
+  for (let i = 0; i < n; i++) {
+    const start = performance.now();
+    $call(f, []);
+    const elapsed = performance.now() - start;
+    total += elapsed;
+    min = Math.min(min, elapsed);
+    max = Math.max(max, elapsed);
+  }
+  return { iterations: n, total_ms: total, mean_ms: total / n, min_ms: min, max_ms: max };
+};
+
+//# sort 1
+const sort = (l) => [...l].sort((a, b) => a - b);
+
+//# take 2
+const take = (n, l) => l.slice(0, Math.max(n, 0));
+
+//# drop 2
+const drop = (n, l) => l.slice(Math.max(n, 0));
+
+//# flatten 1
+const flatten = (l) => l.flat();
+
+//# sum 1
+const sum = (l) => l.reduce((a, b) => a + b, 0);
+
+//# product 1
+const product = (l) => l.reduce((a, b) => a * b, 1);
+
+//# string_to_int 1
+const string_to_int = (s) =>
+  /^[+-]?\d+$/.test(s) ? Number(s) : $fail(`string_to_int: cannot parse "${s}" as Int`);
+
+//# int_to_string 1
+const int_to_string = (n) => String(n);
+
+//# str_trim 1
+const str_trim = (s) => s.trim();
+
+//# str_uppercase 1
+const str_uppercase = (s) => s.toUpperCase();
+
+//# str_lowercase 1
+const str_lowercase = (s) => s.toLowerCase();
+
+//# str_replace 3
+const str_replace = (s, from, to) => s.split(from).join(to);
+
+//# str_starts_with 2
+const str_starts_with = (s, prefix) => s.startsWith(prefix);
+
+//# str_ends_with 2
+const str_ends_with = (s, suffix) => s.endsWith(suffix);
+
+//# str_substring 3
+const str_substring = (s, start, end) => (start > s.length ? "" : s.slice(start, Math.min(end, s.length)));
+
+//# abs 1
+const abs = (n) => Math.abs(n);
+
+//# min 2
+const min = (a, b) => Math.min(a, b);
+
+//# max 2
+const max = (a, b) => Math.max(a, b);
+
+//# pow 2
+const pow = (base, exp) => $pow(base, exp);
+
+//# float_pow 2
+const float_pow = (base, exp) => base ** exp;
+
+//# divmod 2
+const divmod = (a, b) => $divmod("divmod", a, b);
+
+//# div_euclid 2
+const div_euclid = (a, b) => $divmod("div_euclid", a, b)[0];
+
+//# mod_euclid 2
+const mod_euclid = (a, b) => $divmod("mod_euclid", a, b)[1];
+
+//# float_of_int 1
+const float_of_int = (n) => n;
+
+//# int_of_float 1
+const int_of_float = (f) => Math.trunc(f);
+
+//# record_fields 1
+const record_fields = (r) => Object.keys(r).sort().map((k) => $tuple(k, r[k]));
+
+//# record_get 2
+const record_get = (r, name) =>
+  Object.hasOwn(r, name) ? { $tag: "Some", $fields: [r[name]] } : { $tag: "None", $fields: [] };
+
+//# adt_constructor 1
+const adt_constructor = (v) => v.$tag;
+
+//# adt_fields 1
+const adt_fields = (v) => v.$fields;
@@ -1,187 +1,223 @@
 pub mod token;
 
+use crate::ast::{FormatSpec, MAX_FORMAT_WIDTH};
 use crate::error::LyraError;
 use crate::span::Span;
-use token::{Token, TokenKind};
+use token::{Token, TokenKind, TokenOrTrivia, Trivia, TriviaKind};
+
+/// A place in the source: a byte index into it, and the same place
+/// counted in characters, as spans are.
+#[derive(Debug, Clone, Copy, Default)]
+struct Cursor {
+    byte: usize,
+    char: usize,
+}
 
-pub struct Lexer {
-    chars: Vec<char>,
-    pos: usize,
-    start: usize,
+/// The lexer, an iterator over the tokens of a borrowed source. Each item
+/// is a token or an error, lexing carries on past errors, and the last
+/// token is `Eof`. `tokenize` collects it.
+pub struct Lexer<'a> {
+    source: &'a str,
+    pos: Cursor,
+    start: Cursor,
+    /// Where `source` starts in the file, in characters: nonzero for the
+    /// expression inside an interpolated string, so its spans point into
+    /// the original source.
+    base: usize,
+    /// `---` doc comment lines waiting to be attached to the next token.
+    pending_doc: Vec<String>,
+    /// Whitespace and comments skipped so far; `None` unless lexing losslessly.
+    trivia: Option<Vec<Trivia>>,
+    /// Whether `Eof` has been produced.
+    done: bool,
 }
 
-impl Lexer {
-    pub fn new(source: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Self {
         Lexer {
-            chars: source.chars().collect(),
-            pos: 0,
-            start: 0,
+            source,
+            pos: Cursor::default(),
+            start: Cursor::default(),
+            base: 0,
+            pending_doc: Vec::new(),
+            trivia: None,
+            done: false,
         }
     }
 
     pub fn tokenize(&mut self) -> Result<Vec<Token>, Vec<LyraError>> {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
-
-        loop {
-            self.skip_whitespace_and_comments();
-            if self.is_at_end() {
-                tokens.push(Token::new(TokenKind::Eof, Span::new(self.pos, self.pos)));
-                break;
+        for item in self.by_ref() {
+            match item {
+                Ok(token) => tokens.push(token),
+                Err(e) => errors.push(e),
             }
+        }
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
 
-            self.start = self.pos;
-            match self.advance() {
-                '(' => tokens.push(self.make_token(TokenKind::LParen)),
-                ')' => tokens.push(self.make_token(TokenKind::RParen)),
-                '[' => tokens.push(self.make_token(TokenKind::LBracket)),
-                ']' => tokens.push(self.make_token(TokenKind::RBracket)),
-                '{' => tokens.push(self.make_token(TokenKind::LBrace)),
-                '}' => tokens.push(self.make_token(TokenKind::RBrace)),
-                ',' => tokens.push(self.make_token(TokenKind::Comma)),
-                '.' => tokens.push(self.make_token(TokenKind::Dot)),
-                '+' => tokens.push(self.make_token(TokenKind::Plus)),
-                '*' => tokens.push(self.make_token(TokenKind::Star)),
-                '/' => tokens.push(self.make_token(TokenKind::Slash)),
-                '%' => tokens.push(self.make_token(TokenKind::Percent)),
-
-                '-' => {
-                    if self.match_char('>') {
-                        tokens.push(self.make_token(TokenKind::Arrow));
-                    } else {
-                        tokens.push(self.make_token(TokenKind::Minus));
-                    }
+    /// The token starting at `self.start`, its first char already taken.
+    fn scan_token(&mut self, first: char) -> Result<Token, LyraError> {
+        let kind = match first {
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            '{' => TokenKind::LBrace,
+            '}' => TokenKind::RBrace,
+            ',' => TokenKind::Comma,
+            '.' => TokenKind::Dot,
+            '+' => {
+                if self.match_char('+') {
+                    TokenKind::PlusPlus
+                } else {
+                    TokenKind::Plus
                 }
-
-                '|' => {
-                    if self.match_char('>') {
-                        tokens.push(self.make_token(TokenKind::PipeRight));
-                    } else if self.match_char('|') {
-                        tokens.push(self.make_token(TokenKind::Or));
-                    } else {
-                        tokens.push(self.make_token(TokenKind::Pipe));
-                    }
+            }
+            '*' => {
+                if self.match_char('*') {
+                    TokenKind::StarStar
+                } else {
+                    TokenKind::Star
                 }
-
-                '=' => {
-                    if self.match_char('=') {
-                        tokens.push(self.make_token(TokenKind::EqEq));
-                    } else {
-                        tokens.push(self.make_token(TokenKind::Eq));
-                    }
+            }
+            '/' => TokenKind::Slash,
+            '%' => TokenKind::Percent,
+
+            '-' => {
+                if self.match_char('>') {
+                    TokenKind::Arrow
+                } else {
+                    TokenKind::Minus
                 }
+            }
 
-                '!' => {
-                    if self.match_char('=') {
-                        tokens.push(self.make_token(TokenKind::NotEq));
+            '|' => {
+                if self.match_char('>') {
+                    if self.match_char('!') {
+                        TokenKind::PipeTap
                     } else {
-                        tokens.push(self.make_token(TokenKind::Not));
+                        TokenKind::PipeRight
                     }
+                } else if self.match_char('|') {
+                    TokenKind::Or
+                } else {
+                    TokenKind::Pipe
                 }
+            }
 
-                '<' => {
-                    if self.match_char('=') {
-                        tokens.push(self.make_token(TokenKind::Le));
-                    } else {
-                        tokens.push(self.make_token(TokenKind::Lt));
-                    }
-                }
-
-                '>' => {
-                    if self.match_char('=') {
-                        tokens.push(self.make_token(TokenKind::Ge));
-                    } else {
-                        tokens.push(self.make_token(TokenKind::Gt));
-                    }
+            '=' => {
+                if self.match_char('=') {
+                    TokenKind::EqEq
+                } else {
+                    TokenKind::Eq
                 }
+            }
 
-                '&' => {
-                    if self.match_char('&') {
-                        tokens.push(self.make_token(TokenKind::And));
-                    } else {
-                        errors.push(LyraError::UnexpectedChar {
-                            ch: '&',
-                            span: self.current_span(),
-                        });
-                    }
+            '!' => {
+                if self.match_char('=') {
+                    TokenKind::NotEq
+                } else {
+                    TokenKind::Not
                 }
+            }
 
-                ':' => {
-                    if self.match_char(':') {
-                        tokens.push(self.make_token(TokenKind::ColonColon));
-                    } else {
-                        tokens.push(self.make_token(TokenKind::Colon));
-                    }
+            '<' => {
+                if self.match_char('=') {
+                    TokenKind::Le
+                } else {
+                    TokenKind::Lt
                 }
+            }
 
-                '_' if !self.peek().is_alphanumeric() && self.peek() != '_' => {
-                    tokens.push(self.make_token(TokenKind::Underscore));
+            '>' => {
+                if self.match_char('=') {
+                    TokenKind::Ge
+                } else {
+                    TokenKind::Gt
                 }
+            }
 
-                '"' => match self.scan_string() {
-                    Ok(tok) => tokens.push(tok),
-                    Err(e) => errors.push(e),
-                },
+            '?' if self.match_char('.') => TokenKind::QuestionDot,
+            '&' if self.match_char('&') => TokenKind::And,
 
-                c if c.is_ascii_digit() => {
-                    tokens.push(self.scan_number(c));
+            ':' => {
+                if self.match_char(':') {
+                    TokenKind::ColonColon
+                } else {
+                    TokenKind::Colon
                 }
+            }
 
-                c if c.is_alphabetic() || c == '_' => {
-                    tokens.push(self.scan_identifier(c));
-                }
+            '_' if !self.peek().is_alphanumeric() && self.peek() != '_' => TokenKind::Underscore,
 
-                c => {
-                    errors.push(LyraError::UnexpectedChar {
-                        ch: c,
-                        span: self.current_span(),
-                    });
-                }
-            }
-        }
+            '"' => return self.scan_string(),
+            c if c.is_ascii_digit() => return self.scan_number(c),
+            c if c.is_alphabetic() || c == '_' => return Ok(self.scan_identifier()),
 
-        if errors.is_empty() {
-            Ok(tokens)
-        } else {
-            Err(errors)
-        }
+            c => {
+                return Err(LyraError::UnexpectedChar {
+                    ch: c,
+                    span: self.current_span(),
+                })
+            }
+        };
+        Ok(self.make_token(kind))
     }
 
     fn is_at_end(&self) -> bool {
-        self.pos >= self.chars.len()
+        self.pos.byte >= self.source.len()
+    }
+
+    fn rest(&self) -> std::str::Chars<'a> {
+        self.source[self.pos.byte..].chars()
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.chars[self.pos]
-        }
+        self.rest().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        if self.pos + 1 >= self.chars.len() {
-            '\0'
-        } else {
-            self.chars[self.pos + 1]
-        }
+        self.rest().nth(1).unwrap_or('\0')
     }
 
     fn advance(&mut self) -> char {
-        let ch = self.chars[self.pos];
-        self.pos += 1;
+        let ch = self.peek();
+        self.pos.byte += ch.len_utf8();
+        self.pos.char += 1;
         ch
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if !self.is_at_end() && self.chars[self.pos] == expected {
-            self.pos += 1;
+        if !self.is_at_end() && self.peek() == expected {
+            self.advance();
             true
         } else {
             false
         }
     }
 
+    /// The source from `start` to the current position.
+    fn text_from(&self, start: Cursor) -> &'a str {
+        &self.source[start.byte..self.pos.byte]
+    }
+
+    /// A `#!/usr/bin/env lyra` first line lets a script be run directly.
+    fn skip_shebang(&mut self) {
+        if self.pos.byte != 0 || self.base != 0 || self.peek() != '#' || self.peek_next() != '!' {
+            return;
+        }
+        while !self.is_at_end() && self.peek() != '\n' {
+            self.advance();
+        }
+        self.push_trivia(TriviaKind::Comment, Cursor::default());
+    }
+
     fn skip_whitespace_and_comments(&mut self) {
         loop {
             if self.is_at_end() {
@@ -189,25 +225,63 @@ impl Lexer {
             }
             match self.peek() {
                 ' ' | '\t' | '\r' | '\n' => {
-                    self.advance();
+                    let ws_start = self.pos;
+                    while !self.is_at_end() && matches!(self.peek(), ' ' | '\t' | '\r' | '\n') {
+                        self.advance();
+                    }
+                    self.push_trivia(TriviaKind::Whitespace, ws_start);
                 }
                 '-' if self.peek_next() == '-' => {
                     // Line comment: skip to end of line
+                    let comment_start = self.pos;
                     while !self.is_at_end() && self.peek() != '\n' {
                         self.advance();
                     }
+                    // `--- text` is a doc comment for the next declaration
+                    if let Some(text) = self.text_from(comment_start).strip_prefix("---") {
+                        let text = text.strip_prefix(' ').unwrap_or(text);
+                        self.pending_doc.push(text.trim_end().to_string());
+                        self.push_trivia(TriviaKind::DocComment, comment_start);
+                    } else {
+                        self.push_trivia(TriviaKind::Comment, comment_start);
+                    }
                 }
                 _ => break,
             }
         }
     }
 
+    fn push_trivia(&mut self, kind: TriviaKind, start: Cursor) {
+        let span = self.span(start, self.pos);
+        if let Some(trivia) = &mut self.trivia {
+            trivia.push(Trivia { kind, span });
+        }
+    }
+
+    /// Whether only blanks precede the current token on its line.
+    fn at_line_start(&self) -> bool {
+        let before = self.source[..self.start.byte].trim_end_matches([' ', '\t', '\r']);
+        before.is_empty() || before.ends_with('\n')
+    }
+
+    fn take_doc(&mut self) -> Option<String> {
+        if self.pending_doc.is_empty() {
+            None
+        } else {
+            Some(std::mem::take(&mut self.pending_doc).join("\n"))
+        }
+    }
+
     fn make_token(&self, kind: TokenKind) -> Token {
-        Token::new(kind, Span::new(self.start, self.pos))
+        Token::new(kind, self.span(self.start, self.pos))
     }
 
     fn current_span(&self) -> Span {
-        Span::new(self.start, self.pos)
+        self.span(self.start, self.pos)
+    }
+
+    fn span(&self, start: Cursor, end: Cursor) -> Span {
+        Span::new(self.base + start.char, self.base + end.char)
     }
 
     fn scan_string(&mut self) -> Result<Token, LyraError> {
@@ -245,20 +319,13 @@ impl Lexer {
                     current_lit.clear();
                 }
                 // Extract the source text inside {...} (tracking brace nesting)
+                let expr_start = self.pos;
                 let mut depth = 1;
-                let mut expr_src = String::new();
                 while !self.is_at_end() && depth > 0 {
-                    let c = self.advance();
-                    if c == '{' {
-                        depth += 1;
-                        expr_src.push(c);
-                    } else if c == '}' {
-                        depth -= 1;
-                        if depth > 0 {
-                            expr_src.push(c);
-                        }
-                    } else {
-                        expr_src.push(c);
+                    match self.advance() {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
                     }
                 }
                 if depth > 0 {
@@ -266,15 +333,25 @@ impl Lexer {
                         span: self.current_span(),
                     });
                 }
+                // Everything up to the closing `}`
+                let expr_src = &self.text_from(expr_start)[..self.pos.byte - expr_start.byte - 1];
+                let (expr_src, spec) = split_format_spec(expr_src);
+                if spec.as_ref().is_some_and(|spec| !spec.within_limits()) {
+                    return Err(LyraError::FormatTooWide {
+                        limit: MAX_FORMAT_WIDTH,
+                        span: self.span(expr_start, self.pos),
+                    });
+                }
                 // Lex the expression source
-                let mut inner_lexer = Lexer::new(&expr_src);
+                let mut inner_lexer = Lexer::new(expr_src);
+                inner_lexer.base = self.base + expr_start.char;
                 let inner_tokens = inner_lexer.tokenize().map_err(|errs| errs[0].clone())?;
                 // Remove the trailing Eof token
                 let inner_tokens: Vec<_> = inner_tokens
                     .into_iter()
                     .filter(|t| !matches!(t.kind, TokenKind::Eof))
                     .collect();
-                parts.push(token::InterpPart::Tokens(inner_tokens));
+                parts.push(token::InterpPart::Tokens(inner_tokens, spec));
             } else {
                 current_lit.push(ch);
             }
@@ -299,7 +376,7 @@ impl Lexer {
         }
     }
 
-    fn scan_number(&mut self, first: char) -> Token {
+    fn scan_number(&mut self, first: char) -> Result<Token, LyraError> {
         let mut num_str = String::from(first);
         let mut is_float = false;
 
@@ -316,22 +393,44 @@ impl Lexer {
             }
         }
 
+        // Exponent: `1e9`, `2.5e-3`, `1E+6`
+        if !self.is_at_end() && matches!(self.peek(), 'e' | 'E') {
+            is_float = true;
+            num_str.push(self.advance());
+            if matches!(self.peek(), '+' | '-') {
+                num_str.push(self.advance());
+            }
+            if !self.peek().is_ascii_digit() {
+                // Take the rest of the word so `1ex` is one error, not two
+                while !self.is_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_') {
+                    num_str.push(self.advance());
+                }
+                return Err(LyraError::MalformedNumber {
+                    text: num_str,
+                    span: self.current_span(),
+                });
+            }
+            while !self.is_at_end() && self.peek().is_ascii_digit() {
+                num_str.push(self.advance());
+            }
+        }
+
         if is_float {
             let val: f64 = num_str.parse().unwrap_or(0.0);
-            self.make_token(TokenKind::FloatLit(val))
+            Ok(self.make_token(TokenKind::FloatLit(val)))
         } else {
             let val: i64 = num_str.parse().unwrap_or(0);
-            self.make_token(TokenKind::IntLit(val))
+            Ok(self.make_token(TokenKind::IntLit(val)))
         }
     }
 
-    fn scan_identifier(&mut self, first: char) -> Token {
-        let mut ident = String::from(first);
+    fn scan_identifier(&mut self) -> Token {
         while !self.is_at_end() && (self.peek().is_alphanumeric() || self.peek() == '_') {
-            ident.push(self.advance());
+            self.advance();
         }
 
-        let kind = match ident.as_str() {
+        let ident = self.text_from(self.start);
+        let kind = match ident {
             "let" => TokenKind::Let,
             "in" => TokenKind::In,
             "fn" => TokenKind::Fn,
@@ -345,13 +444,94 @@ impl Lexer {
             "import" => TokenKind::Import,
             "true" => TokenKind::BoolLit(true),
             "false" => TokenKind::BoolLit(false),
-            _ => TokenKind::Ident(ident),
+            _ => TokenKind::Ident(ident.to_string()),
         };
 
         self.make_token(kind)
     }
 }
 
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, LyraError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        self.skip_shebang();
+        self.skip_whitespace_and_comments();
+        if self.is_at_end() {
+            self.done = true;
+            return Some(Ok(Token::new(TokenKind::Eof, self.span(self.pos, self.pos))));
+        }
+
+        let doc = self.take_doc();
+        self.start = self.pos;
+        let line_start = self.at_line_start();
+        let first = self.advance();
+        Some(self.scan_token(first).map(|mut token| {
+            token.doc = doc;
+            token.line_start = line_start;
+            token
+        }))
+    }
+}
+
 pub fn tokenize(source: &str) -> Result<Vec<Token>, Vec<LyraError>> {
     Lexer::new(source).tokenize()
 }
+
+/// Tokenize without losing anything: whitespace and comments come back as
+/// trivia, interleaved with the tokens in source order, so concatenating every
+/// piece's text reproduces `source` exactly. For formatters and refactoring tools.
+pub fn tokenize_lossless(source: &str) -> Result<Vec<TokenOrTrivia>, Vec<LyraError>> {
+    let mut lexer = Lexer::new(source);
+    lexer.trivia = Some(Vec::new());
+    let tokens = lexer.tokenize()?;
+    let mut trivia = lexer.trivia.unwrap_or_default().into_iter().peekable();
+
+    let mut pieces = Vec::new();
+    for token in tokens {
+        while let Some(t) = trivia.next_if(|t| t.span.start < token.span.start) {
+            pieces.push(TokenOrTrivia::Trivia(t));
+        }
+        pieces.push(TokenOrTrivia::Token(token));
+    }
+    pieces.extend(trivia.map(TokenOrTrivia::Trivia));
+    Ok(pieces)
+}
+
+/// Split `expr:spec` inside an interpolation at the last `:` outside any
+/// brackets or strings, if what follows is a valid format spec. `::` is
+/// the cons operator, not a separator.
+fn split_format_spec(src: &str) -> (&str, Option<FormatSpec>) {
+    let chars: Vec<(usize, char)> = src.char_indices().collect();
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut split = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i].1;
+        if in_string {
+            match c {
+                '\\' => i += 1,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else {
+            match c {
+                '"' => in_string = true,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ':' if chars.get(i + 1).is_some_and(|&(_, n)| n == ':') => i += 1,
+                ':' if depth == 0 => split = Some(chars[i].0),
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    match split.and_then(|at| Some((at, FormatSpec::parse(&src[at + 1..])?))) {
+        Some((at, spec)) => (&src[..at], Some(spec)),
+        None => (src, None),
+    }
+}
@@ -1,10 +1,12 @@
+use crate::ast::FormatSpec;
 use crate::span::Span;
 
 /// A part of an interpolated string at the token level.
 #[derive(Debug, Clone, PartialEq)]
 pub enum InterpPart {
     Literal(String),
-    Tokens(Vec<Token>),
+    /// The tokens of `{expr}`, and the format spec after a `:` if any.
+    Tokens(Vec<Token>, Option<FormatSpec>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -37,9 +39,12 @@ pub enum TokenKind {
     Arrow,      // ->
     Pipe,       // |
     PipeRight,  // |>
+    PipeTap,    // |>!
     Plus,       // +
+    PlusPlus,   // ++
     Minus,      // -
     Star,       // *
+    StarStar,   // **
     Slash,      // /
     Percent,    // %
     Lt,         // <
@@ -55,6 +60,7 @@ pub enum TokenKind {
     ColonColon, // ::
     Comma,      // ,
     Dot,        // .
+    QuestionDot, // ?.
     Underscore, // _
 
     // Delimiters
@@ -93,9 +99,12 @@ impl TokenKind {
             TokenKind::Arrow => "'->'",
             TokenKind::Pipe => "'|'",
             TokenKind::PipeRight => "'|>'",
+            TokenKind::PipeTap => "'|>!'",
             TokenKind::Plus => "'+'",
+            TokenKind::PlusPlus => "'++'",
             TokenKind::Minus => "'-'",
             TokenKind::Star => "'*'",
+            TokenKind::StarStar => "'**'",
             TokenKind::Slash => "'/'",
             TokenKind::Percent => "'%'",
             TokenKind::Lt => "'<'",
@@ -111,6 +120,7 @@ impl TokenKind {
             TokenKind::ColonColon => "'::'",
             TokenKind::Comma => "','",
             TokenKind::Dot => "'.'",
+            TokenKind::QuestionDot => "'?.'",
             TokenKind::Underscore => "'_'",
             TokenKind::LParen => "'('",
             TokenKind::RParen => "')'",
@@ -127,10 +137,51 @@ impl TokenKind {
 pub struct Token {
     pub kind: TokenKind,
     pub span: Span,
+    /// Text of the `---` doc comment lines directly preceding this token.
+    pub doc: Option<String>,
+    /// The first token on its line.
+    pub line_start: bool,
 }
 
 impl Token {
     pub fn new(kind: TokenKind, span: Span) -> Self {
-        Token { kind, span }
+        Token {
+            kind,
+            span,
+            doc: None,
+            line_start: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// `-- ...` up to the end of the line.
+    Comment,
+    /// `--- ...`, attached to the next declaration.
+    DocComment,
+}
+
+/// Source text between tokens that the parser doesn't see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub span: Span,
+}
+
+/// One piece of a lossless token stream; the spans tile the whole source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenOrTrivia {
+    Token(Token),
+    Trivia(Trivia),
+}
+
+impl TokenOrTrivia {
+    pub fn span(&self) -> Span {
+        match self {
+            TokenOrTrivia::Token(t) => t.span,
+            TokenOrTrivia::Trivia(t) => t.span,
+        }
     }
 }
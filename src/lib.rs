@@ -1,17 +1,34 @@
+pub mod analysis;
 pub mod ast;
+pub mod bench;
+pub mod budget;
+pub mod build;
 pub mod compiler;
+pub mod coverage;
+pub mod debugger;
+pub mod diagnostic;
+pub mod doc;
 pub mod error;
+pub mod incremental;
 pub mod eval;
+pub mod highlight;
+pub mod hooks;
+pub mod js;
 pub mod lexer;
+pub mod lint;
+pub mod macros;
 pub mod parser;
 pub mod repl;
+pub mod selftest;
 pub mod span;
 pub mod stdlib;
+pub mod testing;
 pub mod types;
 pub mod vm;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::rc::Rc;
 
 use error::LyraError;
 use eval::env::Env;
@@ -20,7 +37,7 @@ use types::infer::Inferencer;
 use types::TypeVarGen;
 
 /// Resolve an import path relative to the current file.
-fn resolve_import(current_file: &str, import_path: &str) -> String {
+pub(crate) fn resolve_import(current_file: &str, import_path: &str) -> String {
     let base = Path::new(current_file)
         .parent()
         .unwrap_or_else(|| Path::new("."));
@@ -31,27 +48,101 @@ fn resolve_import(current_file: &str, import_path: &str) -> String {
     resolved.to_string_lossy().to_string()
 }
 
+/// How to run a program.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Deny ambient capabilities, for evaluating untrusted snippets: `import`
+    /// and the file builtins can't touch the disk. Output still goes to stdout.
+    pub sandbox: bool,
+    /// Step and time limits; exceeding one stops the run with `BudgetExceeded`.
+    pub budget: budget::Budget,
+    /// Log every unification, instantiation and generalization to stderr.
+    pub debug_types: bool,
+    /// The builtins the program can use; `Prelude::Only(vec![])` gives a
+    /// bare environment for embedding and benchmarks.
+    pub prelude: stdlib::Prelude,
+    /// Values the embedding host provides, by name, for the program's
+    /// `extern` declarations. A program only sees the ones it declares.
+    pub host: Vec<(String, eval::value::Value)>,
+    /// How deeply expressions, patterns and types may nest, for generated
+    /// code; `None` is `parser::DEFAULT_MAX_DEPTH`.
+    pub max_nesting: Option<usize>,
+    /// The program's command-line arguments, which `args()` returns.
+    pub args: Vec<String>,
+    /// Callbacks the host runs on the program's calls, returns and steps.
+    pub hooks: hooks::Hooks,
+}
+
+impl RunOptions {
+    /// Run `f` with the parsers it makes allowing `max_nesting`.
+    fn nesting<T>(&self, f: impl FnOnce() -> T) -> T {
+        parser::with_max_depth(self.max_nesting.unwrap_or(parser::DEFAULT_MAX_DEPTH), f)
+    }
+}
+
 /// Run a Lyra source file using the tree-walking interpreter.
 pub fn run_file(source: &str, filename: &str) -> Result<(), LyraError> {
+    run_file_with(source, filename, &RunOptions::default())
+}
+
+/// Run a Lyra source file using the tree-walking interpreter, with options.
+/// Errors are printed to stderr as they're found, each once, and the first
+/// is returned.
+pub fn run_file_with(source: &str, filename: &str, options: &RunOptions) -> Result<(), LyraError> {
     let mut imported = HashSet::new();
-    run_file_inner(source, filename, &mut imported)
+    budget::with_budget(options.budget, || {
+        eval::builtins::with_sandbox(options.sandbox, || {
+            eval::builtins::with_args(&options.args, || {
+                hooks::with_hooks(&options.hooks, || {
+                    options.nesting(|| run_file_inner(source, filename, options, &mut imported))
+                })
+            })
+        })
+    })
 }
 
 fn run_file_inner(
     source: &str,
     filename: &str,
+    options: &RunOptions,
     imported: &mut HashSet<String>,
 ) -> Result<(), LyraError> {
-    let tokens = lexer::tokenize(source).map_err(|errs| errs[0].clone())?;
-    let decls = parser::parse(tokens)?;
+    let imports = if options.sandbox { Imports::Deny(SANDBOXED) } else { Imports::Keep };
+    let ast::Program { mut ast, decls } = parse_file(source, filename, imports)
+        .map_err(|e| report(e, source, filename))?
+        .program;
+    eval::resolve::resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
 
     let mut type_env = TypeEnv::new();
     let runtime_env = Env::new();
     let mut gen = TypeVarGen::new();
     let mut inferencer = Inferencer::new();
+    if options.debug_types {
+        inferencer.enable_trace();
+    }
 
-    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+    stdlib::register_prelude(&mut type_env, &runtime_env, &mut gen, &options.prelude)
+        .map_err(|message| report(prelude_error(message), source, filename))?;
+    for (name, value) in &options.host {
+        runtime_env.set(name.clone(), value.clone());
+    }
 
+    // A missing host value stops the program before any of it runs
+    for decl in &decls {
+        if let ast::Decl::Extern { name, type_ann, .. } = decl {
+            let value = runtime_env.get(&name.node);
+            if let Err(e) =
+                eval::check_extern(&name.node, type_ann.node.arity(), value.as_ref(), name.span)
+            {
+                return Err(report(e, source, filename));
+            }
+        }
+    }
+
+    // Checking goes on past a declaration that fails, to report every
+    // error in one run, but nothing more is evaluated
+    let mut failed = None;
     for decl in &decls {
         // Handle imports by loading the file and evaluating it
         if let ast::Decl::Import { path, span } = decl {
@@ -61,84 +152,508 @@ fn run_file_inner(
             }
             imported.insert(resolved.clone());
             let import_source = std::fs::read_to_string(&resolved).map_err(|e| {
-                LyraError::RuntimeError {
-                    message: format!("cannot import \"{}\": {}", path, e),
-                    span: *span,
-                }
+                let message = format!("cannot import \"{}\": {}", path, e);
+                report(LyraError::RuntimeError { message, span: *span }, source, filename)
             })?;
             // Parse and evaluate the imported file in the same environments
-            let import_tokens =
-                lexer::tokenize(&import_source).map_err(|errs| errs[0].clone())?;
-            let import_decls = parser::parse(import_tokens)?;
+            let ast::Program {
+                ast: mut import_ast,
+                decls: import_decls,
+            } = parse_file(&import_source, &resolved, Imports::Keep)
+                .map_err(|e| report(e, &import_source, &resolved))?
+                .program;
+            eval::resolve::resolve_program(&mut import_ast, &import_decls);
+            let import_ast = Rc::new(import_ast);
             for import_decl in &import_decls {
-                if let Err(e) = inferencer.infer_decl(&mut type_env, import_decl) {
+                let inferred = inferencer.infer_decl(&mut type_env, &import_ast, import_decl);
+                print_trace(&mut inferencer, &import_source, &resolved);
+                print_warnings(&mut inferencer, &import_source, &resolved);
+                if let Err(e) = inferred {
                     eprintln!("{}", e.render(&import_source, &resolved));
-                    return Err(e);
+                    inferencer.recover_decl(&mut type_env, &import_ast, import_decl);
+                    failed.get_or_insert(e);
+                    continue;
                 }
-                if let Err(e) = eval::eval_decl(&runtime_env, import_decl) {
-                    eprintln!("{}", e.render(&import_source, &resolved));
-                    return Err(e);
+                if failed.is_some() {
+                    continue;
+                }
+                if let Err(e) = eval::eval_decl(&runtime_env, &import_ast, import_decl) {
+                    return Err(report(e, &import_source, &resolved));
                 }
             }
             continue;
         }
 
-        if let Err(e) = inferencer.infer_decl(&mut type_env, decl) {
+        let inferred = inferencer.infer_decl(&mut type_env, &ast, decl);
+        print_trace(&mut inferencer, source, filename);
+        print_warnings(&mut inferencer, source, filename);
+        if let Err(e) = inferred {
             eprintln!("{}", e.render(source, filename));
-            return Err(e);
+            inferencer.recover_decl(&mut type_env, &ast, decl);
+            failed.get_or_insert(e);
+            continue;
         }
-        if let Err(e) = eval::eval_decl(&runtime_env, decl) {
-            eprintln!("{}", e.render(source, filename));
-            return Err(e);
+        if failed.is_some() {
+            continue;
+        }
+        if let Err(e) = eval::eval_decl(&runtime_env, &ast, decl) {
+            return Err(report(e, source, filename));
         }
     }
 
-    Ok(())
+    failed.map_or(Ok(()), Err)
 }
 
 /// Run a Lyra source file using the bytecode compiler + VM.
 pub fn run_file_vm(source: &str, filename: &str) -> Result<(), LyraError> {
-    let tokens = lexer::tokenize(source).map_err(|errs| errs[0].clone())?;
-    let mut decls = parser::parse(tokens)?;
+    run_file_vm_with(source, filename, &RunOptions::default())
+}
 
-    // Resolve imports: inline imported file declarations
-    let mut imported = HashSet::new();
-    resolve_imports(&mut decls, filename, &mut imported)?;
+/// Run a Lyra source file using the bytecode compiler + VM, with options.
+/// Errors are printed as with [`run_file_with`].
+pub fn run_file_vm_with(
+    source: &str,
+    filename: &str,
+    options: &RunOptions,
+) -> Result<(), LyraError> {
+    let program = compile_file_with(source, filename, options)?;
+    run_program(&program, options)
+}
+
+/// Parse, check and compile a source file and its imports, for running
+/// later with [`run_program`] or saving as a `.lyrc` file. Errors are
+/// printed as they're found, as when running.
+pub fn compile_file_with(
+    source: &str,
+    filename: &str,
+    options: &RunOptions,
+) -> Result<compiler::lyrc::Program, LyraError> {
+    options.nesting(|| compile_file_inner(source, filename, options))
+}
+
+fn compile_file_inner(
+    source: &str,
+    filename: &str,
+    options: &RunOptions,
+) -> Result<compiler::lyrc::Program, LyraError> {
+    let imports = if options.sandbox { Imports::Deny(SANDBOXED) } else { Imports::Inline };
+    let ast::Program { ast, decls } = parse_file(source, filename, imports)
+        .map_err(|e| report(e, source, filename))?
+        .program;
 
     // Type check
     let mut type_env = TypeEnv::new();
     let runtime_env = Env::new();
     let mut gen = TypeVarGen::new();
     let mut inferencer = Inferencer::new();
+    if options.debug_types {
+        inferencer.enable_trace();
+    }
 
-    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+    stdlib::register_prelude(&mut type_env, &runtime_env, &mut gen, &options.prelude)
+        .map_err(|message| report(prelude_error(message), source, filename))?;
 
+    // Every declaration is checked, so all the errors are reported
+    let mut failed = None;
     for decl in &decls {
-        if let Err(e) = inferencer.infer_decl(&mut type_env, decl) {
+        let inferred = inferencer.infer_decl(&mut type_env, &ast, decl);
+        print_trace(&mut inferencer, source, filename);
+        print_warnings(&mut inferencer, source, filename);
+        if let Err(e) = inferred {
             eprintln!("{}", e.render(source, filename));
-            return Err(e);
+            inferencer.recover_decl(&mut type_env, &ast, decl);
+            failed.get_or_insert(e);
         }
     }
+    if let Some(e) = failed {
+        return Err(e);
+    }
 
     // Compile to bytecode
-    let proto = compiler::compile(&decls).map_err(|msg| LyraError::RuntimeError {
-        message: msg,
+    let main = compiler::compile(&ast, &decls).map_err(|msg| {
+        let e = LyraError::RuntimeError {
+            message: msg,
+            span: span::Span::default(),
+        };
+        report(e, source, filename)
+    })?;
+    Ok(compiler::lyrc::Program {
+        filename: filename.to_string(),
+        source: source.to_string(),
+        main,
+    })
+}
+
+/// Run a compiled program on the VM, printing a runtime error against the
+/// source it was compiled from.
+pub fn run_program(
+    program: &compiler::lyrc::Program,
+    options: &RunOptions,
+) -> Result<(), LyraError> {
+    let mut machine = vm::VM::new();
+    stdlib::register_vm_prelude(&mut machine, &options.prelude);
+    for (name, value) in &options.host {
+        machine.define_global(name.clone(), value.clone());
+    }
+    let main = program.main.clone();
+    let result = budget::with_budget(options.budget, || {
+        eval::builtins::with_sandbox(options.sandbox, || {
+            eval::builtins::with_args(&options.args, || {
+                hooks::with_hooks(&options.hooks, || machine.run(main))
+            })
+        })
+    });
+    if let Err(e) = result {
+        return Err(report(e, &program.source, &program.filename));
+    }
+
+    Ok(())
+}
+
+/// Check a file and lower it to a standalone JavaScript program; see `js`.
+/// Warnings are printed; errors are returned for the caller to render.
+pub fn emit_js(source: &str, filename: &str) -> Result<String, LyraError> {
+    let ast::Program { ast, decls } = parse_file(source, filename, Imports::Inline)?.program;
+
+    let mut type_env = TypeEnv::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    inferencer.enable_expr_types();
+    stdlib::register_stdlib(&mut type_env, &Env::new(), &mut gen);
+
+    for decl in &decls {
+        let inferred = inferencer.infer_decl(&mut type_env, &ast, decl);
+        print_warnings(&mut inferencer, source, filename);
+        inferred?;
+    }
+
+    js::emit(&ast, &decls, inferencer.expr_types(), &type_env, filename)
+}
+
+/// Check and evaluate a snippet in a fresh environment with the full stdlib,
+/// returning the value of its last expression (`()` if it has none). A
+/// snippet can't import, so it never reads other files; the steps it takes
+/// count against the current budget.
+pub fn eval_source(source: &str) -> Result<eval::value::Value, LyraError> {
+    let imports = Imports::Deny(EVAL_IMPORTS);
+    let ast::Program { mut ast, decls } = parse_file(source, "<eval>", imports)?.program;
+    eval::resolve::resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
+
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+
+    let mut last = eval::value::Value::Unit;
+    for decl in &decls {
+        inferencer.infer_decl(&mut type_env, &ast, decl)?;
+        if let Some(value) = eval::eval_decl(&runtime_env, &ast, decl)? {
+            last = value;
+        }
+    }
+    Ok(last)
+}
+
+/// [`eval_source`] on the VM, which backs the `eval` builtin: recursing
+/// too deep is an error there rather than a crash.
+pub fn eval_source_vm(source: &str) -> Result<eval::value::Value, LyraError> {
+    let imports = Imports::Deny(EVAL_IMPORTS);
+    let ast::Program { ast, decls } = parse_file(source, "<eval>", imports)?.program;
+
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+    for decl in &decls {
+        inferencer.infer_decl(&mut type_env, &ast, decl)?;
+    }
+    let main = compiler::compile(&ast, &decls).map_err(|message| LyraError::RuntimeError {
+        message,
         span: span::Span::default(),
     })?;
 
-    // Execute on VM
     let mut machine = vm::VM::new();
     stdlib::register_vm_stdlib(&mut machine);
-    if let Err(e) = machine.run(proto) {
-        eprintln!("{}", e.render(source, filename));
+    eval::with_own_vm_globals(|| machine.run(main))
+}
+
+/// Print `e` to stderr against the file it was found in, for the functions
+/// that report errors as they go, and hand it back to return.
+fn report(e: LyraError, source: &str, filename: &str) -> LyraError {
+    eprintln!("{}", e.render(source, filename));
+    e
+}
+
+fn prelude_error(message: String) -> LyraError {
+    LyraError::RuntimeError {
+        message,
+        span: span::Span::default(),
+    }
+}
+
+/// Print the inference steps recorded for `--debug-types` to stderr.
+fn print_trace(inferencer: &mut Inferencer, source: &str, filename: &str) {
+    let events = inferencer.take_trace();
+    if events.is_empty() {
+        return;
+    }
+    let lines = span::LineIndex::new(source);
+    for event in events {
+        eprintln!("{}:{}", filename, event.render(&lines));
+    }
+}
+
+/// Print the warnings found while checking a declaration to stderr.
+fn print_warnings(inferencer: &mut Inferencer, source: &str, filename: &str) {
+    for warning in inferencer.take_warnings() {
+        eprintln!("{}", warning.render(source, filename, diagnostic::Format::Ansi));
+    }
+}
+
+/// Why a sandboxed program can't import.
+const SANDBOXED: &str = "imports are disabled in sandbox mode";
+/// Why an `eval` snippet can't import.
+const EVAL_IMPORTS: &str = "eval can't import files";
+
+/// Sandboxed programs and `eval` snippets can't read other files.
+fn deny_imports(ast: &ast::Ast, decls: &[ast::Decl], reason: &str) -> Result<(), LyraError> {
+    struct FindImport(Option<(String, span::Span)>);
+    impl ast::visit::Visitor for FindImport {
+        fn visit_expr(&mut self, ast: &ast::Ast, expr: ast::ExprId) {
+            match &ast[expr].node {
+                ast::Expr::Import(path) => {
+                    self.0.get_or_insert((path.clone(), ast[expr].span));
+                }
+                _ => ast::visit::walk_expr(self, ast, expr),
+            }
+        }
+    }
+
+    let mut found = FindImport(decls.iter().find_map(|d| match d {
+        ast::Decl::Import { path, span } => Some((path.clone(), *span)),
+        _ => None,
+    }));
+    ast::visit::walk_program(&mut found, ast, decls);
+    match found.0 {
+        Some((path, span)) => Err(LyraError::RuntimeError {
+            message: format!("cannot import \"{}\": {}", path, reason),
+            span,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// What [`parse_file`] does with a file's `import` declarations.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Imports {
+    /// Leave them for the caller, which takes each file on its own.
+    Keep,
+    /// Put each imported file's declarations in its place.
+    Inline,
+    /// Refuse them, and `import` expressions too, for the reason given.
+    Deny(&'static str),
+}
+
+/// A file through the front end.
+pub(crate) struct Parsed {
+    pub program: ast::Program,
+    /// How many declarations at the front come from its modules.
+    pub hoisted: usize,
+    /// The files its `import` expressions loaded, which it depends on too.
+    pub modules: Vec<String>,
+}
+
+/// The front end every command shares: tokenize, parse and expand the
+/// macros of `source`, deal with its `import` declarations as `imports`
+/// says, then resolve its `import` expressions to modules.
+pub(crate) fn parse_file(
+    source: &str,
+    filename: &str,
+    imports: Imports,
+) -> Result<Parsed, LyraError> {
+    let tokens = lexer::tokenize(source).map_err(|errs| errs[0].clone())?;
+    let ast::Program { mut ast, mut decls } = parser::parse(tokens)?;
+    macros::expand_macros(&mut ast, &decls)?;
+    match imports {
+        Imports::Keep => {}
+        Imports::Inline => resolve_imports(&mut ast, &mut decls, filename, &mut HashSet::new())?,
+        Imports::Deny(reason) => deny_imports(&ast, &decls, reason)?,
+    }
+    let own = decls.len();
+    let modules = resolve_modules(&mut ast, &mut decls, filename)?;
+    Ok(Parsed {
+        hoisted: decls.len() - own,
+        program: ast::Program { ast, decls },
+        modules,
+    })
+}
+
+/// Replace each `import "path"` expression with the module it names. Each
+/// file becomes a declaration at the front of the program binding a hidden
+/// name to its top-level lets, nested in order, ending in a record of them,
+/// and the imports become that name. So a module is checked and run once,
+/// before the program, with nothing but the prelude and its own imports in
+/// scope. Its type declarations move to the front too. Returns the files
+/// loaded.
+pub(crate) fn resolve_modules(
+    ast: &mut ast::Ast,
+    decls: &mut Vec<ast::Decl>,
+    current_file: &str,
+) -> Result<Vec<String>, LyraError> {
+    let mut resolver = ModuleResolver {
+        current_file: current_file.to_string(),
+        loading: vec![current_file.to_string()],
+        loaded: HashMap::new(),
+        types: Vec::new(),
+        modules: Vec::new(),
+        error: None,
+    };
+    ast::visit::walk_program_mut(&mut resolver, ast, decls);
+    if let Some(e) = resolver.error {
         return Err(e);
     }
+    decls.splice(0..0, resolver.types.into_iter().chain(resolver.modules));
+    Ok(resolver.loaded.into_keys().collect())
+}
 
-    Ok(())
+struct ModuleResolver {
+    current_file: String,
+    /// Files being loaded, innermost last, to report import cycles.
+    loading: Vec<String>,
+    /// The name bound to each file loaded so far.
+    loaded: HashMap<String, String>,
+    /// Type and extern declarations collected from the modules.
+    types: Vec<ast::Decl>,
+    /// The declarations binding the modules, each after those it imports.
+    modules: Vec<ast::Decl>,
+    error: Option<LyraError>,
+}
+
+impl ast::visit::VisitorMut for ModuleResolver {
+    fn visit_expr_mut(&mut self, ast: &mut ast::Ast, expr: ast::ExprId) {
+        if let ast::Expr::Import(path) = &ast[expr].node {
+            let (path, span) = (path.clone(), ast[expr].span);
+            match self.load(ast, &path, span) {
+                Ok(name) => ast[expr] = span::Spanned::new(ast::Expr::Var(name), span),
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                }
+            }
+        } else {
+            ast::visit::walk_expr_mut(self, ast, expr);
+        }
+    }
+}
+
+/// The name a module is bound to: its path, made an identifier, with a
+/// `#` no name in a program can have.
+fn module_name(resolved: &str) -> String {
+    let path: String = resolved
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("module#{}", path)
+}
+
+impl ModuleResolver {
+    /// Parse the module into `ast` unless it's loaded already, returning
+    /// the name it's bound to.
+    fn load(
+        &mut self,
+        ast: &mut ast::Ast,
+        path: &str,
+        span: span::Span,
+    ) -> Result<String, LyraError> {
+        use ast::{Decl, Expr};
+        use span::Spanned;
+
+        let resolved = resolve_import(&self.current_file, path);
+        if let Some(name) = self.loaded.get(&resolved) {
+            return Ok(name.clone());
+        }
+        if self.loading.contains(&resolved) {
+            return Err(LyraError::RuntimeError {
+                message: format!("cannot import \"{}\": it imports itself", path),
+                span,
+            });
+        }
+        let source = std::fs::read_to_string(&resolved).map_err(|e| LyraError::RuntimeError {
+            message: format!("cannot import \"{}\": {}", path, e),
+            span,
+        })?;
+        let tokens = lexer::tokenize(&source).map_err(|errs| errs[0].clone())?;
+        let mut decls = parser::parse_into(ast, tokens)?;
+        macros::expand_macros(ast, &decls)?;
+        resolve_imports(ast, &mut decls, &resolved, &mut HashSet::new())?;
+
+        // Modules the module imports resolve against its own file
+        let outer = std::mem::replace(&mut self.current_file, resolved.clone());
+        self.loading.push(resolved.clone());
+        ast::visit::walk_program_mut(self, ast, &decls);
+        self.loading.pop();
+        self.current_file = outer;
+
+        let mut exports: Vec<String> = Vec::new();
+        for decl in &decls {
+            if let Decl::Let { name, .. } = decl {
+                exports.retain(|n| n != &name.node);
+                exports.push(name.node.clone());
+            }
+        }
+        let fields = exports
+            .into_iter()
+            .map(|name| (name.clone(), ast.alloc(Expr::Var(name), span)))
+            .collect();
+        let mut module = Spanned::new(Expr::Record(fields), span);
+        let mut types = Vec::new();
+        for decl in decls.into_iter().rev() {
+            let (name, recursive, type_ann, value) = match decl {
+                Decl::Let {
+                    name,
+                    recursive,
+                    type_ann,
+                    body,
+                    ..
+                } => (name, recursive, type_ann, body),
+                // Top-level expressions still run, for their effects
+                Decl::Expr(expr) => (Spanned::new("_".to_string(), ast[expr].span), false, None, expr),
+                Decl::Type { .. } | Decl::Extern { .. } => {
+                    types.push(decl);
+                    continue;
+                }
+                Decl::Prop { .. } | Decl::Import { .. } | Decl::Macro { .. } => continue,
+            };
+            module = Spanned::new(
+                Expr::Let {
+                    name,
+                    recursive,
+                    type_ann,
+                    value,
+                    body: ast.alloc(module.node, module.span),
+                },
+                span,
+            );
+        }
+        self.types.extend(types.into_iter().rev());
+        let name = module_name(&resolved);
+        self.modules.push(Decl::Let {
+            name: Spanned::new(name.clone(), span),
+            recursive: false,
+            type_ann: None,
+            body: ast.alloc(module.node, module.span),
+            doc: None,
+        });
+        self.loaded.insert(resolved, name.clone());
+        Ok(name)
+    }
 }
 
 /// Inline import declarations by replacing them with the imported file's declarations.
-fn resolve_imports(
+pub(crate) fn resolve_imports(
+    ast: &mut ast::Ast,
     decls: &mut Vec<ast::Decl>,
     current_file: &str,
     imported: &mut HashSet<String>,
@@ -160,9 +675,10 @@ fn resolve_imports(
                 })?;
             let import_tokens =
                 lexer::tokenize(&import_source).map_err(|errs| errs[0].clone())?;
-            let mut import_decls = parser::parse(import_tokens)?;
+            let mut import_decls = parser::parse_into(ast, import_tokens)?;
+            macros::expand_macros(ast, &import_decls)?;
             // Recursively resolve imports in the imported file
-            resolve_imports(&mut import_decls, &resolved, imported)?;
+            resolve_imports(ast, &mut import_decls, &resolved, imported)?;
             // Replace the Import decl with the imported declarations
             decls.remove(i);
             for (j, d) in import_decls.into_iter().enumerate() {
@@ -0,0 +1,439 @@
+//! Style checks behind `lyra lint`.
+//!
+//! Each rule looks at a file's parsed declarations, before macro expansion,
+//! and reports what it finds at one of three [`Level`]s. A rule's level comes
+//! from the [`LintConfig`], which a `-- lint-file: deny(rule)` comment
+//! anywhere in the file overrides, which in turn a `-- lint: allow(rule)`
+//! comment overrides for the declaration right after it.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::analysis::{decl_span, pattern_binders};
+use crate::ast::visit::{walk_decl, walk_expr, walk_param, walk_pattern, Visitor};
+use crate::ast::{Ast, Decl, Expr, ExprId, LambdaParam, Pattern, Program, SpannedPattern};
+use crate::diagnostic::{Diagnostic, Label, Severity};
+use crate::error::LyraError;
+use crate::eval::env::Env;
+use crate::lexer::token::{TokenOrTrivia, TriviaKind};
+use crate::span::Span;
+use crate::types::env::TypeEnv;
+use crate::types::TypeVarGen;
+use crate::{lexer, parser, stdlib};
+
+/// A check `lyra lint` can run.
+#[derive(Debug)]
+pub struct Rule {
+    /// What configuration and comments call it, e.g. `unused-param`.
+    pub name: &'static str,
+    pub code: &'static str,
+    pub summary: &'static str,
+}
+
+/// Every rule, in code order.
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "long-pipe",
+        code: "L0001",
+        summary: "a `|>` chain with more stages than `max-pipe-chain`",
+    },
+    Rule {
+        name: "bool-match",
+        code: "L0002",
+        summary: "a `match` on `true` and `false`, which reads better as `if`",
+    },
+    Rule {
+        name: "shadowed-builtin",
+        code: "L0003",
+        summary: "a binding that hides a builtin of the same name",
+    },
+    Rule {
+        name: "unused-param",
+        code: "L0004",
+        summary: "a function parameter the body never uses; start it with `_` to keep it",
+    },
+];
+
+fn rule(name: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|r| r.name == name)
+}
+
+/// What to do with a rule's findings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Don't report them.
+    Allow,
+    /// Report them as warnings.
+    Warn,
+    /// Report them as errors, failing `lyra lint`.
+    Deny,
+}
+
+impl Level {
+    fn parse(s: &str) -> Option<Level> {
+        match s {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// Which rules run, and how strictly.
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    /// The most stages a `|>` chain may have before `long-pipe` fires.
+    pub max_pipe_chain: usize,
+    /// Rules set to something other than [`Level::Warn`].
+    levels: HashMap<&'static str, Level>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            max_pipe_chain: 5,
+            levels: HashMap::new(),
+        }
+    }
+}
+
+impl LintConfig {
+    /// Read a config file: one `key = value` per line, where a key is a rule
+    /// name with a level (`allow`, `warn` or `deny`) or `max-pipe-chain` with
+    /// a number. Blank lines and `--` comments are skipped.
+    pub fn parse(text: &str) -> Result<LintConfig, String> {
+        let mut config = LintConfig::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split("--").next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = value`", i + 1));
+            };
+            let (key, value) = (key.trim(), value.trim());
+            if key == "max-pipe-chain" {
+                config.max_pipe_chain = value
+                    .parse()
+                    .map_err(|_| format!("line {}: '{}' is not a number", i + 1, value))?;
+                continue;
+            }
+            let level = Level::parse(value).ok_or_else(|| {
+                format!(
+                    "line {}: '{}' is not a level; use allow, warn or deny",
+                    i + 1,
+                    value
+                )
+            })?;
+            config
+                .set_level(key, level)
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        }
+        Ok(config)
+    }
+
+    pub fn set_level(&mut self, rule_name: &str, level: Level) -> Result<(), String> {
+        let rule = rule(rule_name).ok_or_else(|| format!("unknown lint rule '{}'", rule_name))?;
+        self.levels.insert(rule.name, level);
+        Ok(())
+    }
+
+    pub fn level(&self, rule_name: &str) -> Level {
+        self.levels.get(rule_name).copied().unwrap_or(Level::Warn)
+    }
+}
+
+/// Lint `source`, returning what the enabled rules found in source order.
+/// Only a file that doesn't lex or parse is an error.
+pub fn lint(source: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, LyraError> {
+    let pieces = lexer::tokenize_lossless(source).map_err(|errs| errs[0].clone())?;
+    let tokens = pieces
+        .iter()
+        .filter_map(|p| match p {
+            TokenOrTrivia::Token(t) => Some(t.clone()),
+            TokenOrTrivia::Trivia(_) => None,
+        })
+        .collect();
+    let Program { ast, decls } = parser::parse(tokens)?;
+
+    let mut type_env = TypeEnv::new();
+    stdlib::register_stdlib(&mut type_env, &Env::new(), &mut TypeVarGen::new());
+    let mut checker = Checker {
+        builtins: type_env.names().into_iter().map(String::from).collect(),
+        max_pipe_chain: config.max_pipe_chain,
+        findings: Vec::new(),
+    };
+    for decl in &decls {
+        checker.visit_decl(&ast, decl);
+    }
+
+    // Each directive comment sets levels for the whole file, or for the
+    // declaration that starts after it
+    let chars: Vec<char> = source.chars().collect();
+    let mut file_levels = Vec::new();
+    let mut decl_levels = Vec::new();
+    let mut out = Vec::new();
+    for piece in &pieces {
+        let TokenOrTrivia::Trivia(trivia) = piece else {
+            continue;
+        };
+        if trivia.kind != TriviaKind::Comment {
+            continue;
+        }
+        let text: String = chars[trivia.span.start..trivia.span.end].iter().collect();
+        let text = text.trim_start_matches('-').trim();
+        let (directive, whole_file) = if let Some(rest) = text.strip_prefix("lint-file:") {
+            (rest, true)
+        } else if let Some(rest) = text.strip_prefix("lint:") {
+            (rest, false)
+        } else {
+            continue;
+        };
+        let scope = decls
+            .iter()
+            .map(|d| decl_span(&ast, d))
+            .find(|s| s.start >= trivia.span.end);
+        for item in parse_directive(directive) {
+            match item {
+                Ok((rule, level)) if whole_file => file_levels.push((rule, level)),
+                Ok((rule, level)) => {
+                    if let Some(scope) = scope {
+                        decl_levels.push((scope, rule, level));
+                    }
+                }
+                Err(message) => out.push(Diagnostic {
+                    severity: Severity::Warning,
+                    kind: "warning",
+                    code: "L0000",
+                    message,
+                    primary: Some(Label {
+                        span: trivia.span,
+                        message: "in this directive".to_string(),
+                    }),
+                    secondary: Vec::new(),
+                    suggestion: None,
+                }),
+            }
+        }
+    }
+
+    for finding in checker.findings {
+        let name = finding.rule.name;
+        let mut level = config.level(name);
+        for (rule, l) in &file_levels {
+            if *rule == name {
+                level = *l;
+            }
+        }
+        for (scope, rule, l) in &decl_levels {
+            if *rule == name && scope.start <= finding.span.start && finding.span.end <= scope.end
+            {
+                level = *l;
+            }
+        }
+        let (severity, kind) = match level {
+            Level::Allow => continue,
+            Level::Warn => (Severity::Warning, "warning"),
+            Level::Deny => (Severity::Error, "error"),
+        };
+        out.push(Diagnostic {
+            severity,
+            kind,
+            code: finding.rule.code,
+            message: format!("{} [{}]", finding.message, name),
+            primary: Some(Label {
+                span: finding.span,
+                message: finding.label,
+            }),
+            secondary: Vec::new(),
+            suggestion: None,
+        });
+    }
+    out.sort_by_key(|d| d.primary.as_ref().map(|l| l.span.start));
+    Ok(out)
+}
+
+/// The `allow(a, b) deny(c)` items after a directive's `lint:`.
+fn parse_directive(text: &str) -> Vec<Result<(&'static str, Level), String>> {
+    let mut items = Vec::new();
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let Some((level, after)) = rest.split_once('(') else {
+            items.push(Err(format!("expected `allow(rule)`, found '{}'", rest)));
+            break;
+        };
+        let Some((names, after)) = after.split_once(')') else {
+            items.push(Err("unclosed '(' in lint directive".to_string()));
+            break;
+        };
+        let Some(level) = Level::parse(level.trim()) else {
+            items.push(Err(format!(
+                "'{}' is not a level; use allow, warn or deny",
+                level.trim()
+            )));
+            break;
+        };
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            items.push(match rule(name) {
+                Some(rule) => Ok((rule.name, level)),
+                None => Err(format!("unknown lint rule '{}'", name)),
+            });
+        }
+        rest = after.trim_start_matches([',', ' ']);
+    }
+    items
+}
+
+struct Finding {
+    rule: &'static Rule,
+    span: Span,
+    message: String,
+    label: String,
+}
+
+/// Runs every rule over a program, collecting findings at every level.
+struct Checker {
+    builtins: HashSet<String>,
+    max_pipe_chain: usize,
+    findings: Vec<Finding>,
+}
+
+impl Checker {
+    fn report(&mut self, rule_name: &str, span: Span, message: String, label: &str) {
+        self.findings.push(Finding {
+            rule: rule(rule_name).expect("a rule in RULES"),
+            span,
+            message,
+            label: label.to_string(),
+        });
+    }
+
+    fn check_binding(&mut self, span: Span, name: &str) {
+        if self.builtins.contains(name) {
+            self.report(
+                "shadowed-builtin",
+                span,
+                format!("'{}' hides the builtin of the same name", name),
+                "bound here",
+            );
+        }
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_decl(&mut self, ast: &Ast, decl: &Decl) {
+        match decl {
+            // A template is checked where it is expanded
+            Decl::Macro { .. } => return,
+            Decl::Let { name, .. } => self.check_binding(name.span, &name.node),
+            _ => {}
+        }
+        walk_decl(self, ast, decl);
+    }
+
+    fn visit_expr(&mut self, ast: &Ast, id: ExprId) {
+        let expr = &ast[id];
+        match &expr.node {
+            Expr::Pipe { .. } => {
+                // Report a chain once, at its outermost `|>`
+                let mut stages = Vec::new();
+                let mut head = id;
+                while let Expr::Pipe { lhs, rhs } = &ast[head].node {
+                    stages.push(*rhs);
+                    head = *lhs;
+                }
+                if stages.len() > self.max_pipe_chain {
+                    self.report(
+                        "long-pipe",
+                        expr.span,
+                        format!(
+                            "pipe chain has {} stages, more than {}",
+                            stages.len(),
+                            self.max_pipe_chain
+                        ),
+                        "consider naming an intermediate result",
+                    );
+                }
+                self.visit_expr(ast, head);
+                for stage in stages.into_iter().rev() {
+                    self.visit_expr(ast, stage);
+                }
+                return;
+            }
+            Expr::Match { arms, .. } => {
+                let is_bool = |p: &SpannedPattern| matches!(p.node, Pattern::BoolLit(_));
+                if arms.len() == 2
+                    && arms.iter().any(|a| is_bool(&a.pattern))
+                    && arms
+                        .iter()
+                        .all(|a| is_bool(&a.pattern) || matches!(a.pattern.node, Pattern::Wildcard))
+                {
+                    self.report(
+                        "bool-match",
+                        expr.span,
+                        "match on a Bool".to_string(),
+                        "use `if ... then ... else ...` instead",
+                    );
+                }
+            }
+            Expr::Lambda { params, body } => {
+                for param in params {
+                    let name = &param.name.node;
+                    if !name.starts_with('_') && !uses(ast, *body, name) {
+                        self.report(
+                            "unused-param",
+                            param.name.span,
+                            format!("parameter '{}' is never used", name),
+                            "not used in the body",
+                        );
+                    }
+                }
+            }
+            Expr::Let { name, .. } => self.check_binding(name.span, &name.node),
+            _ => {}
+        }
+        walk_expr(self, ast, id);
+    }
+
+    fn visit_param(&mut self, param: &LambdaParam) {
+        self.check_binding(param.name.span, &param.name.node);
+        walk_param(self, param);
+    }
+
+    fn visit_pattern(&mut self, pattern: &SpannedPattern) {
+        if let Pattern::Var(name) = &pattern.node {
+            self.check_binding(pattern.span, name);
+        }
+        walk_pattern(self, pattern);
+    }
+}
+
+/// Whether `name`, as bound just outside `id`, is used in it.
+fn uses(ast: &Ast, id: ExprId, name: &str) -> bool {
+    match &ast[id].node {
+        Expr::Var(n) | Expr::Local { name: n, .. } => n == name,
+        Expr::Lambda { params, body } => {
+            !params.iter().any(|p| p.name.node == name) && uses(ast, *body, name)
+        }
+        Expr::Let {
+            name: bound,
+            recursive,
+            value,
+            body,
+            ..
+        } => {
+            let shadows = bound.node == name;
+            (!(shadows && *recursive) && uses(ast, *value, name))
+                || (!shadows && uses(ast, *body, name))
+        }
+        Expr::Match { scrutinee, arms } => {
+            uses(ast, *scrutinee, name)
+                || arms.iter().any(|arm| {
+                    let mut bound = Vec::new();
+                    pattern_binders(&arm.pattern, &mut bound);
+                    !bound.iter().any(|(_, n)| n == name) && uses(ast, arm.body, name)
+                })
+        }
+        node => node.children().into_iter().any(|c| uses(ast, c, name)),
+    }
+}
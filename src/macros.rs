@@ -0,0 +1,462 @@
+//! Macros, expanded between parsing and type checking.
+//!
+//! `macro when(cond, body) = quote if unquote(cond) then unquote(body) else ()`
+//! declares a template. A later call `when(x > 0, println("positive"))` is
+//! replaced by the template with each `unquote(param)` swapped for the
+//! argument's syntax, so the arguments are checked and run only where the
+//! template puts them.
+//!
+//! Expansion is hygienic for the names a template binds: its `let`s, lambda
+//! parameters and pattern variables get fresh names on every expansion, so
+//! they never capture a variable used in an argument. Names the template uses
+//! without binding refer to the top-level definitions, as they would where
+//! the macro is declared: a variable the caller binds under the same name is
+//! renamed so it can't capture them. A macro is visible to the declarations
+//! after it in the same file or REPL session.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::visit::{walk_expr_mut, VisitorMut};
+use crate::ast::*;
+use crate::error::LyraError;
+use crate::eval::quote::unquotes;
+use crate::span::{Span, Spanned};
+
+/// How deeply expansions may nest before a macro is taken to expand into
+/// itself forever.
+const MAX_DEPTH: usize = 64;
+
+/// The macros declared so far.
+#[derive(Default)]
+pub struct Macros {
+    defs: HashMap<String, Macro>,
+    /// Expansions so far, numbering the fresh names of the next one.
+    expansions: usize,
+    /// Names the templates expanded into the current declaration use
+    /// without binding, marked there with a leading `#` until the caller's
+    /// binders are renamed around them.
+    free: HashSet<String>,
+}
+
+struct Macro {
+    params: Vec<String>,
+    /// The quoted template, copied out of the program that declared it so
+    /// later REPL inputs can expand it into theirs.
+    template: Ast,
+    root: ExprId,
+}
+
+/// Expand the macros a program declares, in place.
+pub fn expand_macros(ast: &mut Ast, decls: &[Decl]) -> Result<(), LyraError> {
+    Macros::default().expand(ast, decls)
+}
+
+impl Macros {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register each `macro` declaration and expand calls in the declarations
+    /// that follow it. Macro declarations stay in place; checking and
+    /// evaluation skip them.
+    pub fn expand(&mut self, ast: &mut Ast, decls: &[Decl]) -> Result<(), LyraError> {
+        for decl in decls {
+            match decl {
+                Decl::Macro {
+                    name, params, body, ..
+                } => self.define(ast, name, params, *body)?,
+                Decl::Let { body, .. } | Decl::Prop { body, .. } | Decl::Expr(body) => {
+                    self.expand_expr(ast, *body)?
+                }
+                Decl::Type { .. } | Decl::Import { .. } | Decl::Extern { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `name` is a macro.
+    pub fn contains(&self, name: &str) -> bool {
+        self.defs.contains_key(name)
+    }
+
+    fn define(
+        &mut self,
+        ast: &Ast,
+        name: &Spanned<String>,
+        params: &[Spanned<String>],
+        body: ExprId,
+    ) -> Result<(), LyraError> {
+        let Expr::Quote(template) = ast[body].node else {
+            return Err(macro_error("a macro's body must be a `quote` expression", ast[body].span));
+        };
+        let mut names: Vec<String> = Vec::new();
+        for param in params {
+            if names.contains(&param.node) {
+                let message =
+                    format!("macro '{}' has two parameters named '{}'", name.node, param.node);
+                return Err(macro_error(&message, param.span));
+            }
+            names.push(param.node.clone());
+        }
+        for spliced in unquotes(ast, template) {
+            if !matches!(&ast[spliced].node, Expr::Var(v) if names.contains(v)) {
+                let message = format!(
+                    "macro '{}' can only unquote its parameters ({})",
+                    name.node,
+                    names.join(", ")
+                );
+                return Err(macro_error(&message, ast[spliced].span));
+            }
+        }
+        let mut copy = Ast::new();
+        let root = copy.graft(ast, template);
+        self.defs.insert(
+            name.node.clone(),
+            Macro {
+                params: names,
+                template: copy,
+                root,
+            },
+        );
+        Ok(())
+    }
+
+    fn expand_expr(&mut self, ast: &mut Ast, expr: ExprId) -> Result<(), LyraError> {
+        let mut expander = Expander {
+            macros: self,
+            depth: 0,
+            error: None,
+        };
+        expander.visit_expr_mut(ast, expr);
+        let error = expander.error;
+        // Unmarked even after an error, as analysis goes on with the program
+        let free = std::mem::take(&mut self.free);
+        if !free.is_empty() {
+            self.expansions += 1;
+            let mut uncapture = Uncapture {
+                free,
+                renames: Vec::new(),
+                expansion: self.expansions,
+            };
+            uncapture.visit_expr_mut(ast, expr);
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace the call at `call` with the template of macro `name`, with
+    /// `args` spliced in and spanning the call.
+    fn instantiate(
+        &mut self,
+        ast: &mut Ast,
+        name: &str,
+        args: &[ExprId],
+        call: ExprId,
+    ) -> Result<(), LyraError> {
+        let span = ast[call].span;
+        let def = &self.defs[name];
+        if args.len() != def.params.len() {
+            let message = format!(
+                "macro '{}' expects {} arguments, but got {}",
+                name,
+                def.params.len(),
+                args.len()
+            );
+            return Err(macro_error(&message, span));
+        }
+        self.expansions += 1;
+        let mut instantiate = Instantiate {
+            args: def.params.iter().cloned().zip(args.iter().copied()).collect(),
+            renames: Vec::new(),
+            expansion: self.expansions,
+            span,
+            macros: &self.defs,
+            free: &mut self.free,
+        };
+        let root = &def.template[def.root];
+        let node = root.node.map_children(&mut |child| ast.graft(&def.template, child));
+        ast[call] = Spanned::new(node, root.span);
+        instantiate.visit_expr_mut(ast, call);
+        Ok(())
+    }
+}
+
+fn macro_error(message: &str, span: Span) -> LyraError {
+    LyraError::MacroError {
+        message: message.to_string(),
+        span,
+    }
+}
+
+/// Replaces macro calls, expanding the result again for the macros it calls.
+struct Expander<'a> {
+    macros: &'a mut Macros,
+    depth: usize,
+    error: Option<LyraError>,
+}
+
+impl VisitorMut for Expander<'_> {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: ExprId) {
+        if self.error.is_some() {
+            return;
+        }
+        let span = ast[expr].span;
+        let called = match &ast[expr].node {
+            Expr::App { func, args } => match &ast[*func].node {
+                Expr::Var(name) if self.macros.contains(name) => {
+                    Some((name.clone(), args.clone()))
+                }
+                _ => None,
+            },
+            Expr::Var(name) if self.macros.contains(name) => {
+                let message = format!("macro '{}' can only be called, as {}(...)", name, name);
+                self.error = Some(macro_error(&message, span));
+                return;
+            }
+            _ => None,
+        };
+        let Some((name, args)) = called else {
+            walk_expr_mut(self, ast, expr);
+            return;
+        };
+        if self.depth == MAX_DEPTH {
+            let message = format!(
+                "macro '{}' is still expanding after {} levels; a macro can't call itself",
+                name, MAX_DEPTH
+            );
+            self.error = Some(macro_error(&message, span));
+            return;
+        }
+        match self.macros.instantiate(ast, &name, &args, expr) {
+            Ok(()) => {
+                self.depth += 1;
+                self.visit_expr_mut(ast, expr);
+                self.depth -= 1;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+/// Splices the arguments into a copy of a template, renaming the names it
+/// binds, marking the ones it leaves free and spanning it at the call.
+struct Instantiate<'a> {
+    args: HashMap<String, ExprId>,
+    /// Template binders in scope, innermost last, with their fresh names.
+    renames: Vec<(String, String)>,
+    expansion: usize,
+    span: Span,
+    /// Calls to these expand in turn, so their names aren't marked.
+    macros: &'a HashMap<String, Macro>,
+    free: &'a mut HashSet<String>,
+}
+
+impl Instantiate<'_> {
+    fn bind(&mut self, name: &mut Spanned<String>) {
+        let fresh = format!("{}#{}", name.node, self.expansion);
+        self.renames.push((std::mem::replace(&mut name.node, fresh.clone()), fresh));
+        name.span = self.span;
+    }
+    fn bind_pattern(&mut self, pattern: &mut SpannedPattern) {
+        pattern.span = self.span;
+        match &mut pattern.node {
+            Pattern::Var(name) => {
+                let fresh = format!("{}#{}", name, self.expansion);
+                self.renames.push((std::mem::replace(name, fresh.clone()), fresh));
+            }
+            Pattern::Tuple(elems) | Pattern::List(elems) => {
+                elems.iter_mut().for_each(|p| self.bind_pattern(p))
+            }
+            Pattern::Cons(head, tail) => {
+                self.bind_pattern(head);
+                self.bind_pattern(tail);
+            }
+            Pattern::StringPrefix(_, rest) | Pattern::StringSuffix(rest, _) => {
+                self.bind_pattern(rest)
+            }
+            Pattern::Constructor { args, .. } => args.iter_mut().for_each(|p| self.bind_pattern(p)),
+            Pattern::Wildcard
+            | Pattern::IntLit(_)
+            | Pattern::FloatLit(_)
+            | Pattern::StringLit(_)
+            | Pattern::BoolLit(_)
+            | Pattern::UnitLit => {}
+        }
+    }
+}
+
+impl VisitorMut for Instantiate<'_> {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: ExprId) {
+        let spliced = match &ast[expr].node {
+            Expr::Unquote(inner) => match &ast[*inner].node {
+                Expr::Var(param) => Some(self.args[param]),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(arg) = spliced {
+            // A fresh copy each time, as a parameter may be spliced twice
+            let arg = ast[arg].clone();
+            let node = arg.node.map_children(&mut |child| ast.deep_copy(child));
+            ast[expr] = Spanned::new(node, arg.span);
+            return;
+        }
+        ast[expr].span = self.span;
+        let scope = self.renames.len();
+        match &mut ast[expr].node {
+            Expr::Var(name) => {
+                if let Some((_, fresh)) = self.renames.iter().rev().find(|(old, _)| old == name) {
+                    *name = fresh.clone();
+                } else if !self.macros.contains_key(name.as_str()) {
+                    self.free.insert(name.clone());
+                    name.insert(0, '#');
+                }
+            }
+            Expr::Let {
+                name,
+                recursive,
+                value,
+                body,
+                ..
+            } => {
+                let (value, body) = (*value, *body);
+                if *recursive {
+                    self.bind(name);
+                    self.visit_expr_mut(ast, value);
+                } else {
+                    self.visit_expr_mut(ast, value);
+                    if let Expr::Let { name, .. } = &mut ast[expr].node {
+                        self.bind(name);
+                    }
+                }
+                self.visit_expr_mut(ast, body);
+            }
+            Expr::Lambda { params, body } => {
+                for p in params.iter_mut() {
+                    self.bind(&mut p.name);
+                }
+                let body = *body;
+                self.visit_expr_mut(ast, body);
+            }
+            Expr::Match { scrutinee, arms } => {
+                let scrutinee = *scrutinee;
+                // The arms' bodies are their own nodes, so the patterns can
+                // be renamed out of the arena while the bodies are visited
+                let mut taken = std::mem::take(arms);
+                self.visit_expr_mut(ast, scrutinee);
+                for arm in &mut taken {
+                    self.bind_pattern(&mut arm.pattern);
+                    self.visit_expr_mut(ast, arm.body);
+                    self.renames.truncate(scope);
+                }
+                if let Expr::Match { arms, .. } = &mut ast[expr].node {
+                    *arms = taken;
+                }
+            }
+            _ => walk_expr_mut(self, ast, expr),
+        }
+        self.renames.truncate(scope);
+    }
+}
+
+/// Renames the caller's binders that share a name with a template's free
+/// names, through their scopes, and unmarks the free names so they refer
+/// to the top-level definitions.
+struct Uncapture {
+    free: HashSet<String>,
+    /// Renamed binders in scope, innermost last.
+    renames: Vec<(String, String)>,
+    expansion: usize,
+}
+
+impl Uncapture {
+    fn bind(&mut self, name: &mut String) {
+        if self.free.contains(name.as_str()) {
+            let fresh = format!("{}#{}", name, self.expansion);
+            self.renames.push((std::mem::replace(name, fresh.clone()), fresh));
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &mut SpannedPattern) {
+        match &mut pattern.node {
+            Pattern::Var(name) => self.bind(name),
+            Pattern::Tuple(elems) | Pattern::List(elems) => {
+                elems.iter_mut().for_each(|p| self.bind_pattern(p))
+            }
+            Pattern::Cons(head, tail) => {
+                self.bind_pattern(head);
+                self.bind_pattern(tail);
+            }
+            Pattern::StringPrefix(_, rest) | Pattern::StringSuffix(rest, _) => {
+                self.bind_pattern(rest)
+            }
+            Pattern::Constructor { args, .. } => args.iter_mut().for_each(|p| self.bind_pattern(p)),
+            Pattern::Wildcard
+            | Pattern::IntLit(_)
+            | Pattern::FloatLit(_)
+            | Pattern::StringLit(_)
+            | Pattern::BoolLit(_)
+            | Pattern::UnitLit => {}
+        }
+    }
+}
+
+impl VisitorMut for Uncapture {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, expr: ExprId) {
+        let scope = self.renames.len();
+        match &mut ast[expr].node {
+            Expr::Var(name) => {
+                if let Some(free) = name.strip_prefix('#') {
+                    *name = free.to_string();
+                } else if let Some((_, fresh)) =
+                    self.renames.iter().rev().find(|(old, _)| old == name)
+                {
+                    *name = fresh.clone();
+                }
+            }
+            Expr::Let {
+                name,
+                recursive,
+                value,
+                body,
+                ..
+            } => {
+                let (value, body) = (*value, *body);
+                if *recursive {
+                    self.bind(&mut name.node);
+                    self.visit_expr_mut(ast, value);
+                } else {
+                    self.visit_expr_mut(ast, value);
+                    if let Expr::Let { name, .. } = &mut ast[expr].node {
+                        self.bind(&mut name.node);
+                    }
+                }
+                self.visit_expr_mut(ast, body);
+            }
+            Expr::Lambda { params, body } => {
+                for p in params.iter_mut() {
+                    self.bind(&mut p.name.node);
+                }
+                let body = *body;
+                self.visit_expr_mut(ast, body);
+            }
+            Expr::Match { scrutinee, arms } => {
+                let scrutinee = *scrutinee;
+                let mut taken = std::mem::take(arms);
+                self.visit_expr_mut(ast, scrutinee);
+                for arm in &mut taken {
+                    self.bind_pattern(&mut arm.pattern);
+                    self.visit_expr_mut(ast, arm.body);
+                    self.renames.truncate(scope);
+                }
+                if let Expr::Match { arms, .. } = &mut ast[expr].node {
+                    *arms = taken;
+                }
+            }
+            _ => walk_expr_mut(self, ast, expr),
+        }
+        self.renames.truncate(scope);
+    }
+}
@@ -1,48 +1,946 @@
 use std::env;
 use std::fs;
 use std::process;
+use std::time::Duration;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    // Check for --vm flag
-    let use_vm = args.iter().any(|a| a == "--vm");
-    let file_args: Vec<&String> = args.iter().skip(1).filter(|a| *a != "--vm").collect();
-
-    match file_args.len() {
-        0 => {
-            // No arguments: launch REPL
-            if let Err(e) = lyra::repl::run_repl() {
-                eprintln!("Error: {}", e);
-                process::exit(1);
-            }
-        }
-        1 => {
-            // One argument: execute file
-            let path = file_args[0];
-            match fs::read_to_string(path) {
-                Ok(source) => {
-                    let result = if use_vm {
-                        lyra::run_file_vm(&source, path)
-                    } else {
-                        lyra::run_file(&source, path)
-                    };
-                    if let Err(e) = result {
-                        // Errors from type-check/eval are already printed by run_file/run_file_vm
-                        // but lexer/parser errors may not be, so print them too
-                        eprintln!("{}", e.render(&source, path));
+    // An executable from `lyra build` runs the program it carries
+    if let Ok(exe) = env::current_exe() {
+        match lyra::build::embedded_program(&exe) {
+            Ok(Some(program)) => {
+                let options = lyra::RunOptions { args: args[1..].to_vec(), ..Default::default() };
+                if lyra::run_program(&program, &options).is_err() {
+                    process::exit(1);
+                }
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Error loading the program in {}: {}", exe.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    if args.get(1).map(String::as_str) == Some("repl") {
+        run_repl_cmd(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("doc") {
+        run_doc(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("types") {
+        run_types(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("highlight") {
+        run_highlight(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("lint") {
+        run_lint(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("test") {
+        run_test(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("bench") {
+        run_bench(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("selftest") {
+        run_selftest(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("build") {
+        run_build(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("cov") {
+        run_cov(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("emit-js") {
+        run_emit_js(&args[2..]);
+        return;
+    }
+
+    // Flags for running a file; anything else, and everything after `--`,
+    // is the file and then the program's own arguments
+    let mut use_vm = false;
+    let mut dump_ast = false;
+    let mut interactive = false;
+    let mut format = "debug".to_string();
+    let mut options = lyra::RunOptions::default();
+    let mut file_args: Vec<&String> = Vec::new();
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--vm" => use_vm = true,
+            "--sandbox" => options.sandbox = true,
+            "--debug-types" => options.debug_types = true,
+            "--no-prelude" => options.prelude = lyra::stdlib::Prelude::Only(Vec::new()),
+            "--prelude" => match iter.next() {
+                Some(names) => {
+                    let names = names.split(',').map(|n| n.trim().to_string()).collect();
+                    options.prelude = lyra::stdlib::Prelude::Only(names);
+                }
+                None => usage_error("--prelude expects a comma-separated list of builtins"),
+            },
+            "--dump-ast" => dump_ast = true,
+            "-i" | "--interactive" => interactive = true,
+            "-h" | "--help" if file_args.is_empty() => {
+                print_usage();
+                return;
+            }
+            "--" => file_args.extend(iter.by_ref()),
+            _ if arg.starts_with("--format=") => format = arg["--format=".len()..].to_string(),
+            "--max-steps" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => options.budget.max_steps = Some(n),
+                None => usage_error("--max-steps expects a number"),
+            },
+            "--timeout" => match iter.next().and_then(|ms| ms.parse().ok()) {
+                Some(ms) => options.budget.timeout = Some(Duration::from_millis(ms)),
+                None => usage_error("--timeout expects milliseconds"),
+            },
+            "--max-nesting" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => options.max_nesting = Some(n),
+                None => usage_error("--max-nesting expects a number"),
+            },
+            _ => file_args.push(arg),
+        }
+    }
+
+    if let Err(message) = options.prelude.validate() {
+        usage_error(&message);
+    }
+    if interactive {
+        // The file runs in the REPL's session, so its bindings stay in scope
+        if use_vm || dump_ast {
+            usage_error("--interactive runs the file in the REPL, without --vm or --dump-ast");
+        }
+        let [path] = file_args.as_slice() else {
+            usage_error("--interactive expects one file to run before the REPL starts");
+        };
+        start_repl(&[path.to_string()]);
+        return;
+    }
+
+    let Some((path, program_args)) = file_args.split_first() else {
+        // No arguments: launch REPL
+        start_repl(&[]);
+        return;
+    };
+    options.args = program_args.iter().map(|arg| arg.to_string()).collect();
+    if path.ends_with(".lyrc") {
+        run_compiled(path, &options);
+        return;
+    }
+    match fs::read_to_string(path) {
+        Ok(source) if dump_ast => print_ast(&source, path, &format),
+        Ok(source) => {
+            let result = if use_vm {
+                lyra::run_file_vm_with(&source, path, &options)
+            } else {
+                lyra::run_file_with(&source, path, &options)
+            };
+            // Errors are printed as they're found
+            if result.is_err() {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_usage() {
+    println!("Usage: lyra [--vm] [--sandbox] [--debug-types] [--max-steps <n>] [--timeout <ms>] [file.lyra [args...]]");
+    println!("       lyra [--no-prelude | --prelude <name,...>] [--max-nesting <n>] <file.lyra> [--] [args...]");
+    println!("       lyra repl [--load <file.lyra>]...");
+    println!("       lyra -i <file.lyra>");
+    println!("       lyra --dump-ast [--format=debug|json] <file.lyra>");
+    println!("       lyra types <file.lyra>");
+    println!("       lyra lint [--config <file>] [--allow|--warn|--deny <rule>]... [--rules] <file.lyra>");
+    println!("       lyra doc [--html] [--out <dir>] <file.lyra>");
+    println!("       lyra highlight [--html [--fragment]] <file.lyra>");
+    println!("       lyra test [--cases <n>] [--seed <n>] <file.lyra>");
+    println!("       lyra test --snapshot [--update] [--vm] <file.lyra | dir>");
+    println!("       lyra bench [--iterations <n>] [--vm] <file.lyra | dir>");
+    println!("       lyra selftest <file.lyra | dir>");
+    println!("       lyra cov [--vm | --test] [--lcov <out.info>] <file.lyra>");
+    println!("       lyra build [--lyrc] [-o <out>] <file.lyra>");
+    println!("       lyra [--max-steps <n>] [--timeout <ms>] <file.lyrc> [args...]");
+    println!("       lyra emit-js [-o <out.js>] <file.lyra>");
+}
+
+fn start_repl(files: &[String]) {
+    if let Err(e) = lyra::repl::run_repl_with(files) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// `lyra repl`: start the REPL, first loading each `--load` file.
+fn run_repl_cmd(args: &[String]) {
+    let mut files = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match (arg.as_str(), iter.next()) {
+            ("--load", Some(file)) => files.push(file.clone()),
+            _ => {
+                eprintln!("Usage: lyra repl [--load <file.lyra>]...");
+                process::exit(1);
+            }
+        }
+    }
+    start_repl(&files);
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    process::exit(1);
+}
+
+/// `--dump-ast`: print the parsed program instead of running it.
+fn print_ast(source: &str, path: &str, format: &str) {
+    let parsed = lyra::lexer::tokenize(source)
+        .map_err(|errs| errs[0].clone())
+        .and_then(lyra::parser::parse);
+    let program = match parsed {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}", e.render(source, path));
+            process::exit(1);
+        }
+    };
+    match format {
+        "debug" => println!("{:#?}", program),
+        #[cfg(feature = "serde")]
+        "json" => match serde_json::to_string_pretty(&program) {
+            Ok(json) => println!("{}", json),
+            Err(e) => usage_error(&format!("cannot serialize the AST: {}", e)),
+        },
+        #[cfg(not(feature = "serde"))]
+        "json" => usage_error("--format=json needs lyra built with the `serde` feature"),
+        other => usage_error(&format!("unknown --format '{}' (expected debug or json)", other)),
+    }
+}
+
+/// `lyra doc`: print Markdown (or HTML) API docs for a file and its imports,
+/// or write one file per module into `--out <dir>`.
+fn run_doc(args: &[String]) {
+    let mut html = false;
+    let mut out_dir = None;
+    let mut path = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--html" => html = true,
+            "--out" => out_dir = iter.next(),
+            _ if path.is_none() => path = Some(arg),
+            _ => path = None,
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("Usage: lyra doc [--html] [--out <dir>] <file.lyra>");
+        process::exit(1);
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let modules = match lyra::doc::document(&source, path) {
+        Ok(modules) => modules,
+        Err(e) => {
+            eprintln!("{}", e.render(&source, path));
+            process::exit(1);
+        }
+    };
+
+    for module in &modules {
+        let rendered = if html {
+            lyra::doc::render_html(module)
+        } else {
+            lyra::doc::render_markdown(module)
+        };
+        match out_dir {
+            Some(dir) => {
+                let stem = std::path::Path::new(&module.path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "module".to_string());
+                let target = std::path::Path::new(dir)
+                    .join(stem)
+                    .with_extension(if html { "html" } else { "md" });
+                if let Err(e) = fs::create_dir_all(dir).and_then(|_| fs::write(&target, rendered)) {
+                    eprintln!("Error writing {}: {}", target.display(), e);
+                    process::exit(1);
+                }
+                println!("wrote {}", target.display());
+            }
+            None => println!("{}", rendered),
+        }
+    }
+}
+
+/// `lyra types`: print the generalized type of every top-level binding in a
+/// file, without running it.
+fn run_types(args: &[String]) {
+    let [path] = args else {
+        eprintln!("Usage: lyra types <file.lyra>");
+        process::exit(1);
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    match lyra::doc::document(&source, path) {
+        // The file's own module comes last, after its imports
+        Ok(modules) => {
+            if let Some(module) = modules.last() {
+                print!("{}", lyra::doc::render_signatures(module));
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e.render(&source, path));
+            process::exit(1);
+        }
+    }
+}
+
+/// `lyra highlight`: print a file with syntax colors for a terminal, or as
+/// HTML: a standalone page, or with `--fragment` just the `<pre>` block.
+fn run_highlight(args: &[String]) {
+    let mut html = false;
+    let mut fragment = false;
+    let mut path = None;
+    let mut usage = false;
+    for arg in args {
+        match arg.as_str() {
+            "--html" => html = true,
+            "--fragment" => fragment = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => usage = true,
+        }
+    }
+    let Some(path) = path.filter(|_| !usage && (html || !fragment)) else {
+        eprintln!("Usage: lyra highlight [--html [--fragment]] <file.lyra>");
+        process::exit(1);
+    };
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    if fragment {
+        println!("{}", lyra::highlight::render_html_fragment(&source));
+    } else if html {
+        print!("{}", lyra::highlight::render_html(&source, path));
+    } else {
+        print!("{}", lyra::highlight::render_ansi(&source));
+    }
+}
+
+/// `lyra lint`: report style problems in a file. Fails when a denied rule
+/// fires; `--rules` lists the rules instead.
+fn run_lint(args: &[String]) {
+    use lyra::lint::{lint, LintConfig, Level, RULES};
+
+    let mut config = LintConfig::default();
+    let mut overrides = Vec::new();
+    let mut path = None;
+    let mut usage = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--rules" => {
+                for rule in RULES {
+                    println!("{}  {:<17} {}", rule.code, rule.name, rule.summary);
+                }
+                return;
+            }
+            "--config" => match iter.next() {
+                Some(file) => {
+                    let text = fs::read_to_string(file).unwrap_or_else(|e| {
+                        eprintln!("Error reading {}: {}", file, e);
                         process::exit(1);
+                    });
+                    config = LintConfig::parse(&text).unwrap_or_else(|e| {
+                        usage_error(&format!("{}: {}", file, e));
+                    });
+                }
+                None => usage = true,
+            },
+            "--allow" | "--warn" | "--deny" => match iter.next() {
+                Some(rule) => overrides.push((arg.as_str(), rule)),
+                None => usage = true,
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage = true,
+        }
+    }
+    let Some(path) = path.filter(|_| !usage) else {
+        eprintln!("Usage: lyra lint [--config <file>] [--allow|--warn|--deny <rule>]... [--rules] <file.lyra>");
+        process::exit(1);
+    };
+    // Flags apply after the config file, wherever they are given
+    for (flag, rule) in overrides {
+        let level = match flag {
+            "--allow" => Level::Allow,
+            "--warn" => Level::Warn,
+            _ => Level::Deny,
+        };
+        if let Err(e) = config.set_level(rule, level) {
+            usage_error(&e);
+        }
+    }
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    match lint(&source, &config) {
+        Ok(diagnostics) => {
+            for d in &diagnostics {
+                println!("{}", d.render(&source, path, lyra::diagnostic::Format::Ansi));
+            }
+            if diagnostics
+                .iter()
+                .any(|d| d.severity == lyra::diagnostic::Severity::Error)
+            {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e.render(&source, path));
+            process::exit(1);
+        }
+    }
+}
+
+/// `lyra test`: run every `prop` in a file, reporting shrunk counterexamples.
+/// With `--snapshot`, compare program output against `.expected` files instead.
+fn run_test(args: &[String]) {
+    if args.iter().any(|a| a == "--snapshot") {
+        run_snapshots(args);
+        return;
+    }
+
+    let mut config = lyra::testing::PropConfig::default();
+    let mut path = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--cases" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => config.cases = n,
+                None => path = None,
+            },
+            "--seed" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) => config.seed = n,
+                None => path = None,
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => path = None,
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("Usage: lyra test [--cases <n>] [--seed <n>] <file.lyra>");
+        process::exit(1);
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let results = match lyra::testing::run_props(&source, path, config) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}", e.render(&source, path));
+            process::exit(1);
+        }
+    };
+
+    if print_props(&results, config.seed) > 0 {
+        process::exit(1);
+    }
+}
+
+/// `lyra bench`: run every `bench_` function in a file, or in each file in
+/// a directory, and print their timings side by side.
+fn run_bench(args: &[String]) {
+    use lyra::bench::{run_benches, table, BenchConfig, Iterations};
+
+    let mut config = BenchConfig::default();
+    let mut target = None;
+    let mut usage = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--vm" => config.vm = true,
+            "--iterations" => match iter.next().and_then(|n| n.parse().ok()) {
+                Some(n) if n > 0 => config.iterations = Iterations::Fixed(n),
+                _ => usage = true,
+            },
+            _ if target.is_none() => target = Some(arg),
+            _ => usage = true,
+        }
+    }
+    let Some(target) = target.filter(|_| !usage) else {
+        eprintln!("Usage: lyra bench [--iterations <n>] [--vm] <file.lyra | dir>");
+        process::exit(1);
+    };
+
+    let dir = std::path::Path::new(target).is_dir();
+    let files = match lyra::testing::snapshot_files(std::path::Path::new(target)) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", target, e);
+            process::exit(1);
+        }
+    };
+    let mut results = Vec::new();
+    for file in &files {
+        let path = file.display().to_string();
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        };
+        match run_benches(&source, &path, config) {
+            // Names from different files are told apart by the file's name
+            Ok(benches) => results.extend(benches.into_iter().map(|mut result| {
+                if dir {
+                    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                    result.name = format!("{}/{}", stem, result.name);
+                }
+                result
+            })),
+            Err(e) => {
+                eprintln!("{}", e.render(&source, &path));
+                process::exit(1);
+            }
+        }
+    }
+
+    if results.is_empty() {
+        println!("no benchmarks found (define `let bench_<name> = fn () -> ...`)");
+        return;
+    }
+    print!("{}", table(&results));
+}
+
+/// `lyra selftest`: run a file, or each file in a directory, on both the
+/// tree-walker and the VM, reporting where their output or results differ.
+fn run_selftest(args: &[String]) {
+    use lyra::selftest::{run_selftest, Divergence};
+
+    let [target] = args else {
+        eprintln!("Usage: lyra selftest <file.lyra | dir>");
+        process::exit(1);
+    };
+    let files = match lyra::testing::snapshot_files(std::path::Path::new(target)) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", target, e);
+            process::exit(1);
+        }
+    };
+
+    let side = |result: &Result<String, String>| match result {
+        Ok(value) => value.clone(),
+        Err(error) => format!("error: {}", error),
+    };
+    let mut failed = 0;
+    for file in &files {
+        let path = file.display().to_string();
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        };
+        let report = match run_selftest(&source, &path) {
+            Ok(report) => report,
+            Err(e) => {
+                failed += 1;
+                println!("selftest {} ... FAILED (doesn't check)", path);
+                eprintln!("{}", e.render(&source, &path));
+                continue;
+            }
+        };
+        match report.divergence {
+            None => println!("selftest {} ... ok", path),
+            Some(divergence) => {
+                failed += 1;
+                println!("selftest {} ... DIVERGED", path);
+                let (what, tree_walker, vm) = match divergence {
+                    Divergence::Output {
+                        line,
+                        tree_walker,
+                        vm,
+                    } => {
+                        let line_of = |l: Option<String>| {
+                            l.map_or("(no more output)".to_string(), |l| format!("{:?}", l))
+                        };
+                        (format!("output line {}", line), line_of(tree_walker), line_of(vm))
+                    }
+                    Divergence::Result { tree_walker, vm } => {
+                        ("result".to_string(), side(&tree_walker), side(&vm))
                     }
+                };
+                println!("  {} differs", what);
+                println!("    tree-walker: {}", tree_walker);
+                println!("    vm:          {}", vm);
+            }
+        }
+    }
+
+    println!("\n{} agreed, {} failed", files.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+/// `lyra build`: compile a file and its imports into a standalone
+/// executable, or with `--lyrc` into a `.lyrc` file for `lyra` to run.
+fn run_build(args: &[String]) {
+    let mut lyrc = false;
+    let mut out = None;
+    let mut path = None;
+    let mut usage = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--lyrc" => lyrc = true,
+            "-o" => match iter.next() {
+                Some(o) => out = Some(o.clone()),
+                None => usage = true,
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage = true,
+        }
+    }
+    let Some(path) = path.filter(|_| !usage) else {
+        eprintln!("Usage: lyra build [--lyrc] [-o <out>] <file.lyra>");
+        process::exit(1);
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let Ok(program) = lyra::compile_file_with(&source, path, &lyra::RunOptions::default()) else {
+        process::exit(1);
+    };
+
+    let stem = path.strip_suffix(".lyra").unwrap_or(path);
+    let (out, bytes) = if lyrc {
+        let out = out.unwrap_or_else(|| format!("{}.lyrc", stem));
+        (out, lyra::compiler::lyrc::encode(&program))
+    } else {
+        let runtime = env::current_exe().and_then(fs::read).unwrap_or_else(|e| {
+            eprintln!("Error reading the lyra executable: {}", e);
+            process::exit(1);
+        });
+        let out = out.unwrap_or_else(|| format!("{}{}", stem, env::consts::EXE_SUFFIX));
+        (out, lyra::build::executable(&runtime, &program))
+    };
+    if let Err(e) = fs::write(&out, bytes) {
+        eprintln!("Error writing {}: {}", out, e);
+        process::exit(1);
+    }
+    if !lyrc {
+        make_executable(&out);
+    }
+    println!("built {}", out);
+}
+
+/// `lyra emit-js`: lower a file and its imports to JavaScript, printed or
+/// written to `-o`.
+fn run_emit_js(args: &[String]) {
+    let mut out = None;
+    let mut path = None;
+    let mut usage = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-o" => match iter.next() {
+                Some(o) => out = Some(o.clone()),
+                None => usage = true,
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage = true,
+        }
+    }
+    let Some(path) = path.filter(|_| !usage) else {
+        eprintln!("Usage: lyra emit-js [-o <out.js>] <file.lyra>");
+        process::exit(1);
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let js = match lyra::emit_js(&source, path) {
+        Ok(js) => js,
+        Err(e) => {
+            eprintln!("{}", e.render(&source, path));
+            process::exit(1);
+        }
+    };
+    match out {
+        Some(out) => {
+            if let Err(e) = fs::write(&out, js) {
+                eprintln!("Error writing {}: {}", out, e);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", js),
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &str) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Err(e) = fs::set_permissions(path, fs::Permissions::from_mode(0o755)) {
+        eprintln!("Error making {} executable: {}", path, e);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &str) {}
+
+/// Run a `.lyrc` file from `lyra build --lyrc`.
+fn run_compiled(path: &str, options: &lyra::RunOptions) {
+    let program = fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| lyra::compiler::lyrc::decode(&bytes));
+    match program {
+        Ok(program) => {
+            if lyra::run_program(&program, options).is_err() {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error loading {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Print how each prop went and a summary, returning how many failed.
+fn print_props(results: &[lyra::testing::PropResult], seed: u64) -> usize {
+    let mut failed = 0;
+    for result in results {
+        match &result.outcome {
+            lyra::testing::PropOutcome::Passed => {
+                println!("prop \"{}\" ... ok ({} cases)", result.name, result.cases)
+            }
+            lyra::testing::PropOutcome::Failed {
+                counterexample,
+                shrinks,
+                error,
+            } => {
+                failed += 1;
+                println!(
+                    "prop \"{}\" ... FAILED after {} cases ({} shrinks)",
+                    result.name, result.cases, shrinks
+                );
+                for (name, value) in counterexample {
+                    println!("    {} = {}", name, value);
                 }
-                Err(e) => {
-                    eprintln!("Error reading {}: {}", path, e);
-                    process::exit(1);
+                if let Some(error) = error {
+                    println!("    {}", error);
                 }
             }
         }
-        _ => {
-            eprintln!("Usage: lyra [--vm] [file.lyra]");
+    }
+    println!("\n{} passed, {} failed (seed {})", results.len() - failed, failed, seed);
+    failed
+}
+
+/// `lyra cov`: run a file, or with `--test` its props, and report which lines
+/// ran. `--lcov` also writes an lcov tracefile.
+fn run_cov(args: &[String]) {
+    let mut use_vm = false;
+    let mut props = false;
+    let mut lcov = None;
+    let mut path = None;
+    let mut usage = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--vm" => use_vm = true,
+            "--test" => props = true,
+            "--lcov" => match iter.next() {
+                Some(out) => lcov = Some(out),
+                None => usage = true,
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => usage = true,
+        }
+    }
+    // Props always run on the tree-walker
+    usage |= use_vm && props;
+    let Some(path) = path.filter(|_| !usage) else {
+        eprintln!("Usage: lyra cov [--vm | --test] [--lcov <out.info>] <file.lyra>");
+        process::exit(1);
+    };
+
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let config = lyra::testing::PropConfig::default();
+    let (passed, hits) = lyra::coverage::with_coverage(|| {
+        // A run prints its own errors; `run_props` leaves them to us
+        if props {
+            lyra::testing::run_props(&source, path, config)
+                .map(|results| print_props(&results, config.seed) == 0)
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e.render(&source, path));
+                    false
+                })
+        } else if use_vm {
+            lyra::run_file_vm(&source, path).is_ok()
+        } else {
+            lyra::run_file(&source, path).is_ok()
+        }
+    });
+
+    let files = match lyra::coverage::report(path, &source, &hits) {
+        Ok(files) => files,
+        // The run stopped at the same error and has reported it
+        Err(_) if !passed => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e.render(&source, path));
+            process::exit(1);
+        }
+    };
+    println!();
+    print!("{}", lyra::coverage::render_text(&files));
+    if let Some(out) = lcov {
+        if let Err(e) = fs::write(out, lyra::coverage::render_lcov(&files)) {
+            eprintln!("Error writing {}: {}", out, e);
+            process::exit(1);
+        }
+    }
+    if !passed {
+        process::exit(1);
+    }
+}
+
+/// `lyra test --snapshot`: run each program in a fresh `lyra` process and
+/// compare its stdout with the `.expected` file beside it.
+fn run_snapshots(args: &[String]) {
+    use lyra::testing::{check_snapshot, snapshot_files, SnapshotOutcome};
+
+    let update = args.iter().any(|a| a == "--update");
+    let use_vm = args.iter().any(|a| a == "--vm");
+    let targets: Vec<&String> = args
+        .iter()
+        .filter(|a| !matches!(a.as_str(), "--snapshot" | "--update" | "--vm"))
+        .collect();
+    let [target] = targets.as_slice() else {
+        eprintln!("Usage: lyra test --snapshot [--update] [--vm] <file.lyra | dir>");
+        process::exit(1);
+    };
+
+    let files = match snapshot_files(std::path::Path::new(target)) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", target, e);
+            process::exit(1);
+        }
+    };
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Error: cannot locate the lyra executable: {}", e);
             process::exit(1);
         }
+    };
+
+    let mut failed = 0;
+    for file in &files {
+        let mut cmd = process::Command::new(&exe);
+        cmd.arg(file);
+        if use_vm {
+            cmd.arg("--vm");
+        }
+        let output = match cmd.output() {
+            Ok(output) => output,
+            Err(e) => {
+                eprintln!("Error running {}: {}", file.display(), e);
+                process::exit(1);
+            }
+        };
+        if !output.status.success() {
+            failed += 1;
+            println!("snapshot {} ... FAILED ({})", file.display(), output.status);
+            print!("{}", String::from_utf8_lossy(&output.stderr));
+            continue;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        match check_snapshot(file, &stdout, update) {
+            Ok(SnapshotOutcome::Matched) => println!("snapshot {} ... ok", file.display()),
+            Ok(SnapshotOutcome::Updated) => println!("snapshot {} ... updated", file.display()),
+            Ok(SnapshotOutcome::Missing) => {
+                failed += 1;
+                println!(
+                    "snapshot {} ... FAILED (no .expected file; run with --update)",
+                    file.display()
+                );
+            }
+            Ok(SnapshotOutcome::Mismatch { diff }) => {
+                failed += 1;
+                println!("snapshot {} ... FAILED", file.display());
+                print!("{}", diff);
+            }
+            Err(e) => {
+                eprintln!("Error writing snapshot for {}: {}", file.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    println!("\n{} passed, {} failed", files.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
     }
 }
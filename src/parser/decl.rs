@@ -7,18 +7,23 @@ use super::Parser;
 
 impl Parser {
     pub(crate) fn parse_decl(&mut self) -> Result<Decl, LyraError> {
+        let doc = self.peek_token().doc.clone();
         match self.peek() {
-            TokenKind::Let => self.parse_let_decl(),
-            TokenKind::Type => self.parse_type_decl(),
+            TokenKind::Let => self.parse_let_decl(doc),
+            TokenKind::Type => self.parse_type_decl(doc),
             TokenKind::Import => self.parse_import_decl(),
+            _ if self.at_prop_decl() => self.parse_prop_decl(),
+            _ if self.at_sig_decl() => self.parse_sig_decl(doc),
+            _ if self.at_extern_decl() => self.parse_extern_decl(doc),
+            _ if self.at_macro_decl() => self.parse_macro_decl(),
             _ => {
-                let expr = self.parse_expr()?;
+                let expr = self.parse_body()?;
                 Ok(Decl::Expr(expr))
             }
         }
     }
 
-    fn parse_let_decl(&mut self) -> Result<Decl, LyraError> {
+    fn parse_let_decl(&mut self, doc: Option<String>) -> Result<Decl, LyraError> {
         self.advance(); // consume 'let'
         let recursive = self.match_token(&TokenKind::Rec);
         let name = self.expect_ident()?;
@@ -30,17 +35,18 @@ impl Parser {
         };
 
         self.expect(&TokenKind::Eq)?;
-        let body = self.parse_expr()?;
+        let body = self.parse_body()?;
 
         Ok(Decl::Let {
             name,
             recursive,
             type_ann,
             body,
+            doc,
         })
     }
 
-    fn parse_type_decl(&mut self) -> Result<Decl, LyraError> {
+    fn parse_type_decl(&mut self, doc: Option<String>) -> Result<Decl, LyraError> {
         self.advance(); // consume 'type'
         let name = self.expect_ident()?;
 
@@ -69,6 +75,7 @@ impl Parser {
             name,
             type_params,
             variants,
+            doc,
         })
     }
 
@@ -84,6 +91,7 @@ impl Parser {
                 | TokenKind::Eof
                 | TokenKind::Let
                 | TokenKind::Type => break,
+                _ if self.at_prop_decl() || self.at_sig_decl() || self.at_extern_decl() => break,
                 TokenKind::Ident(s) if s.starts_with(|c: char| c.is_uppercase()) => {
                     // Could be a field type OR the next variant if preceded by |
                     // Since we break on |, an uppercase ident here is a type field
@@ -96,11 +104,7 @@ impl Parser {
             }
         }
 
-        let end = if fields.is_empty() {
-            name.span
-        } else {
-            fields.last().unwrap().span
-        };
+        let end = fields.last().map_or(name.span, |f| f.span);
 
         Ok(Variant {
             name,
@@ -109,6 +113,167 @@ impl Parser {
         })
     }
 
+    /// `sig` (or `val`) is only a keyword when it starts `sig name :`.
+    fn at_sig_decl(&self) -> bool {
+        matches!(self.peek(), TokenKind::Ident(id) if id == "sig" || id == "val")
+            && matches!(self.peek_at(1), TokenKind::Ident(_))
+            && matches!(self.peek_at(2), TokenKind::Colon)
+    }
+
+    /// `sig name : Type` followed by `let name = ...`. The signature becomes
+    /// the `let`'s annotation, so it's checked like `let name : Type = ...`.
+    fn parse_sig_decl(&mut self, doc: Option<String>) -> Result<Decl, LyraError> {
+        self.advance(); // consume 'sig'
+        let sig_name = self.expect_ident()?;
+        self.expect(&TokenKind::Colon)?;
+        let sig = self.parse_type_annotation()?;
+
+        if !self.check(&TokenKind::Let) {
+            return Err(LyraError::UnexpectedToken {
+                expected: format!("the definition of '{}' after its signature", sig_name.node),
+                found: self.peek().describe().to_string(),
+                span: self.peek_span(),
+            });
+        }
+        let let_doc = self.peek_token().doc.clone();
+        let Decl::Let {
+            name,
+            recursive,
+            type_ann,
+            body,
+            doc: let_doc,
+        } = self.parse_let_decl(let_doc.or(doc))?
+        else {
+            unreachable!("parse_let_decl returns a let");
+        };
+
+        if name.node != sig_name.node {
+            return Err(LyraError::UnexpectedToken {
+                expected: format!("the definition of '{}' after its signature", sig_name.node),
+                found: format!("'{}'", name.node),
+                span: name.span,
+            });
+        }
+        if let Some(ann) = type_ann {
+            return Err(LyraError::UnexpectedToken {
+                expected: format!("'=' (the signature already gives the type of '{}')", name.node),
+                found: "a type annotation".to_string(),
+                span: ann.span,
+            });
+        }
+
+        Ok(Decl::Let {
+            name,
+            recursive,
+            type_ann: Some(sig),
+            body,
+            doc: let_doc,
+        })
+    }
+
+    /// `prop` is only a keyword when followed by the property's name.
+    fn at_prop_decl(&self) -> bool {
+        matches!(self.peek(), TokenKind::Ident(id) if id == "prop")
+            && matches!(self.peek_at(1), TokenKind::StringLit(_))
+    }
+
+    fn parse_prop_decl(&mut self) -> Result<Decl, LyraError> {
+        let start = self.peek_span();
+        self.advance(); // consume 'prop'
+        let tok = self.advance().clone();
+        let TokenKind::StringLit(name) = tok.kind else {
+            return Err(LyraError::UnexpectedToken {
+                expected: "property name".to_string(),
+                found: tok.kind.describe().to_string(),
+                span: tok.span,
+            });
+        };
+
+        // Every parameter needs a type so the runner knows what to generate
+        self.expect(&TokenKind::LParen)?;
+        let mut params = Vec::new();
+        loop {
+            let param = self.parse_lambda_param()?;
+            if param.type_ann.is_none() {
+                return Err(LyraError::UnexpectedToken {
+                    expected: format!("a type annotation for '{}'", param.name.node),
+                    found: self.peek().describe().to_string(),
+                    span: self.peek_span(),
+                });
+            }
+            params.push(param);
+            if !self.match_token(&TokenKind::Comma)
+                || matches!(self.peek(), TokenKind::RParen)
+            {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RParen)?;
+        self.expect(&TokenKind::Arrow)?;
+        let body = self.parse_expr()?;
+        let span = start.merge(self.ast[body].span);
+
+        Ok(Decl::Prop {
+            name,
+            params,
+            body,
+            span,
+        })
+    }
+
+    /// `extern` is only a keyword when it starts `extern name :`.
+    fn at_extern_decl(&self) -> bool {
+        matches!(self.peek(), TokenKind::Ident(id) if id == "extern")
+            && matches!(self.peek_at(1), TokenKind::Ident(_))
+            && matches!(self.peek_at(2), TokenKind::Colon)
+    }
+
+    /// `extern name : Type`, a value the host provides.
+    fn parse_extern_decl(&mut self, doc: Option<String>) -> Result<Decl, LyraError> {
+        self.advance(); // consume 'extern'
+        let name = self.expect_ident()?;
+        self.expect(&TokenKind::Colon)?;
+        let type_ann = self.parse_type_annotation()?;
+        Ok(Decl::Extern {
+            name,
+            type_ann,
+            doc,
+        })
+    }
+
+    fn at_macro_decl(&self) -> bool {
+        matches!(self.peek(), TokenKind::Ident(id) if id == "macro")
+            && matches!(self.peek_at(1), TokenKind::Ident(_))
+            && matches!(self.peek_at(2), TokenKind::LParen)
+    }
+
+    /// `macro name(a, b) = quote ...`. The body is only checked for being a
+    /// template when the macro is registered; see `macros`.
+    fn parse_macro_decl(&mut self) -> Result<Decl, LyraError> {
+        let start = self.peek_span();
+        self.advance(); // consume 'macro'
+        let name = self.expect_ident()?;
+        self.expect(&TokenKind::LParen)?;
+        let mut params = Vec::new();
+        while !self.check(&TokenKind::RParen) {
+            params.push(self.expect_ident()?);
+            if !self.match_token(&TokenKind::Comma) {
+                break;
+            }
+        }
+        self.expect(&TokenKind::RParen)?;
+        self.expect(&TokenKind::Eq)?;
+        let body = self.parse_expr()?;
+        let span = start.merge(self.ast[body].span);
+
+        Ok(Decl::Macro {
+            name,
+            params,
+            body,
+            span,
+        })
+    }
+
     fn parse_import_decl(&mut self) -> Result<Decl, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume 'import'
@@ -7,17 +7,21 @@ use super::{infix_binding_power, token_to_binop, Parser};
 
 impl Parser {
     /// Parse an expression with Pratt binding power.
-    pub fn parse_expr(&mut self) -> Result<SpannedExpr, LyraError> {
+    pub fn parse_expr(&mut self) -> Result<ExprId, LyraError> {
         self.parse_expr_bp(0)
     }
 
-    pub(crate) fn parse_expr_bp(&mut self, min_bp: u8) -> Result<SpannedExpr, LyraError> {
+    pub(crate) fn parse_expr_bp(&mut self, min_bp: u8) -> Result<ExprId, LyraError> {
+        self.nested(|p| p.parse_operators(min_bp))
+    }
+
+    fn parse_operators(&mut self, min_bp: u8) -> Result<ExprId, LyraError> {
         let mut lhs = self.parse_prefix()?;
 
         loop {
-            // Check for field access: expr.field (highest precedence postfix)
-            if matches!(self.peek(), TokenKind::Dot) && min_bp <= 19 {
-                self.advance(); // consume '.'
+            // Check for field access: expr.field or expr?.field (highest precedence postfix)
+            if matches!(self.peek(), TokenKind::Dot | TokenKind::QuestionDot) && min_bp <= 19 {
+                let optional = matches!(self.advance().kind, TokenKind::QuestionDot);
                 let field_tok = self.advance().clone();
                 let field = match field_tok.kind {
                     TokenKind::Ident(name) => name,
@@ -29,21 +33,25 @@ impl Parser {
                         })
                     }
                 };
-                let span = lhs.span.merge(field_tok.span);
-                lhs = Spanned::new(
-                    Expr::FieldAccess {
-                        expr: Box::new(lhs),
-                        field,
-                    },
-                    span,
-                );
+                let span = self.ast[lhs].span.merge(field_tok.span);
+                lhs = if optional {
+                    self.desugar_optional_field(lhs, field, span)
+                } else {
+                    self.ast.alloc(Expr::FieldAccess { expr: lhs, field }, span)
+                };
+                continue;
+            }
+
+            // Check for a slice: expr[start:end]
+            if matches!(self.peek(), TokenKind::LBracket) && min_bp <= 19 && self.at_slice() {
+                lhs = self.parse_slice(lhs)?;
                 continue;
             }
 
             // Check for function application: expr(args)
             if matches!(self.peek(), TokenKind::LParen)
                 && !matches!(
-                    lhs.node,
+                    self.ast[lhs].node,
                     Expr::IntLit(_)
                         | Expr::FloatLit(_)
                         | Expr::BoolLit(_)
@@ -66,24 +74,33 @@ impl Parser {
                 }
                 self.advance(); // consume operator
                 let rhs = self.parse_expr_bp(r_bp)?;
-                let span = lhs.span.merge(rhs.span);
+                let span = self.ast[lhs].span.merge(self.ast[rhs].span);
 
                 lhs = match op_kind {
-                    TokenKind::PipeRight => Spanned::new(
-                        Expr::Pipe {
-                            lhs: Box::new(lhs),
-                            rhs: Box::new(rhs),
-                        },
-                        span,
-                    ),
-                    _ => Spanned::new(
-                        Expr::BinOp {
-                            op: token_to_binop(&op_kind),
-                            lhs: Box::new(lhs),
-                            rhs: Box::new(rhs),
-                        },
-                        span,
-                    ),
+                    TokenKind::PipeRight => self.ast.alloc(Expr::Pipe { lhs, rhs }, span),
+                    // x |>! f  desugars to  x |> tap(f)
+                    TokenKind::PipeTap => {
+                        let tap_span = self.ast[rhs].span;
+                        let func = self.ast.alloc(Expr::Var("tap".to_string()), tap_span);
+                        let tap = self.ast.alloc(
+                            Expr::App {
+                                func,
+                                args: vec![rhs],
+                            },
+                            tap_span,
+                        );
+                        self.ast.alloc(Expr::Pipe { lhs, rhs: tap }, span)
+                    }
+                    _ => {
+                        let op = token_to_binop(&op_kind).ok_or_else(|| {
+                            LyraError::UnexpectedToken {
+                                expected: "binary operator".to_string(),
+                                found: op_kind.describe().to_string(),
+                                span,
+                            }
+                        })?;
+                        self.ast.alloc(Expr::BinOp { op, lhs, rhs }, span)
+                    }
                 };
             } else {
                 break;
@@ -94,24 +111,24 @@ impl Parser {
     }
 
     /// Parse prefix / atom expressions (NUD position).
-    fn parse_prefix(&mut self) -> Result<SpannedExpr, LyraError> {
+    fn parse_prefix(&mut self) -> Result<ExprId, LyraError> {
         let tok = self.peek_token().clone();
         match &tok.kind {
             // Literals
             TokenKind::IntLit(n) => {
                 let n = *n;
                 self.advance();
-                Ok(Spanned::new(Expr::IntLit(n), tok.span))
+                Ok(self.ast.alloc(Expr::IntLit(n), tok.span))
             }
             TokenKind::FloatLit(n) => {
                 let n = *n;
                 self.advance();
-                Ok(Spanned::new(Expr::FloatLit(n), tok.span))
+                Ok(self.ast.alloc(Expr::FloatLit(n), tok.span))
             }
             TokenKind::StringLit(s) => {
                 let s = s.clone();
                 self.advance();
-                Ok(Spanned::new(Expr::StringLit(s), tok.span))
+                Ok(self.ast.alloc(Expr::StringLit(s), tok.span))
             }
             TokenKind::InterpolatedString(parts) => {
                 let parts = parts.clone();
@@ -122,13 +139,32 @@ impl Parser {
             TokenKind::BoolLit(b) => {
                 let b = *b;
                 self.advance();
-                Ok(Spanned::new(Expr::BoolLit(b), tok.span))
+                Ok(self.ast.alloc(Expr::BoolLit(b), tok.span))
+            }
+
+            // Quoted code and splices: quote e, unquote(e)
+            TokenKind::Ident(name) if name == "quote" || name == "unquote" => {
+                let quoting = name == "quote";
+                if !quoting && self.quote_depth == 0 {
+                    return Err(LyraError::MisplacedUnquote { span: tok.span });
+                }
+                self.advance();
+                // Inside an unquote we're back to ordinary code
+                let outer = self.quote_depth;
+                self.quote_depth = if quoting { outer + 1 } else { 0 };
+                let operand = self.parse_expr_bp(17);
+                self.quote_depth = outer;
+                let operand = operand?;
+                let span = tok.span.merge(self.ast[operand].span);
+                let node = if quoting { Expr::Quote(operand) } else { Expr::Unquote(operand) };
+                Ok(self.ast.alloc(node, span))
             }
 
             // Variable or Constructor
             TokenKind::Ident(_) => {
                 let ident = self.expect_ident()?;
-                Ok(Spanned::new(Expr::Var(ident.node), ident.span))
+                let ident = self.qualify_constructor(ident);
+                Ok(self.ast.alloc(Expr::Var(ident.node), ident.span))
             }
 
             // Unary minus
@@ -136,11 +172,11 @@ impl Parser {
                 let start = tok.span;
                 self.advance();
                 let operand = self.parse_expr_bp(17)?; // highest precedence
-                let span = start.merge(operand.span);
-                Ok(Spanned::new(
+                let span = start.merge(self.ast[operand].span);
+                Ok(self.ast.alloc(
                     Expr::UnaryOp {
                         op: UnaryOp::Neg,
-                        operand: Box::new(operand),
+                        operand,
                     },
                     span,
                 ))
@@ -151,11 +187,11 @@ impl Parser {
                 let start = tok.span;
                 self.advance();
                 let operand = self.parse_expr_bp(17)?;
-                let span = start.merge(operand.span);
-                Ok(Spanned::new(
+                let span = start.merge(self.ast[operand].span);
+                Ok(self.ast.alloc(
                     Expr::UnaryOp {
                         op: UnaryOp::Not,
-                        operand: Box::new(operand),
+                        operand,
                     },
                     span,
                 ))
@@ -182,6 +218,22 @@ impl Parser {
             // Match expression
             TokenKind::Match => self.parse_match(),
 
+            // Module value: import "path"
+            TokenKind::Import => {
+                self.advance(); // consume 'import'
+                let path_tok = self.advance().clone();
+                match path_tok.kind {
+                    TokenKind::StringLit(path) => {
+                        Ok(self.ast.alloc(Expr::Import(path), tok.span.merge(path_tok.span)))
+                    }
+                    _ => Err(LyraError::UnexpectedToken {
+                        expected: "string path".to_string(),
+                        found: path_tok.kind.describe().to_string(),
+                        span: path_tok.span,
+                    }),
+                }
+            }
+
             _ => Err(LyraError::ExpectedExpression {
                 found: tok.kind.describe().to_string(),
                 span: tok.span,
@@ -189,7 +241,7 @@ impl Parser {
         }
     }
 
-    fn parse_paren_expr(&mut self) -> Result<SpannedExpr, LyraError> {
+    fn parse_paren_expr(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume '('
 
@@ -197,7 +249,7 @@ impl Parser {
         if matches!(self.peek(), TokenKind::RParen) {
             self.advance();
             let span = start.merge(self.previous_span());
-            return Ok(Spanned::new(Expr::UnitLit, span));
+            return Ok(self.ast.alloc(Expr::UnitLit, span));
         }
 
         let first = self.parse_expr()?;
@@ -206,11 +258,14 @@ impl Parser {
         if matches!(self.peek(), TokenKind::Comma) {
             let mut elems = vec![first];
             while self.match_token(&TokenKind::Comma) {
+                if elems.len() > 1 && matches!(self.peek(), TokenKind::RParen) {
+                    break; // allow trailing comma
+                }
                 elems.push(self.parse_expr()?);
             }
             self.expect(&TokenKind::RParen)?;
             let span = start.merge(self.previous_span());
-            return Ok(Spanned::new(Expr::TupleLit(elems), span));
+            return Ok(self.ast.alloc(Expr::TupleLit(elems), span));
         }
 
         // Parenthesized expression
@@ -218,7 +273,7 @@ impl Parser {
         Ok(first)
     }
 
-    fn parse_list_literal(&mut self) -> Result<SpannedExpr, LyraError> {
+    fn parse_list_literal(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume '['
 
@@ -226,16 +281,19 @@ impl Parser {
         if !matches!(self.peek(), TokenKind::RBracket) {
             elems.push(self.parse_expr()?);
             while self.match_token(&TokenKind::Comma) {
+                if matches!(self.peek(), TokenKind::RBracket) {
+                    break; // allow trailing comma
+                }
                 elems.push(self.parse_expr()?);
             }
         }
 
         self.expect(&TokenKind::RBracket)?;
         let span = start.merge(self.previous_span());
-        Ok(Spanned::new(Expr::ListLit(elems), span))
+        Ok(self.ast.alloc(Expr::ListLit(elems), span))
     }
 
-    fn parse_lambda(&mut self) -> Result<SpannedExpr, LyraError> {
+    fn parse_lambda(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume 'fn'
 
@@ -244,6 +302,9 @@ impl Parser {
         if !matches!(self.peek(), TokenKind::RParen) {
             params.push(self.parse_lambda_param()?);
             while self.match_token(&TokenKind::Comma) {
+                if matches!(self.peek(), TokenKind::RParen) {
+                    break; // allow trailing comma
+                }
                 params.push(self.parse_lambda_param()?);
             }
         }
@@ -251,17 +312,11 @@ impl Parser {
         self.expect(&TokenKind::Arrow)?;
 
         let body = self.parse_expr()?;
-        let span = start.merge(body.span);
-        Ok(Spanned::new(
-            Expr::Lambda {
-                params,
-                body: Box::new(body),
-            },
-            span,
-        ))
+        let span = start.merge(self.ast[body].span);
+        Ok(self.ast.alloc(Expr::Lambda { params, body }, span))
     }
 
-    fn parse_lambda_param(&mut self) -> Result<LambdaParam, LyraError> {
+    pub(crate) fn parse_lambda_param(&mut self) -> Result<LambdaParam, LyraError> {
         let name = self.expect_ident()?;
         let type_ann = if self.match_token(&TokenKind::Colon) {
             Some(self.parse_type_annotation()?)
@@ -271,28 +326,64 @@ impl Parser {
         Ok(LambdaParam { name, type_ann })
     }
 
-    fn parse_if(&mut self) -> Result<SpannedExpr, LyraError> {
+    fn parse_if(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume 'if'
 
+        if matches!(self.peek(), TokenKind::Let) {
+            return self.parse_if_let(start);
+        }
+
         let cond = self.parse_expr()?;
         self.expect(&TokenKind::Then)?;
         let then_branch = self.parse_expr()?;
         self.expect(&TokenKind::Else)?;
         let else_branch = self.parse_expr()?;
 
-        let span = start.merge(else_branch.span);
-        Ok(Spanned::new(
+        let span = start.merge(self.ast[else_branch].span);
+        Ok(self.ast.alloc(
             Expr::If {
-                cond: Box::new(cond),
-                then_branch: Box::new(then_branch),
-                else_branch: Box::new(else_branch),
+                cond,
+                then_branch,
+                else_branch,
             },
             span,
         ))
     }
 
-    fn parse_let_expr(&mut self) -> Result<SpannedExpr, LyraError> {
+    /// `if let pat = e then a else b` desugars to `match e with | pat -> a | _ -> b`.
+    fn parse_if_let(&mut self, start: Span) -> Result<ExprId, LyraError> {
+        self.advance(); // consume 'let'
+
+        let pattern = self.parse_pattern()?;
+        self.expect(&TokenKind::Eq)?;
+        let scrutinee = self.parse_expr()?;
+        self.expect(&TokenKind::Then)?;
+        let then_branch = self.parse_expr()?;
+        let else_start = self.peek_span();
+        self.expect(&TokenKind::Else)?;
+        let else_branch = self.parse_expr()?;
+
+        let span = start.merge(self.ast[else_branch].span);
+        Ok(self.ast.alloc(
+            Expr::Match {
+                scrutinee,
+                arms: vec![
+                    MatchArm {
+                        pattern,
+                        body: then_branch,
+                    },
+                    MatchArm {
+                        pattern: Spanned::new(Pattern::Wildcard, else_start),
+                        body: else_branch,
+                    },
+                ],
+            },
+            span,
+        ))
+    }
+
+    fn parse_let_expr(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume 'let'
 
@@ -306,24 +397,103 @@ impl Parser {
         };
 
         self.expect(&TokenKind::Eq)?;
-        let value = self.parse_expr()?;
+        let value = self.parse_body()?;
         self.expect(&TokenKind::In)?;
         let body = self.parse_expr()?;
 
-        let span = start.merge(body.span);
-        Ok(Spanned::new(
+        let span = start.merge(self.ast[body].span);
+        Ok(self.ast.alloc(
             Expr::Let {
                 name,
                 recursive,
                 type_ann,
-                value: Box::new(value),
-                body: Box::new(body),
+                value,
+                body,
+            },
+            span,
+        ))
+    }
+
+    /// A definition's body, with an optional trailing `where` clause.
+    pub(crate) fn parse_body(&mut self) -> Result<ExprId, LyraError> {
+        let body = self.parse_expr()?;
+        let at_where = matches!(self.peek(), TokenKind::Ident(id) if id == "where");
+        if !(at_where && self.at_where_binding()) {
+            return Ok(body);
+        }
+
+        // body where a = x, rec b = y
+        let mut bindings = Vec::new();
+        loop {
+            self.advance(); // consume 'where' or ','
+            let recursive = self.match_token(&TokenKind::Rec);
+            let name = self.expect_ident()?;
+            self.expect(&TokenKind::Eq)?;
+            bindings.push((name, recursive, self.parse_expr()?));
+            if !(self.check(&TokenKind::Comma) && self.at_where_binding()) {
+                break;
+            }
+        }
+        Ok(self.desugar_where(body, bindings))
+    }
+
+    /// `where` is only a keyword when a binding follows it. Also used after
+    /// a `,` to tell another binding from the end of the clause.
+    fn at_where_binding(&self) -> bool {
+        match self.peek_at(1) {
+            TokenKind::Rec => true,
+            TokenKind::Ident(_) => matches!(self.peek_at(2), TokenKind::Eq),
+            _ => false,
+        }
+    }
+
+    /// Whether the `[` ahead opens a slice rather than a list on the next
+    /// line: it's on the same line, with a `:` before any `,` or `]`.
+    fn at_slice(&self) -> bool {
+        if self.peek_token().line_start {
+            return false;
+        }
+        let mut depth = 0;
+        for offset in 1.. {
+            match self.peek_at(offset) {
+                TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+                TokenKind::RParen | TokenKind::RBrace => depth -= 1,
+                TokenKind::RBracket if depth == 0 => return false,
+                TokenKind::RBracket => depth -= 1,
+                TokenKind::Comma if depth == 0 => return false,
+                TokenKind::Colon if depth == 0 => return true,
+                TokenKind::Eof => return false,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// `value[start:end]`, either bound optional.
+    fn parse_slice(&mut self, value: ExprId) -> Result<ExprId, LyraError> {
+        self.advance(); // consume '['
+        let start = match self.peek() {
+            TokenKind::Colon => None,
+            _ => Some(self.parse_expr()?),
+        };
+        self.expect(&TokenKind::Colon)?;
+        let end = match self.peek() {
+            TokenKind::RBracket => None,
+            _ => Some(self.parse_expr()?),
+        };
+        self.expect(&TokenKind::RBracket)?;
+        let span = self.ast[value].span.merge(self.previous_span());
+        Ok(self.ast.alloc(
+            Expr::Slice {
+                expr: value,
+                start,
+                end,
             },
             span,
         ))
     }
 
-    fn parse_match(&mut self) -> Result<SpannedExpr, LyraError> {
+    fn parse_match(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume 'match'
 
@@ -339,15 +509,9 @@ impl Parser {
             arms.push(self.parse_match_arm()?);
         }
 
-        let last_span = arms.last().map(|a| a.body.span).unwrap_or(start);
+        let last_span = arms.last().map(|a| self.ast[a.body].span).unwrap_or(start);
         let span = start.merge(last_span);
-        Ok(Spanned::new(
-            Expr::Match {
-                scrutinee: Box::new(scrutinee),
-                arms,
-            },
-            span,
-        ))
+        Ok(self.ast.alloc(Expr::Match { scrutinee, arms }, span))
     }
 
     fn parse_match_arm(&mut self) -> Result<MatchArm, LyraError> {
@@ -357,78 +521,228 @@ impl Parser {
         Ok(MatchArm { pattern, body })
     }
 
-    fn parse_call(&mut self, func: SpannedExpr) -> Result<SpannedExpr, LyraError> {
+    fn parse_call(&mut self, func: ExprId) -> Result<ExprId, LyraError> {
         self.advance(); // consume '('
-        let mut args = Vec::new();
+        let mut args: Vec<Arg> = Vec::new();
+        let mut placeholders = Vec::new();
         if !matches!(self.peek(), TokenKind::RParen) {
-            args.push(self.parse_expr()?);
-            while self.match_token(&TokenKind::Comma) {
-                args.push(self.parse_expr()?);
+            loop {
+                // name: value
+                let label = if matches!(self.peek(), TokenKind::Ident(_))
+                    && matches!(self.peek_at(1), TokenKind::Colon)
+                {
+                    let label = self.expect_ident()?;
+                    self.advance(); // consume ':'
+                    Some(label)
+                } else {
+                    None
+                };
+                let value = self.parse_call_arg(&mut placeholders)?;
+                if label.is_none() && args.iter().any(|a| a.label.is_some()) {
+                    return Err(LyraError::UnexpectedToken {
+                        expected: "a labeled argument (positional ones come first)".to_string(),
+                        found: "a positional argument".to_string(),
+                        span: self.ast[value].span,
+                    });
+                }
+                args.push(Arg { label, value });
+                if !self.match_token(&TokenKind::Comma)
+                    || matches!(self.peek(), TokenKind::RParen)
+                {
+                    break;
+                }
             }
         }
         self.expect(&TokenKind::RParen)?;
-        let span = func.span.merge(self.previous_span());
-        Ok(Spanned::new(
+        let span = self.ast[func].span.merge(self.previous_span());
+        let node = if args.iter().any(|a| a.label.is_some()) {
+            Expr::LabeledApp { func, args }
+        } else {
             Expr::App {
-                func: Box::new(func),
-                args,
+                func,
+                args: args.into_iter().map(|a| a.value).collect(),
+            }
+        };
+        let call = self.ast.alloc(node, span);
+
+        // f(_, 10)  desugars to  fn ($0) -> f($0, 10)
+        if placeholders.is_empty() {
+            return Ok(call);
+        }
+        Ok(self.ast.alloc(
+            Expr::Lambda {
+                params: placeholders,
+                body: call,
             },
             span,
         ))
     }
 
-    fn parse_record_literal(&mut self) -> Result<SpannedExpr, LyraError> {
+    /// Parse one call argument. A bare `_` becomes a fresh placeholder
+    /// parameter that the enclosing call is wrapped in a lambda over.
+    fn parse_call_arg(
+        &mut self,
+        placeholders: &mut Vec<LambdaParam>,
+    ) -> Result<ExprId, LyraError> {
+        if matches!(self.peek(), TokenKind::Underscore)
+            && matches!(self.peek_at(1), TokenKind::Comma | TokenKind::RParen)
+        {
+            let span = self.peek_span();
+            self.advance(); // consume '_'
+            let name = format!("${}", placeholders.len());
+            placeholders.push(LambdaParam {
+                name: Spanned::new(name.clone(), span),
+                type_ann: None,
+            });
+            return Ok(self.ast.alloc(Expr::Var(name), span));
+        }
+        self.parse_expr()
+    }
+
+    fn parse_record_literal(&mut self) -> Result<ExprId, LyraError> {
         let start = self.peek_span();
         self.advance(); // consume '{'
 
         let mut fields = Vec::new();
         if !matches!(self.peek(), TokenKind::RBrace) {
-            // Parse first field: name: expr
-            let name = self.expect_ident()?;
-            self.expect(&TokenKind::Colon)?;
-            let value = self.parse_expr()?;
-            fields.push((name.node, value));
+            fields.push(self.parse_record_field()?);
 
             while self.match_token(&TokenKind::Comma) {
                 if matches!(self.peek(), TokenKind::RBrace) {
                     break; // allow trailing comma
                 }
-                let name = self.expect_ident()?;
-                self.expect(&TokenKind::Colon)?;
-                let value = self.parse_expr()?;
-                fields.push((name.node, value));
+                fields.push(self.parse_record_field()?);
             }
         }
 
         self.expect(&TokenKind::RBrace)?;
         let span = start.merge(self.previous_span());
-        Ok(Spanned::new(Expr::Record(fields), span))
+        Ok(self.ast.alloc(Expr::Record(fields), span))
+    }
+
+    /// `name: expr`, or just `name` for `name: name`.
+    fn parse_record_field(&mut self) -> Result<(String, ExprId), LyraError> {
+        let name = self.expect_ident()?;
+        if !self.match_token(&TokenKind::Colon) {
+            let value = self.ast.alloc(Expr::Var(name.node.clone()), name.span);
+            return Ok((name.node, value));
+        }
+        let value = self.parse_expr()?;
+        Ok((name.node, value))
     }
 
     fn parse_interpolated_string(
         &mut self,
         parts: Vec<InterpPart>,
         span: Span,
-    ) -> Result<SpannedExpr, LyraError> {
+    ) -> Result<ExprId, LyraError> {
         let mut interp_parts = Vec::new();
         for part in parts {
             match part {
                 InterpPart::Literal(s) => {
                     interp_parts.push(InterpolationPart::Literal(s));
                 }
-                InterpPart::Tokens(tokens) => {
+                InterpPart::Tokens(tokens, spec) => {
                     // Add an Eof token so the sub-parser knows when to stop
                     let mut tokens_with_eof = tokens;
                     tokens_with_eof.push(crate::lexer::token::Token::new(
                         TokenKind::Eof,
                         span,
                     ));
+                    // The sub-parser adds to this parser's arena
                     let mut sub_parser = super::Parser::new(tokens_with_eof);
-                    let expr = sub_parser.parse_expr()?;
-                    interp_parts.push(InterpolationPart::Expr(expr));
+                    sub_parser.ast = std::mem::take(&mut self.ast);
+                    let expr = sub_parser.parse_expr();
+                    self.ast = sub_parser.ast;
+                    let expr = expr?;
+                    interp_parts.push(InterpolationPart::Expr(expr, spec));
                 }
             }
         }
-        Ok(Spanned::new(Expr::Interpolation(interp_parts), span))
+        Ok(self.ast.alloc(Expr::Interpolation(interp_parts), span))
+    }
+}
+
+impl Parser {
+    /// `r?.field` is `match r with | Some(v) -> Some(v.field) | None -> None`,
+    /// for the program's `type Option a = Some a | None`.
+    fn desugar_optional_field(&mut self, option: ExprId, field: String, span: Span) -> ExprId {
+        let bound = "$some".to_string();
+        let option_span = self.ast[option].span;
+        let pattern = |name: &str, args| {
+            let node = Pattern::Constructor {
+                name: name.to_string(),
+                args,
+            };
+            Spanned::new(node, option_span)
+        };
+        let value = self.ast.alloc(Expr::Var(bound.clone()), option_span);
+        let access = self.ast.alloc(Expr::FieldAccess { expr: value, field }, span);
+        let some = self.ast.alloc(Expr::Var("Some".to_string()), span);
+        let some_access = self.ast.alloc(
+            Expr::App {
+                func: some,
+                args: vec![access],
+            },
+            span,
+        );
+        let none = self.ast.alloc(Expr::Var("None".to_string()), span);
+        let arms = vec![
+            MatchArm {
+                pattern: pattern("Some", vec![Spanned::new(Pattern::Var(bound), option_span)]),
+                body: some_access,
+            },
+            MatchArm {
+                pattern: pattern("None", vec![]),
+                body: none,
+            },
+        ];
+        self.ast.alloc(
+            Expr::Match {
+                scrutinee: option,
+                arms,
+            },
+            span,
+        )
+    }
+
+    /// `body where a = x, b = y` is `let a = x in let b = y in body`, so each
+    /// binding sees the ones before it. When the body is a function, the
+    /// bindings go inside it, where they can use its parameters.
+    fn desugar_where(
+        &mut self,
+        body: ExprId,
+        bindings: Vec<(Spanned<String>, bool, ExprId)>,
+    ) -> ExprId {
+        if let Expr::Lambda { body: inner, .. } = self.ast[body].node {
+            let inner = self.desugar_where(inner, bindings);
+            let span = self.ast[body].span.merge(self.ast[inner].span);
+            let lambda = &mut self.ast[body];
+            if let Expr::Lambda { body, .. } = &mut lambda.node {
+                *body = inner;
+            }
+            lambda.span = span;
+            return body;
+        }
+
+        let body_span = self.ast[body].span;
+        let span = bindings
+            .last()
+            .map_or(body_span, |(_, _, value)| body_span.merge(self.ast[*value].span));
+        bindings
+            .into_iter()
+            .rev()
+            .fold(body, |body, (name, recursive, value)| {
+                self.ast.alloc(
+                    Expr::Let {
+                        name,
+                        recursive,
+                        type_ann: None,
+                        value,
+                        body,
+                    },
+                    span,
+                )
+            })
     }
 }
@@ -3,19 +3,83 @@ pub mod expr;
 pub mod pattern;
 pub mod types;
 
+use std::cell::Cell;
+
 use crate::ast::*;
 use crate::error::LyraError;
 use crate::lexer::token::{Token, TokenKind};
 use crate::span::{Span, Spanned};
 
+/// How deeply expressions, patterns and types may nest unless
+/// `with_max_depth` says otherwise. Parsing recurses once per level, so
+/// this keeps well within the native stack.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+thread_local! {
+    static MAX_DEPTH: Cell<usize> = const { Cell::new(DEFAULT_MAX_DEPTH) };
+}
+
+/// Run `f` with parsers allowing `max_depth` levels of nesting, for
+/// machine-generated code nested deeper than people write it. Parsers made
+/// outside `f` keep their limit, and the outer one is restored afterwards.
+pub fn with_max_depth<T>(max_depth: usize, f: impl FnOnce() -> T) -> T {
+    let outer = MAX_DEPTH.with(|m| m.replace(max_depth));
+    // Restore the outer limit even if `f` panics
+    struct Restore(usize);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            MAX_DEPTH.with(|m| m.set(self.0));
+        }
+    }
+    let _restore = Restore(outer);
+    f()
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    /// The arena the parsed expressions go into.
+    pub(crate) ast: Ast,
+    /// How many `quote`s enclose the current expression; `unquote` needs one.
+    pub(crate) quote_depth: usize,
+    /// How many expressions, patterns and types enclose the current one.
+    depth: usize,
+    max_depth: usize,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, pos: 0 }
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        // Navigation relies on a trailing Eof; hand-built token streams may lack one
+        if !matches!(tokens.last(), Some(t) if matches!(t.kind, TokenKind::Eof)) {
+            let end = tokens.last().map_or(Span::default(), |t| Span::new(t.span.end, t.span.end));
+            tokens.push(Token::new(TokenKind::Eof, end));
+        }
+        Parser {
+            tokens,
+            pos: 0,
+            ast: Ast::new(),
+            quote_depth: 0,
+            depth: 0,
+            max_depth: MAX_DEPTH.with(Cell::get),
+        }
+    }
+
+    /// Run `parse` one level of nesting deeper, failing once that passes
+    /// the limit rather than overflowing the stack.
+    pub(crate) fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, LyraError>,
+    ) -> Result<T, LyraError> {
+        if self.depth >= self.max_depth {
+            return Err(LyraError::TooDeeplyNested {
+                limit: self.max_depth,
+                span: self.peek_span(),
+            });
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
 
     pub fn parse_program(&mut self) -> Result<Vec<Decl>, LyraError> {
@@ -32,6 +96,12 @@ impl Parser {
         &self.tokens[self.pos].kind
     }
 
+    /// Look `offset` tokens past the current one, clamped to the trailing Eof.
+    pub(crate) fn peek_at(&self, offset: usize) -> &TokenKind {
+        let idx = (self.pos + offset).min(self.tokens.len() - 1);
+        &self.tokens[idx].kind
+    }
+
     pub(crate) fn peek_token(&self) -> &Token {
         &self.tokens[self.pos]
     }
@@ -97,6 +167,21 @@ impl Parser {
         }
     }
 
+    /// Extend a type name followed by `.Ctor` into the qualified
+    /// constructor name `Type.Ctor`; any other name is returned as is.
+    pub(crate) fn qualify_constructor(&mut self, name: Spanned<String>) -> Spanned<String> {
+        let is_upper = |s: &str| s.starts_with(|c: char| c.is_uppercase());
+        let ctor = match (self.peek(), self.peek_at(1)) {
+            (TokenKind::Dot, TokenKind::Ident(ctor)) if is_upper(&name.node) && is_upper(ctor) => {
+                ctor.clone()
+            }
+            _ => return name,
+        };
+        self.advance();
+        let end = self.advance().span;
+        Spanned::new(format!("{}.{}", name.node, ctor), name.span.merge(end))
+    }
+
     /// Check if next token is an uppercase identifier (constructor).
     #[allow(dead_code)]
     pub(crate) fn peek_is_constructor(&self) -> bool {
@@ -110,7 +195,7 @@ impl Parser {
 /// Binding power for infix operators (left_bp, right_bp).
 pub(crate) fn infix_binding_power(kind: &TokenKind) -> Option<(u8, u8)> {
     match kind {
-        TokenKind::PipeRight => Some((1, 2)),
+        TokenKind::PipeRight | TokenKind::PipeTap => Some((1, 2)),
         TokenKind::Or => Some((3, 4)),
         TokenKind::And => Some((5, 6)),
         TokenKind::EqEq | TokenKind::NotEq => Some((7, 8)),
@@ -118,17 +203,19 @@ pub(crate) fn infix_binding_power(kind: &TokenKind) -> Option<(u8, u8)> {
         TokenKind::ColonColon => Some((12, 11)), // right-associative
         TokenKind::Plus | TokenKind::Minus => Some((13, 14)),
         TokenKind::Star | TokenKind::Slash | TokenKind::Percent => Some((15, 16)),
+        TokenKind::StarStar => Some((18, 17)), // right-associative, binds tighter than unary minus
         _ => None,
     }
 }
 
-pub(crate) fn token_to_binop(kind: &TokenKind) -> BinOp {
-    match kind {
+pub(crate) fn token_to_binop(kind: &TokenKind) -> Option<BinOp> {
+    let op = match kind {
         TokenKind::Plus => BinOp::Add,
         TokenKind::Minus => BinOp::Sub,
         TokenKind::Star => BinOp::Mul,
         TokenKind::Slash => BinOp::Div,
         TokenKind::Percent => BinOp::Mod,
+        TokenKind::StarStar => BinOp::Pow,
         TokenKind::EqEq => BinOp::Eq,
         TokenKind::NotEq => BinOp::NotEq,
         TokenKind::Lt => BinOp::Lt,
@@ -138,10 +225,23 @@ pub(crate) fn token_to_binop(kind: &TokenKind) -> BinOp {
         TokenKind::And => BinOp::And,
         TokenKind::Or => BinOp::Or,
         TokenKind::ColonColon => BinOp::Cons,
-        _ => unreachable!("not a binary operator: {:?}", kind),
-    }
+        _ => return None,
+    };
+    Some(op)
+}
+
+pub fn parse(tokens: Vec<Token>) -> Result<Program, LyraError> {
+    let mut ast = Ast::new();
+    let decls = parse_into(&mut ast, tokens)?;
+    Ok(Program { ast, decls })
 }
 
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Decl>, LyraError> {
-    Parser::new(tokens).parse_program()
+/// Parse declarations whose expressions go into an existing arena, as an
+/// imported file's join the importer's.
+pub fn parse_into(ast: &mut Ast, tokens: Vec<Token>) -> Result<Vec<Decl>, LyraError> {
+    let mut parser = Parser::new(tokens);
+    parser.ast = std::mem::take(ast);
+    let decls = parser.parse_program();
+    *ast = parser.ast;
+    decls
 }
@@ -7,7 +7,11 @@ use super::Parser;
 
 impl Parser {
     pub fn parse_pattern(&mut self) -> Result<SpannedPattern, LyraError> {
-        let mut pat = self.parse_pattern_atom()?;
+        self.nested(Self::parse_cons_pattern)
+    }
+
+    fn parse_cons_pattern(&mut self) -> Result<SpannedPattern, LyraError> {
+        let mut pat = self.parse_string_pattern()?;
 
         // Cons pattern: hd :: tl
         if matches!(self.peek(), TokenKind::ColonColon) {
@@ -23,6 +27,34 @@ impl Parser {
         Ok(pat)
     }
 
+    /// String prefix and suffix patterns: `"GET " ++ rest`, `name ++ ".txt"`.
+    /// `++` groups to the right, so `"<" ++ tag ++ ">"` takes both ends.
+    fn parse_string_pattern(&mut self) -> Result<SpannedPattern, LyraError> {
+        let left = self.parse_pattern_atom()?;
+        if !matches!(self.peek(), TokenKind::PlusPlus) {
+            return Ok(left);
+        }
+        self.advance();
+        let right = self.parse_string_pattern()?;
+        let span = left.span.merge(right.span);
+        let node = match (left.node, right.node) {
+            (Pattern::StringLit(prefix), rest) => {
+                Pattern::StringPrefix(prefix, Box::new(Spanned::new(rest, right.span)))
+            }
+            (rest, Pattern::StringLit(suffix)) => {
+                Pattern::StringSuffix(Box::new(Spanned::new(rest, left.span)), suffix)
+            }
+            (l, r) => {
+                return Err(LyraError::UnexpectedToken {
+                    expected: "a string literal on one side of '++'".to_string(),
+                    found: format!("'{} ++ {}'", l, r),
+                    span,
+                })
+            }
+        };
+        Ok(Spanned::new(node, span))
+    }
+
     fn parse_pattern_atom(&mut self) -> Result<SpannedPattern, LyraError> {
         let tok = self.peek_token().clone();
         match &tok.kind {
@@ -64,8 +96,9 @@ impl Parser {
             TokenKind::Ident(name) => {
                 let name = name.clone();
                 if name.starts_with(|c: char| c.is_uppercase()) {
-                    // Constructor pattern
+                    // Constructor pattern, possibly qualified by its type
                     self.advance();
+                    let name = self.qualify_constructor(Spanned::new(name, tok.span)).node;
                     let mut args = Vec::new();
                     if matches!(self.peek(), TokenKind::LParen) {
                         self.advance(); // consume '('
@@ -8,6 +8,10 @@ use super::Parser;
 impl Parser {
     /// Parse a type annotation (e.g., after `:` in `let x : Int = ...`)
     pub fn parse_type_annotation(&mut self) -> Result<SpannedTypeAnn, LyraError> {
+        self.nested(Self::parse_arrow_type)
+    }
+
+    fn parse_arrow_type(&mut self) -> Result<SpannedTypeAnn, LyraError> {
         let lhs = self.parse_type_atom()?;
 
         // Arrow type: A -> B
@@ -41,26 +45,25 @@ impl Parser {
                 // Type application: Option Int, List a
                 // Collect arguments while we see type atoms that start valid type expressions
                 let mut args = Vec::new();
-                loop {
-                    match self.peek() {
-                        TokenKind::Ident(_) | TokenKind::LParen | TokenKind::LBracket => {
-                            // Only consume if the base is a Named type (not a var)
-                            if matches!(base.node, TypeAnnotation::Named(_)) {
-                                args.push(self.parse_type_atom()?);
-                            } else {
-                                break;
-                            }
-                        }
-                        _ => break,
+                while let TokenKind::Ident(_) | TokenKind::LParen | TokenKind::LBracket = self.peek() {
+                    // Arguments go on the same line, so a type can end a declaration
+                    if self.peek_token().line_start {
+                        break;
+                    }
+                    // Only consume if the base is a Named type (not a var)
+                    if matches!(base.node, TypeAnnotation::Named(_)) {
+                        args.push(self.parse_type_atom()?);
+                    } else {
+                        break;
                     }
                 }
 
-                if args.is_empty() {
-                    Ok(base)
-                } else {
-                    let end = args.last().unwrap().span;
-                    let span = tok.span.merge(end);
-                    Ok(Spanned::new(TypeAnnotation::App(Box::new(base), args), span))
+                match args.last() {
+                    None => Ok(base),
+                    Some(last) => {
+                        let span = tok.span.merge(last.span);
+                        Ok(Spanned::new(TypeAnnotation::App(Box::new(base), args), span))
+                    }
                 }
             }
 
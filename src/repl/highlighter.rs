@@ -6,7 +6,14 @@ use rustyline::Context;
 use rustyline::Helper;
 use std::borrow::Cow;
 
-pub struct LyraHelper;
+use crate::analysis;
+use crate::types::env::TypeEnv;
+
+#[derive(Default)]
+pub struct LyraHelper {
+    /// Names defined so far in the session, for completion.
+    pub env: TypeEnv,
+}
 
 const KEYWORDS: &[&str] = &[
     "let", "in", "fn", "match", "with", "if", "then", "else", "type", "rec", "true", "false",
@@ -18,11 +25,27 @@ impl Completer for LyraHelper {
     type Candidate = Pair;
     fn complete(
         &self,
-        _line: &str,
-        _pos: usize,
+        line: &str,
+        pos: usize,
         _ctx: &Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Pair>)> {
-        Ok((0, vec![]))
+        let analysis = analysis::analyze(line, line[..pos].chars().count(), &self.env);
+        if analysis.prefix.is_empty() {
+            return Ok((pos, vec![]));
+        }
+        let mut candidates: Vec<Pair> = KEYWORDS
+            .iter()
+            .filter(|k| k.starts_with(&analysis.prefix))
+            .map(|k| Pair {
+                display: k.to_string(),
+                replacement: k.to_string(),
+            })
+            .collect();
+        candidates.extend(analysis.completions().map(|s| Pair {
+            display: format!("{} : {}", s.name, s.ty),
+            replacement: s.name.clone(),
+        }));
+        Ok((pos - analysis.prefix.len(), candidates))
     }
 }
 
@@ -1,30 +1,50 @@
 pub mod highlighter;
 
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
+use crate::budget::{self, Budget};
+use crate::ast::{Ast, Decl};
+use crate::debugger::{self, Resume, Stop};
 use crate::eval;
 use crate::eval::env::Env;
+use crate::eval::value::Value;
 use crate::lexer;
+use crate::macros::Macros;
 use crate::parser;
 use crate::types::env::TypeEnv;
 use crate::types::infer::Inferencer;
-use crate::types::TypeVarGen;
+use crate::types::{MonoType, TypeScheme, TypeVarGen};
 use crate::stdlib;
 
 use highlighter::LyraHelper;
 
+/// Set by Ctrl-C to stop the evaluation in progress.
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
 pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
+    run_repl_with(&[])
+}
+
+/// Start the REPL with each of `files` loaded, in order, as `:load` would,
+/// so their bindings are in scope at the first prompt.
+pub fn run_repl_with(files: &[String]) -> Result<(), Box<dyn std::error::Error>> {
     let config = rustyline::Config::builder()
         .auto_add_history(true)
         .build();
 
-    let helper = LyraHelper;
+    let helper = LyraHelper::default();
     let mut rl = Editor::with_config(config)?;
     rl.set_helper(Some(helper));
 
     // Load history
-    let history_path = dirs_next().unwrap_or_default();
+    let history_path = home_file(".lyra_history").unwrap_or_default();
     let _ = rl.load_history(&history_path);
 
     // Persistent environments
@@ -32,15 +52,52 @@ pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
     let runtime_env = Env::new();
     let mut gen = TypeVarGen::new();
     let mut inferencer = Inferencer::new();
+    let mut defs: HashMap<String, Definition> = HashMap::new();
+    let mut macros = Macros::new();
 
     stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+    debugger::set_handler(debug_stop);
+    // At the prompt the line editor reads Ctrl-C as a key; while code runs
+    // it's a signal, which stops that evaluation rather than the REPL
+    let _ = ctrlc::set_handler(|| INTERRUPT.store(true, Ordering::Relaxed));
+    let stdlib_names: HashSet<String> = runtime_env.names().into_iter().collect();
 
     println!("\x1b[1;35mLyra\x1b[0m v10.0 — A functional programming language");
     println!("Type \x1b[1m:help\x1b[0m for help, \x1b[1m:quit\x1b[0m to exit\n");
 
+    let mut settings = ReplSettings::default();
+    if let Some(rc_path) = home_file(".lyrarc").filter(|p| p.exists()) {
+        load_rc(
+            &rc_path,
+            &mut type_env,
+            &runtime_env,
+            &mut inferencer,
+            &mut defs,
+            &mut macros,
+            &mut settings,
+        );
+    }
+
+    for file in files {
+        load_file(
+            file,
+            &mut type_env,
+            &runtime_env,
+            &mut inferencer,
+            &mut defs,
+            &mut macros,
+            &settings,
+        );
+    }
+
     let mut buffer = String::new();
+    // How many results are bound to `_1`, `_2`, ...
+    let mut results = 0;
 
     loop {
+        if let Some(helper) = rl.helper_mut() {
+            helper.env = type_env.clone();
+        }
         let prompt = if buffer.is_empty() {
             "\x1b[1;35mlyra>\x1b[0m "
         } else {
@@ -55,6 +112,49 @@ pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
                     continue;
                 }
 
+                // `:edit` also works mid-input, taking over the unfinished buffer
+                if line == ":edit" || line.starts_with(":edit ") {
+                    let initial = match line[5..].trim() {
+                        "" => std::mem::take(&mut buffer),
+                        name => match defs.get(name) {
+                            Some(Definition {
+                                doc,
+                                source: Some(source),
+                            }) => {
+                                let mut text = String::new();
+                                for line in doc.iter().flat_map(|d| d.lines()) {
+                                    text.push_str(&format!("--- {}\n", line));
+                                }
+                                text + source + "\n"
+                            }
+                            _ => {
+                                eprintln!("  \x1b[1;31merror\x1b[0m: no definition of '{}' to edit", name);
+                                continue;
+                            }
+                        },
+                    };
+                    match edit_in_editor(&initial) {
+                        Ok(edited) if !edited.trim().is_empty() => {
+                            let result = run_input(
+                                &edited,
+                                &mut type_env,
+                                &runtime_env,
+                                &mut inferencer,
+                                &mut defs,
+                                &mut macros,
+                                &settings,
+                            );
+                            if let Some((value, ty)) = result {
+                                show_result(value, &ty, &mut type_env, &runtime_env, &settings, &mut results);
+                            }
+                            show_warnings(&mut inferencer, &edited, "<repl>");
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("  \x1b[1;31merror\x1b[0m: {}", e),
+                    }
+                    continue;
+                }
+
                 // REPL commands (only on first line)
                 if buffer.is_empty() {
                     match line {
@@ -67,6 +167,20 @@ pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
                             println!("  (type environment display not yet implemented)");
                             continue;
                         }
+                        _ if line == ":set" || line.starts_with(":set ") => {
+                            if let Err(e) = apply_set(&line[4..], &mut settings) {
+                                eprintln!("  \x1b[1;31merror\x1b[0m: {}", e);
+                            }
+                            continue;
+                        }
+                        ":bindings" => {
+                            show_bindings(&runtime_env, &type_env, &stdlib_names);
+                            continue;
+                        }
+                        ":memory" => {
+                            show_memory(&runtime_env, &stdlib_names);
+                            continue;
+                        }
                         _ if line.starts_with(":type ") => {
                             let expr_src = &line[6..];
                             match infer_type(expr_src, &type_env, &mut inferencer) {
@@ -75,33 +189,50 @@ pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
                             }
                             continue;
                         }
-                        _ if line.starts_with(":load ") => {
-                            let path = line[6..].trim();
-                            match std::fs::read_to_string(path) {
-                                Ok(source) => {
-                                    match eval_line(
-                                        &source,
-                                        &mut type_env,
-                                        &runtime_env,
-                                        &mut inferencer,
-                                    ) {
-                                        Ok(_) => {
-                                            println!(
-                                                "  \x1b[32mLoaded {}\x1b[0m",
-                                                path
-                                            );
-                                        }
-                                        Err(e) => {
-                                            eprintln!("{}", e.render(&source, path));
-                                        }
+                        _ if line == ":search" || line.starts_with(":search ") => {
+                            show_search(line[7..].trim(), &type_env);
+                            continue;
+                        }
+                        _ if line.starts_with(":doc ") => {
+                            show_doc(line[5..].trim(), &type_env, &defs);
+                            continue;
+                        }
+                        _ if line == ":break" || line.starts_with(":break ") => {
+                            match line[6..].trim() {
+                                "" => show_breakpoints(),
+                                spec => match parse_breakpoint(spec) {
+                                    Some((file, line)) => {
+                                        debugger::add_breakpoint(file, line);
+                                        println!("  breakpoint at {}:{}", file, line);
                                     }
-                                }
-                                Err(e) => {
-                                    eprintln!("\x1b[1;31merror\x1b[0m: {}", e);
-                                }
+                                    None => eprintln!(
+                                        "  \x1b[1;31merror\x1b[0m: expected :break <file>:<line>"
+                                    ),
+                                },
                             }
                             continue;
                         }
+                        ":step" | ":s" => {
+                            debugger::step_next();
+                            println!("  stepping from the first line of the next input");
+                            continue;
+                        }
+                        ":continue" | ":c" => {
+                            eprintln!("  \x1b[1;31merror\x1b[0m: not stopped at a breakpoint");
+                            continue;
+                        }
+                        _ if line.starts_with(":load ") => {
+                            load_file(
+                                line[6..].trim(),
+                                &mut type_env,
+                                &runtime_env,
+                                &mut inferencer,
+                                &mut defs,
+                                &mut macros,
+                                &settings,
+                            );
+                            continue;
+                        }
                         _ => {}
                     }
                 }
@@ -120,16 +251,19 @@ pub fn run_repl() -> Result<(), Box<dyn std::error::Error>> {
                 let source = buffer.clone();
                 buffer.clear();
 
-                // Normal pipeline: lex -> parse -> typecheck -> eval
-                match eval_line(&source, &mut type_env, &runtime_env, &mut inferencer) {
-                    Ok(Some((value, ty))) => {
-                        println!("  \x1b[1m{}\x1b[0m \x1b[36m: {}\x1b[0m", value, ty);
-                    }
-                    Ok(None) => {} // declaration bound, no output
-                    Err(e) => {
-                        eprintln!("{}", e.render(&source, "<repl>"));
-                    }
+                let result = run_input(
+                    &source,
+                    &mut type_env,
+                    &runtime_env,
+                    &mut inferencer,
+                    &mut defs,
+                    &mut macros,
+                    &settings,
+                );
+                if let Some((value, ty)) = result {
+                    show_result(value, &ty, &mut type_env, &runtime_env, &settings, &mut results);
                 }
+                show_warnings(&mut inferencer, &source, "<repl>");
             }
             Err(ReadlineError::Interrupted) => {
                 if !buffer.is_empty() {
@@ -203,20 +337,217 @@ fn is_complete(source: &str) -> bool {
     true
 }
 
+/// Evaluate a complete input, printing any error, and return its value.
+fn run_input(
+    source: &str,
+    type_env: &mut TypeEnv,
+    runtime_env: &Env,
+    inferencer: &mut Inferencer,
+    defs: &mut HashMap<String, Definition>,
+    macros: &mut Macros,
+    settings: &ReplSettings,
+) -> Option<(Value, MonoType)> {
+    // Normal pipeline: lex -> parse -> typecheck -> eval
+    let result = debugger::with_source("<repl>", source, || {
+        eval_line(source, type_env, runtime_env, inferencer, defs, macros, settings.budget)
+    });
+    match result {
+        Ok(result) => result, // `None` for a declaration, which prints nothing
+        Err(e) => {
+            eprintln!("{}", e.render(source, "<repl>"));
+            None
+        }
+    }
+}
+
+/// Print an input's value. One other than `()` is first bound to `it` and
+/// to the next of `_1`, `_2`, ..., counted by `results`, and shown with
+/// that name.
+fn show_result(
+    value: Value,
+    ty: &MonoType,
+    type_env: &mut TypeEnv,
+    runtime_env: &Env,
+    settings: &ReplSettings,
+    results: &mut usize,
+) {
+    let mut line = String::from("  ");
+    if value != Value::Unit {
+        *results += 1;
+        let name = format!("_{}", results);
+        line.push_str(&format!("\x1b[2m{} =\x1b[0m ", name));
+        // Free type variables stay generic, as a `let` would leave them
+        let env_free = type_env.free_vars();
+        let scheme = TypeScheme {
+            vars: ty.free_vars().difference(&env_free).copied().collect(),
+            ty: ty.clone(),
+            constraints: vec![],
+        };
+        for name in [name, "it".to_string()] {
+            type_env.insert(name.clone(), scheme.clone());
+            runtime_env.set(name, value.clone());
+        }
+    }
+    line.push_str(&format!("\x1b[1m{}\x1b[0m", value));
+    if settings.show_types {
+        line.push_str(&format!(" \x1b[36m: {}\x1b[0m", ty));
+    }
+    println!("{}", line);
+}
+
+/// Evaluate a file's declarations into the session, for `:load` and
+/// `lyra repl --load`. Errors are reported, leaving whatever loaded before
+/// them bound.
+fn load_file(
+    path: &str,
+    type_env: &mut TypeEnv,
+    runtime_env: &Env,
+    inferencer: &mut Inferencer,
+    defs: &mut HashMap<String, Definition>,
+    macros: &mut Macros,
+    settings: &ReplSettings,
+) {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("\x1b[1;31merror\x1b[0m: cannot read {}: {}", path, e);
+            return;
+        }
+    };
+    let result = debugger::with_source(path, &source, || {
+        eval_line(&source, type_env, runtime_env, inferencer, defs, macros, settings.budget)
+    });
+    match result {
+        Ok(_) => println!("  \x1b[32mLoaded {}\x1b[0m", path),
+        Err(e) => eprintln!("{}", e.render(&source, path)),
+    }
+    show_warnings(inferencer, &source, path);
+}
+
+/// Print the warnings inference left for `source`, after its value or
+/// error, with the same carets as errors.
+fn show_warnings(inferencer: &mut Inferencer, source: &str, filename: &str) {
+    for warning in inferencer.take_warnings() {
+        eprintln!("{}", warning.render(source, filename, crate::diagnostic::Format::Ansi));
+    }
+}
+
+/// Open `initial` in `$VISUAL` or `$EDITOR` (falling back to `vi`) and
+/// return the saved text.
+fn edit_in_editor(initial: &str) -> std::io::Result<String> {
+    let (path, mut file) = create_edit_file()?;
+    let written = file.write_all(initial.as_bytes());
+    drop(file);
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&path);
+        return Err(e);
+    }
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Editors are often configured with arguments, like `code --wait`
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(&path)
+        .status();
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    let status = status?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!(
+            "{} exited with {}; input discarded",
+            program, status
+        )));
+    }
+    edited
+}
+
+/// Create the file `:edit` hands the editor: new, under a random name in
+/// the temp directory and, on Unix, readable only by us, so no one else
+/// can have planted it or guess where it'll be.
+fn create_edit_file() -> std::io::Result<(std::path::PathBuf, std::fs::File)> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut attempts = 0;
+    loop {
+        let name = format!("lyra-edit-{:016x}.lyra", eval::builtins::random_u64());
+        let path = std::env::temp_dir().join(name);
+        match options.open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists && attempts < 8 => {
+                attempts += 1
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// What the REPL remembers about a user `let` or `type`.
+struct Definition {
+    doc: Option<String>,
+    /// The declaration as typed, without its doc comment.
+    source: Option<String>,
+}
+
+/// The text of `decl` in `source`, from its `let`/`type` keyword to its end.
+fn decl_source(source: &str, ast: &Ast, decl: &Decl) -> Option<String> {
+    let (name, end) = match decl {
+        Decl::Let { name, body, .. } => (name, ast[*body].span.end),
+        Decl::Type { name, variants, .. } => (name, variants.last()?.span.end),
+        _ => return None,
+    };
+    // Spans count chars; walk back from the name over `rec` to the keyword
+    let chars: Vec<char> = source.chars().collect();
+    let before: String = chars.get(..name.span.start)?.iter().collect();
+    let before = before.trim_end();
+    let before = before.strip_suffix("rec").map_or(before, str::trim_end);
+    let start = before
+        .strip_suffix("let")
+        .or_else(|| before.strip_suffix("type"))?
+        .chars()
+        .count();
+    Some(chars.get(start..end)?.iter().collect())
+}
+
 fn eval_line(
     source: &str,
     type_env: &mut TypeEnv,
     runtime_env: &Env,
     inferencer: &mut Inferencer,
+    defs: &mut HashMap<String, Definition>,
+    macros: &mut Macros,
+    budget: Budget,
 ) -> Result<Option<(eval::value::Value, crate::types::MonoType)>, crate::error::LyraError> {
     let tokens = lexer::tokenize(source).map_err(|errs| errs[0].clone())?;
-    let decls = parser::parse(tokens)?;
+    let crate::ast::Program { mut ast, mut decls } = parser::parse(tokens)?;
+    macros.expand(&mut ast, &decls)?;
+    crate::resolve_modules(&mut ast, &mut decls, "<repl>")?;
+    eval::resolve::resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
 
     let mut last_result = None;
 
     for decl in &decls {
-        let ty = inferencer.infer_decl(type_env, decl)?;
-        let val = eval::eval_decl(runtime_env, decl)?;
+        // Warnings wait in the inferencer for `show_warnings`
+        let ty = inferencer.infer_decl(type_env, &ast, decl)?;
+        let val = budget::interruptible(&INTERRUPT, || {
+            budget::with_budget(budget, || eval::eval_decl(runtime_env, &ast, decl))
+        })?;
+
+        // Redefining a name replaces its documentation and source
+        if let Some(name) = decl.name() {
+            defs.insert(
+                name.to_string(),
+                Definition {
+                    doc: decl.doc().map(str::to_string),
+                    source: decl_source(source, &ast, decl),
+                },
+            );
+        }
 
         if let (Some(v), Some(t)) = (val, ty) {
             last_result = Some((v, t));
@@ -226,23 +557,318 @@ fn eval_line(
     Ok(last_result)
 }
 
+/// Print the type and doc comment of a binding or type for `:doc`.
+fn show_doc(name: &str, type_env: &TypeEnv, defs: &HashMap<String, Definition>) {
+    let scheme = type_env.lookup(name);
+    let doc = defs.get(name).and_then(|d| d.doc.as_ref());
+    if scheme.is_none() && doc.is_none() {
+        eprintln!("  \x1b[1;31merror\x1b[0m: unknown name '{}'", name);
+        return;
+    }
+    match scheme {
+        Some(scheme) => println!("  \x1b[1m{}\x1b[0m \x1b[36m: {}\x1b[0m", name, scheme),
+        None => println!("  \x1b[1mtype {}\x1b[0m", name),
+    }
+    match doc {
+        Some(doc) => {
+            for line in doc.lines() {
+                println!("    {}", line);
+            }
+        }
+        None => println!("    \x1b[2m(no documentation)\x1b[0m"),
+    }
+}
+
+/// Most matches `:search` lists before summarizing the rest.
+const SEARCH_LIMIT: usize = 25;
+
+/// List the names and types matching `query` for `:search`, best first.
+fn show_search(query: &str, type_env: &TypeEnv) {
+    if query.is_empty() {
+        eprintln!("  \x1b[1;31merror\x1b[0m: expected :search <name or type>");
+        return;
+    }
+    let hits = crate::analysis::search(query, type_env);
+    if hits.is_empty() {
+        println!("  \x1b[2m(nothing matches '{}')\x1b[0m", query);
+        return;
+    }
+    for hit in hits.iter().take(SEARCH_LIMIT) {
+        println!("  \x1b[1m{}\x1b[0m \x1b[36m: {}\x1b[0m", hit.name, hit.ty);
+    }
+    if hits.len() > SEARCH_LIMIT {
+        println!("  \x1b[2m... and {} more\x1b[0m", hits.len() - SEARCH_LIMIT);
+    }
+}
+
+/// Preferences changed with `:set`, interactively or from `~/.lyrarc`.
+struct ReplSettings {
+    budget: Budget,
+    show_types: bool,
+}
+
+impl Default for ReplSettings {
+    fn default() -> Self {
+        ReplSettings {
+            budget: Budget::default(),
+            show_types: true,
+        }
+    }
+}
+
+/// `:set <option> <value>`, or `:set` alone to show the current settings.
+fn apply_set(args: &str, settings: &mut ReplSettings) -> Result<(), String> {
+    let parts: Vec<&str> = args.split_whitespace().collect();
+    let off = |v: &str| matches!(v, "off" | "none");
+    match parts.as_slice() {
+        [] => {
+            let show = |limit: Option<String>| limit.unwrap_or_else(|| "off".to_string());
+            println!("  timeout    {}", show(settings.budget.timeout.map(|t| format!("{}", t.as_millis()))));
+            println!("  max-steps  {}", show(settings.budget.max_steps.map(|n| n.to_string())));
+            println!("  types      {}", if settings.show_types { "on" } else { "off" });
+        }
+        ["timeout", v] if off(v) => settings.budget.timeout = None,
+        ["timeout", ms] => {
+            let ms = ms.parse().map_err(|_| format!("timeout expects milliseconds, got '{}'", ms))?;
+            settings.budget.timeout = Some(Duration::from_millis(ms));
+        }
+        ["max-steps", v] if off(v) => settings.budget.max_steps = None,
+        ["max-steps", n] => {
+            let n = n.parse().map_err(|_| format!("max-steps expects a number, got '{}'", n))?;
+            settings.budget.max_steps = Some(n);
+        }
+        ["types", "on"] => settings.show_types = true,
+        ["types", "off"] => settings.show_types = false,
+        _ => {
+            return Err(format!(
+                "unknown setting '{}' (try timeout, max-steps or types)",
+                args.trim()
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate a startup file: Lyra definitions, plus `:set` lines for preferences.
+/// Errors are reported but don't stop the REPL from starting.
+fn load_rc(
+    path: &std::path::Path,
+    type_env: &mut TypeEnv,
+    runtime_env: &Env,
+    inferencer: &mut Inferencer,
+    defs: &mut HashMap<String, Definition>,
+    macros: &mut Macros,
+    settings: &mut ReplSettings,
+) {
+    let display = path.display().to_string();
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("  \x1b[1;31merror\x1b[0m: cannot read {}: {}", display, e);
+            return;
+        }
+    };
+
+    // Definitions between commands are evaluated together, so they can span lines
+    let mut source = String::new();
+    let mut flush = |source: &mut String, budget: Budget| {
+        if source.trim().is_empty() {
+            return;
+        }
+        let result = eval_line(source, type_env, runtime_env, inferencer, defs, macros, budget);
+        if let Err(e) = result {
+            eprintln!("{}", e.render(source, &display));
+        }
+        show_warnings(inferencer, source, &display);
+        source.clear();
+    };
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with(':') {
+            flush(&mut source, settings.budget);
+            match trimmed.strip_prefix(":set") {
+                Some(args) => {
+                    if let Err(e) = apply_set(args, settings) {
+                        eprintln!("  \x1b[1;31merror\x1b[0m: {}: {}", display, e);
+                    }
+                }
+                None => eprintln!(
+                    "  \x1b[1;31merror\x1b[0m: {}: only :set is allowed, found '{}'",
+                    display, trimmed
+                ),
+            }
+        } else {
+            // Pad with blank lines so error locations match the file
+            if source.is_empty() {
+                source.push_str(&"\n".repeat(i));
+            }
+            source.push_str(line);
+            source.push('\n');
+        }
+    }
+    flush(&mut source, settings.budget);
+}
+
+/// `noun` as counted `n` times: "1 value", "2 values".
+fn noun(n: usize, noun: &str) -> String {
+    if n == 1 {
+        noun.to_string()
+    } else {
+        format!("{}s", noun)
+    }
+}
+
+/// How many of the largest bindings `:memory` lists.
+const MEMORY_LARGEST: usize = 5;
+
+/// Print what the session's bindings hold, then the largest of yours.
+fn show_memory(runtime_env: &Env, stdlib_names: &HashSet<String>) {
+    let total = eval::memory::env_usage(runtime_env);
+    println!(
+        "  \x1b[1m{}\x1b[0m {}, \x1b[1m{}\x1b[0m {}, about \x1b[1m{}\x1b[0m",
+        total.bindings,
+        noun(total.bindings, "binding"),
+        total.values,
+        noun(total.values, "value"),
+        format_bytes(total.bytes)
+    );
+    let mut largest: Vec<(String, eval::memory::Usage)> = runtime_env
+        .names()
+        .into_iter()
+        .filter_map(|name| {
+            let value = runtime_env.get(&name)?;
+            // A stdlib name still holding its builtin hasn't been redefined
+            if stdlib_names.contains(&name) && matches!(value, Value::Builtin(_)) {
+                return None;
+            }
+            Some((name, eval::memory::binding_usage(&value, runtime_env)))
+        })
+        .collect();
+    largest.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+    let width = largest.iter().take(MEMORY_LARGEST).map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, usage) in largest.iter().take(MEMORY_LARGEST) {
+        println!(
+            "    {:width$}  \x1b[2m{} {}, {}\x1b[0m",
+            name,
+            usage.values,
+            noun(usage.values, "value"),
+            format_bytes(usage.bytes)
+        );
+    }
+    if largest.len() > MEMORY_LARGEST {
+        println!("    \x1b[2m... and {} more\x1b[0m", largest.len() - MEMORY_LARGEST);
+    }
+}
+
+/// `bytes` in B, KB or MB, to one decimal place above a kilobyte.
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0),
+    }
+}
+
+/// Longest value display `:bindings` shows before truncating.
+const BINDING_DISPLAY_WIDTH: usize = 60;
+
+/// List user bindings with their current values, skipping untouched stdlib entries.
+fn show_bindings(runtime_env: &Env, type_env: &TypeEnv, stdlib_names: &HashSet<String>) {
+    let mut shown = 0;
+    for name in runtime_env.names() {
+        let Some(value) = runtime_env.get(&name) else {
+            continue;
+        };
+        // A stdlib name still holding its builtin hasn't been redefined
+        if stdlib_names.contains(&name) && matches!(value, Value::Builtin(_)) {
+            continue;
+        }
+        let mut display = value.to_string();
+        if display.chars().count() > BINDING_DISPLAY_WIDTH {
+            display = display.chars().take(BINDING_DISPLAY_WIDTH).collect();
+            display.push('…');
+        }
+        match type_env.lookup(&name) {
+            Some(scheme) => println!(
+                "  \x1b[1m{}\x1b[0m = {} \x1b[36m: {}\x1b[0m",
+                name,
+                display,
+                scheme.normalized()
+            ),
+            None => println!("  \x1b[1m{}\x1b[0m = {}", name, display),
+        }
+        shown += 1;
+    }
+    if shown == 0 {
+        println!("  \x1b[2m(no bindings yet)\x1b[0m");
+    }
+}
+
+/// `file:line` for `:break`; the file is everything before the last colon.
+fn parse_breakpoint(spec: &str) -> Option<(&str, usize)> {
+    let (file, line) = spec.rsplit_once(':')?;
+    let line = line.trim().parse().ok().filter(|&l| l > 0)?;
+    Some((file.trim(), line)).filter(|(file, _)| !file.is_empty())
+}
+
+fn show_breakpoints() {
+    let breakpoints = debugger::breakpoints();
+    if breakpoints.is_empty() {
+        println!("  \x1b[2m(no breakpoints)\x1b[0m");
+    }
+    for (file, line) in breakpoints {
+        println!("  {}:{}", file, line);
+    }
+}
+
+/// Show where evaluation stopped and the bindings in scope, then ask
+/// whether to step or continue. End of input continues.
+fn debug_stop(stop: &Stop) -> Resume {
+    println!("  \x1b[1;33mstopped\x1b[0m at {}:{}", stop.file, stop.line);
+    println!("  \x1b[2m{:>4} |\x1b[0m {}", stop.line, stop.text);
+    for (name, value) in &stop.locals {
+        let mut display = value.to_string();
+        if display.chars().count() > BINDING_DISPLAY_WIDTH {
+            display = display.chars().take(BINDING_DISPLAY_WIDTH).collect();
+            display.push('…');
+        }
+        println!("  \x1b[1m{}\x1b[0m = {}", name, display);
+    }
+    loop {
+        print!("\x1b[1;33mdebug>\x1b[0m ");
+        let _ = std::io::stdout().flush();
+        let mut input = String::new();
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => return Resume::Continue,
+            Ok(_) => {}
+        }
+        match input.trim() {
+            ":step" | ":s" => return Resume::Step,
+            ":continue" | ":c" => return Resume::Continue,
+            _ => {
+                println!("  :step to go to the next line, :continue to run to the next breakpoint")
+            }
+        }
+    }
+}
+
 fn infer_type(
     source: &str,
     type_env: &TypeEnv,
     inferencer: &mut Inferencer,
 ) -> Result<crate::types::MonoType, crate::error::LyraError> {
     let tokens = lexer::tokenize(source).map_err(|errs| errs[0].clone())?;
-    let decls = parser::parse(tokens)?;
+    let program = parser::parse(tokens)?;
 
-    if let Some(decl) = decls.first() {
+    if let Some(decl) = program.decls.first() {
         match decl {
             crate::ast::Decl::Expr(expr) => {
-                let (_, ty) = inferencer.infer(type_env, expr)?;
+                let (_, ty) = inferencer.infer(type_env, &program.ast, *expr)?;
                 Ok(ty)
             }
             _ => {
                 let mut env = type_env.clone();
-                let ty = inferencer.infer_decl(&mut env, decl)?;
+                let ty = inferencer.infer_decl(&mut env, &program.ast, decl)?;
                 ty.ok_or_else(|| crate::error::LyraError::RuntimeError {
                     message: "no type to display".to_string(),
                     span: crate::span::Span::default(),
@@ -262,8 +888,18 @@ fn print_help() {
     println!("  :help, :h          Show this help message");
     println!("  :quit, :q          Exit the REPL");
     println!("  :type <expr>       Show the type of an expression");
+    println!("  :doc <name>        Show the type and doc comment of a binding");
+    println!("  :search <text>     Find names, or types mentioning the text, like [String]");
     println!("  :load <file>       Load and evaluate a .lyra file");
+    println!("  :edit [name]       Edit the current input (or a definition) in $EDITOR, then run it");
     println!("  :env               Show the type environment");
+    println!("  :bindings          Show your bindings and their current values");
+    println!("  :memory            Show roughly how much memory the bindings hold, largest first");
+    println!("  :break [file:line] Stop when evaluation reaches a line (list breakpoints)");
+    println!("  :step, :s          Stop at each line of the next input; at a stop, go to the next line");
+    println!("  :continue, :c      At a stop, run to the next breakpoint");
+    println!("  :set [opt value]   Show or change settings: timeout <ms>, max-steps <n>, types on|off");
+    println!("  it, _1, _2, ...    The last result, and each result by the number it was shown with");
     println!();
     println!("\x1b[1mLanguage Features:\x1b[0m");
     println!("  let x = 42                              Bind a value");
@@ -271,26 +907,33 @@ fn print_help() {
     println!("  fn (x, y) -> x + y                      Lambda function");
     println!("  if x > 0 then x else -x                 Conditional");
     println!("  match x with | 0 -> a | n -> b          Pattern matching");
+    println!("  if let Some(v) = opt then v else 0      Single-pattern match");
     println!("  type Option a = Some a | None            Algebraic data types");
     println!("  [1, 2, 3] |> map(fn (x) -> x * 2)      Pipe operator");
+    println!("  [1, 2, 3] |> map(add(_, 1))             Placeholder application");
     println!("  1 :: [2, 3]                              Cons operator");
+    println!("  2 ** 3 ** 2                              Power (right-associative)");
     println!("  \"hello {{name}}\"                          String interpolation");
     println!("  {{ name: \"Alice\", age: 30 }}               Record types");
     println!("  person.name                              Field access");
     println!("  import \"utils\"                            Module imports");
+    println!("  --- Adds two numbers                    Doc comment for the next let/type");
     println!();
     println!("\x1b[1mBuilt-in Functions:\x1b[0m");
     println!("  print, println, to_string");
-    println!("  map, filter, fold, zip, sort");
+    println!("  map, filter, fold, zip, sort, tap, memo, bench");
     println!("  head, tail, length, reverse, append, range, nth");
-    println!("  abs, min, max, pow");
+    println!("  abs, min, max, pow, float_pow, divmod, div_euclid, mod_euclid");
     println!("  str_length, str_concat, str_split, str_chars, str_contains");
     println!("  float_of_int, int_of_float");
     println!();
+    println!("\x1b[1mStartup File:\x1b[0m");
+    println!("  ~/.lyrarc is evaluated at launch: definitions plus :set lines");
+    println!();
     println!("\x1b[1mMulti-line Input:\x1b[0m");
     println!("  Unclosed parens/brackets or trailing -> automatically continue to next line");
 }
 
-fn dirs_next() -> Option<std::path::PathBuf> {
-    std::env::var_os("HOME").map(|h| std::path::PathBuf::from(h).join(".lyra_history"))
+fn home_file(name: &str) -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME").map(|h| std::path::PathBuf::from(h).join(name))
 }
@@ -0,0 +1,136 @@
+//! Differential testing behind `lyra selftest`.
+//!
+//! A program is checked once, then run on the tree-walking interpreter and
+//! on the bytecode VM with what it prints captured. The two should agree on
+//! everything the program can observe: its output, line by line, and how it
+//! ended, with the value of its last expression or with an error. The first
+//! place they differ is reported.
+
+use std::rc::Rc;
+
+use crate::ast::{Decl, Program};
+use crate::error::LyraError;
+use crate::eval::builtins::capture_output;
+use crate::eval::env::Env;
+use crate::eval::value::Value;
+use crate::span::Span;
+use crate::types::env::TypeEnv;
+use crate::types::infer::Inferencer;
+use crate::types::TypeVarGen;
+use crate::vm::VM;
+use crate::{compiler, eval, parse_file, stdlib, Imports};
+
+/// What one backend did with a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub output: String,
+    /// The value of the program's last declaration, when that's an
+    /// expression, `()` otherwise, or the error that stopped the run, both as
+    /// displayed.
+    pub result: Result<String, String>,
+}
+
+/// The first difference between the two runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// Output line `line` (from 1) differs; `None` is output that ended
+    /// before it.
+    Output {
+        line: usize,
+        tree_walker: Option<String>,
+        vm: Option<String>,
+    },
+    /// The output agreed, but the runs ended differently.
+    Result {
+        tree_walker: Result<String, String>,
+        vm: Result<String, String>,
+    },
+}
+
+/// Both runs of a program, and where they first differ.
+#[derive(Debug, Clone)]
+pub struct SelftestReport {
+    pub tree_walker: Run,
+    pub vm: Run,
+    pub divergence: Option<Divergence>,
+}
+
+/// Type-check a file (with its imports), then run it on both backends. A
+/// program that doesn't load or check is an error; one that fails while
+/// running is compared like any other.
+pub fn run_selftest(source: &str, filename: &str) -> Result<SelftestReport, LyraError> {
+    let Program { mut ast, decls } = parse_file(source, filename, Imports::Inline)?.program;
+    eval::resolve::resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
+
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+    for decl in &decls {
+        inferencer.infer_decl(&mut type_env, &ast, decl)?;
+    }
+    let main = compiler::compile(&ast, &decls).map_err(|message| LyraError::RuntimeError {
+        message,
+        span: Span::default(),
+    })?;
+
+    let (result, output) = capture_output(|| {
+        let mut last = Value::Unit;
+        for decl in &decls {
+            let value = eval::eval_decl(&runtime_env, &ast, decl)?;
+            // As on the VM, a program ending in an expression has its value
+            last = value.filter(|_| matches!(decl, Decl::Expr(_))).unwrap_or(Value::Unit);
+        }
+        Ok(last)
+    });
+    let tree_walker = Run {
+        output,
+        result: shown(result),
+    };
+
+    let (result, output) = capture_output(|| {
+        let mut vm = VM::new();
+        stdlib::register_vm_stdlib(&mut vm);
+        vm.run(main)
+    });
+    let vm = Run {
+        output,
+        result: shown(result),
+    };
+
+    let divergence = diverge(&tree_walker, &vm);
+    Ok(SelftestReport {
+        tree_walker,
+        vm,
+        divergence,
+    })
+}
+
+fn shown(result: Result<Value, LyraError>) -> Result<String, String> {
+    result.map(|value| value.to_string()).map_err(|e| e.to_string())
+}
+
+fn diverge(tree_walker: &Run, vm: &Run) -> Option<Divergence> {
+    let mut tw_lines = tree_walker.output.split_inclusive('\n');
+    let mut vm_lines = vm.output.split_inclusive('\n');
+    for line in 1.. {
+        match (tw_lines.next(), vm_lines.next()) {
+            (None, None) => break,
+            (a, b) if a == b => {}
+            (a, b) => {
+                let text = |l: Option<&str>| l.map(|l| l.trim_end_matches('\n').to_string());
+                return Some(Divergence::Output {
+                    line,
+                    tree_walker: text(a),
+                    vm: text(b),
+                });
+            }
+        }
+    }
+    (tree_walker.result != vm.result).then(|| Divergence::Result {
+        tree_walker: tree_walker.result.clone(),
+        vm: vm.result.clone(),
+    })
+}
@@ -1,5 +1,6 @@
-/// A byte-offset range within source code.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// A range within source code, in characters (not bytes) from the start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -20,10 +21,15 @@ impl Span {
     pub fn len(self) -> usize {
         self.end.saturating_sub(self.start)
     }
+
+    pub fn is_empty(self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// Any value wrapped with its source location.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Spanned<T> {
     pub node: T,
     pub span: Span,
@@ -34,3 +40,69 @@ impl<T> Spanned<T> {
         Spanned { node, span }
     }
 }
+
+/// Converts between span offsets and 1-based line/column positions, and
+/// extracts line text. Build one per source and reuse it for many lookups.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    source: &'a str,
+    /// Character and byte offset of the start of each line.
+    line_starts: Vec<(usize, usize)>,
+    /// Length of the source in characters.
+    len: usize,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![(0, 0)];
+        let mut len = 0;
+        for (byte, ch) in source.char_indices() {
+            len += 1;
+            if ch == '\n' {
+                line_starts.push((len, byte + 1));
+            }
+        }
+        LineIndex {
+            source,
+            line_starts,
+            len,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The 1-based line and column of an offset. Offsets past the end map to
+    /// the end of the source.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.len);
+        let line = self.line_starts.partition_point(|&(start, _)| start <= offset);
+        (line, offset - self.line_starts[line - 1].0 + 1)
+    }
+
+    /// The offset of a 1-based line and column, if the line exists and the
+    /// column is at most one past its last character.
+    pub fn offset(&self, line: usize, col: usize) -> Option<usize> {
+        let span = self.line_span(line)?;
+        let offset = span.start + col.checked_sub(1)?;
+        (offset <= span.end).then_some(offset)
+    }
+
+    /// The span of a 1-based line, without its line ending.
+    pub fn line_span(&self, line: usize) -> Option<Span> {
+        let &(start, _) = self.line_starts.get(line.checked_sub(1)?)?;
+        let len = self.line_text(line)?.chars().count();
+        Some(Span::new(start, start + len))
+    }
+
+    /// The text of a 1-based line, without its line ending.
+    pub fn line_text(&self, line: usize) -> Option<&'a str> {
+        let &(_, start) = self.line_starts.get(line.checked_sub(1)?)?;
+        let end = match self.line_starts.get(line) {
+            Some(&(_, next)) => next - 1,
+            None => self.source.len(),
+        };
+        Some(self.source[start..end].trim_end_matches('\r'))
+    }
+}
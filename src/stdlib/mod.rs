@@ -1,5 +1,6 @@
+use crate::error::suggest_similar;
 use crate::eval::env::Env;
-use crate::eval::builtins::all_builtins;
+use crate::eval::builtins::{all_builtins, VARIADIC_BUILTINS};
 use crate::eval::register_hof_builtins;
 use crate::types::env::TypeEnv;
 use crate::types::{MonoType, TypeScheme, TypeVarGen};
@@ -17,6 +18,62 @@ pub fn register_stdlib(type_env: &mut TypeEnv, runtime_env: &Env, gen: &mut Type
 
     // Register types for builtins
     register_builtin_types(type_env, gen);
+    for name in VARIADIC_BUILTINS {
+        type_env.set_variadic(name);
+    }
+}
+
+/// Which standard library functions a program starts with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Prelude {
+    /// Every builtin.
+    #[default]
+    Full,
+    /// Only the named builtins; an empty list gives a bare environment.
+    Only(Vec<String>),
+}
+
+impl Prelude {
+    /// Check that every name in an `Only` list is a builtin.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut types = TypeEnv::new();
+        register_prelude(&mut types, &Env::new(), &mut TypeVarGen::new(), self)
+    }
+}
+
+/// Register the builtins `prelude` selects. Fails on a name that isn't a builtin.
+pub fn register_prelude(
+    type_env: &mut TypeEnv,
+    runtime_env: &Env,
+    gen: &mut TypeVarGen,
+    prelude: &Prelude,
+) -> Result<(), String> {
+    let Prelude::Only(names) = prelude else {
+        register_stdlib(type_env, runtime_env, gen);
+        return Ok(());
+    };
+
+    // Register everything on the side and copy over the chosen names
+    let mut all_types = TypeEnv::new();
+    let all_values = Env::new();
+    register_stdlib(&mut all_types, &all_values, gen);
+    for name in names {
+        let (Some(scheme), Some(value)) = (all_types.lookup(name), all_values.get(name)) else {
+            let mut message = format!("unknown builtin '{}' in prelude", name);
+            let mut candidates = all_types.names();
+            candidates.sort_unstable();
+            if let Some(similar) = suggest_similar(name, &candidates) {
+                message.push_str(&format!(" (did you mean '{}'?)", similar));
+            }
+            return Err(message);
+        };
+        type_env.insert(name.clone(), scheme.clone());
+        if all_types.is_variadic(name) {
+            type_env.set_variadic(name);
+        }
+        runtime_env.set(name.clone(), value);
+    }
+    Ok(())
 }
 
 fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
@@ -25,11 +82,13 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
     env.insert("print".to_string(), TypeScheme {
         vars: vec![a],
         ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Unit)),
+        constraints: vec![],
     });
     let a = gen.fresh();
     env.insert("println".to_string(), TypeScheme {
         vars: vec![a],
         ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Unit)),
+        constraints: vec![],
     });
 
     // to_string : a -> String
@@ -37,6 +96,15 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
     env.insert("to_string".to_string(), TypeScheme {
         vars: vec![a],
         ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::String)),
+        constraints: vec![],
+    });
+
+    // type_of : a -> String
+    let a = gen.fresh();
+    env.insert("type_of".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::String)),
+        constraints: vec![],
     });
 
     // String functions
@@ -79,6 +147,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             Box::new(MonoType::Int),
         ),
+        constraints: vec![],
     });
     let a = gen.fresh();
     env.insert("head".to_string(), TypeScheme {
@@ -87,6 +156,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             Box::new(MonoType::Var(a)),
         ),
+        constraints: vec![],
     });
     let a = gen.fresh();
     env.insert("tail".to_string(), TypeScheme {
@@ -95,6 +165,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
         ),
+        constraints: vec![],
     });
     let a = gen.fresh();
     env.insert("reverse".to_string(), TypeScheme {
@@ -103,6 +174,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
         ),
+        constraints: vec![],
     });
     let a = gen.fresh();
     env.insert("append".to_string(), TypeScheme {
@@ -114,6 +186,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             )),
         ),
+        constraints: vec![],
     });
     env.insert("range".to_string(), TypeScheme::mono(
         MonoType::Arrow(
@@ -134,6 +207,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::Var(a)),
             )),
         ),
+        constraints: vec![],
     });
 
     // map : (a -> b) -> [a] -> [b]
@@ -148,6 +222,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::List(Box::new(MonoType::Var(b)))),
             )),
         ),
+        constraints: vec![],
     });
 
     // filter : (a -> Bool) -> [a] -> [a]
@@ -161,6 +236,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             )),
         ),
+        constraints: vec![],
     });
 
     // fold : b -> (b -> a -> b) -> [a] -> b
@@ -181,6 +257,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 )),
             )),
         ),
+        constraints: vec![],
     });
 
     // zip : [a] -> [b] -> [(a, b)]
@@ -195,6 +272,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::List(Box::new(MonoType::Tuple(vec![MonoType::Var(a), MonoType::Var(b)])))),
             )),
         ),
+        constraints: vec![],
     });
 
     // any : (a -> Bool) -> [a] -> Bool
@@ -208,6 +286,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::Bool),
             )),
         ),
+        constraints: vec![],
     });
 
     // all : (a -> Bool) -> [a] -> Bool
@@ -221,6 +300,48 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::Bool),
             )),
         ),
+        constraints: vec![],
+    });
+
+    // tap : (a -> b) -> a -> a
+    let a = gen.fresh();
+    let b = gen.fresh();
+    env.insert("tap".to_string(), TypeScheme {
+        vars: vec![a, b],
+        ty: MonoType::Arrow(
+            Box::new(MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Var(b)))),
+            Box::new(MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Var(a)))),
+        ),
+        constraints: vec![],
+    });
+
+    // memo : (a -> b) -> a -> b
+    let a = gen.fresh();
+    let b = gen.fresh();
+    let fn_ty = MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Var(b)));
+    env.insert("memo".to_string(), TypeScheme {
+        vars: vec![a, b],
+        ty: MonoType::Arrow(Box::new(fn_ty.clone()), Box::new(fn_ty)),
+        constraints: vec![],
+    });
+
+    // bench : a -> Int -> {iterations: Int, max_ms: Float, mean_ms: Float, ...}
+    // A function of no arguments has the type of its result, so it's an `a`
+    let a = gen.fresh();
+    let stats = ["iterations", "max_ms", "mean_ms", "min_ms", "total_ms"]
+        .into_iter()
+        .map(|field| {
+            let ty = if field == "iterations" { MonoType::Int } else { MonoType::Float };
+            (field.to_string(), ty)
+        })
+        .collect();
+    env.insert("bench".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::curried_arrow(
+            vec![MonoType::Var(a), MonoType::Int],
+            MonoType::Record(stats),
+        ),
+        constraints: vec![],
     });
 
     // sort : [Int] -> [Int]
@@ -242,6 +363,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             )),
         ),
+        constraints: vec![],
     });
 
     // drop : Int -> [a] -> [a]
@@ -255,6 +377,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
                 Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
             )),
         ),
+        constraints: vec![],
     });
 
     // flatten : [[a]] -> [a]
@@ -265,6 +388,7 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
             Box::new(MonoType::List(Box::new(MonoType::List(Box::new(MonoType::Var(a)))))),
             Box::new(MonoType::List(Box::new(MonoType::Var(a)))),
         ),
+        constraints: vec![],
     });
 
     // sum : [Int] -> Int
@@ -368,12 +492,314 @@ fn register_builtin_types(env: &mut TypeEnv, gen: &mut TypeVarGen) {
             Box::new(MonoType::Arrow(Box::new(MonoType::Int), Box::new(MonoType::Int))),
         ),
     ));
+    env.insert("float_pow".to_string(), TypeScheme::mono(
+        MonoType::Arrow(
+            Box::new(MonoType::Float),
+            Box::new(MonoType::Arrow(Box::new(MonoType::Float), Box::new(MonoType::Float))),
+        ),
+    ));
+    // divmod : Int -> Int -> (Int, Int), with div_euclid and mod_euclid
+    // giving each half
+    env.insert("divmod".to_string(), TypeScheme::mono(
+        MonoType::curried_arrow(
+            vec![MonoType::Int, MonoType::Int],
+            MonoType::Tuple(vec![MonoType::Int, MonoType::Int]),
+        ),
+    ));
+    for name in ["div_euclid", "mod_euclid"] {
+        env.insert(name.to_string(), TypeScheme::mono(
+            MonoType::curried_arrow(vec![MonoType::Int, MonoType::Int], MonoType::Int),
+        ));
+    }
     env.insert("float_of_int".to_string(), TypeScheme::mono(
         MonoType::Arrow(Box::new(MonoType::Int), Box::new(MonoType::Float)),
     ));
     env.insert("int_of_float".to_string(), TypeScheme::mono(
         MonoType::Arrow(Box::new(MonoType::Float), Box::new(MonoType::Int)),
     ));
+
+    // eval : String -> Result Dynamic String, with the program's own
+    // `type Result a e = Ok a | Err e`. The value's type is only known at
+    // runtime, so like a reflected field it's `Dynamic`.
+    env.insert("eval".to_string(), TypeScheme::mono(MonoType::Arrow(
+        Box::new(MonoType::String),
+        Box::new(MonoType::Con(
+            "Result".to_string(),
+            vec![MonoType::Con("Dynamic".to_string(), vec![]), MonoType::String],
+        )),
+    )));
+
+    // Bytes, a type of its own that annotations name as `Bytes`
+    let bytes = || MonoType::Con("Bytes".to_string(), vec![]);
+    let arrow = |from: MonoType, to: MonoType| MonoType::Arrow(Box::new(from), Box::new(to));
+    for (name, ty) in [
+        ("read_file_bytes", arrow(MonoType::String, bytes())),
+        (
+            "write_file_bytes",
+            MonoType::curried_arrow(vec![MonoType::String, bytes()], MonoType::Unit),
+        ),
+        ("bytes_length", arrow(bytes(), MonoType::Int)),
+        ("bytes_to_list", arrow(bytes(), MonoType::List(Box::new(MonoType::Int)))),
+        ("bytes_from_list", arrow(MonoType::List(Box::new(MonoType::Int)), bytes())),
+        ("utf8_encode", arrow(MonoType::String, bytes())),
+        ("utf8_decode", arrow(bytes(), MonoType::String)),
+        ("base64_encode", arrow(bytes(), MonoType::String)),
+        ("base64_decode", arrow(MonoType::String, bytes())),
+        ("hex_encode", arrow(bytes(), MonoType::String)),
+        ("hex_decode", arrow(MonoType::String, bytes())),
+    ] {
+        env.insert(name.to_string(), TypeScheme::mono(ty));
+    }
+
+    // hash : a -> Int, structural and agreeing with `==`
+    let a = gen.fresh();
+    env.insert("hash".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Int)),
+        constraints: vec![],
+    });
+    // sha256, md5 : a -> String, of a String's UTF-8 or of Bytes; other
+    // values are a runtime error
+    for name in ["sha256", "md5"] {
+        let a = gen.fresh();
+        env.insert(name.to_string(), TypeScheme {
+            vars: vec![a],
+            ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::String)),
+            constraints: vec![],
+        });
+    }
+
+    // log_debug, log_info, log_warn, log_error : a -> (), a message or a
+    // record of fields
+    for name in ["log_debug", "log_info", "log_warn", "log_error"] {
+        let a = gen.fresh();
+        env.insert(name.to_string(), TypeScheme {
+            vars: vec![a],
+            ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::Unit)),
+            constraints: vec![],
+        });
+    }
+    env.insert(
+        "log_set_level".to_string(),
+        TypeScheme::mono(MonoType::Arrow(Box::new(MonoType::String), Box::new(MonoType::Unit))),
+    );
+    // log_level : String, the current level: `log_level()`
+    env.insert("log_level".to_string(), TypeScheme::mono(MonoType::String));
+
+    // term_color : String -> a -> String, the value's text in the named colors
+    let a = gen.fresh();
+    env.insert("term_color".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::curried_arrow(vec![MonoType::String, MonoType::Var(a)], MonoType::String),
+        constraints: vec![],
+    });
+    // term_clear, term_read_key : String and term_size : {columns, rows: Int},
+    // called with no arguments
+    env.insert("term_clear".to_string(), TypeScheme::mono(MonoType::String));
+    env.insert("term_read_key".to_string(), TypeScheme::mono(MonoType::String));
+    env.insert(
+        "term_size".to_string(),
+        TypeScheme::mono(MonoType::Record(
+            ["columns", "rows"].into_iter().map(|f| (f.to_string(), MonoType::Int)).collect(),
+        )),
+    );
+    env.insert(
+        "term_cursor_to".to_string(),
+        TypeScheme::mono(MonoType::curried_arrow(
+            vec![MonoType::Int, MonoType::Int],
+            MonoType::String,
+        )),
+    );
+
+    // memory_stats : {env, vm: {bindings, values, bytes: Int}}, what the
+    // interpreter's global scope and the VM's globals hold: `memory_stats()`
+    let usage = || {
+        MonoType::Record(
+            ["bindings", "values", "bytes"].into_iter().map(|f| (f.to_string(), MonoType::Int)).collect(),
+        )
+    };
+    env.insert(
+        "memory_stats".to_string(),
+        TypeScheme::mono(MonoType::Record(
+            ["env", "vm"].into_iter().map(|f| (f.to_string(), usage())).collect(),
+        )),
+    );
+    // args : [String], the program's command-line arguments: `args()`
+    let strings = || MonoType::List(Box::new(MonoType::String));
+    env.insert("args".to_string(), TypeScheme::mono(strings()));
+    // parse_flags : String -> [String] -> Result Flags String. What a
+    // `Flags` holds comes from the spec at runtime, so it's read by name:
+    // `flag_value` for an option or argument, `flag_set` for a switch and
+    // `flag_rest` for the leftovers.
+    let flags = || MonoType::Con("Flags".to_string(), vec![]);
+    env.insert("parse_flags".to_string(), TypeScheme::mono(MonoType::curried_arrow(
+        vec![MonoType::String, strings()],
+        MonoType::Con("Result".to_string(), vec![flags(), MonoType::String]),
+    )));
+    for (name, ty) in [
+        ("flag_value", MonoType::String),
+        ("flag_set", MonoType::Bool),
+        ("flag_rest", strings()),
+    ] {
+        env.insert(
+            name.to_string(),
+            TypeScheme::mono(MonoType::curried_arrow(vec![flags(), MonoType::String], ty)),
+        );
+    }
+    env.insert(
+        "flags_usage".to_string(),
+        TypeScheme::mono(MonoType::curried_arrow(
+            vec![MonoType::String, MonoType::String],
+            MonoType::String,
+        )),
+    );
+
+    // uuid_v4 : String, a fresh one per call: `uuid_v4()`
+    env.insert("uuid_v4".to_string(), TypeScheme::mono(MonoType::String));
+    env.insert(
+        "uuid_valid".to_string(),
+        TypeScheme::mono(MonoType::Arrow(Box::new(MonoType::String), Box::new(MonoType::Bool))),
+    );
+    // uuid_parse : String -> Result String String, canonical lowercase on Ok
+    env.insert("uuid_parse".to_string(), TypeScheme::mono(MonoType::Arrow(
+        Box::new(MonoType::String),
+        Box::new(MonoType::Con(
+            "Result".to_string(),
+            vec![MonoType::String, MonoType::String],
+        )),
+    )));
+
+    // DateTime, the record {year, month, day, hour, minute, second,
+    // millisecond: Int} in UTC; durations are Ints of milliseconds
+    let datetime = || {
+        MonoType::Record(
+            crate::eval::datetime::FIELDS
+                .iter()
+                .map(|field| (field.to_string(), MonoType::Int))
+                .collect(),
+        )
+    };
+    let int = || MonoType::Int;
+    for (name, ty) in [
+        ("datetime_now", datetime()),
+        (
+            "datetime_parse",
+            MonoType::curried_arrow(
+                vec![MonoType::String, MonoType::String],
+                MonoType::Con("Result".to_string(), vec![datetime(), MonoType::String]),
+            ),
+        ),
+        (
+            "datetime_format",
+            MonoType::curried_arrow(vec![MonoType::String, datetime()], MonoType::String),
+        ),
+        ("datetime_from_millis", arrow(int(), datetime())),
+        ("datetime_to_millis", arrow(datetime(), int())),
+        ("datetime_weekday", arrow(datetime(), int())),
+        ("datetime_day_of_year", arrow(datetime(), int())),
+        ("datetime_add", MonoType::curried_arrow(vec![datetime(), int()], datetime())),
+        ("datetime_diff", MonoType::curried_arrow(vec![datetime(), datetime()], int())),
+        ("duration_seconds", arrow(int(), int())),
+        ("duration_minutes", arrow(int(), int())),
+        ("duration_hours", arrow(int(), int())),
+        ("duration_days", arrow(int(), int())),
+    ] {
+        env.insert(name.to_string(), TypeScheme::mono(ty));
+    }
+
+    // toml_parse, yaml_parse : String -> Result Dynamic String. The shape of
+    // the document is only known at runtime, so like `eval`'s value it's
+    // `Dynamic`, taken apart with reflection.
+    let formats: &[&str] = &[
+        #[cfg(feature = "toml")]
+        "toml_parse",
+        #[cfg(feature = "yaml")]
+        "yaml_parse",
+    ];
+    for name in formats {
+        env.insert(name.to_string(), TypeScheme::mono(MonoType::Arrow(
+            Box::new(MonoType::String),
+            Box::new(MonoType::Con(
+                "Result".to_string(),
+                vec![MonoType::Con("Dynamic".to_string(), vec![]), MonoType::String],
+            )),
+        )));
+    }
+
+    // Reflection. Records and ADTs are inspected at runtime, so the field
+    // values come back as `Dynamic`, a type of its own: show them with
+    // `to_string` or `type_of`, or take them back with the `dynamic_`
+    // conversions, which give `None` for a value of another type.
+    let dynamic = || MonoType::Con("Dynamic".to_string(), vec![]);
+    let option = |ty: MonoType| MonoType::Con("Option".to_string(), vec![ty]);
+
+    // record_fields : a -> [(String, Dynamic)]
+    let a = gen.fresh();
+    env.insert("record_fields".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::Arrow(
+            Box::new(MonoType::Var(a)),
+            Box::new(MonoType::List(Box::new(MonoType::Tuple(vec![
+                MonoType::String,
+                dynamic(),
+            ])))),
+        ),
+        constraints: vec![],
+    });
+
+    // record_get : a -> String -> Option Dynamic, with the program's own
+    // `type Option a = Some a | None`
+    let a = gen.fresh();
+    env.insert("record_get".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::curried_arrow(vec![MonoType::Var(a), MonoType::String], option(dynamic())),
+        constraints: vec![],
+    });
+
+    // adt_constructor : a -> String
+    let a = gen.fresh();
+    env.insert("adt_constructor".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::Arrow(Box::new(MonoType::Var(a)), Box::new(MonoType::String)),
+        constraints: vec![],
+    });
+
+    // adt_fields : a -> [Dynamic]
+    let a = gen.fresh();
+    env.insert("adt_fields".to_string(), TypeScheme {
+        vars: vec![a],
+        ty: MonoType::Arrow(
+            Box::new(MonoType::Var(a)),
+            Box::new(MonoType::List(Box::new(dynamic()))),
+        ),
+        constraints: vec![],
+    });
+
+    // dynamic_int : Dynamic -> Option Int, and so on
+    for (name, ty) in [
+        ("dynamic_int", MonoType::Int),
+        ("dynamic_float", MonoType::Float),
+        ("dynamic_string", MonoType::String),
+        ("dynamic_bool", MonoType::Bool),
+    ] {
+        env.insert(name.to_string(), TypeScheme::mono(arrow(dynamic(), option(ty))));
+    }
+}
+
+/// Define the builtins `prelude` selects as VM globals. Names that aren't
+/// builtins are skipped; `register_prelude` reports them.
+pub fn register_vm_prelude(vm: &mut VM, prelude: &Prelude) {
+    let Prelude::Only(names) = prelude else {
+        register_vm_stdlib(vm);
+        return;
+    };
+    let mut all = VM::new();
+    register_vm_stdlib(&mut all);
+    for name in names {
+        if let Some(value) = all.global(name) {
+            vm.define_global(name.clone(), value);
+        }
+    }
 }
 
 /// Register all stdlib functions as VM globals.
@@ -385,184 +811,169 @@ pub fn register_vm_stdlib(vm: &mut VM) {
         vm.define_global(name, value);
     }
 
-    // Register HOF builtins (map, filter, fold, zip, sort)
+    // Register HOF builtins (map, filter, fold, zip, tap, sort)
     vm.define_global(
         "map".to_string(),
-        Value::Builtin {
-            name: "map".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("map: expected List, got {}", v.type_name())),
-                };
-                let mut results = Vec::new();
-                for item in list {
-                    let result = crate::eval::apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        crate::span::Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    results.push(result);
-                }
-                Ok(Value::List(results))
-            },
-        },
+        Value::builtin("map", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("map: expected List, got {}", v.type_name())),
+            };
+            let mut results = Vec::new();
+            for item in list {
+                let result = crate::eval::apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    crate::span::Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                results.push(result);
+            }
+            Ok(Value::List(results))
+        }),
     );
 
     vm.define_global(
         "filter".to_string(),
-        Value::Builtin {
-            name: "filter".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("filter: expected List, got {}", v.type_name())),
-                };
-                let mut results = Vec::new();
-                for item in list {
-                    let keep = crate::eval::apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        crate::span::Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    if matches!(keep, Value::Bool(true)) {
-                        results.push(item.clone());
-                    }
+        Value::builtin("filter", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("filter: expected List, got {}", v.type_name())),
+            };
+            let mut results = Vec::new();
+            for item in list {
+                let keep = crate::eval::apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    crate::span::Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                if matches!(keep, Value::Bool(true)) {
+                    results.push(item.clone());
                 }
-                Ok(Value::List(results))
-            },
-        },
+            }
+            Ok(Value::List(results))
+        }),
     );
 
     vm.define_global(
         "fold".to_string(),
-        Value::Builtin {
-            name: "fold".to_string(),
-            arity: 3,
-            func: |args| {
-                let mut acc = args[0].clone();
-                let func = &args[1];
-                let list = match &args[2] {
-                    Value::List(l) => l,
-                    v => return Err(format!("fold: expected List, got {}", v.type_name())),
-                };
-                for item in list {
-                    acc = crate::eval::apply_function(
-                        func.clone(),
-                        vec![acc, item.clone()],
-                        crate::span::Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                }
-                Ok(acc)
-            },
-        },
+        Value::builtin("fold", 3, |args| {
+            let mut acc = args[0].clone();
+            let func = &args[1];
+            let list = match &args[2] {
+                Value::List(l) => l,
+                v => return Err(format!("fold: expected List, got {}", v.type_name())),
+            };
+            for item in list {
+                acc = crate::eval::apply_function(
+                    func.clone(),
+                    vec![acc, item.clone()],
+                    crate::span::Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+            }
+            Ok(acc)
+        }),
     );
 
     vm.define_global(
         "zip".to_string(),
-        Value::Builtin {
-            name: "zip".to_string(),
-            arity: 2,
-            func: |args| {
-                let a = match &args[0] {
-                    Value::List(l) => l,
-                    v => return Err(format!("zip: expected List, got {}", v.type_name())),
-                };
-                let b = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("zip: expected List, got {}", v.type_name())),
-                };
-                let pairs: Vec<Value> = a
-                    .iter()
-                    .zip(b.iter())
-                    .map(|(x, y)| Value::Tuple(vec![x.clone(), y.clone()]))
-                    .collect();
-                Ok(Value::List(pairs))
-            },
-        },
+        Value::builtin("zip", 2, |args| {
+            let a = match &args[0] {
+                Value::List(l) => l,
+                v => return Err(format!("zip: expected List, got {}", v.type_name())),
+            };
+            let b = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("zip: expected List, got {}", v.type_name())),
+            };
+            let pairs: Vec<Value> = a
+                .iter()
+                .zip(b.iter())
+                .map(|(x, y)| Value::Tuple(vec![x.clone(), y.clone()]))
+                .collect();
+            Ok(Value::List(pairs))
+        }),
     );
 
     vm.define_global(
         "any".to_string(),
-        Value::Builtin {
-            name: "any".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("any: expected List, got {}", v.type_name())),
-                };
-                for item in list {
-                    let result = crate::eval::apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        crate::span::Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    if matches!(result, Value::Bool(true)) {
-                        return Ok(Value::Bool(true));
-                    }
+        Value::builtin("any", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("any: expected List, got {}", v.type_name())),
+            };
+            for item in list {
+                let result = crate::eval::apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    crate::span::Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                if matches!(result, Value::Bool(true)) {
+                    return Ok(Value::Bool(true));
                 }
-                Ok(Value::Bool(false))
-            },
-        },
+            }
+            Ok(Value::Bool(false))
+        }),
     );
 
     vm.define_global(
         "all".to_string(),
-        Value::Builtin {
-            name: "all".to_string(),
-            arity: 2,
-            func: |args| {
-                let func = &args[0];
-                let list = match &args[1] {
-                    Value::List(l) => l,
-                    v => return Err(format!("all: expected List, got {}", v.type_name())),
-                };
-                for item in list {
-                    let result = crate::eval::apply_function(
-                        func.clone(),
-                        vec![item.clone()],
-                        crate::span::Span::default(),
-                    )
-                    .map_err(|e| format!("{}", e))?;
-                    if matches!(result, Value::Bool(false)) {
-                        return Ok(Value::Bool(false));
-                    }
+        Value::builtin("all", 2, |args| {
+            let func = &args[0];
+            let list = match &args[1] {
+                Value::List(l) => l,
+                v => return Err(format!("all: expected List, got {}", v.type_name())),
+            };
+            for item in list {
+                let result = crate::eval::apply_function(
+                    func.clone(),
+                    vec![item.clone()],
+                    crate::span::Span::default(),
+                )
+                .map_err(|e| format!("{}", e))?;
+                if matches!(result, Value::Bool(false)) {
+                    return Ok(Value::Bool(false));
                 }
-                Ok(Value::Bool(true))
-            },
-        },
+            }
+            Ok(Value::Bool(true))
+        }),
+    );
+
+    vm.define_global(
+        "tap".to_string(),
+        Value::builtin("tap", 2, |args| {
+            crate::eval::apply_function(
+                args[0].clone(),
+                vec![args[1].clone()],
+                crate::span::Span::default(),
+            )
+            .map_err(|e| format!("{}", e))?;
+            Ok(args[1].clone())
+        }),
     );
 
     vm.define_global(
         "sort".to_string(),
-        Value::Builtin {
-            name: "sort".to_string(),
-            arity: 1,
-            func: |args| {
-                let list = match &args[0] {
-                    Value::List(l) => l.clone(),
-                    v => return Err(format!("sort: expected List, got {}", v.type_name())),
-                };
-                let mut ints: Vec<i64> = list
-                    .iter()
-                    .map(|v| match v {
-                        Value::Int(n) => Ok(*n),
-                        v => Err(format!("sort: expected Int elements, got {}", v.type_name())),
-                    })
-                    .collect::<Result<Vec<_>, _>>()?;
-                ints.sort();
-                Ok(Value::List(ints.into_iter().map(Value::Int).collect()))
-            },
-        },
+        Value::builtin("sort", 1, |args| {
+            let list = match &args[0] {
+                Value::List(l) => l.clone(),
+                v => return Err(format!("sort: expected List, got {}", v.type_name())),
+            };
+            let mut ints: Vec<i64> = list
+                .iter()
+                .map(|v| match v {
+                    Value::Int(n) => Ok(*n),
+                    v => Err(format!("sort: expected Int elements, got {}", v.type_name())),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            ints.sort();
+            Ok(Value::List(ints.into_iter().map(Value::Int).collect()))
+        }),
     );
 }
@@ -0,0 +1,448 @@
+//! Property-based testing behind `lyra test`.
+//!
+//! Every `prop` declaration is run against randomly generated arguments drawn
+//! from its parameter annotations. When a case fails, its arguments are shrunk
+//! greedily to a smaller counterexample that still fails.
+//!
+//! Snapshot (golden) tests compare a program's stdout against a checked-in
+//! `.expected` file next to it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::ast::{Ast, Decl, ExprId, LambdaParam, Program, TypeAnnotation};
+use crate::error::LyraError;
+use crate::eval::env::Env;
+use crate::eval::value::Value;
+use crate::types::env::TypeEnv;
+use crate::types::infer::Inferencer;
+use crate::types::TypeVarGen;
+use crate::{eval, parse_file, stdlib, Imports};
+
+/// Largest size passed to the generators, reached on the last case.
+const MAX_SIZE: usize = 30;
+
+/// Upper bound on accepted shrink steps, so shrinking always terminates.
+const MAX_SHRINKS: usize = 1000;
+
+/// How many cases to run per property, and the seed for the generators.
+#[derive(Debug, Clone, Copy)]
+pub struct PropConfig {
+    pub cases: usize,
+    pub seed: u64,
+}
+
+impl Default for PropConfig {
+    fn default() -> Self {
+        Self {
+            cases: 100,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// The result of running one `prop`.
+#[derive(Debug, Clone)]
+pub struct PropResult {
+    pub name: String,
+    /// Cases run, including the failing one.
+    pub cases: usize,
+    pub outcome: PropOutcome,
+}
+
+#[derive(Debug, Clone)]
+pub enum PropOutcome {
+    Passed,
+    Failed {
+        /// Shrunk arguments, paired with their parameter names.
+        counterexample: Vec<(String, Value)>,
+        shrinks: usize,
+        /// The runtime error, when the body failed rather than returned `false`.
+        error: Option<String>,
+    },
+}
+
+impl PropResult {
+    pub fn passed(&self) -> bool {
+        matches!(self.outcome, PropOutcome::Passed)
+    }
+}
+
+/// Type-check and load a file (with its imports), then run every `prop` in it.
+/// Top-level expressions are skipped so test files don't print while loading.
+pub fn run_props(
+    source: &str,
+    filename: &str,
+    config: PropConfig,
+) -> Result<Vec<PropResult>, LyraError> {
+    let Program { mut ast, decls } = parse_file(source, filename, Imports::Inline)?.program;
+    eval::resolve::resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
+
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    stdlib::register_stdlib(&mut type_env, &runtime_env, &mut gen);
+
+    let mut props = Vec::new();
+    for decl in &decls {
+        inferencer.infer_decl(&mut type_env, &ast, decl)?;
+        match decl {
+            Decl::Expr(_) => {}
+            Decl::Prop {
+                name, params, body, ..
+            } => {
+                for p in params {
+                    check_generatable(p)?;
+                }
+                props.push((name, params, *body));
+            }
+            _ => {
+                eval::eval_decl(&runtime_env, &ast, decl)?;
+            }
+        }
+    }
+
+    Ok(props
+        .into_iter()
+        .map(|(name, params, body)| run_prop(&runtime_env, &ast, name, params, body, config))
+        .collect())
+}
+
+fn run_prop(
+    env: &Env,
+    ast: &Rc<Ast>,
+    name: &str,
+    params: &[LambdaParam],
+    body: ExprId,
+    config: PropConfig,
+) -> PropResult {
+    let mut rng = Rng::new(config.seed);
+    let anns: Vec<&TypeAnnotation> = params
+        .iter()
+        .map(|p| &p.type_ann.as_ref().expect("checked by parser").node)
+        .collect();
+
+    for case in 0..config.cases {
+        let size = 1 + case * MAX_SIZE / config.cases.max(1);
+        let args: Vec<Value> = anns.iter().map(|ann| generate(ann, &mut rng, size)).collect();
+        if let Some(error) = check_case(env, ast, params, body, &args) {
+            let (args, error, shrinks) = shrink_failure(env, ast, params, body, args, error);
+            return PropResult {
+                name: name.to_string(),
+                cases: case + 1,
+                outcome: PropOutcome::Failed {
+                    counterexample: params
+                        .iter()
+                        .map(|p| p.name.node.clone())
+                        .zip(args)
+                        .collect(),
+                    shrinks,
+                    error,
+                },
+            };
+        }
+    }
+
+    PropResult {
+        name: name.to_string(),
+        cases: config.cases,
+        outcome: PropOutcome::Passed,
+    }
+}
+
+/// Run one case. `None` means it passed; `Some(error)` that it failed, with
+/// the runtime error if there was one.
+fn check_case(
+    env: &Env,
+    ast: &Rc<Ast>,
+    params: &[LambdaParam],
+    body: ExprId,
+    args: &[Value],
+) -> Option<Option<String>> {
+    let case_env = env.extend();
+    for (p, arg) in params.iter().zip(args) {
+        case_env.set(p.name.node.clone(), arg.clone());
+    }
+    match eval::eval(&case_env, ast, body) {
+        Ok(Value::Bool(true)) => None,
+        Ok(_) => Some(None),
+        Err(e) => Some(Some(e.to_string())),
+    }
+}
+
+/// Repeatedly replace one argument with a smaller value that still fails.
+fn shrink_failure(
+    env: &Env,
+    ast: &Rc<Ast>,
+    params: &[LambdaParam],
+    body: ExprId,
+    mut args: Vec<Value>,
+    mut error: Option<String>,
+) -> (Vec<Value>, Option<String>, usize) {
+    let mut shrinks = 0;
+    'outer: while shrinks < MAX_SHRINKS {
+        for i in 0..args.len() {
+            for candidate in shrink(&args[i]) {
+                let mut trial = args.clone();
+                trial[i] = candidate;
+                if let Some(e) = check_case(env, ast, params, body, &trial) {
+                    args = trial;
+                    error = e;
+                    shrinks += 1;
+                    continue 'outer;
+                }
+            }
+        }
+        break;
+    }
+    (args, error, shrinks)
+}
+
+// ── Generators ──
+
+/// Reject parameter types the generators can't produce, before anything runs.
+fn check_generatable(param: &LambdaParam) -> Result<(), LyraError> {
+    let ann = param.type_ann.as_ref().expect("checked by parser");
+    match find_ungeneratable(&ann.node) {
+        Some(ty) => Err(LyraError::RuntimeError {
+            message: format!(
+                "cannot generate values of type {} for '{}'",
+                ty, param.name.node
+            ),
+            span: ann.span,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn find_ungeneratable(ann: &TypeAnnotation) -> Option<&TypeAnnotation> {
+    match ann {
+        TypeAnnotation::Named(n) if matches!(n.as_str(), "Int" | "Float" | "Bool" | "String") => {
+            None
+        }
+        TypeAnnotation::Unit => None,
+        TypeAnnotation::List(inner) => find_ungeneratable(&inner.node),
+        TypeAnnotation::Tuple(items) => items.iter().find_map(|t| find_ungeneratable(&t.node)),
+        _ => Some(ann),
+    }
+}
+
+/// Generate a value of the given type. `size` bounds magnitudes and lengths.
+fn generate(ann: &TypeAnnotation, rng: &mut Rng, size: usize) -> Value {
+    match ann {
+        TypeAnnotation::Named(n) => match n.as_str() {
+            "Int" => Value::Int(rng.range(-(size as i64), size as i64)),
+            "Float" => Value::Float((rng.unit() * 2.0 - 1.0) * size as f64),
+            "Bool" => Value::Bool(rng.next_u64() & 1 == 1),
+            "String" => {
+                const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+                let len = rng.range(0, size as i64) as usize;
+                Value::String(
+                    (0..len)
+                        .map(|_| CHARS[rng.range(0, CHARS.len() as i64 - 1) as usize] as char)
+                        .collect(),
+                )
+            }
+            _ => unreachable!("checked by check_generatable"),
+        },
+        TypeAnnotation::Unit => Value::Unit,
+        TypeAnnotation::List(inner) => {
+            let len = rng.range(0, size as i64) as usize;
+            Value::List((0..len).map(|_| generate(&inner.node, rng, size)).collect())
+        }
+        TypeAnnotation::Tuple(items) => {
+            Value::Tuple(items.iter().map(|t| generate(&t.node, rng, size)).collect())
+        }
+        _ => unreachable!("checked by check_generatable"),
+    }
+}
+
+/// Smaller candidates for a value, most aggressive first.
+fn shrink(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Int(0) => Vec::new(),
+        Value::Int(n) => {
+            let mut out = vec![Value::Int(0)];
+            if n.abs() > 2 {
+                out.push(Value::Int(n / 2));
+            }
+            if n.abs() > 1 {
+                out.push(Value::Int(n - n.signum()));
+            }
+            out
+        }
+        Value::Float(x) => {
+            let mut out = Vec::new();
+            if *x != 0.0 {
+                out.push(Value::Float(0.0));
+            }
+            if x.trunc() != *x {
+                out.push(Value::Float(x.trunc()));
+            }
+            out
+        }
+        Value::Bool(true) => vec![Value::Bool(false)],
+        Value::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            shrink_seq(&chars, |c| if *c == 'a' { Vec::new() } else { vec!['a'] })
+                .into_iter()
+                .map(|cs| Value::String(cs.into_iter().collect()))
+                .collect()
+        }
+        Value::List(items) => shrink_seq(items, shrink).into_iter().map(Value::List).collect(),
+        Value::Tuple(items) => shrink_each(items).into_iter().map(Value::Tuple).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Drop halves, then single elements, then shrink elements in place.
+fn shrink_seq<T: Clone>(items: &[T], shrink_item: impl Fn(&T) -> Vec<T>) -> Vec<Vec<T>> {
+    let mut out = Vec::new();
+    if items.is_empty() {
+        return out;
+    }
+    out.push(Vec::new());
+    let half = items.len() / 2;
+    if half > 0 {
+        out.push(items[..half].to_vec());
+        out.push(items[half..].to_vec());
+    }
+    if items.len() > 1 {
+        for i in 0..items.len() {
+            let mut fewer = items.to_vec();
+            fewer.remove(i);
+            out.push(fewer);
+        }
+    }
+    for (i, item) in items.iter().enumerate() {
+        for smaller in shrink_item(item) {
+            let mut next = items.to_vec();
+            next[i] = smaller;
+            out.push(next);
+        }
+    }
+    out
+}
+
+/// Shrink one element at a time, keeping the others.
+fn shrink_each(items: &[Value]) -> Vec<Vec<Value>> {
+    let mut out = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        for smaller in shrink(item) {
+            let mut next = items.to_vec();
+            next[i] = smaller;
+            out.push(next);
+        }
+    }
+    out
+}
+
+/// Small xorshift generator, so runs are reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift never leaves zero
+        Rng(if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Uniform in `lo..=hi`.
+    fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        let span = (hi - lo) as u64 + 1;
+        lo + (self.next_u64() % span) as i64
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// ── Snapshots ──
+
+/// How a program's output compared with its `.expected` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotOutcome {
+    Matched,
+    /// The `.expected` file was written (or rewritten) by `--update`.
+    Updated,
+    /// There is no `.expected` file and `--update` wasn't given.
+    Missing,
+    Mismatch { diff: String },
+}
+
+/// The `.expected` file holding a program's golden output.
+pub fn expected_path(program: &Path) -> PathBuf {
+    program.with_extension("expected")
+}
+
+/// The `.lyra` files to snapshot: `target` itself, or the files directly
+/// inside it when it's a directory, sorted by name.
+pub fn snapshot_files(target: &Path) -> io::Result<Vec<PathBuf>> {
+    if !target.is_dir() {
+        return Ok(vec![target.to_path_buf()]);
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(target)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|e| e == "lyra") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Compare `actual` output with the program's `.expected` file, or overwrite
+/// the file with it when `update` is set.
+pub fn check_snapshot(program: &Path, actual: &str, update: bool) -> io::Result<SnapshotOutcome> {
+    let expected_file = expected_path(program);
+    if update {
+        if fs::read_to_string(&expected_file).ok().as_deref() == Some(actual) {
+            return Ok(SnapshotOutcome::Matched);
+        }
+        fs::write(&expected_file, actual)?;
+        return Ok(SnapshotOutcome::Updated);
+    }
+    match fs::read_to_string(&expected_file) {
+        Ok(expected) if expected == actual => Ok(SnapshotOutcome::Matched),
+        Ok(expected) => Ok(SnapshotOutcome::Mismatch {
+            diff: diff_lines(&expected, actual),
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SnapshotOutcome::Missing),
+        Err(e) => Err(e),
+    }
+}
+
+/// Line-by-line difference: `-` lines are expected, `+` lines are actual.
+pub fn diff_lines(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let (e, a) = (expected.get(i), actual.get(i));
+        if e == a {
+            continue;
+        }
+        if let Some(e) = e {
+            out.push_str(&format!("{:>4} - {}\n", i + 1, e));
+        }
+        if let Some(a) = a {
+            out.push_str(&format!("{:>4} + {}\n", i + 1, a));
+        }
+    }
+    out
+}
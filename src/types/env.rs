@@ -7,29 +7,66 @@ use super::{TypeScheme, TypeVar};
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
     bindings: HashMap<String, TypeScheme>,
+    /// Parameter names of functions bound to a lambda, for labeled calls.
+    params: HashMap<String, Vec<String>>,
+    /// Builtins that accept extra arguments like their last parameter.
+    variadic: HashSet<String>,
+}
+
+impl Default for TypeEnv {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeEnv {
     pub fn new() -> Self {
         TypeEnv {
             bindings: HashMap::new(),
+            params: HashMap::new(),
+            variadic: HashSet::new(),
         }
     }
 
+    /// Bind `name`, forgetting the parameter names of anything it shadows.
     pub fn insert(&mut self, name: String, scheme: TypeScheme) {
+        self.params.remove(&name);
+        self.variadic.remove(&name);
         self.bindings.insert(name, scheme);
     }
 
+    /// Record the parameter names of the function bound to `name`.
+    pub fn set_params(&mut self, name: &str, params: Vec<String>) {
+        self.params.insert(name.to_string(), params);
+    }
+
+    pub fn params(&self, name: &str) -> Option<&[String]> {
+        self.params.get(name).map(Vec::as_slice)
+    }
+
+    /// Let calls to `name` pass any number of arguments after the fixed ones.
+    pub fn set_variadic(&mut self, name: &str) {
+        self.variadic.insert(name.to_string());
+    }
+
+    pub fn is_variadic(&self, name: &str) -> bool {
+        self.variadic.contains(name)
+    }
+
     pub fn lookup(&self, name: &str) -> Option<&TypeScheme> {
         self.bindings.get(name)
     }
 
     pub fn remove(&mut self, name: &str) {
+        self.params.remove(name);
+        self.variadic.remove(name);
         self.bindings.remove(name);
     }
 
+    /// The names bound, except those made up for the program, with a `#`
+    /// in them.
     pub fn names(&self) -> Vec<&str> {
-        self.bindings.keys().map(|s| s.as_str()).collect()
+        self.bindings.keys().map(|s| s.as_str()).filter(|s| !s.contains('#')).collect()
     }
 
     pub fn free_vars(&self) -> HashSet<TypeVar> {
@@ -48,6 +85,8 @@ impl TypeEnv {
                 .iter()
                 .map(|(k, v)| (k.clone(), subst.apply_scheme(v)))
                 .collect(),
+            params: self.params.clone(),
+            variadic: self.variadic.clone(),
         }
     }
 }
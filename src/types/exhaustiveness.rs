@@ -1,121 +1,289 @@
+//! Exhaustiveness checking for `match`, after Maranget's "Warnings for
+//! pattern matching": the arms form a matrix with one column per value still
+//! to be inspected, and a value no row matches is built up one constructor
+//! at a time by specializing the matrix on each possible head constructor.
+
 use std::collections::HashMap;
 
-use crate::ast::Pattern;
+use crate::ast::{unqualified, Pattern};
 use crate::span::Spanned;
 
 use super::infer::ConstructorInfo;
-use super::MonoType;
 
-/// Check if a set of match patterns is exhaustive for a given scrutinee type.
+/// Check if a set of match patterns is exhaustive.
 /// Returns a list of missing pattern descriptions, or empty if exhaustive.
 pub fn check_exhaustiveness(
     patterns: &[&Spanned<Pattern>],
-    scrut_type: &MonoType,
     constructors: &HashMap<String, ConstructorInfo>,
 ) -> Vec<String> {
-    // Wildcard or variable pattern covers everything
-    if patterns.iter().any(|p| is_catch_all(&p.node)) {
-        return vec![];
+    let checker = Checker { constructors };
+    let rows: Vec<Vec<Pat>> = patterns.iter().map(|p| vec![checker.lower(&p.node)]).collect();
+    let mut missing: Vec<String> = Vec::new();
+    for witness in checker.missing(&rows, 1) {
+        let shown = render(&witness[0]);
+        if !missing.contains(&shown) {
+            missing.push(shown);
+        }
     }
+    missing
+}
 
-    match scrut_type {
-        MonoType::Bool => check_bool_exhaustiveness(patterns),
-        MonoType::Con(type_name, _) => {
-            check_adt_exhaustiveness(patterns, type_name, constructors)
-        }
-        MonoType::List(_) => check_list_exhaustiveness(patterns),
-        // For other types (Int, String, Float, etc.), we can't check exhaustiveness
-        // practically, but we warn if there's no catch-all
-        _ => {
-            if patterns.is_empty() {
-                vec!["_".to_string()]
-            } else {
-                // Has some specific patterns but no wildcard — warn
-                vec!["_ (wildcard)".to_string()]
+/// Indices of the arms that can never match because the arms before them
+/// already cover every value they would.
+pub fn redundant_arms(
+    patterns: &[&Spanned<Pattern>],
+    constructors: &HashMap<String, ConstructorInfo>,
+) -> Vec<usize> {
+    let checker = Checker { constructors };
+    let rows: Vec<Vec<Pat>> = patterns.iter().map(|p| vec![checker.lower(&p.node)]).collect();
+    (0..rows.len())
+        .filter(|&i| !checker.useful(&rows[..i], &rows[i]))
+        .collect()
+}
+
+/// The head of a pattern that isn't a wildcard.
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Bool(bool),
+    Int(i64),
+    /// Compared by bits so `Ctor` can be `PartialEq` without NaN surprises.
+    Float(u64),
+    Str(String),
+    /// A string prefix or suffix pattern, as written. How it overlaps
+    /// literals isn't tracked, so it only ever covers itself.
+    StrAffix(String),
+    Unit,
+    Tuple(usize),
+    Nil,
+    Cons,
+    Variant(String),
+}
+
+/// A pattern reduced to wildcards and constructors: list literals become
+/// chains of `Cons` ending in `Nil`, variables become wildcards.
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+}
+
+struct Checker<'a> {
+    constructors: &'a HashMap<String, ConstructorInfo>,
+}
+
+impl Checker<'_> {
+    /// Reduce `pattern` to a `Pat`. Variants are named in their qualified
+    /// `Type.Ctor` form, so a pattern written either way means the same.
+    fn lower(&self, pattern: &Pattern) -> Pat {
+        let leaf = |c| Pat::Ctor(c, Vec::new());
+        match pattern {
+            Pattern::Wildcard | Pattern::Var(_) => Pat::Wild,
+            Pattern::IntLit(n) => leaf(Ctor::Int(*n)),
+            Pattern::FloatLit(f) => leaf(Ctor::Float(f.to_bits())),
+            Pattern::StringLit(s) => leaf(Ctor::Str(s.clone())),
+            Pattern::StringPrefix(..) | Pattern::StringSuffix(..) => {
+                leaf(Ctor::StrAffix(pattern.to_string()))
             }
+            Pattern::BoolLit(b) => leaf(Ctor::Bool(*b)),
+            Pattern::UnitLit => leaf(Ctor::Unit),
+            Pattern::Tuple(elems) => Pat::Ctor(
+                Ctor::Tuple(elems.len()),
+                elems.iter().map(|p| self.lower(&p.node)).collect(),
+            ),
+            Pattern::List(elems) => elems.iter().rev().fold(leaf(Ctor::Nil), |tail, p| {
+                Pat::Ctor(Ctor::Cons, vec![self.lower(&p.node), tail])
+            }),
+            Pattern::Cons(head, tail) => {
+                Pat::Ctor(Ctor::Cons, vec![self.lower(&head.node), self.lower(&tail.node)])
+            }
+            Pattern::Constructor { name, args } => Pat::Ctor(
+                Ctor::Variant(self.qualified(name)),
+                args.iter().map(|p| self.lower(&p.node)).collect(),
+            ),
         }
     }
-}
 
-fn is_catch_all(pattern: &Pattern) -> bool {
-    matches!(pattern, Pattern::Wildcard | Pattern::Var(_))
-}
+    /// The `Type.Ctor` form of a constructor name.
+    fn qualified(&self, name: &str) -> String {
+        match self.constructors.get(name) {
+            Some(info) if !name.contains('.') => format!("{}.{}", info.type_name, name),
+            _ => name.to_string(),
+        }
+    }
 
-fn check_bool_exhaustiveness(patterns: &[&Spanned<Pattern>]) -> Vec<String> {
-    let mut has_true = false;
-    let mut has_false = false;
-    for p in patterns {
-        match &p.node {
-            Pattern::BoolLit(true) => has_true = true,
-            Pattern::BoolLit(false) => has_false = true,
-            Pattern::Wildcard | Pattern::Var(_) => return vec![],
-            _ => {}
+    fn arity(&self, ctor: &Ctor) -> usize {
+        match ctor {
+            Ctor::Tuple(n) => *n,
+            Ctor::Cons => 2,
+            Ctor::Variant(name) => self
+                .constructors
+                .get(name)
+                .map_or(0, |info| info.field_types.len()),
+            _ => 0,
         }
     }
-    let mut missing = vec![];
-    if !has_true {
-        missing.push("true".to_string());
+
+    /// Every constructor of the type `ctor` belongs to, or `None` when
+    /// there are too many to list (numbers and strings).
+    fn all_ctors(&self, ctor: &Ctor) -> Option<Vec<Ctor>> {
+        match ctor {
+            Ctor::Bool(_) => Some(vec![Ctor::Bool(true), Ctor::Bool(false)]),
+            Ctor::Unit | Ctor::Tuple(_) => Some(vec![ctor.clone()]),
+            Ctor::Nil | Ctor::Cons => Some(vec![Ctor::Nil, Ctor::Cons]),
+            Ctor::Variant(name) => {
+                let type_name = &self.constructors.get(name)?.type_name;
+                let mut names: Vec<&String> = self
+                    .constructors
+                    .iter()
+                    .filter(|(name, info)| &info.type_name == type_name && name.contains('.'))
+                    .map(|(name, _)| name)
+                    .collect();
+                names.sort();
+                Some(names.into_iter().map(|n| Ctor::Variant(n.clone())).collect())
+            }
+            Ctor::Int(_) | Ctor::Float(_) | Ctor::Str(_) | Ctor::StrAffix(_) => None,
+        }
     }
-    if !has_false {
-        missing.push("false".to_string());
+
+    /// Rows that match `ctor` in the first column, with its arguments
+    /// spliced in place of it.
+    fn specialize(&self, rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+        rows.iter()
+            .filter_map(|row| {
+                let mut out = match &row[0] {
+                    Pat::Ctor(c, args) if c == ctor => args.clone(),
+                    Pat::Ctor(..) => return None,
+                    Pat::Wild => vec![Pat::Wild; arity],
+                };
+                out.extend_from_slice(&row[1..]);
+                Some(out)
+            })
+            .collect()
     }
-    missing
-}
 
-fn check_adt_exhaustiveness(
-    patterns: &[&Spanned<Pattern>],
-    type_name: &str,
-    constructors: &HashMap<String, ConstructorInfo>,
-) -> Vec<String> {
-    // Collect all constructors for this type
-    let all_ctors: Vec<String> = constructors
-        .iter()
-        .filter(|(_, info)| info.type_name == type_name)
-        .map(|(name, _)| name.clone())
-        .collect();
-
-    if all_ctors.is_empty() {
-        return vec![];
+    /// Rows whose first column is a wildcard, without that column.
+    fn default_rows(&self, rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+        rows.iter()
+            .filter(|row| matches!(row[0], Pat::Wild))
+            .map(|row| row[1..].to_vec())
+            .collect()
     }
 
-    // Check which constructors are covered by the patterns
-    let mut covered: std::collections::HashSet<String> = std::collections::HashSet::new();
-    for p in patterns {
-        match &p.node {
-            Pattern::Constructor { name, .. } => {
-                covered.insert(name.clone());
+    /// Whether some value matches `row` but none of `rows`.
+    fn useful(&self, rows: &[Vec<Pat>], row: &[Pat]) -> bool {
+        let Some(first) = row.first() else {
+            return rows.is_empty();
+        };
+        match first {
+            Pat::Ctor(ctor, _) => {
+                let arity = self.arity(ctor);
+                let row = self.specialize(&[row.to_vec()], ctor, arity).remove(0);
+                self.useful(&self.specialize(rows, ctor, arity), &row)
+            }
+            Pat::Wild => {
+                let heads = head_ctors(rows);
+                let all = heads.first().and_then(|c| self.all_ctors(c));
+                match all.filter(|all| all.iter().all(|c| heads.contains(&c))) {
+                    Some(all) => all.iter().any(|ctor| {
+                        let arity = self.arity(ctor);
+                        let row = self.specialize(&[row.to_vec()], ctor, arity).remove(0);
+                        self.useful(&self.specialize(rows, ctor, arity), &row)
+                    }),
+                    None => self.useful(&self.default_rows(rows), &row[1..]),
+                }
             }
-            Pattern::Wildcard | Pattern::Var(_) => return vec![],
-            _ => {}
         }
     }
 
-    let mut missing: Vec<String> = all_ctors
-        .into_iter()
-        .filter(|c| !covered.contains(c))
-        .collect();
-    missing.sort();
-    missing
-}
+    /// Vectors of `width` patterns that no row matches, with `_` wherever
+    /// any value would do. Empty when the rows are exhaustive.
+    fn missing(&self, rows: &[Vec<Pat>], width: usize) -> Vec<Vec<Pat>> {
+        if width == 0 {
+            return if rows.is_empty() { vec![Vec::new()] } else { Vec::new() };
+        }
 
-fn check_list_exhaustiveness(patterns: &[&Spanned<Pattern>]) -> Vec<String> {
-    let mut has_empty = false;
-    let mut has_cons = false;
-    for p in patterns {
-        match &p.node {
-            Pattern::List(elems) if elems.is_empty() => has_empty = true,
-            Pattern::List(_) => {} // specific length — partial coverage
-            Pattern::Cons(_, _) => has_cons = true,
-            Pattern::Wildcard | Pattern::Var(_) => return vec![],
-            _ => {}
+        let heads = head_ctors(rows);
+        let all = heads.first().and_then(|c| self.all_ctors(c));
+
+        // Every constructor appears: a missing value has one of them on top
+        if let Some(all) = all.as_ref().filter(|all| all.iter().all(|c| heads.contains(&c))) {
+            let mut out = Vec::new();
+            for ctor in all {
+                let arity = self.arity(ctor);
+                for w in self.missing(&self.specialize(rows, ctor, arity), arity + width - 1) {
+                    let mut witness = vec![Pat::Ctor(ctor.clone(), w[..arity].to_vec())];
+                    witness.extend_from_slice(&w[arity..]);
+                    out.push(witness);
+                }
+            }
+            return out;
         }
+
+        // Otherwise some head is left to the wildcard rows
+        let rest = self.missing(&self.default_rows(rows), width - 1);
+        if rest.is_empty() {
+            return rest;
+        }
+        let firsts: Vec<Pat> = match all {
+            Some(all) if !heads.is_empty() => all
+                .into_iter()
+                .filter(|c| !heads.contains(&c))
+                .map(|c| Pat::Ctor(c.clone(), vec![Pat::Wild; self.arity(&c)]))
+                .collect(),
+            _ => vec![Pat::Wild],
+        };
+        let mut out = Vec::new();
+        for first in &firsts {
+            for w in &rest {
+                let mut witness = vec![first.clone()];
+                witness.extend_from_slice(w);
+                out.push(witness);
+            }
+        }
+        out
     }
-    let mut missing = vec![];
-    if !has_empty {
-        missing.push("[]".to_string());
+}
+
+/// The distinct constructors at the head of `rows`, in order.
+fn head_ctors(rows: &[Vec<Pat>]) -> Vec<&Ctor> {
+    let mut heads: Vec<&Ctor> = Vec::new();
+    for row in rows {
+        if let Pat::Ctor(c, _) = &row[0] {
+            if !heads.contains(&c) {
+                heads.push(c);
+            }
+        }
     }
-    if !has_cons {
-        missing.push("(_ :: _)".to_string());
+    heads
+}
+
+/// Show a missing value as a pattern, e.g. `Some(None)` or `(_ :: _)`.
+fn render(pat: &Pat) -> String {
+    let list = |items: &[Pat]| items.iter().map(render).collect::<Vec<_>>().join(", ");
+    match pat {
+        Pat::Wild => "_".to_string(),
+        Pat::Ctor(ctor, args) => match ctor {
+            Ctor::Bool(b) => b.to_string(),
+            Ctor::Int(n) => n.to_string(),
+            Ctor::Float(bits) => f64::from_bits(*bits).to_string(),
+            Ctor::Str(s) => format!("\"{}\"", s),
+            Ctor::StrAffix(s) => s.clone(),
+            Ctor::Unit => "()".to_string(),
+            Ctor::Tuple(_) => format!("({})", list(args)),
+            Ctor::Nil => "[]".to_string(),
+            Ctor::Cons => {
+                // A chain of conses shares one pair of parentheses
+                let mut parts = vec![render(&args[0])];
+                let mut tail = &args[1];
+                while let Pat::Ctor(Ctor::Cons, rest) = tail {
+                    parts.push(render(&rest[0]));
+                    tail = &rest[1];
+                }
+                parts.push(render(tail));
+                format!("({})", parts.join(" :: "))
+            }
+            Ctor::Variant(name) if args.is_empty() => unqualified(name).to_string(),
+            Ctor::Variant(name) => format!("{}({})", unqualified(name), list(args)),
+        },
     }
-    missing
 }
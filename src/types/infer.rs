@@ -1,54 +1,338 @@
 use std::collections::HashMap;
 
 use crate::ast::*;
+use crate::diagnostic::{Diagnostic, Label, Severity};
 use crate::error::LyraError;
-use crate::span::Span;
+use crate::span::{Span, Spanned};
 
 use super::env::TypeEnv;
 use super::subst::Subst;
+use super::trace::TraceEvent;
 use super::unify::unify;
-use super::{MonoType, TypeScheme, TypeVar, TypeVarGen};
+use super::{Constraint, MonoType, TypeScheme, TypeVar, TypeVarGen};
 
 pub struct Inferencer {
     gen: TypeVarGen,
     /// Maps constructor names to (type_name, type_params, field_types)
     constructors: HashMap<String, ConstructorInfo>,
+    /// Inference steps recorded for `--debug-types`; `None` when not tracing.
+    trace: Option<Vec<TraceEvent>>,
+    /// Types of local binders (parameters, `let`s, pattern variables) keyed
+    /// by the binder's span, for editor tooling; `None` when not recording.
+    binders: Option<Vec<(Span, MonoType)>>,
+    /// Types of every expression keyed by its span, for backends that lower
+    /// the typed AST; `None` when not recording.
+    exprs: Option<Vec<(Span, MonoType)>>,
+    /// Non-fatal problems found so far, such as incomplete matches.
+    warnings: Vec<Diagnostic>,
+    /// Constraints of numeric operators on types not yet known well enough
+    /// to decide them, oldest first, with the operator's span.
+    pending: Vec<(Constraint, Span)>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ConstructorInfo {
     pub type_name: String,
     pub type_params: Vec<String>,
+    /// The variables standing for `type_params` in `field_types`.
+    pub param_vars: Vec<TypeVar>,
     pub field_types: Vec<MonoType>,
 }
 
+impl Default for Inferencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Inferencer {
     pub fn new() -> Self {
         Inferencer {
             gen: TypeVarGen::new(),
             constructors: HashMap::new(),
+            trace: None,
+            binders: None,
+            exprs: None,
+            warnings: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Start recording the type of every local binder.
+    pub fn enable_binder_types(&mut self) {
+        self.binders.get_or_insert_with(Vec::new);
+    }
+
+    /// The recorded binder types. A declaration's binders are fully resolved
+    /// once it type-checks.
+    pub fn binder_types(&self) -> &[(Span, MonoType)] {
+        self.binders.as_deref().unwrap_or_default()
+    }
+
+    /// Start recording the type of every expression.
+    pub fn enable_expr_types(&mut self) {
+        self.exprs.get_or_insert_with(Vec::new);
+    }
+
+    /// The recorded expression types, resolved like the binder types.
+    /// Expressions that share a span, as in a macro's expansion, each have
+    /// an entry, innermost first.
+    pub fn expr_types(&self) -> &[(Span, MonoType)] {
+        self.exprs.as_deref().unwrap_or_default()
+    }
+
+    /// Constructors of the types declared so far, by name.
+    pub fn constructors(&self) -> &HashMap<String, ConstructorInfo> {
+        &self.constructors
+    }
+
+    fn record_binder(&mut self, span: Span, ty: &MonoType) {
+        if let Some(binders) = &mut self.binders {
+            binders.push((span, ty.clone()));
         }
     }
 
-    /// Instantiate a type scheme with fresh type variables.
-    fn instantiate(&mut self, scheme: &TypeScheme) -> MonoType {
+    /// How many binder and expression types are recorded so far.
+    fn recorded(&self) -> (usize, usize) {
+        (self.binder_types().len(), self.expr_types().len())
+    }
+
+    /// Apply a declaration's final substitution to the binder and expression
+    /// types recorded while checking it.
+    fn resolve_binders(&mut self, from: (usize, usize), subst: &Subst) {
+        let recorded = [(&mut self.binders, from.0), (&mut self.exprs, from.1)];
+        for (types, from) in recorded {
+            if let Some(types) = types {
+                for (_, ty) in &mut types[from..] {
+                    *ty = subst.apply(ty);
+                }
+            }
+        }
+    }
+
+    /// Start recording every unification, instantiation and generalization.
+    pub fn enable_trace(&mut self) {
+        self.trace.get_or_insert_with(Vec::new);
+    }
+
+    /// The steps recorded since the last call; empty when not tracing.
+    pub fn take_trace(&mut self) -> Vec<TraceEvent> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The warnings found since the last call.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn warn(&mut self, code: &'static str, message: String, primary: Label, secondary: Vec<Label>) {
+        self.warnings.push(Diagnostic {
+            severity: Severity::Warning,
+            kind: "warning",
+            code,
+            message,
+            primary: Some(primary),
+            secondary,
+            suggestion: None,
+        });
+    }
+
+    fn record(&mut self, event: impl FnOnce() -> TraceEvent) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(event());
+        }
+    }
+
+    /// Unify two types, recording the step when tracing.
+    fn unify(&mut self, t1: &MonoType, t2: &MonoType, span: Span) -> Result<Subst, LyraError> {
+        let result = unify(t1, t2, span);
+        self.record(|| TraceEvent::Unify {
+            left: t1.clone(),
+            right: t2.clone(),
+            result: result.clone().map_err(|e| e.to_string()),
+            span,
+        });
+        result
+    }
+
+    /// Instantiate a type scheme with fresh type variables, requiring its
+    /// constraints of them.
+    fn instantiate(&mut self, scheme: &TypeScheme, span: Span) -> MonoType {
         let fresh_map: HashMap<TypeVar, MonoType> = scheme
             .vars
             .iter()
             .map(|&v| (v, self.gen.fresh_type()))
             .collect();
         let subst = Subst { map: fresh_map };
-        subst.apply(&scheme.ty)
+        let ty = subst.apply_once(&scheme.ty);
+        for c in &scheme.constraints {
+            self.pending.push((c.map(|t| subst.apply_once(t)), span));
+        }
+        if !scheme.vars.is_empty() {
+            self.record(|| TraceEvent::Instantiate {
+                scheme: scheme.clone(),
+                ty: ty.clone(),
+                span,
+            });
+        }
+        ty
     }
 
-    /// Generalize a type over variables not free in the environment.
-    fn generalize(env: &TypeEnv, ty: &MonoType) -> TypeScheme {
-        let env_free = env.free_vars();
+    /// Generalize the type of `name`'s value, inferred with `subst`, over
+    /// variables not free in the environment. The constraints required since
+    /// `from` that are only of those variables go into the scheme; a variable
+    /// a constraint ties to the environment, or to a variable the type
+    /// doesn't show, is left as it is.
+    fn generalize(
+        &mut self,
+        name: &str,
+        env: &TypeEnv,
+        subst: Subst,
+        ty: &MonoType,
+        span: Span,
+        from: usize,
+    ) -> Result<(Subst, TypeScheme), LyraError> {
+        let subst = self.settle(from, &subst)?.compose(&subst);
+        let ty = subst.apply(ty);
         let ty_free = ty.free_vars();
-        let quantified: Vec<TypeVar> = ty_free.difference(&env_free).copied().collect();
-        TypeScheme {
-            vars: quantified,
-            ty: ty.clone(),
+        let mut fixed = env.apply_subst(&subst).free_vars();
+        let mut grown = true;
+        while grown {
+            grown = false;
+            for (c, _) in &self.pending[from..] {
+                let vars = c.free_vars();
+                if vars.iter().any(|v| fixed.contains(v) || !ty_free.contains(v))
+                    && !vars.is_subset(&fixed)
+                {
+                    fixed.extend(vars);
+                    grown = true;
+                }
+            }
+        }
+        let quantified: Vec<TypeVar> = ty_free.difference(&fixed).copied().collect();
+        let (kept, constraints): (Vec<_>, Vec<_>) = self
+            .pending
+            .split_off(from)
+            .into_iter()
+            .partition(|(c, _)| c.free_vars().iter().any(|v| fixed.contains(v)));
+        self.pending.extend(kept);
+        let mut scheme = TypeScheme::mono(ty.clone());
+        scheme.vars = quantified;
+        for (c, _) in constraints {
+            if !scheme.constraints.contains(&c) {
+                scheme.constraints.push(c);
+            }
+        }
+        self.record(|| TraceEvent::Generalize {
+            name: name.to_string(),
+            ty,
+            scheme: scheme.clone(),
+            span,
+        });
+        Ok((subst, scheme))
+    }
+
+    /// Require `constraint`, deciding it now as far as its types allow and
+    /// keeping the rest for later.
+    fn constrain(&mut self, constraint: Constraint, span: Span) -> Result<Subst, LyraError> {
+        let (s, rest) = self.reduce(&constraint, span)?;
+        self.pending.extend(rest.map(|c| (c, span)));
+        Ok(s)
+    }
+
+    /// Decide what can be of the constraints required since `from`, with the
+    /// substitution `subst` found since, returning what that adds to it.
+    fn settle(&mut self, from: usize, subst: &Subst) -> Result<Subst, LyraError> {
+        let mut added = Subst::new();
+        loop {
+            let decided = added.map.len();
+            for (c, span) in self.pending.split_off(from) {
+                let full = added.compose(subst);
+                let (s, rest) = self.reduce(&c.map(|t| full.apply(t)), span)?;
+                added = s.compose(&added);
+                self.pending.extend(rest.map(|c| (c, span)));
+            }
+            if added.map.len() == decided {
+                return Ok(added);
+            }
+        }
+    }
+
+    /// At the end of a declaration, settle its constraints, taking numbers
+    /// nothing decides to be Ints and compared values to be of one type.
+    fn default_pending(&mut self, subst: Subst) -> Result<Subst, LyraError> {
+        let mut subst = self.settle(0, &subst)?.compose(&subst);
+        while let Some((c, span)) = self.pending.pop() {
+            let c = c.map(|t| subst.apply(t));
+            let chosen = match &c {
+                Constraint::Compare { lhs, rhs } => self.unify(lhs, rhs, span)?,
+                _ => Subst {
+                    map: c.free_vars().into_iter().map(|v| (v, MonoType::Int)).collect(),
+                },
+            };
+            let (s, _) = self.reduce(&c.map(|t| chosen.apply(t)), span)?;
+            subst = s.compose(&chosen).compose(&subst);
+            subst = self.settle(0, &subst)?.compose(&subst);
+        }
+        Ok(subst)
+    }
+
+    /// Decide `constraint` as far as its types are known, giving what that
+    /// substitutes and what's left to decide once they're known better.
+    fn reduce(
+        &mut self,
+        constraint: &Constraint,
+        span: Span,
+    ) -> Result<(Subst, Option<Constraint>), LyraError> {
+        use MonoType::{Float, Int, Var};
+
+        match constraint {
+            Constraint::Numeric { ty: Var(_), .. } => Ok((Subst::new(), Some(constraint.clone()))),
+            // Must be Int or Float, or String for `+`
+            Constraint::Numeric { ty, text } => {
+                let s = self
+                    .unify(ty, &Int, span)
+                    .or_else(|e| match text {
+                        true => self.unify(ty, &MonoType::String, span).map_err(|_| e),
+                        false => Err(e),
+                    })
+                    .or_else(|_| self.unify(ty, &Float, span))?;
+                Ok((s, None))
+            }
+
+            // An Int mixed with a Float is promoted at runtime
+            Constraint::Arith { lhs, rhs, result, .. } if is_mixed_numeric(lhs, rhs) => {
+                Ok((self.unify(result, &Float, span)?, None))
+            }
+            Constraint::Arith { lhs: Var(a), rhs: Var(b), .. } if a != b => {
+                Ok((Subst::new(), Some(constraint.clone())))
+            }
+            Constraint::Arith { lhs: v @ Var(_), rhs: n @ (Int | Float), result, .. }
+            | Constraint::Arith { lhs: n @ (Int | Float), rhs: v @ Var(_), result, .. } => {
+                let result_ty = if *n == Float { Float } else { v.clone() };
+                let s = self.unify(result, &result_ty, span)?;
+                Ok((s, Some(Constraint::Numeric { ty: v.clone(), text: false })))
+            }
+            Constraint::Arith { lhs, rhs, result, text } => {
+                let s1 = self.unify(lhs, rhs, span)?;
+                let ty = s1.apply(lhs);
+                let s2 = self.unify(&s1.apply(result), &ty, span)?;
+                let s = s2.compose(&s1);
+                let (s3, rest) = self.reduce(&Constraint::Numeric { ty, text: *text }, span)?;
+                Ok((s3.compose(&s), rest))
+            }
+
+            Constraint::Compare { lhs, rhs } if is_mixed_numeric(lhs, rhs) => {
+                Ok((Subst::new(), None))
+            }
+            Constraint::Compare { lhs: Var(a), rhs: Var(b) } if a != b => {
+                Ok((Subst::new(), Some(constraint.clone())))
+            }
+            Constraint::Compare { lhs: v @ Var(_), rhs: Int | Float }
+            | Constraint::Compare { lhs: Int | Float, rhs: v @ Var(_) } => {
+                Ok((Subst::new(), Some(Constraint::Numeric { ty: v.clone(), text: false })))
+            }
+            Constraint::Compare { lhs, rhs } => Ok((self.unify(lhs, rhs, span)?, None)),
         }
     }
 
@@ -62,6 +346,7 @@ impl Inferencer {
             name,
             type_params,
             variants,
+            ..
         } = decl
         {
             // Create a mapping from type param names to type variables
@@ -96,18 +381,22 @@ impl Inferencer {
                 let scheme = TypeScheme {
                     vars: param_vars.iter().map(|(_, v)| *v).collect(),
                     ty: ctor_type,
+                    constraints: vec![],
                 };
 
-                env.insert(variant.name.node.clone(), scheme);
+                let info = ConstructorInfo {
+                    type_name: name.node.clone(),
+                    type_params: type_params.iter().map(|p| p.node.clone()).collect(),
+                    param_vars: param_vars.iter().map(|(_, v)| *v).collect(),
+                    field_types,
+                };
 
-                self.constructors.insert(
-                    variant.name.node.clone(),
-                    ConstructorInfo {
-                        type_name: name.node.clone(),
-                        type_params: type_params.iter().map(|p| p.node.clone()).collect(),
-                        field_types,
-                    },
-                );
+                // `Type.Ctor` stays unambiguous when another type reuses the name
+                let qualified = format!("{}.{}", name.node, variant.name.node);
+                env.insert(qualified.clone(), scheme.clone());
+                self.constructors.insert(qualified, info.clone());
+                env.insert(variant.name.node.clone(), scheme);
+                self.constructors.insert(variant.name.node.clone(), info);
             }
         }
         Ok(())
@@ -158,12 +447,212 @@ impl Inferencer {
         }
     }
 
+    /// The scheme a type signature declares, plus its type with each type
+    /// variable held rigid, to check the definition against. A rigid `a` is
+    /// a constructor no other type unifies with, so a body that only works
+    /// for some `a` is rejected.
+    fn signature(&mut self, ann: &SpannedTypeAnn) -> (TypeScheme, MonoType) {
+        let mut names = Vec::new();
+        type_ann_vars(ann, &mut names);
+        let params: Vec<(String, TypeVar)> =
+            names.into_iter().map(|n| (n, self.gen.fresh())).collect();
+        let ty = self.type_ann_to_mono(ann, &params);
+        let rigid = Subst {
+            map: params
+                .iter()
+                .map(|(n, v)| (*v, MonoType::Con(n.clone(), vec![])))
+                .collect(),
+        };
+        let rigid_ty = rigid.apply(&ty);
+        let scheme = TypeScheme {
+            vars: params.into_iter().map(|(_, v)| v).collect(),
+            ty,
+            constraints: vec![],
+        };
+        (scheme, rigid_ty)
+    }
+
+    /// Check an annotated binding's value against its annotation, returning
+    /// the annotation's scheme for the name.
+    fn check_annotated(
+        &mut self,
+        env: &TypeEnv,
+        name: &Spanned<String>,
+        recursive: bool,
+        ann: &SpannedTypeAnn,
+        ast: &Ast,
+        value: ExprId,
+    ) -> Result<(Subst, TypeScheme), LyraError> {
+        let (scheme, expected) = self.signature(ann);
+        let mut value_env = env.clone();
+        if recursive {
+            value_env.insert(name.node.clone(), scheme.clone());
+            bind_params(&mut value_env, &name.node, &ast[value]);
+        }
+        let s = self.check(&value_env, ast, value, &expected, ann.span)?;
+        self.record_binder(name.span, &scheme.ty);
+        Ok((s, scheme))
+    }
+
+    /// Check an expression against a known type, which the annotation at
+    /// `origin` gave. A lambda takes its parameter types from it, so a
+    /// mismatch is reported where it occurs in the body instead of at the
+    /// whole function.
+    fn check(
+        &mut self,
+        env: &TypeEnv,
+        ast: &Ast,
+        expr: ExprId,
+        expected: &MonoType,
+        origin: Span,
+    ) -> Result<Subst, LyraError> {
+        if let Expr::Lambda { params, body } = &ast[expr].node {
+            let mut param_types = Vec::new();
+            let mut ret = expected;
+            while param_types.len() < params.len() {
+                let MonoType::Arrow(from, to) = ret else {
+                    break;
+                };
+                param_types.push(from.as_ref().clone());
+                ret = to;
+            }
+            if param_types.len() == params.len() {
+                let mut body_env = env.clone();
+                for (param, ty) in params.iter().zip(&param_types) {
+                    self.record_binder(param.name.span, ty);
+                    body_env.insert(param.name.node.clone(), TypeScheme::mono(ty.clone()));
+                }
+                return self.check(&body_env, ast, *body, ret, origin);
+            }
+        }
+
+        let (s1, ty) = self.infer(env, ast, expr)?;
+        let s2 = self
+            .unify(&s1.apply(expected), &ty, ast[expr].span)
+            .map_err(|e| e.with_origin(origin, "expected because of this annotation"))?;
+        Ok(s2.compose(&s1))
+    }
+
+    /// Warn about values no arm matches and arms that can never match.
+    fn check_arms(&mut self, span: Span, arms: &[MatchArm]) {
+        use super::exhaustiveness::{check_exhaustiveness, redundant_arms};
+
+        let patterns: Vec<_> = arms.iter().map(|a| &a.pattern).collect();
+        let missing = check_exhaustiveness(&patterns, &self.constructors);
+        if !missing.is_empty() {
+            self.warn(
+                "W0001",
+                format!("non-exhaustive patterns: missing {}", missing.join(", ")),
+                Label {
+                    span,
+                    message: format!("not covered: {}", missing.join(", ")),
+                },
+                Vec::new(),
+            );
+        }
+        for i in redundant_arms(&patterns, &self.constructors) {
+            // Point at an earlier catch-all, the usual culprit
+            let catch_all = arms[..i]
+                .iter()
+                .find(|a| matches!(a.pattern.node, Pattern::Wildcard | Pattern::Var(_)));
+            self.warn(
+                "W0002",
+                "unreachable match arm".to_string(),
+                Label {
+                    span: arms[i].pattern.span,
+                    message: "earlier arms already match every value this one does".to_string(),
+                },
+                catch_all
+                    .map(|a| Label {
+                        span: a.pattern.span,
+                        message: "this arm matches anything".to_string(),
+                    })
+                    .into_iter()
+                    .collect(),
+            );
+        }
+    }
+
+    fn infer_app(
+        &mut self,
+        env: &TypeEnv,
+        ast: &Ast,
+        func: ExprId,
+        args: &[ExprId],
+        span: Span,
+    ) -> Result<(Subst, MonoType), LyraError> {
+        // A variadic builtin checks each extra argument against a fresh
+        // instance of its last parameter
+        let variadic = match &ast[func].node {
+            Expr::Var(name) | Expr::Local { name, .. } if env.is_variadic(name) => env.lookup(name),
+            _ => None,
+        };
+        let fixed = variadic.map_or(args.len(), |s| s.ty.params().len().min(args.len()));
+        let (args, extra) = args.split_at(fixed);
+
+        let (s1, fn_ty) = self.infer(env, ast, func)?;
+        // `println()` runs a variadic builtin with no arguments
+        if variadic.is_some() && args.is_empty() && extra.is_empty() {
+            let mut ret = fn_ty;
+            while let MonoType::Arrow(_, inner) = ret {
+                ret = *inner;
+            }
+            return Ok((s1, ret));
+        }
+        let mut subst = s1;
+        let mut current_fn_ty = fn_ty;
+
+        for &arg in args {
+            let env2 = env.apply_subst(&subst);
+            let (s2, arg_ty) = self.infer(&env2, ast, arg)?;
+            subst = s2.compose(&subst);
+
+            let ret_ty = self.gen.fresh_type();
+            let expected_fn =
+                MonoType::Arrow(Box::new(subst.apply(&arg_ty)), Box::new(ret_ty.clone()));
+            let s3 = self.unify(&subst.apply(&current_fn_ty), &expected_fn, span)?;
+            subst = s3.compose(&subst);
+            current_fn_ty = subst.apply(&ret_ty);
+        }
+
+        if let Some(scheme) = variadic {
+            for &arg in extra {
+                let ty = self.instantiate(scheme, ast[arg].span);
+                let param = ty.params().last().map(|p| (*p).clone());
+                let env2 = env.apply_subst(&subst);
+                let (s2, arg_ty) = self.infer(&env2, ast, arg)?;
+                subst = s2.compose(&subst);
+                if let Some(param) = param {
+                    let s3 = self.unify(&param, &subst.apply(&arg_ty), ast[arg].span)?;
+                    subst = s3.compose(&subst);
+                }
+            }
+        }
+
+        Ok((subst, current_fn_ty))
+    }
+
     /// Infer the type of an expression. Returns (substitution, type).
     pub fn infer(
         &mut self,
         env: &TypeEnv,
-        expr: &SpannedExpr,
+        ast: &Ast,
+        expr: ExprId,
     ) -> Result<(Subst, MonoType), LyraError> {
+        let inferred = self.infer_expr(env, ast, expr)?;
+        if let Some(exprs) = &mut self.exprs {
+            exprs.push((ast[expr].span, inferred.1.clone()));
+        }
+        Ok(inferred)
+    }
+
+    fn infer_expr(
+        &mut self,
+        env: &TypeEnv,
+        ast: &Ast,
+        expr: ExprId,
+    ) -> Result<(Subst, MonoType), LyraError> {
+        let expr = &ast[expr];
         match &expr.node {
             // ── Literals ──
             Expr::IntLit(_) => Ok((Subst::new(), MonoType::Int)),
@@ -173,9 +662,9 @@ impl Inferencer {
             Expr::UnitLit => Ok((Subst::new(), MonoType::Unit)),
 
             // ── Variable ──
-            Expr::Var(name) => match env.lookup(name) {
+            Expr::Var(name) | Expr::Local { name, .. } => match env.lookup(name) {
                 Some(scheme) => {
-                    let ty = self.instantiate(scheme);
+                    let ty = self.instantiate(scheme, expr.span);
                     Ok((Subst::new(), ty))
                 }
                 None => {
@@ -195,13 +684,16 @@ impl Inferencer {
                     let tv = self.gen.fresh_type();
                     Ok((Subst::new(), MonoType::List(Box::new(tv))))
                 } else {
-                    let (mut subst, first_ty) = self.infer(env, &elems[0])?;
-                    for elem in &elems[1..] {
+                    let (mut subst, first_ty) = self.infer(env, ast, elems[0])?;
+                    for &elem in &elems[1..] {
                         let env2 = env.apply_subst(&subst);
-                        let (s, ty) = self.infer(&env2, elem)?;
+                        let (s, ty) = self.infer(&env2, ast, elem)?;
                         subst = s.compose(&subst);
-                        let s_u =
-                            unify(&subst.apply(&first_ty), &subst.apply(&ty), elem.span)?;
+                        let s_u = self
+                            .unify(&subst.apply(&first_ty), &subst.apply(&ty), ast[elem].span)
+                            .map_err(|e| {
+                                e.with_origin(ast[elems[0]].span, "expected because of the first element")
+                            })?;
                         subst = s_u.compose(&subst);
                     }
                     Ok((
@@ -215,9 +707,9 @@ impl Inferencer {
             Expr::TupleLit(elems) => {
                 let mut subst = Subst::new();
                 let mut types = Vec::new();
-                for elem in elems {
+                for &elem in elems {
                     let env2 = env.apply_subst(&subst);
-                    let (s, ty) = self.infer(&env2, elem)?;
+                    let (s, ty) = self.infer(&env2, ast, elem)?;
                     subst = s.compose(&subst);
                     types.push(subst.apply(&ty));
                 }
@@ -231,10 +723,11 @@ impl Inferencer {
 
                 let mut new_env = env.clone();
                 for (param, ty) in params.iter().zip(&param_types) {
+                    self.record_binder(param.name.span, ty);
                     new_env.insert(param.name.node.clone(), TypeScheme::mono(ty.clone()));
                 }
 
-                let (s, body_ty) = self.infer(&new_env, body)?;
+                let (s, body_ty) = self.infer(&new_env, ast, *body)?;
 
                 let fn_type = param_types
                     .into_iter()
@@ -248,48 +741,49 @@ impl Inferencer {
 
             // ── Application ──
             Expr::App { func, args } => {
-                let (s1, fn_ty) = self.infer(env, func)?;
-                let mut subst = s1;
-                let mut current_fn_ty = fn_ty;
-
-                for arg in args {
-                    let env2 = env.apply_subst(&subst);
-                    let (s2, arg_ty) = self.infer(&env2, arg)?;
-                    subst = s2.compose(&subst);
-
-                    let ret_ty = self.gen.fresh_type();
-                    let expected_fn = MonoType::Arrow(
-                        Box::new(subst.apply(&arg_ty)),
-                        Box::new(ret_ty.clone()),
-                    );
-                    let s3 = unify(
-                        &subst.apply(&current_fn_ty),
-                        &expected_fn,
-                        expr.span,
-                    )?;
-                    subst = s3.compose(&subst);
-                    current_fn_ty = subst.apply(&ret_ty);
-                }
+                self.infer_app(env, ast, *func, args, expr.span)
+            }
 
-                Ok((subst, current_fn_ty))
+            // Labels are resolved against the callee's declared parameters
+            Expr::LabeledApp { func, args } => {
+                let params = match &ast[*func].node {
+                    Expr::Var(name) | Expr::Local { name, .. } => {
+                        if env.lookup(name).is_none() {
+                            self.infer(env, ast, *func)?;
+                        }
+                        env.params(name).map(|p| (name.as_str(), p))
+                    }
+                    _ => None,
+                };
+                let Some((name, params)) = params else {
+                    return Err(LyraError::LabelMismatch {
+                        name: ast.display(*func).to_string(),
+                        message: "labeled arguments need a function declared with named parameters"
+                            .to_string(),
+                        span: ast[*func].span,
+                    });
+                };
+                let order = order_args(ast, name, params, args, expr.span)?;
+                let args: Vec<ExprId> = order.into_iter().map(|i| args[i].value).collect();
+                self.infer_app(env, ast, *func, &args, expr.span)
             }
 
             // ── Binary operation ──
-            Expr::BinOp { op, lhs, rhs } => self.infer_binop(env, op, lhs, rhs, expr.span),
+            Expr::BinOp { op, lhs, rhs } => self.infer_binop(env, op, ast, *lhs, *rhs, expr.span),
 
             // ── Unary operation ──
             Expr::UnaryOp { op, operand } => {
-                let (s, ty) = self.infer(env, operand)?;
+                let (s, ty) = self.infer(env, ast, *operand)?;
                 match op {
                     UnaryOp::Neg => {
                         // Allow neg on Int or Float
-                        let s2 = unify(&ty, &MonoType::Int, expr.span)
-                            .or_else(|_| unify(&ty, &MonoType::Float, expr.span))?;
+                        let numeric = Constraint::Numeric { ty: ty.clone(), text: false };
+                        let s2 = self.constrain(numeric, expr.span)?;
                         let s = s2.compose(&s);
                         Ok((s.clone(), s.apply(&ty)))
                     }
                     UnaryOp::Not => {
-                        let s2 = unify(&ty, &MonoType::Bool, expr.span)?;
+                        let s2 = self.unify(&ty, &MonoType::Bool, expr.span)?;
                         let s = s2.compose(&s);
                         Ok((s, MonoType::Bool))
                     }
@@ -298,9 +792,9 @@ impl Inferencer {
 
             // ── Pipe ──
             Expr::Pipe { lhs, rhs } => {
-                let (s1, lhs_ty) = self.infer(env, lhs)?;
+                let (s1, lhs_ty) = self.infer(env, ast, *lhs)?;
                 let env2 = env.apply_subst(&s1);
-                let (s2, rhs_ty) = self.infer(&env2, rhs)?;
+                let (s2, rhs_ty) = self.infer(&env2, ast, *rhs)?;
                 let subst = s2.compose(&s1);
 
                 let ret_ty = self.gen.fresh_type();
@@ -308,7 +802,7 @@ impl Inferencer {
                     Box::new(subst.apply(&lhs_ty)),
                     Box::new(ret_ty.clone()),
                 );
-                let s3 = unify(&subst.apply(&rhs_ty), &expected_fn, expr.span)?;
+                let s3 = self.unify(&subst.apply(&rhs_ty), &expected_fn, expr.span)?;
                 let s = s3.compose(&subst);
                 Ok((s.clone(), s.apply(&ret_ty)))
             }
@@ -319,16 +813,20 @@ impl Inferencer {
                 then_branch,
                 else_branch,
             } => {
-                let (s1, cond_ty) = self.infer(env, cond)?;
-                let s2 = unify(&cond_ty, &MonoType::Bool, cond.span)?;
+                let (s1, cond_ty) = self.infer(env, ast, *cond)?;
+                let s2 = self.unify(&cond_ty, &MonoType::Bool, ast[*cond].span)?;
                 let mut s = s2.compose(&s1);
 
-                let (s3, then_ty) = self.infer(&env.apply_subst(&s), then_branch)?;
+                let (s3, then_ty) = self.infer(&env.apply_subst(&s), ast, *then_branch)?;
                 s = s3.compose(&s);
-                let (s4, else_ty) = self.infer(&env.apply_subst(&s), else_branch)?;
+                let (s4, else_ty) = self.infer(&env.apply_subst(&s), ast, *else_branch)?;
                 s = s4.compose(&s);
 
-                let s5 = unify(&s.apply(&then_ty), &s.apply(&else_ty), expr.span)?;
+                let s5 = self
+                    .unify(&s.apply(&then_ty), &s.apply(&else_ty), ast[*else_branch].span)
+                    .map_err(|e| {
+                        e.with_origin(ast[*then_branch].span, "expected because of the `then` branch")
+                    })?;
                 s = s5.compose(&s);
                 Ok((s.clone(), s.apply(&then_ty)))
             }
@@ -337,47 +835,67 @@ impl Inferencer {
             Expr::Let {
                 name,
                 recursive,
+                type_ann,
                 value,
                 body,
-                ..
             } => {
-                if *recursive {
+                if let Some(ann) = type_ann {
+                    let (s1, scheme) = self.check_annotated(env, name, *recursive, ann, ast, *value)?;
+                    let mut body_env = env.apply_subst(&s1);
+                    body_env.insert(name.node.clone(), scheme);
+                    bind_params(&mut body_env, &name.node, &ast[*value]);
+                    let (s2, body_ty) = self.infer(&body_env, ast, *body)?;
+                    Ok((s2.compose(&s1), body_ty))
+                } else if *recursive {
                     let fresh = self.gen.fresh_type();
                     let mut rec_env = env.clone();
                     rec_env.insert(name.node.clone(), TypeScheme::mono(fresh.clone()));
+                    bind_params(&mut rec_env, &name.node, &ast[*value]);
 
-                    let (s1, bind_ty) = self.infer(&rec_env, value)?;
-                    let s2 = unify(&s1.apply(&fresh), &bind_ty, expr.span)?;
+                    let from = self.pending.len();
+                    let (s1, bind_ty) = self.infer(&rec_env, ast, *value)?;
+                    let s2 = self.unify(&s1.apply(&fresh), &bind_ty, expr.span)?;
                     let combined = s2.compose(&s1);
 
                     let generalized_ty = combined.apply(&bind_ty);
-                    let scheme =
-                        Self::generalize(&env.apply_subst(&combined), &generalized_ty);
+                    self.record_binder(name.span, &generalized_ty);
+                    let (combined, scheme) = self.generalize(
+                        &name.node,
+                        env,
+                        combined,
+                        &generalized_ty,
+                        name.span,
+                        from,
+                    )?;
 
                     let mut body_env = env.apply_subst(&combined);
                     body_env.insert(name.node.clone(), scheme);
-                    let (s3, body_ty) = self.infer(&body_env, body)?;
+                    bind_params(&mut body_env, &name.node, &ast[*value]);
+                    let (s3, body_ty) = self.infer(&body_env, ast, *body)?;
                     Ok((s3.compose(&combined), body_ty))
                 } else {
-                    let (s1, bind_ty) = self.infer(env, value)?;
-                    let scheme = Self::generalize(&env.apply_subst(&s1), &bind_ty);
+                    let from = self.pending.len();
+                    let (s1, bind_ty) = self.infer(env, ast, *value)?;
+                    self.record_binder(name.span, &bind_ty);
+                    let (s1, scheme) =
+                        self.generalize(&name.node, env, s1, &bind_ty, name.span, from)?;
 
                     let mut body_env = env.apply_subst(&s1);
                     body_env.insert(name.node.clone(), scheme);
-                    let (s2, body_ty) = self.infer(&body_env, body)?;
+                    bind_params(&mut body_env, &name.node, &ast[*value]);
+                    let (s2, body_ty) = self.infer(&body_env, ast, *body)?;
                     Ok((s2.compose(&s1), body_ty))
                 }
             }
 
             // ── Match expression ──
             Expr::Match { scrutinee, arms } => {
-                let (s1, scrut_ty) = self.infer(env, scrutinee)?;
+                let (s1, scrut_ty) = self.infer(env, ast, *scrutinee)?;
                 let result_ty = self.gen.fresh_type();
                 let mut subst = s1;
 
                 for arm in arms {
                     let (s_pat, bindings) = self.infer_pattern(
-                        &env.apply_subst(&subst),
                         &arm.pattern,
                         &subst.apply(&scrut_ty),
                     )?;
@@ -388,31 +906,18 @@ impl Inferencer {
                         arm_env.insert(name, TypeScheme::mono(ty));
                     }
 
-                    let (s_body, body_ty) = self.infer(&arm_env, &arm.body)?;
+                    let (s_body, body_ty) = self.infer(&arm_env, ast, arm.body)?;
                     subst = s_body.compose(&subst);
 
-                    let s_unify = unify(
-                        &subst.apply(&result_ty),
-                        &subst.apply(&body_ty),
-                        arm.body.span,
-                    )?;
+                    let s_unify = self
+                        .unify(&subst.apply(&result_ty), &subst.apply(&body_ty), ast[arm.body].span)
+                        .map_err(|e| {
+                            e.with_origin(ast[arms[0].body].span, "expected because of the first arm")
+                        })?;
                     subst = s_unify.compose(&subst);
                 }
 
-                // Check exhaustiveness (emit warning, not error)
-                let final_scrut_ty = subst.apply(&scrut_ty);
-                let pattern_refs: Vec<_> = arms.iter().map(|a| &a.pattern).collect();
-                let missing = super::exhaustiveness::check_exhaustiveness(
-                    &pattern_refs,
-                    &final_scrut_ty,
-                    &self.constructors,
-                );
-                if !missing.is_empty() {
-                    eprintln!(
-                        "\x1b[1;33mwarning\x1b[0m: non-exhaustive patterns: missing {}",
-                        missing.join(", ")
-                    );
-                }
+                self.check_arms(expr.span, arms);
 
                 Ok((subst.clone(), subst.apply(&result_ty)))
             }
@@ -421,8 +926,8 @@ impl Inferencer {
             Expr::Interpolation(parts) => {
                 let mut subst = Subst::new();
                 for part in parts {
-                    if let InterpolationPart::Expr(e) = part {
-                        let (s, _ty) = self.infer(&env.apply_subst(&subst), e)?;
+                    if let InterpolationPart::Expr(e, _) = part {
+                        let (s, _ty) = self.infer(&env.apply_subst(&subst), ast, *e)?;
                         subst = s.compose(&subst);
                     }
                 }
@@ -434,7 +939,7 @@ impl Inferencer {
                 let mut subst = Subst::new();
                 let mut field_types = std::collections::BTreeMap::new();
                 for (name, val_expr) in fields {
-                    let (s, ty) = self.infer(&env.apply_subst(&subst), val_expr)?;
+                    let (s, ty) = self.infer(&env.apply_subst(&subst), ast, *val_expr)?;
                     subst = s.compose(&subst);
                     field_types.insert(name.clone(), subst.apply(&ty));
                 }
@@ -443,16 +948,51 @@ impl Inferencer {
 
             // ── Field access ──
             Expr::FieldAccess { expr: obj, field } => {
-                let (s1, obj_ty) = self.infer(env, obj)?;
+                let (s1, obj_ty) = self.infer(env, ast, *obj)?;
                 let result_ty = self.gen.fresh_type();
                 // Expect the object to be a record containing this field
                 let mut expected_fields = std::collections::BTreeMap::new();
                 expected_fields.insert(field.clone(), result_ty.clone());
-                let expected = MonoType::Record(expected_fields);
-                let s2 = unify(&s1.apply(&obj_ty), &expected, expr.span)?;
+                let expected = MonoType::OpenRecord(expected_fields);
+                let s2 = self.unify(&s1.apply(&obj_ty), &expected, expr.span)?;
                 let s = s2.compose(&s1);
                 Ok((s.clone(), s.apply(&result_ty)))
             }
+
+            // ── Slice ──
+            // A list or a String, sliced by Int bounds into the same type. A
+            // value of no known type yet is taken to be a list.
+            Expr::Slice { expr: obj, start, end } => {
+                let (mut s, obj_ty) = self.infer(env, ast, *obj)?;
+                for &bound in [start, end].into_iter().flatten() {
+                    let (s1, ty) = self.infer(&env.apply_subst(&s), ast, bound)?;
+                    s = s1.compose(&s);
+                    let s2 = self.unify(&s.apply(&ty), &MonoType::Int, ast[bound].span)?;
+                    s = s2.compose(&s);
+                }
+                let obj_ty = s.apply(&obj_ty);
+                let item = self.gen.fresh_type();
+                let s3 = self
+                    .unify(&obj_ty, &MonoType::List(Box::new(item)), ast[*obj].span)
+                    .or_else(|e| self.unify(&obj_ty, &MonoType::String, ast[*obj].span).map_err(|_| e))?;
+                s = s3.compose(&s);
+                Ok((s.clone(), s.apply(&obj_ty)))
+            }
+
+            Expr::Import(path) => Err(unresolved_import(path, expr.span)),
+
+            // ── Quoted code ──
+            // Only the spliced expressions run, so only they are checked.
+            Expr::Quote(inner) => {
+                let mut subst = Subst::new();
+                for spliced in crate::eval::quote::unquotes(ast, *inner) {
+                    let env2 = env.apply_subst(&subst);
+                    let (s, _) = self.infer(&env2, ast, spliced)?;
+                    subst = s.compose(&subst);
+                }
+                Ok((subst, MonoType::Con("Quoted".into(), vec![])))
+            }
+            Expr::Unquote(_) => Err(LyraError::MisplacedUnquote { span: expr.span }),
         }
     }
 
@@ -460,47 +1000,47 @@ impl Inferencer {
         &mut self,
         env: &TypeEnv,
         op: &BinOp,
-        lhs: &SpannedExpr,
-        rhs: &SpannedExpr,
+        ast: &Ast,
+        lhs: ExprId,
+        rhs: ExprId,
         span: Span,
     ) -> Result<(Subst, MonoType), LyraError> {
-        let (s1, lhs_ty) = self.infer(env, lhs)?;
+        let (s1, lhs_ty) = self.infer(env, ast, lhs)?;
         let env2 = env.apply_subst(&s1);
-        let (s2, rhs_ty) = self.infer(&env2, rhs)?;
+        let (s2, rhs_ty) = self.infer(&env2, ast, rhs)?;
         let mut s = s2.compose(&s1);
 
         match op {
-            // Arithmetic: Int -> Int -> Int (or Float)
-            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
-                let s3 = unify(&s.apply(&lhs_ty), &s.apply(&rhs_ty), span)?;
-                s = s3.compose(&s);
-                let unified_ty = s.apply(&lhs_ty);
-                // Must be Int or Float
-                let s4 = unify(&unified_ty, &MonoType::Int, span)
-                    .or_else(|_| unify(&unified_ty, &MonoType::Float, span))?;
-                s = s4.compose(&s);
-                Ok((s.clone(), s.apply(&lhs_ty)))
-            }
-
-            // Comparison: a -> a -> Bool (for ordered types)
-            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge => {
-                let s3 = unify(&s.apply(&lhs_ty), &s.apply(&rhs_ty), span)?;
+            // Arithmetic: Int or Float, Float if either is, or String for `+`
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod | BinOp::Pow => {
+                let result = self.gen.fresh_type();
+                let constraint = Constraint::Arith {
+                    lhs: s.apply(&lhs_ty),
+                    rhs: s.apply(&rhs_ty),
+                    result: result.clone(),
+                    text: *op == BinOp::Add,
+                };
+                let s3 = self.constrain(constraint, span)?;
                 s = s3.compose(&s);
-                Ok((s, MonoType::Bool))
+                Ok((s.clone(), s.apply(&result)))
             }
 
-            // Equality: a -> a -> Bool
-            BinOp::Eq | BinOp::NotEq => {
-                let s3 = unify(&s.apply(&lhs_ty), &s.apply(&rhs_ty), span)?;
+            // Comparison and equality: a -> a -> Bool, or an Int and a Float
+            BinOp::Lt | BinOp::Gt | BinOp::Le | BinOp::Ge | BinOp::Eq | BinOp::NotEq => {
+                let constraint = Constraint::Compare {
+                    lhs: s.apply(&lhs_ty),
+                    rhs: s.apply(&rhs_ty),
+                };
+                let s3 = self.constrain(constraint, span)?;
                 s = s3.compose(&s);
                 Ok((s, MonoType::Bool))
             }
 
             // Logical: Bool -> Bool -> Bool
             BinOp::And | BinOp::Or => {
-                let s3 = unify(&s.apply(&lhs_ty), &MonoType::Bool, span)?;
+                let s3 = self.unify(&s.apply(&lhs_ty), &MonoType::Bool, span)?;
                 s = s3.compose(&s);
-                let s4 = unify(&s.apply(&rhs_ty), &MonoType::Bool, span)?;
+                let s4 = self.unify(&s.apply(&rhs_ty), &MonoType::Bool, span)?;
                 s = s4.compose(&s);
                 Ok((s, MonoType::Bool))
             }
@@ -508,7 +1048,7 @@ impl Inferencer {
             // Cons: a -> [a] -> [a]
             BinOp::Cons => {
                 let list_ty = MonoType::List(Box::new(s.apply(&lhs_ty)));
-                let s3 = unify(&s.apply(&rhs_ty), &list_ty, span)?;
+                let s3 = self.unify(&s.apply(&rhs_ty), &list_ty, span)?;
                 s = s3.compose(&s);
                 Ok((s.clone(), s.apply(&rhs_ty)))
             }
@@ -518,7 +1058,6 @@ impl Inferencer {
     /// Infer types from a pattern, returning bindings introduced.
     fn infer_pattern(
         &mut self,
-        env: &TypeEnv,
         pattern: &SpannedPattern,
         expected: &MonoType,
     ) -> Result<(Subst, Vec<(String, MonoType)>), LyraError> {
@@ -526,31 +1065,38 @@ impl Inferencer {
             Pattern::Wildcard => Ok((Subst::new(), vec![])),
 
             Pattern::Var(name) => {
+                self.record_binder(pattern.span, expected);
                 Ok((Subst::new(), vec![(name.clone(), expected.clone())]))
             }
 
             Pattern::IntLit(_) => {
-                let s = unify(expected, &MonoType::Int, pattern.span)?;
+                let s = self.unify(expected, &MonoType::Int, pattern.span)?;
                 Ok((s, vec![]))
             }
 
             Pattern::FloatLit(_) => {
-                let s = unify(expected, &MonoType::Float, pattern.span)?;
+                let s = self.unify(expected, &MonoType::Float, pattern.span)?;
                 Ok((s, vec![]))
             }
 
             Pattern::StringLit(_) => {
-                let s = unify(expected, &MonoType::String, pattern.span)?;
+                let s = self.unify(expected, &MonoType::String, pattern.span)?;
                 Ok((s, vec![]))
             }
 
+            Pattern::StringPrefix(_, rest) | Pattern::StringSuffix(rest, _) => {
+                let s1 = self.unify(expected, &MonoType::String, pattern.span)?;
+                let (s2, bindings) = self.infer_pattern(rest, &MonoType::String)?;
+                Ok((s2.compose(&s1), bindings))
+            }
+
             Pattern::BoolLit(_) => {
-                let s = unify(expected, &MonoType::Bool, pattern.span)?;
+                let s = self.unify(expected, &MonoType::Bool, pattern.span)?;
                 Ok((s, vec![]))
             }
 
             Pattern::UnitLit => {
-                let s = unify(expected, &MonoType::Unit, pattern.span)?;
+                let s = self.unify(expected, &MonoType::Unit, pattern.span)?;
                 Ok((s, vec![]))
             }
 
@@ -558,13 +1104,13 @@ impl Inferencer {
                 let elem_types: Vec<MonoType> =
                     pats.iter().map(|_| self.gen.fresh_type()).collect();
                 let tuple_ty = MonoType::Tuple(elem_types.clone());
-                let s1 = unify(expected, &tuple_ty, pattern.span)?;
+                let s1 = self.unify(expected, &tuple_ty, pattern.span)?;
 
                 let mut subst = s1;
                 let mut bindings = Vec::new();
                 for (pat, ty) in pats.iter().zip(&elem_types) {
                     let (s, b) =
-                        self.infer_pattern(env, pat, &subst.apply(ty))?;
+                        self.infer_pattern(pat, &subst.apply(ty))?;
                     subst = s.compose(&subst);
                     bindings.extend(b);
                 }
@@ -574,14 +1120,12 @@ impl Inferencer {
             Pattern::List(pats) => {
                 let elem_ty = self.gen.fresh_type();
                 let list_ty = MonoType::List(Box::new(elem_ty.clone()));
-                let s1 = unify(expected, &list_ty, pattern.span)?;
+                let s1 = self.unify(expected, &list_ty, pattern.span)?;
 
                 let mut subst = s1;
                 let mut bindings = Vec::new();
                 for pat in pats {
-                    let (s, b) = self.infer_pattern(
-                        env,
-                        pat,
+                    let (s, b) = self.infer_pattern(pat,
                         &subst.apply(&elem_ty),
                     )?;
                     subst = s.compose(&subst);
@@ -593,19 +1137,15 @@ impl Inferencer {
             Pattern::Cons(head, tail) => {
                 let elem_ty = self.gen.fresh_type();
                 let list_ty = MonoType::List(Box::new(elem_ty.clone()));
-                let s1 = unify(expected, &list_ty, pattern.span)?;
+                let s1 = self.unify(expected, &list_ty, pattern.span)?;
                 let mut subst = s1;
 
-                let (s2, head_bindings) = self.infer_pattern(
-                    env,
-                    head,
+                let (s2, head_bindings) = self.infer_pattern(head,
                     &subst.apply(&elem_ty),
                 )?;
                 subst = s2.compose(&subst);
 
-                let (s3, tail_bindings) = self.infer_pattern(
-                    env,
-                    tail,
+                let (s3, tail_bindings) = self.infer_pattern(tail,
                     &subst.apply(&list_ty),
                 )?;
                 subst = s3.compose(&subst);
@@ -632,12 +1172,21 @@ impl Inferencer {
                     });
                 }
 
-                // Create fresh type variables for type params
+                // Create fresh type variables for type params, standing for
+                // them in the fields too
                 let fresh_params: Vec<(String, MonoType)> = info
                     .type_params
                     .iter()
                     .map(|p| (p.clone(), self.gen.fresh_type()))
                     .collect();
+                let instantiate = Subst {
+                    map: info
+                        .param_vars
+                        .iter()
+                        .zip(&fresh_params)
+                        .map(|(v, (_, t))| (*v, t.clone()))
+                        .collect(),
+                };
 
                 let result_ty = if fresh_params.is_empty() {
                     MonoType::Con(info.type_name.clone(), vec![])
@@ -648,14 +1197,14 @@ impl Inferencer {
                     )
                 };
 
-                let s1 = unify(expected, &result_ty, pattern.span)?;
+                let s1 = self.unify(expected, &result_ty, pattern.span)?;
                 let mut subst = s1;
 
                 let mut bindings = Vec::new();
                 for (arg_pat, field_ty) in args.iter().zip(&info.field_types) {
-                    let concrete_field = subst.apply(field_ty);
+                    let concrete_field = subst.apply(&instantiate.apply(field_ty));
                     let (s, b) =
-                        self.infer_pattern(env, arg_pat, &concrete_field)?;
+                        self.infer_pattern(arg_pat, &concrete_field)?;
                     subst = s.compose(&subst);
                     bindings.extend(b);
                 }
@@ -669,33 +1218,52 @@ impl Inferencer {
     pub fn infer_decl(
         &mut self,
         env: &mut TypeEnv,
+        ast: &Ast,
         decl: &Decl,
     ) -> Result<Option<MonoType>, LyraError> {
+        let first_binder = self.recorded();
+        self.pending.clear();
         match decl {
             Decl::Let {
                 name,
                 recursive,
+                type_ann,
                 body,
                 ..
             } => {
-                if *recursive {
+                if let Some(ann) = type_ann {
+                    let (s, scheme) = self.check_annotated(env, name, *recursive, ann, ast, *body)?;
+                    let s = self.default_pending(s)?;
+                    self.resolve_binders(first_binder, &s);
+                    let ty = scheme.ty.clone();
+                    env.insert(name.node.clone(), scheme);
+                    bind_params(env, &name.node, &ast[*body]);
+                    Ok(Some(ty))
+                } else if *recursive {
                     let fresh = self.gen.fresh_type();
                     let mut rec_env = env.clone();
                     rec_env.insert(name.node.clone(), TypeScheme::mono(fresh.clone()));
+                    bind_params(&mut rec_env, &name.node, &ast[*body]);
 
-                    let (s1, bind_ty) = self.infer(&rec_env, body)?;
-                    let s2 = unify(&s1.apply(&fresh), &bind_ty, body.span)?;
+                    let (s1, bind_ty) = self.infer(&rec_env, ast, *body)?;
+                    let s2 = self.unify(&s1.apply(&fresh), &bind_ty, ast[*body].span)?;
                     let combined = s2.compose(&s1);
 
-                    let final_ty = combined.apply(&bind_ty);
-                    let scheme = Self::generalize(&env.apply_subst(&combined), &final_ty);
-                    env.insert(name.node.clone(), scheme);
-                    Ok(Some(final_ty))
+                    let (combined, scheme) =
+                        self.generalize(&name.node, env, combined, &bind_ty, name.span, 0)?;
+                    let combined = self.default_pending(combined)?;
+                    self.resolve_binders(first_binder, &combined);
+                    env.insert(name.node.clone(), combined.apply_scheme(&scheme));
+                    bind_params(env, &name.node, &ast[*body]);
+                    Ok(Some(combined.apply(&bind_ty)))
                 } else {
-                    let (s, ty) = self.infer(env, body)?;
-                    let scheme = Self::generalize(&env.apply_subst(&s), &ty);
-                    env.insert(name.node.clone(), scheme);
-                    Ok(Some(ty))
+                    let (s, ty) = self.infer(env, ast, *body)?;
+                    let (s, scheme) = self.generalize(&name.node, env, s, &ty, name.span, 0)?;
+                    let s = self.default_pending(s)?;
+                    self.resolve_binders(first_binder, &s);
+                    env.insert(name.node.clone(), s.apply_scheme(&scheme));
+                    bind_params(env, &name.node, &ast[*body]);
+                    Ok(Some(s.apply(&ty)))
                 }
             }
 
@@ -705,14 +1273,109 @@ impl Inferencer {
             }
 
             Decl::Expr(expr) => {
-                let (_, ty) = self.infer(env, expr)?;
-                Ok(Some(ty))
+                let (s, ty) = self.infer(env, ast, *expr)?;
+                let s = self.default_pending(s)?;
+                self.resolve_binders(first_binder, &s);
+                Ok(Some(s.apply(&ty)))
+            }
+
+            // Parameters take their annotated types; the body must be a Bool
+            Decl::Prop { params, body, .. } => {
+                let mut prop_env = env.clone();
+                for p in params {
+                    let ty = match &p.type_ann {
+                        Some(ann) => self.type_ann_to_mono(ann, &[]),
+                        None => self.gen.fresh_type(),
+                    };
+                    self.record_binder(p.name.span, &ty);
+                    prop_env.insert(p.name.node.clone(), TypeScheme::mono(ty));
+                }
+                let (s, ty) = self.infer(&prop_env, ast, *body)?;
+                let s2 = self.unify(&s.apply(&ty), &MonoType::Bool, ast[*body].span)?;
+                let s = self.default_pending(s2.compose(&s))?;
+                self.resolve_binders(first_binder, &s);
+                Ok(None)
             }
 
             Decl::Import { .. } => {
                 // Import type checking will be implemented in Phase 4
                 Ok(None)
             }
+
+            // Only the expansions of a macro's calls are checked
+            Decl::Macro { .. } => Ok(None),
+
+            // The host's value is taken to have the declared type
+            Decl::Extern { name, type_ann, .. } => {
+                let (scheme, _) = self.signature(type_ann);
+                self.record_binder(name.span, &scheme.ty);
+                env.insert(name.node.clone(), scheme);
+                Ok(None)
+            }
+        }
+    }
+
+    /// After `decl` failed to check, bind the name it declares so later
+    /// declarations can still be checked: to its annotated type if it has
+    /// one, else to a fresh type that fits any use, so uses of it don't
+    /// report errors of their own.
+    pub fn recover_decl(&mut self, env: &mut TypeEnv, ast: &Ast, decl: &Decl) {
+        if let Decl::Let { name, type_ann, body, .. } = decl {
+            let scheme = match type_ann {
+                Some(ann) => self.signature(ann).0,
+                None => {
+                    let a = self.gen.fresh();
+                    TypeScheme { vars: vec![a], ty: MonoType::Var(a), constraints: vec![] }
+                }
+            };
+            self.record_binder(name.span, &scheme.ty);
+            env.insert(name.node.clone(), scheme);
+            bind_params(env, &name.node, &ast[*body]);
+        }
+    }
+}
+
+/// An Int on one side and a Float on the other: the Int is promoted at runtime,
+/// so arithmetic on them gives a Float.
+fn is_mixed_numeric(lhs: &MonoType, rhs: &MonoType) -> bool {
+    matches!(
+        (lhs, rhs),
+        (MonoType::Int, MonoType::Float) | (MonoType::Float, MonoType::Int)
+    )
+}
+
+/// Let labeled calls to `name` match against the parameters of the
+/// function it's bound to.
+fn bind_params(env: &mut TypeEnv, name: &str, value: &SpannedExpr) {
+    if let Some(params) = value.node.param_names() {
+        env.set_params(name, params);
+    }
+}
+
+/// Type variable names in an annotation, in order of first appearance.
+fn type_ann_vars(ann: &SpannedTypeAnn, names: &mut Vec<String>) {
+    match &ann.node {
+        TypeAnnotation::Var(name) => {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        TypeAnnotation::Named(_) | TypeAnnotation::Unit => {}
+        TypeAnnotation::Arrow(from, to) => {
+            type_ann_vars(from, names);
+            type_ann_vars(to, names);
+        }
+        TypeAnnotation::App(base, args) => {
+            type_ann_vars(base, names);
+            for a in args {
+                type_ann_vars(a, names);
+            }
+        }
+        TypeAnnotation::Tuple(elems) => {
+            for e in elems {
+                type_ann_vars(e, names);
+            }
         }
+        TypeAnnotation::List(inner) => type_ann_vars(inner, names),
     }
 }
@@ -2,6 +2,7 @@ pub mod env;
 pub mod exhaustiveness;
 pub mod infer;
 pub mod subst;
+pub mod trace;
 pub mod unify;
 
 use std::collections::{BTreeMap, HashSet};
@@ -24,6 +25,9 @@ pub enum MonoType {
     Tuple(Vec<MonoType>),
     Con(String, Vec<MonoType>),
     Record(BTreeMap<String, MonoType>),
+    /// A record known to have these fields and maybe more, as field access
+    /// expects of its object.
+    OpenRecord(BTreeMap<String, MonoType>),
 }
 
 impl MonoType {
@@ -54,7 +58,7 @@ impl MonoType {
                 }
                 s
             }
-            MonoType::Record(fields) => {
+            MonoType::Record(fields) | MonoType::OpenRecord(fields) => {
                 let mut s = HashSet::new();
                 for ty in fields.values() {
                     s.extend(ty.free_vars());
@@ -72,6 +76,17 @@ impl MonoType {
             .rev()
             .fold(ret, |acc, p| MonoType::Arrow(Box::new(p), Box::new(acc)))
     }
+
+    /// The parameter types of a curried function type, outermost first.
+    pub fn params(&self) -> Vec<&MonoType> {
+        let mut params = Vec::new();
+        let mut ty = self;
+        while let MonoType::Arrow(param, ret) = ty {
+            params.push(param.as_ref());
+            ty = ret;
+        }
+        params
+    }
 }
 
 impl fmt::Display for MonoType {
@@ -109,7 +124,7 @@ impl fmt::Display for MonoType {
                 }
                 Ok(())
             }
-            MonoType::Record(fields) => {
+            MonoType::Record(fields) | MonoType::OpenRecord(fields) => {
                 write!(f, "{{ ")?;
                 for (i, (name, ty)) in fields.iter().enumerate() {
                     if i > 0 {
@@ -117,17 +132,83 @@ impl fmt::Display for MonoType {
                     }
                     write!(f, "{}: {}", name, ty)?;
                 }
+                if matches!(self, MonoType::OpenRecord(_)) {
+                    write!(f, ", ..")?;
+                }
                 write!(f, " }}")
             }
         }
     }
 }
 
-/// Polymorphic type scheme: forall a b . T
+/// What a numeric operator asks of its operand types, kept until they're
+/// known well enough to check. `text` says `+`, which also joins Strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    /// `ty` is Int or Float, or a String for `+`.
+    Numeric { ty: MonoType, text: bool },
+    /// `result` is what arithmetic on `lhs` and `rhs` gives: Float if either
+    /// is a Float, else the type they share.
+    Arith { lhs: MonoType, rhs: MonoType, result: MonoType, text: bool },
+    /// `lhs` and `rhs` can be compared: they're one type, or Int and Float.
+    Compare { lhs: MonoType, rhs: MonoType },
+}
+
+impl Constraint {
+    pub fn types(&self) -> Vec<&MonoType> {
+        match self {
+            Constraint::Numeric { ty, .. } => vec![ty],
+            Constraint::Arith { lhs, rhs, result, .. } => vec![lhs, rhs, result],
+            Constraint::Compare { lhs, rhs } => vec![lhs, rhs],
+        }
+    }
+
+    pub fn free_vars(&self) -> HashSet<TypeVar> {
+        self.types().into_iter().flat_map(MonoType::free_vars).collect()
+    }
+
+    /// The constraint with `f` applied to each of its types.
+    pub fn map(&self, mut f: impl FnMut(&MonoType) -> MonoType) -> Constraint {
+        match self {
+            Constraint::Numeric { ty, text } => Constraint::Numeric { ty: f(ty), text: *text },
+            Constraint::Arith { lhs, rhs, result, text } => Constraint::Arith {
+                lhs: f(lhs),
+                rhs: f(rhs),
+                result: f(result),
+                text: *text,
+            },
+            Constraint::Compare { lhs, rhs } => Constraint::Compare { lhs: f(lhs), rhs: f(rhs) },
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (class, types) = match self {
+            Constraint::Numeric { text, .. } | Constraint::Arith { text, .. } => {
+                (if *text { "Add" } else { "Num" }, self.types())
+            }
+            Constraint::Compare { .. } => ("Cmp", self.types()),
+        };
+        write!(f, "{}", class)?;
+        for ty in types {
+            match ty {
+                MonoType::Arrow(..) => write!(f, " ({})", ty)?,
+                MonoType::Con(_, args) if !args.is_empty() => write!(f, " ({})", ty)?,
+                _ => write!(f, " {}", ty)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Polymorphic type scheme: forall a b . C => T
 #[derive(Debug, Clone)]
 pub struct TypeScheme {
     pub vars: Vec<TypeVar>,
     pub ty: MonoType,
+    /// What the operators in its definition ask of the quantified variables.
+    pub constraints: Vec<Constraint>,
 }
 
 impl TypeScheme {
@@ -135,6 +216,7 @@ impl TypeScheme {
         TypeScheme {
             vars: vec![],
             ty,
+            constraints: vec![],
         }
     }
 
@@ -145,6 +227,48 @@ impl TypeScheme {
         }
         s
     }
+
+    /// Renumber the quantified variables from 0 in order of appearance, so
+    /// the printed scheme doesn't depend on how many variables were generated.
+    pub fn normalized(&self) -> TypeScheme {
+        let mut order = Vec::new();
+        collect_vars_in_order(&self.ty, &mut order);
+        order.retain(|v| self.vars.contains(v));
+        let mut subst = subst::Subst::new();
+        for (i, v) in order.iter().enumerate() {
+            subst.map.insert(*v, MonoType::Var(i as TypeVar));
+        }
+        let mut constraints: Vec<Constraint> =
+            self.constraints.iter().map(|c| c.map(|t| subst.apply_once(t))).collect();
+        constraints.sort_by_key(|c| c.to_string());
+        TypeScheme {
+            vars: (0..order.len() as TypeVar).collect(),
+            ty: subst.apply_once(&self.ty),
+            constraints,
+        }
+    }
+}
+
+fn collect_vars_in_order(ty: &MonoType, out: &mut Vec<TypeVar>) {
+    match ty {
+        MonoType::Var(v) if !out.contains(v) => out.push(*v),
+        MonoType::Arrow(a, b) => {
+            collect_vars_in_order(a, out);
+            collect_vars_in_order(b, out);
+        }
+        MonoType::List(inner) => collect_vars_in_order(inner, out),
+        MonoType::Tuple(elems) | MonoType::Con(_, elems) => {
+            for e in elems {
+                collect_vars_in_order(e, out);
+            }
+        }
+        MonoType::Record(fields) | MonoType::OpenRecord(fields) => {
+            for ty in fields.values() {
+                collect_vars_in_order(ty, out);
+            }
+        }
+        _ => {}
+    }
 }
 
 impl fmt::Display for TypeScheme {
@@ -156,7 +280,14 @@ impl fmt::Display for TypeScheme {
             for v in &self.vars {
                 write!(f, " t{}", v)?;
             }
-            write!(f, ". {}", self.ty)
+            write!(f, ".")?;
+            for (i, c) in self.constraints.iter().enumerate() {
+                write!(f, "{}{}", if i == 0 { " " } else { ", " }, c)?;
+            }
+            if !self.constraints.is_empty() {
+                write!(f, " =>")?;
+            }
+            write!(f, " {}", self.ty)
         }
     }
 }
@@ -166,6 +297,12 @@ pub struct TypeVarGen {
     next: TypeVar,
 }
 
+impl Default for TypeVarGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TypeVarGen {
     pub fn new() -> Self {
         TypeVarGen { next: 0 }
@@ -46,6 +46,38 @@ impl Subst {
                     fields.iter().map(|(k, v)| (k.clone(), self.apply(v))).collect(),
                 )
             }
+            MonoType::OpenRecord(fields) => {
+                MonoType::OpenRecord(
+                    fields.iter().map(|(k, v)| (k.clone(), self.apply(v))).collect(),
+                )
+            }
+            _ => ty.clone(),
+        }
+    }
+
+    /// Apply this substitution in a single pass, without re-applying it to the
+    /// replacements. Used for instantiation, where the fresh variables may share
+    /// numbers with the quantified ones.
+    pub fn apply_once(&self, ty: &MonoType) -> MonoType {
+        match ty {
+            MonoType::Var(v) => self.map.get(v).cloned().unwrap_or_else(|| ty.clone()),
+            MonoType::Arrow(a, b) => {
+                MonoType::Arrow(Box::new(self.apply_once(a)), Box::new(self.apply_once(b)))
+            }
+            MonoType::List(inner) => MonoType::List(Box::new(self.apply_once(inner))),
+            MonoType::Tuple(elems) => {
+                MonoType::Tuple(elems.iter().map(|e| self.apply_once(e)).collect())
+            }
+            MonoType::Con(name, args) => MonoType::Con(
+                name.clone(),
+                args.iter().map(|a| self.apply_once(a)).collect(),
+            ),
+            MonoType::Record(fields) => MonoType::Record(
+                fields.iter().map(|(k, v)| (k.clone(), self.apply_once(v))).collect(),
+            ),
+            MonoType::OpenRecord(fields) => MonoType::OpenRecord(
+                fields.iter().map(|(k, v)| (k.clone(), self.apply_once(v))).collect(),
+            ),
             _ => ty.clone(),
         }
     }
@@ -60,6 +92,7 @@ impl Subst {
         TypeScheme {
             vars: scheme.vars.clone(),
             ty: filtered.apply(&scheme.ty),
+            constraints: scheme.constraints.iter().map(|c| c.map(|t| filtered.apply(t))).collect(),
         }
     }
 
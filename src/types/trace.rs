@@ -0,0 +1,94 @@
+//! Inference trace behind `--debug-types`: every unification, instantiation
+//! and generalization the inferencer performs, in order, with the span that
+//! caused it.
+
+use std::fmt;
+
+use crate::span::{LineIndex, Span};
+
+use super::subst::Subst;
+use super::{MonoType, TypeScheme};
+
+/// One step of type inference.
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    /// `left ~ right`, with the resulting substitution or why it failed.
+    Unify {
+        left: MonoType,
+        right: MonoType,
+        result: Result<Subst, String>,
+        span: Span,
+    },
+    /// A variable's scheme was instantiated with fresh type variables.
+    Instantiate {
+        scheme: TypeScheme,
+        ty: MonoType,
+        span: Span,
+    },
+    /// A `let` binding's type was generalized into a scheme.
+    Generalize {
+        name: String,
+        ty: MonoType,
+        scheme: TypeScheme,
+        span: Span,
+    },
+}
+
+impl TraceEvent {
+    pub fn span(&self) -> Span {
+        match self {
+            TraceEvent::Unify { span, .. }
+            | TraceEvent::Instantiate { span, .. }
+            | TraceEvent::Generalize { span, .. } => *span,
+        }
+    }
+
+    /// Render the event prefixed with its `line:col`.
+    pub fn render(&self, lines: &LineIndex) -> String {
+        let (line, col) = lines.line_col(self.span().start);
+        format!("{}:{}: {}", line, col, self)
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Unify {
+                left,
+                right,
+                result,
+                ..
+            } => {
+                write!(f, "unify {} ~ {}", left, right)?;
+                match result {
+                    Ok(subst) => write!(f, " => {}", DisplaySubst(subst)),
+                    Err(msg) => write!(f, " => {}", msg),
+                }
+            }
+            TraceEvent::Instantiate { scheme, ty, .. } => {
+                write!(f, "instantiate {} => {}", scheme, ty)
+            }
+            TraceEvent::Generalize {
+                name, ty, scheme, ..
+            } => write!(f, "generalize {} : {} => {}", name, ty, scheme),
+        }
+    }
+}
+
+/// Substitutions print as `{t1 := Int, t2 := [t1]}`, ordered by variable.
+struct DisplaySubst<'a>(&'a Subst);
+
+impl fmt::Display for DisplaySubst<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<_> = self.0.map.iter().collect();
+        entries.sort_by_key(|(v, _)| **v);
+        write!(f, "{{")?;
+        for (i, (v, ty)) in entries.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "t{} := {}", v, ty)?;
+        }
+        write!(f, "}}")
+    }
+}
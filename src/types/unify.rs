@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use crate::error::LyraError;
 use crate::span::Span;
 
@@ -44,20 +46,27 @@ pub fn unify(t1: &MonoType, t2: &MonoType, span: Span) -> Result<Subst, LyraErro
         }
 
         // Record types — structural: unify common fields, allow extra fields on either side
-        (MonoType::Record(f1), MonoType::Record(f2)) => {
-            let mut subst = Subst::new();
-            for (name, ty1) in f1 {
-                if let Some(ty2) = f2.get(name) {
-                    let s = unify(&subst.apply(ty1), &subst.apply(ty2), span)?;
-                    subst = s.compose(&subst);
-                }
+        (MonoType::Record(f1), MonoType::Record(f2))
+        | (MonoType::OpenRecord(f1), MonoType::OpenRecord(f2)) => unify_fields(f1, f2, span),
+
+        // A record with known fields must have every field asked of it
+        (MonoType::Record(closed), MonoType::OpenRecord(open))
+        | (MonoType::OpenRecord(open), MonoType::Record(closed)) => {
+            if open.keys().any(|name| !closed.contains_key(name)) {
+                return Err(LyraError::TypeMismatch {
+                    expected: t1.to_string(),
+                    found: t2.to_string(),
+                    origin: None,
+                    span,
+                });
             }
-            Ok(subst)
+            unify_fields(closed, open, span)
         }
 
         _ => Err(LyraError::TypeMismatch {
             expected: t1.to_string(),
             found: t2.to_string(),
+            origin: None,
             span,
         }),
     }
@@ -86,11 +95,29 @@ fn occurs(var: TypeVar, ty: &MonoType) -> bool {
         MonoType::List(inner) => occurs(var, inner),
         MonoType::Tuple(elems) => elems.iter().any(|e| occurs(var, e)),
         MonoType::Con(_, args) => args.iter().any(|a| occurs(var, a)),
-        MonoType::Record(fields) => fields.values().any(|t| occurs(var, t)),
+        MonoType::Record(fields) | MonoType::OpenRecord(fields) => {
+            fields.values().any(|t| occurs(var, t))
+        }
         _ => false,
     }
 }
 
+/// Unify the fields `a` and `b` have in common.
+fn unify_fields(
+    a: &BTreeMap<String, MonoType>,
+    b: &BTreeMap<String, MonoType>,
+    span: Span,
+) -> Result<Subst, LyraError> {
+    let mut subst = Subst::new();
+    for (name, ty1) in a {
+        if let Some(ty2) = b.get(name) {
+            let s = unify(&subst.apply(ty1), &subst.apply(ty2), span)?;
+            subst = s.compose(&subst);
+        }
+    }
+    Ok(subst)
+}
+
 fn unify_many(a: &[MonoType], b: &[MonoType], span: Span) -> Result<Subst, LyraError> {
     let mut subst = Subst::new();
     for (t1, t2) in a.iter().zip(b.iter()) {
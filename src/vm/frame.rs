@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::compiler::bytecode::FunctionProto;
 use crate::eval::value::Value;
 
@@ -5,22 +7,29 @@ use crate::eval::value::Value;
 #[derive(Debug, Clone)]
 pub struct CallFrame {
     /// The function being executed.
-    pub function: FunctionProto,
+    pub function: Rc<FunctionProto>,
     /// Instruction pointer (index into function's chunk.code).
     pub ip: usize,
     /// Base index into the VM's value stack for this frame's locals.
     pub stack_base: usize,
     /// Captured upvalues for closures.
-    pub upvalues: Vec<Value>,
+    pub upvalues: Rc<[Value]>,
+    /// Extra arguments from an over-applied call, applied to the result on return.
+    pub pending_args: Vec<Value>,
+    /// The calls the frame stands for, each returning its result: one, and
+    /// one more per tail call that reused it. None for the program's own.
+    pub calls: usize,
 }
 
 impl CallFrame {
-    pub fn new(function: FunctionProto, stack_base: usize, upvalues: Vec<Value>) -> Self {
+    pub fn new(function: Rc<FunctionProto>, stack_base: usize, upvalues: Rc<[Value]>) -> Self {
         CallFrame {
             function,
             ip: 0,
             stack_base,
             upvalues,
+            pending_args: Vec::new(),
+            calls: 1,
         }
     }
 }
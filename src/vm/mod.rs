@@ -1,9 +1,13 @@
 pub mod frame;
 
+use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::rc::Rc;
 
-use crate::compiler::bytecode::{FunctionProto, Op};
-use crate::eval::value::Value;
+use crate::compiler::bytecode::{Comparison, FunctionProto, Op};
+use crate::ast::{BinOp, UnaryOp};
+use crate::eval::value::{binop_error, int_pow, promote_numeric, unop_error, Value};
 use crate::eval::{apply_function};
 use crate::span::Span;
 use crate::error::LyraError;
@@ -17,32 +21,122 @@ const MAX_STACK: usize = 65536;
 pub struct VM {
     stack: Vec<Value>,
     frames: Vec<CallFrame>,
-    globals: HashMap<String, Value>,
+    /// Shared with the VMs that run this one's functions for builtins
+    /// calling back into them.
+    globals: Rc<RefCell<HashMap<String, Value>>>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VM {
     pub fn new() -> Self {
+        Self::with_globals(Rc::default())
+    }
+
+    /// A VM that reads and defines globals in `globals`, as a callback's
+    /// VM shares those of the VM that called the builtin.
+    pub fn with_globals(globals: Rc<RefCell<HashMap<String, Value>>>) -> Self {
         VM {
             stack: Vec::with_capacity(256),
             frames: Vec::with_capacity(64),
-            globals: HashMap::new(),
+            globals,
         }
     }
 
     pub fn define_global(&mut self, name: String, value: Value) {
-        self.globals.insert(name, value);
+        self.globals.borrow_mut().insert(name, value);
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name).cloned()
     }
 
     fn push(&mut self, value: Value) {
         self.stack.push(value);
     }
 
-    fn pop(&mut self) -> Value {
-        self.stack.pop().expect("stack underflow")
+    fn pop(&mut self) -> Result<Value, LyraError> {
+        self.stack.pop().ok_or_else(|| self.stack_underflow())
+    }
+
+    fn peek(&self) -> Result<&Value, LyraError> {
+        self.stack.last().ok_or_else(|| self.stack_underflow())
+    }
+
+    /// Pop the two operands of a binary instruction, left one first.
+    fn pop_pair(&mut self) -> Result<(Value, Value), LyraError> {
+        let b = self.pop()?;
+        let a = self.pop()?;
+        Ok((a, b))
+    }
+
+    /// Remove the top `n` values, oldest first.
+    fn pop_n(&mut self, n: usize) -> Result<Vec<Value>, LyraError> {
+        let start = self.stack_index_below(n)?;
+        Ok(self.stack.drain(start..).collect())
     }
 
-    fn peek(&self) -> &Value {
-        self.stack.last().expect("stack underflow")
+    /// Index of the slot `n` below the top of the stack.
+    fn stack_index_below(&self, n: usize) -> Result<usize, LyraError> {
+        self.stack
+            .len()
+            .checked_sub(n)
+            .ok_or_else(|| self.stack_underflow())
+    }
+
+    fn constant(&self, idx: usize) -> Result<Value, LyraError> {
+        let frame = self.frame();
+        frame
+            .function
+            .chunk
+            .constants
+            .get(idx)
+            .cloned()
+            .ok_or_else(|| self.bad_operand("constant", idx))
+    }
+
+    fn local(&self, slot: usize) -> Result<Value, LyraError> {
+        self.stack
+            .get(self.frame().stack_base + slot)
+            .cloned()
+            .ok_or_else(|| self.bad_operand("local slot", slot))
+    }
+
+    fn upvalue(&self, idx: usize) -> Result<Value, LyraError> {
+        self.frame()
+            .upvalues
+            .get(idx)
+            .cloned()
+            .ok_or_else(|| self.bad_operand("upvalue", idx))
+    }
+
+    /// An instruction referring to a constant, local or upvalue that doesn't exist.
+    fn bad_operand(&self, what: &str, idx: usize) -> LyraError {
+        LyraError::RuntimeError {
+            message: format!("invalid bytecode: no {} {}", what, idx),
+            span: self.current_span(),
+        }
+    }
+
+    /// A pattern instruction taking apart a value that doesn't have the part.
+    fn missing_part(&self, what: String) -> LyraError {
+        let found = self.peek().map_or("nothing", Value::type_name);
+        LyraError::RuntimeError {
+            message: format!("invalid bytecode: no {} in {}", what, found),
+            span: self.current_span(),
+        }
+    }
+
+    /// Reported instead of panicking when bytecode pops more than it pushed.
+    fn stack_underflow(&self) -> LyraError {
+        LyraError::RuntimeError {
+            message: "stack underflow".to_string(),
+            span: self.current_span(),
+        }
     }
 
     fn frame(&self) -> &CallFrame {
@@ -53,47 +147,201 @@ impl VM {
         self.frames.last_mut().expect("no call frame")
     }
 
-    fn read_op(&mut self) -> Op {
-        let frame = self.frames.last_mut().unwrap();
-        let op = frame.function.chunk.code[frame.ip].clone();
+    /// Advance past the current frame's next instruction and return it,
+    /// borrowed from `function`, the frame's prototype.
+    fn read_op<'a>(&mut self, function: &'a FunctionProto) -> &'a Op {
+        let frame = self.frame_mut();
+        let op = &function.chunk.code[frame.ip];
         frame.ip += 1;
         op
     }
 
     fn current_span(&self) -> Span {
-        let frame = self.frame();
-        if frame.ip > 0 && frame.ip - 1 < frame.function.chunk.spans.len() {
-            frame.function.chunk.spans[frame.ip - 1]
-        } else {
-            Span::default()
-        }
+        let Some(frame) = self.frames.last() else {
+            return Span::default();
+        };
+        frame
+            .ip
+            .checked_sub(1)
+            .and_then(|idx| frame.function.chunk.span(idx))
+            .unwrap_or_default()
     }
 
     /// Execute a single compiled function with arguments (used by apply_function for VM interop).
-    pub fn call_function(&mut self, proto: FunctionProto, args: Vec<Value>) -> Result<Value, LyraError> {
+    pub fn call_function(&mut self, proto: Rc<FunctionProto>, args: Vec<Value>) -> Result<Value, LyraError> {
         let stack_base = self.stack.len();
         for arg in args {
             self.push(arg);
         }
-        let frame = CallFrame::new(proto, stack_base, vec![]);
+        crate::hooks::call(&proto.name, &self.stack[stack_base..]);
+        let frame = CallFrame::new(proto, stack_base, Rc::new([]));
         self.frames.push(frame);
         self.execute()
     }
 
     /// Execute a closure with captured upvalues and arguments (used by apply_function for VM interop).
-    pub fn call_closure(&mut self, proto: FunctionProto, upvalues: Vec<Value>, args: Vec<Value>) -> Result<Value, LyraError> {
+    pub fn call_closure(&mut self, proto: Rc<FunctionProto>, upvalues: Rc<[Value]>, args: Vec<Value>) -> Result<Value, LyraError> {
         let stack_base = self.stack.len();
         for arg in args {
             self.push(arg);
         }
+        crate::hooks::call(&proto.name, &self.stack[stack_base..]);
         let frame = CallFrame::new(proto, stack_base, upvalues);
         self.frames.push(frame);
         self.execute()
     }
 
+    /// Call the value sitting below the top `argc` stack slots. Compiled
+    /// functions get a new frame; everything else goes through `apply_function`.
+    fn call_value(&mut self, argc: usize) -> Result<(), LyraError> {
+        let func_idx = self.stack_index_below(argc + 1)?;
+        let func = self.stack[func_idx].clone();
+
+        match func {
+            // Under-applied compiled function: capture the args, like the tree-walker
+            Value::Function(ref proto) | Value::ClosureVal { ref proto, .. }
+                if argc < proto.arity as usize =>
+            {
+                let args: Vec<Value> = self.stack.drain(func_idx + 1..).collect();
+                self.stack.pop(); // pop the function
+                self.push(Value::PartialApp {
+                    func: Box::new(func),
+                    applied_args: args.into(),
+                });
+            }
+            Value::Function(proto) => {
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(LyraError::RuntimeError {
+                        message: "stack overflow".to_string(),
+                        span: self.current_span(),
+                    });
+                }
+                let pending = self.split_extra_args(func_idx, argc, proto.arity as usize);
+                crate::hooks::call(&proto.name, &self.stack[func_idx + 1..]);
+                let mut frame = CallFrame::new(proto, func_idx + 1, Rc::new([]));
+                frame.pending_args = pending;
+                self.frames.push(frame);
+            }
+            Value::ClosureVal { proto, upvalues } => {
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err(LyraError::RuntimeError {
+                        message: "stack overflow".to_string(),
+                        span: self.current_span(),
+                    });
+                }
+                let pending = self.split_extra_args(func_idx, argc, proto.arity as usize);
+                crate::hooks::call(&proto.name, &self.stack[func_idx + 1..]);
+                let mut frame = CallFrame::new(proto, func_idx + 1, upvalues);
+                frame.pending_args = pending;
+                self.frames.push(frame);
+            }
+            // Partially applied compiled function: splice the captured args
+            // back onto the stack and call the underlying function directly
+            Value::PartialApp { func: inner, applied_args }
+                if matches!(*inner, Value::Function(_) | Value::ClosureVal { .. }) =>
+            {
+                let extra = applied_args.len();
+                self.stack[func_idx] = *inner;
+                self.stack.splice(func_idx + 1..func_idx + 1, applied_args.iter().cloned());
+                return self.call_value(argc + extra);
+            }
+            // Fall back to tree-walking for builtins and partial app
+            Value::Builtin(_)
+            | Value::Closure(_)
+            | Value::PartialApp { .. }
+            | Value::Memo { .. } => {
+                let args: Vec<Value> = self.stack.drain(func_idx + 1..).collect();
+                self.stack.pop(); // pop the function
+                // Share globals so callbacks can access them via mini-VM
+                crate::eval::set_vm_globals(Rc::clone(&self.globals));
+                let result = apply_function(func, args, self.current_span())?;
+                self.push(result);
+            }
+            other => return Err(crate::eval::not_callable(&other, None, self.current_span())),
+        }
+        Ok(())
+    }
+
+    /// Call the function under the top `argc` values in place of the
+    /// current frame when it takes exactly that many arguments, and as
+    /// `call_value` does otherwise.
+    fn tail_call(&mut self, argc: usize) -> Result<(), LyraError> {
+        let func_idx = self.stack_index_below(argc + 1)?;
+        match self.stack[func_idx].clone() {
+            Value::Function(proto) | Value::ClosureVal { proto, .. }
+                if argc == proto.arity as usize =>
+            {
+                let upvalues = match &self.stack[func_idx] {
+                    Value::ClosureVal { upvalues, .. } => Rc::clone(upvalues),
+                    _ => Rc::new([]),
+                };
+                // Move args to the current frame's base
+                let base = self.frame().stack_base;
+                let args: Vec<Value> = self.stack.drain(func_idx + 1..).collect();
+                self.stack.truncate(base);
+                crate::hooks::call(&proto.name, &args);
+                for arg in args {
+                    self.push(arg);
+                }
+                // Reuse frame
+                let frame = self.frame_mut();
+                frame.function = proto;
+                frame.upvalues = upvalues;
+                frame.ip = 0;
+                frame.calls += 1;
+                Ok(())
+            }
+            // A partially applied compiled function takes its captured
+            // args back and is tail called itself
+            Value::PartialApp { func: inner, applied_args }
+                if matches!(*inner, Value::Function(_) | Value::ClosureVal { .. }) =>
+            {
+                let extra = applied_args.len();
+                self.stack[func_idx] = *inner;
+                self.stack.splice(func_idx + 1..func_idx + 1, applied_args.iter().cloned());
+                self.tail_call(argc + extra)
+            }
+            // Under- or over-applied, or not compiled: can't reuse the frame
+            _ => self.call_value(argc),
+        }
+    }
+
+    /// Remove arguments beyond `arity` from the stack so they can be applied
+    /// to the call's result once the callee returns.
+    fn split_extra_args(&mut self, func_idx: usize, argc: usize, arity: usize) -> Vec<Value> {
+        if argc > arity {
+            self.stack.split_off(func_idx + 1 + arity)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Push the value returned by `frame`, re-applying any pending
+    /// over-applied arguments.
+    fn push_result(&mut self, result: Value, frame: CallFrame) -> Result<(), LyraError> {
+        let argc = frame.pending_args.len();
+        if argc > 0 && !result.is_callable() {
+            let arity = frame.function.arity as usize;
+            return Err(LyraError::CallFailed {
+                name: frame.function.name.clone(),
+                arity,
+                found: arity + argc,
+                message: format!("result {} is not a function", result.describe()),
+                span: self.current_span(),
+            });
+        }
+        self.push(result);
+        if argc > 0 {
+            self.stack.extend(frame.pending_args);
+            self.call_value(argc)?;
+        }
+        Ok(())
+    }
+
     /// Execute a compiled function prototype.
     pub fn run(&mut self, main: FunctionProto) -> Result<Value, LyraError> {
-        let main_frame = CallFrame::new(main, 0, vec![]);
+        let mut main_frame = CallFrame::new(Rc::new(main), 0, Rc::new([]));
+        main_frame.calls = 0;
         self.frames.push(main_frame);
         self.execute()
     }
@@ -110,95 +358,105 @@ impl VM {
             };
             if frame_done {
                 // End of function
-                let result = self.pop();
+                let result = self.pop()?;
                 let base = frame_base;
-                self.frames.pop();
+                let frame = self.frames.pop().unwrap();
                 self.stack.truncate(base);
+                for _ in 0..frame.calls {
+                    crate::hooks::ret(&result);
+                }
                 if self.frames.is_empty() {
                     return Ok(result);
                 }
-                self.push(result);
+                self.push_result(result, frame)?;
                 continue;
             }
 
-            let op = self.read_op();
+            if !crate::budget::tick() {
+                return Err(crate::budget::exceeded(self.current_span()));
+            }
+            // Holding the prototype lets the op be borrowed rather than cloned
+            // while the frame it came from is changed or popped
+            let function = Rc::clone(&self.frame().function);
+            let op = self.read_op(&function);
+            if crate::coverage::recording() {
+                crate::coverage::record(self.current_span());
+            }
+            if crate::hooks::active() {
+                crate::hooks::op(self.current_span())?;
+            }
 
-            match op {
+            match *op {
                 Op::Constant(idx) => {
-                    let val = self.frame().function.chunk.constants[idx].clone();
+                    let val = self.constant(idx)?;
                     self.push(val);
                 }
                 Op::Unit => self.push(Value::Unit),
                 Op::True => self.push(Value::Bool(true)),
                 Op::False => self.push(Value::Bool(false)),
                 Op::Pop => {
-                    self.pop();
+                    self.pop()?;
                 }
                 Op::Dup => {
-                    let val = self.peek().clone();
+                    let val = self.peek()?.clone();
                     self.push(val);
                 }
 
                 // ── Variables ──
                 Op::GetLocal(slot) => {
-                    let base = self.frame().stack_base;
-                    let val = self.stack[base + slot].clone();
+                    let val = self.local(slot)?;
                     self.push(val);
                 }
                 Op::SetLocal(slot) => {
-                    let base = self.frame().stack_base;
-                    let val = self.peek().clone();
-                    self.stack[base + slot] = val;
+                    let val = self.peek()?.clone();
+                    let idx = self.frame().stack_base + slot;
+                    match self.stack.get_mut(idx) {
+                        Some(local) => *local = val,
+                        None => return Err(self.bad_operand("local slot", slot)),
+                    }
                 }
                 Op::GetUpvalue(idx) => {
-                    let val = self.frame().upvalues[idx].clone();
+                    let val = self.upvalue(idx)?;
                     self.push(val);
                 }
-                Op::GetGlobal(name) => {
-                    let val = self.globals.get(&name).cloned().ok_or_else(|| {
-                        let candidates: Vec<&str> =
-                            self.globals.keys().map(|s| s.as_str()).collect();
+                Op::GetSelf => {
+                    let frame = self.frame();
+                    let proto = frame.function.clone();
+                    let val = if frame.upvalues.is_empty() {
+                        Value::Function(proto)
+                    } else {
+                        Value::ClosureVal { proto, upvalues: Rc::clone(&frame.upvalues) }
+                    };
+                    self.push(val);
+                }
+                Op::GetGlobal(ref name) => {
+                    let val = self.global(name).ok_or_else(|| {
+                        let globals = self.globals.borrow();
+                        let candidates: Vec<&str> = globals.keys().map(|s| s.as_str()).collect();
                         LyraError::UndefinedVariable {
-                            suggestion: crate::error::suggest_similar(&name, &candidates),
+                            suggestion: crate::error::suggest_similar(name, &candidates),
                             name: name.clone(),
                             span: self.current_span(),
                         }
                     })?;
                     self.push(val);
                 }
-                Op::DefineGlobal(name) => {
-                    let val = self.pop();
-                    self.globals.insert(name, val);
+                Op::DefineGlobal(ref name) => {
+                    let val = self.pop()?;
+                    self.define_global(name.clone(), val);
                 }
 
                 // ── Arithmetic ──
                 Op::Add => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let result = match (&a, &b) {
-                        (Value::Int(x), Value::Int(y)) => Value::Int(x + y),
-                        (Value::Float(x), Value::Float(y)) => Value::Float(x + y),
-                        (Value::String(x), Value::String(y)) => {
-                            Value::String(format!("{}{}", x, y))
-                        }
-                        _ => {
-                            return Err(LyraError::RuntimeError {
-                                message: format!(
-                                    "cannot add {} and {}",
-                                    a.type_name(),
-                                    b.type_name()
-                                ),
-                                span: self.current_span(),
-                            })
-                        }
-                    };
+                    let (a, b) = self.pop_pair()?;
+                    let result = self.add(a, b)?;
                     self.push(result);
                 }
-                Op::Sub => self.binary_arith(|a, b| a - b, |a, b| a - b)?,
-                Op::Mul => self.binary_arith(|a, b| a * b, |a, b| a * b)?,
+                Op::Sub => self.binary_arith(BinOp::Sub, |a, b| a - b, |a, b| a - b)?,
+                Op::Mul => self.binary_arith(BinOp::Mul, |a, b| a * b, |a, b| a * b)?,
                 Op::Div => {
-                    let b = self.pop();
-                    let a = self.pop();
+                    let (a, b) = self.pop_pair()?;
+                    let (a, b) = promote_numeric(a, b);
                     match (&a, &b) {
                         (Value::Int(_), Value::Int(0)) => {
                             return Err(LyraError::DivisionByZero {
@@ -209,15 +467,15 @@ impl VM {
                         (Value::Float(x), Value::Float(y)) => self.push(Value::Float(x / y)),
                         _ => {
                             return Err(LyraError::RuntimeError {
-                                message: "invalid division operands".to_string(),
+                                message: binop_error(BinOp::Div, &a, &b),
                                 span: self.current_span(),
                             })
                         }
                     }
                 }
                 Op::Mod => {
-                    let b = self.pop();
-                    let a = self.pop();
+                    let (a, b) = self.pop_pair()?;
+                    let (a, b) = promote_numeric(a, b);
                     match (&a, &b) {
                         (Value::Int(_), Value::Int(0)) => {
                             return Err(LyraError::DivisionByZero {
@@ -228,20 +486,42 @@ impl VM {
                         (Value::Float(x), Value::Float(y)) => self.push(Value::Float(x % y)),
                         _ => {
                             return Err(LyraError::RuntimeError {
-                                message: "invalid mod operands".to_string(),
+                                message: binop_error(BinOp::Mod, &a, &b),
+                                span: self.current_span(),
+                            })
+                        }
+                    }
+                }
+                Op::Pow => {
+                    let (a, b) = self.pop_pair()?;
+                    let (a, b) = promote_numeric(a, b);
+                    match (&a, &b) {
+                        (Value::Int(x), Value::Int(y)) => match int_pow(*x, *y) {
+                            Ok(n) => self.push(Value::Int(n)),
+                            Err(message) => {
+                                return Err(LyraError::RuntimeError {
+                                    message,
+                                    span: self.current_span(),
+                                })
+                            }
+                        },
+                        (Value::Float(x), Value::Float(y)) => self.push(Value::Float(x.powf(*y))),
+                        _ => {
+                            return Err(LyraError::RuntimeError {
+                                message: binop_error(BinOp::Pow, &a, &b),
                                 span: self.current_span(),
                             })
                         }
                     }
                 }
                 Op::Negate => {
-                    let val = self.pop();
+                    let val = self.pop()?;
                     match val {
                         Value::Int(n) => self.push(Value::Int(-n)),
                         Value::Float(n) => self.push(Value::Float(-n)),
-                        _ => {
+                        other => {
                             return Err(LyraError::RuntimeError {
-                                message: "cannot negate non-number".to_string(),
+                                message: unop_error(UnaryOp::Neg, &other),
                                 span: self.current_span(),
                             })
                         }
@@ -249,29 +529,45 @@ impl VM {
                 }
 
                 // ── Comparison ──
-                Op::Equal => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(Value::Bool(a == b));
+                Op::Equal => self.binary_eq(true)?,
+                Op::NotEqual => self.binary_eq(false)?,
+                Op::Less => self.binary_cmp(Ordering::is_lt)?,
+                Op::Greater => self.binary_cmp(Ordering::is_gt)?,
+                Op::LessEqual => self.binary_cmp(Ordering::is_le)?,
+                Op::GreaterEqual => self.binary_cmp(Ordering::is_ge)?,
+
+                // ── Superinstructions ──
+                Op::GetLocal2Add(a, b) => {
+                    let result = self.add(self.local(a)?, self.local(b)?)?;
+                    self.push(result);
                 }
-                Op::NotEqual => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(Value::Bool(a != b));
+                Op::ConstantAdd(idx) => {
+                    let a = self.pop()?;
+                    let result = self.add(a, self.constant(idx)?)?;
+                    self.push(result);
+                }
+                Op::CompareJump(cmp, offset) => {
+                    match cmp {
+                        Comparison::Equal => self.binary_eq(true)?,
+                        Comparison::NotEqual => self.binary_eq(false)?,
+                        Comparison::Less => self.binary_cmp(Ordering::is_lt)?,
+                        Comparison::Greater => self.binary_cmp(Ordering::is_gt)?,
+                        Comparison::LessEqual => self.binary_cmp(Ordering::is_le)?,
+                        Comparison::GreaterEqual => self.binary_cmp(Ordering::is_ge)?,
+                    }
+                    if let Value::Bool(false) = self.peek()? {
+                        self.frame_mut().ip += offset;
+                    }
                 }
-                Op::Less => self.binary_cmp(|a, b| a < b, |a, b| a < b)?,
-                Op::Greater => self.binary_cmp(|a, b| a > b, |a, b| a > b)?,
-                Op::LessEqual => self.binary_cmp(|a, b| a <= b, |a, b| a <= b)?,
-                Op::GreaterEqual => self.binary_cmp(|a, b| a >= b, |a, b| a >= b)?,
 
                 // ── Logic ──
                 Op::Not => {
-                    let val = self.pop();
+                    let val = self.pop()?;
                     match val {
                         Value::Bool(b) => self.push(Value::Bool(!b)),
-                        _ => {
+                        other => {
                             return Err(LyraError::RuntimeError {
-                                message: "cannot negate non-boolean".to_string(),
+                                message: unop_error(UnaryOp::Not, &other),
                                 span: self.current_span(),
                             })
                         }
@@ -283,7 +579,7 @@ impl VM {
                     self.frame_mut().ip += offset;
                 }
                 Op::JumpIfFalse(offset) => {
-                    if let Value::Bool(false) = self.peek() {
+                    if let Value::Bool(false) = self.peek()? {
                         self.frame_mut().ip += offset;
                     }
                 }
@@ -293,147 +589,87 @@ impl VM {
 
                 // ── Functions ──
                 Op::Call(arg_count) => {
-                    let argc = arg_count as usize;
-                    let func_idx = self.stack.len() - argc - 1;
-                    let func = self.stack[func_idx].clone();
-
-                    match func {
-                        Value::Function(proto) => {
-                            if self.frames.len() >= MAX_FRAMES {
-                                return Err(LyraError::RuntimeError {
-                                    message: "stack overflow".to_string(),
-                                    span: self.current_span(),
-                                });
-                            }
-                            let frame = CallFrame::new(proto, func_idx + 1, vec![]);
-                            self.frames.push(frame);
-                        }
-                        Value::ClosureVal { proto, upvalues } => {
-                            if self.frames.len() >= MAX_FRAMES {
-                                return Err(LyraError::RuntimeError {
-                                    message: "stack overflow".to_string(),
-                                    span: self.current_span(),
-                                });
-                            }
-                            let frame = CallFrame::new(proto, func_idx + 1, upvalues);
-                            self.frames.push(frame);
-                        }
-                        // Fall back to tree-walking for builtins and partial app
-                        Value::Builtin { .. } | Value::Closure { .. } | Value::PartialApp { .. } => {
-                            let args: Vec<Value> =
-                                self.stack.drain(func_idx + 1..).collect();
-                            self.stack.pop(); // pop the function
-                            // Save globals so callbacks can access them via mini-VM
-                            crate::eval::set_vm_globals(self.globals.clone());
-                            let result =
-                                apply_function(func, args, self.current_span())?;
-                            self.push(result);
-                        }
-                        _ => {
-                            return Err(LyraError::NotCallable {
-                                span: self.current_span(),
-                            })
-                        }
+                    self.call_value(arg_count as usize)?;
+                }
+                Op::CallBare(ref name) => match crate::eval::bare_call(self.peek()?, name) {
+                    Some(builtin) => {
+                        self.pop()?;
+                        let result =
+                            crate::eval::call_builtin(builtin, Vec::new(), self.current_span())?;
+                        self.push(result);
                     }
-                }
+                    None => self.call_value(0)?,
+                },
 
-                Op::TailCall(arg_count) => {
-                    let argc = arg_count as usize;
-                    let func_idx = self.stack.len() - argc - 1;
-                    let func = self.stack[func_idx].clone();
-
-                    match func {
-                        Value::Function(proto) | Value::ClosureVal { proto, .. } => {
-                            // Move args to the current frame's base
-                            let base = self.frame().stack_base;
-                            let args: Vec<Value> =
-                                self.stack.drain(func_idx + 1..).collect();
-                            self.stack.truncate(base);
-                            for arg in args {
-                                self.push(arg);
-                            }
-                            // Reuse frame
-                            let frame = self.frame_mut();
-                            frame.function = proto;
-                            frame.ip = 0;
-                        }
-                        _ => {
-                            // Fall back to regular call
-                            let args: Vec<Value> =
-                                self.stack.drain(func_idx + 1..).collect();
-                            self.stack.pop();
-                            crate::eval::set_vm_globals(self.globals.clone());
-                            let result =
-                                apply_function(func, args, self.current_span())?;
-                            self.push(result);
-                        }
-                    }
-                }
+                Op::TailCall(arg_count) => self.tail_call(arg_count as usize)?,
 
                 Op::Return => {
-                    let result = self.pop();
-                    let base = self.frame().stack_base;
-                    self.frames.pop();
-                    self.stack.truncate(base.saturating_sub(1).max(0)); // pop function + locals
+                    let result = self.pop()?;
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.stack_base.saturating_sub(1)); // pop function + locals
+                    for _ in 0..frame.calls {
+                        crate::hooks::ret(&result);
+                    }
                     if self.frames.is_empty() {
                         return Ok(result);
                     }
-                    self.push(result);
+                    self.push_result(result, frame)?;
                 }
 
-                Op::Closure(const_idx, upvalue_refs) => {
-                    let proto = match self.frame().function.chunk.constants[const_idx].clone() {
+                Op::Closure(const_idx, ref upvalue_refs) => {
+                    let proto = match self.constant(const_idx)? {
                         Value::Function(p) => p,
-                        _ => panic!("closure constant is not a function"),
+                        other => {
+                            return Err(LyraError::RuntimeError {
+                                message: format!(
+                                    "closure constant is {}, not a function",
+                                    other.type_name()
+                                ),
+                                span: self.current_span(),
+                            })
+                        }
                     };
 
                     let mut upvalues = Vec::new();
-                    for uv_ref in &upvalue_refs {
+                    for uv_ref in upvalue_refs {
                         if uv_ref.is_local {
-                            let base = self.frame().stack_base;
-                            upvalues.push(self.stack[base + uv_ref.index].clone());
+                            upvalues.push(self.local(uv_ref.index)?);
                         } else {
-                            upvalues.push(self.frame().upvalues[uv_ref.index].clone());
+                            upvalues.push(self.upvalue(uv_ref.index)?);
                         }
                     }
 
                     if upvalues.is_empty() {
                         self.push(Value::Function(proto));
                     } else {
+                        let upvalues = upvalues.into();
                         self.push(Value::ClosureVal { proto, upvalues });
                     }
                 }
 
                 // ── Data structures ──
                 Op::MakeList(n) => {
-                    let start = self.stack.len() - n;
-                    let items: Vec<Value> = self.stack.drain(start..).collect();
+                    let items = self.pop_n(n)?;
                     self.push(Value::List(items));
                 }
                 Op::MakeTuple(n) => {
-                    let start = self.stack.len() - n;
-                    let items: Vec<Value> = self.stack.drain(start..).collect();
+                    let items = self.pop_n(n)?;
                     self.push(Value::Tuple(items));
                 }
-                Op::MakeAdt(tag, n) => {
-                    let start = self.stack.len() - n;
-                    let fields: Vec<Value> = self.stack.drain(start..).collect();
-                    self.push(Value::Adt {
-                        constructor: tag,
-                        fields,
-                    });
+                Op::MakeAdt(ref tag, n) => {
+                    let fields = self.pop_n(n)?;
+                    self.push(Value::adt(tag.clone(), fields));
                 }
                 Op::Cons => {
-                    let tail = self.pop();
-                    let head = self.pop();
+                    let (head, tail) = self.pop_pair()?;
                     match tail {
                         Value::List(mut list) => {
                             list.insert(0, head);
                             self.push(Value::List(list));
                         }
-                        _ => {
+                        tail => {
                             return Err(LyraError::RuntimeError {
-                                message: ":: requires a list on the right".to_string(),
+                                message: binop_error(BinOp::Cons, &head, &tail),
                                 span: self.current_span(),
                             })
                         }
@@ -441,9 +677,9 @@ impl VM {
                 }
 
                 // ── Pattern matching helpers ──
-                Op::TestTag(tag, offset) => {
-                    if let Value::Adt { constructor, .. } = self.peek() {
-                        if constructor != &tag {
+                Op::TestTag(ref tag, offset) => {
+                    if let Value::Adt(adt) = self.peek()? {
+                        if adt.constructor != *tag {
                             self.frame_mut().ip += offset;
                         }
                     } else {
@@ -451,7 +687,7 @@ impl VM {
                     }
                 }
                 Op::TestInt(n, offset) => {
-                    if let Value::Int(v) = self.peek() {
+                    if let Value::Int(v) = self.peek()? {
                         if *v != n {
                             self.frame_mut().ip += offset;
                         }
@@ -459,8 +695,17 @@ impl VM {
                         self.frame_mut().ip += offset;
                     }
                 }
+                Op::TestFloat(f, offset) => {
+                    if let Value::Float(v) = self.peek()? {
+                        if *v != f {
+                            self.frame_mut().ip += offset;
+                        }
+                    } else {
+                        self.frame_mut().ip += offset;
+                    }
+                }
                 Op::TestBool(b, offset) => {
-                    if let Value::Bool(v) = self.peek() {
+                    if let Value::Bool(v) = self.peek()? {
                         if *v != b {
                             self.frame_mut().ip += offset;
                         }
@@ -468,22 +713,32 @@ impl VM {
                         self.frame_mut().ip += offset;
                     }
                 }
-                Op::TestString(s, offset) => {
-                    if let Value::String(v) = self.peek() {
-                        if v != &s {
+                Op::TestString(ref s, offset) => {
+                    if let Value::String(v) = self.peek()? {
+                        if v != s {
                             self.frame_mut().ip += offset;
                         }
                     } else {
                         self.frame_mut().ip += offset;
                     }
                 }
+                Op::TestStringPrefix(ref s, offset) => {
+                    if !matches!(self.peek()?, Value::String(v) if v.starts_with(s.as_str())) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                Op::TestStringSuffix(ref s, offset) => {
+                    if !matches!(self.peek()?, Value::String(v) if v.ends_with(s.as_str())) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
                 Op::TestUnit(offset) => {
-                    if !matches!(self.peek(), Value::Unit) {
+                    if !matches!(self.peek()?, Value::Unit) {
                         self.frame_mut().ip += offset;
                     }
                 }
                 Op::TestEmptyList(offset) => {
-                    if let Value::List(l) = self.peek() {
+                    if let Value::List(l) = self.peek()? {
                         if !l.is_empty() {
                             self.frame_mut().ip += offset;
                         }
@@ -492,7 +747,7 @@ impl VM {
                     }
                 }
                 Op::TestCons(offset) => {
-                    if let Value::List(l) = self.peek() {
+                    if let Value::List(l) = self.peek()? {
                         if l.is_empty() {
                             self.frame_mut().ip += offset;
                         }
@@ -501,7 +756,7 @@ impl VM {
                     }
                 }
                 Op::TestTuple(n, offset) => {
-                    if let Value::Tuple(t) = self.peek() {
+                    if let Value::Tuple(t) = self.peek()? {
                         if t.len() != n {
                             self.frame_mut().ip += offset;
                         }
@@ -509,59 +764,108 @@ impl VM {
                         self.frame_mut().ip += offset;
                     }
                 }
-                Op::GetAdtField(idx) => {
-                    let val = self.peek().clone();
-                    if let Value::Adt { fields, .. } = val {
-                        self.push(fields[idx].clone());
+                Op::TestListLen(n, offset) => {
+                    if let Value::List(l) = self.peek()? {
+                        if l.len() != n {
+                            self.frame_mut().ip += offset;
+                        }
+                    } else {
+                        self.frame_mut().ip += offset;
                     }
                 }
+                Op::GetAdtField(idx) => {
+                    let field = match self.peek()? {
+                        Value::Adt(adt) => adt.fields.get(idx).cloned(),
+                        _ => None,
+                    };
+                    let field = field.ok_or_else(|| self.missing_part(format!("field {}", idx)))?;
+                    self.push(field);
+                }
                 Op::GetListHead => {
-                    let val = self.peek().clone();
-                    if let Value::List(l) = val {
-                        self.push(l[0].clone());
-                    }
+                    let head = match self.peek()? {
+                        Value::List(l) => l.first().cloned(),
+                        _ => None,
+                    };
+                    let head = head.ok_or_else(|| self.missing_part("head".to_string()))?;
+                    self.push(head);
                 }
                 Op::GetListTail => {
-                    let val = self.peek().clone();
-                    if let Value::List(l) = val {
-                        self.push(Value::List(l[1..].to_vec()));
-                    }
+                    let tail = match self.peek()? {
+                        Value::List(l) => l.get(1..).map(|t| Value::List(t.to_vec())),
+                        _ => None,
+                    };
+                    let tail = tail.ok_or_else(|| self.missing_part("tail".to_string()))?;
+                    self.push(tail);
                 }
                 Op::GetTupleField(idx) => {
-                    let val = self.peek().clone();
-                    if let Value::Tuple(t) = val {
-                        self.push(t[idx].clone());
-                    }
+                    let field = match self.peek()? {
+                        Value::Tuple(t) => t.get(idx).cloned(),
+                        _ => None,
+                    };
+                    let field = field.ok_or_else(|| self.missing_part(format!("field {}", idx)))?;
+                    self.push(field);
+                }
+                Op::GetListElement(idx) => {
+                    let element = match self.peek()? {
+                        Value::List(l) => l.get(idx).cloned(),
+                        _ => None,
+                    };
+                    let element =
+                        element.ok_or_else(|| self.missing_part(format!("element {}", idx)))?;
+                    self.push(element);
+                }
+                Op::GetStringSlice(start, end) => {
+                    let slice = match self.peek()? {
+                        Value::String(s) => s
+                            .len()
+                            .checked_sub(end)
+                            .and_then(|stop| s.get(start..stop))
+                            .map(|slice| Value::String(slice.to_string())),
+                        _ => None,
+                    };
+                    let slice = slice
+                        .ok_or_else(|| self.missing_part(format!("slice {}..-{}", start, end)))?;
+                    self.push(slice);
+                }
+                Op::CheckExtern(ref name, arity) => {
+                    let span = self.current_span();
+                    crate::eval::check_extern(name, arity, self.global(name).as_ref(), span)?;
                 }
                 Op::PopMatch => {
-                    self.pop();
+                    self.pop()?;
                 }
                 Op::Swap => {
-                    let len = self.stack.len();
-                    self.stack.swap(len - 1, len - 2);
+                    let below = self.stack_index_below(2)?;
+                    self.stack.swap(below, below + 1);
                 }
                 Op::PopUnder(n) => {
-                    let top = self.pop();
+                    let top = self.pop()?;
                     for _ in 0..n {
-                        self.pop();
+                        self.pop()?;
                     }
                     self.push(top);
                 }
 
                 // ── Records ──
-                Op::MakeRecord(names) => {
-                    let start = self.stack.len() - names.len();
-                    let values: Vec<Value> = self.stack.drain(start..).collect();
+                Op::MakeRecord(ref names) => {
+                    let values = self.pop_n(names.len())?;
                     let mut map = std::collections::BTreeMap::new();
-                    for (name, val) in names.into_iter().zip(values) {
+                    for (name, val) in names.iter().cloned().zip(values) {
                         map.insert(name, val);
                     }
                     self.push(Value::Record(map));
                 }
-                Op::GetField(name) => {
-                    let val = self.pop();
+                Op::Quote(ref ast, expr, n) => {
+                    let mut spliced = self.pop_n(n)?.into_iter();
+                    let quoted = crate::eval::quote::quote(ast, expr, &mut |_| {
+                        Ok(spliced.next().expect("one value per unquote"))
+                    })?;
+                    self.push(quoted);
+                }
+                Op::GetField(ref name) => {
+                    let val = self.pop()?;
                     if let Value::Record(map) = val {
-                        if let Some(field_val) = map.get(&name) {
+                        if let Some(field_val) = map.get(name) {
                             self.push(field_val.clone());
                         } else {
                             return Err(LyraError::RuntimeError {
@@ -579,62 +883,97 @@ impl VM {
 
                 // ── String ops ──
                 Op::ToString => {
-                    let val = self.pop();
+                    let val = self.pop()?;
                     self.push(Value::String(val.display_unquoted()));
                 }
-                Op::StringConcat => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    match (a, b) {
-                        (Value::String(a), Value::String(b)) => {
-                            self.push(Value::String(format!("{}{}", a, b)));
-                        }
-                        _ => {
-                            return Err(LyraError::RuntimeError {
-                                message: "string concat requires strings".to_string(),
-                                span: self.current_span(),
-                            })
+                Op::MatchFail(ref patterns) => {
+                    let val = self.pop()?;
+                    return Err(LyraError::MatchFailure {
+                        value: val.describe(),
+                        patterns: patterns.clone(),
+                        span: self.current_span(),
+                    });
+                }
+                Op::Format(ref spec) => {
+                    let val = self.pop()?;
+                    self.push(Value::String(val.display_formatted(spec)));
+                }
+                Op::Slice => {
+                    let (start, end) = self.pop_pair()?;
+                    let val = self.pop()?;
+                    let bound = |v: Value| match v {
+                        Value::Int(i) => Some(i),
+                        _ => None,
+                    };
+                    let sliced = crate::eval::value::slice(&val, bound(start), bound(end))
+                        .map_err(|message| LyraError::RuntimeError {
+                            message,
+                            span: self.current_span(),
+                        })?;
+                    self.push(sliced);
+                }
+                Op::BuildString(n) => {
+                    let parts = self.pop_n(n)?;
+                    let mut built = String::new();
+                    for part in parts {
+                        match part {
+                            Value::String(s) => built.push_str(&s),
+                            other => {
+                                return Err(LyraError::RuntimeError {
+                                    message: format!(
+                                        "cannot build a string from {}",
+                                        other.describe()
+                                    ),
+                                    span: self.current_span(),
+                                })
+                            }
                         }
                     }
+                    self.push(Value::String(built));
                 }
 
                 Op::Print => {
-                    let val = self.pop();
-                    match &val {
-                        Value::String(s) => println!("{}", s),
-                        v => println!("{}", v),
-                    }
+                    let val = self.pop()?;
+                    crate::eval::builtins::write_output(&format!("{}\n", val.display_unquoted()));
                     self.push(Value::Unit);
                 }
                 Op::PrintRaw => {
-                    let val = self.pop();
-                    match &val {
-                        Value::String(s) => print!("{}", s),
-                        v => print!("{}", v),
-                    }
+                    let val = self.pop()?;
+                    crate::eval::builtins::write_output(&val.display_unquoted());
                     self.push(Value::Unit);
                 }
             }
         }
     }
 
+    /// `a + b`: numbers, promoted as needed, or Strings joined.
+    fn add(&self, a: Value, b: Value) -> Result<Value, LyraError> {
+        let (a, b) = promote_numeric(a, b);
+        match (&a, &b) {
+            (Value::Int(x), Value::Int(y)) => Ok(Value::Int(x + y)),
+            (Value::Float(x), Value::Float(y)) => Ok(Value::Float(x + y)),
+            (Value::String(x), Value::String(y)) => Ok(Value::String(format!("{}{}", x, y))),
+            _ => Err(LyraError::RuntimeError {
+                message: binop_error(BinOp::Add, &a, &b),
+                span: self.current_span(),
+            }),
+        }
+    }
+
     fn binary_arith(
         &mut self,
+        op: BinOp,
         int_op: fn(i64, i64) -> i64,
         float_op: fn(f64, f64) -> f64,
     ) -> Result<(), LyraError> {
-        let b = self.pop();
-        let a = self.pop();
+        let (a, b) = self.pop_pair()?;
+        let (a, b) = promote_numeric(a, b);
         match (&a, &b) {
             (Value::Int(x), Value::Int(y)) => self.push(Value::Int(int_op(*x, *y))),
             (Value::Float(x), Value::Float(y)) => self.push(Value::Float(float_op(*x, *y))),
             _ => {
                 return Err(LyraError::RuntimeError {
-                    message: format!(
-                        "arithmetic on {} and {}",
-                        a.type_name(),
-                        b.type_name()
-                    ),
+                    message: binop_error(op, &a, &b),
                     span: self.current_span(),
                 })
             }
@@ -642,27 +981,27 @@ impl VM {
         Ok(())
     }
 
-    fn binary_cmp(
-        &mut self,
-        int_op: fn(&i64, &i64) -> bool,
-        float_op: fn(&f64, &f64) -> bool,
-    ) -> Result<(), LyraError> {
-        let b = self.pop();
-        let a = self.pop();
-        match (&a, &b) {
-            (Value::Int(x), Value::Int(y)) => self.push(Value::Bool(int_op(x, y))),
-            (Value::Float(x), Value::Float(y)) => self.push(Value::Bool(float_op(x, y))),
-            _ => {
-                return Err(LyraError::RuntimeError {
-                    message: format!(
-                        "comparison on {} and {}",
-                        a.type_name(),
-                        b.type_name()
-                    ),
-                    span: self.current_span(),
-                })
-            }
-        }
+    /// Compare the top two values (see `Value::equals`), pushing whether
+    /// their equality is `expected`.
+    fn binary_eq(&mut self, expected: bool) -> Result<(), LyraError> {
+        let (a, b) = self.pop_pair()?;
+        let equal = a.equals(&b).map_err(|message| LyraError::RuntimeError {
+            message,
+            span: self.current_span(),
+        })?;
+        self.push(Value::Bool(equal == expected));
+        Ok(())
+    }
+
+    /// Order the top two values structurally (see `Value::compare`), pushing
+    /// whether `test` holds; an unordered pair, with a NaN, gives false.
+    fn binary_cmp(&mut self, test: fn(Ordering) -> bool) -> Result<(), LyraError> {
+        let (a, b) = self.pop_pair()?;
+        let order = a.compare(&b).map_err(|message| LyraError::RuntimeError {
+            message,
+            span: self.current_span(),
+        })?;
+        self.push(Value::Bool(order.is_some_and(test)));
         Ok(())
     }
 }
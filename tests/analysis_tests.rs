@@ -0,0 +1,173 @@
+use lyra::analysis::{analyze, search, semantic_tokens, Analysis, SymbolKind, TokenClass};
+use lyra::eval::env::Env;
+use lyra::stdlib::register_stdlib;
+use lyra::types::env::TypeEnv;
+use lyra::types::TypeVarGen;
+
+/// Analyze `source` at the `@` marker, with the stdlib in scope.
+fn analyze_at(marked: &str) -> Analysis {
+    let offset = marked.chars().position(|c| c == '@').unwrap();
+    let source = marked.replacen('@', "", 1);
+    let mut type_env = TypeEnv::new();
+    register_stdlib(&mut type_env, &Env::new(), &mut TypeVarGen::new());
+    analyze(&source, offset, &type_env)
+}
+
+fn locals(analysis: &Analysis) -> Vec<String> {
+    analysis
+        .symbols
+        .iter()
+        .filter(|s| s.kind == SymbolKind::Local)
+        .map(|s| format!("{} : {}", s.name, s.ty))
+        .collect()
+}
+
+// ── Scope ──
+
+#[test]
+fn analysis_lists_locals_innermost_first_with_types() {
+    let a = analyze_at("let f = fn (xs) -> map(fn (n) -> n + @1, xs)");
+    assert_eq!(locals(&a), vec!["n : t0", "xs : [t0]"]);
+}
+
+#[test]
+fn analysis_scopes_let_in_and_match_arms() {
+    let src = "let rec go = fn (n) ->\n  match n with\n  | 0 -> \"\"\n  | k -> let s = to_string(k) in go(@k - 1)";
+    let a = analyze_at(src);
+    assert_eq!(locals(&a), vec!["s : String", "k : Int", "n : Int"]);
+    assert!(a.symbols.iter().any(|s| s.name == "go" && s.kind == SymbolKind::Global));
+
+    // Before `in`, the binding isn't visible yet
+    let a = analyze_at("let s = 1 + @2 in s");
+    assert!(locals(&a).is_empty());
+}
+
+#[test]
+fn analysis_sees_only_earlier_top_level_bindings() {
+    let a = analyze_at("let before = 1\nlet here = @before\nlet after = 2");
+    let names: Vec<&str> = a.symbols.iter().map(|s| s.name.as_str()).collect();
+    assert!(names.contains(&"before"));
+    assert!(!names.contains(&"here"));
+    assert!(!names.contains(&"after"));
+}
+
+// ── Completion ──
+
+#[test]
+fn analysis_completes_prefix_with_types() {
+    let a = analyze_at("let length_of = fn (xs) -> length(xs)\nlen@");
+    assert_eq!(a.prefix, "len");
+    let found: Vec<String> = a
+        .completions()
+        .map(|s| format!("{} : {}", s.name, s.ty))
+        .collect();
+    assert_eq!(
+        found,
+        vec!["length : forall t0. [t0] -> Int", "length_of : forall t0. [t0] -> Int"]
+    );
+}
+
+#[test]
+fn analysis_falls_back_to_globals_when_source_does_not_parse() {
+    let a = analyze_at("let y = map(xs, fil@");
+    assert_eq!(a.prefix, "fil");
+    assert!(a.completions().any(|s| s.name == "filter"));
+    assert_eq!(a.enclosing, None);
+}
+
+#[test]
+fn analysis_reports_enclosing_expression() {
+    let src = "let f = fn (x) -> x + @1";
+    let a = analyze_at(src);
+    let span = a.enclosing.unwrap();
+    let text: String = src.replacen('@', "", 1).chars().skip(span.start).take(span.len()).collect();
+    assert_eq!(text, "1");
+}
+
+// ── Search ──
+
+/// `name : type` for each `search` hit over the stdlib.
+fn search_stdlib(query: &str) -> Vec<String> {
+    let mut type_env = TypeEnv::new();
+    register_stdlib(&mut type_env, &Env::new(), &mut TypeVarGen::new());
+    search(query, &type_env)
+        .into_iter()
+        .map(|s| format!("{} : {}", s.name, s.ty))
+        .collect()
+}
+
+#[test]
+fn search_ranks_names_before_types_and_fuzzy_matches() {
+    let hits = search_stdlib("str_len");
+    assert_eq!(hits[0], "str_length : String -> Int");
+
+    // Types match with spacing ignored; names containing the query come first
+    let hits = search_stdlib("String ->[String]");
+    assert!(hits.contains(&"str_chars : String -> [String]".to_string()), "{:?}", hits);
+    assert!(hits.iter().all(|h| h.contains("String -> [String]")), "{:?}", hits);
+
+    let hits = search_stdlib("strlen");
+    assert_eq!(hits, vec!["str_length : String -> Int"]);
+    assert_eq!(search_stdlib("fitler"), vec!["filter : forall t0. (t0 -> Bool) -> [t0] -> [t0]"]);
+    assert!(search_stdlib("   ").is_empty());
+}
+
+// ── Semantic tokens ──
+
+fn classes(source: &str) -> Vec<(String, TokenClass)> {
+    let mut type_env = TypeEnv::new();
+    register_stdlib(&mut type_env, &Env::new(), &mut TypeVarGen::new());
+    let chars: Vec<char> = source.chars().collect();
+    semantic_tokens(source, &type_env)
+        .into_iter()
+        .map(|t| (chars[t.span.start..t.span.end].iter().collect(), t.class))
+        .collect()
+}
+
+#[test]
+fn semantic_tokens_classify_by_resolution() {
+    use TokenClass::*;
+    let source = "-- apply twice\nlet twice = fn (f, x) -> f(f(x))\nlet n = twice(fn (k) -> k * 2, 1)";
+    assert_eq!(
+        classes(source),
+        vec![
+            ("-- apply twice".to_string(), Comment),
+            ("let".to_string(), Keyword),
+            ("twice".to_string(), Function),
+            ("fn".to_string(), Keyword),
+            ("f".to_string(), Function),
+            ("x".to_string(), Variable),
+            ("f".to_string(), Function),
+            ("f".to_string(), Function),
+            ("x".to_string(), Variable),
+            ("let".to_string(), Keyword),
+            ("n".to_string(), Variable),
+            ("twice".to_string(), Function),
+            ("fn".to_string(), Keyword),
+            ("k".to_string(), Variable),
+            ("k".to_string(), Variable),
+            ("2".to_string(), Number),
+            ("1".to_string(), Number),
+        ]
+    );
+}
+
+#[test]
+fn semantic_tokens_classify_types_and_constructors() {
+    let source = "type Shape a = Circle Float | Tag a\nlet s = Circle(1.0)\nprint(\"{s}\")";
+    let found = classes(source);
+    let class_of = |text: &str| found.iter().find(|(t, _)| t == text).unwrap().1;
+    assert_eq!(class_of("Shape"), TokenClass::Type);
+    assert_eq!(class_of("a"), TokenClass::Type);
+    assert_eq!(class_of("Float"), TokenClass::Type);
+    assert_eq!(class_of("Circle"), TokenClass::Constructor);
+    assert_eq!(class_of("print"), TokenClass::Function);
+    assert_eq!(class_of("\"{s}\""), TokenClass::String);
+}
+
+#[test]
+fn semantic_tokens_fall_back_to_spelling_when_source_does_not_parse() {
+    let found = classes("let x = Some(map");
+    assert_eq!(found[2], ("Some".to_string(), TokenClass::Constructor));
+    assert_eq!(found[3], ("map".to_string(), TokenClass::Function));
+}
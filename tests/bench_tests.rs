@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use lyra::bench::{measure, run_benches, table, BenchConfig, Iterations};
+
+fn fixed(n: usize, vm: bool) -> BenchConfig {
+    BenchConfig {
+        iterations: Iterations::Fixed(n),
+        vm,
+    }
+}
+
+// ── measure ──
+
+#[test]
+fn bench_measure_fixed_and_timed() {
+    let mut calls = 0;
+    let stats = measure(Iterations::Fixed(7), || -> Result<(), ()> {
+        calls += 1;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!((calls, stats.iterations), (7, 7));
+    assert!(stats.min <= stats.mean() && stats.mean() <= stats.max);
+
+    let stats = measure(Iterations::For(Duration::ZERO), || -> Result<(), ()> { Ok(()) });
+    assert_eq!(stats.unwrap().iterations, 1);
+}
+
+#[test]
+fn bench_measure_stops_at_the_first_error() {
+    let mut calls = 0;
+    let result = measure(Iterations::Fixed(10), || {
+        calls += 1;
+        if calls == 3 {
+            Err("boom")
+        } else {
+            Ok(())
+        }
+    });
+    assert_eq!((result.unwrap_err(), calls), ("boom", 3));
+}
+
+// ── lyra bench ──
+
+#[test]
+fn bench_runs_bench_functions_on_both_backends() {
+    let source = "let rec fib = fn (n) -> if n <= 1 then n else fib(n - 1) + fib(n - 2)\n\
+                  let bench_fib = fn () -> fib(10)\n\
+                  let helper = fn () -> 1\n\
+                  println(\"not run\")\n\
+                  let bench_sum = fn () -> sum(range(0, 100))";
+    for vm in [false, true] {
+        let results = run_benches(source, "bench.lyra", fixed(3, vm)).unwrap();
+        let names: Vec<&str> = results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["bench_fib", "bench_sum"]);
+        assert!(results.iter().all(|r| r.stats.iterations == 3));
+    }
+}
+
+#[test]
+fn bench_rejects_non_thunks_and_reports_errors() {
+    let err = run_benches("let bench_x = 5", "bench.lyra", fixed(1, false)).unwrap_err();
+    assert!(err.to_string().contains("must be a function of no arguments"), "{}", err);
+
+    let source = "let bench_fail = fn () -> head([])";
+    for vm in [false, true] {
+        let err = run_benches(source, "bench.lyra", fixed(1, vm)).unwrap_err();
+        assert!(err.to_string().contains("empty list"), "{}", err);
+    }
+}
+
+#[test]
+fn bench_table_compares_means() {
+    let source = "let bench_a = fn () -> 1\nlet bench_b = fn () -> 2";
+    let results = run_benches(source, "bench.lyra", fixed(2, false)).unwrap();
+    let table = table(&results);
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("benchmark") && lines[0].ends_with("relative"));
+    assert!(lines[1].starts_with("bench_a") && lines[2].starts_with("bench_b"));
+    assert!(lines[1..].iter().any(|line| line.ends_with("1.00x")));
+}
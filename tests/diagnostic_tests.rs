@@ -0,0 +1,91 @@
+use lyra::diagnostic::{Diagnostic, Format, Label, Severity};
+use lyra::error::LyraError;
+use lyra::span::Span;
+
+fn undefined_abd() -> LyraError {
+    LyraError::UndefinedVariable {
+        name: "abd".to_string(),
+        suggestion: Some("abc".to_string()),
+        span: Span::new(12, 15),
+    }
+}
+
+const SOURCE: &str = "let abc = 1\nabd + 1";
+
+#[test]
+fn diagnostic_carries_structured_fields() {
+    let d = undefined_abd().diagnostic();
+    assert_eq!(d.severity, Severity::Error);
+    assert_eq!(d.kind, "type error");
+    assert_eq!(d.code, "E0203");
+    assert_eq!(d.message, "undefined variable 'abd'");
+    assert_eq!(d.primary.as_ref().map(|l| l.span), Some(Span::new(12, 15)));
+    assert_eq!(d.suggestion.as_deref(), Some("abc"));
+}
+
+#[test]
+fn diagnostic_renders_plain_text() {
+    let plain = undefined_abd().diagnostic().render(SOURCE, "t.lyra", Format::Plain);
+    assert_eq!(
+        plain,
+        "type error: undefined variable 'abd'\n --> t.lyra:2:1\n  |\n2 | abd + 1\n  | ^^^ undefined variable 'abd'\n  = help: did you mean 'abc'?"
+    );
+    assert!(!plain.contains('\x1b'));
+
+    let ansi = undefined_abd().render(SOURCE, "t.lyra");
+    assert!(ansi.contains("\x1b[1;31mtype error\x1b[0m"));
+}
+
+#[test]
+fn diagnostic_renders_secondary_labels() {
+    let d = Diagnostic {
+        severity: Severity::Warning,
+        kind: "warning",
+        code: "W0001",
+        message: "shadowed binding".to_string(),
+        primary: Some(Label {
+            span: Span::new(12, 15),
+            message: "used here".to_string(),
+        }),
+        secondary: vec![Label {
+            span: Span::new(4, 7),
+            message: "defined here".to_string(),
+        }],
+        suggestion: None,
+    };
+    assert_eq!(
+        d.render(SOURCE, "t.lyra", Format::Plain),
+        "warning: shadowed binding\n --> t.lyra:2:1\n  |\n2 | abd + 1\n  | ^^^ used here\n  |\n1 | let abc = 1\n  |     --- defined here"
+    );
+}
+
+#[test]
+fn diagnostic_renders_json() {
+    let json = undefined_abd().diagnostic().render(SOURCE, "dir/\"t\".lyra", Format::Json);
+    assert_eq!(
+        json,
+        "{\"severity\":\"error\",\"kind\":\"type error\",\"code\":\"E0203\",\"message\":\"undefined variable 'abd'\",\"file\":\"dir/\\\"t\\\".lyra\",\"primary\":{\"start\":12,\"end\":15,\"line\":2,\"column\":1,\"message\":\"undefined variable 'abd'\"},\"secondary\":[],\"suggestion\":\"abc\"}"
+    );
+
+    let io = LyraError::IoError { msg: "boom".to_string() };
+    assert!(io.diagnostic().render("", "x", Format::Json).contains("\"primary\":null"));
+}
+
+#[test]
+fn call_failures_give_the_arity_only_when_it_differs() {
+    let failed = |arity, found| LyraError::CallFailed {
+        name: "str_length".to_string(),
+        arity,
+        found,
+        message: "expected String, got Int".to_string(),
+        span: Span::new(0, 1),
+    };
+    assert_eq!(
+        failed(1, 1).diagnostic().message,
+        "in call to 'str_length': expected String, got Int"
+    );
+    assert_eq!(
+        failed(1, 2).diagnostic().message,
+        "in call to 'str_length' (arity 1, called with 2 arguments): expected String, got Int"
+    );
+}
@@ -0,0 +1,89 @@
+use lyra::doc::{document, render_html, render_markdown, render_signatures};
+
+// ── Collection ──
+
+#[test]
+fn doc_collects_schemes_and_comments() {
+    let source = "--- Wraps a value.\ntype Box a = Box a\n\n--- Identity.\nlet id = fn (x) -> x\nlet n = 1\nid(n)";
+    let modules = document(source, "box.lyra").unwrap();
+    assert_eq!(modules.len(), 1);
+
+    let items = &modules[0].items;
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].signature, "type Box a = Box a");
+    assert_eq!(items[0].variants, vec!["Box : forall t0. t0 -> Box t0".to_string()]);
+    assert_eq!(items[0].doc.as_deref(), Some("Wraps a value."));
+    assert_eq!(items[1].signature, "id : forall t0. t0 -> t0");
+    assert_eq!(items[1].doc.as_deref(), Some("Identity."));
+    assert_eq!(items[2].signature, "n : Int");
+    assert_eq!(items[2].doc, None);
+}
+
+#[test]
+fn doc_shows_what_a_function_asks_of_its_numbers() {
+    let source = "let add = fn (a, b) -> a + b\nlet twice = fn (x) -> x * 2\nlet same = fn (a, b) -> a == b";
+    let modules = document(source, "num.lyra").unwrap();
+    let signatures: Vec<_> = modules[0].items.iter().map(|i| i.signature.as_str()).collect();
+    assert_eq!(
+        signatures,
+        [
+            "add : forall t0 t1 t2. Add t0 t1 t2 => t0 -> t1 -> t2",
+            "twice : forall t0. Num t0 => t0 -> t0",
+            "same : forall t0 t1. Cmp t0 t1 => t0 -> t1 -> Bool",
+        ]
+    );
+}
+
+#[test]
+fn doc_lists_imported_modules_first() {
+    let path = "examples/modules/main.lyra";
+    let source = std::fs::read_to_string(path).unwrap();
+    let modules = document(&source, path).unwrap();
+    assert_eq!(modules.len(), 2);
+    assert!(modules[0].path.ends_with("math_utils.lyra"));
+    assert!(modules[0].items.iter().any(|i| i.signature == "square : forall t0. Num t0 => t0 -> t0"));
+    assert_eq!(modules[1].path, path);
+}
+
+#[test]
+fn doc_resolves_modules_bound_as_values() {
+    let path = "examples/modules/values.lyra";
+    let source = std::fs::read_to_string(path).unwrap();
+    let modules = document(&source, path).unwrap();
+    assert_eq!(modules.len(), 1);
+    let signatures: Vec<_> = modules[0].items.iter().map(|i| i.signature.as_str()).collect();
+    assert_eq!(
+        signatures,
+        ["m : forall t0 t1 t2. Num t0, Num t1, Num t2 => { abs: t0 -> t0, cube: t1 -> t1, factorial: Int -> Int, square: t2 -> t2 }"]
+    );
+}
+
+#[test]
+fn doc_reports_type_errors() {
+    assert!(document("let x = 1 + \"a\"", "bad.lyra").is_err());
+}
+
+// ── Rendering ──
+
+#[test]
+fn doc_renders_markdown_and_html() {
+    let modules = document("--- Compare <a> & <b>.\nlet lt = fn (a, b) -> a < b", "cmp.lyra").unwrap();
+
+    let md = render_markdown(&modules[0]);
+    assert!(md.starts_with("# `cmp.lyra`"));
+    assert!(md.contains("## `lt`"));
+    assert!(md.contains("Compare <a> & <b>."));
+
+    let html = render_html(&modules[0]);
+    assert!(html.contains("<section id=\"lt\">"));
+    assert!(html.contains("<p>Compare &lt;a&gt; &amp; &lt;b&gt;.</p>"));
+}
+
+#[test]
+fn doc_renders_signatures() {
+    let modules = document("type Box a = Box a\nlet id = fn (x) -> x\nid(1)", "box.lyra").unwrap();
+    assert_eq!(
+        render_signatures(&modules[0]),
+        "type Box a = Box a\n  Box : forall t0. t0 -> Box t0\nid : forall t0. t0 -> t0\n"
+    );
+}
@@ -0,0 +1,1262 @@
+use std::rc::Rc;
+
+use lyra::ast::Program;
+use lyra::eval::env::Env;
+use lyra::eval::eval_decl;
+use lyra::eval::resolve::resolve_program;
+use lyra::eval::value::Value;
+use lyra::lexer::tokenize;
+use lyra::macros::expand_macros;
+use lyra::parser::parse;
+use lyra::stdlib::register_stdlib;
+use lyra::types::env::TypeEnv;
+use lyra::types::infer::Inferencer;
+use lyra::types::TypeVarGen;
+
+/// Type-check and evaluate source with the tree-walking interpreter, returning the last value.
+fn eval_run(source: &str) -> Result<Value, String> {
+    let tokens = tokenize(source).map_err(|errs| format!("{:?}", errs))?;
+    let Program { mut ast, decls } = parse(tokens).map_err(|e| format!("{:?}", e))?;
+    expand_macros(&mut ast, &decls).map_err(|e| format!("{:?}", e))?;
+    resolve_program(&mut ast, &decls);
+    let ast = Rc::new(ast);
+
+    let mut type_env = TypeEnv::new();
+    let runtime_env = Env::new();
+    let mut gen = TypeVarGen::new();
+    let mut inferencer = Inferencer::new();
+    register_stdlib(&mut type_env, &runtime_env, &mut gen);
+
+    let mut last = Value::Unit;
+    for decl in &decls {
+        inferencer
+            .infer_decl(&mut type_env, &ast, decl)
+            .map_err(|e| format!("{:?}", e))?;
+        if let Some(v) = eval_decl(&runtime_env, &ast, decl).map_err(|e| format!("{:?}", e))? {
+            last = v;
+        }
+    }
+    Ok(last)
+}
+
+// ── If let ──
+
+#[test]
+fn eval_if_let_match() {
+    assert_eq!(
+        eval_run("type Option = Some Int | None\nif let Some(x) = Some(41) then x + 1 else 0").unwrap(),
+        Value::Int(42)
+    );
+}
+
+#[test]
+fn eval_if_let_fallback() {
+    assert_eq!(
+        eval_run("type Option = Some Int | None\nif let Some(x) = None then x + 1 else 0").unwrap(),
+        Value::Int(0)
+    );
+}
+
+// ── Tap ──
+
+#[test]
+fn eval_tap_returns_argument() {
+    assert_eq!(
+        eval_run("[1, 2, 3] |> tap(fn (xs) -> length(xs)) |> sum").unwrap(),
+        Value::Int(6)
+    );
+}
+
+#[test]
+fn eval_pipe_tap_sugar() {
+    assert_eq!(
+        eval_run("let f = fn (x) -> x * 2\n5 |>! f |> f").unwrap(),
+        Value::Int(10)
+    );
+}
+
+// ── Where ──
+
+#[test]
+fn eval_where_bindings_see_earlier_ones_and_params() {
+    assert_eq!(
+        eval_run("let hyp2 = fn (a, b) -> s where a2 = a * a, b2 = b * b, s = a2 + b2\nhyp2(3, 4)")
+            .unwrap(),
+        Value::Int(25)
+    );
+    assert_eq!(
+        eval_run("let n = go(5) where rec go = fn (k) -> if k == 0 then 0 else 2 + go(k - 1)\nn")
+            .unwrap(),
+        Value::Int(10)
+    );
+}
+
+#[test]
+fn eval_where_scopes_over_every_match_arm() {
+    let src = "let f = fn (n) -> match n with\n  | 0 -> k\n  | _ -> n * k\n  where k = 10\nf(0) + f(2)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(30));
+}
+
+// ── Format specs ──
+
+#[test]
+fn eval_interpolation_format_specs() {
+    assert_eq!(
+        eval_run("let pi = 3.14159\nlet n = -42\n\"{pi:.2}|{n:>6}|{n:06}|{\"abc\":-^7}|{true:<6}|\"")
+            .unwrap(),
+        Value::String("3.14|   -42|-00042|--abc--|true  |".to_string())
+    );
+}
+
+// ── Labeled arguments ──
+
+#[test]
+fn eval_labeled_arguments_reorder() {
+    assert_eq!(
+        eval_run("let sub = fn (a, b) -> a - b\nsub(b: 1, a: 10) + sub(20, b: 2)").unwrap(),
+        Value::Int(27)
+    );
+}
+
+#[test]
+fn eval_labeled_and_variadic_calls_to_locals() {
+    let src = "let f = fn (x) -> let mk = fn (b, a) -> a - b in mk(a: 10, b: x)\nf(1)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(9));
+    // A local named like a variadic builtin takes the arguments it declares
+    let src = "let f = fn (println) -> println(1)\nf(fn (n) -> n + 1)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(2));
+    assert!(eval_run("let f = fn (println) -> println(1, 2)\nf(fn (n) -> n)").is_err());
+    assert_eq!(eval_run("let f = fn (x) -> println(x, 2, 3)\nf(1)").unwrap(), Value::Unit);
+}
+
+// ── Memo ──
+
+#[test]
+fn eval_memo_fib() {
+    let src = "let rec fib = memo(fn (n) -> if n < 2 then n else fib(n - 1) + fib(n - 2))\nfib(15)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(610));
+}
+
+#[test]
+fn eval_bench_returns_timings() {
+    let src = "let s = bench(fn () -> sum(range(0, 10)), 5)\n\
+               let ordered = s.min_ms <= s.mean_ms && s.mean_ms <= s.max_ms\n\
+               let result = (s.iterations, ordered, s.total_ms >= 0.0)\n\
+               result";
+    assert_eq!(eval_run(src).unwrap().to_string(), "(5, true, true)");
+    let err = eval_run("bench(fn () -> 1, 0)").unwrap_err();
+    assert!(err.contains("iterations must be positive"), "{}", err);
+}
+
+#[test]
+fn eval_memo_structural_keys() {
+    assert_eq!(
+        eval_run("let total = memo(fn (xs) -> sum(xs))\ntotal([1, 2]) + total([1, 2]) + total([3])").unwrap(),
+        Value::Int(9)
+    );
+}
+
+// ── Placeholder application ──
+
+#[test]
+fn eval_placeholder_call() {
+    assert_eq!(
+        eval_run("let sub = fn (a, b) -> a - b\n10 |> sub(_, 3)").unwrap(),
+        Value::Int(7)
+    );
+}
+
+// ── Over-application ──
+
+#[test]
+fn eval_over_application_matches_vm() {
+    assert_eq!(
+        eval_run("let f = fn (a) -> fn (b) -> a * 10 + b\nf(4, 2)").unwrap(),
+        Value::Int(42)
+    );
+}
+
+// ── Call failures ──
+
+#[test]
+fn eval_builtin_failure_names_callee() {
+    let err = eval_run("head(tail([1]))").unwrap_err();
+    assert!(
+        err.contains("CallFailed { name: \"head\", arity: 1, found: 1, message: \"empty list\""),
+        "{}",
+        err
+    );
+}
+
+// ── Numeric promotion ──
+
+#[test]
+fn eval_mixed_int_float_arithmetic() {
+    assert_eq!(eval_run("1 + 2.5").unwrap(), Value::Float(3.5));
+    assert_eq!(eval_run("let n = 7\nn / 2.0").unwrap(), Value::Float(3.5));
+    assert_eq!(eval_run("2.0 * 3 - 1").unwrap(), Value::Float(5.0));
+    assert_eq!(eval_run("3 > 2.5").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn eval_promotion_through_generic_functions() {
+    assert_eq!(eval_run("let f = fn (x) -> x + 2.5\nf(1)").unwrap(), Value::Float(3.5));
+    assert_eq!(eval_run("let g = fn (a, b) -> a + b\ng(1, 2.5)").unwrap(), Value::Float(3.5));
+    assert_eq!(eval_run("let g = fn (a, b) -> a + b\ng(1, 2)").unwrap(), Value::Int(3));
+    assert_eq!(eval_run("let half = fn (x) -> x / 2\nhalf(7)").unwrap(), Value::Int(3));
+    assert_eq!(eval_run("let half = fn (x) -> x / 2\nhalf(7.0)").unwrap(), Value::Float(3.5));
+    assert_eq!(eval_run("1 == 1.0 && 2 != 2.5").unwrap(), Value::Bool(true));
+}
+
+// ── Ordering ──
+
+const ORDERING: &str = "type Opt = Nothing | Just Int\n\n\
+                        let nan = 0.0 / 0.0\n\
+                        let result = [\n\
+                          \"a\" <= \"b\", \"abd\" > \"abc\", [1, 2] < [1, 3], [1, 2] < [1, 2, 0],\n\
+                          (1, \"b\") < (1, \"c\"), Just(1) < Just(2), Just(3) >= Just(3),\n\
+                          Just(0) < Nothing, { a: 1, b: 2 } < { a: 1, b: 3 }, false < true,\n\
+                          !(nan < 1.0), !(nan >= 1.0), !([1.0] <= [nan])\n\
+                        ]\n\
+                        all(fn (b) -> b, result)";
+
+#[test]
+fn eval_orders_values_structurally() {
+    assert_eq!(eval_run(ORDERING).unwrap(), Value::Bool(true));
+    let err = eval_run("let f = fn (x) -> x\nf < f").unwrap_err();
+    assert!(err.contains("cannot order functions"), "{}", err);
+}
+
+const EQUALITY: &str = "type Opt = Nothing | Just Int\n\n\
+                        let nan = 0.0 / 0.0\n\
+                        let result = [\n\
+                          [1, 2] == [1, 2], [1, 2] != [1, 2, 3], Just(1) == Just(1),\n\
+                          Just(1) != Nothing, { a: 1, b: \"x\" } == { a: 1, b: \"x\" },\n\
+                          !(nan == nan), nan != nan, !([nan] == [nan]), 0.0 == -0.0\n\
+                        ]\n\
+                        all(fn (b) -> b, result)";
+
+#[test]
+fn eval_equality_is_structural_with_ieee_floats() {
+    assert_eq!(eval_run(EQUALITY).unwrap(), Value::Bool(true));
+    let err = eval_run("let f = fn (x) -> x\nf == f").unwrap_err();
+    assert!(err.contains("cannot compare functions"), "{}", err);
+    let err = eval_run("[(1, fn (x) -> x)] != [(1, fn (x) -> x)]").unwrap_err();
+    assert!(err.contains("cannot compare functions"), "{}", err);
+}
+
+// ── Power ──
+
+#[test]
+fn eval_power_operator() {
+    assert_eq!(eval_run("2 ** 3 ** 2").unwrap(), Value::Int(512));
+    assert_eq!(eval_run("float_pow(9.0, 0.5)").unwrap(), Value::Float(3.0));
+    assert!(eval_run("10 ** 40").unwrap_err().contains("integer overflow"));
+}
+
+#[test]
+fn eval_string_concatenation_chains() {
+    let src = "let tag = \"b\"\n(\"<\" + tag + \">\" + \"{tag}\", \"\" + \"\" + \"\")";
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::Tuple(vec![Value::String("<b>b".to_string()), Value::String(String::new())])
+    );
+    assert_eq!(eval_run("1 + 2 + 3.5").unwrap(), Value::Float(6.5));
+}
+
+// ── Euclidean division ──
+
+#[test]
+fn eval_euclidean_division() {
+    let pair = |q, r| Value::Tuple(vec![Value::Int(q), Value::Int(r)]);
+    let src = "[divmod(7, 2), divmod(-7, 2), divmod(7, -2), divmod(-7, -2)]";
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::List(vec![pair(3, 1), pair(-4, 1), pair(-3, 1), pair(4, 1)])
+    );
+    assert_eq!(
+        eval_run("(div_euclid(-7, 2), mod_euclid(-7, 2), -7 % 2)").unwrap(),
+        Value::Tuple(vec![Value::Int(-4), Value::Int(1), Value::Int(-1)])
+    );
+    assert!(eval_run("mod_euclid(1, 0)").unwrap_err().contains("division by zero"));
+    let min = "let min = -9223372036854775807 - 1\n";
+    assert_eq!(eval_run(&format!("{}mod_euclid(min, -1)", min)).unwrap(), Value::Int(0));
+    assert!(eval_run(&format!("{}divmod(min, -1)", min)).unwrap_err().contains("integer overflow"));
+}
+
+// ── Reflection ──
+
+#[test]
+fn eval_record_reflection() {
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(
+        eval_run("record_fields({ name: \"Ann\", age: 36 })").unwrap(),
+        Value::List(vec![
+            Value::Tuple(vec![s("age"), Value::Int(36)]),
+            Value::Tuple(vec![s("name"), s("Ann")]),
+        ])
+    );
+    let get = "type Option a = Some a | None\nlet p = { age: 36 }\n\
+               let int = fn (field) -> match field with\n\
+               | Some(v) -> (match dynamic_int(v) with | Some(n) -> n | None -> -1)\n\
+               | None -> 0\n\
+               [int(record_get(p, \"age\")), int(record_get(p, \"zip\")), \
+               int(record_get({ age: \"36\" }, \"age\"))]";
+    assert_eq!(
+        eval_run(get).unwrap(),
+        Value::List(vec![Value::Int(36), Value::Int(0), Value::Int(-1)])
+    );
+    // Field values are Dynamic, not whatever type the caller wants
+    let err = eval_run(
+        "type Option a = Some a | None\n\
+         match record_get({ a: 1 }, \"a\") with | Some(s) -> str_length(s) | None -> 0",
+    )
+    .unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+    let err = eval_run("record_fields(1)").unwrap_err();
+    assert!(err.contains("message: \"expected Record, got Int\""), "{}", err);
+}
+
+#[test]
+fn eval_optional_chaining() {
+    let src = "type Option a = Some a | None\n\
+               let users = [Some({ name: \"Ada\", address: { city: \"London\" } }), None]\n\
+               let names = map(fn (u) -> u?.name, users)\n\
+               let cities = map(fn (u) -> u?.address?.city, users)\n\
+               let result = (names, cities)\n\
+               result";
+    assert_eq!(
+        eval_run(src).unwrap().to_string(),
+        "([Some(\"Ada\"), None], [Some(\"London\"), None])"
+    );
+    let err = eval_run("type Option a = Some a | None\nlet r = { a: 1 }\nr?.a").unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+}
+
+#[test]
+fn eval_optional_chaining_keeps_optional_fields_wrapped() {
+    let src = "type Option a = Some a | None\n\
+               let user = Some({ address: Some({ city: \"London\" }) })\n\
+               let city = match user?.address with | Some(Some(a)) -> Some(a.city) | _ -> None\n\
+               let result = (user?.address, city)\n\
+               result";
+    assert_eq!(
+        eval_run(src).unwrap().to_string(),
+        "(Some(Some({ city: \"London\" })), Some(\"London\"))"
+    );
+    let err = eval_run("type Option a = Some a | None\nlet user = Some({ address: Some({ city: 1 }) })\nuser?.address?.city")
+        .unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+}
+
+#[test]
+fn eval_slices_clamp_and_count_chars() {
+    let src = "let xs = [1, 2, 3, 4, 5]\n\
+               let s = \"héllo wörld\"\n\
+               let result = ((xs[1:4], xs[:2], xs[3:], xs[-2:], xs[4:2], xs[-10:10]), \
+               (s[1:4], s[6:], s[-3:], s[20:], s[:]))\n\
+               result";
+    assert_eq!(
+        eval_run(src).unwrap().to_string(),
+        "(([2, 3, 4], [1, 2], [4, 5], [4, 5], [], [1, 2, 3, 4, 5]), \
+         (\"éll\", \"wörld\", \"rld\", \"\", \"héllo wörld\"))"
+    );
+}
+
+#[test]
+fn eval_adt_reflection() {
+    let src = "type Shape = Circle Int | Rect Int Int\nlet r = Rect(2, 3)\n\
+               let parts = (adt_constructor(r), adt_fields(r), adt_fields(Circle(1)))\nparts";
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::Tuple(vec![
+            Value::String("Rect".to_string()),
+            Value::List(vec![Value::Int(2), Value::Int(3)]),
+            Value::List(vec![Value::Int(1)]),
+        ])
+    );
+    let err = eval_run("adt_fields([1])").unwrap_err();
+    assert!(err.contains("message: \"expected ADT, got List\""), "{}", err);
+}
+
+#[test]
+fn eval_quote_builds_inspectable_code() {
+    let show = |src: &str| eval_run(src).map(|v| v.to_string());
+    assert_eq!(show("let x = 1\nquote (1 + x)").unwrap(), "BinOp(\"+\", IntLit(1), Var(\"x\"))");
+    assert_eq!(
+        show("let n = 20\nlet body = quote (n * 2)\nquote (fn (n) -> unquote(body) + unquote(n))")
+            .unwrap(),
+        "Lambda([\"n\"], BinOp(\"+\", BinOp(\"*\", Var(\"n\"), IntLit(2)), IntLit(20)))"
+    );
+    assert_eq!(show("let q = quote [a, b]\nadt_constructor(q)").unwrap(), "\"ListLit\"");
+    let err = show("quote (unquote(fn (x) -> x))").unwrap_err();
+    assert!(err.contains("cannot splice Function into quoted code"), "{}", err);
+}
+
+// ── Macros ──
+
+#[test]
+fn eval_macros_expand_hygienically() {
+    let src = "macro when(cond, body) = quote if unquote(cond) then unquote(body) else 0\n\
+               macro twice(e) = quote (let x = unquote(e) in x + x)\n\
+               let x = 10\n\
+               let y = when(x > 5, twice(x + 1))\ny";
+    // The template's `x` is renamed, so it doesn't capture the argument's
+    assert_eq!(eval_run(src).unwrap(), Value::Int(22));
+    // An argument is evaluated only where the template splices it
+    let src = "macro when(cond, body) = quote if unquote(cond) then unquote(body) else 0\n\
+               when(false, 1 / 0)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(0));
+}
+
+#[test]
+fn eval_macro_free_names_are_not_captured_by_the_caller() {
+    // `str_length` in the template is the builtin, not the caller's parameter
+    let src = "macro len(s) = quote str_length(unquote(s))
+               let f = fn (str_length) -> len(\"ab\") + str_length
+               f(10)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(12));
+    // Renaming the parameter keeps it usable as a label
+    let src = "macro len(s) = quote str_length(unquote(s))
+               let f = fn (str_length, n) -> len(\"abc\") * n + str_length
+               f(n: 2, str_length: 1)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(7));
+    let src = "macro len(s) = quote str_length(unquote(s))
+               match \"x\" with | str_length -> len(str_length)";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(1));
+}
+
+#[test]
+fn eval_macro_errors() {
+    let err = |src: &str| eval_run(src).unwrap_err();
+    assert!(err("macro m(a) = a").contains("a macro's body must be a `quote` expression"));
+    assert!(err("macro m(a) = quote unquote(b)").contains("can only unquote its parameters (a)"));
+    let arity = err("macro m(a) = quote unquote(a)\nm(1, 2)");
+    assert!(arity.contains("expects 1 arguments, but got 2"), "{}", arity);
+    assert!(err("macro m(a) = quote m(unquote(a))\nm(1)").contains("a macro can't call itself"));
+    assert!(err("macro m(a) = quote unquote(a)\nlet f = m").contains("can only be called"));
+}
+
+// ── Debugger ──
+
+#[test]
+fn eval_debugger_stops_at_breakpoints_and_steps() {
+    use lyra::debugger::{self, Resume};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let stops = Rc::new(RefCell::new(Vec::new()));
+    let seen = stops.clone();
+    debugger::set_handler(move |stop| {
+        let locals: Vec<String> =
+            stop.locals.iter().map(|(n, v)| format!("{}={}", n, v)).collect();
+        seen.borrow_mut().push((stop.line, locals.join(" ")));
+        // Step out of the first stop, then run on
+        if seen.borrow().len() == 1 {
+            Resume::Step
+        } else {
+            Resume::Continue
+        }
+    });
+    debugger::add_breakpoint("prog", 3);
+    let src = "let f = fn (n) ->\n  let m = n * 2 in\n  m + 1\nlet a = f(3)\nlet b = f(10)\na + b";
+    let result = debugger::with_source("prog", src, || lyra::eval_source(src));
+    assert_eq!(result.unwrap(), Value::Int(28));
+    assert_eq!(
+        *stops.borrow(),
+        [
+            (3, "m=6 n=3".to_string()),
+            (5, String::new()),
+            (3, "m=20 n=10".to_string()),
+        ]
+    );
+    // Breakpoints in other files don't trip
+    stops.borrow_mut().clear();
+    debugger::with_source("other", src, || lyra::eval_source(src)).unwrap();
+    assert!(stops.borrow().is_empty());
+}
+
+// ── Runtime evaluation ──
+
+#[test]
+fn eval_builtin_returns_a_result() {
+    let result = |call: &str| {
+        eval_run(&format!("type Result a e = Ok a | Err e\nlet y = 1\nlet r = {}\nr", call))
+    };
+    let adt = |tag: &str, field: Value| Value::adt(tag.to_string(), vec![field]);
+    assert_eq!(result("eval(\"let x = 20\\nx * 2 + 2\")").unwrap(), adt("Ok", Value::Int(42)));
+    let Value::Adt(adt) = result("eval(\"1 + true\")").unwrap() else {
+        panic!("expected an ADT")
+    };
+    assert_eq!(adt.constructor, "Err");
+    assert!(matches!(&adt.fields[0], Value::String(m) if m.contains("type mismatch")), "{:?}", adt.fields);
+    // Snippets don't see the caller's bindings or read files
+    let Value::Adt(adt) = result("eval(\"y\")").unwrap() else {
+        panic!("expected an ADT")
+    };
+    assert!(matches!(&adt.fields[0], Value::String(m) if m.contains("undefined variable 'y'")));
+    let Value::Adt(adt) = result("eval(\"import \\\"utils\\\"\")").unwrap() else {
+        panic!("expected an ADT")
+    };
+    assert!(matches!(&adt.fields[0], Value::String(m) if m.contains("eval can't import files")));
+}
+
+#[test]
+fn eval_builtin_values_are_dynamic() {
+    let src = "type Result a e = Ok a | Err e\ntype Option a = Some a | None\n\
+               let n = match eval(\"20 + 22\") with\n\
+               | Ok(v) -> (match dynamic_int(v) with | Some(n) -> n | None -> 0)\n\
+               | Err(e) -> -1\n\
+               n";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(42));
+    // The result can't be used at a type the snippet didn't give it
+    let err = eval_run(
+        "type Result a e = Ok a | Err e\nmatch eval(\"\\\"s\\\"\") with | Ok(n) -> n + 1 | Err(e) -> 0",
+    )
+    .unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+}
+
+#[test]
+fn eval_builtin_reports_deep_recursion() {
+    let src = "type Result a e = Ok a | Err e\n\
+               let r = eval(\"let rec f = fn (n) -> 1 + f(n + 1)\\nf(0)\")\nr";
+    let Value::Adt(adt) = eval_run(src).unwrap() else {
+        panic!("expected an ADT")
+    };
+    assert_eq!(adt.constructor, "Err");
+    assert!(matches!(&adt.fields[0], Value::String(m) if m.contains("stack overflow")), "{:?}", adt.fields);
+}
+
+#[test]
+fn eval_builtin_shares_the_budget() {
+    use lyra::budget::{with_budget, Budget};
+    let budget = Budget {
+        max_steps: Some(300),
+        timeout: None,
+    };
+    let src = "type Result a e = Ok a | Err e\n\
+               let r = eval(\"sum(map(fn (x) -> x * x, range(0, 100000)))\")";
+    let err = with_budget(budget, || eval_run(src)).unwrap_err();
+    assert!(err.starts_with("BudgetExceeded"), "{}", err);
+}
+
+// ── Bytes ──
+
+#[test]
+fn eval_bytes_encodings_round_trip() {
+    let text = |src: &str| match eval_run(src).unwrap() {
+        Value::String(s) => s,
+        v => panic!("expected a String, got {}", v),
+    };
+    // RFC 4648 test vectors
+    for (plain, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")] {
+        assert_eq!(text(&format!("base64_encode(utf8_encode(\"{}\"))", plain)), encoded);
+        assert_eq!(text(&format!("utf8_decode(base64_decode(\"{}\"))", encoded)), plain);
+    }
+    assert_eq!(text("utf8_decode(base64_decode(\"Zm8\"))"), "fo");
+    assert_eq!(text("hex_encode(bytes_from_list([0, 127, 255]))"), "007fff");
+    assert_eq!(
+        eval_run("bytes_to_list(hex_decode(\"00FF\"))").unwrap(),
+        Value::List(vec![Value::Int(0), Value::Int(255)])
+    );
+    assert_eq!(eval_run("utf8_encode(\"héllo\")").unwrap().to_string(), "<6 bytes: 68c3a96c6c6f>");
+    assert_eq!(eval_run("hex_decode(\"0a\") == bytes_from_list([10])").unwrap(), Value::Bool(true));
+    assert!(eval_run("let b : Bytes = utf8_encode(\"x\")\nstr_length(b)").is_err());
+}
+
+#[test]
+fn eval_bytes_errors_explain_the_input() {
+    let err = |src: &str| eval_run(src).unwrap_err();
+    assert!(err("base64_decode(\"Zm9v!A\")").contains("invalid character '!'"));
+    assert!(err("hex_decode(\"abc\")").contains("odd number of digits"));
+    assert!(err("utf8_decode(bytes_from_list([104, 255]))").contains("invalid UTF-8 at byte 1"));
+    assert!(err("bytes_from_list([256])").contains("256 is not a byte (0 to 255)"));
+}
+
+#[test]
+fn eval_file_bytes_round_trip_outside_the_sandbox() {
+    use lyra::{run_file_with, RunOptions};
+
+    let path = std::env::temp_dir().join(format!("lyra_bytes_{}.bin", std::process::id()));
+    let src = format!(
+        "let data = bytes_from_list([0, 1, 254, 255])\nwrite_file_bytes(\"{p}\", data)\nread_file_bytes(\"{p}\") == data",
+        p = path.display()
+    );
+    assert_eq!(eval_run(&src).unwrap(), Value::Bool(true));
+    let _ = std::fs::remove_file(&path);
+
+    let options = RunOptions {
+        sandbox: true,
+        ..RunOptions::default()
+    };
+    let err = run_file_with("read_file_bytes(\"Cargo.toml\")", "sandbox.lyra", &options).unwrap_err();
+    assert!(format!("{:?}", err).contains("file access is disabled in sandbox mode"));
+}
+
+// ── Hashing ──
+
+#[test]
+fn eval_digests_match_known_vectors() {
+    let text = |src: &str| eval_run(src).unwrap().display_unquoted();
+    assert_eq!(text("sha256(\"\")"), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(text("sha256(\"abc\")"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert_eq!(
+        text("sha256(\"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq\")"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    assert_eq!(text("md5(\"\")"), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(
+        text("md5(utf8_encode(\"The quick brown fox jumps over the lazy dog\"))"),
+        "9e107d9d372bb6826bd81d3542a419d6"
+    );
+    assert!(eval_run("sha256(1)").unwrap_err().contains("expected String or Bytes, got Int"));
+}
+
+#[test]
+fn eval_hash_agrees_with_equality() {
+    let src = "type Shape = Circle Float | Square Float\n\
+               let r = [hash((1, \"a\")) == hash((1, \"a\")), hash(0.0) == hash(-0.0), \
+               hash({ x: Circle(1.0) }) == hash({ x: Circle(1.0) }), hash(Circle(1.0)) == hash(Square(1.0)), \
+               hash([1, 2]) == hash([2, 1])]\nr";
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::List(vec![
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(false)
+        ])
+    );
+    assert!(eval_run("hash(fn (x) -> x)").unwrap_err().contains("cannot hash functions"));
+}
+
+#[test]
+fn eval_uuids_are_v4_and_parse_to_canonical_form() {
+    let src = "let a = uuid_v4()\nlet b = uuid_v4()\n\
+               let r = [uuid_valid(a), a == b, str_substring(a, 14, 15) == \"4\", \
+               uuid_valid(\"not-a-uuid\"), uuid_valid(\"6ba7b810-9dad-11d1-80b4-00c04fd430c8\")]\nr";
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::List(vec![
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Bool(true)
+        ])
+    );
+
+    let parse = |text: &str| {
+        let src = format!("type Result a e = Ok a | Err e\nlet r = uuid_parse(\"{}\")\nr", text);
+        eval_run(&src).unwrap().to_string()
+    };
+    assert_eq!(
+        parse("6BA7B8109DAD11D180B400C04FD430C8"),
+        "Ok(\"6ba7b810-9dad-11d1-80b4-00c04fd430c8\")"
+    );
+    assert_eq!(
+        parse("6ba7b810-9dad11d1-80b4-00c04fd430c8a"),
+        "Err(\"'6ba7b810-9dad11d1-80b4-00c04fd430c8a' is not a UUID: expected 8-4-4-4-12 hex digits\")"
+    );
+}
+
+// ── Logging ──
+
+#[test]
+fn eval_log_lines_are_logfmt() {
+    use lyra::eval::logging::{line, Level};
+    let ms = 1_700_000_000_123;
+    assert_eq!(
+        line(Level::Info, &Value::String("server up".to_string()), ms),
+        "time=2023-11-14T22:13:20.123Z level=info msg=\"server up\""
+    );
+    let record = eval_run("{ path: \"/api\", ms: 12, note: \"said \\\"hi\\\"\" }").unwrap();
+    assert_eq!(
+        line(Level::Warn, &record, ms),
+        "time=2023-11-14T22:13:20.123Z level=warn ms=12 note=\"said \\\"hi\\\"\" path=/api"
+    );
+}
+
+#[test]
+fn eval_log_level_is_set_by_name() {
+    assert_eq!(
+        eval_run("log_set_level(\"WARN\")\nlog_level()").unwrap(),
+        Value::String("warn".to_string())
+    );
+    let err = eval_run("log_set_level(\"loud\")").unwrap_err();
+    assert!(err.contains("'loud' is not a level; use debug, info, warn, error or off"), "{}", err);
+}
+
+// ── Terminal control ──
+
+#[test]
+fn eval_term_escapes() {
+    let text = |src: &str| eval_run(src).unwrap().display_unquoted();
+    if std::env::var_os("NO_COLOR").is_none() {
+        assert_eq!(text("term_color(\"bold, bright_red bg_blue\", 42)"), "\x1b[1;91;44m42\x1b[0m");
+    }
+    assert_eq!(text("term_color(\"\", \"plain\")"), "plain");
+    assert_eq!(text("str_concat(term_clear(), term_cursor_to(3, 10))"), "\x1b[2J\x1b[H\x1b[3;10H");
+    assert_eq!(eval_run("term_size().columns > 0").unwrap(), Value::Bool(true));
+    let err = eval_run("term_color(\"purple\", \"x\")").unwrap_err();
+    assert!(err.contains("unknown color 'purple'"), "{}", err);
+    let err = eval_run("term_cursor_to(0, 1)").unwrap_err();
+    assert!(err.contains("rows and columns start at 1"), "{}", err);
+}
+
+// ── Memory stats ──
+
+#[test]
+fn eval_memory_stats_counts_what_globals_hold() {
+    let src = "let before = memory_stats().env
+               let xs = range(0, 1000)
+               let after = memory_stats().env
+               let r = (after.bindings - before.bindings, after.values - before.values >= 1000, after.bytes > before.bytes)
+               r";
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::Tuple(vec![Value::Int(2), Value::Bool(true), Value::Bool(true)])
+    );
+}
+
+// ── Command-line flags ──
+
+/// `parse_flags(spec, args)` as its `Ok` record or `Err` message.
+fn parse_flags(spec: &str, args: &[&str]) -> String {
+    let args: Vec<String> = args.iter().map(|a| format!("{:?}", a)).collect();
+    let src = format!(
+        "type Result a e = Ok a | Err e\nlet r = parse_flags({:?}, [{}])\nr",
+        spec,
+        args.join(", ")
+    );
+    eval_run(&src).unwrap().to_string()
+}
+
+#[test]
+fn eval_parse_flags_reads_options_switches_and_positionals() {
+    let spec = "-n, --count <n>  How many [default: 3]\n--dry-run  Just say\n<input>  File\n<rest>...";
+    assert_eq!(
+        parse_flags(spec, &["in.txt", "--count=5", "--dry-run", "a", "--", "-b"]),
+        "Ok({ count: \"5\", dry_run: true, help: false, input: \"in.txt\", rest: [\"a\", \"-b\"] })"
+    );
+    assert_eq!(
+        parse_flags(spec, &["-n", "7", "in.txt"]),
+        "Ok({ count: \"7\", dry_run: false, help: false, input: \"in.txt\", rest: [] })"
+    );
+    assert_eq!(parse_flags(spec, &["--count"]), "Err(\"option '--count' expects a value\")");
+    assert_eq!(parse_flags(spec, &["--dry-run=no", "x"]), "Err(\"switch '--dry-run' doesn't take a value\")");
+    assert_eq!(parse_flags(spec, &["-x"]), "Err(\"unknown option '-x'\")");
+    assert_eq!(parse_flags(spec, &[]), "Err(\"missing argument <input>\")");
+    assert_eq!(parse_flags("--out <f>", &[]), "Err(\"missing required option '--out'\")");
+    assert_eq!(parse_flags("<a>", &["x", "y"]), "Err(\"unexpected argument 'y'\")");
+    // --help leaves what's missing empty rather than failing
+    assert_eq!(
+        parse_flags("--out <f>\n<a>", &["-h"]),
+        "Ok({ a: \"\", help: true, out: \"\" })"
+    );
+}
+
+#[test]
+fn eval_parsed_flags_are_read_by_name() {
+    let run = |body: &str| {
+        eval_run(&format!(
+            "type Result a e = Ok a | Err e\n\
+             let spec = \"-n, --count <n>  How many [default: 3]\\n--dry-run  Just say\\n<input>  File\\n<rest>...\"\n\
+             let r = match parse_flags(spec, [\"in.txt\", \"--dry-run\", \"a\", \"b\"]) with\n\
+             | Ok(flags) -> {}\n\
+             | Err(message) -> message\n\
+             r",
+            body
+        ))
+    };
+    assert_eq!(
+        run("let input = flag_value(flags, \"<input>\") in \
+             let n = string_to_int(flag_value(flags, \"count\")) in \
+             let dry = if flag_set(flags, \"--dry-run\") then \"dry\" else \"wet\" in \
+             let rest = length(flag_rest(flags, \"rest\")) in \
+             \"{input} {n + 1} {dry} {rest}\"")
+        .unwrap(),
+        Value::String("in.txt 4 dry 2".to_string())
+    );
+    let err = run("flag_value(flags, \"dry_run\")").unwrap_err();
+    assert!(err.contains("'dry_run' isn't an option or argument"), "{}", err);
+    let err = run("flag_value(flags, \"verbose\")").unwrap_err();
+    assert!(err.contains("the spec has no 'verbose'"), "{}", err);
+    // A Flags isn't a record of whatever fields the program names
+    let err = run("flags.input").unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+}
+
+#[test]
+fn eval_flags_usage_lists_the_spec() {
+    let spec = "-o, --output <file>  Where to write [default: out.txt]\n--quiet  Say less\n<input>  The file to read";
+    let usage = eval_run(&format!("flags_usage(\"tool\", {:?})", spec)).unwrap().display_unquoted();
+    assert_eq!(
+        usage,
+        "Usage: tool [options] <input>\n\n\
+         Arguments:\n  <input>              The file to read\n\n\
+         Options:\n  -o, --output <file>  Where to write [default: out.txt]\n      --quiet          Say less\n  -h, --help           Show this help\n"
+    );
+    let err = eval_run("flags_usage(\"tool\", \"--quiet  Hush [default: yes]\")").unwrap_err();
+    assert!(err.contains("spec line 1: switch '--quiet' can't have a default"), "{}", err);
+    assert_eq!(eval_run("args()").unwrap(), Value::List(vec![]));
+}
+
+// ── Dates and times ──
+
+#[test]
+fn eval_datetime_formats_and_does_arithmetic() {
+    let src = "let d = { year: 2024, month: 2, day: 28, hour: 22, minute: 5, second: 9, millisecond: 42 }\n\
+               let later = datetime_add(d, duration_days(1) + duration_hours(3))\n\
+               let r = [datetime_format(\"%a %d %b %Y %H:%M:%S.%f (day %j)\", d), \
+               datetime_format(\"%Y-%m-%dT%H:%M:%S%z\", later), \
+               to_string(datetime_diff(later, d)), to_string(datetime_weekday(later)), \
+               to_string(later.day), to_string(datetime_to_millis(datetime_from_millis(-1)))]\nr";
+    assert_eq!(
+        eval_run(src).unwrap().to_string(),
+        "[\"Wed 28 Feb 2024 22:05:09.042 (day 059)\", \"2024-03-01T01:05:09+00:00\", \
+         \"97200000\", \"5\", \"1\", \"-1\"]"
+    );
+    assert_eq!(eval_run("datetime_from_millis(0).year").unwrap(), Value::Int(1970));
+    let err = eval_run("datetime_format(\"%Y\", { year: 2023, month: 2, day: 29, hour: 0, minute: 0, second: 0, millisecond: 0 })")
+        .unwrap_err();
+    assert!(err.contains("day 29 is out of range 1..28"), "{}", err);
+}
+
+#[test]
+fn eval_datetime_parse_reads_formats_and_offsets() {
+    let parse = |format: &str, text: &str| {
+        let src = format!(
+            "type Result a e = Ok a | Err e\nlet r = datetime_parse(\"{}\", \"{}\")\n\
+             match r with | Ok(d) -> datetime_format(\"%Y-%m-%d %H:%M:%S.%f\", d) | Err(e) -> e",
+            format, text
+        );
+        eval_run(&src).unwrap().display_unquoted()
+    };
+    assert_eq!(parse("%Y-%m-%dT%H:%M:%S%z", "2024-03-01T01:30:00+02:00"), "2024-02-29 23:30:00.000");
+    assert_eq!(parse("%d %B %Y %H:%M:%S.%f", "7 july 1969 20:17:40.5"), "1969-07-07 20:17:40.500");
+    assert_eq!(parse("%Y/%j", "2023/365"), "2023-12-31 00:00:00.000");
+    assert_eq!(parse("%Y-%m-%d", "2023-02-31"), "day 31 is out of range 1..28");
+    assert_eq!(parse("%a %Y-%m-%d", "Mon 2024-02-29"), "2024-02-29 is a Thursday, not a Monday");
+    assert_eq!(parse("%Y-%m-%d", "2024-1x-01"), "expected '-' at column 7");
+    assert_eq!(parse("%Y", "2024!"), "unexpected '!' at column 5");
+}
+
+// ── Config formats ──
+
+#[cfg(feature = "toml")]
+#[test]
+fn eval_toml_parse_builds_records() {
+    let src = "type Result a e = Ok a | Err e\n\
+               let r = toml_parse(\"title = \\\"demo\\\"\\n[server]\\nport = 8080\\nratio = 0.5\\ntags = [\\\"a\\\", \\\"b\\\"]\\nup = true\")\nr";
+    let server = Value::Record(
+        [
+            ("port", Value::Int(8080)),
+            ("ratio", Value::Float(0.5)),
+            ("tags", Value::List(vec![Value::String("a".into()), Value::String("b".into())])),
+            ("up", Value::Bool(true)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect(),
+    );
+    let doc = Value::Record(
+        [("server", server), ("title", Value::String("demo".into()))]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    );
+    assert_eq!(eval_run(src).unwrap(), Value::adt("Ok".to_string(), vec![doc]));
+
+    let err = eval_run("type Result a e = Ok a | Err e\nlet r = toml_parse(\"a = 1\\nb = x\")\nr").unwrap();
+    assert_eq!(
+        err,
+        Value::adt(
+            "Err".to_string(),
+            vec![Value::String("line 2, column 5: invalid string, expected `\"`, `'`".into())]
+        )
+    );
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn eval_toml_documents_are_read_by_reflection() {
+    let src = "type Result a e = Ok a | Err e\ntype Option a = Some a | None\n\
+               let port = match toml_parse(\"port = 8080\") with\n\
+               | Ok(doc) -> (match record_get(doc, \"port\") with\n\
+                 | Some(v) -> (match dynamic_int(v) with | Some(n) -> n | None -> 0)\n\
+                 | None -> 0)\n\
+               | Err(e) -> 0\n\
+               port + 1";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(8081));
+    let err = eval_run(
+        "type Result a e = Ok a | Err e\nmatch toml_parse(\"a = 1\") with | Ok(doc) -> doc.a | Err(e) -> 0",
+    )
+    .unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn eval_yaml_parse_builds_records() {
+    let src = "type Result a e = Ok a | Err e\n\
+               let r = yaml_parse(\"name: demo\\nports:\\n  - 80\\n  - 443\\nratio: 1.5\\nextra: ~\")\nr";
+    let doc = Value::Record(
+        [
+            ("extra", Value::Unit),
+            ("name", Value::String("demo".into())),
+            ("ports", Value::List(vec![Value::Int(80), Value::Int(443)])),
+            ("ratio", Value::Float(1.5)),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect(),
+    );
+    assert_eq!(eval_run(src).unwrap(), Value::adt("Ok".to_string(), vec![doc]));
+    let Value::Adt(adt) = eval_run("type Result a e = Ok a | Err e\nlet r = yaml_parse(\"a: [1\")\nr").unwrap() else {
+        panic!("expected an ADT")
+    };
+    assert_eq!(adt.constructor, "Err");
+}
+
+// ── Execution budgets ──
+
+#[test]
+fn eval_step_budget_reaches_through_callbacks() {
+    use lyra::budget::{with_budget, Budget};
+    let budget = Budget {
+        max_steps: Some(1000),
+        timeout: None,
+    };
+    let src = "sum(map(fn (x) -> x * x, range(0, 100000)))";
+    let err = with_budget(budget, || eval_run(src)).unwrap_err();
+    assert!(err.starts_with("BudgetExceeded") && err.contains("more than 1000 steps"), "{}", err);
+    assert_eq!(eval_run("sum(map(fn (x) -> x, range(0, 10)))").unwrap(), Value::Int(45));
+}
+
+#[test]
+fn eval_interrupt_stops_the_run() {
+    use lyra::budget::interruptible;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let src = "sum(map(fn (i) -> sum(map(fn (j) -> i * j, range(0, 10000))), range(0, 10000)))";
+    let setter = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        FLAG.store(true, Ordering::Relaxed);
+    });
+    let err = interruptible(&FLAG, || eval_run(src)).unwrap_err();
+    setter.join().unwrap();
+    assert!(err.starts_with("Interrupted"), "{}", err);
+    // The next run starts with the flag cleared
+    assert_eq!(interruptible(&FLAG, || eval_run("1 + 2")).unwrap(), Value::Int(3));
+}
+
+#[test]
+fn env_names_lists_own_scope() {
+    let env = Env::new();
+    env.set("b".to_string(), Value::Int(2));
+    env.set("a".to_string(), Value::Int(1));
+    let child = env.extend();
+    child.set("c".to_string(), Value::Unit);
+    assert_eq!(env.names(), vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(child.names(), vec!["c".to_string()]);
+}
+
+#[test]
+fn env_visible_names_include_parents() {
+    let env = Env::new();
+    env.set("a".to_string(), Value::Int(1));
+    let child = env.extend();
+    child.set("b".to_string(), Value::Int(2));
+    child.set("a".to_string(), Value::Int(3));
+    assert_eq!(child.visible_names(), vec!["a".to_string(), "b".to_string()]);
+}
+
+#[test]
+fn eval_undefined_variable_suggests_similar_name() {
+    // Skip the type checker, which would catch this first
+    let env = Env::new();
+    env.set("total".to_string(), Value::Int(1));
+    let Program { ast, decls } =
+        parse(tokenize("let f = fn (count) -> cout + totl\nf(1)").unwrap()).unwrap();
+    let ast = Rc::new(ast);
+    eval_decl(&env, &ast, &decls[0]).unwrap();
+    match eval_decl(&env, &ast, &decls[1]) {
+        Err(lyra::error::LyraError::UndefinedVariable { name, suggestion, .. }) => {
+            assert_eq!(name, "cout");
+            assert_eq!(suggestion.as_deref(), Some("count"));
+        }
+        other => panic!("expected UndefinedVariable, got {:?}", other),
+    }
+}
+
+#[test]
+fn eval_not_callable_names_the_callee() {
+    // Skip the type checker, which would catch this first
+    let env = Env::new();
+    let Program { ast, decls } = parse(tokenize("let n = 42\nn(1)\n\"a\" - 1").unwrap()).unwrap();
+    let ast = Rc::new(ast);
+    eval_decl(&env, &ast, &decls[0]).unwrap();
+    let err = eval_decl(&env, &ast, &decls[1]).unwrap_err();
+    assert_eq!(err.to_string(), "runtime error: 'n' is not callable: it is Int 42");
+    let err = eval_decl(&env, &ast, &decls[2]).unwrap_err();
+    assert_eq!(err.to_string(), "runtime error: cannot subtract Int 1 from String \"a\"");
+}
+
+#[test]
+fn eval_operand_errors_read_as_the_vm_s() {
+    // The same text as vm_operand_errors_show_the_values
+    let env = Env::new();
+    let source = "let x = !\"a\"\n1 + true\n[1] - 1";
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let ast = Rc::new(ast);
+    let errs: Vec<String> =
+        decls.iter().map(|d| eval_decl(&env, &ast, d).unwrap_err().to_string()).collect();
+    assert_eq!(errs, [
+        "runtime error: cannot apply ! to String \"a\"",
+        "runtime error: cannot add Int 1 and Bool true",
+        "runtime error: cannot subtract Int 1 from List [1]",
+    ]);
+}
+
+#[test]
+fn eval_unapplied_constructors_are_functions() {
+    let src = "type Opt a = Some a | None\n\
+               let names = (type_of(Some), type_of(Opt.Some), type_of(None), map(Some, [1, 2]))\nnames";
+    let s = |v: &str| Value::String(v.to_string());
+    let some = |n| Value::adt("Some".to_string(), vec![Value::Int(n)]);
+    assert_eq!(
+        eval_run(src).unwrap(),
+        Value::Tuple(vec![s("Function"), s("Function"), s("None"), Value::List(vec![some(1), some(2)])])
+    );
+}
+
+#[test]
+fn eval_qualified_constructors() {
+    let src = "type Tree = Leaf | Node Tree Int Tree\ntype Heap = Empty | Node Int\n\
+               let t = Tree.Node(Tree.Leaf, 4, Leaf)\n\
+               match t with | Tree.Node(_, v, _) -> v | Leaf -> 0";
+    assert_eq!(eval_run(src).unwrap(), Value::Int(4));
+}
+
+const NEGATIVE_PATTERNS: &str =
+    "let sign = fn (n) -> match n with | -1 -> \"minus\" | 0 -> \"zero\" | _ -> \"other\"\n\
+     let half = fn (x) -> match x with | -0.5 -> true | _ -> false\n\
+     let first = fn (p) -> match p with | (-2, y) -> y | _ -> 0\n\
+     let result = (sign(-1), sign(0), sign(1), half(-0.5), half(0.5), first((-2, 7)))\n\
+     result";
+
+#[test]
+fn eval_match_negative_literals() {
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(
+        eval_run(NEGATIVE_PATTERNS).unwrap(),
+        Value::Tuple(vec![
+            s("minus"),
+            s("zero"),
+            s("other"),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Int(7),
+        ])
+    );
+}
+
+const STRING_PATTERNS: &str =
+    "let route = fn (req) -> match req with | \"GET \" ++ path -> path \
+     | \"POST \" ++ _ -> \"post\" | _ -> \"other\"\n\
+     let tag = fn (s) -> match s with | \"<\" ++ name ++ \">\" -> name | _ -> \"?\"\n\
+     let stem = fn (f) -> match f with | base ++ \".txt\" -> base | _ -> \"\"\n\
+     [route(\"GET /index\"), route(\"POST /x\"), route(\"PUT\"), tag(\"<b>\"), tag(\"<\"), \
+     stem(\"naïve.txt\"), stem(\"a.md\")]";
+
+#[test]
+fn eval_match_string_prefix_and_suffix() {
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(
+        eval_run(STRING_PATTERNS).unwrap(),
+        Value::List(vec![
+            s("/index"),
+            s("post"),
+            s("other"),
+            s("b"),
+            s("?"),
+            s("naïve"),
+            s(""),
+        ])
+    );
+}
+
+#[test]
+fn eval_match_failure_shows_the_value() {
+    let err = eval_run("type Option = Some Int | None\nmatch Some(3) with | None -> 0 | Some(1) -> 1")
+        .unwrap_err();
+    assert!(err.contains("MatchFailure"), "{}", err);
+    assert!(err.contains("value: \"Some(3)\""), "{}", err);
+    assert!(err.contains("patterns: [\"None\", \"Some(1)\"]"), "{}", err);
+}
+
+// ── Variable resolution ──
+
+/// Every variable in `expr`, in order: a resolved one as `name@depth.slot`.
+fn variables(ast: &lyra::ast::Ast, expr: lyra::ast::ExprId) -> Vec<String> {
+    use lyra::ast::visit::{walk_expr, Visitor};
+    use lyra::ast::{Ast, Expr, ExprId};
+
+    struct Collect(Vec<String>);
+    impl Visitor for Collect {
+        fn visit_expr(&mut self, ast: &Ast, expr: ExprId) {
+            match &ast[expr].node {
+                Expr::Local { name, depth, slot } => {
+                    self.0.push(format!("{}@{}.{}", name, depth, slot))
+                }
+                Expr::Var(name) => self.0.push(name.clone()),
+                _ => walk_expr(self, ast, expr),
+            }
+        }
+    }
+    let mut collect = Collect(Vec::new());
+    collect.visit_expr(ast, expr);
+    collect.0
+}
+
+#[test]
+fn resolve_places_locals_by_scope_and_slot() {
+    use lyra::ast::Decl;
+    use lyra::eval::resolve::resolve_decl;
+
+    let src = "let rec f = fn (a, b) -> let c = a in match (b, c) with | (x, y) -> f(x, y) + g";
+    let Program { mut ast, decls } = parse(tokenize(src).unwrap()).unwrap();
+    resolve_decl(&mut ast, &decls[0]);
+    let Decl::Let { body, .. } = &decls[0] else {
+        panic!("expected a let");
+    };
+    // The call's scope holds `a`, `b` and then `f`; the global `g` keeps its name
+    assert_eq!(
+        variables(&ast, *body),
+        ["a@0.0", "b@1.1", "c@0.0", "f@2.2", "x@0.0", "y@0.1", "g"]
+    );
+}
+
+#[test]
+fn resolved_locals_follow_shadowing_and_closures() {
+    let src = "let x = 1\n\
+               let f = fn (x) -> let x = x + 10 in fn (y) -> x + y\n\
+               let rec count = fn (n, count) -> if n == 0 then count else count + n\n\
+               let g = fn (n) ->\n\
+               let rec go = fn (i, acc) -> if i > n then acc else go(i + 1, acc + i) in go(1, 0)\n\
+               f(5)(100) + x + count(3, 4) + g(4)";
+    // 115 + 1 + 7 + 10: a parameter named like its function shadows it
+    assert_eq!(eval_run(src).unwrap(), Value::Int(133));
+}
+
+#[test]
+fn resolved_locals_reach_unquotes_and_recursive_values() {
+    let src = "let pick = fn (flag) ->\n\
+               let rec go =\n\
+               if flag then fn (n) -> if n == 0 then 0 else go(n - 1) else fn (n) -> n in\n\
+               go(3)\n\
+               let spliced = fn (n) -> quote (1 + unquote(n))\n\
+               let result = (pick(true), pick(false), adt_fields(spliced(2)))\n\
+               result";
+    let value = eval_run(src).unwrap();
+    assert_eq!(value.to_string(), "(0, 3, [\"+\", IntLit(1), IntLit(2)])");
+}
+
+// ── Host bindings ──
+
+#[test]
+fn eval_extern_binds_the_host_value() {
+    use lyra::{run_file_with, RunOptions};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    static SEEN: AtomicI64 = AtomicI64::new(0);
+    let host = |name: &str, func: fn(Vec<Value>) -> Result<Value, String>| {
+        (name.to_string(), Value::builtin(name, 1, func))
+    };
+    let options = RunOptions {
+        host: vec![
+            host("double", |args| match &args[0] {
+                Value::Int(n) => Ok(Value::Int(n * 2)),
+                other => Err(format!("expected Int, got {}", other.type_name())),
+            }),
+            host("record", |args| {
+                if let Value::Int(n) = args[0] {
+                    SEEN.store(n, Ordering::Relaxed);
+                }
+                Ok(Value::Unit)
+            }),
+        ],
+        ..RunOptions::default()
+    };
+    let src = "extern double : Int -> Int\nextern record : Int -> ()\nrecord(double(21))";
+    run_file_with(src, "host.lyra", &options).unwrap();
+    assert_eq!(SEEN.load(Ordering::Relaxed), 42);
+
+    // Missing or mismatched host values stop the program before it starts
+    let missing = run_file_with("println(1)\nextern load : String -> Int", "host.lyra", &RunOptions::default());
+    assert!(matches!(missing, Err(lyra::error::LyraError::UnboundExtern { .. })), "{:?}", missing);
+    let err = run_file_with("extern double : Int -> Int -> Int", "host.lyra", &options).unwrap_err();
+    let err = format!("{:?}", err);
+    assert!(err.contains("declared to take 2 arguments, but the host's takes 1 argument"), "{}", err);
+}
+
+// ── Instrumentation hooks ──
+
+/// The calls and returns `src` makes under `run_file_with`, and the first
+/// error if a hook stops it after `max_ops` steps.
+fn eval_traced(src: &str, max_ops: usize) -> (Vec<String>, Option<String>) {
+    use lyra::hooks::Hooks;
+    use lyra::{run_file_with, RunOptions};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let ops = Rc::new(Cell::new(0));
+    let (calls, returns) = (events.clone(), events.clone());
+    let hooks = Hooks::new()
+        .on_call(move |name, args| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            calls.borrow_mut().push(format!("call {}({})", name, args.join(", ")));
+        })
+        .on_return(move |value| returns.borrow_mut().push(format!("return {}", value)))
+        .on_op(move |_| {
+            ops.set(ops.get() + 1);
+            match ops.get() > max_ops {
+                true => Err("out of host budget".to_string()),
+                false => Ok(()),
+            }
+        });
+    let options = RunOptions { hooks, ..RunOptions::default() };
+    let err = run_file_with(src, "hooks.lyra", &options).err().map(|e| format!("{:?}", e));
+    let events = events.borrow().clone();
+    (events, err)
+}
+
+#[test]
+fn eval_hooks_report_calls_returns_and_steps() {
+    let src = "let rec fact = fn (n) -> if n <= 1 then 1 else n * fact(n - 1)\nfact(3)";
+    let (events, err) = eval_traced(src, usize::MAX);
+    assert_eq!(err, None);
+    assert_eq!(
+        events,
+        ["call fact(3)", "call fact(2)", "call fact(1)", "return 1", "return 2", "return 6"]
+    );
+
+    // Tail calls return once per call, like any other
+    let src = "let rec count = fn (n) -> if n == 0 then 0 else count(n - 1)\ncount(2)";
+    let (events, _) = eval_traced(src, usize::MAX);
+    assert_eq!(events.iter().filter(|e| e.starts_with("call")).count(), 3, "{:?}", events);
+    assert_eq!(events.iter().filter(|e| e.starts_with("return")).count(), 3, "{:?}", events);
+
+    let src = "let rec spin = fn (n) -> spin(n + 1)\nspin(0)";
+    let (_, err) = eval_traced(src, 100);
+    assert!(err.unwrap().contains("out of host budget"));
+}
+
+// ── Value layout ──
+
+#[test]
+fn value_fits_in_four_words() {
+    assert!(std::mem::size_of::<Value>() <= 32, "{}", std::mem::size_of::<Value>());
+}
@@ -0,0 +1,157 @@
+//! Fuzz-style tests: the lexer and parser must turn any input into tokens,
+//! a program, or a `LyraError`, never a panic.
+
+use lyra::lexer::tokenize;
+use lyra::parser::parse;
+
+/// Fragments that exercise every token kind plus a few broken ones.
+const FRAGMENTS: &[&str] = &[
+    "let",
+    "rec",
+    "in",
+    "fn",
+    "match",
+    "with",
+    "if",
+    "then",
+    "else",
+    "type",
+    "import",
+    "prop",
+    "true",
+    "x",
+    "Some",
+    "_",
+    "42",
+    "3.14",
+    "99999999999999999999",
+    "\"s\"",
+    "\"a{x}b\"",
+    "\"{",
+    "\"",
+    "\\",
+    "{",
+    "}",
+    "(",
+    ")",
+    "[",
+    "]",
+    ",",
+    ".",
+    ":",
+    "::",
+    "=",
+    "==",
+    "!=",
+    "<",
+    "<=",
+    ">",
+    ">=",
+    "+",
+    "-",
+    "*",
+    "**",
+    "/",
+    "%",
+    "&&",
+    "||",
+    "!",
+    "->",
+    "|>",
+    "|",
+    "@",
+    "--- doc",
+    "-- c",
+    "\n",
+    " ",
+    "é",
+    "\u{0}",
+];
+
+/// Small xorshift generator so failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+fn front_end(source: &str) {
+    let result = std::panic::catch_unwind(|| {
+        if let Ok(tokens) = tokenize(source) {
+            let _ = parse(tokens);
+        }
+    });
+    assert!(result.is_ok(), "front end panicked on {:?}", source);
+}
+
+#[test]
+fn fuzz_fragment_soup() {
+    let mut rng = Rng(0x1dea);
+    for _ in 0..5000 {
+        let len = 1 + rng.next(24);
+        let source: String = (0..len)
+            .map(|_| FRAGMENTS[rng.next(FRAGMENTS.len())])
+            .collect::<Vec<_>>()
+            .join(" ");
+        front_end(&source);
+    }
+}
+
+#[test]
+fn fuzz_truncated_programs() {
+    let program = std::fs::read_to_string(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples/showcase.lyra"
+    ))
+    .unwrap();
+    let chars: Vec<char> = program.chars().collect();
+    for end in 0..chars.len() {
+        front_end(&chars[..end].iter().collect::<String>());
+    }
+}
+
+#[test]
+fn fuzz_edge_inputs() {
+    for source in [
+        "",
+        " ",
+        "\n",
+        "---",
+        "--- only a doc comment",
+        "\"",
+        "\"{",
+        "\"{}\"",
+        "prop",
+        "prop \"p\"",
+        "type",
+        "type T =",
+        "let",
+        "let x",
+        "match",
+        "(",
+        ")",
+        "[1,",
+        "fn",
+        "fn (",
+        "x.",
+        "1.",
+        "_",
+        "_(_, _)",
+    ] {
+        front_end(source);
+    }
+}
+
+#[test]
+fn parse_tokens_without_eof() {
+    // Token streams built by hand may lack the trailing Eof
+    assert!(parse(Vec::new()).is_ok());
+    let mut tokens = tokenize("let x = 1 +").unwrap();
+    tokens.pop();
+    assert!(parse(tokens).is_err());
+}
@@ -0,0 +1,31 @@
+use lyra::highlight::{render_ansi, render_html, render_html_fragment};
+
+#[test]
+fn highlight_ansi_colors_classified_tokens_only() {
+    let out = render_ansi("let n = 1 + 2 -- sum");
+    assert_eq!(
+        out,
+        "\x1b[1;34mlet\x1b[0m n = \x1b[96m1\x1b[0m + \x1b[96m2\x1b[0m \x1b[90m-- sum\x1b[0m"
+    );
+}
+
+#[test]
+fn highlight_html_escapes_and_classifies() {
+    let out = render_html_fragment("let f = fn (g) -> g(\"<a>\")\nf(Some)");
+    assert_eq!(
+        out,
+        "<pre class=\"lyra\"><code><span class=\"kw\">let</span> <span class=\"fn\">f</span> = \
+         <span class=\"kw\">fn</span> (<span class=\"fn\">g</span>) -&gt; <span class=\"fn\">g</span>(\
+         <span class=\"str\">&quot;&lt;a&gt;&quot;</span>)\n<span class=\"fn\">f</span>(<span class=\"ctor\">Some</span>)\
+         </code></pre>"
+    );
+    let page = render_html("1", "a<b>.lyra");
+    assert!(page.starts_with("<!DOCTYPE html>"));
+    assert!(page.contains("<title>a&lt;b&gt;.lyra</title>"));
+    assert!(page.contains(".lyra .kw {"));
+}
+
+#[test]
+fn highlight_leaves_unlexable_source_plain() {
+    assert_eq!(render_ansi("let s = \"open"), "let s = \"open");
+}
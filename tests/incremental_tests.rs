@@ -0,0 +1,107 @@
+use lyra::incremental::Checker;
+
+const MAIN: &str = "/virtual/main.lyra";
+const UTIL: &str = "/virtual/util.lyra";
+
+fn checker(util: &str, main: &str) -> Checker {
+    let mut checker = Checker::new();
+    checker.set_source(UTIL, util.to_string());
+    checker.set_source(MAIN, main.to_string());
+    checker
+}
+
+// ── Caching ──
+
+#[test]
+fn incremental_reuses_unchanged_files() {
+    let mut c = checker("let sq = fn (x) -> x * x", "import \"util\"\nlet y = sq(3)");
+    let first = c.check(MAIN).unwrap();
+    assert_eq!(first.checked, vec![UTIL, MAIN]);
+    assert!(first.reused.is_empty());
+
+    let second = c.check(MAIN).unwrap();
+    assert!(second.checked.is_empty());
+    assert_eq!(second.reused, vec![UTIL, MAIN]);
+}
+
+#[test]
+fn incremental_rechecks_only_the_edited_file() {
+    let mut c = checker("let sq = fn (x) -> x * x", "import \"util\"\nlet y = sq(3)");
+    c.check(MAIN).unwrap();
+
+    c.set_source(MAIN, "import \"util\"\nlet y = sq(4)".to_string());
+    let report = c.check(MAIN).unwrap();
+    assert_eq!(report.checked, vec![MAIN]);
+    assert_eq!(report.reused, vec![UTIL]);
+}
+
+#[test]
+fn incremental_skips_dependents_when_interface_is_unchanged() {
+    let mut c = checker("let sq = fn (x) -> x * x", "import \"util\"\nlet y = sq(3)");
+    c.check(MAIN).unwrap();
+
+    c.set_source(UTIL, "let sq = fn (n) -> n * n + 0".to_string());
+    let report = c.check(MAIN).unwrap();
+    assert_eq!(report.checked, vec![UTIL]);
+    assert_eq!(report.reused, vec![MAIN]);
+}
+
+// ── Invalidation ──
+
+#[test]
+fn incremental_rechecks_dependents_when_interface_changes() {
+    let mut c = checker("let sq = fn (x) -> x * x", "import \"util\"\nlet y = sq(3) + 1");
+    c.check(MAIN).unwrap();
+
+    c.set_source(UTIL, "let sq = fn (x) -> to_string(x)".to_string());
+    let err = c.check(MAIN).unwrap_err();
+    assert_eq!(err.path, MAIN);
+
+    // Fixing the dependent makes the next check succeed
+    c.set_source(MAIN, "import \"util\"\nlet y = sq(3)".to_string());
+    let report = c.check(MAIN).unwrap();
+    assert_eq!(report.checked, vec![MAIN]);
+    assert_eq!(c.bindings(MAIN).unwrap()[0].1.to_string(), "String");
+}
+
+#[test]
+fn incremental_shares_types_across_modules() {
+    let mut c = checker(
+        "type Shape = Circle Int | Square Int",
+        "import \"util\"\nlet area = fn (s) -> match s with | Circle(r) -> 3 * r * r | Square(w) -> w * w",
+    );
+    c.check(MAIN).unwrap();
+    assert_eq!(c.bindings(MAIN).unwrap()[0].1.to_string(), "Shape -> Int");
+}
+
+#[test]
+fn incremental_reports_missing_files() {
+    let mut c = Checker::new();
+    c.set_source(MAIN, "import \"missing\"".to_string());
+    let err = c.check(MAIN).unwrap_err();
+    assert_eq!(err.path, "/virtual/missing.lyra");
+}
+
+#[test]
+fn incremental_rechecks_when_a_module_changes() {
+    let dir = std::env::temp_dir().join("lyra_test_incremental_modules");
+    std::fs::create_dir_all(&dir).unwrap();
+    let module = dir.join("m.lyra");
+    let main = dir.join("main.lyra");
+    let main = main.to_str().unwrap();
+    std::fs::write(&module, "let sq = fn (x) -> x * x").unwrap();
+
+    let mut c = Checker::new();
+    c.set_source(main, "let m = import \"m\"\nlet y = m.sq(3)".to_string());
+    c.check(main).unwrap();
+    let bindings: Vec<_> =
+        c.bindings(main).unwrap().iter().map(|(n, s)| format!("{} : {}", n, s.normalized())).collect();
+    assert_eq!(bindings, ["m : forall t0. Num t0 => { sq: t0 -> t0 }", "y : Int"]);
+    assert_eq!(c.check(main).unwrap().reused, vec![main]);
+
+    std::fs::write(&module, "let sq = fn (x) -> to_string(x)").unwrap();
+    let report = c.check(main).unwrap();
+    assert_eq!(report.checked, vec![main]);
+    assert_eq!(c.bindings(main).unwrap()[1].1.to_string(), "String");
+    std::fs::remove_dir_all(&dir).ok();
+}
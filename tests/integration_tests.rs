@@ -1,4 +1,4 @@
-/// Integration tests that run full Lyra programs end-to-end via both backends.
+//! Integration tests that run full Lyra programs end-to-end via both backends.
 
 use std::process::Command;
 
@@ -32,6 +32,18 @@ fn example_path(name: &str) -> String {
     format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), name)
 }
 
+#[test]
+fn help_shows_every_subcommand() {
+    let output = Command::new(lyra_bin()).arg("--help").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success());
+    for command in [
+        "repl", "doc", "types", "highlight", "lint", "test", "bench", "selftest", "build", "cov", "emit-js",
+    ] {
+        assert!(stdout.contains(&format!("lyra {} ", command)), "no {} in {}", command, stdout);
+    }
+}
+
 // ── Showcase example ──
 
 #[test]
@@ -128,6 +140,199 @@ fn modules_vm() {
     }
 }
 
+#[test]
+fn module_values_both_backends() {
+    let path = example_path("modules/values.lyra");
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(&path, vm);
+        assert!(success, "modules/values.lyra failed (vm: {}):\n{}", vm, stderr);
+        assert_eq!(stdout, "5! = 120\nsquare(7) = 49\n");
+    }
+}
+
+#[test]
+fn modules_see_only_the_prelude_and_themselves() {
+    let dir = std::env::temp_dir().join("lyra_test_module_scope");
+    std::fs::create_dir_all(&dir).unwrap();
+    let module = dir.join("m.lyra");
+    let main = dir.join("main.lyra");
+    let main = main.to_str().unwrap();
+
+    std::fs::write(&module, "let use = fn () -> helper()\n").unwrap();
+    std::fs::write(main, "let helper = fn () -> 202\nlet m = import \"m\"\nprintln(m.use())\n").unwrap();
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(main, vm);
+        assert!(!success && stdout.is_empty(), "vm: {}: {}", vm, stdout);
+        assert!(stderr.contains("undefined variable 'helper'"), "vm: {}: {}", vm, stderr);
+    }
+
+    // Run once, before the program, however many times it's imported
+    std::fs::write(&module, "println(\"loaded\")\nlet helper = fn () -> 1\nlet use = fn () -> helper()\n")
+        .unwrap();
+    std::fs::write(
+        main,
+        "println(\"main\")\nlet f = fn (helper) -> (import \"m\").use()\nlet m = import \"m\"\nprintln(f(5) + m.use())\n",
+    )
+    .unwrap();
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(main, vm);
+        assert!(success, "vm: {}: {}", vm, stderr);
+        assert_eq!(stdout, "loaded\nmain\n2\n", "vm: {}", vm);
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn sandbox_denies_imports() {
+    let paths = [example_path("modules/main.lyra"), example_path("modules/values.lyra")];
+    for (path, vm) in paths.iter().flat_map(|p| [(p, false), (p, true)]) {
+        let mut cmd = Command::new(lyra_bin());
+        cmd.args([path.as_str(), "--sandbox"]);
+        if vm {
+            cmd.arg("--vm");
+        }
+        let output = cmd.output().expect("failed to run lyra");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(stderr.contains("disabled in sandbox mode"), "stderr: {}", stderr);
+    }
+}
+
+#[test]
+fn sandbox_runs_self_contained_programs() {
+    let mut cmd = Command::new(lyra_bin());
+    cmd.args([example_path("hello.lyra").as_str(), "--sandbox"]);
+    let output = cmd.output().expect("failed to run lyra");
+    assert!(output.status.success());
+}
+
+// ── Program arguments ──
+
+#[test]
+fn program_arguments_reach_parse_flags() {
+    let path = example_path("flags.lyra");
+    for vm in [false, true] {
+        let mut cmd = Command::new(lyra_bin());
+        if vm {
+            cmd.arg("--vm");
+        }
+        // lyra's own flags after the file apply to lyra, up to a `--`
+        cmd.args([path.as_str(), "notes.txt", "--", "-v", "--", "--vm", "b"]);
+        let output = cmd.output().expect("failed to run lyra");
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(
+            stdout,
+            "reading notes.txt into out.txt, verbose: true, rest: [\"--vm\", \"b\"]\n"
+        );
+    }
+
+    let output = Command::new(lyra_bin())
+        .args([path.as_str(), "--help"])
+        .output()
+        .expect("failed to run lyra");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Usage: flags.lyra [options] <input> [<rest>...]"), "{}", stdout);
+}
+
+// ── Terminal control ──
+
+#[test]
+fn term_read_key_names_keys_from_piped_input() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = std::env::temp_dir().join("lyra_test_keys");
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("keys.lyra");
+    std::fs::write(
+        &program,
+        "let rec loop = fn (keys) -> match term_read_key() with\n\
+         | \"\" -> keys\n\
+         | key -> loop(append(keys, [key]))\n\
+         println(loop([]))",
+    )
+    .unwrap();
+    for vm in [false, true] {
+        let mut cmd = Command::new(lyra_bin());
+        cmd.arg(&program).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+        if vm {
+            cmd.arg("--vm");
+        }
+        let mut child = cmd.spawn().expect("failed to run lyra");
+        child.stdin.take().unwrap().write_all("aé\r\x1b[A\x1b[3~\x03\x1b".as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "[\"a\", \"é\", \"enter\", \"up\", \"delete\", \"ctrl+c\", \"escape\"]\n"
+        );
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── Logging ──
+
+#[test]
+fn lyra_log_sets_the_starting_log_level() {
+    let dir = std::env::temp_dir().join("lyra_test_logging");
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("log.lyra");
+    std::fs::write(&program, "log_info(\"skipped\")\nlog_warn({ disk: 91 })\nprintln(\"done\")").unwrap();
+    for vm in [false, true] {
+        let mut cmd = Command::new(lyra_bin());
+        cmd.env("LYRA_LOG", "warn").arg(&program);
+        if vm {
+            cmd.arg("--vm");
+        }
+        let output = cmd.output().expect("failed to run lyra");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "done\n");
+        assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+        assert!(stderr.starts_with("time=") && stderr.ends_with(" level=warn disk=91\n"), "{}", stderr);
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── Prelude selection ──
+
+#[test]
+fn no_prelude_leaves_builtins_undefined() {
+    for vm in [false, true] {
+        let mut cmd = Command::new(lyra_bin());
+        cmd.args([example_path("hello.lyra").as_str(), "--no-prelude"]);
+        if vm {
+            cmd.arg("--vm");
+        }
+        let output = cmd.output().expect("failed to run lyra");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success());
+        assert!(stderr.contains("undefined variable 'println'"), "stderr: {}", stderr);
+    }
+}
+
+#[test]
+fn prelude_registers_only_the_named_builtins() {
+    let path = example_path("hello.lyra");
+    for vm in [false, true] {
+        let mut cmd = Command::new(lyra_bin());
+        cmd.args([path.as_str(), "--prelude", "println,to_string"]);
+        if vm {
+            cmd.arg("--vm");
+        }
+        let output = cmd.output().expect("failed to run lyra");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "stderr: {}", stderr);
+    }
+    let output = Command::new(lyra_bin())
+        .args([path.as_str(), "--prelude", "printn"])
+        .output()
+        .expect("failed to run lyra");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("unknown builtin 'printn' in prelude"), "stderr: {}", stderr);
+}
+
 // ── VM benchmark example ──
 
 #[test]
@@ -140,6 +345,18 @@ fn vm_bench_runs() {
     }
 }
 
+#[test]
+fn vm_deeply_nested_callbacks_report_stack_overflow() {
+    let dir = std::env::temp_dir().join("lyra_test_nested_callbacks");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("deep.lyra");
+    std::fs::write(&path, "let rec f = fn (n) -> sum(map(fn (x) -> f(x + 1), [n]))\nprintln(f(0))\n").unwrap();
+    let (_, stderr, success) = run_lyra(path.to_str().unwrap(), true);
+    assert!(!success);
+    assert!(stderr.contains("runtime error\x1b[0m: stack overflow"), "{}", stderr);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
 // ── Error cases ──
 
 #[test]
@@ -154,6 +371,44 @@ fn type_error_shown() {
     std::fs::remove_file(&path).ok();
 }
 
+#[test]
+fn errors_are_printed_once() {
+    let dir = std::env::temp_dir();
+    let path = dir.join("lyra_test_errors_once.lyra");
+    for src in ["let x = (1\n", "println(1 / 0)\n"] {
+        std::fs::write(&path, src).unwrap();
+        for vm in [false, true] {
+            let (_, stderr, success) = run_lyra(path.to_str().unwrap(), vm);
+            assert!(!success);
+            assert_eq!(stderr.matches("-->").count(), 1, "{}", stderr);
+        }
+    }
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn type_errors_are_all_reported_in_one_run() {
+    let dir = std::env::temp_dir();
+    let path = dir.join("lyra_test_type_errors.lyra");
+    std::fs::write(
+        &path,
+        "println(\"before\")\nlet x = 1 + \"a\"\nprintln(x + 1)\nlet n : Int = true\nprintln(\"after\")\n",
+    )
+    .unwrap();
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(path.to_str().unwrap(), vm);
+        assert!(!success);
+        // Each error is printed once
+        assert_eq!(stderr.matches("type error").count(), 2, "{}", stderr);
+        assert!(stderr.contains(":2:9"), "{}", stderr);
+        assert!(stderr.contains(":4:15"), "{}", stderr);
+        // Uses of x aren't errors of their own, and nothing after an error runs
+        assert!(!stderr.contains(":3:"), "{}", stderr);
+        assert!(!stdout.contains("after"), "{}", stdout);
+    }
+    std::fs::remove_file(&path).ok();
+}
+
 #[test]
 fn undefined_variable_suggests() {
     let dir = std::env::temp_dir();
@@ -165,6 +420,54 @@ fn undefined_variable_suggests() {
     std::fs::remove_file(&path).ok();
 }
 
+#[test]
+fn println_joins_arguments_with_spaces() {
+    let path = std::env::temp_dir().join("lyra_test_println_variadic.lyra");
+    std::fs::write(&path, "let x = 42\nprintln(\"x =\", x, [1, 2], true)\nprint(\"a\", 1)").unwrap();
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(path.to_str().unwrap(), vm);
+        assert!(success, "stderr: {}", stderr);
+        assert_eq!(stdout, "x = 42 [1, 2] true\na 1");
+    }
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn println_without_arguments_prints_a_newline() {
+    let path = std::env::temp_dir().join("lyra_test_println_empty.lyra");
+    // Only a call by the builtin's own name runs it; `p()` leaves `p` as it is
+    std::fs::write(&path, "print()\nprintln()\nlet p = println\nlet q = p()\nq(\"x\")\nprintln()").unwrap();
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(path.to_str().unwrap(), vm);
+        assert!(success, "stderr: {}", stderr);
+        assert_eq!(stdout, "\nx\n\n");
+    }
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn labeled_arguments_are_evaluated_in_source_order() {
+    let path = std::env::temp_dir().join("lyra_test_labeled_order.lyra");
+    // Inlined, called and tail-called, each with its arguments out of order
+    std::fs::write(
+        &path,
+        "let note = fn (s, n) -> let shown = println(s) in n\n\
+         let sub = fn (a, b) -> a - b\n\
+         println(sub(b: note(\"b\", 1), a: note(\"a\", 10)))\n\
+         let rec count = fn (from, to) -> if from >= to then 0 else 1 + count(from + 1, to)\n\
+         println(count(to: note(\"to\", 5), from: note(\"from\", 2)))\n\
+         let f = fn (x) -> count(to: note(\"to\", x), from: note(\"from\", 0))\n\
+         println(f(3))\n",
+    )
+    .unwrap();
+    for vm in [false, true] {
+        let (stdout, stderr, success) = run_lyra(path.to_str().unwrap(), vm);
+        assert!(success, "stderr: {}", stderr);
+        assert_eq!(stdout, "b\na\n9\nto\nfrom\n3\nto\nfrom\n3\n");
+    }
+    std::fs::remove_file(&path).ok();
+}
+
 // ── Both backends agree on output ──
 
 #[test]
@@ -190,3 +493,514 @@ fn both_backends_agree_on_records() {
     assert!(success_tw && success_vm, "both backends should succeed");
     assert_eq!(stdout_tw, stdout_vm, "tree-walker and VM should produce identical output");
 }
+
+// ── Snapshot tests ──
+
+fn run_snapshot(args: &[&str]) -> (String, bool) {
+    let bin = lyra_bin();
+    let output = Command::new(&bin)
+        .args(["test", "--snapshot"])
+        .args(args)
+        .output()
+        .expect("failed to run lyra");
+    (String::from_utf8_lossy(&output.stdout).to_string(), output.status.success())
+}
+
+#[test]
+fn examples_match_expected_output() {
+    for name in ["adt", "factorial", "hello", "interpolation", "pipes", "records", "showcase"] {
+        let path = example_path(&format!("{}.lyra", name));
+        for vm in [false, true] {
+            let args: Vec<&str> = if vm { vec!["--vm", &path] } else { vec![&path] };
+            let (stdout, success) = run_snapshot(&args);
+            assert!(success, "{} (vm: {}) does not match its snapshot:\n{}", name, vm, stdout);
+        }
+    }
+}
+
+#[test]
+fn snapshot_update_then_detect_change() {
+    let dir = std::env::temp_dir().join("lyra_test_snapshots");
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("greet.lyra");
+    let expected = dir.join("greet.expected");
+    std::fs::write(&program, "println(\"hi\")\nprintln(1 + 1)").unwrap();
+    std::fs::remove_file(&expected).ok();
+    let dir_arg = dir.to_str().unwrap();
+
+    let (stdout, success) = run_snapshot(&[dir_arg]);
+    assert!(!success && stdout.contains("--update"), "missing snapshot should fail: {}", stdout);
+
+    let (_, success) = run_snapshot(&["--update", dir_arg]);
+    assert!(success);
+    assert_eq!(std::fs::read_to_string(&expected).unwrap(), "hi\n2\n");
+
+    std::fs::write(&program, "println(\"hi\")\nprintln(1 + 2)").unwrap();
+    let (stdout, success) = run_snapshot(&[dir_arg]);
+    assert!(!success);
+    assert!(stdout.contains("- 2") && stdout.contains("+ 3"), "diff missing: {}", stdout);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+fn run_cov(args: &[&str]) -> (String, bool) {
+    let output = Command::new(lyra_bin())
+        .arg("cov")
+        .args(args)
+        .output()
+        .expect("failed to run lyra cov");
+    (String::from_utf8_lossy(&output.stdout).to_string(), output.status.success())
+}
+
+#[test]
+fn cov_reports_unrun_lines_on_both_backends() {
+    let dir = std::env::temp_dir().join("lyra_test_cov");
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("sign.lyra");
+    let lcov = dir.join("sign.info");
+    std::fs::write(
+        &program,
+        "let sign = fn(n) ->\n  if n > 0 then\n    1\n  else\n    0 - 1\n\nprintln(sign(5))\n",
+    )
+    .unwrap();
+    let program_arg = program.to_str().unwrap();
+
+    for vm in [false, true] {
+        let mut args = vec!["--lcov", lcov.to_str().unwrap(), program_arg];
+        if vm {
+            args.insert(0, "--vm");
+        }
+        let (stdout, success) = run_cov(&args);
+        assert!(success, "lyra cov failed: {}", stdout);
+        assert!(stdout.starts_with("1\n"), "program output missing: {}", stdout);
+        assert!(stdout.contains("sign.lyra: 4/5 lines (80.0%)"), "{}", stdout);
+        assert!(stdout.contains("not run: 5"), "{}", stdout);
+
+        let info = std::fs::read_to_string(&lcov).unwrap();
+        assert!(info.contains(&format!("SF:{}\n", program_arg)), "{}", info);
+        assert!(info.contains("DA:3,1\nDA:5,0\n"), "{}", info);
+        assert!(info.contains("LF:5\nLH:4\nend_of_record"), "{}", info);
+    }
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn cov_measures_props_under_test() {
+    let dir = std::env::temp_dir().join("lyra_test_cov_props");
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("abs.lyra");
+    std::fs::write(
+        &program,
+        "let abs = fn(n) ->\n  if n < 0 then\n    0 - n\n  else\n    n\n\n\
+         prop \"abs is non-negative\" (n : Int) -> abs(n) >= 0\n\
+         println(\"not run by lyra test\")\n",
+    )
+    .unwrap();
+
+    let (stdout, success) = run_cov(&["--test", program.to_str().unwrap()]);
+    assert!(success, "{}", stdout);
+    assert!(stdout.contains("prop \"abs is non-negative\" ... ok"), "{}", stdout);
+    assert!(stdout.contains("abs.lyra: 5/6 lines"), "{}", stdout);
+    assert!(stdout.contains("not run: 8"), "{}", stdout);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn build_makes_standalone_executables() {
+    let dir = std::env::temp_dir().join("lyra_test_build");
+    std::fs::create_dir_all(&dir).unwrap();
+    let greeting = "let greet = fn(name) -> \"hello, {name}\"\n";
+    std::fs::write(dir.join("greeting.lyra"), greeting).unwrap();
+    let program = dir.join("app.lyra");
+    std::fs::write(&program, "import \"greeting.lyra\"\nprintln(greet(\"build\"))\n").unwrap();
+    let exe = dir.join("app-bin");
+    let lyrc = dir.join("app.lyrc");
+    let bin = lyra_bin();
+
+    let build = |args: &[&str]| {
+        let output = Command::new(&bin).arg("build").args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    };
+    build(&["-o", exe.to_str().unwrap(), program.to_str().unwrap()]);
+    build(&["--lyrc", program.to_str().unwrap()]);
+
+    // Neither needs the sources any more
+    std::fs::remove_file(&program).unwrap();
+    std::fs::remove_file(dir.join("greeting.lyra")).unwrap();
+    let output = Command::new(&exe).output().expect("failed to run the built executable");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello, build\n");
+    let (stdout, stderr, success) = run_lyra(lyrc.to_str().unwrap(), false);
+    assert!(success, "{}", stderr);
+    assert_eq!(stdout, "hello, build\n");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn emit_js_programs_print_what_the_interpreter_prints() {
+    if Command::new("node").arg("--version").output().is_err() {
+        eprintln!("skipping: node is not installed");
+        return;
+    }
+    let dir = std::env::temp_dir().join("lyra_test_emit_js");
+    std::fs::create_dir_all(&dir).unwrap();
+    let bin = lyra_bin();
+    let run_js = |name: &str, path: &str| {
+        let js = dir.join(format!("{}.js", name));
+        let output = Command::new(&bin)
+            .args(["emit-js", "-o", js.to_str().unwrap()])
+            .arg(path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        let output = Command::new("node").arg(&js).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    for name in ["adt", "interpolation", "pipes", "records", "showcase"] {
+        let stdout = run_js(name, &example_path(&format!("{}.lyra", name)));
+        let expected =
+            std::fs::read_to_string(example_path(&format!("{}.expected", name))).unwrap();
+        assert_eq!(stdout, expected, "{}", name);
+    }
+    // Builtins the examples don't use, against the interpreter's output
+    let snippets = [(
+        "divmod",
+        "println(divmod(-7, 2))\n\
+         println(match divmod(7, -2) with | (q, r) -> q * 10 + r)\n\
+         println(div_euclid(-7, 2), mod_euclid(-7, 2))\n",
+    ), (
+        "println",
+        "print()\nprintln()\nlet p = println\nlet q = p()\nq(\"x\")\n\
+         let f = fn (println) -> println()\nprintln(f(fn (x) -> x + 1)(1))\n",
+    ), (
+        "labeled",
+        "let note = fn (s, n) -> let shown = println(s) in n\n\
+         let sub = fn (a, b) -> a - b\n\
+         println(sub(b: note(\"b\", 1), a: note(\"a\", 10)), sub(a: 3, b: 1))\n",
+    ), (
+        "ternary",
+        "let c = 0\n\
+         println(10 + (if (match c with | 0 -> false | _ -> true) then 1 else 2))\n",
+    ), (
+        "promotion",
+        "let f = fn (x) -> x + 2.5\nlet g = fn (a, b) -> a * b\n\
+         println(f(1), f(1.5), g(2, 2.5), g(2, 3), 1 == 1.0, 2 != 2.5)\n",
+    ), (
+        "string_order",
+        "let a = \"\u{1F600}\"\nlet b = \"\u{FF61}\"\n\
+         println(a < b, a > b, (\"x\" + a) <= (\"x\" + b), [a] < [b], \"ab\" < \"a\")\n",
+    )];
+    for (name, src) in snippets {
+        let path = dir.join(format!("{}.lyra", name));
+        std::fs::write(&path, src).unwrap();
+        let path = path.to_str().unwrap();
+        let (expected, stderr, success) = run_lyra(path, false);
+        assert!(success, "{}", stderr);
+        assert_eq!(run_js(name, path), expected, "{}", name);
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn emit_js_parenthesizes_a_ternary_whose_condition_is_a_block() {
+    let source = "let c = 0\n\
+                  println(10 + (if (match c with | 0 -> false | _ -> true) then 1 else 2))\n";
+    let js = lyra::emit_js(source, "ternary.lyra").unwrap();
+    assert!(js.contains("println(10 + ((() => {"), "{}", js);
+    assert!(js.contains("})() ? 1 : 2));"), "{}", js);
+}
+
+#[test]
+fn emit_js_rejects_division_of_numbers_of_unknown_kind() {
+    let err = lyra::emit_js("let half = fn (x) -> x / 2\nprintln(half(7))\n", "half.lyra").unwrap_err();
+    assert_eq!(err.code(), "E0501");
+    let js = lyra::emit_js("sig half : Int -> Int\nlet half = fn (x) -> x / 2\nprintln(half(7))\n", "half.lyra");
+    assert!(js.unwrap().contains("$div(x, 2)"));
+}
+
+#[test]
+fn emit_js_lowers_matches_and_rejects_quotes() {
+    let source = "type Shape = Circle Float | Square Float\n\
+                  let area = fn (s) -> match s with\n\
+                  | Circle(r) -> 3.0 * r * r\n\
+                  | Square(w) -> w * w\n\
+                  println(area(Square(2.0)))\n";
+    let js = lyra::emit_js(source, "shapes.lyra").unwrap();
+    assert!(js.contains("switch (s.$tag)"), "{}", js);
+    assert!(js.contains("case \"Circle\": {"), "{}", js);
+    assert!(js.contains("println($str(area(Square(2.0)), \"F\"));"), "{}", js);
+    // Only the builtins the program calls are included
+    assert!(js.contains("const println"), "{}", js);
+    assert!(!js.contains("const str_concat"), "{}", js);
+
+    let err = lyra::emit_js("let code = quote (1 + 2)\n", "quoted.lyra").unwrap_err();
+    assert_eq!(err.code(), "E0501");
+}
+
+#[test]
+fn repl_binds_results_to_it_and_numbered_names() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = std::env::temp_dir().join("lyra_test_repl_results");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(lyra_bin())
+        .arg("repl")
+        .env("HOME", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"println(\"hi\")\n20 + 1\nit * 2\n[]\n_1 + _2\nlength(_3)\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    for expected in ["_1 =\x1b[0m \x1b[1m21", "_2 =\x1b[0m \x1b[1m42", "_4 =\x1b[0m \x1b[1m63", "_5 =\x1b[0m \x1b[1m0"] {
+        assert!(stdout.contains(expected), "missing {:?} in {}", expected, stdout);
+    }
+    // `()` results aren't numbered
+    assert!(stdout.contains("  \x1b[1m()\x1b[0m"), "{}", stdout);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn repl_renders_warnings_with_carets_after_the_value() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = std::env::temp_dir().join("lyra_test_repl_warnings");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(lyra_bin())
+        .arg("repl")
+        .env("HOME", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"type Light = Red | Green\nmatch Red with | Red -> 1\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stdout.contains("_1 =\x1b[0m \x1b[1m1"), "{}", stdout);
+    assert!(stderr.contains("non-exhaustive patterns: missing Green"), "{}", stderr);
+    assert!(stderr.contains("<repl>:1:1"), "{}", stderr);
+    assert!(stderr.contains("^^^"), "{}", stderr);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn repl_memory_lists_the_largest_bindings() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = std::env::temp_dir().join("lyra_test_repl_memory");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(lyra_bin())
+        .arg("repl")
+        .env("HOME", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"let small = 1\nlet big = range(0, 5000)\n:memory\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("bindings, "), "{}", stdout);
+    let big = stdout.find("big ").expect("big listed");
+    let small = stdout.find("small ").expect("small listed");
+    assert!(big < small, "{}", stdout);
+    assert!(stdout.contains("5001 values"), "{}", stdout);
+    assert!(stdout[small..].contains("\x1b[2m1 value, "), "{}", stdout);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Run the REPL with `home` as `$HOME`, feeding it `input`; its stdout,
+/// stderr and whether it succeeded.
+fn run_repl(home: &std::path::Path, input: &str) -> (String, String, bool) {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(lyra_bin())
+        .arg("repl")
+        .env("HOME", home)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    (stdout, stderr, output.status.success())
+}
+
+#[test]
+fn repl_set_changes_settings_and_rejects_bad_ones() {
+    let dir = std::env::temp_dir().join("lyra_test_repl_set");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = ":set timeout 250\n:set max-steps 1000\n:set types off\n:set\n\
+                 :set timeout soon\n:set max-steps -1\n:set types maybe\n:set verbose on\n:set timeout\n\
+                 :set timeout off\n:set max-steps none\n:set types on\n:set\n";
+    let (stdout, stderr, success) = run_repl(&dir, input);
+    assert!(success, "{}", stderr);
+    let settings: Vec<&str> = stdout.lines().filter(|l| l.starts_with("  ")).collect();
+    assert_eq!(
+        settings,
+        [
+            "  timeout    250",
+            "  max-steps  1000",
+            "  types      off",
+            "  timeout    off",
+            "  max-steps  off",
+            "  types      on",
+        ],
+        "{}",
+        stdout
+    );
+    for expected in [
+        "timeout expects milliseconds, got 'soon'",
+        "max-steps expects a number, got '-1'",
+        "unknown setting 'types maybe'",
+        "unknown setting 'verbose on'",
+        "unknown setting 'timeout'",
+    ] {
+        assert!(stderr.contains(expected), "missing {:?} in {}", expected, stderr);
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn repl_lyrarc_loads_what_it_can_and_reports_the_rest() {
+    let dir = std::env::temp_dir().join("lyra_test_repl_rc");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(".lyrarc"),
+        "let double = fn (x) -> x * 2\n\
+         :set types off\n\
+         :set max-steps lots\n\
+         :load other.lyra\n\
+         let broken = 1 + \"a\"\n\
+         :set timeout 500\n\
+         let triple = fn (x) -> x * 3\n",
+    )
+    .unwrap();
+    let (stdout, stderr, success) = run_repl(&dir, "double(triple(1))\n:set\n");
+    assert!(success, "{}", stderr);
+    // Good lines still apply, and the bad ones are reported against the file
+    assert!(stdout.contains("6"), "{}", stdout);
+    assert!(stdout.contains("  timeout    500\n  max-steps  off\n  types      off"), "{}", stdout);
+    assert!(stderr.contains(".lyrarc: max-steps expects a number, got 'lots'"), "{}", stderr);
+    assert!(stderr.contains(".lyrarc: only :set is allowed, found ':load other.lyra'"), "{}", stderr);
+    assert!(stderr.contains(".lyrarc:5:14"), "{}", stderr);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn repl_edit_runs_what_the_editor_saved() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = std::env::temp_dir().join("lyra_test_repl_edit");
+    std::fs::create_dir_all(&dir).unwrap();
+    // The editor notes the file it was given and what it held, then saves new text
+    let log = dir.join("editor.log");
+    let editor = dir.join("editor.sh");
+    std::fs::write(
+        &editor,
+        format!(
+            "ls -l \"$1\" >> {log}\necho \"$1\" >> {log}\ncat \"$1\" >> {log}\nprintf 'let answer = 41 + 1\\n' > \"$1\"\n",
+            log = log.display()
+        ),
+    )
+    .unwrap();
+    let mut child = Command::new(lyra_bin())
+        .arg("repl")
+        .env("HOME", &dir)
+        .env_remove("VISUAL")
+        .env("EDITOR", format!("sh {}", editor.display()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"let seed = 1\n:edit seed\nanswer\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("42"), "{}", stdout);
+
+    let log = std::fs::read_to_string(&log).unwrap();
+    let mut lines = log.lines();
+    let mode = lines.next().unwrap();
+    assert!(mode.starts_with("-rw-------"), "{}", log);
+    let path = lines.next().unwrap();
+    // A random name, not one derived from the process id
+    let name = std::path::Path::new(path).file_name().unwrap().to_str().unwrap();
+    let random = name.strip_prefix("lyra-edit-").and_then(|n| n.strip_suffix(".lyra"));
+    assert!(random.is_some_and(|r| r.len() == 16 && r.chars().all(|c| c.is_ascii_hexdigit())), "{}", log);
+    assert_eq!(lines.next(), Some("let seed = 1"), "{}", log);
+    // The file is gone once its text is read back
+    assert!(!std::path::Path::new(path).exists(), "{}", path);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn repl_starts_with_files_loaded() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = std::env::temp_dir().join("lyra_test_repl_load");
+    std::fs::create_dir_all(&dir).unwrap();
+    let double = dir.join("double.lyra");
+    let triple = dir.join("triple.lyra");
+    std::fs::write(&double, "let double = fn (x) -> x * 2\nprintln(\"script ran\")\n").unwrap();
+    std::fs::write(&triple, "let triple = fn (x) -> x * 3\n").unwrap();
+    let (double, triple) = (double.to_str().unwrap(), triple.to_str().unwrap());
+
+    let bin = lyra_bin();
+    let runs: [(&[&str], &str, &str); 2] = [
+        (&["-i", double], "double(21)\n", "42"),
+        (&["repl", "--load", double, "--load", triple], "double(triple(2))\n", "12"),
+    ];
+    for (args, input, expected) in runs {
+        // A home without a `.lyrarc` or history
+        let mut child = Command::new(&bin)
+            .args(args)
+            .env("HOME", &dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("script ran") && stdout.contains(expected), "{}", stdout);
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
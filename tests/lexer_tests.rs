@@ -1,3 +1,5 @@
+#![allow(clippy::approx_constant)]
+
 use lyra::lexer::tokenize;
 use lyra::lexer::token::TokenKind;
 
@@ -27,6 +29,27 @@ fn lex_float_literals() {
     ]);
 }
 
+#[test]
+fn lex_scientific_float_literals() {
+    let kinds = token_kinds("1e9 2.5e-3 1E+6");
+    assert_eq!(kinds, vec![
+        TokenKind::FloatLit(1e9),
+        TokenKind::FloatLit(2.5e-3),
+        TokenKind::FloatLit(1e6),
+        TokenKind::Eof,
+    ]);
+}
+
+#[test]
+fn lex_malformed_exponent_is_an_error() {
+    for source in ["1e", "2.5e+", "3ex"] {
+        let errs = tokenize(source).expect_err(source);
+        assert_eq!(errs[0].code(), "E0003", "{}", source);
+        let span = errs[0].span().unwrap();
+        assert_eq!((span.start, span.end), (0, source.len()), "{}", source);
+    }
+}
+
 #[test]
 fn lex_string_literal() {
     let kinds = token_kinds("\"hello world\"");
@@ -142,6 +165,94 @@ fn lex_interpolated_string() {
     assert!(matches!(kinds[0], TokenKind::InterpolatedString(_)));
 }
 
+#[test]
+fn lex_interpolation_format_spec() {
+    use lyra::ast::{Align, FormatSpec};
+    use lyra::lexer::token::InterpPart;
+
+    let specs = |source: &str| match &token_kinds(source)[0] {
+        TokenKind::InterpolatedString(parts) => parts
+            .iter()
+            .filter_map(|p| match p {
+                InterpPart::Tokens(_, spec) => Some(spec.clone()),
+                InterpPart::Literal(_) => None,
+            })
+            .collect::<Vec<_>>(),
+        other => panic!("expected interpolated string, got {:?}", other),
+    };
+    let [Some(right), Some(float)] = &specs("\"{n:*>8} {pi:.3}\"")[..] else {
+        panic!("expected two specs")
+    };
+    assert_eq!((right.fill, right.align, right.width), ('*', Some(Align::Right), Some(8)));
+    assert_eq!(float.precision, Some(3));
+    assert_eq!(FormatSpec::parse("08.2").map(|s| (s.zero, s.width)), Some((true, Some(8))));
+    // Cons, nested records and annotations aren't format specs
+    assert_eq!(specs("\"{x :: xs} { {a: 1}.a } {let y : Int = 1 in y}\""), vec![None, None, None]);
+}
+
+#[test]
+fn lex_format_spec_width_is_bounded() {
+    assert!(tokenize("\"{n:1024} {x:.1024}\"").is_ok());
+    for source in ["\"{n:1025}\"", "\"{x:.99999}\"", "\"{n:>99999999999999999999999}\""] {
+        let errs = tokenize(source).expect_err(source);
+        assert_eq!(errs[0].code(), "E0004", "{}", source);
+        assert!(errs[0].to_string().contains("at most 1024"), "{}", errs[0]);
+    }
+}
+
+#[test]
+fn lex_interpolation_spans_point_into_the_source() {
+    use lyra::lexer::token::InterpPart;
+
+    let source = "let s = \"ab {xy + \"{z}\"}\"";
+    let tokens = tokenize(source).unwrap();
+    let TokenKind::InterpolatedString(parts) = &tokens[3].kind else {
+        panic!("expected interpolated string")
+    };
+    let InterpPart::Tokens(inner, _) = &parts[1] else {
+        panic!("expected tokens")
+    };
+    let text = |t: &lyra::lexer::token::Token| source[t.span.start..t.span.end].to_string();
+    assert_eq!(text(&inner[0]), "xy");
+    let TokenKind::InterpolatedString(nested) = &inner[2].kind else {
+        panic!("expected nested interpolated string")
+    };
+    let InterpPart::Tokens(z, _) = &nested[0] else {
+        panic!("expected tokens")
+    };
+    assert_eq!(text(&z[0]), "z");
+}
+
+#[test]
+fn lex_spans_count_chars_not_bytes() {
+    use lyra::lexer::token::InterpPart;
+
+    let source = "let naïve = \"√{x}\" -- ünïcode\nnaïve";
+    let tokens = tokenize(source).unwrap();
+    let starts: Vec<usize> = tokens.iter().map(|t| t.span.start).collect();
+    assert_eq!(starts, [0, 4, 10, 12, 30, 35]);
+    assert_eq!(tokens[1].kind, TokenKind::Ident("naïve".to_string()));
+    let TokenKind::InterpolatedString(parts) = &tokens[3].kind else {
+        panic!("expected interpolated string")
+    };
+    let InterpPart::Tokens(inner, _) = &parts[1] else {
+        panic!("expected tokens")
+    };
+    assert_eq!(inner[0].span.start, 15);
+    assert!(tokens[4].line_start);
+}
+
+#[test]
+fn lex_iterates_lazily_past_errors() {
+    use lyra::lexer::Lexer;
+
+    let mut lexer = Lexer::new("1 ? 2");
+    assert_eq!(lexer.next().unwrap().unwrap().kind, TokenKind::IntLit(1));
+    assert!(lexer.next().unwrap().is_err());
+    let rest: Vec<TokenKind> = lexer.map(|t| t.unwrap().kind).collect();
+    assert_eq!(rest, [TokenKind::IntLit(2), TokenKind::Eof]);
+}
+
 #[test]
 fn lex_record_braces() {
     let kinds = token_kinds("{ x: 1 }");
@@ -160,6 +271,18 @@ fn lex_dot_operator() {
     ]);
 }
 
+#[test]
+fn lex_optional_dot_operator() {
+    let kinds = token_kinds("x?.y");
+    assert_eq!(kinds, vec![
+        TokenKind::Ident("x".to_string()),
+        TokenKind::QuestionDot,
+        TokenKind::Ident("y".to_string()),
+        TokenKind::Eof,
+    ]);
+    assert!(tokenize("x?y").is_err());
+}
+
 #[test]
 fn lex_underscore_wildcard() {
     let kinds = token_kinds("_");
@@ -178,3 +301,91 @@ fn lex_import_keyword() {
         TokenKind::Eof,
     ]);
 }
+
+#[test]
+fn lex_pipe_tap() {
+    let kinds = token_kinds("x |>! f |> g");
+    assert_eq!(kinds, vec![
+        TokenKind::Ident("x".to_string()),
+        TokenKind::PipeTap,
+        TokenKind::Ident("f".to_string()),
+        TokenKind::PipeRight,
+        TokenKind::Ident("g".to_string()),
+        TokenKind::Eof,
+    ]);
+}
+
+#[test]
+fn lex_power_operator() {
+    let kinds = token_kinds("2 ** 3 * 4");
+    assert_eq!(kinds, vec![
+        TokenKind::IntLit(2),
+        TokenKind::StarStar,
+        TokenKind::IntLit(3),
+        TokenKind::Star,
+        TokenKind::IntLit(4),
+        TokenKind::Eof,
+    ]);
+}
+
+#[test]
+fn lex_doc_comment_attaches_to_next_token() {
+    let tokens = tokenize("--- Adds one.\n---   Second line\n-- plain\nlet f = 1").unwrap();
+    assert_eq!(tokens[0].kind, TokenKind::Let);
+    assert_eq!(tokens[0].doc.as_deref(), Some("Adds one.\n  Second line"));
+    assert!(tokens[1..].iter().all(|t| t.doc.is_none()));
+}
+
+#[test]
+fn lex_skips_leading_shebang() {
+    let tokens = tokenize("#!/usr/bin/env lyra\nlet x = 1").unwrap();
+    assert_eq!(tokens[0].kind, TokenKind::Let);
+    assert_eq!(tokens[0].span.start, 20);
+    // Only on the first line
+    assert!(tokenize("let x = 1\n#!/usr/bin/env lyra").is_err());
+}
+
+#[test]
+fn lex_lossless_reconstructs_source() {
+    use lyra::lexer::tokenize_lossless;
+
+    let source = "--- Doc.\nlet  x = \"hi {name}\"  -- trailing\n\n\tx |> f\n";
+    let pieces = tokenize_lossless(source).unwrap();
+    let chars: Vec<char> = source.chars().collect();
+    let rebuilt: String = pieces
+        .iter()
+        .flat_map(|p| chars[p.span().start..p.span().end].iter())
+        .collect();
+    assert_eq!(rebuilt, source);
+
+    let mut pos = 0;
+    for piece in &pieces {
+        assert_eq!(piece.span().start, pos, "pieces must tile the source");
+        pos = piece.span().end;
+    }
+}
+
+#[test]
+fn lex_lossless_classifies_trivia() {
+    use lyra::lexer::token::{TokenOrTrivia, TriviaKind};
+    use lyra::lexer::tokenize_lossless;
+
+    let pieces = tokenize_lossless("--- d\n-- c\nx").unwrap();
+    let trivia: Vec<TriviaKind> = pieces
+        .iter()
+        .filter_map(|p| match p {
+            TokenOrTrivia::Trivia(t) => Some(t.kind),
+            TokenOrTrivia::Token(_) => None,
+        })
+        .collect();
+    assert_eq!(
+        trivia,
+        vec![
+            TriviaKind::DocComment,
+            TriviaKind::Whitespace,
+            TriviaKind::Comment,
+            TriviaKind::Whitespace,
+        ]
+    );
+    assert!(matches!(&pieces[4], TokenOrTrivia::Token(t) if t.kind == TokenKind::Ident("x".into())));
+}
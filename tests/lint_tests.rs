@@ -0,0 +1,90 @@
+use lyra::diagnostic::Severity;
+use lyra::lint::{lint, Level, LintConfig};
+use lyra::span::LineIndex;
+
+/// `(line, col, code)` for each finding in `source` under `config`.
+fn findings(source: &str, config: &LintConfig) -> Vec<(usize, usize, &'static str)> {
+    let lines = LineIndex::new(source);
+    lint(source, config)
+        .unwrap()
+        .iter()
+        .map(|d| {
+            let (line, col) = lines.line_col(d.primary.as_ref().unwrap().span.start);
+            (line, col, d.code)
+        })
+        .collect()
+}
+
+#[test]
+fn lint_rules_report_at_their_spans() {
+    let source = "let f = fn (x, y, _z) -> x\n\
+                  let g = fn (b) -> match b with | true -> 1 | _ -> 2\n\
+                  let map = fn (f, xs) -> f(xs)\n\
+                  let p = 1 |> abs |> abs |> abs";
+    let mut config = LintConfig::default();
+    config.max_pipe_chain = 2;
+    assert_eq!(
+        findings(source, &config),
+        vec![
+            (1, 16, "L0004"),
+            (2, 19, "L0002"),
+            (3, 5, "L0003"),
+            (4, 9, "L0001"),
+        ]
+    );
+}
+
+#[test]
+fn lint_unused_params_respect_shadowing() {
+    let config = LintConfig::default();
+    assert!(findings("let f = fn (x) -> fn (y) -> x + y", &config).is_empty());
+    assert_eq!(
+        findings("let f = fn (x) -> let x = 1 in x", &config),
+        vec![(1, 13, "L0004")]
+    );
+    assert_eq!(
+        findings("let f = fn (x) -> match 1 with | x -> x", &config),
+        vec![(1, 13, "L0004")]
+    );
+    assert_eq!(
+        findings("let f = fn (x) -> let rec x = fn (n) -> x(n) in x", &config),
+        vec![(1, 13, "L0004")]
+    );
+}
+
+#[test]
+fn lint_comments_set_levels_per_declaration_or_file() {
+    let source = "-- lint: allow(unused-param)\n\
+                  let f = fn (x) -> 1\n\
+                  let g = fn (y) -> 2";
+    assert_eq!(findings(source, &LintConfig::default()), vec![(3, 13, "L0004")]);
+
+    let source = format!("-- lint-file: deny(unused-param)\n{}", source);
+    let diagnostics = lint(&source, &LintConfig::default()).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+
+    let diagnostics = lint("-- lint: allow(nope)\nlet x = 1", &LintConfig::default()).unwrap();
+    assert_eq!(diagnostics[0].message, "unknown lint rule 'nope'");
+}
+
+#[test]
+fn lint_config_files_set_levels_and_limits() {
+    let config = LintConfig::parse(
+        "-- stricter than the default\nmax-pipe-chain = 1\nunused-param = allow\nbool-match = deny\n",
+    )
+    .unwrap();
+    assert_eq!(config.max_pipe_chain, 1);
+    assert_eq!(config.level("unused-param"), Level::Allow);
+    assert_eq!(config.level("bool-match"), Level::Deny);
+    assert_eq!(config.level("long-pipe"), Level::Warn);
+    assert_eq!(
+        findings("let f = fn (x) -> 1 |> abs |> abs", &config),
+        vec![(1, 19, "L0001")]
+    );
+
+    let err = LintConfig::parse("long-pipe = loud").unwrap_err();
+    assert_eq!(err, "line 1: 'loud' is not a level; use allow, warn or deny");
+    let err = LintConfig::parse("no-such-rule = deny").unwrap_err();
+    assert_eq!(err, "line 1: unknown lint rule 'no-such-rule'");
+}
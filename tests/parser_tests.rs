@@ -2,24 +2,24 @@ use lyra::ast::*;
 use lyra::lexer::tokenize;
 use lyra::parser::parse;
 
-fn parse_source(source: &str) -> Vec<Decl> {
+fn parse_source(source: &str) -> Program {
     let tokens = tokenize(source).expect("lexer should succeed");
     parse(tokens).expect("parser should succeed")
 }
 
 #[test]
 fn parse_integer_expression() {
-    let decls = parse_source("42");
+    let Program { ast, decls } = parse_source("42");
     assert_eq!(decls.len(), 1);
     match &decls[0] {
-        Decl::Expr(expr) => assert!(matches!(expr.node, Expr::IntLit(42))),
+        Decl::Expr(expr) => assert!(matches!(ast[*expr].node, Expr::IntLit(42))),
         _ => panic!("expected Expr decl"),
     }
 }
 
 #[test]
 fn parse_let_binding() {
-    let decls = parse_source("let x = 42");
+    let decls = parse_source("let x = 42").decls;
     assert_eq!(decls.len(), 1);
     match &decls[0] {
         Decl::Let { name, recursive, .. } => {
@@ -32,7 +32,7 @@ fn parse_let_binding() {
 
 #[test]
 fn parse_recursive_let() {
-    let decls = parse_source("let rec f = fn (x) -> x");
+    let decls = parse_source("let rec f = fn (x) -> x").decls;
     match &decls[0] {
         Decl::Let { name, recursive, .. } => {
             assert_eq!(name.node, "f");
@@ -44,9 +44,9 @@ fn parse_recursive_let() {
 
 #[test]
 fn parse_lambda() {
-    let decls = parse_source("fn (x, y) -> x + y");
+    let Program { ast, decls } = parse_source("fn (x, y) -> x + y");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::Lambda { params, .. } => {
                 assert_eq!(params.len(), 2);
                 assert_eq!(params[0].name.node, "x");
@@ -60,14 +60,14 @@ fn parse_lambda() {
 
 #[test]
 fn parse_function_application() {
-    let decls = parse_source("f(1, 2)");
+    let Program { ast, decls } = parse_source("f(1, 2)");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::App { func, args } => {
-                assert!(matches!(func.node, Expr::Var(ref name) if name == "f"));
+                assert!(matches!(ast[*func].node, Expr::Var(ref name) if name == "f"));
                 assert_eq!(args.len(), 2);
             }
-            _ => panic!("expected App, got {:?}", expr.node),
+            _ => panic!("expected App, got {:?}", ast[*expr].node),
         },
         _ => panic!("expected Expr decl"),
     }
@@ -75,10 +75,10 @@ fn parse_function_application() {
 
 #[test]
 fn parse_if_expression() {
-    let decls = parse_source("if true then 1 else 2");
+    let Program { ast, decls } = parse_source("if true then 1 else 2");
     match &decls[0] {
         Decl::Expr(expr) => {
-            assert!(matches!(expr.node, Expr::If { .. }));
+            assert!(matches!(ast[*expr].node, Expr::If { .. }));
         }
         _ => panic!("expected Expr decl"),
     }
@@ -86,9 +86,9 @@ fn parse_if_expression() {
 
 #[test]
 fn parse_match_expression() {
-    let decls = parse_source("match x with | 0 -> true | _ -> false");
+    let Program { ast, decls } = parse_source("match x with | 0 -> true | _ -> false");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::Match { arms, .. } => {
                 assert_eq!(arms.len(), 2);
             }
@@ -98,11 +98,50 @@ fn parse_match_expression() {
     }
 }
 
+#[test]
+fn parse_string_affix_patterns() {
+    let Program { ast, decls } = parse_source("match s with | \"<\" ++ tag ++ \">\" -> tag | _ -> \"\"");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::Match { arms, .. } => match &arms[0].pattern.node {
+                Pattern::StringPrefix(prefix, rest) => {
+                    assert_eq!(prefix, "<");
+                    assert!(matches!(&rest.node, Pattern::StringSuffix(tag, suffix)
+                        if suffix == ">" && matches!(&tag.node, Pattern::Var(v) if v == "tag")));
+                }
+                other => panic!("expected StringPrefix, got {:?}", other),
+            },
+            _ => panic!("expected Match"),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+    let tokens = tokenize("match s with | a ++ b -> a").unwrap();
+    let err = parse(tokens).unwrap_err();
+    assert!(err.to_string().contains("a string literal on one side of '++'"), "{}", err);
+}
+
+#[test]
+fn parse_negative_literal_patterns() {
+    let Program { ast, decls } = parse_source("match x with | -1 -> 0 | -2.5 -> 1 | (-3, _) -> 2 | _ -> 3");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::Match { arms, .. } => {
+                assert!(matches!(arms[0].pattern.node, Pattern::IntLit(-1)));
+                assert!(matches!(arms[1].pattern.node, Pattern::FloatLit(f) if f == -2.5));
+                assert!(matches!(&arms[2].pattern.node, Pattern::Tuple(pats)
+                    if matches!(pats[0].node, Pattern::IntLit(-3))));
+            }
+            _ => panic!("expected Match"),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+}
+
 #[test]
 fn parse_list_literal() {
-    let decls = parse_source("[1, 2, 3]");
+    let Program { ast, decls } = parse_source("[1, 2, 3]");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::ListLit(elems) => assert_eq!(elems.len(), 3),
             _ => panic!("expected ListLit"),
         },
@@ -112,9 +151,9 @@ fn parse_list_literal() {
 
 #[test]
 fn parse_tuple_literal() {
-    let decls = parse_source("(1, 2, 3)");
+    let Program { ast, decls } = parse_source("(1, 2, 3)");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::TupleLit(elems) => assert_eq!(elems.len(), 3),
             _ => panic!("expected TupleLit"),
         },
@@ -124,10 +163,10 @@ fn parse_tuple_literal() {
 
 #[test]
 fn parse_pipe_operator() {
-    let decls = parse_source("[1,2,3] |> length");
+    let Program { ast, decls } = parse_source("[1,2,3] |> length");
     match &decls[0] {
         Decl::Expr(expr) => {
-            assert!(matches!(expr.node, Expr::Pipe { .. }));
+            assert!(matches!(ast[*expr].node, Expr::Pipe { .. }));
         }
         _ => panic!("expected Expr decl"),
     }
@@ -135,9 +174,9 @@ fn parse_pipe_operator() {
 
 #[test]
 fn parse_binary_operators() {
-    let decls = parse_source("1 + 2 * 3");
+    let Program { ast, decls } = parse_source("1 + 2 * 3");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::BinOp { op, .. } => {
                 assert_eq!(*op, BinOp::Add); // + is lower precedence, so top-level
             }
@@ -149,9 +188,9 @@ fn parse_binary_operators() {
 
 #[test]
 fn parse_cons_operator() {
-    let decls = parse_source("1 :: [2, 3]");
+    let Program { ast, decls } = parse_source("1 :: [2, 3]");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::BinOp { op, .. } => {
                 assert_eq!(*op, BinOp::Cons);
             }
@@ -164,11 +203,11 @@ fn parse_cons_operator() {
 #[test]
 fn parse_let_in_expression() {
     // let...in is only valid inside an expression context (e.g. inside a function body)
-    let decls = parse_source("fn (n) -> let x = 1 in x + n");
+    let Program { ast, decls } = parse_source("fn (n) -> let x = 1 in x + n");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::Lambda { body, .. } => {
-                assert!(matches!(body.node, Expr::Let { .. }));
+                assert!(matches!(ast[*body].node, Expr::Let { .. }));
             }
             _ => panic!("expected Lambda wrapping Let expression"),
         },
@@ -178,9 +217,9 @@ fn parse_let_in_expression() {
 
 #[test]
 fn parse_type_declaration() {
-    let decls = parse_source("type Color = Red | Green | Blue");
+    let decls = parse_source("type Color = Red | Green | Blue").decls;
     match &decls[0] {
-        Decl::Type { name, variants, type_params } => {
+        Decl::Type { name, variants, type_params, .. } => {
             assert_eq!(name.node, "Color");
             assert_eq!(type_params.len(), 0);
             assert_eq!(variants.len(), 3);
@@ -194,7 +233,7 @@ fn parse_type_declaration() {
 
 #[test]
 fn parse_type_with_fields() {
-    let decls = parse_source("type Shape = Circle Int | Rectangle Int Int");
+    let decls = parse_source("type Shape = Circle Int | Rectangle Int Int").decls;
     match &decls[0] {
         Decl::Type { variants, .. } => {
             assert_eq!(variants[0].fields.len(), 1);
@@ -206,9 +245,9 @@ fn parse_type_with_fields() {
 
 #[test]
 fn parse_record_literal() {
-    let decls = parse_source("{ name: \"Alice\", age: 30 }");
+    let Program { ast, decls } = parse_source("{ name: \"Alice\", age: 30 }");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::Record(fields) => {
                 assert_eq!(fields.len(), 2);
                 assert_eq!(fields[0].0, "name");
@@ -220,11 +259,45 @@ fn parse_record_literal() {
     }
 }
 
+#[test]
+fn parse_record_field_punning() {
+    let Program { ast, decls } = parse_source("{ name, age: 30, city, }");
+    let Decl::Expr(expr) = &decls[0] else {
+        panic!("expected Expr decl")
+    };
+    let Expr::Record(fields) = &ast[*expr].node else {
+        panic!("expected Record")
+    };
+    let names: Vec<&str> = fields.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, ["name", "age", "city"]);
+    assert!(matches!(&ast[fields[0].1].node, Expr::Var(v) if v == "name"));
+    assert!(matches!(&ast[fields[2].1].node, Expr::Var(v) if v == "city"));
+}
+
+#[test]
+fn parse_slices() {
+    let Program { ast, decls } = parse_source("xs[1:n + 1]\nxs[:2]\ns[-3:]\nlet ys = xs\n[1, 2]");
+    let bounds: Vec<(bool, bool)> = decls[..3]
+        .iter()
+        .map(|d| match d {
+            Decl::Expr(e) => match &ast[*e].node {
+                Expr::Slice { start, end, .. } => (start.is_some(), end.is_some()),
+                _ => panic!("expected Slice"),
+            },
+            _ => panic!("expected Expr decl"),
+        })
+        .collect();
+    assert_eq!(bounds, [(true, true), (false, true), (true, false)]);
+    // A list on the next line is its own expression
+    assert_eq!(decls.len(), 5);
+    assert!(matches!(&decls[4], Decl::Expr(e) if matches!(ast[*e].node, Expr::ListLit(_))));
+}
+
 #[test]
 fn parse_field_access() {
-    let decls = parse_source("person.name");
+    let Program { ast, decls } = parse_source("person.name");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::FieldAccess { field, .. } => {
                 assert_eq!(field, "name");
             }
@@ -236,7 +309,7 @@ fn parse_field_access() {
 
 #[test]
 fn parse_import_declaration() {
-    let decls = parse_source("import \"utils\"");
+    let decls = parse_source("import \"utils\"").decls;
     match &decls[0] {
         Decl::Import { path, .. } => {
             assert_eq!(path, "utils");
@@ -247,10 +320,10 @@ fn parse_import_declaration() {
 
 #[test]
 fn parse_interpolated_string() {
-    let decls = parse_source("\"hello {name}\"");
+    let Program { ast, decls } = parse_source("\"hello {name}\"");
     match &decls[0] {
         Decl::Expr(expr) => {
-            assert!(matches!(expr.node, Expr::Interpolation(_)));
+            assert!(matches!(ast[*expr].node, Expr::Interpolation(_)));
         }
         _ => panic!("expected Expr decl"),
     }
@@ -258,9 +331,9 @@ fn parse_interpolated_string() {
 
 #[test]
 fn parse_unary_negation() {
-    let decls = parse_source("-42");
+    let Program { ast, decls } = parse_source("-42");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::UnaryOp { op, .. } => {
                 assert_eq!(*op, UnaryOp::Neg);
             }
@@ -272,9 +345,9 @@ fn parse_unary_negation() {
 
 #[test]
 fn parse_unary_not() {
-    let decls = parse_source("!true");
+    let Program { ast, decls } = parse_source("!true");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::UnaryOp { op, .. } => {
                 assert_eq!(*op, UnaryOp::Not);
             }
@@ -286,7 +359,7 @@ fn parse_unary_not() {
 
 #[test]
 fn parse_multiple_declarations() {
-    let decls = parse_source("let x = 1\nlet y = 2\nx + y");
+    let decls = parse_source("let x = 1\nlet y = 2\nx + y").decls;
     assert_eq!(decls.len(), 3);
     assert!(matches!(decls[0], Decl::Let { .. }));
     assert!(matches!(decls[1], Decl::Let { .. }));
@@ -295,15 +368,385 @@ fn parse_multiple_declarations() {
 
 #[test]
 fn parse_nested_field_access() {
-    let decls = parse_source("a.b.c");
+    let Program { ast, decls } = parse_source("a.b.c");
     match &decls[0] {
-        Decl::Expr(expr) => match &expr.node {
+        Decl::Expr(expr) => match &ast[*expr].node {
             Expr::FieldAccess { expr: inner, field } => {
                 assert_eq!(field, "c");
-                assert!(matches!(inner.node, Expr::FieldAccess { .. }));
+                assert!(matches!(ast[*inner].node, Expr::FieldAccess { .. }));
             }
             _ => panic!("expected FieldAccess"),
         },
         _ => panic!("expected Expr decl"),
     }
 }
+
+#[test]
+fn parse_if_let_desugars_to_match() {
+    let Program { ast, decls } = parse_source("if let Some(x) = opt then x else 0");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::Match { arms, .. } => {
+                assert_eq!(arms.len(), 2);
+                assert!(matches!(arms[0].pattern.node, Pattern::Constructor { .. }));
+                assert!(matches!(arms[1].pattern.node, Pattern::Wildcard));
+            }
+            _ => panic!("expected Match"),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+}
+
+#[test]
+fn parse_placeholder_call_desugars_to_lambda() {
+    let Program { ast, decls } = parse_source("add(_, 10)");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::Lambda { params, body } => {
+                assert_eq!(params.len(), 1);
+                match &ast[*body].node {
+                    Expr::App { args, .. } => {
+                        assert!(matches!(&ast[args[0]].node, Expr::Var(n) if *n == params[0].name.node));
+                        assert!(matches!(ast[args[1]].node, Expr::IntLit(10)));
+                    }
+                    _ => panic!("expected App body"),
+                }
+            }
+            _ => panic!("expected Lambda"),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+}
+
+#[test]
+fn parse_power_is_right_associative() {
+    let Program { ast, decls } = parse_source("2 ** 3 ** 2");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::BinOp { op, lhs, rhs } => {
+                assert_eq!(*op, BinOp::Pow);
+                assert!(matches!(ast[*lhs].node, Expr::IntLit(2)));
+                assert!(matches!(ast[*rhs].node, Expr::BinOp { op: BinOp::Pow, .. }));
+            }
+            _ => panic!("expected BinOp::Pow"),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+}
+
+#[test]
+fn parse_power_binds_tighter_than_negation() {
+    let Program { ast, decls } = parse_source("-2 ** 2");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::UnaryOp { operand, .. } => {
+                assert!(matches!(ast[*operand].node, Expr::BinOp { op: BinOp::Pow, .. }));
+            }
+            _ => panic!("expected UnaryOp"),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+}
+
+#[test]
+fn parse_doc_comments_on_declarations() {
+    let Program { decls, .. } = parse_source(
+        "--- The answer.\nlet x = 42\n-- not a doc comment\nlet y = 1\n--- A colour.\n--- Pick one.\ntype Color = Red | Blue",
+    );
+    assert_eq!(decls[0].doc(), Some("The answer."));
+    assert_eq!(decls[1].doc(), None);
+    assert_eq!(decls[2].name(), Some("Color"));
+    assert_eq!(decls[2].doc(), Some("A colour.\nPick one."));
+}
+
+#[test]
+fn parse_prop_declaration() {
+    let decls = parse_source("type T = A\nprop \"rev\" (xs : [Int], n : Int) -> reverse(reverse(xs)) == xs\nlet prop = 1").decls;
+    assert_eq!(decls.len(), 3);
+    match &decls[1] {
+        Decl::Prop { name, params, .. } => {
+            assert_eq!(name, "rev");
+            assert_eq!(params.len(), 2);
+            assert!(params.iter().all(|p| p.type_ann.is_some()));
+        }
+        _ => panic!("expected Prop decl"),
+    }
+    assert_eq!(decls[2].name(), Some("prop"));
+}
+
+#[test]
+fn parse_prop_requires_annotations() {
+    let tokens = tokenize("prop \"bad\" (xs) -> true").unwrap();
+    assert!(parse(tokens).is_err());
+}
+
+#[test]
+fn parse_sig_becomes_let_annotation() {
+    let Program { decls, .. } = parse_source(
+        "--- Doubles.\nsig double : Int -> Int\nlet double = fn (x) -> x * 2\nval n : Int\nlet n = 1\nlet sig = 2",
+    );
+    assert_eq!(decls.len(), 3);
+    match &decls[0] {
+        Decl::Let { name, type_ann, .. } => {
+            assert_eq!(name.node, "double");
+            assert!(matches!(
+                type_ann.as_ref().map(|a| &a.node),
+                Some(TypeAnnotation::Arrow(..))
+            ));
+        }
+        _ => panic!("expected Let decl"),
+    }
+    assert_eq!(decls[0].doc(), Some("Doubles."));
+    assert_eq!(decls[1].name(), Some("n"));
+    assert_eq!(decls[2].name(), Some("sig"));
+}
+
+#[test]
+fn parse_sig_must_precede_its_definition() {
+    for source in [
+        "sig f : Int\nlet g = 1",
+        "sig f : Int\n42",
+        "sig f : Int\nlet f : Int = 1",
+    ] {
+        assert!(parse(tokenize(source).unwrap()).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn parse_extern_declaration() {
+    let decls = parse_source("--- From the host.\nextern parse_config : String -> Config\nextern now : Float\nlet extern = 1").decls;
+    assert_eq!(decls.len(), 3);
+    match &decls[0] {
+        Decl::Extern { name, type_ann, .. } => {
+            assert_eq!(name.node, "parse_config");
+            assert_eq!(type_ann.node.arity(), 1);
+        }
+        _ => panic!("expected Extern decl"),
+    }
+    assert_eq!(decls[0].doc(), Some("From the host."));
+    assert_eq!(decls[1].name(), Some("now"));
+    assert_eq!(decls[2].name(), Some("extern"));
+}
+
+#[test]
+fn parse_where_desugars_to_nested_lets() {
+    let Program { ast, decls } = parse_source("let x = a + b where a = 1, b = a\nlet where = 2");
+    let Decl::Let { body, .. } = &decls[0] else {
+        panic!("expected Let decl")
+    };
+    let Expr::Let { name, body, .. } = &ast[*body].node else {
+        panic!("expected Let expr")
+    };
+    assert_eq!(name.node, "a");
+    assert!(matches!(&ast[*body].node, Expr::Let { name, .. } if name.node == "b"));
+    assert_eq!(decls[1].name(), Some("where"));
+}
+
+#[test]
+fn parse_labeled_call() {
+    let Program { ast, decls } = parse_source("f(1, age: 36, name: x)\ng(1)");
+    match &decls[0] {
+        Decl::Expr(expr) => match &ast[*expr].node {
+            Expr::LabeledApp { args, .. } => {
+                let labels: Vec<_> =
+                    args.iter().map(|a| a.label.as_ref().map(|l| l.node.as_str())).collect();
+                assert_eq!(labels, vec![None, Some("age"), Some("name")]);
+            }
+            other => panic!("expected LabeledApp, got {:?}", other),
+        },
+        _ => panic!("expected Expr decl"),
+    }
+    assert!(matches!(&decls[1], Decl::Expr(e) if matches!(ast[*e].node, Expr::App { .. })));
+    assert!(parse(tokenize("f(age: 36, 1)").unwrap()).is_err());
+}
+
+#[test]
+fn parse_import_expression() {
+    let Program { ast, decls } = parse_source("import \"utils\"\nlet u = import \"lib/shapes\"");
+    assert!(matches!(decls[0], Decl::Import { .. }));
+    match &decls[1] {
+        Decl::Let { body, .. } => {
+            assert!(matches!(&ast[*body].node, Expr::Import(path) if path == "lib/shapes"))
+        }
+        _ => panic!("expected Let decl"),
+    }
+}
+
+#[test]
+fn parse_qualified_constructors() {
+    let Program { ast, decls } = parse_source("match Tree.Node(x) with | Tree.Leaf -> 0 | Node(n) -> n\nM.x");
+    let Decl::Expr(expr) = &decls[0] else { panic!("expected Expr decl") };
+    let Expr::Match { scrutinee, arms } = &ast[*expr].node else { panic!("expected Match") };
+    let Expr::App { func, .. } = &ast[*scrutinee].node else { panic!("expected App") };
+    assert!(matches!(&ast[*func].node, Expr::Var(n) if n == "Tree.Node"));
+    let names: Vec<_> = arms
+        .iter()
+        .map(|arm| match &arm.pattern.node {
+            Pattern::Constructor { name, .. } => name.as_str(),
+            other => panic!("expected constructor pattern, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(names, vec!["Tree.Leaf", "Node"]);
+    // Only `Type.Ctor` is a qualified name; other field access is unchanged
+    let Decl::Expr(access) = &decls[1] else { panic!("expected Expr decl") };
+    assert!(matches!(ast[*access].node, Expr::FieldAccess { .. }));
+}
+
+#[test]
+fn parse_trailing_commas() {
+    let Program { ast, decls } = parse_source(
+        "let a = [1, 2,]\nlet b = (1, 2,)\nlet c = f(1,\n  2,\n)\nlet d = fn (a, b,) -> a\n\
+         let e = { x: 1, }\nlet f = f(x: 1,)",
+    );
+    let bodies: Vec<_> = decls
+        .iter()
+        .map(|d| match d {
+            Decl::Let { body, .. } => &ast[*body].node,
+            _ => panic!("expected Let decl"),
+        })
+        .collect();
+    assert!(matches!(bodies[0], Expr::ListLit(elems) if elems.len() == 2));
+    assert!(matches!(bodies[1], Expr::TupleLit(elems) if elems.len() == 2));
+    assert!(matches!(bodies[2], Expr::App { args, .. } if args.len() == 2));
+    assert!(matches!(bodies[3], Expr::Lambda { params, .. } if params.len() == 2));
+    assert!(matches!(bodies[4], Expr::Record(fields) if fields.len() == 1));
+    assert!(matches!(bodies[5], Expr::LabeledApp { args, .. } if args.len() == 1));
+    // A lone comma isn't a list, and `(1,)` isn't a one-element tuple
+    for source in ["[,]", "f(,)", "(1,)", "fn (,) -> 1", "[1,,]"] {
+        assert!(parse(tokenize(source).unwrap()).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn parse_quote_and_unquote() {
+    let Program { ast, decls } = parse_source("quote (f(unquote(x) + 1))");
+    let Decl::Expr(expr) = &decls[0] else { panic!("expected Expr decl") };
+    let Expr::Quote(inner) = &ast[*expr].node else { panic!("expected Quote") };
+    let Expr::App { args, .. } = &ast[*inner].node else { panic!("expected App") };
+    let Expr::BinOp { lhs, .. } = &ast[args[0]].node else { panic!("expected BinOp") };
+    assert!(matches!(ast[*lhs].node, Expr::Unquote(_)));
+    // Outside a quote there is nothing to splice into
+    let err = parse(tokenize("let y = unquote(x)").unwrap()).unwrap_err();
+    assert!(matches!(err, lyra::error::LyraError::MisplacedUnquote { .. }), "{:?}", err);
+}
+
+#[test]
+fn parse_macro_decl() {
+    let Program { ast, decls } =
+        parse_source("macro when(cond, body) = quote if unquote(cond) then unquote(body) else ()");
+    let Decl::Macro { name, params, body, .. } = &decls[0] else { panic!("expected Macro decl") };
+    assert_eq!(name.node, "when");
+    assert_eq!(params.iter().map(|p| p.node.as_str()).collect::<Vec<_>>(), ["cond", "body"]);
+    assert!(matches!(&ast[*body].node, Expr::Quote(inner) if matches!(ast[*inner].node, Expr::If { .. })));
+    // Without the parenthesised parameters, `macro` is an ordinary name
+    assert!(matches!(&parse_source("macro + 1").decls[0], Decl::Expr(_)));
+}
+
+// ── Visitor ──
+
+#[test]
+fn visitor_reaches_every_node_in_source_order() {
+    use lyra::ast::visit::{walk_expr, walk_pattern, walk_program, Visitor};
+
+    #[derive(Default)]
+    struct Names(Vec<String>);
+    impl Visitor for Names {
+        fn visit_expr(&mut self, ast: &Ast, expr: ExprId) {
+            if let Expr::Var(name) = &ast[expr].node {
+                self.0.push(name.clone());
+            }
+            walk_expr(self, ast, expr);
+        }
+        fn visit_pattern(&mut self, pattern: &SpannedPattern) {
+            if let Pattern::Var(name) = &pattern.node {
+                self.0.push(format!("pat {}", name));
+            }
+            walk_pattern(self, pattern);
+        }
+        fn visit_type_ann(&mut self, ann: &SpannedTypeAnn) {
+            self.0.push(format!("type {}", ann.node));
+        }
+    }
+
+    let Program { ast, decls } = parse_source(
+        "let f = fn (xs : [Int]) -> match xs with | h :: t -> g(h, \"{t}\") | [] -> z\nf.len",
+    );
+    let mut names = Names::default();
+    walk_program(&mut names, &ast, &decls);
+    assert_eq!(
+        names.0,
+        vec!["type [Int]", "xs", "pat h", "pat t", "g", "h", "t", "z", "f"]
+    );
+}
+
+#[test]
+fn visitor_override_can_prune_subtrees() {
+    use lyra::ast::visit::{walk_expr, walk_program, Visitor};
+
+    struct OutsideLambdas(usize);
+    impl Visitor for OutsideLambdas {
+        fn visit_expr(&mut self, ast: &Ast, expr: ExprId) {
+            match &ast[expr].node {
+                Expr::Lambda { .. } => {}
+                Expr::Var(_) => self.0 += 1,
+                _ => walk_expr(self, ast, expr),
+            }
+        }
+    }
+
+    let mut count = OutsideLambdas(0);
+    let program = parse_source("map(fn (x) -> x + y, xs) |> length");
+    walk_program(&mut count, &program.ast, &program.decls);
+    assert_eq!(count.0, 3);
+}
+
+// ── Nesting depth ──
+
+// Debug builds spend a lot of stack per level, so these stay well under the
+// default limit to fit in a test thread.
+fn parse_nested(source: &str, limit: usize) -> Result<Program, lyra::error::LyraError> {
+    lyra::parser::with_max_depth(limit, || parse(tokenize(source).unwrap()))
+}
+
+#[test]
+fn parse_rejects_runaway_nesting_without_overflowing() {
+    let source = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+    let err = parse_nested(&source, 32).unwrap_err();
+    assert!(
+        matches!(err, lyra::error::LyraError::TooDeeplyNested { limit: 32, .. }),
+        "{:?}",
+        err
+    );
+    // Patterns and type annotations count toward the same limit
+    let pattern = format!("match x with | {}y{} -> 0", "(".repeat(500), ")".repeat(500));
+    let ty = format!("let f = fn (x : {}Int{}) -> x", "(".repeat(500), ")".repeat(500));
+    for source in [pattern, ty] {
+        let err = parse_nested(&source, 32).unwrap_err();
+        assert!(matches!(err, lyra::error::LyraError::TooDeeplyNested { .. }), "{:?}", err);
+    }
+}
+
+#[test]
+fn parse_nesting_limit_is_configurable() {
+    let source = format!("{}1{}", "(".repeat(20), ")".repeat(20));
+    assert!(parse_nested(&source, 32).is_ok());
+    let err = parse_nested(&source, 10).unwrap_err();
+    assert!(matches!(err, lyra::error::LyraError::TooDeeplyNested { limit: 10, .. }), "{:?}", err);
+    // The limit only applies inside the wrapper
+    assert_eq!(lyra::parser::DEFAULT_MAX_DEPTH, 128);
+    assert!(parse(tokenize(&source).unwrap()).is_ok());
+}
+
+// ── Serialization ──
+
+#[cfg(feature = "serde")]
+#[test]
+fn ast_round_trips_through_json() {
+    let program = parse_source(
+        "type Opt a = Some a | None\nlet rec f = fn (x : Int) -> match x with | 0 -> \"z\" | n -> \"{n}\"\nf(-1.5)",
+    );
+    let json = serde_json::to_string(&program).unwrap();
+    let back: Program = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    assert_eq!(format!("{:?}", back), format!("{:?}", program));
+}
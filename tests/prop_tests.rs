@@ -0,0 +1,79 @@
+use lyra::eval::value::Value;
+use lyra::testing::{run_props, PropConfig, PropOutcome};
+
+fn counterexample(source: &str) -> Vec<(String, Value)> {
+    let results = run_props(source, "props.lyra", PropConfig::default()).unwrap();
+    match &results[0].outcome {
+        PropOutcome::Failed { counterexample, .. } => counterexample.clone(),
+        PropOutcome::Passed => panic!("expected '{}' to fail", results[0].name),
+    }
+}
+
+// ── Running ──
+
+#[test]
+fn prop_passing_properties() {
+    let source = "let double = fn (n) -> n * 2\n\
+                  prop \"rev involutive\" (xs : [Int]) -> reverse(reverse(xs)) == xs\n\
+                  prop \"double even\" (n : Int) -> double(n) % 2 == 0\n\
+                  prop \"tuples\" (p : (Bool, String), u : ()) -> true";
+    let results = run_props(source, "props.lyra", PropConfig::default()).unwrap();
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|r| r.passed() && r.cases == 100));
+}
+
+#[test]
+fn prop_same_seed_same_result() {
+    let source = "prop \"small\" (xs : [Int]) -> sum(xs) < 20";
+    let config = PropConfig { cases: 50, seed: 7 };
+    let a = run_props(source, "props.lyra", config).unwrap();
+    let b = run_props(source, "props.lyra", config).unwrap();
+    assert_eq!(a[0].cases, b[0].cases);
+}
+
+#[test]
+fn prop_rejects_ungeneratable_types() {
+    let source = "prop \"fns\" (f : Int -> Int) -> true";
+    assert!(run_props(source, "props.lyra", PropConfig::default()).is_err());
+}
+
+// ── Shrinking ──
+
+#[test]
+fn prop_shrinks_int_to_boundary() {
+    let found = counterexample("prop \"small\" (n : Int) -> n < 7");
+    assert_eq!(found, vec![("n".to_string(), Value::Int(7))]);
+}
+
+#[test]
+fn prop_shrinks_list_to_minimal() {
+    let found = counterexample("prop \"short\" (xs : [Int]) -> length(xs) < 3");
+    assert_eq!(
+        found,
+        vec![(
+            "xs".to_string(),
+            Value::List(vec![Value::Int(0), Value::Int(0), Value::Int(0)])
+        )]
+    );
+}
+
+#[test]
+fn prop_runtime_errors_fail_with_message() {
+    let results = run_props(
+        "prop \"head\" (xs : [Int]) -> head(xs) == head(xs)",
+        "props.lyra",
+        PropConfig::default(),
+    )
+    .unwrap();
+    match &results[0].outcome {
+        PropOutcome::Failed {
+            counterexample,
+            error,
+            ..
+        } => {
+            assert_eq!(counterexample[0].1, Value::List(Vec::new()));
+            assert!(error.as_deref().unwrap().contains("empty list"));
+        }
+        PropOutcome::Passed => panic!("expected failure"),
+    }
+}
@@ -0,0 +1,57 @@
+use lyra::selftest::{run_selftest, Divergence};
+
+fn example(name: &str) -> (String, String) {
+    let path = format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), name);
+    (std::fs::read_to_string(&path).unwrap(), path)
+}
+
+#[test]
+fn selftest_examples_agree() {
+    for name in ["adt", "factorial", "interpolation", "pipes", "records", "showcase"] {
+        let (source, path) = example(&format!("{}.lyra", name));
+        let report = run_selftest(&source, &path).unwrap();
+        assert_eq!(report.divergence, None, "{}", name);
+        assert!(!report.tree_walker.output.is_empty(), "{}", name);
+    }
+}
+
+#[test]
+fn selftest_compares_values_and_errors() {
+    let report = run_selftest("let xs = [1, 2, 3]\nprintln(\"sum\")\nsum(xs)", "t.lyra").unwrap();
+    assert_eq!(report.divergence, None);
+    assert_eq!(report.vm.output, "sum\n");
+    assert_eq!(report.vm.result, Ok("6".to_string()));
+
+    let report = run_selftest("println(\"before\")\nprintln(1 / 0)", "t.lyra").unwrap();
+    assert_eq!(report.divergence, None);
+    assert!(matches!(&report.tree_walker.result, Err(e) if e.contains("division by zero")));
+
+    // A program that doesn't check isn't run
+    assert!(run_selftest("1 + \"a\"", "t.lyra").is_err());
+}
+
+#[test]
+fn selftest_reports_the_first_differing_line() {
+    // Each backend draws its own UUID
+    let report = run_selftest("println(\"same\")\nprintln(uuid_v4())\nprintln(1)", "t.lyra");
+    match report.unwrap().divergence {
+        Some(Divergence::Output {
+            line,
+            tree_walker,
+            vm,
+        }) => {
+            assert_eq!(line, 2);
+            assert_ne!(tree_walker, vm);
+        }
+        other => panic!("expected an output divergence, got {:?}", other),
+    }
+}
+
+#[test]
+fn selftest_backends_show_functions_alike() {
+    let source = "let inc = fn (x) -> x + 1\nlet add = fn (a, b) -> a + b\nprintln(inc)\nprintln(add(1))\nprintln(fn (x) -> inc(x))\ninc";
+    let report = run_selftest(source, "t.lyra").unwrap();
+    assert_eq!(report.divergence, None);
+    assert_eq!(report.vm.output, report.tree_walker.output);
+    assert!(report.vm.output.starts_with("<function>\n"));
+}
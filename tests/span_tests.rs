@@ -0,0 +1,53 @@
+use lyra::span::{LineIndex, Span};
+
+#[test]
+fn line_index_maps_offsets_both_ways() {
+    let source = "let a = 1\n\nlet b = \"é\" + a\r\nb";
+    let lines = LineIndex::new(source);
+    assert_eq!(lines.line_count(), 4);
+
+    assert_eq!(lines.line_col(0), (1, 1));
+    assert_eq!(lines.line_col(9), (1, 10));
+    assert_eq!(lines.line_col(10), (2, 1));
+    assert_eq!(lines.line_col(11), (3, 1));
+    // Offsets count characters, so the column after "é" is unaffected by its UTF-8 width
+    let a = source.chars().count() - 4;
+    assert_eq!(lines.line_col(a), (3, 15));
+    assert_eq!(lines.line_col(1000), (4, 2));
+
+    assert_eq!(lines.offset(3, 15), Some(a));
+    assert_eq!(lines.offset(1, 10), Some(9));
+    assert_eq!(lines.offset(1, 11), None);
+    assert_eq!(lines.offset(5, 1), None);
+    assert_eq!(lines.offset(0, 1), None);
+}
+
+#[test]
+fn line_index_extracts_lines() {
+    let source = "first\nsecond é\r\n\nlast";
+    let lines = LineIndex::new(source);
+    assert_eq!(lines.line_text(1), Some("first"));
+    assert_eq!(lines.line_text(2), Some("second é"));
+    assert_eq!(lines.line_text(3), Some(""));
+    assert_eq!(lines.line_text(4), Some("last"));
+    assert_eq!(lines.line_text(5), None);
+    assert_eq!(lines.line_span(1), Some(Span::new(0, 5)));
+    assert_eq!(lines.line_span(2), Some(Span::new(6, 14)));
+    assert_eq!(lines.line_span(4), Some(Span::new(17, 21)));
+}
+
+#[test]
+fn error_render_points_at_characters_after_non_ascii_text() {
+    let source = "let s = \"héllo\"\nlet n = s + 1";
+    let tokens = lyra::lexer::tokenize(source).unwrap();
+    let program = lyra::parser::parse(tokens).unwrap();
+    let mut env = lyra::types::env::TypeEnv::new();
+    let mut inferencer = lyra::types::infer::Inferencer::new();
+    inferencer
+        .infer_decl(&mut env, &program.ast, &program.decls[0])
+        .unwrap();
+    let err = inferencer
+        .infer_decl(&mut env, &program.ast, &program.decls[1])
+        .unwrap_err();
+    assert!(err.render(source, "t.lyra").contains("t.lyra:2:9"));
+}
@@ -1,5 +1,9 @@
+#![allow(clippy::approx_constant)]
+
+use lyra::ast::Program;
 use lyra::lexer::tokenize;
 use lyra::parser::parse;
+use lyra::span::LineIndex;
 use lyra::stdlib::register_stdlib;
 use lyra::types::env::TypeEnv;
 use lyra::types::infer::Inferencer;
@@ -8,7 +12,7 @@ use lyra::eval::env::Env;
 
 fn typecheck(source: &str) -> Result<(), String> {
     let tokens = tokenize(source).map_err(|errs| format!("{:?}", errs))?;
-    let decls = parse(tokens).map_err(|e| format!("{:?}", e))?;
+    let Program { ast, decls } = parse(tokens).map_err(|e| format!("{:?}", e))?;
 
     let mut type_env = TypeEnv::new();
     let runtime_env = Env::new();
@@ -19,7 +23,7 @@ fn typecheck(source: &str) -> Result<(), String> {
 
     for decl in &decls {
         inferencer
-            .infer_decl(&mut type_env, decl)
+            .infer_decl(&mut type_env, &ast, decl)
             .map_err(|e| format!("{:?}", e))?;
     }
     Ok(())
@@ -67,6 +71,21 @@ fn infer_int_arithmetic() {
     assert!(typecheck("7 % 3").is_ok());
 }
 
+#[test]
+fn infer_string_add() {
+    assert!(typecheck("let s = \"a\" + \"b\"\nstr_length(s + \"c\")").is_ok());
+    assert!(typecheck_fails("\"a\" - \"b\""));
+}
+
+#[test]
+fn infer_slices() {
+    assert!(typecheck("let xs = [1, 2, 3]\nsum(xs[1:])").is_ok());
+    assert!(typecheck("let s = \"abc\"\nstr_length(s[:2])").is_ok());
+    assert!(typecheck("let tail = fn (l) -> l[1:]\nsum(tail([1, 2]))").is_ok());
+    assert!(typecheck_fails("let n = 5\nn[1:2]"));
+    assert!(typecheck_fails("let xs = [1]\nxs[\"a\":]"));
+}
+
 #[test]
 fn infer_int_string_add_fails() {
     assert!(typecheck_fails("1 + \"hello\""));
@@ -219,6 +238,32 @@ fn infer_record_field_access() {
     ).is_ok());
 }
 
+#[test]
+fn reject_missing_record_field() {
+    assert!(typecheck_fails("let r = { x: 1 }\nr.z"));
+    // Fields asked of a parameter are only known to be there
+    assert!(typecheck("let f = fn (p) -> p.x + p.y\nf({ x: 1, y: 2, z: 3 })").is_ok());
+}
+
+#[test]
+fn infer_optional_field_access() {
+    let option = "type Option a = Some a | None\n";
+    assert!(typecheck(&format!("{option}let r = Some({{ a: 1 }})\nr?.a")).is_ok());
+    assert!(typecheck_fails(&format!("{option}let r = Some(3)\nr?.q")));
+    assert!(typecheck_fails(&format!("{option}let r = Some({{ a: 1 }})\nr?.q")));
+    // The field's type comes through the option
+    assert!(typecheck_fails(&format!(
+        "{option}let r = Some({{ a: 1 }})\nmatch r?.a with | Some(s) -> str_length(s) | None -> 0"
+    )));
+}
+
+#[test]
+fn constructor_patterns_bind_field_types() {
+    assert!(typecheck_fails(
+        "type Box a = Box a\nmatch Box(1) with | Box(s) -> str_length(s)"
+    ));
+}
+
 // ── String interpolation ──
 
 #[test]
@@ -297,3 +342,400 @@ fn infer_stdlib_str_utilities() {
 fn undefined_variable_fails() {
     assert!(typecheck_fails("undefined_var"));
 }
+
+// ── Tap ──
+
+#[test]
+fn infer_tap() {
+    assert!(typecheck("tap(fn (xs) -> length(xs), [1, 2])").is_ok());
+    assert!(typecheck("map(fn (xs) -> length(xs), [[1]])").is_ok());
+    assert!(typecheck_fails("tap(fn (x) -> x + 1, \"s\")"));
+}
+
+// ── Memo ──
+
+#[test]
+fn infer_memo() {
+    assert!(typecheck("let f = memo(fn (n) -> n * 2)\nf(3) + 1").is_ok());
+    assert!(typecheck_fails("let f = memo(fn (n) -> n * 2)\nf(\"s\")"));
+}
+
+// ── Numeric promotion ──
+
+#[test]
+fn infer_mixed_int_float_arithmetic() {
+    assert!(typecheck("int_of_float(1 + 2.5)").is_ok());
+    assert!(typecheck("let n = 3\nint_of_float(n / 2.0)").is_ok());
+    assert!(typecheck("if 1 < 2.5 then 1.0 else 2.0").is_ok());
+    assert!(typecheck_fails("int_to_string(1 + 2.5)"));
+    assert!(typecheck_fails("1 + \"s\" * 2.0"));
+}
+
+#[test]
+fn infer_promotion_through_generic_functions() {
+    assert!(typecheck("let f = fn (x) -> x + 2.5
+int_of_float(f(1))").is_ok());
+    assert!(typecheck("let f = fn (x) -> x + 2.5
+int_of_float(f(1.5))").is_ok());
+    assert!(typecheck("let g = fn (a, b) -> a + b
+int_of_float(g(1, 2.5))").is_ok());
+    assert!(typecheck("let g = fn (a, b) -> a + b
+int_to_string(g(1, 2))").is_ok());
+    assert!(typecheck("let g = fn (a, b) -> a + b
+str_length(g(\"a\", \"b\"))").is_ok());
+    for op in ["-", "*", "/", "%", "**"] {
+        let source = format!("let h = fn (a, b) -> a {} b\nint_of_float(h(1, 2.5))", op);
+        assert!(typecheck(&source).is_ok(), "{}", op);
+        assert!(typecheck_fails(&format!("let h = fn (a, b) -> a {} b\nh(\"a\", \"b\")", op)), "{}", op);
+    }
+    assert!(typecheck_fails("let f = fn (x) -> x + 2.5\nf(\"s\")"));
+    assert!(typecheck_fails("let g = fn (a, b) -> a + b\ng(1, \"s\")"));
+    assert!(typecheck_fails("let g = fn (a, b) -> a + b\nint_to_string(g(1, 2.5))"));
+    assert!(typecheck_fails("let g = fn (a, b) -> a + b\ng(true, false)"));
+    assert!(typecheck_fails("sig inc : a -> a\nlet inc = fn (x) -> x + 1"));
+}
+
+#[test]
+fn infer_promotion_in_comparisons() {
+    for op in ["==", "!=", "<", ">", "<=", ">="] {
+        assert!(typecheck(&format!("if 1 {} 1.0 then 1 else 2", op)).is_ok(), "{}", op);
+        let source = format!("let same = fn (a, b) -> a {} b\nsame(1, 2.5) && same(\"a\", \"b\")", op);
+        assert!(typecheck(&source).is_ok(), "{}", op);
+        assert!(typecheck_fails(&format!("1 {} \"a\"", op)), "{}", op);
+        assert!(typecheck_fails(&format!("let same = fn (a, b) -> a {} b\nsame(1, \"a\")", op)), "{}", op);
+    }
+}
+
+// ── Power ──
+
+#[test]
+fn infer_power() {
+    assert!(typecheck("int_to_string(2 ** 10)").is_ok());
+    assert!(typecheck("int_of_float(2.0 ** 0.5)").is_ok());
+    assert!(typecheck("int_of_float(2 ** 0.5)").is_ok());
+    assert!(typecheck("float_pow(2.0, 0.5)").is_ok());
+    assert!(typecheck_fails("float_pow(2, 3)"));
+}
+
+#[test]
+fn infer_prop_body_must_be_bool() {
+    assert!(typecheck("prop \"ok\" (xs : [Int]) -> length(xs) >= 0").is_ok());
+    assert!(typecheck_fails("prop \"not bool\" (n : Int) -> n + 1"));
+    assert!(typecheck_fails("prop \"wrong param\" (s : String) -> s + 1 > 0"));
+}
+
+
+// ── Signatures ──
+
+#[test]
+fn sig_checks_definition() {
+    assert!(typecheck("sig double : Int -> Int\nlet double = fn (x) -> x * 2\ndouble(2)").is_ok());
+    assert!(typecheck_fails("sig double : Int -> Int\nlet double = fn (x) -> x == 2"));
+    assert!(typecheck_fails("sig double : Int -> Int\nlet double = fn (x) -> x * 2\ndouble(\"a\")"));
+}
+
+#[test]
+fn extern_takes_its_declared_type() {
+    assert!(typecheck("extern double : Int -> Int\ndouble(2) + 1").is_ok());
+    assert!(typecheck_fails("extern double : Int -> Int\ndouble(\"a\")"));
+    assert!(typecheck("extern first : [a] -> a\nfirst([1]) + 1\nfirst([\"s\"])").is_ok());
+}
+
+#[test]
+fn sig_type_variables_are_rigid() {
+    assert!(typecheck("sig id : a -> a\nlet id = fn (x) -> x\nid(1)\nid(\"s\")").is_ok());
+    assert!(typecheck_fails("sig id : a -> a\nlet id = fn (x) -> 5"));
+    assert!(typecheck_fails("sig pair : a -> b -> (a, b)\nlet pair = fn (x, y) -> (x, x)"));
+    assert!(typecheck(
+        "sig len : [a] -> Int\nlet rec len = fn (xs) -> match xs with | [] -> 0 | _ :: t -> 1 + len(t)"
+    )
+    .is_ok());
+}
+
+#[test]
+fn sig_mismatch_points_into_the_body() {
+    let source = "sig greet : String -> Int\nlet greet = fn (name) ->\n  \"hello {name}\"";
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let err = Inferencer::new()
+        .infer_decl(&mut TypeEnv::new(), &ast, &decls[0])
+        .unwrap_err();
+    let (line, col) = LineIndex::new(source).line_col(err.span().unwrap().start);
+    assert_eq!((line, col), (3, 3));
+}
+
+// ── Exhaustiveness ──
+
+/// Missing patterns of the `match` that ends `source`, after its type
+/// declarations.
+fn missing_patterns(source: &str) -> Vec<String> {
+    use lyra::ast::{Decl, Expr};
+    use lyra::types::exhaustiveness::check_exhaustiveness;
+
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let mut env = TypeEnv::new();
+    let mut inferencer = Inferencer::new();
+    for decl in &decls[..decls.len() - 1] {
+        inferencer.infer_decl(&mut env, &ast, decl).unwrap();
+    }
+    let Some(Decl::Expr(expr)) = decls.last() else {
+        panic!("expected a trailing match")
+    };
+    let Expr::Match { arms, .. } = &ast[*expr].node else {
+        panic!("expected a trailing match")
+    };
+    let patterns: Vec<_> = arms.iter().map(|a| &a.pattern).collect();
+    check_exhaustiveness(&patterns, inferencer.constructors())
+}
+
+const OPTION: &str = "type Option a = Some a | None\n";
+
+#[test]
+fn exhaustiveness_of_nested_constructors() {
+    let src = format!("{}match x with\n| Some(Some(_)) -> 1\n| None -> 0", OPTION);
+    assert_eq!(missing_patterns(&src), vec!["Some(None)"]);
+    let src = format!(
+        "{}match x with\n| Some(Some(_)) -> 1\n| Some(None) -> 2\n| None -> 0",
+        OPTION
+    );
+    assert!(missing_patterns(&src).is_empty());
+}
+
+#[test]
+fn qualified_constructors_resolve_per_type() {
+    let types = "type Tree = Leaf | Node Tree Int Tree\ntype Heap = Empty | Node Int\n";
+    assert!(typecheck(&format!("{}let t : Tree = Tree.Node(Leaf, 1, Tree.Leaf)", types)).is_ok());
+    assert!(typecheck(&format!("{}let h : Heap = Node(1)", types)).is_ok());
+    assert!(typecheck_fails(&format!("{}let t : Tree = Node(Leaf, 1, Leaf)", types)));
+    assert!(typecheck_fails(&format!("{}let t = Tree.Empty", types)));
+    let src = format!("{}let t = Leaf\nmatch t with\n| Tree.Node(_, _, _) -> 1", types);
+    assert_eq!(missing_patterns(&src), vec!["Leaf"]);
+    let src = format!("{}let t = Leaf\nmatch t with\n| Tree.Node(_, _, _) -> 1\n| Leaf -> 0",
+        types);
+    assert!(missing_patterns(&src).is_empty());
+}
+
+#[test]
+fn exhaustiveness_of_tuples_and_booleans() {
+    let src = format!(
+        "{}match x with\n| (true, Some(_)) -> 1\n| (false, _) -> 2\n| (_, None) -> 3",
+        OPTION
+    );
+    assert!(missing_patterns(&src).is_empty());
+    let src = format!("{}match x with\n| (true, Some(_)) -> 1\n| (false, None) -> 2", OPTION);
+    assert_eq!(missing_patterns(&src), vec!["(true, None)", "(false, Some(_))"]);
+}
+
+#[test]
+fn exhaustiveness_of_lists_and_literals() {
+    assert_eq!(missing_patterns("match x with\n| [] -> 0\n| [a] -> 1"), vec!["(_ :: _ :: _)"]);
+    assert!(missing_patterns("match x with\n| [] -> 0\n| [a] -> 1\n| a :: b :: rest -> 2")
+        .is_empty());
+    assert_eq!(missing_patterns("match n with\n| 0 -> 1\n| 1 -> 2"), vec!["_"]);
+    assert!(missing_patterns("match n with\n| 0 -> 1\n| n -> 2").is_empty());
+}
+
+/// Indices of the unreachable arms of the `match` that ends `source`.
+fn redundant(source: &str) -> Vec<usize> {
+    use lyra::ast::{Decl, Expr};
+    use lyra::types::exhaustiveness::redundant_arms;
+
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let mut env = TypeEnv::new();
+    let mut inferencer = Inferencer::new();
+    for decl in &decls[..decls.len() - 1] {
+        inferencer.infer_decl(&mut env, &ast, decl).unwrap();
+    }
+    let Some(Decl::Expr(expr)) = decls.last() else {
+        panic!("expected a trailing match")
+    };
+    let Expr::Match { arms, .. } = &ast[*expr].node else {
+        panic!("expected a trailing match")
+    };
+    let patterns: Vec<_> = arms.iter().map(|a| &a.pattern).collect();
+    redundant_arms(&patterns, inferencer.constructors())
+}
+
+#[test]
+fn redundant_arms_are_found() {
+    assert_eq!(redundant("match n with\n| 1 -> 0\n| 1 -> 1\n| _ -> 2"), vec![1]);
+    assert_eq!(redundant("match n with\n| _ -> 0\n| 1 -> 1\n| n -> 2"), vec![1, 2]);
+    let src = format!(
+        "{}match x with\n| (Some(_), _) -> 0\n| (None, true) -> 1\n| (None, false) -> 2\n| (_, true) -> 3",
+        OPTION
+    );
+    assert_eq!(redundant(&src), vec![3]);
+    assert!(redundant("match xs with\n| [] -> 0\n| [a] -> 1\n| a :: rest -> 2").is_empty());
+}
+
+#[test]
+fn match_problems_are_reported_as_warnings() {
+    let source = "let f = fn (b) -> match b with\n| true -> 1\n| _ -> 2\n| false -> 3\n\
+                  let g = fn (b) -> match b with\n| true -> 1";
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let mut env = TypeEnv::new();
+    let mut inferencer = Inferencer::new();
+    for decl in &decls {
+        inferencer.infer_decl(&mut env, &ast, decl).unwrap();
+    }
+    let warnings = inferencer.take_warnings();
+    let codes: Vec<_> = warnings.iter().map(|w| w.code).collect();
+    assert_eq!(codes, vec!["W0002", "W0001"]);
+    let lines = LineIndex::new(source);
+    let arm = warnings[0].primary.as_ref().unwrap().span;
+    assert_eq!(lines.line_col(arm.start), (4, 3));
+    assert_eq!(lines.line_col(warnings[0].secondary[0].span.start), (3, 3));
+    assert_eq!(warnings[1].message, "non-exhaustive patterns: missing false");
+    assert!(inferencer.take_warnings().is_empty());
+}
+
+// ── Mismatch origins ──
+
+/// The primary and secondary `(line, col)` of the first error in `source`.
+fn mismatch_sites(source: &str) -> ((usize, usize), (usize, usize), String) {
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let mut env = TypeEnv::new();
+    let mut inferencer = Inferencer::new();
+    let err = decls
+        .iter()
+        .find_map(|d| inferencer.infer_decl(&mut env, &ast, d).err())
+        .expect("should not typecheck");
+    let d = err.diagnostic();
+    let lines = LineIndex::new(source);
+    let origin = &d.secondary[0];
+    (
+        lines.line_col(d.primary.unwrap().span.start),
+        lines.line_col(origin.span.start),
+        origin.message.clone(),
+    )
+}
+
+#[test]
+fn mismatch_points_at_both_sites() {
+    let (found, origin, why) = mismatch_sites("if true
+then 1
+else \"no\"");
+    assert_eq!((found, origin), ((3, 6), (2, 6)));
+    assert!(why.contains("`then` branch"), "{}", why);
+
+    let (found, origin, _) = mismatch_sites("[1,\n 2,\n true]");
+    assert_eq!((found, origin), ((3, 2), (1, 2)));
+
+    let (found, origin, _) = mismatch_sites("match 1 with\n| 0 -> 1\n| _ -> \"x\"");
+    assert_eq!((found, origin), ((3, 8), (2, 8)));
+
+    let (found, origin, why) = mismatch_sites("sig n : Int\nlet n =\n  true");
+    assert_eq!((found, origin), ((3, 3), (1, 9)));
+    assert!(why.contains("annotation"), "{}", why);
+}
+
+// ── Variadic builtins ──
+
+#[test]
+fn println_takes_any_number_of_values() {
+    assert!(typecheck("println(\"n =\", 1, [true], (1, \"a\"))").is_ok());
+    assert!(typecheck("let f = fn (x) -> x\nprintln(f(1), f(\"a\"), f)").is_ok());
+    // Shadowing a variadic builtin removes its extra arguments
+    assert!(typecheck_fails("let println = fn (x) -> x\nprintln(1, 2)"));
+    // Called with none, it prints an empty line
+    assert!(typecheck("let u : () = println()").is_ok());
+    assert!(typecheck_fails("let p = println\nlet u : () = p()"));
+}
+
+// ── Labeled arguments ──
+
+#[test]
+fn labeled_arguments_are_checked_by_name() {
+    let user = "let make_user = fn (name, age) -> if name == \"\" then age else age + 1\n";
+    assert!(typecheck(&format!("{}make_user(age: 36, name: \"Ada\")", user)).is_ok());
+    assert!(typecheck_fails(&format!("{}make_user(age: \"Ada\", name: 36)", user)));
+    assert!(typecheck_fails(&format!("{}make_user(name: \"Ada\", agee: 36)", user)));
+    assert!(typecheck_fails(&format!("{}make_user(name: \"Ada\")", user)));
+    assert!(typecheck_fails(&format!("{}make_user(\"Ada\", name: \"Bo\")", user)));
+}
+
+#[test]
+fn labels_need_declared_parameter_names() {
+    assert!(typecheck_fails("println(x: 1)"));
+    // A parameter shadowing a labeled function has no names of its own
+    assert!(typecheck_fails("let f = fn (a) -> a\nlet g = fn (f) -> f(a: 1)"));
+}
+
+// ── Debug trace ──
+
+fn trace(source: &str) -> Vec<String> {
+    let tokens = tokenize(source).unwrap();
+    let Program { ast, decls } = parse(tokens).unwrap();
+    let mut type_env = TypeEnv::new();
+    let mut inferencer = Inferencer::new();
+    inferencer.enable_trace();
+    let lines = LineIndex::new(source);
+    let mut events = Vec::new();
+    for decl in &decls {
+        let _ = inferencer.infer_decl(&mut type_env, &ast, decl);
+        events.extend(inferencer.take_trace().iter().map(|e| e.render(&lines)));
+    }
+    events
+}
+
+#[test]
+fn trace_records_inference_steps() {
+    let events = trace("let id = fn(x) -> x\nlet n = id(1)");
+    assert_eq!(
+        events,
+        vec![
+            "1:5: generalize id : t0 -> t0 => forall t0. t0 -> t0",
+            "2:9: instantiate forall t0. t0 -> t0 => t1 -> t1",
+            "2:9: unify t1 -> t1 ~ Int -> t2 => {t1 := Int, t2 := Int}",
+            "2:5: generalize n : Int => Int",
+        ]
+    );
+}
+
+#[test]
+fn trace_records_failed_unification() {
+    let events = trace("let b = if true then 1 else \"no\"");
+    let last = events.last().unwrap();
+    assert!(last.starts_with("1:29: unify Int ~ String => "), "{}", last);
+    assert!(last.contains("type mismatch"), "{}", last);
+}
+
+#[test]
+fn trace_is_off_by_default() {
+    let mut inferencer = Inferencer::new();
+    let Program { ast, decls } = parse(tokenize("let x = 1 + 2").unwrap()).unwrap();
+    inferencer.infer_decl(&mut TypeEnv::new(), &ast, &decls[0]).unwrap();
+    assert!(inferencer.take_trace().is_empty());
+}
+
+// ── Error recovery ──
+
+/// The line of each error in `source`, checking past failed declarations.
+fn error_lines(source: &str) -> Vec<usize> {
+    let Program { ast, decls } = parse(tokenize(source).unwrap()).unwrap();
+    let mut type_env = TypeEnv::new();
+    let mut inferencer = Inferencer::new();
+    register_stdlib(&mut type_env, &Env::new(), &mut TypeVarGen::new());
+    let index = LineIndex::new(source);
+    let mut lines = Vec::new();
+    for decl in &decls {
+        if let Err(e) = inferencer.infer_decl(&mut type_env, &ast, decl) {
+            lines.push(index.line_col(e.span().unwrap().start).0);
+            inferencer.recover_decl(&mut type_env, &ast, decl);
+        }
+    }
+    lines
+}
+
+#[test]
+fn failed_declarations_dont_hide_later_errors() {
+    let src = "let x = 1 + \"a\"\n\
+               let y = x + 1\n\
+               let s = str_length(x)\n\
+               let n : Int = \"s\"\n\
+               let m = n + 1\n\
+               let bad = str_length(n)\n\
+               let f = fn (a) -> a + true\n\
+               f(2)";
+    // Uses of x and f fit anything; n keeps its annotated Int
+    assert_eq!(error_lines(src), vec![1, 4, 6, 7]);
+}
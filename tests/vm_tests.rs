@@ -1,7 +1,13 @@
+#![allow(clippy::approx_constant)]
+
+use lyra::ast::Program;
+use lyra::compiler::bytecode::FunctionProto;
 use lyra::compiler::compile;
+use lyra::compiler::lyrc;
 use lyra::eval::env::Env;
 use lyra::eval::value::Value;
 use lyra::lexer::tokenize;
+use lyra::macros::expand_macros;
 use lyra::parser::parse;
 use lyra::stdlib::{register_stdlib, register_vm_stdlib};
 use lyra::types::env::TypeEnv;
@@ -11,8 +17,14 @@ use lyra::vm::VM;
 
 /// Compile and run source code on the VM, returning the last value on the stack.
 fn vm_run(source: &str) -> Result<Value, String> {
+    run_proto(vm_compile(source)?)
+}
+
+/// Type-check and compile source code to its top-level function.
+fn vm_compile(source: &str) -> Result<FunctionProto, String> {
     let tokens = tokenize(source).map_err(|errs| format!("{:?}", errs))?;
-    let decls = parse(tokens).map_err(|e| format!("{:?}", e))?;
+    let Program { mut ast, decls } = parse(tokens).map_err(|e| format!("{:?}", e))?;
+    expand_macros(&mut ast, &decls).map_err(|e| format!("{:?}", e))?;
 
     // Type check first
     let mut type_env = TypeEnv::new();
@@ -22,14 +34,14 @@ fn vm_run(source: &str) -> Result<Value, String> {
     register_stdlib(&mut type_env, &runtime_env, &mut gen);
     for decl in &decls {
         inferencer
-            .infer_decl(&mut type_env, decl)
+            .infer_decl(&mut type_env, &ast, decl)
             .map_err(|e| format!("{:?}", e))?;
     }
 
-    // Compile
-    let proto = compile(&decls).map_err(|e| e.to_string())?;
+    compile(&ast, &decls)
+}
 
-    // Run
+fn run_proto(proto: FunctionProto) -> Result<Value, String> {
     let mut vm = VM::new();
     register_vm_stdlib(&mut vm);
     vm.run(proto).map_err(|e| format!("{:?}", e))
@@ -110,6 +122,41 @@ fn vm_comparison() {
     assert_eq!(vm_run("1 >= 2").unwrap(), Value::Bool(false));
 }
 
+const ORDERING: &str = "type Opt = Nothing | Just Int\n\n\
+                        let nan = 0.0 / 0.0\n\
+                        let result = [\n\
+                          \"a\" <= \"b\", \"abd\" > \"abc\", [1, 2] < [1, 3], [1, 2] < [1, 2, 0],\n\
+                          (1, \"b\") < (1, \"c\"), Just(1) < Just(2), Just(3) >= Just(3),\n\
+                          Just(0) < Nothing, { a: 1, b: 2 } < { a: 1, b: 3 }, false < true,\n\
+                          !(nan < 1.0), !(nan >= 1.0), !([1.0] <= [nan])\n\
+                        ]\n\
+                        all(fn (b) -> b, result)";
+
+#[test]
+fn vm_orders_values_structurally() {
+    assert_eq!(vm_run(ORDERING).unwrap(), Value::Bool(true));
+    let err = vm_run("let f = fn (x) -> x\nf < f").unwrap_err();
+    assert!(err.contains("cannot order functions"), "{}", err);
+}
+
+const EQUALITY: &str = "type Opt = Nothing | Just Int\n\n\
+                        let nan = 0.0 / 0.0\n\
+                        let result = [\n\
+                          [1, 2] == [1, 2], [1, 2] != [1, 2, 3], Just(1) == Just(1),\n\
+                          Just(1) != Nothing, { a: 1, b: \"x\" } == { a: 1, b: \"x\" },\n\
+                          !(nan == nan), nan != nan, !([nan] == [nan]), 0.0 == -0.0\n\
+                        ]\n\
+                        all(fn (b) -> b, result)";
+
+#[test]
+fn vm_equality_is_structural_with_ieee_floats() {
+    assert_eq!(vm_run(EQUALITY).unwrap(), Value::Bool(true));
+    let err = vm_run("let f = fn (x) -> x\nf == f").unwrap_err();
+    assert!(err.contains("cannot compare functions"), "{}", err);
+    let err = vm_run("[(1, fn (x) -> x)] != [(1, fn (x) -> x)]").unwrap_err();
+    assert!(err.contains("cannot compare functions"), "{}", err);
+}
+
 #[test]
 fn vm_boolean_logic() {
     assert_eq!(vm_run("true && false").unwrap(), Value::Bool(false));
@@ -159,6 +206,17 @@ fn vm_recursive_function() {
     );
 }
 
+#[test]
+fn vm_local_recursive_let() {
+    let src = "let sum_to = fn (n) ->\n  let rec go = fn (i, acc) -> if i == 0 then acc else go(i - 1, acc + i) in\n  let k = 2 in\n  go(n, 0) * k\nsum_to(100)";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(10100));
+    let captured = "let f = fn (k) ->\n  let rec h = fn (i) -> if i == 0 then k else h(i - 1) in\n  map(h, [1, 2])\nf(7)";
+    assert_eq!(
+        vm_run(captured).unwrap(),
+        Value::List(vec![Value::Int(7), Value::Int(7)])
+    );
+}
+
 #[test]
 fn vm_closure() {
     assert_eq!(
@@ -253,17 +311,285 @@ fn vm_match_list() {
     );
 }
 
+const NEGATIVE_PATTERNS: &str =
+    "let sign = fn (n) -> match n with | -1 -> \"minus\" | 0 -> \"zero\" | _ -> \"other\"\n\
+     let half = fn (x) -> match x with | -0.5 -> true | _ -> false\n\
+     let first = fn (p) -> match p with | (-2, y) -> y | _ -> 0\n\
+     let result = (sign(-1), sign(0), sign(1), half(-0.5), half(0.5), first((-2, 7)))\n\
+     result";
+
+#[test]
+fn vm_match_negative_literals() {
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(
+        vm_run(NEGATIVE_PATTERNS).unwrap(),
+        Value::Tuple(vec![
+            s("minus"),
+            s("zero"),
+            s("other"),
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Int(7),
+        ])
+    );
+}
+
+#[test]
+fn vm_match_float() {
+    let src = "let kind = fn (x) -> match x with | 0.0 -> \"zero\" | 1.5 -> \"one and a half\" \
+               | _ -> \"other\"\n\
+               [kind(1.5), kind(-0.0), kind(0.5), kind(0.0 / 0.0)]";
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(
+        vm_run(src).unwrap(),
+        Value::List(vec![s("one and a half"), s("zero"), s("other"), s("other")])
+    );
+}
+
+#[test]
+fn vm_match_fixed_length_list() {
+    let src = "let sum2 = fn (xs) -> match xs with | [a, b] -> a + b | [a] -> a | _ -> 0\n\
+               [sum2([1, 2]), sum2([5]), sum2([1, 2, 3]), sum2([])]";
+    assert_eq!(
+        vm_run(src).unwrap(),
+        Value::List(vec![Value::Int(3), Value::Int(5), Value::Int(0), Value::Int(0)])
+    );
+}
+
+#[test]
+fn vm_match_nested_patterns() {
+    let src = "type Opt = Nothing | Just Int\n\
+               let f = fn (p) -> match p with | (Just(0), _) -> 0 | (Just(n), [m, 2]) -> n + m \
+               | (Nothing, x :: _) -> x | _ -> -1\n\
+               [f((Just(0), [])), f((Just(4), [5, 2])), f((Just(4), [5, 3])), f((Nothing, [7]))]";
+    assert_eq!(
+        vm_run(src).unwrap(),
+        Value::List(vec![Value::Int(0), Value::Int(9), Value::Int(-1), Value::Int(7)])
+    );
+    let err = vm_run("type Opt = Nothing | Just Int\nmatch [Just(1)] with | [Nothing] -> 0")
+        .unwrap_err();
+    assert!(err.contains("value: \"List [Just(1)]\""), "{}", err);
+}
+
+const STRING_PATTERNS: &str =
+    "let route = fn (req) -> match req with | \"GET \" ++ path -> path \
+     | \"POST \" ++ _ -> \"post\" | _ -> \"other\"\n\
+     let tag = fn (s) -> match s with | \"<\" ++ name ++ \">\" -> name | _ -> \"?\"\n\
+     let stem = fn (f) -> match f with | base ++ \".txt\" -> base | _ -> \"\"\n\
+     [route(\"GET /index\"), route(\"POST /x\"), route(\"PUT\"), tag(\"<b>\"), tag(\"<\"), \
+     stem(\"naïve.txt\"), stem(\"a.md\")]";
+
+#[test]
+fn vm_match_string_prefix_and_suffix() {
+    let s = |v: &str| Value::String(v.to_string());
+    assert_eq!(
+        vm_run(STRING_PATTERNS).unwrap(),
+        Value::List(vec![
+            s("/index"),
+            s("post"),
+            s("other"),
+            s("b"),
+            s("?"),
+            s("naïve"),
+            s(""),
+        ])
+    );
+}
+
+#[test]
+fn vm_match_failure_shows_the_value() {
+    let err = vm_run("type T = A | B | C\nmatch C with | A -> 0 | B -> 1").unwrap_err();
+    assert!(err.contains("MatchFailure"), "{}", err);
+    assert!(err.contains("value: \"C\", patterns: [\"A\", \"B\"]"), "{}", err);
+}
+
 // ── ADTs ──
 
+#[test]
+fn vm_qualified_constructors() {
+    let src = "type Tree = Leaf | Node Tree Int Tree\ntype Heap = Empty | Node Int\n\
+               let t = Tree.Node(Tree.Leaf, 4, Leaf)\n\
+               match t with | Tree.Node(_, v, _) -> v | Leaf -> 0";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(4));
+}
+
+#[test]
+fn vm_quote_splices_stack_values() {
+    let src = "let xs = [1, 2]\n\
+               let q = quote (match unquote(xs) with | [a, _] -> a + unquote(length(xs)))\nq";
+    assert_eq!(
+        vm_run(src).unwrap().to_string(),
+        "Match(ListLit([IntLit(1), IntLit(2)]), \
+         [(\"[a, _]\", BinOp(\"+\", Var(\"a\"), IntLit(2)))])"
+    );
+}
+
+#[test]
+fn vm_macros_expand_before_compiling() {
+    let src = "macro unless(cond, body) = quote if unquote(cond) then 0 else unquote(body)\n\
+               macro sum_with(n, e) = quote match (unquote(n), 1) with | (a, _) -> a + unquote(e)\n\
+               let a = 5\n\
+               unless(false, sum_with(2, a))";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(7));
+}
+
+#[test]
+fn vm_macro_free_names_are_not_captured_by_the_caller() {
+    let src = "macro len(s) = quote str_length(unquote(s))
+               let f = fn (str_length) -> len(\"ab\") + str_length
+               f(str_length: 10)";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(12));
+}
+
+#[test]
+fn vm_lyrc_round_trips_compiled_programs() {
+    let src = "type Shape = Circle Float | Rect Float Float\n\
+               let area = fn(s) -> match s with | Circle(0.0) -> 0.0 | Circle(r) -> r * r \
+               | Rect(w, h) -> w * h\n\
+               let pair = fn(xs) -> match xs with | [a, b] -> a + b | _ -> 0\n\
+               let rec count = fn(n) -> if n == 0 then 0 else 1 + count(n - 1)\n\
+               let scale = fn(k) -> fn(x) -> x * k\n\
+               let label = \"{area(Rect(2.0, 3.0)):.1}|{count(3)}|{scale(2)(21):>4}|\
+               {pair([1, 2])}\"\n\
+               (label, quote (1 + unquote(count(2))), { name: \"lyra\", tags: [-1, 2] })";
+    let program = lyrc::Program {
+        filename: "round_trip.lyra".to_string(),
+        source: src.to_string(),
+        main: vm_compile(src).unwrap(),
+    };
+    let decoded = lyrc::decode(&lyrc::encode(&program)).unwrap();
+    assert_eq!(decoded.filename, "round_trip.lyra");
+    assert_eq!(decoded.source, src);
+    assert_eq!(
+        run_proto(decoded.main).unwrap().to_string(),
+        run_proto(program.main).unwrap().to_string()
+    );
+}
+
+#[test]
+fn vm_lyrc_rejects_damaged_files() {
+    let main = vm_compile("1 + 2").unwrap();
+    let program = lyrc::Program {
+        filename: "f.lyra".to_string(),
+        source: "1 + 2".to_string(),
+        main,
+    };
+    let bytes = lyrc::encode(&program);
+    assert!(lyrc::decode(b"#!/bin/sh").unwrap_err().contains("not a compiled Lyra program"));
+    assert!(lyrc::decode(&bytes[..bytes.len() - 3]).unwrap_err().contains("truncated"));
+    let mut newer = bytes.clone();
+    newer[4] = 99;
+    assert!(lyrc::decode(&newer).unwrap_err().contains("format 99"));
+}
+
+#[test]
+fn vm_lyrc_rejects_invalid_bytecode() {
+    use lyra::compiler::bytecode::{Chunk, Op, UpvalueRef};
+    use lyra::span::Span;
+    use std::rc::Rc;
+    fn proto(name: &str, upvalue_count: usize, build: impl FnOnce(&mut Chunk)) -> FunctionProto {
+        let mut chunk = Chunk::new();
+        build(&mut chunk);
+        FunctionProto { name: name.to_string(), arity: 0, chunk, upvalue_count }
+    }
+    let load = |main: FunctionProto| {
+        let program = lyrc::Program {
+            filename: "f.lyra".to_string(),
+            source: String::new(),
+            main,
+        };
+        lyrc::decode(&lyrc::encode(&program)).map(|_| ())
+    };
+    let span = Span::default();
+
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::Constant(3), span);
+    }));
+    assert!(err.unwrap_err().contains("'<main>' at op 0: no constant 3"));
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::True, span);
+        c.emit(Op::JumpIfFalse(7), span);
+    }));
+    assert!(err.unwrap_err().contains("at op 1: jumps outside the code"));
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::Loop(2), span);
+    }));
+    assert!(err.unwrap_err().contains("jumps outside the code"));
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::Unit, span);
+        c.emit(Op::Add, span);
+    }));
+    assert!(err.unwrap_err().contains("at op 1: takes 2 values from a stack 1 high"));
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::Unit, span);
+        c.emit(Op::GetLocal(1), span);
+    }));
+    assert!(err.unwrap_err().contains("no local slot 1"));
+    // The jump leaves the stack one lower than the code it skips over
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::True, span);
+        c.emit(Op::JumpIfFalse(1), span);
+        c.emit(Op::Unit, span);
+        c.emit(Op::Pop, span);
+    }));
+    assert!(err.unwrap_err().contains("at op 3: stack is 2 high here by one path, 1 by another"));
+    let err = load(proto("<main>", 0, |c| {
+        c.emit(Op::Jump(1), span);
+        c.emit(Op::Unit, span);
+        c.emit(Op::Unit, span);
+    }));
+    assert!(err.unwrap_err().contains("at op 1: op is never reached"));
+
+    // Functions in the constant pool are checked too
+    let inner = proto("inner", 1, |c| {
+        c.emit(Op::GetUpvalue(4), span);
+    });
+    let err = load(proto("<main>", 0, |c| {
+        let idx = c.add_constant(Value::Function(Rc::new(inner)));
+        c.emit(Op::Unit, span);
+        c.emit(Op::Closure(idx, vec![UpvalueRef { is_local: true, index: 0 }]), span);
+    }));
+    assert!(err.unwrap_err().contains("'inner' at op 0: no upvalue 4"));
+    let err = load(proto("<main>", 0, |c| {
+        let idx = c.add_constant(Value::Int(1));
+        c.emit(Op::Closure(idx, Vec::new()), span);
+    }));
+    assert!(err.unwrap_err().contains("constant 0 isn't a function"));
+}
+
+#[test]
+fn vm_constant_pool_stores_each_literal_once() {
+    // `let rec` keeps the functions from being inlined into the main chunk
+    let src = "let rec f = fn(x) -> if x > 10 then x * 10 + 10 else x - 10\n\
+               let rec g = fn(s) -> s == \"a\" || s == \"b\" || s == \"a\"\n\
+               let result = (f(1) + 10, g(\"a\"), 0.0, 2.5, 0.0)\n\
+               result";
+    let main = vm_compile(src).unwrap();
+    let Some(Value::Function(f)) = main.chunk.constants.iter().find(|c| c.is_callable()) else {
+        panic!("expected f's prototype among the constants");
+    };
+    assert_eq!(f.chunk.constants.len(), 1);
+    let literals: Vec<String> = main
+        .chunk
+        .constants
+        .iter()
+        .filter(|c| !c.is_callable())
+        .map(|c| c.to_string())
+        .collect();
+    // Pools are per chunk, so `10` is stored again here
+    assert_eq!(literals, ["1", "10", "\"a\"", "0.0", "2.5"]);
+    assert_eq!(vm_run(src).unwrap().to_string(), "(1, true, 0.0, 2.5, 0.0)");
+}
+
 #[test]
 fn vm_adt_construction() {
     let result = vm_run(
         "type Color = Red | Green | Blue\nlet c = Red\nc"
     ).unwrap();
     match result {
-        Value::Adt { constructor, fields } => {
-            assert_eq!(constructor, "Red");
-            assert!(fields.is_empty());
+        Value::Adt(adt) => {
+            assert_eq!(adt.constructor, "Red");
+            assert!(adt.fields.is_empty());
         }
         _ => panic!("expected ADT value"),
     }
@@ -275,9 +601,9 @@ fn vm_adt_with_fields() {
         "type Shape = Circle Int\nlet s = Circle(5)\ns"
     ).unwrap();
     match result {
-        Value::Adt { constructor, fields } => {
-            assert_eq!(constructor, "Circle");
-            assert_eq!(fields, vec![Value::Int(5)]);
+        Value::Adt(adt) => {
+            assert_eq!(adt.constructor, "Circle");
+            assert_eq!(adt.fields, vec![Value::Int(5)]);
         }
         _ => panic!("expected ADT value"),
     }
@@ -325,6 +651,81 @@ fn vm_interpolation() {
     );
 }
 
+#[test]
+fn vm_interpolation_format_specs() {
+    assert_eq!(
+        vm_run("let pi = 3.14159\nlet n = -42\n\"{pi:.2}|{n:>6}|{n:06}|{\"abc\":-^7}|{true:<6}|\"")
+            .unwrap(),
+        Value::String("3.14|   -42|-00042|--abc--|true  |".to_string())
+    );
+}
+
+#[test]
+fn vm_builds_strings_in_one_op() {
+    use lyra::compiler::bytecode::Op;
+    let src = "let tag = \"b\"\n\
+               let n = 3\n\
+               let a = \"<{tag}>{n}\"\n\
+               let b = \"<\" + tag + \">\" + to_string(n)\n\
+               let result = (a, b, 1 + 2 + 3.5)\n\
+               result";
+    let main = vm_compile(src).unwrap();
+    let builds: Vec<&Op> = main.chunk.code.iter().filter(|op| matches!(op, Op::BuildString(_))).collect();
+    assert!(matches!(builds[..], [Op::BuildString(4), Op::BuildString(4)]), "{}", main.chunk.disassemble("main"));
+    assert_eq!(run_proto(main).unwrap().to_string(), "(\"<b>3\", \"<b>3\", 6.5)");
+}
+
+#[test]
+fn vm_fuses_hot_op_sequences() {
+    use lyra::compiler::bytecode::{Comparison, Op};
+    // `let rec` keeps the functions from being inlined into the main chunk
+    let src = "let rec sum = fn(n, acc) -> if n <= 0 then acc else sum(n - 1, acc + n)\n\
+               let rec bump = fn(x, c) -> x + (if c then 1 else 2)\n\
+               let result = (sum(100, 0), bump(10, true), bump(10, false), sum(3, 0) + 1)\n\
+               result";
+    let main = vm_compile(src).unwrap();
+    let functions: Vec<Vec<Op>> = main
+        .chunk
+        .constants
+        .iter()
+        .filter_map(|c| match c {
+            Value::Function(f) => Some(f.chunk.code.clone()),
+            _ => None,
+        })
+        .collect();
+    let [sum, bump] = &functions[..] else {
+        panic!("expected the two functions among the constants")
+    };
+    assert!(sum.iter().any(|op| matches!(op, Op::GetLocal2Add(1, 0))), "{:?}", sum);
+    assert!(sum.iter().any(|op| matches!(op, Op::CompareJump(Comparison::LessEqual, _))), "{:?}", sum);
+    // The else branch jumps to the `Add`, so it stays apart from the `2`
+    assert!(!bump.iter().any(|op| matches!(op, Op::ConstantAdd(_))), "{:?}", bump);
+    assert!(main.chunk.code.iter().any(|op| matches!(op, Op::ConstantAdd(_))));
+
+    let program = lyrc::Program {
+        filename: "fused.lyra".to_string(),
+        source: src.to_string(),
+        main,
+    };
+    let decoded = lyrc::decode(&lyrc::encode(&program)).unwrap();
+    assert_eq!(run_proto(decoded.main).unwrap().to_string(), "(5050, 11, 12, 7)");
+}
+
+#[test]
+fn vm_chunk_stores_a_span_per_run_of_ops() {
+    use lyra::compiler::bytecode::{Chunk, Op};
+    use lyra::span::Span;
+    let (a, b) = (Span::new(0, 5), Span::new(6, 9));
+    let mut chunk = Chunk::new();
+    for span in [a, a, a, b, a] {
+        chunk.emit(Op::Unit, span);
+    }
+    let spans: Vec<Option<Span>> = (0..6).map(|i| chunk.span(i)).collect();
+    assert_eq!(spans, [Some(a), Some(a), Some(a), Some(b), Some(a), None]);
+    let listing = chunk.disassemble("runs");
+    assert_eq!(listing.matches(" @").count(), 3, "{}", listing);
+}
+
 // ── Records ──
 
 #[test]
@@ -347,8 +748,80 @@ fn vm_record_field_access() {
     );
 }
 
+#[test]
+fn vm_record_field_punning() {
+    assert_eq!(
+        vm_run("let make = fn (x, y) -> { x, y, sum: x + y }\nlet r = make(1, 2)\nr.x + r.y + r.sum").unwrap(),
+        Value::Int(6)
+    );
+}
+
+#[test]
+fn vm_optional_chaining() {
+    let src = "type Option a = Some a | None\n\
+               let users = [Some({ name: \"Ada\", address: { city: \"London\" } }), None]\n\
+               let cities = map(fn (u) -> u?.address?.city, users)\n\
+               cities";
+    assert_eq!(vm_run(src).unwrap().to_string(), "[Some(\"London\"), None]");
+}
+
+#[test]
+fn vm_optional_chaining_keeps_optional_fields_wrapped() {
+    let src = "type Option a = Some a | None\n\
+               let user = Some({ address: Some({ city: \"London\" }) })\n\
+               let city = match user?.address with | Some(Some(a)) -> Some(a.city) | _ -> None\n\
+               let result = (user?.address, city)\n\
+               result";
+    assert_eq!(
+        vm_run(src).unwrap().to_string(),
+        "(Some(Some({ city: \"London\" })), Some(\"London\"))"
+    );
+    let err = vm_run("type Option a = Some a | None\nlet user = Some({ address: Some({ city: 1 }) })\nuser?.address?.city")
+        .unwrap_err();
+    assert!(err.contains("TypeMismatch"), "{}", err);
+}
+
+#[test]
+fn vm_slices_clamp_and_count_chars() {
+    let src = "let xs = [1, 2, 3, 4, 5]\n\
+               let s = \"héllo wörld\"\n\
+               let result = ((xs[1:4], xs[:2], xs[3:], xs[-2:], xs[4:2], xs[-10:10]), \
+               (s[1:4], s[6:], s[-3:], s[20:], s[:]))\n\
+               result";
+    assert_eq!(
+        vm_run(src).unwrap().to_string(),
+        "(([2, 3, 4], [1, 2], [4, 5], [4, 5], [], [1, 2, 3, 4, 5]), \
+         (\"éll\", \"wörld\", \"rld\", \"\", \"héllo wörld\"))"
+    );
+}
+
 // ── New stdlib ──
 
+#[test]
+fn vm_stdlib_type_of() {
+    let src = "type Shape = Circle Int | Square Int\n\
+               let names = [type_of(1), type_of([\"a\"]), type_of([[1.5]]), type_of([]),\n\
+               type_of({ x: 1 }), type_of(Circle(2)), type_of(fn (x) -> x), type_of(()),\n\
+               type_of((1, true))]\nnames";
+    let names = ["Int", "[String]", "[[Float]]", "List", "Record", "Circle", "Function", "()", "Tuple"];
+    assert_eq!(
+        vm_run(src).unwrap(),
+        Value::List(names.iter().map(|n| Value::String(n.to_string())).collect())
+    );
+}
+
+#[test]
+fn vm_unapplied_constructors_are_functions() {
+    let src = "type Opt a = Some a | None\n\
+               let names = (type_of(Some), type_of(Opt.Some), type_of(None), map(Some, [1, 2]))\nnames";
+    let s = |v: &str| Value::String(v.to_string());
+    let some = |n| Value::adt("Some".to_string(), vec![Value::Int(n)]);
+    assert_eq!(
+        vm_run(src).unwrap(),
+        Value::Tuple(vec![s("Function"), s("Function"), s("None"), Value::List(vec![some(1), some(2)])])
+    );
+}
+
 #[test]
 fn vm_stdlib_take() {
     assert_eq!(
@@ -418,3 +891,677 @@ fn vm_stdlib_str_utilities() {
         Value::String("hello".to_string())
     );
 }
+
+// ── If let ──
+
+#[test]
+fn vm_if_let_match() {
+    assert_eq!(
+        vm_run("type Option = Some Int | None\nif let Some(x) = Some(41) then x + 1 else 0").unwrap(),
+        Value::Int(42)
+    );
+}
+
+#[test]
+fn vm_if_let_fallback() {
+    assert_eq!(
+        vm_run("type Option = Some Int | None\nif let Some(x) = None then x + 1 else 0").unwrap(),
+        Value::Int(0)
+    );
+}
+
+// ── Tap ──
+
+#[test]
+fn vm_tap_returns_argument() {
+    assert_eq!(
+        vm_run("[1, 2, 3] |> tap(fn (xs) -> length(xs)) |> sum").unwrap(),
+        Value::Int(6)
+    );
+}
+
+#[test]
+fn vm_pipe_tap_sugar() {
+    assert_eq!(
+        vm_run("let f = fn (x) -> x * 2\n5 |>! f |> f").unwrap(),
+        Value::Int(10)
+    );
+}
+
+// ── Memo ──
+
+#[test]
+fn vm_memo_fib() {
+    let src = "let rec fib = memo(fn (n) -> if n < 2 then n else fib(n - 1) + fib(n - 2))\nfib(15)";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(610));
+}
+
+#[test]
+fn vm_bench_returns_timings() {
+    let src = "let s = bench(fn () -> sum(range(0, 10)), 5)\n\
+               let result = (s.iterations, s.min_ms <= s.max_ms)\nresult";
+    assert_eq!(vm_run(src).unwrap().to_string(), "(5, true)");
+}
+
+#[test]
+fn vm_memo_multi_arg() {
+    assert_eq!(
+        vm_run("let add = memo(fn (a, b) -> a + b)\nadd(1, 2) + add(1, 2) + add(2)(3)").unwrap(),
+        Value::Int(11)
+    );
+}
+
+// ── Placeholder application ──
+
+#[test]
+fn vm_placeholder_call() {
+    assert_eq!(
+        vm_run("let sub = fn (a, b) -> a - b\n[1, 2, 3] |> map(sub(_, 1)) |> sum").unwrap(),
+        Value::Int(3)
+    );
+}
+
+#[test]
+fn vm_placeholder_multiple() {
+    assert_eq!(
+        vm_run("let f = fn (a, b, c) -> a * 100 + b * 10 + c\nlet g = f(_, 2, _)\ng(1, 3)").unwrap(),
+        Value::Int(123)
+    );
+}
+
+// ── Labeled arguments ──
+
+#[test]
+fn vm_labeled_arguments_reorder() {
+    assert_eq!(
+        vm_run("let sub = fn (a, b) -> a - b\nsub(b: 1, a: 10) + sub(20, b: 2)").unwrap(),
+        Value::Int(27)
+    );
+    assert_eq!(
+        vm_run("let f = fn (x) -> let g = fn (p, q) -> p * 10 + q in g(q: x, p: 2)\nf(7)")
+            .unwrap(),
+        Value::Int(27)
+    );
+}
+
+// ── Partial application of compiled functions ──
+
+#[test]
+fn vm_partial_application_of_compiled_function() {
+    assert_eq!(
+        vm_run("let add = fn (a, b) -> a + b\nlet inc = add(1)\ninc(41)").unwrap(),
+        Value::Int(42)
+    );
+}
+
+#[test]
+fn vm_partial_application_of_closure() {
+    assert_eq!(
+        vm_run("let make = fn (k) -> fn (a, b, c) -> k + a + b + c\nlet f = make(1000)\nlet g = f(100)\nlet h = g(10)\nh(1)").unwrap(),
+        Value::Int(1111)
+    );
+}
+
+#[test]
+fn vm_partial_application_in_tail_position() {
+    assert_eq!(
+        vm_run("let add = fn (a, b) -> a + b\nlet adder = fn (n) -> add(n)\nmap(adder(10), [1, 2])").unwrap(),
+        Value::List(vec![Value::Int(11), Value::Int(12)])
+    );
+}
+
+#[test]
+fn vm_tail_call_of_partial_application_reuses_the_frame() {
+    // Deeper than the VM's 256 frames
+    let src = "let rec loop = fn (n, acc) -> if n == 0 then acc else loop(n - 1)(acc + 1)\nloop(10000, 0)";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(10000));
+}
+
+// ── Callbacks from builtins ──
+
+#[test]
+fn vm_callbacks_see_globals_defined_after_the_first_builtin_call() {
+    let src = "println(\"start\")\nlet k = 3\nlet scale = fn (x) -> x * k\nrange(0, 4) |> map(scale) |> filter(fn (x) -> x > k) |> sum";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(15));
+}
+
+// ── Over-application of compiled functions ──
+
+#[test]
+fn vm_over_application_reapplies_extra_args() {
+    assert_eq!(
+        vm_run("let f = fn (a) -> fn (b) -> a * 10 + b\nf(4, 2)").unwrap(),
+        Value::Int(42)
+    );
+}
+
+#[test]
+fn vm_over_application_of_closure_in_tail_position() {
+    assert_eq!(
+        vm_run("let make = fn (k) -> fn (a) -> fn (b) -> k + a + b\nlet go = fn (x) -> make(x, 10, 1)\ngo(100) + 1").unwrap(),
+        Value::Int(112)
+    );
+}
+
+// ── Call failures name the callee ──
+
+#[test]
+fn vm_builtin_failure_names_callee() {
+    let err = vm_run("let xs = tail([1])\nhead(xs)").unwrap_err();
+    assert!(
+        err.contains("CallFailed { name: \"head\", arity: 1, found: 1, message: \"empty list\""),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn vm_partial_builtin_failure_counts_all_args() {
+    let err = vm_run("let at = nth([1, 2])\nat(5)").unwrap_err();
+    assert!(err.contains("name: \"nth\", arity: 2, found: 2"), "{}", err);
+}
+
+// ── Numeric promotion ──
+
+#[test]
+fn vm_mixed_int_float_arithmetic() {
+    assert_eq!(vm_run("1 + 2.5").unwrap(), Value::Float(3.5));
+    assert_eq!(vm_run("let n = 7\nn / 2.0").unwrap(), Value::Float(3.5));
+    assert_eq!(vm_run("2.0 * 3 - 1").unwrap(), Value::Float(5.0));
+    assert_eq!(vm_run("3 > 2.5").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn vm_promotion_through_generic_functions() {
+    assert_eq!(vm_run("let f = fn (x) -> x + 2.5\nf(1)").unwrap(), Value::Float(3.5));
+    assert_eq!(vm_run("let g = fn (a, b) -> a + b\ng(1, 2.5)").unwrap(), Value::Float(3.5));
+    assert_eq!(vm_run("let g = fn (a, b) -> a + b\ng(1, 2)").unwrap(), Value::Int(3));
+    assert_eq!(vm_run("let half = fn (x) -> x / 2\nhalf(7)").unwrap(), Value::Int(3));
+    assert_eq!(vm_run("let half = fn (x) -> x / 2\nhalf(7.0)").unwrap(), Value::Float(3.5));
+    assert_eq!(vm_run("1 == 1.0 && 2 != 2.5").unwrap(), Value::Bool(true));
+}
+
+// ── Power ──
+
+#[test]
+fn vm_power_operator() {
+    assert_eq!(vm_run("2 ** 10").unwrap(), Value::Int(1024));
+    assert_eq!(vm_run("2 ** 3 ** 2").unwrap(), Value::Int(512));
+    assert_eq!(vm_run("-2 ** 2").unwrap(), Value::Int(-4));
+    assert_eq!(vm_run("4.0 ** 0.5").unwrap(), Value::Float(2.0));
+    assert_eq!(vm_run("2 ** 0.5 * 2 ** 0.5 > 1.99").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn vm_power_rejects_negative_int_exponent() {
+    assert!(vm_run("2 ** -1").unwrap_err().contains("negative exponent"));
+    assert!(vm_run("pow(2, -1)").unwrap_err().contains("negative exponent"));
+}
+
+// ── Euclidean division ──
+
+#[test]
+fn vm_euclidean_division() {
+    let pair = |q, r| Value::Tuple(vec![Value::Int(q), Value::Int(r)]);
+    let src = "[divmod(7, 2), divmod(-7, 2), divmod(7, -2), divmod(-7, -2)]";
+    assert_eq!(
+        vm_run(src).unwrap(),
+        Value::List(vec![pair(3, 1), pair(-4, 1), pair(-3, 1), pair(4, 1)])
+    );
+    assert_eq!(
+        vm_run("(div_euclid(-7, 2), mod_euclid(-7, 2), -7 % 2)").unwrap(),
+        Value::Tuple(vec![Value::Int(-4), Value::Int(1), Value::Int(-1)])
+    );
+    assert!(vm_run("mod_euclid(1, 0)").unwrap_err().contains("division by zero"));
+    let min = "let min = -9223372036854775807 - 1\n";
+    assert_eq!(vm_run(&format!("{}mod_euclid(min, -1)", min)).unwrap(), Value::Int(0));
+    assert!(vm_run(&format!("{}divmod(min, -1)", min)).unwrap_err().contains("integer overflow"));
+}
+
+// ── Inlining ──
+
+#[test]
+fn vm_inlines_small_functions_and_constructors() {
+    use lyra::compiler::bytecode::Op;
+    let src = "type Shape = Circle Float | Rect Float Float\n\
+               let inc = fn(x) -> x + 1\n\
+               let double = fn(x) -> x * 2\n\
+               let shapes = (Shape.Rect(1.0, 2.0), Circle(0.5))\n\
+               let result = (1 |> inc |> double, double(inc(3)), shapes)\n\
+               result";
+    let main = vm_compile(src).unwrap();
+    let calls = main.chunk.code.iter().filter(|op| matches!(op, Op::Call(_))).count();
+    assert_eq!(calls, 0, "{}", main.chunk.disassemble("main"));
+    assert_eq!(run_proto(main).unwrap().to_string(), "(4, 8, (Rect(1.0, 2.0), Circle(0.5)))");
+}
+
+#[test]
+fn vm_inlining_respects_scopes() {
+    // A global the body uses but the call site shadows, swapped parameter
+    // names, a local function and a parameter shadowing it
+    let src = "let k = 100\n\
+               let addk = fn(x) -> x + k\n\
+               let shadow = fn(k) -> addk(k)\n\
+               let sub = fn(a, b) -> a - b\n\
+               let flip = fn(a, b) -> sub(b, a)\n\
+               let local = fn(n) ->\n\
+               let sq = fn(x) -> x * x in let f = fn(sq) -> sq + 1 in sq(n) + f(n)\n\
+               let result = (shadow(1), flip(10, 3), local(5))\n\
+               result";
+    assert_eq!(vm_run(src).unwrap().to_string(), "(101, -7, 31)");
+    // Functions bound twice are called, not inlined, so each call sees the latest
+    let src = "let f = fn(x) -> x + 1\n\
+               let g = fn(x) -> f(x)\n\
+               let f = fn(x) -> x * 10\n\
+               g(2)";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(20));
+}
+
+#[test]
+fn vm_inlined_tail_calls_stay_tail_calls() {
+    let src = "let next = fn(f, i, acc) -> f(i - 1, acc + 1)\n\
+               let rec count = fn(i, acc) -> if i == 0 then acc else next(count, i, acc)\n\
+               count(100000, 0)";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(100000));
+}
+
+#[test]
+fn vm_locals_sit_above_temporaries() {
+    let src = "let result = (10 + (let x = 2 in x * 3), [1, match 4 with | n -> n + 1])\n\
+               result";
+    assert_eq!(vm_run(src).unwrap().to_string(), "(16, [1, 5])");
+}
+
+// ── Hashing ──
+
+#[test]
+fn vm_hash_and_digests_match_the_interpreter() {
+    assert_eq!(
+        vm_run("[hash([1, 2]) == hash([1, 2]), sha256(\"abc\") == sha256(utf8_encode(\"abc\"))]").unwrap(),
+        Value::List(vec![Value::Bool(true), Value::Bool(true)])
+    );
+    assert_eq!(
+        vm_run("md5(\"\")").unwrap(),
+        Value::String("d41d8cd98f00b204e9800998ecf8427e".to_string())
+    );
+}
+
+#[test]
+fn vm_uuid_v4_is_called_with_no_arguments() {
+    assert_eq!(
+        vm_run("let id = uuid_v4()\n[uuid_valid(id), str_length(id) == 36, id == uuid_v4()]").unwrap(),
+        Value::List(vec![Value::Bool(true), Value::Bool(true), Value::Bool(false)])
+    );
+}
+
+#[test]
+fn vm_memory_stats_reports_the_vm_globals() {
+    assert_eq!(
+        vm_run("let xs = range(0, 1000)\nlet s = memory_stats().vm\nlet r = (s.bindings > 0, s.values > 1000)\nr").unwrap(),
+        Value::Tuple(vec![Value::Bool(true), Value::Bool(true)])
+    );
+}
+
+#[test]
+fn vm_datetime_builtins_match_the_interpreter() {
+    assert_eq!(
+        vm_run("let d = datetime_from_millis(951782400000)\n\
+                let r = (datetime_format(\"%Y-%m-%d %A\", d), datetime_day_of_year(d), datetime_now().year > 2000)\nr")
+        .unwrap(),
+        Value::Tuple(vec![
+            Value::String("2000-02-29 Tuesday".to_string()),
+            Value::Int(60),
+            Value::Bool(true)
+        ])
+    );
+}
+
+// ── Closure captures ──
+
+/// The function prototypes pooled in `proto`'s chunk, in order.
+fn nested_protos(proto: &FunctionProto) -> Vec<std::rc::Rc<FunctionProto>> {
+    proto
+        .chunk
+        .constants
+        .iter()
+        .filter_map(|c| match c {
+            Value::Function(p) => Some(p.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn vm_closures_capture_only_values_they_read() {
+    use lyra::compiler::bytecode::Op;
+    // `sq` is inlined into the closure, which then captures only `n`; the
+    // function that captures nothing is a plain constant
+    let src = "let f = fn(n) -> let sq = fn(x) -> x * x in fn(y) -> sq(y) + n\n\
+               let g = fn(n) -> fn(x) -> x + 1\n\
+               let result = (f(1)(3), g(0)(1))\n\
+               result";
+    let main = vm_compile(src).unwrap();
+    let [f, g] = &nested_protos(&main)[..] else {
+        panic!("expected f and g");
+    };
+    let counts: Vec<usize> = nested_protos(f).iter().map(|p| p.upvalue_count).collect();
+    assert_eq!(counts, [0, 1]);
+    assert!(!g.chunk.code.iter().any(|op| matches!(op, Op::Closure(..))));
+    assert_eq!(run_proto(main).unwrap().to_string(), "(10, 2)");
+}
+
+#[test]
+fn vm_recursive_closures_share_their_captures() {
+    let src = "let sum_to = fn(xs) ->\n\
+               let rec go = fn(i, acc) ->\n\
+               if i == length(xs) then acc else go(i + 1, acc + nth(xs, i)) in\n\
+               go(0, 0)\n\
+               sum_to(range(0, 500))";
+    assert_eq!(vm_run(src).unwrap(), Value::Int(124750));
+}
+
+// ── Reflection ──
+
+#[test]
+fn vm_reflected_fields_are_taken_back_by_type() {
+    let src = "type Option a = Some a | None\n\
+               let r = { a: 1, b: \"x\" }\n\
+               let get = fn (name) -> match record_get(r, name) with\n\
+               | Some(v) -> (match dynamic_string(v) with | Some(s) -> s | None -> to_string(v))\n\
+               | None -> \"?\"\n\
+               str_concat(get(\"a\"), str_concat(get(\"b\"), get(\"c\")))";
+    assert_eq!(vm_run(src).unwrap(), Value::String("1x?".to_string()));
+}
+
+// ── Malformed bytecode ──
+
+fn run_chunk(build: impl FnOnce(&mut lyra::compiler::bytecode::Chunk)) -> String {
+    use lyra::compiler::bytecode::{Chunk, FunctionProto};
+    let mut chunk = Chunk::new();
+    build(&mut chunk);
+    let main = FunctionProto {
+        name: "<main>".to_string(),
+        arity: 0,
+        chunk,
+        upvalue_count: 0,
+    };
+    let mut vm = VM::new();
+    format!("{:?}", vm.run(main).expect_err("malformed bytecode should fail"))
+}
+
+#[test]
+fn vm_stack_underflow_is_an_error() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let err = run_chunk(|c| {
+        c.emit(Op::Add, Span::new(3, 4));
+    });
+    assert!(err.contains("stack underflow") && err.contains("start: 3"), "{}", err);
+    let err = run_chunk(|c| {
+        c.emit(Op::MakeList(2), Span::default());
+    });
+    assert!(err.contains("stack underflow"), "{}", err);
+}
+
+#[test]
+fn vm_closure_over_non_function_is_an_error() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let err = run_chunk(|c| {
+        let idx = c.add_constant(Value::Int(1));
+        c.emit(Op::Closure(idx, Vec::new()), Span::default());
+    });
+    assert!(err.contains("closure constant is Int, not a function"), "{}", err);
+}
+
+#[test]
+fn vm_bad_operands_are_errors() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let err = run_chunk(|c| {
+        c.emit(Op::GetLocal(5), Span::default());
+    });
+    assert!(err.contains("no local slot 5"), "{}", err);
+    let err = run_chunk(|c| {
+        c.emit(Op::Constant(0), Span::default());
+    });
+    assert!(err.contains("no constant 0"), "{}", err);
+}
+
+#[test]
+fn vm_pattern_ops_on_the_wrong_shape_are_errors() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let on = |value: Value, op: Op| {
+        run_chunk(|c| {
+            let idx = c.add_constant(value);
+            c.emit(Op::Constant(idx), Span::default());
+            c.emit(op, Span::default());
+        })
+    };
+    let some = Value::adt("Some", vec![Value::Int(1)]);
+    let err = on(some, Op::GetAdtField(1));
+    assert!(err.contains("no field 1 in Some"), "{}", err);
+    let err = on(Value::Int(1), Op::GetAdtField(0));
+    assert!(err.contains("no field 0 in Int"), "{}", err);
+    let err = on(Value::List(Vec::new()), Op::GetListHead);
+    assert!(err.contains("no head in List"), "{}", err);
+    let err = on(Value::Int(1), Op::GetListHead);
+    assert!(err.contains("no head in Int"), "{}", err);
+    let err = on(Value::List(Vec::new()), Op::GetListTail);
+    assert!(err.contains("no tail in List"), "{}", err);
+    let err = on(Value::Bool(true), Op::GetListTail);
+    assert!(err.contains("no tail in Bool"), "{}", err);
+    let err = on(Value::Tuple(vec![Value::Int(1)]), Op::GetTupleField(2));
+    assert!(err.contains("no field 2 in Tuple"), "{}", err);
+    let err = on(Value::Unit, Op::GetTupleField(0));
+    assert!(err.contains("no field 0 in ()"), "{}", err);
+}
+
+#[test]
+fn vm_list_element_out_of_range_is_an_error() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let err = run_chunk(|c| {
+        let idx = c.add_constant(Value::List(vec![Value::Int(1)]));
+        c.emit(Op::Constant(idx), Span::default());
+        c.emit(Op::GetListElement(1), Span::default());
+    });
+    assert!(err.contains("no element 1 in List"), "{}", err);
+    let err = run_chunk(|c| {
+        let idx = c.add_constant(Value::Int(1));
+        c.emit(Op::Constant(idx), Span::default());
+        c.emit(Op::GetListElement(0), Span::default());
+    });
+    assert!(err.contains("no element 0 in Int"), "{}", err);
+}
+
+#[test]
+fn vm_string_slice_out_of_range_is_an_error() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let slice = |s: &str, start: usize, end: usize| {
+        run_chunk(|c| {
+            let idx = c.add_constant(Value::String(s.to_string()));
+            c.emit(Op::Constant(idx), Span::default());
+            c.emit(Op::GetStringSlice(start, end), Span::default());
+        })
+    };
+    let err = slice("ab", 0, 3);
+    assert!(err.contains("no slice 0..-3 in String"), "{}", err);
+    let err = slice("ab", 2, 1);
+    assert!(err.contains("no slice 2..-1 in String"), "{}", err);
+    // Not on a character boundary
+    let err = slice("é", 1, 0);
+    assert!(err.contains("no slice 1..-0 in String"), "{}", err);
+}
+
+#[test]
+fn vm_operand_errors_show_the_values() {
+    use lyra::compiler::bytecode::Op;
+    use lyra::span::Span;
+    let err = run_chunk(|c| {
+        let one = c.add_constant(Value::Int(1));
+        let yes = c.add_constant(Value::Bool(true));
+        c.emit(Op::Constant(one), Span::default());
+        c.emit(Op::Constant(yes), Span::default());
+        c.emit(Op::Add, Span::default());
+    });
+    assert!(err.contains("cannot add Int 1 and Bool true"), "{}", err);
+    let err = run_chunk(|c| {
+        let list = c.add_constant(Value::List(vec![Value::Int(1)]));
+        let one = c.add_constant(Value::Int(1));
+        c.emit(Op::Constant(list), Span::default());
+        c.emit(Op::Constant(one), Span::default());
+        c.emit(Op::Sub, Span::default());
+    });
+    assert!(err.contains("cannot subtract Int 1 from List [1]"), "{}", err);
+    let err = run_chunk(|c| {
+        let a = c.add_constant(Value::String("a".to_string()));
+        c.emit(Op::Constant(a), Span::default());
+        c.emit(Op::Not, Span::default());
+    });
+    assert!(err.contains("cannot apply ! to String \\\"a\\\""), "{}", err);
+    let err = run_chunk(|c| {
+        let list = c.add_constant(Value::List((0..30).map(Value::Int).collect()));
+        c.emit(Op::Constant(list), Span::default());
+        c.emit(Op::Call(0), Span::default());
+    });
+    assert!(err.contains("NotCallable") && err.contains("List [0, 1, 2,"), "{}", err);
+    assert!(err.contains("...\""), "long values are cut short: {}", err);
+}
+
+// ── Execution budgets ──
+
+#[test]
+fn vm_step_budget_stops_infinite_loop() {
+    use lyra::budget::{with_budget, Budget};
+    let budget = Budget {
+        max_steps: Some(10_000),
+        timeout: None,
+    };
+    let spin = "let rec spin = fn (n) -> spin(n + 1)\nspin(0)";
+    let err = with_budget(budget, || vm_run(spin)).unwrap_err();
+    assert!(err.contains("BudgetExceeded") && err.contains("more than 10000 steps"), "{}", err);
+    // The budget ends with the run
+    let countdown = "let rec go = fn (n) -> if n == 0 then 0 else go(n - 1)\ngo(20000)";
+    assert_eq!(vm_run(countdown).unwrap(), Value::Int(0));
+}
+
+#[test]
+fn vm_timeout_stops_infinite_loop() {
+    use lyra::budget::{with_budget, Budget};
+    let budget = Budget {
+        max_steps: None,
+        timeout: Some(std::time::Duration::from_millis(50)),
+    };
+    let spin = "let rec spin = fn (n) -> spin(n + 1)\nspin(0)";
+    let err = with_budget(budget, || vm_run(spin)).unwrap_err();
+    assert!(err.contains("ran longer than 50ms"), "{}", err);
+}
+
+#[test]
+fn vm_interrupt_stops_infinite_loop() {
+    use lyra::budget::interruptible;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static FLAG: AtomicBool = AtomicBool::new(false);
+    let setter = std::thread::spawn(|| {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        FLAG.store(true, Ordering::Relaxed);
+    });
+    let spin = "let rec spin = fn (n) -> spin(n + 1)\nspin(0)";
+    let err = interruptible(&FLAG, || vm_run(spin)).unwrap_err();
+    setter.join().unwrap();
+    assert!(err.contains("Interrupted"), "{}", err);
+}
+
+// ── Host bindings ──
+
+#[test]
+fn vm_extern_binds_the_host_value() {
+    use lyra::{run_file_vm_with, RunOptions};
+    use std::sync::atomic::{AtomicI64, Ordering};
+    static SEEN: AtomicI64 = AtomicI64::new(0);
+    let host = |name: &str, func: fn(Vec<Value>) -> Result<Value, String>| {
+        (name.to_string(), Value::builtin(name, 1, func))
+    };
+    let options = RunOptions {
+        host: vec![
+            host("double", |args| match &args[0] {
+                Value::Int(n) => Ok(Value::Int(n * 2)),
+                other => Err(format!("expected Int, got {}", other.type_name())),
+            }),
+            host("record", |args| {
+                if let Value::Int(n) = args[0] {
+                    SEEN.store(n, Ordering::Relaxed);
+                }
+                Ok(Value::Unit)
+            }),
+        ],
+        ..RunOptions::default()
+    };
+    let src = "extern double : Int -> Int\nextern record : Int -> ()\nrecord(double(21))";
+    run_file_vm_with(src, "host.lyra", &options).unwrap();
+    assert_eq!(SEEN.load(Ordering::Relaxed), 42);
+
+    // Missing or mismatched host values stop the program before it starts
+    let missing = run_file_vm_with("println(1)\nextern load : String -> Int", "host.lyra", &RunOptions::default());
+    assert!(matches!(missing, Err(lyra::error::LyraError::UnboundExtern { .. })), "{:?}", missing);
+    let err = run_file_vm_with("extern double : Int -> Int -> Int", "host.lyra", &options).unwrap_err();
+    let err = format!("{:?}", err);
+    assert!(err.contains("declared to take 2 arguments, but the host's takes 1 argument"), "{}", err);
+}
+
+// ── Instrumentation hooks ──
+
+/// The calls and returns `src` makes under `run_file_vm_with`, and the first
+/// error if a hook stops it after `max_ops` steps.
+fn vm_traced(src: &str, max_ops: usize) -> (Vec<String>, Option<String>) {
+    use lyra::hooks::Hooks;
+    use lyra::{run_file_vm_with, RunOptions};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let ops = Rc::new(Cell::new(0));
+    let (calls, returns) = (events.clone(), events.clone());
+    let hooks = Hooks::new()
+        .on_call(move |name, args| {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            calls.borrow_mut().push(format!("call {}({})", name, args.join(", ")));
+        })
+        .on_return(move |value| returns.borrow_mut().push(format!("return {}", value)))
+        .on_op(move |_| {
+            ops.set(ops.get() + 1);
+            match ops.get() > max_ops {
+                true => Err("out of host budget".to_string()),
+                false => Ok(()),
+            }
+        });
+    let options = RunOptions { hooks, ..RunOptions::default() };
+    let err = run_file_vm_with(src, "hooks.lyra", &options).err().map(|e| format!("{:?}", e));
+    let events = events.borrow().clone();
+    (events, err)
+}
+
+#[test]
+fn vm_hooks_report_calls_returns_and_steps() {
+    let src = "let rec fact = fn (n) -> if n <= 1 then 1 else n * fact(n - 1)\nfact(3)";
+    let (events, err) = vm_traced(src, usize::MAX);
+    assert_eq!(err, None);
+    // The compiled function is named as its errors name it
+    let events: Vec<String> = events.iter().map(|e| e.replace("<lambda>", "fact")).collect();
+    assert_eq!(
+        events,
+        ["call fact(3)", "call fact(2)", "call fact(1)", "return 1", "return 2", "return 6"]
+    );
+
+    // Tail calls return once per call, like any other
+    let src = "let rec count = fn (n) -> if n == 0 then 0 else count(n - 1)\ncount(2)";
+    let (events, _) = vm_traced(src, usize::MAX);
+    assert_eq!(events.iter().filter(|e| e.starts_with("call")).count(), 3, "{:?}", events);
+    assert_eq!(events.iter().filter(|e| e.starts_with("return")).count(), 3, "{:?}", events);
+
+    let src = "let rec spin = fn (n) -> spin(n + 1)\nspin(0)";
+    let (_, err) = vm_traced(src, 100);
+    assert!(err.unwrap().contains("out of host budget"));
+}